@@ -9,6 +9,12 @@ use std::os::unix::io::RawFd;
 use thiserror::Error;
 use vmm_sys_util::sock_ctrl_msg::ScmSocket;
 
+#[cfg(feature = "tls_api")]
+mod tls;
+
+#[cfg(feature = "tls_api")]
+pub use self::tls::TlsStream;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Error writing to or reading from HTTP socket: {0}")]
@@ -23,6 +29,21 @@ pub enum Error {
     ContentLengthParsing(std::num::ParseIntError),
     #[error("Server responded with an error: {0:?}: {1:?}")]
     ServerResponse(StatusCode, Option<String>),
+    #[cfg(feature = "tls_api")]
+    #[error("File descriptors cannot be sent over TCP")]
+    TcpSendFds,
+    #[cfg(feature = "tls_api")]
+    #[error("Error reading {0}: {1}")]
+    ReadFile(std::path::PathBuf, std::io::Error),
+    #[cfg(feature = "tls_api")]
+    #[error("No certificate or private key found in {0}")]
+    MissingPem(std::path::PathBuf),
+    #[cfg(feature = "tls_api")]
+    #[error("Invalid server name: {0}")]
+    InvalidServerName(String),
+    #[cfg(feature = "tls_api")]
+    #[error("Error configuring TLS: {0}")]
+    Tls(rustls::Error),
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -31,6 +52,8 @@ pub enum StatusCode {
     Ok,
     NoContent,
     BadRequest,
+    Unauthorized,
+    Forbidden,
     NotFound,
     InternalServerError,
     NotImplemented,
@@ -44,6 +67,8 @@ impl StatusCode {
             200 => StatusCode::Ok,
             204 => StatusCode::NoContent,
             400 => StatusCode::BadRequest,
+            401 => StatusCode::Unauthorized,
+            403 => StatusCode::Forbidden,
             404 => StatusCode::NotFound,
             500 => StatusCode::InternalServerError,
             501 => StatusCode::NotImplemented,
@@ -65,6 +90,22 @@ impl StatusCode {
     }
 }
 
+/// Transport carrying the API requests and responses.
+pub trait ApiSocket: Read + Write {
+    /// Writes the request line and the headers common to all the requests,
+    /// passing `fds` along to the VMM.
+    fn write_request_head(&mut self, head: &[u8], fds: &[RawFd]) -> Result<(), Error>;
+}
+
+impl<T: Read + Write + ScmSocket> ApiSocket for T {
+    fn write_request_head(&mut self, head: &[u8], fds: &[RawFd]) -> Result<(), Error> {
+        self.send_with_fds(&[head], fds)
+            .map_err(Error::SocketSendFds)?;
+
+        Ok(())
+    }
+}
+
 fn get_header<'a>(res: &'a str, header: &'a str) -> Option<&'a str> {
     let header_str = format!("{header}: ");
     res.find(&header_str)
@@ -81,7 +122,7 @@ fn get_status_code(res: &str) -> Result<StatusCode, Error> {
     }
 }
 
-fn parse_http_response(socket: &mut dyn Read) -> Result<Option<String>, Error> {
+fn parse_http_response(socket: &mut (impl Read + ?Sized)) -> Result<Option<String>, Error> {
     let mut res = String::new();
     let mut body_offset = None;
     let mut content_length: Option<usize> = None;
@@ -130,22 +171,18 @@ fn parse_http_response(socket: &mut dyn Read) -> Result<Option<String>, Error> {
 
 /// Make an API request using the fully qualified command name.
 /// For example, full_command could be "vm.create" or "vmm.ping".
-pub fn simple_api_full_command_with_fds_and_response<T: Read + Write + ScmSocket>(
+pub fn simple_api_full_command_with_fds_and_response<T: ApiSocket + ?Sized>(
     socket: &mut T,
     method: &str,
     full_command: &str,
     request_body: Option<&str>,
     request_fds: Vec<RawFd>,
 ) -> Result<Option<String>, Error> {
-    socket
-        .send_with_fds(
-            &[format!(
-                "{method} /api/v1/{full_command} HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n"
-            )
-            .as_bytes()],
-            &request_fds,
-        )
-        .map_err(Error::SocketSendFds)?;
+    socket.write_request_head(
+        format!("{method} /api/v1/{full_command} HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n")
+            .as_bytes(),
+        &request_fds,
+    )?;
 
     if let Some(request_body) = request_body {
         socket
@@ -166,7 +203,7 @@ pub fn simple_api_full_command_with_fds_and_response<T: Read + Write + ScmSocket
     parse_http_response(socket)
 }
 
-pub fn simple_api_full_command_with_fds<T: Read + Write + ScmSocket>(
+pub fn simple_api_full_command_with_fds<T: ApiSocket + ?Sized>(
     socket: &mut T,
     method: &str,
     full_command: &str,
@@ -188,7 +225,7 @@ pub fn simple_api_full_command_with_fds<T: Read + Write + ScmSocket>(
     Ok(())
 }
 
-pub fn simple_api_full_command<T: Read + Write + ScmSocket>(
+pub fn simple_api_full_command<T: ApiSocket + ?Sized>(
     socket: &mut T,
     method: &str,
     full_command: &str,
@@ -197,7 +234,7 @@ pub fn simple_api_full_command<T: Read + Write + ScmSocket>(
     simple_api_full_command_with_fds(socket, method, full_command, request_body, Vec::new())
 }
 
-pub fn simple_api_full_command_and_response<T: Read + Write + ScmSocket>(
+pub fn simple_api_full_command_and_response<T: ApiSocket + ?Sized>(
     socket: &mut T,
     method: &str,
     full_command: &str,
@@ -212,21 +249,47 @@ pub fn simple_api_full_command_and_response<T: Read + Write + ScmSocket>(
     )
 }
 
-pub fn simple_api_command_with_fds<T: Read + Write + ScmSocket>(
+/// Make a VM API request targeting the VM `vm_id`, or the default VM when
+/// `vm_id` is None.
+pub fn simple_api_vm_command_with_fds<T: ApiSocket + ?Sized>(
     socket: &mut T,
     method: &str,
+    vm_id: Option<&str>,
     c: &str,
     request_body: Option<&str>,
     request_fds: Vec<RawFd>,
 ) -> Result<(), Error> {
     // Create the full VM command. For VMM commands, use
     // simple_api_full_command().
-    let full_command = format!("vm.{c}");
+    let full_command = match vm_id {
+        Some(vm_id) => format!("vms/{vm_id}/vm.{c}"),
+        None => format!("vm.{c}"),
+    };
 
     simple_api_full_command_with_fds(socket, method, &full_command, request_body, request_fds)
 }
 
-pub fn simple_api_command<T: Read + Write + ScmSocket>(
+pub fn simple_api_vm_command<T: ApiSocket + ?Sized>(
+    socket: &mut T,
+    method: &str,
+    vm_id: Option<&str>,
+    c: &str,
+    request_body: Option<&str>,
+) -> Result<(), Error> {
+    simple_api_vm_command_with_fds(socket, method, vm_id, c, request_body, Vec::new())
+}
+
+pub fn simple_api_command_with_fds<T: ApiSocket + ?Sized>(
+    socket: &mut T,
+    method: &str,
+    c: &str,
+    request_body: Option<&str>,
+    request_fds: Vec<RawFd>,
+) -> Result<(), Error> {
+    simple_api_vm_command_with_fds(socket, method, None, c, request_body, request_fds)
+}
+
+pub fn simple_api_command<T: ApiSocket + ?Sized>(
     socket: &mut T,
     method: &str,
     c: &str,
@@ -0,0 +1,120 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use crate::{ApiSocket, Error};
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|e| Error::ReadFile(path.to_owned(), e))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let pem = read_pem(path)?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(|e| Error::ReadFile(path.to_owned(), e))?;
+    if certs.is_empty() {
+        return Err(Error::MissingPem(path.to_owned()));
+    }
+
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, Error> {
+    let pem = read_pem(path)?;
+    rustls_pemfile::private_key(&mut pem.as_slice())
+        .map_err(|e| Error::ReadFile(path.to_owned(), e))?
+        .ok_or_else(|| Error::MissingPem(path.to_owned()))
+}
+
+/// HTTP API connection over TLS, the VMM being reached through TCP.
+pub struct TlsStream {
+    stream: StreamOwned<ClientConnection, TcpStream>,
+    // Sent along with every request when set
+    token: Option<String>,
+}
+
+impl TlsStream {
+    /// Connects to the VMM listening on `addr`, a `<host>:<port>` pair. The
+    /// VMM certificate must be issued by `ca` for `host`. The client
+    /// authenticates with its certificate and private key, with a bearer
+    /// token, or both.
+    pub fn connect(
+        addr: &str,
+        ca: &Path,
+        client_cert: Option<(&Path, &Path)>,
+        token: Option<String>,
+    ) -> Result<Self, Error> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(ca)? {
+            roots.add(cert).map_err(Error::Tls)?;
+        }
+
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let config = match client_cert {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+                .map_err(Error::Tls)?,
+            None => builder.with_no_client_auth(),
+        };
+
+        let host = addr
+            .rsplit_once(':')
+            .map_or(addr, |(host, _)| host)
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let server_name = ServerName::try_from(host.to_owned())
+            .map_err(|_| Error::InvalidServerName(host.to_owned()))?;
+        let connection =
+            ClientConnection::new(Arc::new(config), server_name).map_err(Error::Tls)?;
+        let socket = TcpStream::connect(addr).map_err(Error::Socket)?;
+
+        Ok(TlsStream {
+            stream: StreamOwned::new(connection, socket),
+            token,
+        })
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl ApiSocket for TlsStream {
+    fn write_request_head(&mut self, head: &[u8], fds: &[RawFd]) -> Result<(), Error> {
+        if !fds.is_empty() {
+            return Err(Error::TcpSendFds);
+        }
+
+        self.stream.write_all(head).map_err(Error::Socket)?;
+        if let Some(token) = &self.token {
+            self.stream
+                .write_all(format!("Authorization: Bearer {token}\r\n").as_bytes())
+                .map_err(Error::Socket)?;
+        }
+
+        Ok(())
+    }
+}
@@ -15,6 +15,12 @@ pub enum DiskFileError {
     /// Failed creating a new AsyncIo.
     #[error("Failed creating a new AsyncIo: {0}")]
     NewAsyncIo(#[source] std::io::Error),
+    /// Failed resizing the disk file.
+    #[error("Failed resizing the disk file: {0}")]
+    Resize(#[source] std::io::Error),
+    /// Resizing is not supported by this disk file format.
+    #[error("Resizing is not supported by this disk file format")]
+    ResizeNotSupported,
 }
 
 pub type DiskFileResult<T> = std::result::Result<T, DiskFileError>;
@@ -25,6 +31,9 @@ pub trait DiskFile: Send {
     fn topology(&mut self) -> DiskTopology {
         DiskTopology::default()
     }
+    fn resize(&mut self, _size: u64) -> DiskFileResult<()> {
+        Err(DiskFileError::ResizeNotSupported)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -876,3 +876,30 @@ impl DiskTopology {
         })
     }
 }
+
+/// Grow a raw disk to `size` bytes.
+///
+/// Block devices are not resized here, they are expected to have been grown
+/// beforehand and their size is only checked against `size`.
+pub fn resize_raw_disk(f: &File, size: u64) -> std::io::Result<()> {
+    if DiskTopology::is_block_device(f)? {
+        let mut f = f;
+        let device_size = f.seek(SeekFrom::End(0))?;
+        if device_size < size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Block device size {device_size} is smaller than {size}"),
+            ));
+        }
+        return Ok(());
+    }
+
+    let file_size = f.metadata()?.len();
+    if size < file_size {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Shrinking the disk from {file_size} to {size} is not supported"),
+        ));
+    }
+    f.set_len(size)
+}
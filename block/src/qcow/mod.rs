@@ -97,12 +97,18 @@ pub enum Error {
     RefcountTableOffEnd,
     #[error("Too many clusters specified for refcount")]
     RefcountTableTooLarge,
+    #[error("Refcount table too small to grow the image to {0} bytes")]
+    RefcountTableTooSmall(u64),
+    #[error("Failed to resize image: {0}")]
+    Resizing(io::Error),
     #[error("Failed to seek file: {0}")]
     SeekingFile(io::Error),
     #[error("Failed to set file size: {0}")]
     SettingFileSize(io::Error),
     #[error("Failed to set refcount refcount: {0}")]
     SettingRefcountRefcount(io::Error),
+    #[error("Shrinking the image is not supported")]
+    ShrinkNotSupported,
     #[error("Size too small for number of clusters")]
     SizeTooSmallForNumberOfClusters,
     #[error("L1 entry table too large: {0}")]
@@ -685,6 +691,145 @@ impl QcowFile {
         self.backing_file = backing;
     }
 
+    /// Grows the virtual size of the image to `new_size` bytes.
+    ///
+    /// The L1 table is extended in place when its clusters have room for the new entries,
+    /// otherwise it is moved to newly allocated clusters at the end of the file. The refcount
+    /// table is never moved, so the image can only grow as far as it can reference clusters.
+    pub fn resize(&mut self, new_size: u64) -> Result<()> {
+        if new_size < self.virtual_size() {
+            return Err(Error::ShrinkNotSupported);
+        }
+        if new_size == self.virtual_size() {
+            return Ok(());
+        }
+        if new_size > MAX_QCOW_FILE_SIZE {
+            return Err(Error::FileTooBig(new_size));
+        }
+
+        // Start from a consistent on-disk state, the tables are reloaded from it.
+        self.flush().map_err(Error::Resizing)?;
+
+        let cluster_size = self.raw_file.cluster_size();
+        let entries_per_cluster = cluster_size / size_of::<u64>() as u64;
+        let num_clusters = div_round_up_u64(new_size, cluster_size);
+        let num_l2_clusters = div_round_up_u64(num_clusters, entries_per_cluster);
+        if num_l2_clusters > MAX_RAM_POINTER_TABLE_SIZE {
+            return Err(Error::TooManyL1Entries(num_l2_clusters));
+        }
+        let old_l1_clusters = div_round_up_u64(self.l1_table.len() as u64, entries_per_cluster);
+        let l1_clusters = div_round_up_u64(num_l2_clusters, entries_per_cluster);
+        let header_clusters = div_round_up_u64(size_of::<QcowHeader>() as u64, cluster_size);
+
+        // Make the refcount table cover the clusters of the grown image, including the ones
+        // holding both the old and the new L1 tables.
+        let refcount_clusters = max_refcount_clusters(
+            self.header.refcount_order,
+            cluster_size as u32,
+            (num_clusters + old_l1_clusters + l1_clusters + num_l2_clusters + header_clusters)
+                as u32,
+        );
+        let refcount_table_capacity =
+            u64::from(self.header.refcount_table_clusters) * entries_per_cluster;
+        if refcount_clusters > refcount_table_capacity {
+            return Err(Error::RefcountTableTooSmall(new_size));
+        }
+        if refcount_clusters > self.refcounts.ref_table().len() as u64 {
+            self.refcounts = RefCount::new(
+                &mut self.raw_file,
+                self.header.refcount_table_offset,
+                refcount_clusters,
+                self.refcounts.refcounts_per_block(),
+                cluster_size,
+            )
+            .map_err(Error::ReadingRefCounts)?;
+        }
+
+        let mut l1_table = self.l1_table.get_values().to_vec();
+        l1_table.resize(num_l2_clusters as usize, 0);
+
+        let old_l1_table_offset = self.header.l1_table_offset;
+        let l1_table_offset = if l1_clusters > old_l1_clusters {
+            // The new table must be contiguous, allocate it past the end of the file.
+            let max_valid_cluster_offset = self.refcounts.max_valid_cluster_offset();
+            let mut l1_table_offset = None;
+            for _ in 0..l1_clusters {
+                let addr = self
+                    .raw_file
+                    .add_cluster_end(max_valid_cluster_offset)
+                    .map_err(Error::Resizing)?
+                    .ok_or(Error::NoFreeClusters)?;
+                l1_table_offset.get_or_insert(addr);
+            }
+            // 'unwrap' is OK because at least one cluster was allocated.
+            let l1_table_offset = l1_table_offset.unwrap();
+            for i in 0..l1_clusters {
+                let mut unref_clusters = self
+                    .set_cluster_refcount(l1_table_offset + i * cluster_size, 1)
+                    .map_err(Error::Resizing)?;
+                self.unref_clusters.append(&mut unref_clusters);
+            }
+            l1_table_offset
+        } else {
+            old_l1_table_offset
+        };
+
+        // The new L1 table and its refcounts must be on disk before the header points to it.
+        self.raw_file
+            .write_pointer_table(l1_table_offset, &l1_table, 0)
+            .map_err(Error::Resizing)?;
+        self.refcounts
+            .flush_blocks(&mut self.raw_file)
+            .map_err(Error::Resizing)?;
+        self.refcounts
+            .flush_table(&mut self.raw_file)
+            .map_err(Error::Resizing)?;
+        self.raw_file
+            .file_mut()
+            .sync_all()
+            .map_err(Error::Resizing)?;
+
+        self.header.size = new_size;
+        self.header.l1_size = num_l2_clusters as u32;
+        self.header.l1_table_offset = l1_table_offset;
+        self.write_resized_header()?;
+        self.l1_table = VecCache::from_vec(l1_table);
+
+        // Release the clusters of the previous L1 table once nothing references them.
+        if l1_table_offset != old_l1_table_offset {
+            for i in 0..old_l1_clusters {
+                let addr = old_l1_table_offset + i * cluster_size;
+                let mut unref_clusters = self
+                    .set_cluster_refcount(addr, 0)
+                    .map_err(Error::Resizing)?;
+                self.unref_clusters.append(&mut unref_clusters);
+                self.unref_clusters.push(addr);
+            }
+        }
+
+        self.flush().map_err(Error::Resizing)
+    }
+
+    // Updates the header fields modified by a resize. The rest of the header is left untouched.
+    fn write_resized_header(&mut self) -> Result<()> {
+        // Offsets of the size, l1_size and l1_table_offset fields in the header.
+        const SIZE_OFFSET: u64 = 24;
+        const L1_SIZE_OFFSET: u64 = 36;
+
+        let file = self.raw_file.file_mut();
+        file.seek(SeekFrom::Start(SIZE_OFFSET))
+            .map_err(Error::WritingHeader)?;
+        file.write_u64::<BigEndian>(self.header.size)
+            .map_err(Error::WritingHeader)?;
+        file.seek(SeekFrom::Start(L1_SIZE_OFFSET))
+            .map_err(Error::WritingHeader)?;
+        file.write_u32::<BigEndian>(self.header.l1_size)
+            .map_err(Error::WritingHeader)?;
+        file.write_u64::<BigEndian>(self.header.l1_table_offset)
+            .map_err(Error::WritingHeader)?;
+        file.sync_all().map_err(Error::WritingHeader)
+    }
+
     /// Returns the `QcowHeader` for this file.
     pub fn header(&self) -> &QcowHeader {
         &self.header
@@ -2766,6 +2911,45 @@ mod tests {
         });
     }
 
+    #[test]
+    fn resize_grow() {
+        with_default_file(0x10_0000, false, |mut qcow_file| {
+            let data = [0x55u8; 0x1000];
+            qcow_file.write_all(&data).expect("Failed to write.");
+
+            // Growing to 8 TB requires a second L1 cluster, moving the table.
+            let new_size = 0x800_0000_0000;
+            let old_l1_table_offset = qcow_file.header().l1_table_offset;
+            qcow_file.resize(new_size).expect("Failed to resize.");
+            assert_eq!(qcow_file.virtual_size(), new_size);
+            assert_ne!(qcow_file.header().l1_table_offset, old_l1_table_offset);
+            assert!(matches!(
+                qcow_file.resize(0x10_0000),
+                Err(Error::ShrinkNotSupported)
+            ));
+
+            qcow_file
+                .seek(SeekFrom::Start(new_size - data.len() as u64))
+                .expect("Failed to seek.");
+            qcow_file.write_all(&data).expect("Failed to write.");
+            qcow_file.flush().expect("Failed to flush.");
+
+            // Reopen the image to check the header and the tables on disk.
+            let raw_file = qcow_file.raw_file.file_mut().try_clone().unwrap();
+            let mut reopened = QcowFile::from(raw_file).expect("Failed to reopen.");
+            assert_eq!(reopened.virtual_size(), new_size);
+            let mut readback = [0u8; 0x1000];
+            reopened.rewind().expect("Failed to seek.");
+            reopened.read_exact(&mut readback).expect("Failed to read.");
+            assert_eq!(readback, data);
+            reopened
+                .seek(SeekFrom::Start(new_size - data.len() as u64))
+                .expect("Failed to seek.");
+            reopened.read_exact(&mut readback).expect("Failed to read.");
+            assert_eq!(readback, data);
+        });
+    }
+
     fn seek_cur(file: &mut QcowFile) -> u64 {
         file.stream_position().unwrap()
     }
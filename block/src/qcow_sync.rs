@@ -35,6 +35,14 @@ impl DiskFile for QcowDiskSync {
     fn new_async_io(&self, _ring_depth: u32) -> DiskFileResult<Box<dyn AsyncIo>> {
         Ok(Box::new(QcowSync::new(self.qcow_file.clone())) as Box<dyn AsyncIo>)
     }
+
+    fn resize(&mut self, size: u64) -> DiskFileResult<()> {
+        self.qcow_file
+            .lock()
+            .unwrap()
+            .resize(size)
+            .map_err(|e| DiskFileError::Resize(std::io::Error::other(e)))
+    }
 }
 
 pub struct QcowSync {
@@ -12,7 +12,7 @@ use vmm_sys_util::eventfd::EventFd;
 use crate::async_io::{
     AsyncIo, AsyncIoError, AsyncIoResult, DiskFile, DiskFileError, DiskFileResult,
 };
-use crate::DiskTopology;
+use crate::{resize_raw_disk, DiskTopology};
 
 pub struct RawFileDisk {
     file: File,
@@ -46,6 +46,10 @@ impl DiskFile for RawFileDisk {
             DiskTopology::default()
         }
     }
+
+    fn resize(&mut self, size: u64) -> DiskFileResult<()> {
+        resize_raw_disk(&self.file, size).map_err(DiskFileError::Resize)
+    }
 }
 
 pub struct RawFileAsync {
@@ -15,7 +15,7 @@ use vmm_sys_util::eventfd::EventFd;
 use crate::async_io::{
     AsyncIo, AsyncIoError, AsyncIoResult, DiskFile, DiskFileError, DiskFileResult,
 };
-use crate::DiskTopology;
+use crate::{resize_raw_disk, DiskTopology};
 
 pub struct RawFileDiskAio {
     file: File,
@@ -49,6 +49,10 @@ impl DiskFile for RawFileDiskAio {
             DiskTopology::default()
         }
     }
+
+    fn resize(&mut self, size: u64) -> DiskFileResult<()> {
+        resize_raw_disk(&self.file, size).map_err(DiskFileError::Resize)
+    }
 }
 
 pub struct RawFileAsyncAio {
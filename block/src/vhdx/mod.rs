@@ -5,8 +5,9 @@
 use std::collections::btree_map::BTreeMap;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
 
-use byteorder::{BigEndian, ByteOrder};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
 use remain::sorted;
 use thiserror::Error;
 use uuid::Uuid;
@@ -25,6 +26,10 @@ mod vhdx_metadata;
 #[sorted]
 #[derive(Error, Debug)]
 pub enum VhdxError {
+    #[error("BAT region too small to grow the disk to {0} bytes")]
+    BatTooSmall(u64),
+    #[error("Invalid disk size {0}")]
+    InvalidDiskSize(u64),
     #[error("Not a VHDx file {0}")]
     NotVhdx(#[source] VhdxHeaderError),
     #[error("Failed to parse VHDx header {0}")]
@@ -37,6 +42,12 @@ pub enum VhdxError {
     ReadBatEntry(#[source] VhdxBatError),
     #[error("Failed reading sector from disk {0}")]
     ReadFailed(#[source] VhdxIoError),
+    #[error("Failed resizing disk {0}")]
+    Resize(#[source] std::io::Error),
+    #[error("Failed updating VHDx header {0}")]
+    UpdateHeader(#[source] VhdxHeaderError),
+    #[error("Failed writing BAT entries {0}")]
+    WriteBatEntry(#[source] VhdxBatError),
     #[error("Failed writing to sector on disk {0}")]
     WriteFailed(#[source] VhdxIoError),
 }
@@ -93,6 +104,61 @@ impl Vhdx {
     pub fn virtual_disk_size(&self) -> u64 {
         self.disk_spec.virtual_disk_size
     }
+
+    /// Grow the virtual disk to `new_size` bytes. The BAT region is not
+    /// relocated, so the new entries must fit in the space already reserved
+    /// for it.
+    pub fn resize(&mut self, new_size: u64) -> Result<()> {
+        let logical_sector_size = self.disk_spec.logical_sector_size as u64;
+        if new_size < self.virtual_disk_size() || new_size % logical_sector_size != 0 {
+            return Err(VhdxError::InvalidDiskSize(new_size));
+        }
+        if new_size == self.virtual_disk_size() {
+            return Ok(());
+        }
+
+        let entry_count = BatEntry::calculate_entries(
+            self.disk_spec.block_size,
+            new_size,
+            self.disk_spec.chunk_ratio,
+        );
+        if entry_count as usize > self.bat_entry.length as usize / size_of::<BatEntry>() {
+            return Err(VhdxError::BatTooSmall(new_size));
+        }
+
+        if self.first_write {
+            self.first_write = false;
+            self.vhdx_header
+                .update(&mut self.file)
+                .map_err(VhdxError::UpdateHeader)?;
+        }
+
+        // The new blocks are not allocated yet. Make sure the BAT says so
+        // before the larger size becomes visible.
+        let old_entry_count = self.bat_entries.len();
+        self.bat_entries
+            .resize(entry_count as usize, BatEntry::default());
+        BatEntry::write_bat_entries(
+            &mut self.file,
+            self.bat_entry.file_offset + (old_entry_count * size_of::<BatEntry>()) as u64,
+            &self.bat_entries[old_entry_count..],
+        )
+        .map_err(VhdxError::WriteBatEntry)?;
+        self.file.sync_all().map_err(VhdxError::Resize)?;
+
+        self.file
+            .seek(SeekFrom::Start(self.disk_spec.virtual_disk_size_offset))
+            .map_err(VhdxError::Resize)?;
+        self.file
+            .write_u64::<LittleEndian>(new_size)
+            .map_err(VhdxError::Resize)?;
+        self.file.sync_all().map_err(VhdxError::Resize)?;
+
+        self.disk_spec.virtual_disk_size = new_size;
+        self.disk_spec.total_sectors = new_size / logical_sector_size;
+
+        Ok(())
+    }
 }
 
 impl Read for Vhdx {
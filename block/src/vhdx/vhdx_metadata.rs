@@ -97,6 +97,8 @@ pub struct DiskSpec {
     pub physical_sector_size: u32,
     pub chunk_ratio: u64,
     pub total_sectors: u64,
+    // Location of the virtual disk size metadata item in the file.
+    pub virtual_disk_size_offset: u64,
 }
 
 impl DiskSpec {
@@ -159,6 +161,8 @@ impl DiskSpec {
                 disk_spec.virtual_disk_size = f
                     .read_u64::<LittleEndian>()
                     .map_err(VhdxMetadataError::ReadMetadata)?;
+                disk_spec.virtual_disk_size_offset =
+                    metadata_region.file_offset + metadata_entry.offset as u64;
 
                 metadata_presence |= METADATA_VIRTUAL_DISK_SIZE_PRESENT;
             } else if metadata_entry.item_id
@@ -35,6 +35,14 @@ impl DiskFile for VhdxDiskSync {
                 as Box<dyn AsyncIo>,
         )
     }
+
+    fn resize(&mut self, size: u64) -> DiskFileResult<()> {
+        self.vhdx_file
+            .lock()
+            .unwrap()
+            .resize(size)
+            .map_err(|e| DiskFileError::Resize(std::io::Error::other(e)))
+    }
 }
 
 pub struct VhdxSync {
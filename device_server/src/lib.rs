@@ -0,0 +1,1014 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Out-of-process device server.
+//!
+//! Hosts one of the virtio devices from `virtio_devices` behind a vfio-user
+//! server socket. The device is exposed through the regular virtio-pci
+//! transport, so that the VMM can drive it as any other vfio-user device
+//! (see `--user-device`).
+
+#[macro_use]
+extern crate log;
+
+mod seccomp_filters;
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Barrier, Mutex};
+use std::{io, process, thread};
+
+use block::async_io::DiskFile;
+use block::qcow_sync::QcowDiskSync;
+use block::raw_sync::RawFileDiskSync;
+use block::{detect_image_type, qcow, ImageType};
+use landlock::{
+    path_beneath_rules, Access, AccessFs, BitFlags, Ruleset, RulesetAttr, RulesetCreatedAttr,
+    RulesetError, ABI,
+};
+use option_parser::{OptionParser, OptionParserError, Toggle};
+use pci::{PciCapabilityId, PciDevice, PciDeviceError, MSIX_TABLE_ENTRY_SIZE};
+use seccompiler::{apply_filter, SeccompAction};
+use thiserror::Error;
+use vfio_bindings::bindings::vfio::*;
+use vfio_user::{IrqInfo, Server, ServerBackend};
+use virtio_devices::transport::{
+    VirtioPciDevice, VirtioPciDeviceActivator, VirtioPciDeviceError, VirtioTransport,
+};
+use virtio_devices::vsock::{VsockUnixBackend, VsockUnixError};
+use virtio_devices::{Block, Console, Endpoint, Rng, VirtioDevice, Vsock};
+use vm_allocator::{AddressAllocator, SystemAllocator};
+use vm_device::interrupt::{
+    InterruptIndex, InterruptManager, InterruptSourceConfig, InterruptSourceGroup,
+    MsiIrqGroupConfig,
+};
+use vm_memory::bitmap::AtomicBitmap;
+use vm_memory::mmap::MmapRegion;
+use vm_memory::{FileOffset, GuestAddress, GuestAddressSpace, GuestMemoryAtomic};
+use vmm_sys_util::eventfd::{EventFd, EFD_NONBLOCK};
+
+use crate::seccomp_filters::get_seccomp_filter;
+
+type GuestMemoryMmap = vm_memory::GuestMemoryMmap<AtomicBitmap>;
+type GuestRegionMmap = vm_memory::GuestRegionMmap<AtomicBitmap>;
+
+// Index of the BAR holding the virtio-pci capabilities.
+const VIRTIO_BAR_INDEX: usize = 0;
+// Size of the PCI configuration space exposed through the config region.
+const PCI_CONFIG_SPACE_SIZE: u64 = 256;
+const PCI_CAPABILITY_LIST_OFFSET: usize = 0x34;
+
+// The BARs are only meaningful to the VMM, which relocates them in the guest
+// address space. These ranges are only used to lay them out locally.
+const MMIO32_BASE: u64 = 0xc000_0000;
+const MMIO32_SIZE: u64 = 0x2000_0000;
+const MMIO64_BASE: u64 = 1 << 40;
+const MMIO64_SIZE: u64 = 1 << 40;
+
+// https://docs.rs/landlock/latest/landlock/enum.ABI.html for more info on ABI
+const LANDLOCK_ABI: ABI = ABI::V3;
+
+pub const BLOCK_SYNTAX: &str = "virtio-block device parameters \
+\"path=<image_path>,readonly=on|off,direct=on|off,num_queues=<number_of_queues>,\
+queue_size=<size_of_each_queue>,serial=<serial_number>\"";
+pub const RNG_SYNTAX: &str = "virtio-rng device parameters \"src=<entropy_source_path>\"";
+pub const CONSOLE_SYNTAX: &str =
+    "virtio-console device parameters \"file=<output_file_path>\", output is discarded if not set";
+pub const VSOCK_SYNTAX: &str =
+    "virtio-vsock device parameters \"cid=<context_id>,socket=<socket_path>\"";
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to parse configuration: {0:?}")]
+    ParseConfig(OptionParserError),
+    #[error("Missing parameter: {0}")]
+    MissingParameter(&'static str),
+    #[error("Failed to open disk image: {0}")]
+    OpenDisk(#[source] io::Error),
+    #[error("Failed to detect disk image type: {0}")]
+    DetectImageType(#[source] io::Error),
+    #[error("Unsupported disk image type, only RAW and QCOW2 are supported")]
+    UnsupportedImageType,
+    #[error("Failed to open QCOW disk image: {0}")]
+    CreateQcowDisk(#[source] qcow::Error),
+    #[error("Failed to open console output file: {0}")]
+    OpenConsoleFile(#[source] io::Error),
+    #[error("Failed to create vsock backend: {0:?}")]
+    CreateVsockBackend(VsockUnixError),
+    #[error("Failed to create virtio device: {0}")]
+    CreateVirtioDevice(#[source] io::Error),
+    #[error("Failed to create EventFd: {0}")]
+    CreateEventFd(#[source] io::Error),
+    #[error("Failed to spawn thread: {0}")]
+    SpawnThread(#[source] io::Error),
+    #[error("Failed to create virtio-pci transport: {0}")]
+    CreateVirtioPciDevice(#[source] VirtioPciDeviceError),
+    #[error("Failed to create address allocator")]
+    CreateAllocator,
+    #[error("Failed to allocate PCI BARs: {0:?}")]
+    AllocateBars(PciDeviceError),
+    #[error("Failed to create vfio-user server: {0}")]
+    CreateServer(#[source] vfio_user::Error),
+    #[error("vfio-user server failure: {0}")]
+    RunServer(#[source] vfio_user::Error),
+    #[error("Failed to create seccomp filter: {0}")]
+    CreateSeccompFilter(#[source] seccompiler::Error),
+    #[error("Failed to apply seccomp filter: {0}")]
+    ApplySeccompFilter(#[source] seccompiler::Error),
+    #[error("Failed to apply landlock rules: {0}")]
+    ApplyLandlock(#[source] RulesetError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Device hosted by the server, as described on the command line.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeviceConfig {
+    Block {
+        path: PathBuf,
+        readonly: bool,
+        direct: bool,
+        num_queues: usize,
+        queue_size: u16,
+        serial: Option<String>,
+    },
+    Rng {
+        src: PathBuf,
+    },
+    Console {
+        file: Option<PathBuf>,
+    },
+    Vsock {
+        cid: u32,
+        socket: PathBuf,
+    },
+}
+
+impl DeviceConfig {
+    pub fn parse_block(block: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser
+            .add("path")
+            .add("readonly")
+            .add("direct")
+            .add("num_queues")
+            .add("queue_size")
+            .add("serial");
+        parser.parse(block).map_err(Error::ParseConfig)?;
+
+        let path = parser
+            .get("path")
+            .map(PathBuf::from)
+            .ok_or(Error::MissingParameter("path"))?;
+        let readonly = parser
+            .convert::<Toggle>("readonly")
+            .map_err(Error::ParseConfig)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let direct = parser
+            .convert::<Toggle>("direct")
+            .map_err(Error::ParseConfig)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let num_queues = parser
+            .convert("num_queues")
+            .map_err(Error::ParseConfig)?
+            .unwrap_or(1);
+        let queue_size = parser
+            .convert("queue_size")
+            .map_err(Error::ParseConfig)?
+            .unwrap_or(128);
+        let serial = parser.get("serial");
+
+        Ok(DeviceConfig::Block {
+            path,
+            readonly,
+            direct,
+            num_queues,
+            queue_size,
+            serial,
+        })
+    }
+
+    pub fn parse_rng(rng: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("src");
+        parser.parse(rng).map_err(Error::ParseConfig)?;
+
+        let src = parser
+            .get("src")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/dev/urandom"));
+
+        Ok(DeviceConfig::Rng { src })
+    }
+
+    pub fn parse_console(console: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("file");
+        parser.parse(console).map_err(Error::ParseConfig)?;
+
+        let file = parser.get("file").map(PathBuf::from);
+
+        Ok(DeviceConfig::Console { file })
+    }
+
+    pub fn parse_vsock(vsock: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("cid").add("socket");
+        parser.parse(vsock).map_err(Error::ParseConfig)?;
+
+        let cid = parser
+            .convert("cid")
+            .map_err(Error::ParseConfig)?
+            .ok_or(Error::MissingParameter("cid"))?;
+        let socket = parser
+            .get("socket")
+            .map(PathBuf::from)
+            .ok_or(Error::MissingParameter("socket"))?;
+
+        Ok(DeviceConfig::Vsock { cid, socket })
+    }
+
+    fn id(&self) -> &'static str {
+        match self {
+            DeviceConfig::Block { .. } => "block",
+            DeviceConfig::Rng { .. } => "rng",
+            DeviceConfig::Console { .. } => "console",
+            DeviceConfig::Vsock { .. } => "vsock",
+        }
+    }
+
+    // Paths the device may access once the server is running, along with
+    // the access it needs. These match the rules the VMM applies to the same
+    // devices.
+    fn landlock_rules(&self) -> Vec<(PathBuf, BitFlags<AccessFs>)> {
+        let read = AccessFs::from_read(LANDLOCK_ABI);
+        let read_write = read | AccessFs::from_write(LANDLOCK_ABI);
+
+        match self {
+            DeviceConfig::Block { path, readonly, .. } => {
+                vec![(path.clone(), if *readonly { read } else { read_write })]
+            }
+            DeviceConfig::Rng { src } => vec![(src.clone(), read)],
+            DeviceConfig::Console { file } => {
+                file.iter().map(|file| (file.clone(), read_write)).collect()
+            }
+            DeviceConfig::Vsock { socket, .. } => vec![(socket.clone(), read_write)],
+        }
+    }
+
+    fn create_device(
+        &self,
+        seccomp_action: SeccompAction,
+        exit_evt: EventFd,
+    ) -> Result<Arc<Mutex<dyn VirtioDevice>>> {
+        let device: Arc<Mutex<dyn VirtioDevice>> = match self {
+            DeviceConfig::Block {
+                path,
+                readonly,
+                direct,
+                num_queues,
+                queue_size,
+                serial,
+            } => {
+                let mut options = OpenOptions::new();
+                options.read(true).write(!readonly);
+                if *direct {
+                    options.custom_flags(libc::O_DIRECT);
+                }
+                let mut file = options.open(path).map_err(Error::OpenDisk)?;
+                let image = match detect_image_type(&mut file).map_err(Error::DetectImageType)? {
+                    ImageType::Raw => Box::new(RawFileDiskSync::new(file)) as Box<dyn DiskFile>,
+                    ImageType::Qcow2 => {
+                        Box::new(QcowDiskSync::new(file, *direct).map_err(Error::CreateQcowDisk)?)
+                            as Box<dyn DiskFile>
+                    }
+                    _ => return Err(Error::UnsupportedImageType),
+                };
+
+                Arc::new(Mutex::new(
+                    Block::new(
+                        self.id().to_string(),
+                        image,
+                        path.clone(),
+                        *readonly,
+                        false,
+                        *num_queues,
+                        *queue_size,
+                        serial.clone(),
+                        seccomp_action,
+                        None,
+                        Default::default(),
+                        exit_evt,
+                        None,
+                        BTreeMap::new(),
+                    )
+                    .map_err(Error::CreateVirtioDevice)?,
+                ))
+            }
+            DeviceConfig::Rng { src } => Arc::new(Mutex::new(
+                Rng::new(
+                    self.id().to_string(),
+                    &src.to_string_lossy(),
+                    None,
+                    false,
+                    seccomp_action,
+                    exit_evt,
+                    None,
+                )
+                .map_err(Error::CreateVirtioDevice)?,
+            )),
+            DeviceConfig::Console { file } => {
+                let endpoint = if let Some(file) = file {
+                    let file = OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(file)
+                        .map_err(Error::OpenConsoleFile)?;
+                    Endpoint::File(Arc::new(file))
+                } else {
+                    Endpoint::Null
+                };
+
+                let (console, _resizer) = Console::new(
+                    self.id().to_string(),
+                    endpoint,
+                    None,
+                    false,
+                    seccomp_action,
+                    exit_evt,
+                    None,
+                )
+                .map_err(Error::CreateVirtioDevice)?;
+
+                Arc::new(Mutex::new(console))
+            }
+            DeviceConfig::Vsock { cid, socket } => {
+                let backend =
+                    VsockUnixBackend::new(*cid, socket.to_string_lossy().to_string(), false)
+                        .map_err(Error::CreateVsockBackend)?;
+
+                Arc::new(Mutex::new(
+                    Vsock::new(
+                        self.id().to_string(),
+                        *cid,
+                        socket.clone(),
+                        backend,
+                        false,
+                        false,
+                        seccomp_action,
+                        exit_evt,
+                        None,
+                    )
+                    .map_err(Error::CreateVirtioDevice)?,
+                ))
+            }
+        };
+
+        Ok(device)
+    }
+}
+
+/// MSI-X vectors of the device, backed by the eventfds the VMM provides
+/// through VFIO_USER_DEVICE_SET_IRQS. Routing and masking are handled by the
+/// VMM, the server only has to signal the right eventfd.
+struct MsixInterruptGroup {
+    vectors: Mutex<Vec<Option<EventFd>>>,
+}
+
+impl MsixInterruptGroup {
+    fn new(count: u16) -> Self {
+        MsixInterruptGroup {
+            vectors: Mutex::new((0..count).map(|_| None).collect()),
+        }
+    }
+
+    fn set_vector(&self, index: usize, eventfd: Option<EventFd>) {
+        if let Some(vector) = self.vectors.lock().unwrap().get_mut(index) {
+            *vector = eventfd;
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.vectors.lock().unwrap().len()
+    }
+}
+
+impl InterruptSourceGroup for MsixInterruptGroup {
+    fn trigger(&self, index: InterruptIndex) -> vm_device::interrupt::Result<()> {
+        if let Some(Some(eventfd)) = self.vectors.lock().unwrap().get(index as usize) {
+            eventfd.write(1)?;
+        }
+
+        Ok(())
+    }
+
+    fn notifier(&self, index: InterruptIndex) -> Option<EventFd> {
+        self.vectors
+            .lock()
+            .unwrap()
+            .get(index as usize)?
+            .as_ref()?
+            .try_clone()
+            .ok()
+    }
+
+    fn update(
+        &self,
+        _index: InterruptIndex,
+        _config: InterruptSourceConfig,
+        _masked: bool,
+        _set_gsi: bool,
+    ) -> vm_device::interrupt::Result<()> {
+        Ok(())
+    }
+
+    fn set_gsi(&self) -> vm_device::interrupt::Result<()> {
+        Ok(())
+    }
+}
+
+struct MsixInterruptManager {
+    group: Arc<MsixInterruptGroup>,
+}
+
+impl InterruptManager for MsixInterruptManager {
+    type GroupConfig = MsiIrqGroupConfig;
+
+    fn create_group(
+        &self,
+        _config: Self::GroupConfig,
+    ) -> vm_device::interrupt::Result<Arc<dyn InterruptSourceGroup>> {
+        Ok(self.group.clone())
+    }
+
+    fn destroy_group(
+        &self,
+        _group: Arc<dyn InterruptSourceGroup>,
+    ) -> vm_device::interrupt::Result<()> {
+        Ok(())
+    }
+}
+
+struct DeviceServerBackend {
+    device: VirtioPciDevice,
+    memory: GuestMemoryAtomic<GuestMemoryMmap>,
+    interrupts: Arc<MsixInterruptGroup>,
+    pending_activations: Arc<Mutex<Vec<VirtioPciDeviceActivator>>>,
+    bar_addr: u64,
+    msix_table_offset: Option<u64>,
+}
+
+impl DeviceServerBackend {
+    fn read_config(&mut self, offset: u64, data: &mut [u8]) {
+        for (i, byte) in data.iter_mut().enumerate() {
+            let offset = offset as usize + i;
+            let reg = self.device.read_config_register(offset / 4);
+            *byte = (reg >> ((offset % 4) * 8)) as u8;
+        }
+    }
+
+    fn write_config(&mut self, offset: u64, data: &[u8]) {
+        self.device
+            .write_config_register((offset / 4) as usize, offset % 4, data);
+    }
+
+    fn write_bar(&mut self, offset: u64, data: &[u8]) {
+        // Queue notifications are regular BAR writes from the VMM point of
+        // view, since no ioeventfd can be registered for them.
+        let addr = self.bar_addr + offset;
+        if let Some((eventfd, _)) = self
+            .device
+            .ioeventfds(self.bar_addr)
+            .find(|(_, notify_addr)| *notify_addr == addr)
+        {
+            if let Err(e) = eventfd.write(1) {
+                error!("Failed to notify queue: {}", e);
+            }
+            return;
+        }
+
+        if let Some(barrier) = self.device.write_bar(self.bar_addr, offset, data) {
+            self.activate(barrier);
+        }
+    }
+
+    // The transport expects the activation to happen from another thread,
+    // both sides meeting on the barrier once the device is activated.
+    fn activate(&mut self, barrier: Arc<Barrier>) {
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+            })
+        };
+
+        for mut activator in self.pending_activations.lock().unwrap().drain(..) {
+            if let Err(e) = activator.activate() {
+                error!("Failed to activate device: {:?}", e);
+                barrier.wait();
+            }
+        }
+
+        if waiter.join().is_err() {
+            error!("Failed to join activation thread");
+        }
+    }
+
+    // The VMM emulates the MSI-X table guest side and only enables the
+    // vectors it provided an eventfd for. Unmask them in the local copy of
+    // the table so that the transport forwards the interrupts.
+    fn set_vector_masked(&mut self, vector: usize, masked: bool) {
+        if let Some(table_offset) = self.msix_table_offset {
+            let offset = table_offset + (vector * MSIX_TABLE_ENTRY_SIZE) as u64 + 12;
+            self.device
+                .write_bar(self.bar_addr, offset, &(masked as u32).to_le_bytes());
+        }
+    }
+}
+
+impl ServerBackend for DeviceServerBackend {
+    fn region_read(&mut self, region: u32, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        match region {
+            VFIO_PCI_CONFIG_REGION_INDEX => self.read_config(offset, data),
+            VFIO_PCI_BAR0_REGION_INDEX => self.device.read_bar(self.bar_addr, offset, data),
+            _ => data.fill(0),
+        }
+
+        Ok(())
+    }
+
+    fn region_write(&mut self, region: u32, offset: u64, data: &[u8]) -> io::Result<()> {
+        match region {
+            VFIO_PCI_CONFIG_REGION_INDEX => self.write_config(offset, data),
+            VFIO_PCI_BAR0_REGION_INDEX => self.write_bar(offset, data),
+            _ => warn!("Unexpected write to region {}", region),
+        }
+
+        Ok(())
+    }
+
+    fn dma_map(
+        &mut self,
+        _flags: vfio_user::DmaMapFlags,
+        offset: u64,
+        address: u64,
+        size: u64,
+        fd: Option<File>,
+    ) -> io::Result<()> {
+        let file = fd.ok_or_else(|| io::Error::other("DMA mapping requires a file descriptor"))?;
+        let region = MmapRegion::build(
+            Some(FileOffset::new(file, offset)),
+            size as usize,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+        )
+        .map_err(io::Error::other)?;
+        let region =
+            GuestRegionMmap::new(region, GuestAddress(address)).map_err(io::Error::other)?;
+
+        let memory = self
+            .memory
+            .memory()
+            .insert_region(Arc::new(region))
+            .map_err(io::Error::other)?;
+        self.memory.lock().unwrap().replace(memory);
+
+        Ok(())
+    }
+
+    fn dma_unmap(
+        &mut self,
+        _flags: vfio_user::DmaUnmapFlags,
+        address: u64,
+        size: u64,
+    ) -> io::Result<()> {
+        let (memory, _) = self
+            .memory
+            .memory()
+            .remove_region(GuestAddress(address), size)
+            .map_err(io::Error::other)?;
+        self.memory.lock().unwrap().replace(memory);
+
+        Ok(())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_irqs(
+        &mut self,
+        index: u32,
+        flags: u32,
+        start: u32,
+        count: u32,
+        fds: Vec<File>,
+    ) -> io::Result<()> {
+        if index != VFIO_PCI_MSIX_IRQ_INDEX {
+            return Err(io::Error::other(format!("Unsupported IRQ index {index}")));
+        }
+
+        if flags & VFIO_IRQ_SET_DATA_EVENTFD != 0 {
+            for (i, fd) in fds.into_iter().enumerate() {
+                let vector = start as usize + i;
+                // SAFETY: the file descriptor is owned by the file it is
+                // taken from.
+                let eventfd = unsafe { EventFd::from_raw_fd(fd.into_raw_fd()) };
+                self.interrupts.set_vector(vector, Some(eventfd));
+                self.set_vector_masked(vector, false);
+            }
+        } else if flags & VFIO_IRQ_SET_DATA_NONE != 0 {
+            // A count of 0 disables all the vectors.
+            let end = if count == 0 {
+                self.interrupts.count()
+            } else {
+                (start + count) as usize
+            };
+            for vector in start as usize..end {
+                self.interrupts.set_vector(vector, None);
+                self.set_vector_masked(vector, true);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Restrict the server to the vfio-user socket and to the paths used by the
+// device. This applies to the calling thread and to the threads it spawns
+// afterwards, including the virtio threads of the device.
+fn apply_landlock(socket: &Path, config: &DeviceConfig) -> Result<()> {
+    let mut rules = config.landlock_rules();
+    rules.push((
+        socket.to_path_buf(),
+        AccessFs::from_read(LANDLOCK_ABI) | AccessFs::from_write(LANDLOCK_ABI),
+    ));
+
+    let mut ruleset = Ruleset::default()
+        .handle_access(AccessFs::from_all(LANDLOCK_ABI))
+        .and_then(|ruleset| ruleset.create())
+        .map_err(Error::ApplyLandlock)?;
+    for (path, access) in rules {
+        ruleset = ruleset
+            .add_rules(path_beneath_rules([path], access))
+            .map_err(Error::ApplyLandlock)?;
+    }
+    ruleset.restrict_self().map_err(Error::ApplyLandlock)?;
+
+    Ok(())
+}
+
+fn find_msix_table_offset(device: &mut VirtioPciDevice) -> Option<u64> {
+    let mut cap_offset =
+        (device.read_config_register(PCI_CAPABILITY_LIST_OFFSET / 4) & 0xfc) as usize;
+    while cap_offset != 0 {
+        let reg = device.read_config_register(cap_offset / 4);
+        if reg & 0xff == PciCapabilityId::MsiX as u32 {
+            let table = device.read_config_register(cap_offset / 4 + 1);
+            return Some(u64::from(table & !0x7));
+        }
+        cap_offset = ((reg >> 8) & 0xfc) as usize;
+    }
+
+    None
+}
+
+pub fn start_device_server(
+    socket: &Path,
+    config: &DeviceConfig,
+    seccomp_action: SeccompAction,
+    landlock_enable: bool,
+) -> Result<()> {
+    let memory = GuestMemoryAtomic::new(GuestMemoryMmap::new());
+    let exit_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::CreateEventFd)?;
+
+    let virtio_device = config.create_device(
+        seccomp_action.clone(),
+        exit_evt.try_clone().map_err(Error::CreateEventFd)?,
+    )?;
+
+    // One vector per queue, plus one for configuration changes.
+    let msix_num = virtio_device.lock().unwrap().queue_max_sizes().len() as u16 + 1;
+    let interrupts = Arc::new(MsixInterruptGroup::new(msix_num));
+    let interrupt_manager: Arc<dyn InterruptManager<GroupConfig = MsiIrqGroupConfig>> =
+        Arc::new(MsixInterruptManager {
+            group: interrupts.clone(),
+        });
+
+    let pending_activations = Arc::new(Mutex::new(Vec::new()));
+    let mut device = VirtioPciDevice::new(
+        config.id().to_string(),
+        memory.clone(),
+        virtio_device,
+        msix_num,
+        None,
+        &interrupt_manager,
+        0,
+        EventFd::new(EFD_NONBLOCK).map_err(Error::CreateEventFd)?,
+        false,
+        None,
+        pending_activations.clone(),
+        None,
+    )
+    .map_err(Error::CreateVirtioPciDevice)?;
+
+    let allocator = Arc::new(Mutex::new(
+        SystemAllocator::new(
+            GuestAddress(0),
+            1 << 16,
+            GuestAddress(MMIO32_BASE),
+            MMIO32_SIZE,
+            #[cfg(target_arch = "x86_64")]
+            vec![],
+        )
+        .ok_or(Error::CreateAllocator)?,
+    ));
+    let mut mmio32_allocator = AddressAllocator::new(GuestAddress(MMIO32_BASE), MMIO32_SIZE)
+        .ok_or(Error::CreateAllocator)?;
+    let mut mmio64_allocator = AddressAllocator::new(GuestAddress(MMIO64_BASE), MMIO64_SIZE)
+        .ok_or(Error::CreateAllocator)?;
+    let bars = device
+        .allocate_bars(
+            &allocator,
+            &mut mmio32_allocator,
+            &mut mmio64_allocator,
+            None,
+        )
+        .map_err(Error::AllocateBars)?;
+    let bar = bars
+        .iter()
+        .find(|bar| bar.idx() == VIRTIO_BAR_INDEX)
+        .ok_or(Error::AllocateBars(PciDeviceError::MissingResource))?;
+
+    let regions = (0..VFIO_PCI_NUM_REGIONS)
+        .map(|index| {
+            let size = match index {
+                VFIO_PCI_CONFIG_REGION_INDEX => PCI_CONFIG_SPACE_SIZE,
+                VFIO_PCI_BAR0_REGION_INDEX => bar.size(),
+                _ => 0,
+            };
+            let flags = if size > 0 {
+                VFIO_REGION_INFO_FLAG_READ | VFIO_REGION_INFO_FLAG_WRITE
+            } else {
+                0
+            };
+
+            vfio_region_info {
+                argsz: std::mem::size_of::<vfio_region_info>() as u32,
+                flags,
+                index,
+                cap_offset: 0,
+                size,
+                offset: 0,
+            }
+        })
+        .collect();
+    let irqs = vec![IrqInfo {
+        index: VFIO_PCI_MSIX_IRQ_INDEX,
+        count: u32::from(msix_num),
+        flags: VFIO_IRQ_INFO_EVENTFD,
+    }];
+
+    let msix_table_offset = find_msix_table_offset(&mut device);
+    let mut backend = DeviceServerBackend {
+        device,
+        memory,
+        interrupts,
+        pending_activations,
+        bar_addr: bar.addr(),
+        msix_table_offset,
+    };
+
+    let server = Server::new(socket, false, irqs, regions).map_err(Error::CreateServer)?;
+
+    // Everything the device needs is opened by now, and the socket is bound.
+    if landlock_enable {
+        apply_landlock(socket, config)?;
+    }
+
+    let seccomp_filter = get_seccomp_filter(&seccomp_action).map_err(Error::CreateSeccompFilter)?;
+    if !seccomp_filter.is_empty() {
+        apply_filter(&seccomp_filter).map_err(Error::ApplySeccompFilter)?;
+    }
+
+    // The device signals this eventfd on unrecoverable errors.
+    thread::Builder::new()
+        .name("device_server_exit".to_string())
+        .spawn(move || {
+            let mut pollfd = libc::pollfd {
+                fd: exit_evt.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: FFI call with a valid pollfd
+            while unsafe { libc::poll(&mut pollfd, 1, -1) } < 0 {}
+            error!("Device reported an unrecoverable error, exiting");
+            process::exit(1);
+        })
+        .map_err(Error::SpawnThread)?;
+
+    info!("Serving device on {}", socket.display());
+    server.run(&mut backend).map_err(Error::RunServer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use vfio_user::Client;
+    use vmm_sys_util::tempdir::TempDir;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_block() {
+        assert_eq!(
+            DeviceConfig::parse_block("path=/path/to/disk.raw").unwrap(),
+            DeviceConfig::Block {
+                path: PathBuf::from("/path/to/disk.raw"),
+                readonly: false,
+                direct: false,
+                num_queues: 1,
+                queue_size: 128,
+                serial: None,
+            }
+        );
+        assert_eq!(
+            DeviceConfig::parse_block(
+                "path=/path/to/disk.raw,readonly=on,direct=on,num_queues=4,queue_size=256,serial=test"
+            )
+            .unwrap(),
+            DeviceConfig::Block {
+                path: PathBuf::from("/path/to/disk.raw"),
+                readonly: true,
+                direct: true,
+                num_queues: 4,
+                queue_size: 256,
+                serial: Some("test".to_string()),
+            }
+        );
+        assert!(matches!(
+            DeviceConfig::parse_block("readonly=on"),
+            Err(Error::MissingParameter("path"))
+        ));
+        assert!(matches!(
+            DeviceConfig::parse_block("path=/path/to/disk.raw,readonly=yes"),
+            Err(Error::ParseConfig(_))
+        ));
+        assert!(matches!(
+            DeviceConfig::parse_block("path=/path/to/disk.raw,queue_size=big"),
+            Err(Error::ParseConfig(_))
+        ));
+        assert!(matches!(
+            DeviceConfig::parse_block("path=/path/to/disk.raw,id=disk0"),
+            Err(Error::ParseConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_rng() {
+        assert_eq!(
+            DeviceConfig::parse_rng("").unwrap(),
+            DeviceConfig::Rng {
+                src: PathBuf::from("/dev/urandom")
+            }
+        );
+        assert_eq!(
+            DeviceConfig::parse_rng("src=/dev/random").unwrap(),
+            DeviceConfig::Rng {
+                src: PathBuf::from("/dev/random")
+            }
+        );
+        assert!(matches!(
+            DeviceConfig::parse_rng("source=/dev/random"),
+            Err(Error::ParseConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_console() {
+        assert_eq!(
+            DeviceConfig::parse_console("").unwrap(),
+            DeviceConfig::Console { file: None }
+        );
+        assert_eq!(
+            DeviceConfig::parse_console("file=/tmp/console.log").unwrap(),
+            DeviceConfig::Console {
+                file: Some(PathBuf::from("/tmp/console.log"))
+            }
+        );
+        assert!(matches!(
+            DeviceConfig::parse_console("socket=/tmp/console.sock"),
+            Err(Error::ParseConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_vsock() {
+        assert_eq!(
+            DeviceConfig::parse_vsock("cid=3,socket=/tmp/vsock").unwrap(),
+            DeviceConfig::Vsock {
+                cid: 3,
+                socket: PathBuf::from("/tmp/vsock")
+            }
+        );
+        assert!(matches!(
+            DeviceConfig::parse_vsock("socket=/tmp/vsock"),
+            Err(Error::MissingParameter("cid"))
+        ));
+        assert!(matches!(
+            DeviceConfig::parse_vsock("cid=3"),
+            Err(Error::MissingParameter("socket"))
+        ));
+        assert!(matches!(
+            DeviceConfig::parse_vsock("cid=three,socket=/tmp/vsock"),
+            Err(Error::ParseConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_landlock_rules() {
+        let read = AccessFs::from_read(LANDLOCK_ABI);
+        let read_write = read | AccessFs::from_write(LANDLOCK_ABI);
+
+        let block = DeviceConfig::parse_block("path=/path/to/disk.raw").unwrap();
+        assert_eq!(
+            block.landlock_rules(),
+            vec![(PathBuf::from("/path/to/disk.raw"), read_write)]
+        );
+        let block = DeviceConfig::parse_block("path=/path/to/disk.raw,readonly=on").unwrap();
+        assert_eq!(
+            block.landlock_rules(),
+            vec![(PathBuf::from("/path/to/disk.raw"), read)]
+        );
+
+        let rng = DeviceConfig::parse_rng("").unwrap();
+        assert_eq!(
+            rng.landlock_rules(),
+            vec![(PathBuf::from("/dev/urandom"), read)]
+        );
+
+        let console = DeviceConfig::parse_console("").unwrap();
+        assert!(console.landlock_rules().is_empty());
+        let console = DeviceConfig::parse_console("file=/tmp/console.log").unwrap();
+        assert_eq!(
+            console.landlock_rules(),
+            vec![(PathBuf::from("/tmp/console.log"), read_write)]
+        );
+
+        let vsock = DeviceConfig::parse_vsock("cid=3,socket=/tmp/vsock").unwrap();
+        assert_eq!(
+            vsock.landlock_rules(),
+            vec![(PathBuf::from("/tmp/vsock"), read_write)]
+        );
+    }
+
+    // Serve a device with the seccomp filter enforced, any syscall missing
+    // from the filter killing the test.
+    #[test]
+    fn test_device_server_seccomp() {
+        let dir = TempDir::new_with_prefix("/tmp/ch").unwrap();
+        let socket = dir.as_path().join("rng.sock");
+
+        let server = {
+            let socket = socket.clone();
+            thread::spawn(move || {
+                let config = DeviceConfig::parse_rng("").unwrap();
+                start_device_server(&socket, &config, SeccompAction::Trap, false)
+            })
+        };
+
+        let mut client = loop {
+            match Client::new(&socket) {
+                Ok(client) => break client,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+
+        let mut ids = [0u8; 4];
+        client
+            .region_read(VFIO_PCI_CONFIG_REGION_INDEX, 0, &mut ids)
+            .unwrap();
+        // virtio vendor ID, modern virtio-rng device ID
+        assert_eq!(u32::from_le_bytes(ids), 0x1044_1af4);
+
+        let memory = TempFile::new().unwrap().into_file();
+        memory.set_len(0x10000).unwrap();
+        client
+            .dma_map(0, 0x10_0000, 0x10000, memory.as_raw_fd())
+            .unwrap();
+        client.dma_unmap(0x10_0000, 0x10000).unwrap();
+
+        drop(client);
+        server.join().unwrap().unwrap();
+    }
+}
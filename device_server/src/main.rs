@@ -0,0 +1,104 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::path::Path;
+use std::process;
+
+use clap::{Arg, ArgAction, ArgGroup, Command};
+use device_server::{start_device_server, DeviceConfig};
+use seccompiler::SeccompAction;
+
+fn main() {
+    env_logger::init();
+
+    let cmd_arguments = Command::new("ch-device-server")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about("Serve a virtio device over vfio-user.")
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("socket")
+                .long("socket")
+                .help("Path to the vfio-user socket to listen on")
+                .num_args(1)
+                .required(true),
+        )
+        .arg(
+            Arg::new("block")
+                .long("block")
+                .help(device_server::BLOCK_SYNTAX)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("rng")
+                .long("rng")
+                .help(device_server::RNG_SYNTAX)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("console")
+                .long("console")
+                .help(device_server::CONSOLE_SYNTAX)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("vsock")
+                .long("vsock")
+                .help(device_server::VSOCK_SYNTAX)
+                .num_args(1),
+        )
+        .group(
+            ArgGroup::new("device")
+                .args(["block", "rng", "console", "vsock"])
+                .required(true),
+        )
+        .arg(
+            Arg::new("seccomp")
+                .long("seccomp")
+                .num_args(1)
+                .value_parser(["true", "false", "log"])
+                .default_value("true"),
+        )
+        .arg(
+            Arg::new("landlock")
+                .long("landlock")
+                .num_args(0)
+                .help("enable/disable Landlock.")
+                .action(ArgAction::SetTrue)
+                .default_value("false"),
+        )
+        .get_matches();
+
+    let config = if let Some(block) = cmd_arguments.get_one::<String>("block") {
+        DeviceConfig::parse_block(block)
+    } else if let Some(rng) = cmd_arguments.get_one::<String>("rng") {
+        DeviceConfig::parse_rng(rng)
+    } else if let Some(console) = cmd_arguments.get_one::<String>("console") {
+        DeviceConfig::parse_console(console)
+    } else {
+        DeviceConfig::parse_vsock(cmd_arguments.get_one::<String>("vsock").unwrap())
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed parsing parameters: {e}");
+            process::exit(1);
+        }
+    };
+
+    let seccomp_action = match cmd_arguments.get_one::<String>("seccomp").unwrap().as_str() {
+        "true" => SeccompAction::Trap,
+        "false" => SeccompAction::Allow,
+        _ => SeccompAction::Log,
+    };
+
+    let socket = cmd_arguments.get_one::<String>("socket").unwrap();
+    let landlock_enable = cmd_arguments.get_flag("landlock");
+    if let Err(e) = start_device_server(Path::new(socket), &config, seccomp_action, landlock_enable)
+    {
+        eprintln!("Device server failed: {e}");
+        process::exit(1);
+    }
+}
@@ -4,11 +4,11 @@
 //
 
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
 use std::io;
 use std::os::unix::io::AsRawFd;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use once_cell::sync::OnceCell;
@@ -16,6 +16,11 @@ use serde::Serialize;
 
 static MONITOR: OnceCell<MonitorHandle> = OnceCell::new();
 
+/// Number of most recent events kept for subscribers asking for a replay.
+pub const EVENT_BACKLOG_SIZE: usize = 256;
+/// Maximum number of subscribers registered through `Subscribers`.
+pub const MAX_SUBSCRIBERS: usize = 16;
+
 #[derive(Serialize)]
 struct Event<'a> {
     timestamp: Duration,
@@ -24,10 +29,73 @@ struct Event<'a> {
     properties: Option<&'a HashMap<Cow<'a, str>, Cow<'a, str>>>,
 }
 
+#[derive(Default)]
+struct SubscribersState {
+    backlog: VecDeque<Arc<String>>,
+    senders: Vec<flume::Sender<Arc<String>>>,
+}
+
+/// Set of event subscribers which, unlike `Monitor::subscribe()`, can still be
+/// extended once the monitor thread is running. It also keeps a bounded
+/// backlog of the latest events so that new subscribers can catch up.
+///
+/// The subscribers receive every event as a single line of compact JSON,
+/// newline included.
+#[derive(Clone, Default)]
+pub struct Subscribers {
+    state: Arc<Mutex<SubscribersState>>,
+}
+
+impl Subscribers {
+    /// Registers a new subscriber. When `replay` is set, the events from the
+    /// backlog are queued before any new event. Returns `None` when there are
+    /// already `MAX_SUBSCRIBERS` subscribers.
+    pub fn subscribe(&self, replay: bool) -> Option<flume::Receiver<Arc<String>>> {
+        let mut state = self.state.lock().unwrap();
+        // Forget about the subscribers which went away since the last event.
+        state.senders.retain(|tx| !tx.is_disconnected());
+        if state.senders.len() >= MAX_SUBSCRIBERS {
+            return None;
+        }
+
+        let (tx, rx) = flume::bounded(EVENT_BACKLOG_SIZE);
+        if replay {
+            for event in state.backlog.iter() {
+                tx.try_send(event.clone()).ok();
+            }
+        }
+        state.senders.push(tx);
+        Some(rx)
+    }
+
+    /// Records the event in the backlog and forwards it to every subscriber,
+    /// dropping the ones which have gone away or are too far behind.
+    pub fn publish(&self, event: &str) {
+        // The events are pretty printed for the event monitor file, turn them
+        // into a single line once for all the subscribers.
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(event) else {
+            return;
+        };
+        let mut line = event.to_string();
+        line.push('\n');
+        let event = Arc::new(line);
+
+        let mut state = self.state.lock().unwrap();
+        if state.backlog.len() == EVENT_BACKLOG_SIZE {
+            state.backlog.pop_front();
+        }
+        state.backlog.push_back(event.clone());
+        state
+            .senders
+            .retain(|tx| tx.try_send(event.clone()).is_ok());
+    }
+}
+
 pub struct Monitor {
     pub rx: flume::Receiver<String>,
     pub file: Option<File>,
     pub broadcast: Vec<flume::Sender<Arc<String>>>,
+    pub subscribers: Subscribers,
 }
 
 impl Monitor {
@@ -36,6 +104,7 @@ impl Monitor {
             rx,
             file,
             broadcast: vec![],
+            subscribers: Subscribers::default(),
         }
     }
 
@@ -44,6 +113,11 @@ impl Monitor {
         self.broadcast.push(tx);
         rx
     }
+
+    /// Returns a handle allowing subscriptions at any point in time.
+    pub fn subscribers(&self) -> Subscribers {
+        self.subscribers.clone()
+    }
 }
 
 struct MonitorHandle {
@@ -130,3 +204,90 @@ macro_rules! event {
         }
      };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(i: usize) -> String {
+        format!("{{\n  \"event\": {i}\n}}")
+    }
+
+    fn line(i: usize) -> Arc<String> {
+        Arc::new(format!("{{\"event\":{i}}}\n"))
+    }
+
+    fn listening(subscribers: &Subscribers) -> usize {
+        let state = subscribers.state.lock().unwrap();
+        state
+            .senders
+            .iter()
+            .filter(|tx| !tx.is_disconnected())
+            .count()
+    }
+
+    #[test]
+    fn test_subscribers_replay() {
+        let subscribers = Subscribers::default();
+        for i in 0..EVENT_BACKLOG_SIZE + 2 {
+            subscribers.publish(&event(i));
+        }
+
+        let rx = subscribers.subscribe(false).unwrap();
+        assert!(rx.is_empty());
+
+        let rx = subscribers.subscribe(true).unwrap();
+        assert_eq!(rx.len(), EVENT_BACKLOG_SIZE);
+        assert_eq!(rx.recv().unwrap(), line(2));
+    }
+
+    #[test]
+    fn test_subscribers_publish() {
+        let subscribers = Subscribers::default();
+        let rx = subscribers.subscribe(false).unwrap();
+
+        subscribers.publish("{\n  \"source\": \"vm\",\n  \"event\": \"booted\"\n}");
+        assert_eq!(
+            *rx.recv().unwrap(),
+            "{\"event\":\"booted\",\"source\":\"vm\"}\n"
+        );
+
+        subscribers.publish("not json");
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn test_subscribers_limit() {
+        let subscribers = Subscribers::default();
+        let mut receivers: Vec<_> = (0..MAX_SUBSCRIBERS)
+            .map(|_| subscribers.subscribe(false).unwrap())
+            .collect();
+        assert_eq!(listening(&subscribers), MAX_SUBSCRIBERS);
+        assert!(subscribers.subscribe(false).is_none());
+
+        // A subscriber going away makes room for a new one.
+        receivers.pop();
+        assert!(subscribers.subscribe(false).is_some());
+    }
+
+    #[test]
+    fn test_subscribers_lagging() {
+        let subscribers = Subscribers::default();
+        let lagging = subscribers.subscribe(false).unwrap();
+        let rx = subscribers.subscribe(false).unwrap();
+
+        for i in 0..EVENT_BACKLOG_SIZE {
+            subscribers.publish(&event(i));
+            rx.recv().unwrap();
+        }
+        assert_eq!(listening(&subscribers), 2);
+
+        // The lagging subscriber is dropped once its queue is full, after
+        // having been given the chance to drain it.
+        subscribers.publish(&event(EVENT_BACKLOG_SIZE));
+        assert_eq!(listening(&subscribers), 1);
+        assert_eq!(rx.recv().unwrap(), line(EVENT_BACKLOG_SIZE));
+        assert_eq!(lagging.iter().count(), EVENT_BACKLOG_SIZE);
+        assert!(lagging.recv().is_err());
+    }
+}
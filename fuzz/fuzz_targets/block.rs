@@ -64,6 +64,7 @@ fuzz_target!(|bytes: &[u8]| -> Corpus {
         None,
         SeccompAction::Allow,
         None,
+        Default::default(),
         EventFd::new(EFD_NONBLOCK).unwrap(),
         None,
         queue_affinity,
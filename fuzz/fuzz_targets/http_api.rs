@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 #![no_main]
+use std::collections::HashMap;
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 use std::sync::mpsc::{channel, Receiver};
@@ -11,14 +12,16 @@ use std::thread;
 use libfuzzer_sys::{fuzz_target, Corpus};
 use micro_http::Request;
 use once_cell::sync::Lazy;
+use virtio_devices::RateLimiterConfig;
 use vm_migration::MigratableError;
 use vmm::api::http::*;
 use vmm::api::{
-    ApiRequest, RequestHandler, VmInfoResponse, VmReceiveMigrationData, VmSendMigrationData,
-    VmmPingResponse,
+    ApiRequest, RequestHandler, VmInfoResponse, VmMetricsResponse, VmReceiveMigrationData,
+    VmSendMigrationData, VmUpdateData, VmmPingResponse,
 };
 use vmm::config::RestoreConfig;
-use vmm::vm::{Error as VmError, VmState};
+use vmm::config_check::ConfigCheckReport;
+use vmm::vm::{Error as VmError, VmState, VmView};
 use vmm::vm_config::*;
 use vmm::{EpollContext, EpollDispatch};
 use vmm_sys_util::eventfd::EventFd;
@@ -160,9 +163,11 @@ impl RequestHandler for StubApiRequestHandler {
                 rng: RngConfig {
                     src: PathBuf::from("/dev/urandom"),
                     iommu: false,
+                    rate_limiter_config: None,
                 },
                 balloon: None,
                 fs: None,
+                shares: None,
                 pmem: None,
                 serial: ConsoleConfig {
                     file: None,
@@ -221,6 +226,22 @@ impl RequestHandler for StubApiRequestHandler {
         Ok(())
     }
 
+    fn vmm_create_vm(&mut self, id: Option<String>, _: Box<VmConfig>) -> Result<String, VmError> {
+        Ok(id.unwrap_or_else(|| "vm1".to_string()))
+    }
+
+    fn vmm_validate_config(&self, _: Box<VmConfig>) -> ConfigCheckReport {
+        ConfigCheckReport::default()
+    }
+
+    fn vmm_list_vms(&self) -> Result<Option<Vec<u8>>, VmError> {
+        Ok(None)
+    }
+
+    fn vm_views(&self) -> HashMap<String, VmView> {
+        HashMap::new()
+    }
+
     fn vm_resize(&mut self, _: Option<u8>, _: Option<u64>, _: Option<u64>) -> Result<(), VmError> {
         Ok(())
     }
@@ -229,6 +250,18 @@ impl RequestHandler for StubApiRequestHandler {
         Ok(())
     }
 
+    fn vm_update_rate_limiter(&mut self, _: String, _: RateLimiterConfig) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn vm_resize_disk(&mut self, _: String, _: u64) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn vm_update(&mut self, _: VmUpdateData) -> Result<Option<Vec<u8>>, VmError> {
+        Ok(None)
+    }
+
     fn vm_add_device(&mut self, _: DeviceConfig) -> Result<Option<Vec<u8>>, VmError> {
         Ok(None)
     }
@@ -269,6 +302,14 @@ impl RequestHandler for StubApiRequestHandler {
         Ok(None)
     }
 
+    fn vm_balloon_statistics(&mut self) -> Result<Option<Vec<u8>>, VmError> {
+        Ok(None)
+    }
+
+    fn vm_vcpu_stats(&mut self) -> Result<Option<Vec<u8>>, VmError> {
+        Ok(None)
+    }
+
     fn vm_power_button(&mut self) -> Result<(), VmError> {
         Ok(())
     }
@@ -284,6 +325,18 @@ impl RequestHandler for StubApiRequestHandler {
     fn vm_nmi(&mut self) -> Result<(), VmError> {
         Ok(())
     }
+
+    fn vm_migration_status(&mut self) -> Result<Option<Vec<u8>>, VmError> {
+        Ok(None)
+    }
+
+    fn vm_migration_cancel(&mut self) -> Result<(), MigratableError> {
+        Ok(())
+    }
+
+    fn vm_metrics(&mut self) -> Result<VmMetricsResponse, VmError> {
+        Err(VmError::VmNotRunning)
+    }
 }
 
 fn http_receiver_stub(exit_evt: EventFd, api_evt: EventFd, api_receiver: Receiver<ApiRequest>) {
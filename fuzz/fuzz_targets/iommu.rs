@@ -68,6 +68,7 @@ fuzz_target!(|bytes: &[u8]| -> Corpus {
         ((MEM_SIZE - IOVA_SPACE_SIZE) as u64, (MEM_SIZE - 1) as u64),
         64,
         None,
+        None,
     )
     .unwrap();
 
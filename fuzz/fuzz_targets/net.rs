@@ -76,6 +76,9 @@ fuzz_target!(|bytes: &[u8]| -> Corpus {
         QUEUE_SIZE,
         SeccompAction::Allow,
         None,
+        None,
+        None,
+        Default::default(),
         EventFd::new(EFD_NONBLOCK).unwrap(),
         None,
         true,
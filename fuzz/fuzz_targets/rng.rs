@@ -62,6 +62,7 @@ fuzz_target!(|bytes: &[u8]| -> Corpus {
     let mut rng = virtio_devices::Rng::new(
         "fuzzer_rng".to_owned(),
         "/dev/urandom",
+        None,
         false,
         SeccompAction::Allow,
         EventFd::new(EFD_NONBLOCK).unwrap(),
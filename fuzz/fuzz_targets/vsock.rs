@@ -101,6 +101,7 @@ fuzz_target!(|bytes: &[u8]| -> Corpus {
         vsock_path,
         backend,
         false,
+        false,
         SeccompAction::Allow,
         EventFd::new(EFD_NONBLOCK).unwrap(),
         None,
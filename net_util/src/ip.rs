@@ -0,0 +1,209 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use serde::de::{Deserialize, Deserializer, Error as SerdeError};
+use serde::ser::{Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum IpNetworkError {
+    #[error("Invalid IP address: {0}")]
+    InvalidAddress(String),
+    #[error("Invalid prefix length: {0}")]
+    InvalidPrefixLength(String),
+    #[error("Address {0} and netmask {1} are not from the same family")]
+    FamilyMismatch(IpAddr, IpAddr),
+    #[error("Netmask {0} is not contiguous")]
+    NonContiguousNetmask(IpAddr),
+}
+
+/// An IPv4 or IPv6 address along with the prefix length of the subnet it
+/// belongs to, e.g. `192.168.249.1/24` or `fd00::1/64`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+fn max_prefix_len(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+impl IpNetwork {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, IpNetworkError> {
+        if prefix_len > max_prefix_len(&addr) {
+            return Err(IpNetworkError::InvalidPrefixLength(prefix_len.to_string()));
+        }
+
+        Ok(IpNetwork { addr, prefix_len })
+    }
+
+    /// Build a network from an address and a netmask expressed as an
+    /// address of the same family, e.g. `255.255.255.0` or `ffff:ffff::`.
+    pub fn with_netmask(addr: IpAddr, netmask: IpAddr) -> Result<Self, IpNetworkError> {
+        // Left-align IPv4 masks so both families can be checked the same way.
+        let mask = match (addr, netmask) {
+            (IpAddr::V4(_), IpAddr::V4(m)) => (u32::from(m) as u128) << 96,
+            (IpAddr::V6(_), IpAddr::V6(m)) => u128::from(m),
+            _ => return Err(IpNetworkError::FamilyMismatch(addr, netmask)),
+        };
+
+        let prefix_len = mask.leading_ones();
+        // All bits set in the mask must be at the front.
+        if mask.count_ones() != prefix_len {
+            return Err(IpNetworkError::NonContiguousNetmask(netmask));
+        }
+
+        Self::new(addr, prefix_len as u8)
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn netmask(&self) -> IpAddr {
+        match self.addr {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(
+                u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0),
+            )),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(
+                u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = IpNetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| IpNetworkError::InvalidAddress(addr.to_owned()))?;
+        let prefix_len = match prefix_len {
+            Some(p) => p
+                .parse::<u8>()
+                .map_err(|_| IpNetworkError::InvalidPrefixLength(p.to_owned()))?,
+            // A bare address is a host route.
+            None => max_prefix_len(&addr),
+        };
+
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl Serialize for IpNetwork {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpNetwork {
+    fn deserialize<D>(deserializer: D) -> Result<IpNetwork, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|e| D::Error::custom(format!("The provided IP network is invalid: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_network_parse() {
+        let net: IpNetwork = "192.168.249.1/24".parse().unwrap();
+        assert_eq!(net.addr(), IpAddr::V4(Ipv4Addr::new(192, 168, 249, 1)));
+        assert_eq!(net.prefix_len(), 24);
+        assert_eq!(net.netmask(), "255.255.255.0".parse::<IpAddr>().unwrap());
+
+        let net: IpNetwork = "fd00::1/64".parse().unwrap();
+        assert_eq!(net.addr(), "fd00::1".parse::<IpAddr>().unwrap());
+        assert_eq!(net.prefix_len(), 64);
+        assert_eq!(
+            net.netmask(),
+            "ffff:ffff:ffff:ffff::".parse::<IpAddr>().unwrap()
+        );
+
+        let net: IpNetwork = "fd00::1".parse().unwrap();
+        assert_eq!(net.prefix_len(), 128);
+        let net: IpNetwork = "10.0.0.1/0".parse().unwrap();
+        assert_eq!(net.netmask(), "0.0.0.0".parse::<IpAddr>().unwrap());
+
+        "10.0.0.1/33".parse::<IpNetwork>().unwrap_err();
+        "fd00::1/129".parse::<IpNetwork>().unwrap_err();
+        "10.0.0/24".parse::<IpNetwork>().unwrap_err();
+        "10.0.0.1/".parse::<IpNetwork>().unwrap_err();
+    }
+
+    #[test]
+    fn test_ip_network_with_netmask() {
+        let net = IpNetwork::with_netmask(
+            "192.168.249.1".parse().unwrap(),
+            "255.255.255.128".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(net.prefix_len(), 25);
+
+        let net = IpNetwork::with_netmask(
+            "fd00::1".parse().unwrap(),
+            "ffff:ffff:ffff:ffff:ffff::".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(net.prefix_len(), 80);
+
+        assert_eq!(
+            IpNetwork::with_netmask("fd00::1".parse().unwrap(), "255.255.255.0".parse().unwrap()),
+            Err(IpNetworkError::FamilyMismatch(
+                "fd00::1".parse().unwrap(),
+                "255.255.255.0".parse().unwrap()
+            ))
+        );
+        IpNetwork::with_netmask("10.0.0.1".parse().unwrap(), "255.0.255.0".parse().unwrap())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_ip_network_serialization_and_deserialization() {
+        let net: IpNetwork =
+            serde_json::from_str("\"fd00::1/64\"").expect("IpNetwork deserialization failed.");
+        assert_eq!(net.prefix_len(), 64);
+
+        let s = serde_json::to_string(&net).expect("IpNetwork serialization failed.");
+        assert_eq!(s, "\"fd00::1/64\"");
+    }
+}
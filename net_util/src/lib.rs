@@ -9,6 +9,7 @@
 extern crate log;
 
 mod ctrl_queue;
+mod ip;
 mod mac;
 mod open_tap;
 mod queue_pair;
@@ -32,9 +33,12 @@ use vm_memory::ByteValued;
 type GuestMemoryMmap = vm_memory::GuestMemoryMmap<AtomicBitmap>;
 
 pub use ctrl_queue::{CtrlQueue, Error as CtrlQueueError};
+pub use ip::{IpNetwork, IpNetworkError};
 pub use mac::{MacAddr, MAC_ADDR_LEN};
 pub use open_tap::{open_tap, Error as OpenTapError};
-pub use queue_pair::{NetCounters, NetQueuePair, NetQueuePairError, RxVirtio, TxVirtio};
+pub use queue_pair::{
+    NetCounters, NetQueuePair, NetQueuePairError, NetRateLimiter, RxVirtio, TxVirtio,
+};
 pub use tap::{Error as TapError, Tap};
 
 #[derive(Error, Debug)]
@@ -87,6 +91,17 @@ fn create_inet_socket() -> Result<net::UdpSocket> {
     Ok(unsafe { net::UdpSocket::from_raw_fd(sock) })
 }
 
+fn create_inet6_socket() -> Result<net::UdpSocket> {
+    // SAFETY: we check the return value.
+    let sock = unsafe { libc::socket(libc::AF_INET6, libc::SOCK_DGRAM, 0) };
+    if sock < 0 {
+        return Err(Error::CreateSocket(IoError::last_os_error()));
+    }
+
+    // SAFETY: nothing else will use or hold onto the raw sock fd.
+    Ok(unsafe { net::UdpSocket::from_raw_fd(sock) })
+}
+
 fn create_unix_socket() -> Result<net::UdpSocket> {
     // SAFETY: we check the return value.
     let sock = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM, 0) };
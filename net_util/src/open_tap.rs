@@ -2,13 +2,13 @@
 //
 // SPDX-License-Identifier: Apache-2.0 AND BSD-3-Clause
 
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 use std::path::Path;
 use std::{fs, io};
 
 use thiserror::Error;
 
-use super::{vnet_hdr_len, MacAddr, Tap, TapError};
+use super::{vnet_hdr_len, IpNetwork, MacAddr, Tap, TapError};
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -26,6 +26,10 @@ pub enum Error {
     TapSetIp(TapError),
     #[error("Setting tap netmask failed: {0}")]
     TapSetNetmask(TapError),
+    #[error("Adding tap IPv6 address failed: {0}")]
+    TapAddIpv6(TapError),
+    #[error("Only a single IPv4 address can be assigned to a tap interface")]
+    MultipleIpv4Addresses,
     #[error("Setting MAC address failed: {0}")]
     TapSetMac(TapError),
     #[error("Getting MAC address failed: {0}")]
@@ -60,12 +64,35 @@ fn check_mq_support(if_name: &Option<&str>, queue_pairs: usize) -> Result<()> {
     Ok(())
 }
 
-/// Create a new virtio network device with the given IP address and
-/// netmask.
+fn set_ip_addrs(tap: &Tap, ip_addrs: &[IpNetwork]) -> Result<()> {
+    if ip_addrs.iter().filter(|n| n.addr().is_ipv4()).count() > 1 {
+        return Err(Error::MultipleIpv4Addresses);
+    }
+
+    for ip_network in ip_addrs {
+        match ip_network.addr() {
+            IpAddr::V4(ip) => {
+                tap.set_ip_addr(ip).map_err(Error::TapSetIp)?;
+                if let IpAddr::V4(mask) = ip_network.netmask() {
+                    tap.set_netmask(mask).map_err(Error::TapSetNetmask)?;
+                }
+            }
+            IpAddr::V6(ip) => {
+                tap.add_ipv6_addr(ip, ip_network.prefix_len())
+                    .map_err(Error::TapAddIpv6)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Create a new virtio network device with the given IP addresses. At most
+/// one IPv4 address can be provided, while any number of IPv6 addresses are
+/// accepted.
 pub fn open_tap(
     if_name: Option<&str>,
-    ip_addr: Option<Ipv4Addr>,
-    netmask: Option<Ipv4Addr>,
+    ip_addrs: &[IpNetwork],
     host_mac: &mut Option<MacAddr>,
     mtu: Option<u16>,
     num_rx_q: usize,
@@ -93,12 +120,7 @@ pub fn open_tap(
             };
             // Don't overwrite ip configuration of existing interfaces:
             if !tap_existed {
-                if let Some(ip) = ip_addr {
-                    tap.set_ip_addr(ip).map_err(Error::TapSetIp)?;
-                }
-                if let Some(mask) = netmask {
-                    tap.set_netmask(mask).map_err(Error::TapSetNetmask)?;
-                }
+                set_ip_addrs(&tap, ip_addrs)?;
             } else {
                 warn!(
                     "Tap {} already exists. IP configuration will not be overwritten.",
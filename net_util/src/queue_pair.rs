@@ -8,7 +8,8 @@ use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use rate_limiter::{RateLimiter, TokenType};
+use rate_limiter::group::RateLimiterGroupHandle;
+use rate_limiter::{RateLimiter, ThrottleStats, TokenType};
 use thiserror::Error;
 use virtio_queue::{Queue, QueueOwnedT, QueueT};
 use vm_memory::bitmap::Bitmap;
@@ -44,7 +45,7 @@ impl TxVirtio {
         mem: &vm_memory::GuestMemoryMmap<B>,
         tap: &Tap,
         queue: &mut Queue,
-        rate_limiter: &mut Option<RateLimiter>,
+        rate_limiter: &mut Option<NetRateLimiter>,
         access_platform: Option<&Arc<dyn AccessPlatform>>,
     ) -> Result<bool, NetQueuePairError> {
         let mut retry_write = false;
@@ -172,7 +173,7 @@ impl RxVirtio {
         mem: &vm_memory::GuestMemoryMmap<B>,
         tap: &Tap,
         queue: &mut Queue,
-        rate_limiter: &mut Option<RateLimiter>,
+        rate_limiter: &mut Option<NetRateLimiter>,
         access_platform: Option<&Arc<dyn AccessPlatform>>,
     ) -> Result<bool, NetQueuePairError> {
         let mut exhausted_descs = true;
@@ -379,6 +380,62 @@ pub enum NetQueuePairError {
     DescriptorInvalidHeader,
     #[error("Invalid virtio-net header")]
     InvalidVirtioNetHeader,
+    #[error("Error handling rate limiter event: {0:?}")]
+    RateLimiterEventHandler(rate_limiter::Error),
+    #[error("Error handling rate limiter group event: {0}")]
+    RateLimiterGroupEventHandler(rate_limiter::group::Error),
+}
+
+/// Rate limiter throttling one direction of a `NetQueuePair`. It is either
+/// dedicated to the queue, or shared with other queues, possibly from other
+/// devices, through a `RateLimiterGroup`.
+pub enum NetRateLimiter {
+    Queue(Arc<RateLimiter>),
+    Group(RateLimiterGroupHandle),
+}
+
+impl NetRateLimiter {
+    pub fn consume(&self, tokens: u64, token_type: TokenType) -> bool {
+        match self {
+            NetRateLimiter::Queue(rate_limiter) => rate_limiter.consume(tokens, token_type),
+            NetRateLimiter::Group(handle) => handle.consume(tokens, token_type),
+        }
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        match self {
+            NetRateLimiter::Queue(rate_limiter) => rate_limiter.is_blocked(),
+            NetRateLimiter::Group(handle) => handle.is_blocked(),
+        }
+    }
+
+    pub fn throttle_stats(&self) -> Arc<ThrottleStats> {
+        match self {
+            NetRateLimiter::Queue(rate_limiter) => rate_limiter.throttle_stats(),
+            NetRateLimiter::Group(handle) => handle.throttle_stats(),
+        }
+    }
+
+    /// Must be called upon every event on the FD returned by `as_raw_fd()`.
+    pub fn event_handler(&self) -> Result<(), NetQueuePairError> {
+        match self {
+            NetRateLimiter::Queue(rate_limiter) => rate_limiter
+                .event_handler()
+                .map_err(NetQueuePairError::RateLimiterEventHandler),
+            NetRateLimiter::Group(handle) => handle
+                .event_handler()
+                .map_err(NetQueuePairError::RateLimiterGroupEventHandler),
+        }
+    }
+}
+
+impl AsRawFd for NetRateLimiter {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            NetRateLimiter::Queue(rate_limiter) => rate_limiter.as_raw_fd(),
+            NetRateLimiter::Group(handle) => handle.as_raw_fd(),
+        }
+    }
 }
 
 pub struct NetQueuePair {
@@ -397,8 +454,8 @@ pub struct NetQueuePair {
     pub tap_rx_event_id: u16,
     pub tap_tx_event_id: u16,
     pub rx_desc_avail: bool,
-    pub rx_rate_limiter: Option<RateLimiter>,
-    pub tx_rate_limiter: Option<RateLimiter>,
+    pub rx_rate_limiter: Option<NetRateLimiter>,
+    pub tx_rate_limiter: Option<NetRateLimiter>,
     pub access_platform: Option<Arc<dyn AccessPlatform>>,
 }
 
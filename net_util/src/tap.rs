@@ -15,8 +15,8 @@ use thiserror::Error;
 use vmm_sys_util::ioctl::{ioctl_with_mut_ref, ioctl_with_ref, ioctl_with_val};
 
 use super::{
-    create_inet_socket, create_sockaddr, create_unix_socket, vnet_hdr_len, Error as NetUtilError,
-    MacAddr,
+    create_inet6_socket, create_inet_socket, create_sockaddr, create_unix_socket, vnet_hdr_len,
+    Error as NetUtilError, MacAddr,
 };
 use crate::mac::MAC_ADDR_LEN;
 
@@ -247,6 +247,37 @@ impl Tap {
         unsafe { Self::ioctl_with_ref(&sock, net_gen::sockios::SIOCSIFADDR as c_ulong, &ifreq) }
     }
 
+    /// Add a host-side IPv6 address to the tap interface. Contrary to
+    /// `set_ip_addr()`, this can be called several times to assign multiple
+    /// addresses.
+    pub fn add_ipv6_addr(&self, ip_addr: net::Ipv6Addr, prefix_len: u8) -> Result<()> {
+        let sock = create_inet6_socket().map_err(Error::NetUtil)?;
+
+        let ifreq = libc::in6_ifreq {
+            ifr6_addr: libc::in6_addr {
+                s6_addr: ip_addr.octets(),
+            },
+            ifr6_prefixlen: prefix_len as u32,
+            ifr6_ifindex: self.if_index()?,
+        };
+
+        // SAFETY: ioctl is safe. Called with a valid sock fd, and we check the return.
+        unsafe { Self::ioctl_with_ref(&sock, net_gen::sockios::SIOCSIFADDR as c_ulong, &ifreq) }
+    }
+
+    /// Get the index of the tap interface.
+    pub fn if_index(&self) -> Result<c_int> {
+        let sock = create_unix_socket().map_err(Error::NetUtil)?;
+
+        let ifreq = self.get_ifreq();
+
+        // SAFETY: ioctl is safe. Called with a valid sock fd, and we check the return.
+        unsafe { Self::ioctl_with_ref(&sock, net_gen::sockios::SIOCGIFINDEX as c_ulong, &ifreq)? };
+
+        // SAFETY: access a union field
+        Ok(unsafe { ifreq.ifr_ifru.ifru_ivalue })
+    }
+
     /// Set mac addr for tap interface.
     pub fn set_mac_addr(&self, addr: MacAddr) -> Result<()> {
         // Checking if the mac address already matches the desired one
@@ -609,6 +640,19 @@ mod tests {
         tap.set_netmask(netmask).unwrap();
     }
 
+    #[test]
+    fn test_tap_configure_ipv6() {
+        let _tap_ip_guard = TAP_IP_LOCK.lock().unwrap();
+
+        let tap = Tap::new(1).unwrap();
+        assert!(tap.if_index().unwrap() > 0);
+
+        tap.add_ipv6_addr("fd00:cafe::1".parse().unwrap(), 64)
+            .unwrap();
+        tap.add_ipv6_addr("fd00:beef::1".parse().unwrap(), 64)
+            .unwrap();
+    }
+
     #[test]
     fn test_set_options() {
         let _tap_ip_guard = TAP_IP_LOCK.lock().unwrap();
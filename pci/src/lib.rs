@@ -15,6 +15,7 @@ mod msi;
 mod msix;
 mod vfio;
 mod vfio_user;
+mod vfio_user_client;
 
 use std::fmt::{self, Debug, Display};
 use std::num::ParseIntError;
@@ -36,6 +37,7 @@ pub use self::msi::{msi_num_enabled_vectors, MsiCap, MsiConfig};
 pub use self::msix::{MsixCap, MsixConfig, MsixTableEntry, MSIX_CONFIG_ID, MSIX_TABLE_ENTRY_SIZE};
 pub use self::vfio::{MmioRegion, VfioDmaMapping, VfioPciDevice, VfioPciError};
 pub use self::vfio_user::{VfioUserDmaMapping, VfioUserPciDevice, VfioUserPciDeviceError};
+pub use self::vfio_user_client::VfioUserClient;
 
 /// PCI has four interrupt pins A->D.
 #[derive(Copy, Clone)]
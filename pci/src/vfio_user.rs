@@ -8,11 +8,13 @@ use std::os::unix::prelude::AsRawFd;
 use std::ptr::null_mut;
 use std::sync::{Arc, Barrier, Mutex};
 
+use anyhow::anyhow;
 use hypervisor::HypervisorVmError;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use vfio_bindings::bindings::vfio::*;
 use vfio_ioctls::VfioIrq;
-use vfio_user::{Client, Error as VfioUserError};
+use vfio_user::Error as VfioUserError;
 use vm_allocator::{AddressAllocator, MemorySlotAllocator, SystemAllocator};
 use vm_device::dma_mapping::ExternalDmaMapping;
 use vm_device::interrupt::{InterruptManager, InterruptSourceGroup, MsiIrqGroupConfig};
@@ -21,21 +23,46 @@ use vm_memory::bitmap::AtomicBitmap;
 use vm_memory::{
     Address, GuestAddress, GuestAddressSpace, GuestMemory, GuestMemoryRegion, GuestRegionMmap,
 };
+use vm_migration::protocol::MemoryRangeTable;
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::vfio::{UserMemoryRegion, Vfio, VfioCommon, VfioError, VFIO_COMMON_ID};
+use crate::vfio_user_client::{
+    VfioUserClient, VFIO_DEVICE_STATE_RESUMING, VFIO_DEVICE_STATE_RUNNING, VFIO_DEVICE_STATE_STOP,
+    VFIO_DEVICE_STATE_STOP_COPY, VFIO_MIGRATION_STOP_COPY,
+};
 use crate::{
     BarReprogrammingParams, PciBarConfiguration, PciBdf, PciDevice, PciDeviceError, PciSubclass,
     VfioPciError,
 };
 
+const VFIO_USER_MIGRATION_ID: &str = "vfio_user_migration";
+
+// Granularity of the DMA dirty page logging, matching the one used for the
+// guest memory.
+const DIRTY_LOG_PAGE_SIZE: u64 = 4096;
+
+/// Internal state of the device, as read from the server in the STOP_COPY
+/// state of the VFIO migration protocol.
+#[derive(Serialize, Deserialize)]
+struct VfioUserMigrationState {
+    data: Vec<u8>,
+}
+
 pub struct VfioUserPciDevice {
     id: String,
     vm: Arc<dyn hypervisor::Vm>,
-    client: Arc<Mutex<Client>>,
+    client: Arc<Mutex<VfioUserClient>>,
     common: VfioCommon,
     memory_slot_allocator: MemorySlotAllocator,
+    // The server supports the STOP_COPY migration state, and the migration
+    // messages can be sent to it.
+    migratable: bool,
+    // Device state restored from a snapshot, written to the server right
+    // before the device is resumed.
+    pending_state: Option<VfioUserMigrationState>,
+    dirty_log: bool,
 }
 
 #[derive(Error, Debug)]
@@ -52,6 +79,14 @@ pub enum VfioUserPciDeviceError {
     InitializeLegacyInterrupts(#[source] VfioPciError),
     #[error("Failed to create VfioCommon: {0}")]
     CreateVfioCommon(#[source] VfioPciError),
+    #[error("Device is not migratable")]
+    MigrationNotSupported,
+    #[error("Failed to set the device state to {0}: {1}")]
+    SetDeviceState(u32, #[source] VfioUserError),
+    #[error("Failed to transfer the migration data: {0}")]
+    MigrationData(#[source] VfioUserError),
+    #[error("Failed to retrieve the migration state: {0}")]
+    RetrieveMigrationState(#[source] MigratableError),
 }
 
 #[derive(Copy, Clone)]
@@ -70,7 +105,8 @@ impl VfioUserPciDevice {
     pub fn new(
         id: String,
         vm: &Arc<dyn hypervisor::Vm>,
-        client: Arc<Mutex<Client>>,
+        client: Arc<Mutex<VfioUserClient>>,
+        migratable: bool,
         msi_interrupt_manager: Arc<dyn InterruptManager<GroupConfig = MsiIrqGroupConfig>>,
         legacy_interrupt_group: Option<Arc<dyn InterruptSourceGroup>>,
         bdf: PciBdf,
@@ -101,15 +137,81 @@ impl VfioUserPciDevice {
         )
         .map_err(VfioUserPciDeviceError::CreateVfioCommon)?;
 
+        // The migration messages are only sent to servers known to support
+        // them, as others may not cope with unknown commands.
+        if migratable {
+            let flags = client
+                .lock()
+                .unwrap()
+                .migration_flags()
+                .map_err(VfioUserPciDeviceError::Client)?;
+            if flags & VFIO_MIGRATION_STOP_COPY == 0 {
+                return Err(VfioUserPciDeviceError::MigrationNotSupported);
+            }
+        }
+
+        let pending_state: Option<VfioUserMigrationState> =
+            vm_migration::state_from_id(snapshot.as_ref(), VFIO_USER_MIGRATION_ID)
+                .map_err(VfioUserPciDeviceError::RetrieveMigrationState)?;
+        if pending_state.is_some() && !migratable {
+            return Err(VfioUserPciDeviceError::MigrationNotSupported);
+        }
+
         Ok(Self {
             id,
             vm: vm.clone(),
             client,
             common,
             memory_slot_allocator,
+            migratable,
+            pending_state,
+            dirty_log: false,
         })
     }
 
+    fn set_device_state(&self, state: u32) -> Result<(), VfioUserPciDeviceError> {
+        self.client
+            .lock()
+            .unwrap()
+            .set_device_state(state)
+            .map_err(|e| VfioUserPciDeviceError::SetDeviceState(state, e))
+    }
+
+    /// Read the internal state of the device, which must be stopped. The
+    /// device is left stopped.
+    fn save_device_state(&self) -> Result<VfioUserMigrationState, VfioUserPciDeviceError> {
+        self.set_device_state(VFIO_DEVICE_STATE_STOP_COPY)?;
+
+        let data = self
+            .client
+            .lock()
+            .unwrap()
+            .read_migration_data()
+            .map_err(VfioUserPciDeviceError::MigrationData)?;
+
+        self.set_device_state(VFIO_DEVICE_STATE_STOP)?;
+
+        Ok(VfioUserMigrationState { data })
+    }
+
+    /// Load a previously saved internal state into the device, leaving it
+    /// stopped.
+    fn restore_device_state(
+        &self,
+        state: &VfioUserMigrationState,
+    ) -> Result<(), VfioUserPciDeviceError> {
+        self.set_device_state(VFIO_DEVICE_STATE_STOP)?;
+        self.set_device_state(VFIO_DEVICE_STATE_RESUMING)?;
+
+        self.client
+            .lock()
+            .unwrap()
+            .write_migration_data(&state.data)
+            .map_err(VfioUserPciDeviceError::MigrationData)?;
+
+        self.set_device_state(VFIO_DEVICE_STATE_STOP)
+    }
+
     pub fn map_mmio_regions(&mut self) -> Result<(), VfioUserPciDeviceError> {
         for mmio_region in &mut self.common.mmio_regions {
             let region_flags = self
@@ -299,7 +401,7 @@ enum Regions {
 }
 
 struct VfioUserClientWrapper {
-    client: Arc<Mutex<Client>>,
+    client: Arc<Mutex<VfioUserClient>>,
 }
 
 impl Vfio for VfioUserClientWrapper {
@@ -537,7 +639,30 @@ impl Drop for VfioUserPciDevice {
     }
 }
 
-impl Pausable for VfioUserPciDevice {}
+impl Pausable for VfioUserPciDevice {
+    fn pause(&mut self) -> std::result::Result<(), MigratableError> {
+        if self.migratable {
+            self.set_device_state(VFIO_DEVICE_STATE_STOP)
+                .map_err(|e| MigratableError::Pause(anyhow!("{}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    fn resume(&mut self) -> std::result::Result<(), MigratableError> {
+        if let Some(state) = self.pending_state.take() {
+            self.restore_device_state(&state)
+                .map_err(|e| MigratableError::Restore(anyhow!("{}", e)))?;
+        }
+
+        if self.migratable {
+            self.set_device_state(VFIO_DEVICE_STATE_RUNNING)
+                .map_err(|e| MigratableError::Resume(anyhow!("{}", e)))?;
+        }
+
+        Ok(())
+    }
+}
 
 impl Snapshottable for VfioUserPciDevice {
     fn id(&self) -> String {
@@ -550,19 +675,120 @@ impl Snapshottable for VfioUserPciDevice {
         // Snapshot VfioCommon
         vfio_pci_dev_snapshot.add_snapshot(self.common.id(), self.common.snapshot()?);
 
+        // Snapshot the internal state of the device. A state restored but
+        // not yet loaded into the device is carried over as is.
+        if let Some(state) = &self.pending_state {
+            vfio_pci_dev_snapshot.add_snapshot(
+                VFIO_USER_MIGRATION_ID.to_string(),
+                Snapshot::new_from_state(state)?,
+            );
+        } else if self.migratable {
+            let state = self
+                .save_device_state()
+                .map_err(|e| MigratableError::Snapshot(anyhow!("{}", e)))?;
+            vfio_pci_dev_snapshot.add_snapshot(
+                VFIO_USER_MIGRATION_ID.to_string(),
+                Snapshot::new_from_state(&state)?,
+            );
+        } else {
+            warn!(
+                "vfio-user device {} is not migratable, its internal state is not saved",
+                self.id
+            );
+        }
+
         Ok(vfio_pci_dev_snapshot)
     }
 }
+
 impl Transportable for VfioUserPciDevice {}
-impl Migratable for VfioUserPciDevice {}
+
+impl Migratable for VfioUserPciDevice {
+    fn start_migration(&mut self) -> std::result::Result<(), MigratableError> {
+        if !self.migratable {
+            return Err(MigratableError::StartMigration(anyhow!(
+                "vfio-user device {}: {}",
+                self.id,
+                VfioUserPciDeviceError::MigrationNotSupported
+            )));
+        }
+
+        Ok(())
+    }
+
+    // The server writes directly into guest memory, bypassing the dirty page
+    // tracking from the hypervisor, so it logs the pages it writes to itself.
+    fn start_dirty_log(&mut self) -> std::result::Result<(), MigratableError> {
+        if !self.migratable {
+            return Ok(());
+        }
+
+        self.client
+            .lock()
+            .unwrap()
+            .start_dma_logging(DIRTY_LOG_PAGE_SIZE)
+            .map_err(|e| {
+                MigratableError::StartDirtyLog(anyhow!(
+                    "Error starting DMA logging for vfio-user device {}: {}",
+                    self.id,
+                    e
+                ))
+            })?;
+        self.dirty_log = true;
+
+        Ok(())
+    }
+
+    fn stop_dirty_log(&mut self) -> std::result::Result<(), MigratableError> {
+        if !self.dirty_log {
+            return Ok(());
+        }
+
+        self.dirty_log = false;
+        self.client.lock().unwrap().stop_dma_logging().map_err(|e| {
+            MigratableError::StopDirtyLog(anyhow!(
+                "Error stopping DMA logging for vfio-user device {}: {}",
+                self.id,
+                e
+            ))
+        })
+    }
+
+    fn dirty_log(&mut self) -> std::result::Result<MemoryRangeTable, MigratableError> {
+        let mut table = MemoryRangeTable::default();
+        if !self.dirty_log {
+            return Ok(table);
+        }
+
+        let mut client = self.client.lock().unwrap();
+        for (iova, length) in client.dma_ranges() {
+            let bitmap = client
+                .dma_logging_report(iova, length, DIRTY_LOG_PAGE_SIZE)
+                .map_err(|e| {
+                    MigratableError::DirtyLog(anyhow!(
+                        "Error retrieving dirty pages of vfio-user device {}: {}",
+                        self.id,
+                        e
+                    ))
+                })?;
+            table.extend(MemoryRangeTable::from_bitmap(
+                bitmap,
+                iova,
+                DIRTY_LOG_PAGE_SIZE,
+            ));
+        }
+
+        Ok(table)
+    }
+}
 
 pub struct VfioUserDmaMapping<M: GuestAddressSpace> {
-    client: Arc<Mutex<Client>>,
+    client: Arc<Mutex<VfioUserClient>>,
     memory: Arc<M>,
 }
 
 impl<M: GuestAddressSpace> VfioUserDmaMapping<M> {
-    pub fn new(client: Arc<Mutex<Client>>, memory: Arc<M>) -> Self {
+    pub fn new(client: Arc<Mutex<VfioUserClient>>, memory: Arc<M>) -> Self {
         Self { client, memory }
     }
 }
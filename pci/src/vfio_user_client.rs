@@ -0,0 +1,1057 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! vfio-user client.
+//!
+//! Same protocol handling as `vfio_user::Client`, which does not give access
+//! to its socket, extended with the messages of the VFIO migration protocol
+//! (version 2): device features, migration data transfer and DMA dirty page
+//! logging.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use std::num::Wrapping;
+use std::os::unix::io::RawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use vfio_bindings::bindings::vfio::*;
+use vfio_user::{DmaMapFlags, Error, IrqInfo, Region};
+use vm_memory::{ByteValued, FileOffset};
+use vmm_sys_util::sock_ctrl_msg::ScmSocket;
+
+// Commands, as defined by the vfio-user specification.
+const VFIO_USER_VERSION: u16 = 1;
+const VFIO_USER_DMA_MAP: u16 = 2;
+const VFIO_USER_DMA_UNMAP: u16 = 3;
+const VFIO_USER_DEVICE_GET_INFO: u16 = 4;
+const VFIO_USER_DEVICE_GET_REGION_INFO: u16 = 5;
+const VFIO_USER_DEVICE_GET_IRQ_INFO: u16 = 7;
+const VFIO_USER_DEVICE_SET_IRQS: u16 = 8;
+const VFIO_USER_REGION_READ: u16 = 9;
+const VFIO_USER_REGION_WRITE: u16 = 10;
+const VFIO_USER_DEVICE_RESET: u16 = 13;
+const VFIO_USER_DEVICE_FEATURE: u16 = 16;
+const VFIO_USER_MIG_DATA_READ: u16 = 17;
+const VFIO_USER_MIG_DATA_WRITE: u16 = 18;
+
+const HEADER_FLAGS_TYPE_MASK: u32 = 0xf;
+const HEADER_FLAGS_COMMAND: u32 = 0;
+const HEADER_FLAGS_REPLY: u32 = 1;
+const HEADER_FLAGS_ERROR: u32 = 1 << 5;
+
+const DEVICE_FEATURE_GET: u32 = 1 << 16;
+const DEVICE_FEATURE_SET: u32 = 1 << 17;
+
+const DEVICE_FEATURE_MIGRATION: u32 = 1;
+const DEVICE_FEATURE_MIG_DEVICE_STATE: u32 = 2;
+const DEVICE_FEATURE_DMA_LOGGING_START: u32 = 6;
+const DEVICE_FEATURE_DMA_LOGGING_STOP: u32 = 7;
+const DEVICE_FEATURE_DMA_LOGGING_REPORT: u32 = 8;
+
+/// The device supports the STOP_COPY migration state.
+pub const VFIO_MIGRATION_STOP_COPY: u64 = 1 << 0;
+
+/// Migration states of a device.
+pub const VFIO_DEVICE_STATE_STOP: u32 = 1;
+pub const VFIO_DEVICE_STATE_RUNNING: u32 = 2;
+pub const VFIO_DEVICE_STATE_STOP_COPY: u32 = 3;
+pub const VFIO_DEVICE_STATE_RESUMING: u32 = 4;
+
+const DEFAULT_MAX_MSG_FDS: u32 = 1;
+const DEFAULT_MAX_DATA_XFER_SIZE: u32 = 1048576;
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct Header {
+    message_id: u16,
+    command: u16,
+    message_size: u32,
+    flags: u32,
+    error: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct Version {
+    major: u16,
+    minor: u16,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct DmaMap {
+    argsz: u32,
+    flags: u32,
+    offset: u64,
+    address: u64,
+    size: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct DmaUnmap {
+    argsz: u32,
+    flags: u32,
+    address: u64,
+    size: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct DeviceInfo {
+    argsz: u32,
+    flags: u32,
+    num_regions: u32,
+    num_irqs: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct RegionInfo {
+    argsz: u32,
+    flags: u32,
+    index: u32,
+    cap_offset: u32,
+    size: u64,
+    offset: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct CapHeader {
+    id: u16,
+    version: u16,
+    next: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct SparseMmapCap {
+    header: CapHeader,
+    nr_areas: u32,
+    reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct SparseMmapArea {
+    offset: u64,
+    size: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct RegionAccess {
+    offset: u64,
+    region: u32,
+    count: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct IrqInfoMessage {
+    argsz: u32,
+    flags: u32,
+    index: u32,
+    count: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct SetIrqs {
+    argsz: u32,
+    flags: u32,
+    index: u32,
+    start: u32,
+    count: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct DeviceFeature {
+    argsz: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct MigrationFeature {
+    flags: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct MigStateFeature {
+    device_state: u32,
+    data_fd: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct DmaLoggingControl {
+    page_size: u64,
+    num_ranges: u32,
+    reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct DmaLoggingRange {
+    iova: u64,
+    length: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct DmaLoggingReport {
+    iova: u64,
+    length: u64,
+    page_size: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy, Debug)]
+struct MigData {
+    argsz: u32,
+    size: u32,
+}
+
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for Header {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for Version {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for DmaMap {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for DmaUnmap {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for DeviceInfo {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for RegionInfo {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for CapHeader {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for SparseMmapCap {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for SparseMmapArea {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for RegionAccess {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for IrqInfoMessage {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for SetIrqs {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for DeviceFeature {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for MigrationFeature {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for MigStateFeature {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for DmaLoggingControl {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for DmaLoggingRange {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for DmaLoggingReport {}
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for MigData {}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MigrationCapabilities {
+    pgsize: u32,
+}
+
+const fn default_max_msg_fds() -> u32 {
+    DEFAULT_MAX_MSG_FDS
+}
+
+const fn default_max_data_xfer_size() -> u32 {
+    DEFAULT_MAX_DATA_XFER_SIZE
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Capabilities {
+    #[serde(default = "default_max_msg_fds")]
+    max_msg_fds: u32,
+    #[serde(default = "default_max_data_xfer_size")]
+    max_data_xfer_size: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    migration: Option<MigrationCapabilities>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CapabilitiesData {
+    capabilities: Capabilities,
+}
+
+fn invalid_reply(message: &str) -> Error {
+    Error::StreamRead(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+// Copy a structure out of a message payload, which carries no alignment
+// guarantee.
+fn read_obj<T: ByteValued + Default>(data: &[u8], offset: usize) -> Result<T, Error> {
+    let mut obj = T::default();
+    let bytes = offset
+        .checked_add(size_of::<T>())
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| invalid_reply("Truncated reply"))?;
+    obj.as_mut_slice().copy_from_slice(bytes);
+    Ok(obj)
+}
+
+// Payload of a reply, along with the file descriptor it carried if any.
+struct Reply {
+    data: Vec<u8>,
+    file: Option<File>,
+}
+
+pub struct VfioUserClient {
+    stream: UnixStream,
+    next_message_id: Wrapping<u16>,
+    max_data_xfer_size: u32,
+    resettable: bool,
+    regions: Vec<Region>,
+    // Guest memory mapped for DMA, indexed by address.
+    dma_ranges: BTreeMap<u64, u64>,
+}
+
+impl VfioUserClient {
+    pub fn new(path: &Path) -> Result<VfioUserClient, Error> {
+        let stream = UnixStream::connect(path).map_err(Error::Connect)?;
+
+        let mut client = VfioUserClient {
+            stream,
+            next_message_id: Wrapping(0),
+            max_data_xfer_size: DEFAULT_MAX_DATA_XFER_SIZE,
+            resettable: false,
+            regions: Vec::new(),
+            dma_ranges: BTreeMap::new(),
+        };
+
+        client.negotiate_version()?;
+
+        client.regions = client.get_regions()?;
+
+        Ok(client)
+    }
+
+    // Send a command and wait for its reply, failing with the error the
+    // server replied with if any.
+    fn send_command(
+        &mut self,
+        command: u16,
+        payload: &[&[u8]],
+        fds: &[RawFd],
+    ) -> Result<Reply, Error> {
+        let header = Header {
+            message_id: self.next_message_id.0,
+            command,
+            message_size: (size_of::<Header>() + payload.iter().map(|p| p.len()).sum::<usize>())
+                as u32,
+            flags: HEADER_FLAGS_COMMAND,
+            error: 0,
+        };
+        debug!("Command: {:?}", header);
+        self.next_message_id += Wrapping(1);
+
+        let mut message = header.as_slice().to_vec();
+        for p in payload {
+            message.extend_from_slice(p);
+        }
+        if fds.is_empty() {
+            self.stream
+                .write_all(&message)
+                .map_err(Error::StreamWrite)?;
+        } else {
+            self.stream
+                .send_with_fds(&[message.as_slice()], fds)
+                .map_err(Error::SendWithFd)?;
+        }
+
+        let mut reply = Header::default();
+        let (size, file) = self
+            .stream
+            .recv_with_fd(reply.as_mut_slice())
+            .map_err(Error::ReceiveWithFd)?;
+        if size == 0 {
+            return Err(Error::StreamRead(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            )));
+        }
+        self.stream
+            .read_exact(&mut reply.as_mut_slice()[size..])
+            .map_err(Error::StreamRead)?;
+        debug!("Reply: {:?}", reply);
+
+        let size = (reply.message_size as usize)
+            .checked_sub(size_of::<Header>())
+            .ok_or_else(|| invalid_reply("Invalid reply size"))?;
+        let mut data = vec![0u8; size];
+        self.stream
+            .read_exact(&mut data)
+            .map_err(Error::StreamRead)?;
+
+        if reply.message_id != header.message_id
+            || reply.command != command
+            || reply.flags & HEADER_FLAGS_TYPE_MASK != HEADER_FLAGS_REPLY
+        {
+            return Err(invalid_reply("Unexpected reply"));
+        }
+        if reply.flags & HEADER_FLAGS_ERROR != 0 {
+            return Err(Error::Backend(io::Error::from_raw_os_error(
+                reply.error as i32,
+            )));
+        }
+
+        Ok(Reply { data, file })
+    }
+
+    fn negotiate_version(&mut self) -> Result<(), Error> {
+        let caps = CapabilitiesData {
+            capabilities: Capabilities {
+                max_msg_fds: DEFAULT_MAX_MSG_FDS,
+                max_data_xfer_size: DEFAULT_MAX_DATA_XFER_SIZE,
+                migration: Some(MigrationCapabilities {
+                    // SAFETY: FFI call with valid arguments
+                    pgsize: unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u32,
+                }),
+            },
+        };
+        let mut version_data = serde_json::to_vec(&caps).map_err(Error::SerializeCapabilites)?;
+        version_data.push(0);
+
+        let version = Version { major: 0, minor: 1 };
+        let reply =
+            self.send_command(VFIO_USER_VERSION, &[version.as_slice(), &version_data], &[])?;
+
+        let server_version: Version = read_obj(&reply.data, 0)?;
+        let server_version_data = &reply.data[size_of::<Version>()..];
+        let server_version_data = server_version_data
+            .strip_suffix(&[0])
+            .unwrap_or(server_version_data);
+        let server_caps: CapabilitiesData =
+            serde_json::from_slice(server_version_data).map_err(Error::DeserializeCapabilites)?;
+        debug!(
+            "Received server version information: major = {} minor = {} capabilities = {:?}",
+            server_version.major, server_version.minor, &server_caps.capabilities
+        );
+
+        self.max_data_xfer_size = self
+            .max_data_xfer_size
+            .min(server_caps.capabilities.max_data_xfer_size);
+
+        Ok(())
+    }
+
+    fn get_regions(&mut self) -> Result<Vec<Region>, Error> {
+        let get_info = DeviceInfo {
+            argsz: size_of::<DeviceInfo>() as u32,
+            ..Default::default()
+        };
+        let reply = self.send_command(VFIO_USER_DEVICE_GET_INFO, &[get_info.as_slice()], &[])?;
+        let info: DeviceInfo = read_obj(&reply.data, 0)?;
+
+        if info.flags & VFIO_DEVICE_FLAGS_PCI != VFIO_DEVICE_FLAGS_PCI {
+            return Err(Error::NotPciDevice);
+        }
+
+        self.resettable = info.flags & VFIO_DEVICE_FLAGS_RESET == VFIO_DEVICE_FLAGS_RESET;
+
+        let mut regions = Vec::new();
+        for index in 0..info.num_regions {
+            let (region_info, file, sparse_areas) = self.get_region_info(index)?;
+            regions.push(Region {
+                flags: region_info.flags,
+                index: region_info.index,
+                size: region_info.size,
+                file_offset: file.map(|f| FileOffset::new(f, region_info.offset)),
+                sparse_areas,
+            });
+        }
+
+        Ok(regions)
+    }
+
+    fn get_region_info(
+        &mut self,
+        index: u32,
+    ) -> Result<(RegionInfo, Option<File>, Vec<vfio_region_sparse_mmap_area>), Error> {
+        let mut get_region_info = RegionInfo {
+            argsz: size_of::<RegionInfo>() as u32,
+            index,
+            ..Default::default()
+        };
+        let reply = self.send_command(
+            VFIO_USER_DEVICE_GET_REGION_INFO,
+            &[get_region_info.as_slice()],
+            &[],
+        )?;
+        let region_info: RegionInfo = read_obj(&reply.data, 0)?;
+
+        // Retrieve the region info again with capabilities if needed
+        if region_info.argsz as usize <= size_of::<RegionInfo>() {
+            return Ok((region_info, reply.file, Vec::new()));
+        }
+
+        get_region_info.argsz = region_info.argsz;
+        let reply = self.send_command(
+            VFIO_USER_DEVICE_GET_REGION_INFO,
+            &[get_region_info.as_slice()],
+            &[],
+        )?;
+        let region_info: RegionInfo = read_obj(&reply.data, 0)?;
+        let sparse_areas = Self::parse_region_caps(&reply.data, &region_info)?;
+
+        Ok((region_info, reply.file, sparse_areas))
+    }
+
+    // Capability offsets are relative to the beginning of the region info,
+    // which is where `data` starts.
+    fn parse_region_caps(
+        data: &[u8],
+        region_info: &RegionInfo,
+    ) -> Result<Vec<vfio_region_sparse_mmap_area>, Error> {
+        let mut sparse_areas = Vec::new();
+
+        let mut cap_offset = region_info.cap_offset as usize;
+        while cap_offset != 0 {
+            let cap_header: CapHeader = read_obj(data, cap_offset)?;
+            match cap_header.id as u32 {
+                VFIO_REGION_INFO_CAP_SPARSE_MMAP => {
+                    let sparse_mmap: SparseMmapCap = read_obj(data, cap_offset)?;
+                    for i in 0..sparse_mmap.nr_areas as usize {
+                        let area: SparseMmapArea = read_obj(
+                            data,
+                            cap_offset
+                                + size_of::<SparseMmapCap>()
+                                + i * size_of::<SparseMmapArea>(),
+                        )?;
+                        sparse_areas.push(vfio_region_sparse_mmap_area {
+                            offset: area.offset,
+                            size: area.size,
+                        });
+                    }
+                }
+                _ => {
+                    warn!(
+                        "Ignoring unsupported vfio region capability (id = '{}')",
+                        cap_header.id
+                    );
+                }
+            }
+            cap_offset = cap_header.next as usize;
+        }
+
+        Ok(sparse_areas)
+    }
+
+    pub fn dma_map(
+        &mut self,
+        offset: u64,
+        address: u64,
+        size: u64,
+        fd: RawFd,
+    ) -> Result<(), Error> {
+        let dma_map = DmaMap {
+            argsz: size_of::<DmaMap>() as u32,
+            flags: DmaMapFlags::READ_WRITE.bits(),
+            offset,
+            address,
+            size,
+        };
+        self.send_command(VFIO_USER_DMA_MAP, &[dma_map.as_slice()], &[fd])?;
+        self.dma_ranges.insert(address, size);
+
+        Ok(())
+    }
+
+    pub fn dma_unmap(&mut self, address: u64, size: u64) -> Result<(), Error> {
+        let dma_unmap = DmaUnmap {
+            argsz: size_of::<DmaUnmap>() as u32,
+            flags: 0,
+            address,
+            size,
+        };
+        self.send_command(VFIO_USER_DMA_UNMAP, &[dma_unmap.as_slice()], &[])?;
+        self.dma_ranges.remove(&address);
+
+        Ok(())
+    }
+
+    pub fn reset(&mut self) -> Result<(), Error> {
+        self.send_command(VFIO_USER_DEVICE_RESET, &[], &[])?;
+
+        Ok(())
+    }
+
+    pub fn region_read(&mut self, region: u32, offset: u64, data: &mut [u8]) -> Result<(), Error> {
+        let region_read = RegionAccess {
+            offset,
+            region,
+            count: data.len() as u32,
+        };
+        let reply = self.send_command(VFIO_USER_REGION_READ, &[region_read.as_slice()], &[])?;
+
+        let read = reply
+            .data
+            .get(size_of::<RegionAccess>()..size_of::<RegionAccess>() + data.len())
+            .ok_or_else(|| invalid_reply("Truncated region read"))?;
+        data.copy_from_slice(read);
+
+        Ok(())
+    }
+
+    pub fn region_write(&mut self, region: u32, offset: u64, data: &[u8]) -> Result<(), Error> {
+        let region_write = RegionAccess {
+            offset,
+            region,
+            count: data.len() as u32,
+        };
+        self.send_command(
+            VFIO_USER_REGION_WRITE,
+            &[region_write.as_slice(), data],
+            &[],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_irq_info(&mut self, index: u32) -> Result<IrqInfo, Error> {
+        let get_irq_info = IrqInfoMessage {
+            argsz: size_of::<IrqInfoMessage>() as u32,
+            index,
+            ..Default::default()
+        };
+        let reply = self.send_command(
+            VFIO_USER_DEVICE_GET_IRQ_INFO,
+            &[get_irq_info.as_slice()],
+            &[],
+        )?;
+        let irq_info: IrqInfoMessage = read_obj(&reply.data, 0)?;
+
+        Ok(IrqInfo {
+            index: irq_info.index,
+            flags: irq_info.flags,
+            count: irq_info.count,
+        })
+    }
+
+    pub fn set_irqs(
+        &mut self,
+        index: u32,
+        flags: u32,
+        start: u32,
+        count: u32,
+        fds: &[RawFd],
+    ) -> Result<(), Error> {
+        let set_irqs = SetIrqs {
+            argsz: size_of::<SetIrqs>() as u32,
+            flags,
+            index,
+            start,
+            count,
+        };
+        self.send_command(VFIO_USER_DEVICE_SET_IRQS, &[set_irqs.as_slice()], fds)?;
+
+        Ok(())
+    }
+
+    pub fn region(&self, region_index: u32) -> Option<&Region> {
+        self.regions
+            .iter()
+            .find(|&region| region.index == region_index)
+    }
+
+    pub fn resettable(&self) -> bool {
+        self.resettable
+    }
+
+    pub fn shutdown(&self) -> Result<(), Error> {
+        self.stream
+            .shutdown(std::net::Shutdown::Both)
+            .map_err(Error::StreamShutdown)
+    }
+
+    fn device_feature(
+        &mut self,
+        flags: u32,
+        data: &[u8],
+        reply_size: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let feature = DeviceFeature {
+            argsz: (size_of::<DeviceFeature>() + data.len().max(reply_size)) as u32,
+            flags,
+        };
+        let reply =
+            self.send_command(VFIO_USER_DEVICE_FEATURE, &[feature.as_slice(), data], &[])?;
+
+        Ok(reply.data[size_of::<DeviceFeature>().min(reply.data.len())..].to_vec())
+    }
+
+    /// Migration capabilities of the device, as `VFIO_MIGRATION_*` flags.
+    /// Fails if the device can't be migrated.
+    pub fn migration_flags(&mut self) -> Result<u64, Error> {
+        let data = self.device_feature(
+            DEVICE_FEATURE_MIGRATION | DEVICE_FEATURE_GET,
+            &[],
+            size_of::<MigrationFeature>(),
+        )?;
+        let migration: MigrationFeature = read_obj(&data, 0)?;
+
+        Ok(migration.flags)
+    }
+
+    pub fn set_device_state(&mut self, device_state: u32) -> Result<(), Error> {
+        let mig_state = MigStateFeature {
+            device_state,
+            data_fd: u32::MAX,
+        };
+        self.device_feature(
+            DEVICE_FEATURE_MIG_DEVICE_STATE | DEVICE_FEATURE_SET,
+            mig_state.as_slice(),
+            0,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn device_state(&mut self) -> Result<u32, Error> {
+        let data = self.device_feature(
+            DEVICE_FEATURE_MIG_DEVICE_STATE | DEVICE_FEATURE_GET,
+            &[],
+            size_of::<MigStateFeature>(),
+        )?;
+        let mig_state: MigStateFeature = read_obj(&data, 0)?;
+
+        Ok(mig_state.device_state)
+    }
+
+    /// Read the whole migration data of the device, which must be in the
+    /// STOP_COPY state.
+    pub fn read_migration_data(&mut self) -> Result<Vec<u8>, Error> {
+        let mut migration_data = Vec::new();
+        loop {
+            let mig_data = MigData {
+                argsz: size_of::<MigData>() as u32 + self.max_data_xfer_size,
+                size: self.max_data_xfer_size,
+            };
+            let reply = self.send_command(VFIO_USER_MIG_DATA_READ, &[mig_data.as_slice()], &[])?;
+            let read: MigData = read_obj(&reply.data, 0)?;
+            if read.size == 0 {
+                break;
+            }
+            let data = reply
+                .data
+                .get(size_of::<MigData>()..size_of::<MigData>() + read.size as usize)
+                .ok_or_else(|| invalid_reply("Truncated migration data"))?;
+            migration_data.extend_from_slice(data);
+        }
+
+        Ok(migration_data)
+    }
+
+    /// Write migration data to the device, which must be in the RESUMING
+    /// state.
+    pub fn write_migration_data(&mut self, migration_data: &[u8]) -> Result<(), Error> {
+        for data in migration_data.chunks(self.max_data_xfer_size as usize) {
+            let mig_data = MigData {
+                argsz: (size_of::<MigData>() + data.len()) as u32,
+                size: data.len() as u32,
+            };
+            self.send_command(VFIO_USER_MIG_DATA_WRITE, &[mig_data.as_slice(), data], &[])?;
+        }
+
+        Ok(())
+    }
+
+    /// Guest memory ranges mapped for DMA, as (address, size).
+    pub fn dma_ranges(&self) -> Vec<(u64, u64)> {
+        self.dma_ranges.iter().map(|(a, s)| (*a, *s)).collect()
+    }
+
+    /// Start logging the pages the device writes to in all the ranges
+    /// mapped for DMA.
+    pub fn start_dma_logging(&mut self, page_size: u64) -> Result<(), Error> {
+        let control = DmaLoggingControl {
+            page_size,
+            num_ranges: self.dma_ranges.len() as u32,
+            reserved: 0,
+        };
+        let mut data = control.as_slice().to_vec();
+        for (iova, length) in self.dma_ranges.iter() {
+            let range = DmaLoggingRange {
+                iova: *iova,
+                length: *length,
+            };
+            data.extend_from_slice(range.as_slice());
+        }
+        self.device_feature(
+            DEVICE_FEATURE_DMA_LOGGING_START | DEVICE_FEATURE_SET,
+            &data,
+            0,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn stop_dma_logging(&mut self) -> Result<(), Error> {
+        self.device_feature(DEVICE_FEATURE_DMA_LOGGING_STOP | DEVICE_FEATURE_SET, &[], 0)?;
+
+        Ok(())
+    }
+
+    /// Bitmap of the pages written by the device in the given range since
+    /// the previous report, one bit per page.
+    pub fn dma_logging_report(
+        &mut self,
+        iova: u64,
+        length: u64,
+        page_size: u64,
+    ) -> Result<Vec<u64>, Error> {
+        let report = DmaLoggingReport {
+            iova,
+            length,
+            page_size,
+        };
+        let bitmap_size = length.div_ceil(page_size).div_ceil(64) as usize * size_of::<u64>();
+        let data = self.device_feature(
+            DEVICE_FEATURE_DMA_LOGGING_REPORT | DEVICE_FEATURE_GET,
+            report.as_slice(),
+            size_of::<DmaLoggingReport>() + bitmap_size,
+        )?;
+
+        let bitmap = data
+            .get(size_of::<DmaLoggingReport>()..size_of::<DmaLoggingReport>() + bitmap_size)
+            .ok_or_else(|| invalid_reply("Truncated dirty page bitmap"))?;
+
+        Ok(bitmap
+            .chunks_exact(size_of::<u64>())
+            .map(|b| u64::from_ne_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use vmm_sys_util::tempdir::TempDir;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    // Commands received by the server, along with their payload.
+    type Received = Arc<Mutex<Vec<(u16, Vec<u8>)>>>;
+
+    // Serve a single client, replying to each command with the payload
+    // returned by `handler` or with the errno it failed with.
+    fn spawn_server(
+        dir: &TempDir,
+        mut handler: impl FnMut(u16, &[u8]) -> Result<Vec<u8>, u32> + Send + 'static,
+    ) -> (std::path::PathBuf, Received) {
+        let path = dir.as_path().join("vfio-user.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let received = Received::default();
+        let server_received = received.clone();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            loop {
+                let mut header = Header::default();
+                if stream.read_exact(header.as_mut_slice()).is_err() {
+                    break;
+                }
+                let mut payload = vec![0u8; header.message_size as usize - size_of::<Header>()];
+                stream.read_exact(&mut payload).unwrap();
+                let reply = handler(header.command, &payload);
+                server_received
+                    .lock()
+                    .unwrap()
+                    .push((header.command, payload));
+
+                let (data, flags, error) = match reply {
+                    Ok(data) => (data, HEADER_FLAGS_REPLY, 0),
+                    Err(errno) => (Vec::new(), HEADER_FLAGS_REPLY | HEADER_FLAGS_ERROR, errno),
+                };
+                let reply = Header {
+                    message_id: header.message_id,
+                    command: header.command,
+                    message_size: (size_of::<Header>() + data.len()) as u32,
+                    flags,
+                    error,
+                };
+                stream.write_all(reply.as_slice()).unwrap();
+                stream.write_all(&data).unwrap();
+            }
+        });
+
+        (path, received)
+    }
+
+    // Replies to the messages exchanged while connecting to a PCI device
+    // without any region.
+    fn connect_reply(command: u16, payload: &[u8]) -> Option<Vec<u8>> {
+        match command {
+            VFIO_USER_VERSION => {
+                let mut data = Version { major: 0, minor: 1 }.as_slice().to_vec();
+                data.extend_from_slice(b"{\"capabilities\":{\"max_data_xfer_size\":8}}\0");
+                Some(data)
+            }
+            VFIO_USER_DEVICE_GET_INFO => {
+                let mut info: DeviceInfo = read_obj(payload, 0).unwrap();
+                info.flags = VFIO_DEVICE_FLAGS_PCI | VFIO_DEVICE_FLAGS_RESET;
+                Some(info.as_slice().to_vec())
+            }
+            _ => None,
+        }
+    }
+
+    fn feature_reply(flags: u32, data: &[u8]) -> Vec<u8> {
+        let mut reply = DeviceFeature {
+            argsz: (size_of::<DeviceFeature>() + data.len()) as u32,
+            flags,
+        }
+        .as_slice()
+        .to_vec();
+        reply.extend_from_slice(data);
+        reply
+    }
+
+    #[test]
+    fn test_client_migration() {
+        let dir = TempDir::new().unwrap();
+        let device_data = b"some device state".to_vec();
+        let server_data = device_data.clone();
+        let mut read_offset = 0;
+        let (path, received) = spawn_server(&dir, move |command, payload| {
+            if let Some(reply) = connect_reply(command, payload) {
+                return Ok(reply);
+            }
+            match command {
+                VFIO_USER_DMA_MAP | VFIO_USER_DMA_UNMAP => Ok(payload.to_vec()),
+                VFIO_USER_DEVICE_FEATURE => {
+                    let feature: DeviceFeature = read_obj(payload, 0).unwrap();
+                    match feature.flags {
+                        f if f == DEVICE_FEATURE_MIGRATION | DEVICE_FEATURE_GET => {
+                            Ok(feature_reply(
+                                f,
+                                MigrationFeature {
+                                    flags: VFIO_MIGRATION_STOP_COPY,
+                                }
+                                .as_slice(),
+                            ))
+                        }
+                        f if f == DEVICE_FEATURE_DMA_LOGGING_REPORT | DEVICE_FEATURE_GET => {
+                            let mut data = payload[size_of::<DeviceFeature>()..].to_vec();
+                            data.extend_from_slice(&0b10u64.to_ne_bytes());
+                            Ok(feature_reply(f, &data))
+                        }
+                        f if f & DEVICE_FEATURE_SET != 0 => Ok(feature_reply(f, &[])),
+                        _ => Err(libc::EINVAL as u32),
+                    }
+                }
+                VFIO_USER_MIG_DATA_READ => {
+                    let mig_data: MigData = read_obj(payload, 0).unwrap();
+                    let end = server_data.len().min(read_offset + mig_data.size as usize);
+                    let data = &server_data[read_offset..end];
+                    read_offset = end;
+                    let mut reply = MigData {
+                        argsz: (size_of::<MigData>() + data.len()) as u32,
+                        size: data.len() as u32,
+                    }
+                    .as_slice()
+                    .to_vec();
+                    reply.extend_from_slice(data);
+                    Ok(reply)
+                }
+                VFIO_USER_MIG_DATA_WRITE => Ok(payload[..size_of::<MigData>()].to_vec()),
+                _ => Err(libc::ENOTSUP as u32),
+            }
+        });
+
+        let mut client = VfioUserClient::new(&path).unwrap();
+        assert!(client.resettable());
+        assert_eq!(client.max_data_xfer_size, 8);
+        assert_eq!(client.migration_flags().unwrap(), VFIO_MIGRATION_STOP_COPY);
+
+        // The device state is read and written in chunks of the maximum
+        // transfer size negotiated with the server.
+        client
+            .set_device_state(VFIO_DEVICE_STATE_STOP_COPY)
+            .unwrap();
+        assert_eq!(client.read_migration_data().unwrap(), device_data);
+        client.set_device_state(VFIO_DEVICE_STATE_RESUMING).unwrap();
+        client.write_migration_data(&device_data).unwrap();
+
+        let memory = TempFile::new().unwrap();
+        client
+            .dma_map(0, 0x10_0000, 0x1_0000, memory.as_file().as_raw_fd())
+            .unwrap();
+        client
+            .dma_map(0x1_0000, 0x20_0000, 0x1_0000, memory.as_file().as_raw_fd())
+            .unwrap();
+        client.dma_unmap(0x20_0000, 0x1_0000).unwrap();
+        assert_eq!(client.dma_ranges(), vec![(0x10_0000, 0x1_0000)]);
+        client.start_dma_logging(4096).unwrap();
+        assert_eq!(
+            client
+                .dma_logging_report(0x10_0000, 0x1_0000, 4096)
+                .unwrap(),
+            vec![0b10]
+        );
+        client.stop_dma_logging().unwrap();
+
+        let received = received.lock().unwrap();
+        let features: Vec<&Vec<u8>> = received
+            .iter()
+            .filter(|(command, _)| *command == VFIO_USER_DEVICE_FEATURE)
+            .map(|(_, payload)| payload)
+            .collect();
+        let mig_state: MigStateFeature = read_obj(features[1], size_of::<DeviceFeature>()).unwrap();
+        assert_eq!(mig_state.device_state, VFIO_DEVICE_STATE_STOP_COPY);
+        let control: DmaLoggingControl = read_obj(features[3], size_of::<DeviceFeature>()).unwrap();
+        assert_eq!(control.page_size, 4096);
+        assert_eq!(control.num_ranges, 1);
+        let range: DmaLoggingRange = read_obj(
+            features[3],
+            size_of::<DeviceFeature>() + size_of::<DmaLoggingControl>(),
+        )
+        .unwrap();
+        assert_eq!(range.iova, 0x10_0000);
+        assert_eq!(range.length, 0x1_0000);
+
+        let written: Vec<u8> = received
+            .iter()
+            .filter(|(command, _)| *command == VFIO_USER_MIG_DATA_WRITE)
+            .flat_map(|(_, payload)| payload[size_of::<MigData>()..].to_vec())
+            .collect();
+        assert_eq!(written, device_data);
+    }
+
+    #[test]
+    fn test_client_error_reply() {
+        let dir = TempDir::new().unwrap();
+        let (path, _) = spawn_server(&dir, |command, payload| {
+            connect_reply(command, payload).ok_or(libc::ENOTSUP as u32)
+        });
+
+        let mut client = VfioUserClient::new(&path).unwrap();
+        assert!(matches!(
+            client.migration_flags(),
+            Err(Error::Backend(e)) if e.raw_os_error() == Some(libc::ENOTSUP)
+        ));
+        // The connection is still usable after an error.
+        assert!(matches!(
+            client.set_device_state(VFIO_DEVICE_STATE_STOP),
+            Err(Error::Backend(_))
+        ));
+    }
+}
@@ -7,13 +7,22 @@
 use core::panic::AssertUnwindSafe;
 use std::fs::File;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
 use std::{io, result, thread};
 
 use thiserror::Error;
 use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::timerfd::TimerFd;
 
-use crate::{RateLimiter, TokenType};
+use crate::{
+    BucketUpdate, RateLimiter, ThrottleStats, TokenType, REFILL_TIMER_INTERVAL_MS, TIMER_REFILL_DUR,
+};
+
+// Period over which the consumption of the handles of a group is compared
+// to their share of the group budget.
+const FAIRNESS_WINDOW_MS: u64 = REFILL_TIMER_INTERVAL_MS;
 
 /// Errors associated with rate-limiter group.
 #[derive(Debug, Error)]
@@ -41,6 +50,158 @@ pub enum Error {
     /// Cannot write to EventFd.
     #[error("Error writing to EventFd: {0}")]
     EventFdWrite(#[source] io::Error),
+
+    /// Cannot create or read from TimerFd.
+    #[error("Error using TimerFd: {0}")]
+    TimerFd(#[source] io::Error),
+
+    /// The group is already nested under another group.
+    #[error("Rate-limiter group already has a parent")]
+    ParentAlreadySet,
+}
+
+/// Share of the budget of a RateLimiterGroup a RateLimiterGroupHandle is
+/// entitled to when it competes with the other handles of the group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HandleShare {
+    /// Weight of the handle relative to the other active handles.
+    pub weight: u64,
+    /// Bytes per second guaranteed to the handle, within the group limit.
+    pub min_bytes_rate: u64,
+    /// Operations per second guaranteed to the handle, within the group limit.
+    pub min_ops_rate: u64,
+}
+
+impl HandleShare {
+    // Returns the number of tokens of `token_type` guaranteed over a
+    // fairness window.
+    fn min_tokens(&self, token_type: TokenType) -> u64 {
+        let rate = match token_type {
+            TokenType::Bytes => self.min_bytes_rate,
+            TokenType::Ops => self.min_ops_rate,
+        };
+        rate.saturating_mul(FAIRNESS_WINDOW_MS) / 1000
+    }
+}
+
+impl Default for HandleShare {
+    fn default() -> Self {
+        HandleShare {
+            weight: 1,
+            min_bytes_rate: 0,
+            min_ops_rate: 0,
+        }
+    }
+}
+
+fn token_index(token_type: TokenType) -> usize {
+    match token_type {
+        TokenType::Bytes => 0,
+        TokenType::Ops => 1,
+    }
+}
+
+// Consumption of a RateLimiterGroupHandle over the current fairness window.
+struct Member {
+    id: u64,
+    share: HandleShare,
+    eventfd: Arc<EventFd>,
+    deferred: Arc<AtomicBool>,
+    // Tokens consumed during the current window, indexed by token type.
+    used: [u64; 2],
+    // Whether the handle asked for tokens during the current and the
+    // previous window.
+    active: bool,
+    was_active: bool,
+}
+
+impl Member {
+    fn is_active(&self) -> bool {
+        self.active || self.was_active
+    }
+}
+
+struct Members {
+    list: Vec<Member>,
+    next_id: u64,
+    window_start: Instant,
+}
+
+impl Members {
+    fn get_mut(&mut self, id: u64) -> &mut Member {
+        self.list
+            .iter_mut()
+            .find(|member| member.id == id)
+            .expect("RateLimiterGroupHandle must be subscribed to RateLimiterGroup")
+    }
+
+    // Starts a new fairness window if the current one is over.
+    fn roll_window(&mut self) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < TIMER_REFILL_DUR {
+            return;
+        }
+
+        // Handles which stayed idle for a whole window stop contending.
+        let consecutive = elapsed < 2 * TIMER_REFILL_DUR;
+        self.window_start = Instant::now();
+        for member in self.list.iter_mut() {
+            member.was_active = member.active && consecutive;
+            member.active = false;
+            member.used = [0; 2];
+        }
+    }
+
+    // Returns whether handle `id` can consume `tokens` without eating into
+    // the share of the other active handles.
+    //
+    // Each active handle is entitled to the part of the group budget matching
+    // its weight, and never less than its guaranteed minimum. A handle going
+    // over its share may only borrow the budget the other active handles are
+    // not entitled to.
+    fn within_share(
+        &self,
+        id: u64,
+        tokens: u64,
+        token_type: TokenType,
+        rate_limiter: &RateLimiter,
+    ) -> bool {
+        let Some(window_tokens) = rate_limiter.refill_amount(token_type, FAIRNESS_WINDOW_MS) else {
+            return true;
+        };
+
+        let index = token_index(token_type);
+        let total_weight: u64 = self
+            .list
+            .iter()
+            .filter(|member| member.id == id || member.is_active())
+            .map(|member| member.share.weight)
+            .sum();
+        let quota = |member: &Member| {
+            let fair =
+                window_tokens as u128 * member.share.weight as u128 / total_weight.max(1) as u128;
+            (fair as u64).max(member.share.min_tokens(token_type))
+        };
+
+        let mut fits = false;
+        let mut reserved = 0u64;
+        for member in self.list.iter() {
+            if member.id == id {
+                fits = member.used[index].saturating_add(tokens) <= quota(member);
+            } else if member.is_active() {
+                reserved =
+                    reserved.saturating_add(quota(member).saturating_sub(member.used[index]));
+            }
+        }
+
+        if fits || reserved == 0 {
+            return true;
+        }
+
+        rate_limiter
+            .available(token_type)
+            .is_none_or(|available| available >= reserved.saturating_add(tokens))
+    }
 }
 
 /// Handle to a RateLimiterGroup
@@ -48,23 +209,94 @@ pub enum Error {
 /// The RateLimiterGroupHandle may be used in exactly the same way as
 /// the RateLimiter type. When the RateLimiter within a RateLimiterGroup
 /// is unblocked, each RateLimiterGroupHandle will be notified.
+///
+/// When several handles compete for the budget of the group, each of them
+/// gets a part of it matching its `HandleShare`.
 pub struct RateLimiterGroupHandle {
+    id: u64,
+    share: HandleShare,
     eventfd: Arc<EventFd>,
+    deferred: Arc<AtomicBool>,
+    stats: Arc<ThrottleStats>,
     inner: Arc<RateLimiterGroupInner>,
 }
 
 impl RateLimiterGroupHandle {
-    fn new(inner: Arc<RateLimiterGroupInner>) -> result::Result<Self, Error> {
+    fn new(inner: Arc<RateLimiterGroupInner>, share: HandleShare) -> result::Result<Self, Error> {
         let eventfd = Arc::new(EventFd::new(0).map_err(Error::EventFd)?);
-        inner.handles.lock().unwrap().push(eventfd.clone());
-        Ok(Self { eventfd, inner })
+        let deferred = Arc::new(AtomicBool::new(false));
+
+        let mut members = inner.members.lock().unwrap();
+        let id = members.next_id;
+        members.next_id += 1;
+        members.list.push(Member {
+            id,
+            share,
+            eventfd: eventfd.clone(),
+            deferred: deferred.clone(),
+            used: [0; 2],
+            active: false,
+            was_active: false,
+        });
+        drop(members);
+
+        Ok(Self {
+            id,
+            share,
+            eventfd,
+            deferred,
+            stats: Arc::new(ThrottleStats::default()),
+            inner,
+        })
     }
 
     /// Attempts to consume tokens and returns whether that is possible.
     ///
     /// If rate limiting is disabled on provided `token_type`, this function will always succeed.
     pub fn consume(&self, tokens: u64, token_type: TokenType) -> bool {
-        self.inner.rate_limiter.consume(tokens, token_type)
+        let consumed = self.consume_tokens(tokens, token_type);
+        if consumed {
+            self.stats.unthrottle();
+        } else {
+            self.stats.throttle();
+        }
+        consumed
+    }
+
+    fn consume_tokens(&self, tokens: u64, token_type: TokenType) -> bool {
+        if self.is_blocked() {
+            return false;
+        }
+
+        let inner = &self.inner;
+        let mut members = inner.members.lock().unwrap();
+        members.roll_window();
+        let within_share = members.within_share(self.id, tokens, token_type, &inner.rate_limiter);
+        let member = members.get_mut(self.id);
+        member.active = true;
+
+        if !within_share {
+            // Hold the handle back until the next fairness window, leaving
+            // the remaining budget to the handles below their share.
+            self.deferred.store(true, Ordering::Relaxed);
+            inner.arm_fairness_timer();
+            return false;
+        }
+
+        if !inner.rate_limiter.consume(tokens, token_type) {
+            return false;
+        }
+
+        if let Some(parent) = inner.parent.get() {
+            if !parent.consume(tokens, token_type) {
+                inner.rate_limiter.manual_replenish(tokens, token_type);
+                return false;
+            }
+        }
+
+        let used = &mut member.used[token_index(token_type)];
+        *used = used.saturating_add(tokens);
+        true
     }
 
     /// Adds tokens of `token_type` to their respective bucket.
@@ -72,7 +304,15 @@ impl RateLimiterGroupHandle {
     /// Can be used to *manually* add tokens to a bucket. Useful for reverting a
     /// `consume()` if needed.
     pub fn manual_replenish(&self, tokens: u64, token_type: TokenType) {
-        self.inner.rate_limiter.manual_replenish(tokens, token_type)
+        let inner = &self.inner;
+        let mut members = inner.members.lock().unwrap();
+        let used = &mut members.get_mut(self.id).used[token_index(token_type)];
+        *used = used.saturating_sub(tokens);
+
+        inner.rate_limiter.manual_replenish(tokens, token_type);
+        if let Some(parent) = inner.parent.get() {
+            parent.manual_replenish(tokens, token_type);
+        }
     }
 
     /// This function needs to be called every time there is an event on the
@@ -88,16 +328,24 @@ impl RateLimiterGroupHandle {
     /// Returns whether this rate limiter is blocked.
     ///
     /// The limiter 'blocks' when a `consume()` operation fails because there was not enough
-    /// budget for it.
+    /// budget for it, either in this group, in one of its parents, or left over by the
+    /// handles below their share.
     /// An event will be generated on the exported FD when the limiter 'unblocks'.
     pub fn is_blocked(&self) -> bool {
         self.inner.rate_limiter.is_blocked()
+            || self.deferred.load(Ordering::Relaxed)
+            || self.inner.parent.get().is_some_and(|p| p.is_blocked())
+    }
+
+    /// Returns the throttling accounting of this handle.
+    pub fn throttle_stats(&self) -> Arc<ThrottleStats> {
+        self.stats.clone()
     }
 }
 
 impl Clone for RateLimiterGroupHandle {
     fn clone(&self) -> Self {
-        RateLimiterGroupHandle::new(self.inner.clone()).unwrap()
+        RateLimiterGroupHandle::new(self.inner.clone(), self.share).unwrap()
     }
 }
 
@@ -109,23 +357,68 @@ impl AsRawFd for RateLimiterGroupHandle {
 
 impl Drop for RateLimiterGroupHandle {
     fn drop(&mut self) {
-        let mut handles = self.inner.handles.lock().unwrap();
-        let index = handles
+        let mut members = self.inner.members.lock().unwrap();
+        let index = members
+            .list
             .iter()
-            .position(|handle| handle.as_raw_fd() == self.eventfd.as_raw_fd())
+            .position(|member| member.id == self.id)
             .expect("RateLimiterGroupHandle must be subscribed to RateLimiterGroup");
-        handles.remove(index);
+        members.list.remove(index);
     }
 }
 
 struct RateLimiterGroupInner {
     id: String,
     rate_limiter: RateLimiter,
-    handles: Mutex<Vec<Arc<EventFd>>>,
+    members: Mutex<Members>,
+    // Wakes up the handles held back to let the others get their share.
+    fairness_timer: Mutex<TimerFd>,
+    fairness_timer_active: AtomicBool,
+    parent: OnceLock<RateLimiterGroupHandle>,
+}
+
+impl RateLimiterGroupInner {
+    fn arm_fairness_timer(&self) {
+        if !self.fairness_timer_active.swap(true, Ordering::Relaxed) {
+            self.fairness_timer
+                .lock()
+                .unwrap()
+                .reset(TIMER_REFILL_DUR, None)
+                .expect("Can't arm the timer (unexpected 'timerfd_settime' failure).");
+        }
+    }
+
+    fn notify_handles(&self) -> Result<(), Error> {
+        let members = self.members.lock().unwrap();
+        for member in members.list.iter() {
+            member.eventfd.write(1).map_err(Error::EventFdWrite)?
+        }
+        Ok(())
+    }
+
+    fn notify_deferred_handles(&self) -> Result<(), Error> {
+        self.fairness_timer
+            .lock()
+            .unwrap()
+            .wait()
+            .map_err(|e| Error::TimerFd(e.into()))?;
+        self.fairness_timer_active.store(false, Ordering::Relaxed);
+
+        let members = self.members.lock().unwrap();
+        for member in members.list.iter() {
+            if member.deferred.swap(false, Ordering::Relaxed) {
+                member.eventfd.write(1).map_err(Error::EventFdWrite)?
+            }
+        }
+        Ok(())
+    }
 }
 
 /// A RateLimiterGroup is an extension of RateLimiter that enables rate-limiting
 /// the aggregate io consumption of multiple consumers.
+///
+/// Groups can be nested, in which case the consumers of a group are also
+/// limited by the budget of its parent.
 pub struct RateLimiterGroup {
     inner: Arc<RateLimiterGroupInner>,
     epoll_file: File,
@@ -138,6 +431,8 @@ pub struct RateLimiterGroup {
 enum EpollDispatch {
     Kill = 1,
     Unblocked = 2,
+    FairnessWindow = 3,
+    ParentUnblocked = 4,
     Unknown,
 }
 
@@ -147,6 +442,8 @@ impl From<u64> for EpollDispatch {
         match v {
             1 => Kill,
             2 => Unblocked,
+            3 => FairnessWindow,
+            4 => ParentUnblocked,
             _ => Unknown,
         }
     }
@@ -173,6 +470,8 @@ impl RateLimiterGroup {
         )
         .map_err(Error::RateLimiter)?;
 
+        let fairness_timer = TimerFd::new().map_err(|e| Error::TimerFd(e.into()))?;
+
         let epoll_fd = epoll::create(true).map_err(Error::Epoll)?;
         let kill_evt = EventFd::new(0).map_err(Error::EventFd)?;
 
@@ -192,6 +491,14 @@ impl RateLimiterGroup {
         )
         .map_err(Error::Epoll)?;
 
+        epoll::ctl(
+            epoll_fd,
+            epoll::ControlOptions::EPOLL_CTL_ADD,
+            fairness_timer.as_raw_fd(),
+            epoll::Event::new(epoll::Events::EPOLLIN, EpollDispatch::FairnessWindow as u64),
+        )
+        .map_err(Error::Epoll)?;
+
         // Use 'File' to enforce closing on 'epoll_fd'
         // SAFETY: epoll_fd is valid
         let epoll_file = unsafe { File::from_raw_fd(epoll_fd) };
@@ -200,7 +507,14 @@ impl RateLimiterGroup {
             inner: Arc::new(RateLimiterGroupInner {
                 id: id.to_string(),
                 rate_limiter,
-                handles: Mutex::new(Vec::new()),
+                members: Mutex::new(Members {
+                    list: Vec::new(),
+                    next_id: 0,
+                    window_start: Instant::now(),
+                }),
+                fairness_timer: Mutex::new(fairness_timer),
+                fairness_timer_active: AtomicBool::new(false),
+                parent: OnceLock::new(),
             }),
             epoll_file,
             kill_evt,
@@ -210,7 +524,49 @@ impl RateLimiterGroup {
 
     /// Create a new RateLimiterGroupHandle.
     pub fn new_handle(&self) -> result::Result<RateLimiterGroupHandle, Error> {
-        RateLimiterGroupHandle::new(self.inner.clone())
+        self.new_handle_with_share(HandleShare::default())
+    }
+
+    /// Create a new RateLimiterGroupHandle entitled to `share` of the group
+    /// budget.
+    pub fn new_handle_with_share(
+        &self,
+        share: HandleShare,
+    ) -> result::Result<RateLimiterGroupHandle, Error> {
+        RateLimiterGroupHandle::new(self.inner.clone(), share)
+    }
+
+    /// Nests this group under `parent`. Tokens consumed through the handles
+    /// of this group are also consumed from `parent`, where this group
+    /// competes with the other consumers according to `share`.
+    pub fn set_parent(
+        &self,
+        parent: &RateLimiterGroup,
+        share: HandleShare,
+    ) -> result::Result<(), Error> {
+        let handle = parent.new_handle_with_share(share)?;
+        let fd = handle.as_raw_fd();
+        self.inner
+            .parent
+            .set(handle)
+            .map_err(|_| Error::ParentAlreadySet)?;
+
+        epoll::ctl(
+            self.epoll_file.as_raw_fd(),
+            epoll::ControlOptions::EPOLL_CTL_ADD,
+            fd,
+            epoll::Event::new(
+                epoll::Events::EPOLLIN,
+                EpollDispatch::ParentUnblocked as u64,
+            ),
+        )
+        .map_err(Error::Epoll)
+    }
+
+    /// Updates the parameters of the token buckets shared by every
+    /// RateLimiterGroupHandle of this group.
+    pub fn update_buckets(&self, bytes: BucketUpdate, ops: BucketUpdate) {
+        self.inner.rate_limiter.update_buckets(bytes, ops)
     }
 
     /// Start a worker thread to broadcast an event to each RateLimiterGroupHandle
@@ -222,7 +578,7 @@ impl RateLimiterGroup {
             .name(format!("rate-limit-group-{}", inner.id))
             .spawn(move || {
                 let res = std::panic::catch_unwind(AssertUnwindSafe(move || {
-                    const EPOLL_EVENTS_LEN: usize = 2;
+                    const EPOLL_EVENTS_LEN: usize = 4;
 
                     let mut events =
                         [epoll::Event::new(epoll::Events::empty(), 0); EPOLL_EVENTS_LEN];
@@ -248,10 +604,16 @@ impl RateLimiterGroup {
                                 }
                                 EpollDispatch::Unblocked => {
                                     inner.rate_limiter.event_handler().unwrap();
-                                    let handles = inner.handles.lock().unwrap();
-                                    for handle in handles.iter() {
-                                        handle.write(1).map_err(Error::EventFdWrite)?
+                                    inner.notify_handles()?;
+                                }
+                                EpollDispatch::FairnessWindow => {
+                                    inner.notify_deferred_handles()?;
+                                }
+                                EpollDispatch::ParentUnblocked => {
+                                    if let Some(parent) = inner.parent.get() {
+                                        parent.event_handler()?;
                                     }
+                                    inner.notify_handles()?;
                                 }
                                 EpollDispatch::Kill => {
                                     info!(
@@ -304,9 +666,9 @@ pub(crate) mod tests {
 
     use vmm_sys_util::eventfd::EventFd;
 
-    use super::RateLimiterGroupHandle;
+    use super::{Error, HandleShare, RateLimiterGroupHandle};
     use crate::group::RateLimiterGroup;
-    use crate::{TokenBucket, TokenType, REFILL_TIMER_INTERVAL_MS};
+    use crate::{BucketUpdate, TokenBucket, TokenType, REFILL_TIMER_INTERVAL_MS};
 
     impl RateLimiterGroupHandle {
         fn bandwidth(&self) -> Option<TokenBucket> {
@@ -337,6 +699,25 @@ pub(crate) mod tests {
         assert_eq!(ops.budget(), 1003);
     }
 
+    #[test]
+    fn test_rate_limiter_group_update_buckets() {
+        let l = RateLimiterGroup::new("test", 1000, 0, 1000, 10, 0, 1000).unwrap();
+        let h1 = l.new_handle().unwrap();
+        let h2 = l.new_handle().unwrap();
+
+        l.update_buckets(
+            BucketUpdate::Update(TokenBucket::new(2000, 0, 500).unwrap()),
+            BucketUpdate::Disabled,
+        );
+
+        for h in [&h1, &h2] {
+            let bw = h.bandwidth().unwrap();
+            assert_eq!(bw.capacity(), 2000);
+            assert_eq!(bw.refill_time_ms(), 500);
+            assert!(h.ops().is_none());
+        }
+    }
+
     #[test]
     fn test_rate_limiter_group_manual_replenish() {
         // rate limiter with limit of 1000 bytes/s and 1000 ops/s
@@ -529,4 +910,99 @@ pub(crate) mod tests {
         assert!(!h.is_blocked());
         assert!(h.consume(100, TokenType::Bytes));
     }
+
+    #[test]
+    fn test_rate_limiter_group_weighted_share() {
+        // rate limiter with limit of 1000 bytes/s, that is 100 bytes per
+        // fairness window, shared 3:1 between h1 and h2
+        let mut l = RateLimiterGroup::new("test", 1000, 0, 1000, 0, 0, 0).unwrap();
+        l.start_thread(EventFd::new(0).unwrap()).unwrap();
+
+        let share = |weight| HandleShare {
+            weight,
+            ..Default::default()
+        };
+        let h1 = l.new_handle_with_share(share(3)).unwrap();
+        let h2 = l.new_handle_with_share(share(1)).unwrap();
+
+        // h2 becomes active, 20 bytes of its 25 bytes share are left
+        assert!(h2.consume(5, TokenType::Bytes));
+        // h1 can go over its share as long as it leaves h2's share alone
+        assert!(h1.consume(900, TokenType::Bytes));
+        // this would eat into h2's share, h1 is held back
+        assert!(!h1.consume(90, TokenType::Bytes));
+        assert!(h1.is_blocked());
+        assert_eq!(h1.throttle_stats().throttled_count(), 1);
+        // while h2 can still get its share
+        assert!(!h2.is_blocked());
+        assert!(h2.consume(20, TokenType::Bytes));
+
+        // h1 is notified once the fairness window is over
+        thread::sleep(Duration::from_millis(REFILL_TIMER_INTERVAL_MS + 10));
+        h1.event_handler().unwrap();
+        assert!(!h1.is_blocked());
+        assert!(h1.consume(10, TokenType::Bytes));
+        assert!(h1.throttle_stats().throttled_time() >= Duration::from_millis(100));
+        assert_eq!(h2.throttle_stats().throttled_count(), 0);
+    }
+
+    #[test]
+    fn test_rate_limiter_group_min_rate() {
+        // rate limiter with limit of 10000 bytes/s, that is 1000 bytes per
+        // fairness window
+        let l = RateLimiterGroup::new("test", 10000, 0, 1000, 0, 0, 0).unwrap();
+
+        // h1 has a small weight but is guaranteed 5000 bytes/s
+        let h1 = l
+            .new_handle_with_share(HandleShare {
+                weight: 1,
+                min_bytes_rate: 5000,
+                min_ops_rate: 0,
+            })
+            .unwrap();
+        let h2 = l
+            .new_handle_with_share(HandleShare {
+                weight: 9,
+                ..Default::default()
+            })
+            .unwrap();
+
+        // h1 becomes active, 400 bytes of its guaranteed 500 bytes are left
+        assert!(h1.consume(100, TokenType::Bytes));
+        assert!(h2.consume(9000, TokenType::Bytes));
+        // h2 can't take the bytes guaranteed to h1
+        assert!(!h2.consume(800, TokenType::Bytes));
+        assert!(h2.is_blocked());
+        assert!(h1.consume(400, TokenType::Bytes));
+    }
+
+    #[test]
+    fn test_rate_limiter_group_nested() {
+        // parent with limit of 1000 bytes/s above a child of 10000 bytes/s
+        let mut parent = RateLimiterGroup::new("parent", 1000, 0, 1000, 0, 0, 0).unwrap();
+        parent.start_thread(EventFd::new(0).unwrap()).unwrap();
+        let mut child = RateLimiterGroup::new("child", 10000, 0, 1000, 0, 0, 0).unwrap();
+        child.set_parent(&parent, HandleShare::default()).unwrap();
+        assert!(matches!(
+            child.set_parent(&parent, HandleShare::default()),
+            Err(Error::ParentAlreadySet)
+        ));
+        child.start_thread(EventFd::new(0).unwrap()).unwrap();
+
+        let h = child.new_handle().unwrap();
+
+        // do the full 1000 bytes of the parent
+        assert!(h.consume(1000, TokenType::Bytes));
+        // the child has budget left but the parent doesn't
+        assert!(!h.consume(100, TokenType::Bytes));
+        assert!(h.is_blocked());
+        // the tokens are given back to the child
+        assert!(h.bandwidth().unwrap().budget() >= 9000);
+
+        // the parent unblocking is forwarded to the handles of the child
+        thread::sleep(Duration::from_millis(REFILL_TIMER_INTERVAL_MS + 10));
+        h.event_handler().unwrap();
+        assert!(!h.is_blocked());
+        assert!(h.consume(100, TokenType::Bytes));
+    }
 }
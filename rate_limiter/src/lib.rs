@@ -48,8 +48,8 @@ extern crate log;
 
 use std::io;
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use vmm_sys_util::timerfd::TimerFd;
@@ -174,19 +174,7 @@ impl TokenBucket {
             }
         }
 
-        // Compute time passed since last refill/update.
-        let time_delta = self.last_update.elapsed().as_nanos() as u64;
-        self.last_update = Instant::now();
-
-        // At each 'time_delta' nanoseconds the bucket should refill with:
-        // refill_amount = (time_delta * size) / (complete_refill_time_ms * 1_000_000)
-        // `processed_capacity` and `processed_refill_time` are the result of simplifying above
-        // fraction formula with their greatest-common-factor.
-        self.budget += (time_delta * self.processed_capacity) / self.processed_refill_time;
-
-        if self.budget >= self.size {
-            self.budget = self.size;
-        }
+        self.refill();
 
         if tokens > self.budget {
             // This operation requests a bandwidth higher than the bucket size
@@ -209,6 +197,35 @@ impl TokenBucket {
         BucketReduction::Success
     }
 
+    // Adds the tokens earned since the last refill/update to the budget.
+    fn refill(&mut self) {
+        // Compute time passed since last refill/update.
+        let time_delta = self.last_update.elapsed().as_nanos() as u64;
+        self.last_update = Instant::now();
+
+        // At each 'time_delta' nanoseconds the bucket should refill with:
+        // refill_amount = (time_delta * size) / (complete_refill_time_ms * 1_000_000)
+        // `processed_capacity` and `processed_refill_time` are the result of simplifying above
+        // fraction formula with their greatest-common-factor.
+        self.budget += (time_delta * self.processed_capacity) / self.processed_refill_time;
+
+        if self.budget >= self.size {
+            self.budget = self.size;
+        }
+    }
+
+    // Returns the number of tokens that can be consumed right now, one time
+    // burst included.
+    fn available(&mut self) -> u64 {
+        self.refill();
+        self.budget.saturating_add(self.one_time_burst)
+    }
+
+    // Returns the number of tokens the bucket refills with over `interval_ms`.
+    fn refill_amount(&self, interval_ms: u64) -> u64 {
+        (self.size as u128 * interval_ms as u128 / self.refill_time as u128) as u64
+    }
+
     /// "Manually" adds tokens to bucket.
     pub fn replenish(&mut self, tokens: u64) {
         // This means we are still during the burst interval.
@@ -244,6 +261,7 @@ impl TokenBucket {
 }
 
 /// Enum that describes the type of token used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TokenType {
     /// Token type used for bandwidth limiting.
     Bytes,
@@ -252,6 +270,7 @@ pub enum TokenType {
 }
 
 /// Enum that describes the type of token bucket update.
+#[derive(Clone)]
 pub enum BucketUpdate {
     /// No Update - same as before.
     None,
@@ -261,6 +280,75 @@ pub enum BucketUpdate {
     Update(TokenBucket),
 }
 
+/// Accounting of the time a consumer of a rate limiter spent throttled.
+///
+/// A consumer is considered throttled from the first `consume()` operation
+/// that fails until the next one that succeeds.
+pub struct ThrottleStats {
+    // Reference point of the timestamps below.
+    epoch: Instant,
+    // Nanoseconds since `epoch` at which the ongoing throttling started, plus
+    // one so that zero can stand for "not throttled".
+    throttled_since: AtomicU64,
+    throttled_ns: AtomicU64,
+    throttled_count: AtomicU64,
+}
+
+impl ThrottleStats {
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64 + 1
+    }
+
+    // Records a failed `consume()` operation.
+    pub(crate) fn throttle(&self) {
+        if self
+            .throttled_since
+            .compare_exchange(0, self.now(), Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.throttled_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Records a successful `consume()` operation.
+    pub(crate) fn unthrottle(&self) {
+        if self.throttled_since.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let since = self.throttled_since.swap(0, Ordering::Relaxed);
+        if since != 0 {
+            self.throttled_ns
+                .fetch_add(self.now().saturating_sub(since), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the total time spent throttled, ongoing throttling included.
+    pub fn throttled_time(&self) -> Duration {
+        let mut ns = self.throttled_ns.load(Ordering::Relaxed);
+        let since = self.throttled_since.load(Ordering::Relaxed);
+        if since != 0 {
+            ns += self.now().saturating_sub(since);
+        }
+        Duration::from_nanos(ns)
+    }
+
+    /// Returns how many times the consumer got throttled.
+    pub fn throttled_count(&self) -> u64 {
+        self.throttled_count.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for ThrottleStats {
+    fn default() -> Self {
+        ThrottleStats {
+            epoch: Instant::now(),
+            throttled_since: AtomicU64::new(0),
+            throttled_ns: AtomicU64::new(0),
+            throttled_count: AtomicU64::new(0),
+        }
+    }
+}
+
 /// Rate Limiter that works on both bandwidth and ops/s limiting.
 ///
 /// Bandwidth (bytes/s) and ops/s limiting can be used at the same time or individually.
@@ -281,6 +369,8 @@ pub struct RateLimiter {
 
     // Internal flag that quickly determines timer state.
     timer_active: AtomicBool,
+
+    stats: Arc<ThrottleStats>,
 }
 
 struct RateLimiterInner {
@@ -367,6 +457,7 @@ impl RateLimiter {
                 timer_fd,
             }),
             timer_active: AtomicBool::new(false),
+            stats: Arc::new(ThrottleStats::default()),
         })
     }
 
@@ -374,6 +465,16 @@ impl RateLimiter {
     ///
     /// If rate limiting is disabled on provided `token_type`, this function will always succeed.
     pub fn consume(&self, tokens: u64, token_type: TokenType) -> bool {
+        let consumed = self.consume_tokens(tokens, token_type);
+        if consumed {
+            self.stats.unthrottle();
+        } else {
+            self.stats.throttle();
+        }
+        consumed
+    }
+
+    fn consume_tokens(&self, tokens: u64, token_type: TokenType) -> bool {
         // If the timer is active, we can't consume tokens from any bucket and the function fails.
         if self.is_blocked() {
             return false;
@@ -448,6 +549,34 @@ impl RateLimiter {
         self.timer_active.load(Ordering::Relaxed)
     }
 
+    /// Returns the throttling accounting of this rate limiter.
+    pub fn throttle_stats(&self) -> Arc<ThrottleStats> {
+        self.stats.clone()
+    }
+
+    // Returns the number of tokens of `token_type` that can be consumed right
+    // now, or `None` if rate limiting is disabled on that token type.
+    pub(crate) fn available(&self, token_type: TokenType) -> Option<u64> {
+        let mut guard = self.inner.lock().unwrap();
+        match token_type {
+            TokenType::Bytes => guard.bandwidth.as_mut(),
+            TokenType::Ops => guard.ops.as_mut(),
+        }
+        .map(|bucket| bucket.available())
+    }
+
+    // Returns the number of tokens of `token_type` the limiter lets through
+    // over `interval_ms`, or `None` if rate limiting is disabled on that
+    // token type.
+    pub(crate) fn refill_amount(&self, token_type: TokenType, interval_ms: u64) -> Option<u64> {
+        let guard = self.inner.lock().unwrap();
+        match token_type {
+            TokenType::Bytes => guard.bandwidth.as_ref(),
+            TokenType::Ops => guard.ops.as_ref(),
+        }
+        .map(|bucket| bucket.refill_amount(interval_ms))
+    }
+
     /// This function needs to be called every time there is an event on the
     /// FD provided by this object's `AsRawFd` trait implementation.
     ///
@@ -482,7 +611,7 @@ impl RateLimiter {
 
     /// Updates the parameters of the token buckets associated with this RateLimiter.
     // TODO: Please note that, right now, the buckets become full after being updated.
-    pub fn update_buckets(&mut self, bytes: BucketUpdate, ops: BucketUpdate) {
+    pub fn update_buckets(&self, bytes: BucketUpdate, ops: BucketUpdate) {
         let mut guard = self.inner.lock().unwrap();
         match bytes {
             BucketUpdate::Disabled => guard.bandwidth = None,
@@ -871,9 +1000,35 @@ pub(crate) mod tests {
         assert!(l.consume(100, TokenType::Bytes));
     }
 
+    #[test]
+    fn test_rate_limiter_throttle_stats() {
+        // rate limiter with limit of 1000 bytes/s
+        let l = RateLimiter::new(1000, 0, 1000, 0, 0, 0).unwrap();
+        let stats = l.throttle_stats();
+
+        assert!(l.consume(1000, TokenType::Bytes));
+        assert_eq!(stats.throttled_count(), 0);
+        assert_eq!(stats.throttled_time(), Duration::ZERO);
+
+        // failed attempts while throttled count once
+        assert!(!l.consume(100, TokenType::Bytes));
+        assert!(!l.consume(100, TokenType::Bytes));
+        assert_eq!(stats.throttled_count(), 1);
+
+        thread::sleep(Duration::from_millis(REFILL_TIMER_INTERVAL_MS));
+        l.event_handler().unwrap();
+        assert!(l.consume(100, TokenType::Bytes));
+
+        // the throttled time stops growing once consume() succeeds
+        let throttled_time = stats.throttled_time();
+        assert!(throttled_time >= Duration::from_millis(REFILL_TIMER_INTERVAL_MS));
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(stats.throttled_time(), throttled_time);
+    }
+
     #[test]
     fn test_update_buckets() {
-        let mut x = RateLimiter::new(1000, 2000, 1000, 10, 20, 1000).unwrap();
+        let x = RateLimiter::new(1000, 2000, 1000, 10, 20, 1000).unwrap();
 
         let initial_bw = x.bandwidth();
         let initial_ops = x.ops();
@@ -6,18 +6,22 @@
 use std::io::Read;
 use std::marker::PhantomData;
 use std::os::unix::net::UnixStream;
+#[cfg(feature = "tls_api")]
+use std::path::Path;
 use std::{fmt, process};
 
+#[cfg(feature = "tls_api")]
+use api_client::TlsStream;
 use api_client::{
-    simple_api_command, simple_api_command_with_fds, simple_api_full_command,
+    simple_api_full_command, simple_api_vm_command, simple_api_vm_command_with_fds, ApiSocket,
     Error as ApiClientError,
 };
 use clap::{Arg, ArgAction, ArgMatches, Command};
 use option_parser::{ByteSized, ByteSizedParseError};
 use vmm::config::RestoreConfig;
 use vmm::vm_config::{
-    DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, UserDeviceConfig, VdpaConfig,
-    VsockConfig,
+    DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, RateLimiterGroupConfig,
+    UserDeviceConfig, VdpaConfig, VsockConfig,
 };
 #[cfg(feature = "dbus_api")]
 use zbus::{proxy, zvariant::Optional};
@@ -30,8 +34,10 @@ enum Error {
     #[cfg(feature = "dbus_api")]
     DBusApiClient(zbus::Error),
     InvalidCpuCount(std::num::ParseIntError),
+    InvalidJobId(std::num::ParseIntError),
     InvalidMemorySize(ByteSizedParseError),
     InvalidBalloonSize(ByteSizedParseError),
+    InvalidDiskSize(ByteSizedParseError),
     AddDeviceConfig(vmm::config::Error),
     AddDiskConfig(vmm::config::Error),
     AddFsConfig(vmm::config::Error),
@@ -40,9 +46,12 @@ enum Error {
     AddUserDeviceConfig(vmm::config::Error),
     AddVdpaConfig(vmm::config::Error),
     AddVsockConfig(vmm::config::Error),
+    UpdateRateLimiterConfig(vmm::config::Error),
     Restore(vmm::config::Error),
     ReadingStdin(std::io::Error),
     ReadingFile(std::io::Error),
+    #[cfg(feature = "dbus_api")]
+    DBusVmScope,
 }
 
 impl fmt::Display for Error {
@@ -53,8 +62,10 @@ impl fmt::Display for Error {
             #[cfg(feature = "dbus_api")]
             DBusApiClient(e) => write!(f, "Error D-Bus proxy: {e}"),
             InvalidCpuCount(e) => write!(f, "Error parsing CPU count: {e}"),
+            InvalidJobId(e) => write!(f, "Error parsing job identifier: {e}"),
             InvalidMemorySize(e) => write!(f, "Error parsing memory size: {e:?}"),
             InvalidBalloonSize(e) => write!(f, "Error parsing balloon size: {e:?}"),
+            InvalidDiskSize(e) => write!(f, "Error parsing disk size: {e:?}"),
             AddDeviceConfig(e) => write!(f, "Error parsing device syntax: {e}"),
             AddDiskConfig(e) => write!(f, "Error parsing disk syntax: {e}"),
             AddFsConfig(e) => write!(f, "Error parsing filesystem syntax: {e}"),
@@ -63,15 +74,18 @@ impl fmt::Display for Error {
             AddUserDeviceConfig(e) => write!(f, "Error parsing user device syntax: {e}"),
             AddVdpaConfig(e) => write!(f, "Error parsing vDPA device syntax: {e}"),
             AddVsockConfig(e) => write!(f, "Error parsing vsock syntax: {e}"),
+            UpdateRateLimiterConfig(e) => write!(f, "Error parsing rate limiter syntax: {e}"),
             Restore(e) => write!(f, "Error parsing restore syntax: {e}"),
             ReadingStdin(e) => write!(f, "Error reading from stdin: {e}"),
             ReadingFile(e) => write!(f, "Error reading from file: {e}"),
+            #[cfg(feature = "dbus_api")]
+            DBusVmScope => write!(f, "The D-Bus API only accepts --vm with the create command"),
         }
     }
 }
 
 enum TargetApi<'a> {
-    HttpApi(UnixStream, PhantomData<&'a ()>),
+    HttpApi(Box<dyn ApiSocket>, PhantomData<&'a ()>),
     #[cfg(feature = "dbus_api")]
     DBusApi(DBusApi1ProxyBlocking<'a>),
 }
@@ -81,6 +95,12 @@ enum TargetApi<'a> {
 trait DBusApi1 {
     fn vmm_ping(&self) -> zbus::Result<String>;
     fn vmm_shutdown(&self) -> zbus::Result<()>;
+    fn vmm_create_vm(&self, id: &str, vm_config: &str) -> zbus::Result<Optional<String>>;
+    fn vmm_validate_config(&self, vm_config: &str) -> zbus::Result<Optional<String>>;
+    fn vmm_list_vms(&self) -> zbus::Result<Optional<String>>;
+    fn vmm_jobs(&self) -> zbus::Result<Optional<String>>;
+    fn vmm_job_status(&self, job_data: &str) -> zbus::Result<Optional<String>>;
+    fn vmm_job_cancel(&self, job_data: &str) -> zbus::Result<()>;
     fn vm_add_device(&self, device_config: &str) -> zbus::Result<Optional<String>>;
     fn vm_add_disk(&self, disk_config: &str) -> zbus::Result<Optional<String>>;
     fn vm_add_fs(&self, fs_config: &str) -> zbus::Result<Optional<String>>;
@@ -89,24 +109,31 @@ trait DBusApi1 {
     fn vm_add_user_device(&self, vm_add_user_device: &str) -> zbus::Result<Optional<String>>;
     fn vm_add_vdpa(&self, vdpa_config: &str) -> zbus::Result<Optional<String>>;
     fn vm_add_vsock(&self, vsock_config: &str) -> zbus::Result<Optional<String>>;
+    fn vm_balloon_statistics(&self) -> zbus::Result<Optional<String>>;
     fn vm_boot(&self) -> zbus::Result<()>;
     fn vm_coredump(&self, vm_coredump_data: &str) -> zbus::Result<()>;
     fn vm_counters(&self) -> zbus::Result<Optional<String>>;
     fn vm_create(&self, vm_config: &str) -> zbus::Result<()>;
     fn vm_delete(&self) -> zbus::Result<()>;
     fn vm_info(&self) -> zbus::Result<String>;
+    fn vm_migration_cancel(&self) -> zbus::Result<()>;
+    fn vm_migration_status(&self) -> zbus::Result<Optional<String>>;
     fn vm_pause(&self) -> zbus::Result<()>;
     fn vm_power_button(&self) -> zbus::Result<()>;
     fn vm_reboot(&self) -> zbus::Result<()>;
     fn vm_remove_device(&self, vm_remove_device: &str) -> zbus::Result<()>;
     fn vm_resize(&self, vm_resize: &str) -> zbus::Result<()>;
     fn vm_resize_zone(&self, vm_resize_zone: &str) -> zbus::Result<()>;
+    fn vm_resize_disk(&self, vm_resize_disk: &str) -> zbus::Result<()>;
+    fn vm_update(&self, vm_update: &str) -> zbus::Result<Optional<String>>;
+    fn vm_update_rate_limiter(&self, vm_update_rate_limiter: &str) -> zbus::Result<()>;
     fn vm_restore(&self, restore_config: &str) -> zbus::Result<()>;
     fn vm_receive_migration(&self, receive_migration_data: &str) -> zbus::Result<()>;
     fn vm_send_migration(&self, receive_migration_data: &str) -> zbus::Result<()>;
     fn vm_resume(&self) -> zbus::Result<()>;
     fn vm_shutdown(&self) -> zbus::Result<()>;
     fn vm_snapshot(&self, vm_snapshot_config: &str) -> zbus::Result<()>;
+    fn vm_vcpu_stats(&self) -> zbus::Result<Optional<String>>;
 }
 
 #[cfg(feature = "dbus_api")]
@@ -189,6 +216,46 @@ impl<'a> DBusApi1ProxyBlocking<'a> {
         self.print_response(self.vm_counters())
     }
 
+    fn api_vmm_create_vm(&self, id: &str, vm_config: &str) -> ApiResult {
+        self.print_response(self.vmm_create_vm(id, vm_config))
+    }
+
+    fn api_vmm_validate_config(&self, vm_config: &str) -> ApiResult {
+        self.print_response(self.vmm_validate_config(vm_config))
+    }
+
+    fn api_vmm_list_vms(&self) -> ApiResult {
+        self.print_response(self.vmm_list_vms())
+    }
+
+    fn api_vmm_jobs(&self) -> ApiResult {
+        self.print_response(self.vmm_jobs())
+    }
+
+    fn api_vmm_job_status(&self, job_data: &str) -> ApiResult {
+        self.print_response(self.vmm_job_status(job_data))
+    }
+
+    fn api_vmm_job_cancel(&self, job_data: &str) -> ApiResult {
+        self.vmm_job_cancel(job_data).map_err(Error::DBusApiClient)
+    }
+
+    fn api_vm_vcpu_stats(&self) -> ApiResult {
+        self.print_response(self.vm_vcpu_stats())
+    }
+
+    fn api_vm_balloon_statistics(&self) -> ApiResult {
+        self.print_response(self.vm_balloon_statistics())
+    }
+
+    fn api_vm_migration_status(&self) -> ApiResult {
+        self.print_response(self.vm_migration_status())
+    }
+
+    fn api_vm_migration_cancel(&self) -> ApiResult {
+        self.vm_migration_cancel().map_err(Error::DBusApiClient)
+    }
+
     fn api_vm_create(&self, vm_config: &str) -> ApiResult {
         self.vm_create(vm_config).map_err(Error::DBusApiClient)
     }
@@ -229,6 +296,20 @@ impl<'a> DBusApi1ProxyBlocking<'a> {
             .map_err(Error::DBusApiClient)
     }
 
+    fn api_vm_resize_disk(&self, vm_resize_disk: &str) -> ApiResult {
+        self.vm_resize_disk(vm_resize_disk)
+            .map_err(Error::DBusApiClient)
+    }
+
+    fn api_vm_update(&self, vm_update: &str) -> ApiResult {
+        self.print_response(self.vm_update(vm_update))
+    }
+
+    fn api_vm_update_rate_limiter(&self, vm_update_rate_limiter: &str) -> ApiResult {
+        self.vm_update_rate_limiter(vm_update_rate_limiter)
+            .map_err(Error::DBusApiClient)
+    }
+
     fn api_vm_restore(&self, restore_config: &str) -> ApiResult {
         self.vm_restore(restore_config)
             .map_err(Error::DBusApiClient)
@@ -261,48 +342,101 @@ impl<'a> DBusApi1ProxyBlocking<'a> {
 impl TargetApi<'_> {
     fn do_command(&mut self, matches: &ArgMatches) -> ApiResult {
         match self {
-            Self::HttpApi(api_socket, _) => rest_api_do_command(matches, api_socket),
+            Self::HttpApi(api_socket, _) => rest_api_do_command(matches, api_socket.as_mut()),
             #[cfg(feature = "dbus_api")]
             Self::DBusApi(proxy) => dbus_api_do_command(matches, proxy),
         }
     }
 }
 
-fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResult {
+fn rest_api_do_command(matches: &ArgMatches, socket: &mut dyn ApiSocket) -> ApiResult {
+    let vm = matches.get_one::<String>("vm").map(String::as_str);
+
     match matches.subcommand_name() {
+        Some("list-vms") => simple_api_full_command(socket, "GET", "vmm.list-vms", None)
+            .map_err(Error::HttpApiClient),
+        Some("validate-config") => {
+            let data = create_data(
+                matches
+                    .subcommand_matches("validate-config")
+                    .unwrap()
+                    .get_one::<String>("path")
+                    .unwrap(),
+            )?;
+            simple_api_full_command(socket, "PUT", "vmm.validate-config", Some(&data))
+                .map_err(Error::HttpApiClient)
+        }
+        Some("jobs") => {
+            simple_api_full_command(socket, "GET", "vmm.jobs", None).map_err(Error::HttpApiClient)
+        }
+        Some("job-status") => {
+            let job_data = job_data(
+                matches
+                    .subcommand_matches("job-status")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+            )?;
+            simple_api_full_command(socket, "GET", "vmm.job-status", Some(&job_data))
+                .map_err(Error::HttpApiClient)
+        }
+        Some("job-cancel") => {
+            let job_data = job_data(
+                matches
+                    .subcommand_matches("job-cancel")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+            )?;
+            simple_api_full_command(socket, "PUT", "vmm.job-cancel", Some(&job_data))
+                .map_err(Error::HttpApiClient)
+        }
         Some("boot") => {
-            simple_api_command(socket, "PUT", "boot", None).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "PUT", vm, "boot", None).map_err(Error::HttpApiClient)
         }
         Some("delete") => {
-            simple_api_command(socket, "PUT", "delete", None).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "PUT", vm, "delete", None).map_err(Error::HttpApiClient)
         }
         Some("shutdown-vmm") => simple_api_full_command(socket, "PUT", "vmm.shutdown", None)
             .map_err(Error::HttpApiClient),
         Some("resume") => {
-            simple_api_command(socket, "PUT", "resume", None).map_err(Error::HttpApiClient)
-        }
-        Some("power-button") => {
-            simple_api_command(socket, "PUT", "power-button", None).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "PUT", vm, "resume", None).map_err(Error::HttpApiClient)
         }
+        Some("power-button") => simple_api_vm_command(socket, "PUT", vm, "power-button", None)
+            .map_err(Error::HttpApiClient),
         Some("reboot") => {
-            simple_api_command(socket, "PUT", "reboot", None).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "PUT", vm, "reboot", None).map_err(Error::HttpApiClient)
         }
         Some("pause") => {
-            simple_api_command(socket, "PUT", "pause", None).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "PUT", vm, "pause", None).map_err(Error::HttpApiClient)
         }
         Some("info") => {
-            simple_api_command(socket, "GET", "info", None).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "GET", vm, "info", None).map_err(Error::HttpApiClient)
         }
         Some("counters") => {
-            simple_api_command(socket, "GET", "counters", None).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "GET", vm, "counters", None).map_err(Error::HttpApiClient)
+        }
+        Some("vcpu-stats") => simple_api_vm_command(socket, "GET", vm, "vcpu-stats", None)
+            .map_err(Error::HttpApiClient),
+        Some("balloon-stats") => simple_api_vm_command(socket, "GET", vm, "balloon-stats", None)
+            .map_err(Error::HttpApiClient),
+        Some("migration-status") => {
+            simple_api_vm_command(socket, "GET", vm, "migration-status", None)
+                .map_err(Error::HttpApiClient)
+        }
+        Some("migration-cancel") => {
+            simple_api_vm_command(socket, "PUT", vm, "migration-cancel", None)
+                .map_err(Error::HttpApiClient)
         }
         Some("ping") => {
             simple_api_full_command(socket, "GET", "vmm.ping", None).map_err(Error::HttpApiClient)
         }
         Some("shutdown") => {
-            simple_api_command(socket, "PUT", "shutdown", None).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "PUT", vm, "shutdown", None).map_err(Error::HttpApiClient)
+        }
+        Some("nmi") => {
+            simple_api_vm_command(socket, "PUT", vm, "nmi", None).map_err(Error::HttpApiClient)
         }
-        Some("nmi") => simple_api_command(socket, "PUT", "nmi", None).map_err(Error::HttpApiClient),
         Some("resize") => {
             let resize = resize_config(
                 matches
@@ -320,8 +454,13 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .unwrap()
                     .get_one::<String>("balloon")
                     .map(|x| x as &str),
+                matches
+                    .subcommand_matches("resize")
+                    .unwrap()
+                    .get_flag("resize_job"),
             )?;
-            simple_api_command(socket, "PUT", "resize", Some(&resize)).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "PUT", vm, "resize", Some(&resize))
+                .map_err(Error::HttpApiClient)
         }
         Some("resize-zone") => {
             let resize_zone = resize_zone_config(
@@ -336,7 +475,51 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("size")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "resize-zone", Some(&resize_zone))
+            simple_api_vm_command(socket, "PUT", vm, "resize-zone", Some(&resize_zone))
+                .map_err(Error::HttpApiClient)
+        }
+        Some("resize-disk") => {
+            let resize_disk = resize_disk_config(
+                matches
+                    .subcommand_matches("resize-disk")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+                matches
+                    .subcommand_matches("resize-disk")
+                    .unwrap()
+                    .get_one::<String>("size")
+                    .unwrap(),
+            )?;
+            simple_api_vm_command(socket, "PUT", vm, "resize-disk", Some(&resize_disk))
+                .map_err(Error::HttpApiClient)
+        }
+        Some("update-rate-limiter") => {
+            let update_rate_limiter = update_rate_limiter_config(
+                matches
+                    .subcommand_matches("update-rate-limiter")
+                    .unwrap()
+                    .get_one::<String>("rate_limiter_config")
+                    .unwrap(),
+            )?;
+            simple_api_vm_command(
+                socket,
+                "PUT",
+                vm,
+                "update-rate-limiter",
+                Some(&update_rate_limiter),
+            )
+            .map_err(Error::HttpApiClient)
+        }
+        Some("update") => {
+            let data = create_data(
+                matches
+                    .subcommand_matches("update")
+                    .unwrap()
+                    .get_one::<String>("path")
+                    .unwrap(),
+            )?;
+            simple_api_vm_command(socket, "PUT", vm, "update", Some(&data))
                 .map_err(Error::HttpApiClient)
         }
         Some("add-device") => {
@@ -347,7 +530,7 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("device_config")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "add-device", Some(&device_config))
+            simple_api_vm_command(socket, "PUT", vm, "add-device", Some(&device_config))
                 .map_err(Error::HttpApiClient)
         }
         Some("remove-device") => {
@@ -358,8 +541,14 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("id")
                     .unwrap(),
             );
-            simple_api_command(socket, "PUT", "remove-device", Some(&remove_device_data))
-                .map_err(Error::HttpApiClient)
+            simple_api_vm_command(
+                socket,
+                "PUT",
+                vm,
+                "remove-device",
+                Some(&remove_device_data),
+            )
+            .map_err(Error::HttpApiClient)
         }
         Some("add-disk") => {
             let disk_config = add_disk_config(
@@ -369,7 +558,7 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("disk_config")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "add-disk", Some(&disk_config))
+            simple_api_vm_command(socket, "PUT", vm, "add-disk", Some(&disk_config))
                 .map_err(Error::HttpApiClient)
         }
         Some("add-fs") => {
@@ -380,7 +569,7 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("fs_config")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "add-fs", Some(&fs_config))
+            simple_api_vm_command(socket, "PUT", vm, "add-fs", Some(&fs_config))
                 .map_err(Error::HttpApiClient)
         }
         Some("add-pmem") => {
@@ -391,7 +580,7 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("pmem_config")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "add-pmem", Some(&pmem_config))
+            simple_api_vm_command(socket, "PUT", vm, "add-pmem", Some(&pmem_config))
                 .map_err(Error::HttpApiClient)
         }
         Some("add-net") => {
@@ -402,7 +591,7 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("net_config")
                     .unwrap(),
             )?;
-            simple_api_command_with_fds(socket, "PUT", "add-net", Some(&net_config), fds)
+            simple_api_vm_command_with_fds(socket, "PUT", vm, "add-net", Some(&net_config), fds)
                 .map_err(Error::HttpApiClient)
         }
         Some("add-user-device") => {
@@ -413,7 +602,7 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("device_config")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "add-user-device", Some(&device_config))
+            simple_api_vm_command(socket, "PUT", vm, "add-user-device", Some(&device_config))
                 .map_err(Error::HttpApiClient)
         }
         Some("add-vdpa") => {
@@ -424,7 +613,7 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("vdpa_config")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "add-vdpa", Some(&vdpa_config))
+            simple_api_vm_command(socket, "PUT", vm, "add-vdpa", Some(&vdpa_config))
                 .map_err(Error::HttpApiClient)
         }
         Some("add-vsock") => {
@@ -435,7 +624,7 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("vsock_config")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "add-vsock", Some(&vsock_config))
+            simple_api_vm_command(socket, "PUT", vm, "add-vsock", Some(&vsock_config))
                 .map_err(Error::HttpApiClient)
         }
         Some("snapshot") => {
@@ -445,8 +634,12 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .unwrap()
                     .get_one::<String>("snapshot_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("snapshot")
+                    .unwrap()
+                    .get_flag("snapshot_job"),
             );
-            simple_api_command(socket, "PUT", "snapshot", Some(&snapshot_config))
+            simple_api_vm_command(socket, "PUT", vm, "snapshot", Some(&snapshot_config))
                 .map_err(Error::HttpApiClient)
         }
         Some("restore") => {
@@ -456,8 +649,12 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .unwrap()
                     .get_one::<String>("restore_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("restore")
+                    .unwrap()
+                    .get_flag("restore_job"),
             )?;
-            simple_api_command_with_fds(socket, "PUT", "restore", Some(&restore_config), fds)
+            simple_api_vm_command_with_fds(socket, "PUT", vm, "restore", Some(&restore_config), fds)
                 .map_err(Error::HttpApiClient)
         }
         Some("coredump") => {
@@ -467,8 +664,12 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .unwrap()
                     .get_one::<String>("coredump_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("coredump")
+                    .unwrap()
+                    .get_flag("coredump_job"),
             );
-            simple_api_command(socket, "PUT", "coredump", Some(&coredump_config))
+            simple_api_vm_command(socket, "PUT", vm, "coredump", Some(&coredump_config))
                 .map_err(Error::HttpApiClient)
         }
         Some("send-migration") => {
@@ -482,9 +683,23 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .subcommand_matches("send-migration")
                     .unwrap()
                     .get_flag("send_migration_local"),
+                matches
+                    .subcommand_matches("send-migration")
+                    .unwrap()
+                    .get_flag("send_migration_detach"),
+                matches
+                    .subcommand_matches("send-migration")
+                    .unwrap()
+                    .get_flag("send_migration_job"),
             );
-            simple_api_command(socket, "PUT", "send-migration", Some(&send_migration_data))
-                .map_err(Error::HttpApiClient)
+            simple_api_vm_command(
+                socket,
+                "PUT",
+                vm,
+                "send-migration",
+                Some(&send_migration_data),
+            )
+            .map_err(Error::HttpApiClient)
         }
         Some("receive-migration") => {
             let receive_migration_data = receive_migration_data(
@@ -493,10 +708,19 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .unwrap()
                     .get_one::<String>("receive_migration_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("receive-migration")
+                    .unwrap()
+                    .get_flag("receive_migration_detach"),
+                matches
+                    .subcommand_matches("receive-migration")
+                    .unwrap()
+                    .get_flag("receive_migration_job"),
             );
-            simple_api_command(
+            simple_api_vm_command(
                 socket,
                 "PUT",
+                vm,
                 "receive-migration",
                 Some(&receive_migration_data),
             )
@@ -510,7 +734,8 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .get_one::<String>("path")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "create", Some(&data)).map_err(Error::HttpApiClient)
+            simple_api_vm_command(socket, "PUT", vm, "create", Some(&data))
+                .map_err(Error::HttpApiClient)
         }
         _ => unreachable!(),
     }
@@ -518,7 +743,53 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
 
 #[cfg(feature = "dbus_api")]
 fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>) -> ApiResult {
+    if let Some(vm) = matches.get_one::<String>("vm") {
+        if matches.subcommand_name() != Some("create") {
+            return Err(Error::DBusVmScope);
+        }
+        let data = create_data(
+            matches
+                .subcommand_matches("create")
+                .unwrap()
+                .get_one::<String>("path")
+                .unwrap(),
+        )?;
+        return proxy.api_vmm_create_vm(vm, &data);
+    }
+
     match matches.subcommand_name() {
+        Some("list-vms") => proxy.api_vmm_list_vms(),
+        Some("validate-config") => {
+            let data = create_data(
+                matches
+                    .subcommand_matches("validate-config")
+                    .unwrap()
+                    .get_one::<String>("path")
+                    .unwrap(),
+            )?;
+            proxy.api_vmm_validate_config(&data)
+        }
+        Some("jobs") => proxy.api_vmm_jobs(),
+        Some("job-status") => {
+            let job_data = job_data(
+                matches
+                    .subcommand_matches("job-status")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+            )?;
+            proxy.api_vmm_job_status(&job_data)
+        }
+        Some("job-cancel") => {
+            let job_data = job_data(
+                matches
+                    .subcommand_matches("job-cancel")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+            )?;
+            proxy.api_vmm_job_cancel(&job_data)
+        }
         Some("boot") => proxy.api_vm_boot(),
         Some("delete") => proxy.api_vm_delete(),
         Some("shutdown-vmm") => proxy.api_vmm_shutdown(),
@@ -528,6 +799,10 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
         Some("pause") => proxy.api_vm_pause(),
         Some("info") => proxy.api_vm_info(),
         Some("counters") => proxy.api_vm_counters(),
+        Some("vcpu-stats") => proxy.api_vm_vcpu_stats(),
+        Some("balloon-stats") => proxy.api_vm_balloon_statistics(),
+        Some("migration-status") => proxy.api_vm_migration_status(),
+        Some("migration-cancel") => proxy.api_vm_migration_cancel(),
         Some("ping") => proxy.api_vmm_ping(),
         Some("shutdown") => proxy.api_vm_shutdown(),
         Some("resize") => {
@@ -547,6 +822,10 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
                     .unwrap()
                     .get_one::<String>("balloon")
                     .map(|x| x as &str),
+                matches
+                    .subcommand_matches("resize")
+                    .unwrap()
+                    .get_flag("resize_job"),
             )?;
             proxy.api_vm_resize(&resize)
         }
@@ -565,6 +844,41 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
             )?;
             proxy.api_vm_resize_zone(&resize_zone)
         }
+        Some("resize-disk") => {
+            let resize_disk = resize_disk_config(
+                matches
+                    .subcommand_matches("resize-disk")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+                matches
+                    .subcommand_matches("resize-disk")
+                    .unwrap()
+                    .get_one::<String>("size")
+                    .unwrap(),
+            )?;
+            proxy.api_vm_resize_disk(&resize_disk)
+        }
+        Some("update-rate-limiter") => {
+            let update_rate_limiter = update_rate_limiter_config(
+                matches
+                    .subcommand_matches("update-rate-limiter")
+                    .unwrap()
+                    .get_one::<String>("rate_limiter_config")
+                    .unwrap(),
+            )?;
+            proxy.api_vm_update_rate_limiter(&update_rate_limiter)
+        }
+        Some("update") => {
+            let data = create_data(
+                matches
+                    .subcommand_matches("update")
+                    .unwrap()
+                    .get_one::<String>("path")
+                    .unwrap(),
+            )?;
+            proxy.api_vm_update(&data)
+        }
         Some("add-device") => {
             let device_config = add_device_config(
                 matches
@@ -662,6 +976,10 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
                     .unwrap()
                     .get_one::<String>("snapshot_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("snapshot")
+                    .unwrap()
+                    .get_flag("snapshot_job"),
             );
             proxy.api_vm_snapshot(&snapshot_config)
         }
@@ -672,6 +990,10 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
                     .unwrap()
                     .get_one::<String>("restore_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("restore")
+                    .unwrap()
+                    .get_flag("restore_job"),
             )?;
             proxy.api_vm_restore(&restore_config)
         }
@@ -682,6 +1004,10 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
                     .unwrap()
                     .get_one::<String>("coredump_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("coredump")
+                    .unwrap()
+                    .get_flag("coredump_job"),
             );
             proxy.api_vm_coredump(&coredump_config)
         }
@@ -696,6 +1022,14 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
                     .subcommand_matches("send-migration")
                     .unwrap()
                     .get_flag("send_migration_local"),
+                matches
+                    .subcommand_matches("send-migration")
+                    .unwrap()
+                    .get_flag("send_migration_detach"),
+                matches
+                    .subcommand_matches("send-migration")
+                    .unwrap()
+                    .get_flag("send_migration_job"),
             );
             proxy.api_vm_send_migration(&send_migration_data)
         }
@@ -706,6 +1040,14 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
                     .unwrap()
                     .get_one::<String>("receive_migration_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("receive-migration")
+                    .unwrap()
+                    .get_flag("receive_migration_detach"),
+                matches
+                    .subcommand_matches("receive-migration")
+                    .unwrap()
+                    .get_flag("receive_migration_job"),
             );
             proxy.api_vm_receive_migration(&receive_migration_data)
         }
@@ -727,6 +1069,7 @@ fn resize_config(
     cpus: Option<&str>,
     memory: Option<&str>,
     balloon: Option<&str>,
+    job: bool,
 ) -> Result<String, Error> {
     let desired_vcpus: Option<u8> = if let Some(cpus) = cpus {
         Some(cpus.parse().map_err(Error::InvalidCpuCount)?)
@@ -760,6 +1103,7 @@ fn resize_config(
         desired_vcpus,
         desired_ram,
         desired_balloon,
+        job,
     };
 
     Ok(serde_json::to_string(&resize).unwrap())
@@ -777,6 +1121,26 @@ fn resize_zone_config(id: &str, size: &str) -> Result<String, Error> {
     Ok(serde_json::to_string(&resize_zone).unwrap())
 }
 
+fn resize_disk_config(id: &str, size: &str) -> Result<String, Error> {
+    let resize_disk = vmm::api::VmResizeDiskData {
+        id: id.to_owned(),
+        desired_size: size.parse::<ByteSized>().map_err(Error::InvalidDiskSize)?.0,
+    };
+
+    Ok(serde_json::to_string(&resize_disk).unwrap())
+}
+
+fn update_rate_limiter_config(config: &str) -> Result<String, Error> {
+    let rate_limiter_config =
+        RateLimiterGroupConfig::parse(config).map_err(Error::UpdateRateLimiterConfig)?;
+    let update_rate_limiter = vmm::api::VmUpdateRateLimiterData {
+        id: rate_limiter_config.id,
+        rate_limiter_config: rate_limiter_config.rate_limiter_config,
+    };
+
+    Ok(serde_json::to_string(&update_rate_limiter).unwrap())
+}
+
 fn add_device_config(config: &str) -> Result<String, Error> {
     let device_config = DeviceConfig::parse(config).map_err(Error::AddDeviceConfig)?;
     let device_config = serde_json::to_string(&device_config).unwrap();
@@ -845,16 +1209,18 @@ fn add_vsock_config(config: &str) -> Result<String, Error> {
     Ok(vsock_config)
 }
 
-fn snapshot_config(url: &str) -> String {
+fn snapshot_config(url: &str, job: bool) -> String {
     let snapshot_config = vmm::api::VmSnapshotConfig {
         destination_url: String::from(url),
+        job,
     };
 
     serde_json::to_string(&snapshot_config).unwrap()
 }
 
-fn restore_config(config: &str) -> Result<(String, Vec<i32>), Error> {
+fn restore_config(config: &str, job: bool) -> Result<(String, Vec<i32>), Error> {
     let mut restore_config = RestoreConfig::parse(config).map_err(Error::Restore)?;
+    restore_config.job = job;
     // RestoreConfig is modified on purpose to take out the file descriptors.
     // These fds are passed to the server side process via SCM_RIGHTS
     let fds = match &mut restore_config.net_fds {
@@ -869,31 +1235,44 @@ fn restore_config(config: &str) -> Result<(String, Vec<i32>), Error> {
     Ok((restore_config, fds))
 }
 
-fn coredump_config(destination_url: &str) -> String {
+fn coredump_config(destination_url: &str, job: bool) -> String {
     let coredump_config = vmm::api::VmCoredumpData {
         destination_url: String::from(destination_url),
+        job,
     };
 
     serde_json::to_string(&coredump_config).unwrap()
 }
 
-fn receive_migration_data(url: &str) -> String {
+fn receive_migration_data(url: &str, detach: bool, job: bool) -> String {
     let receive_migration_data = vmm::api::VmReceiveMigrationData {
         receiver_url: url.to_owned(),
+        detach,
+        job,
     };
 
     serde_json::to_string(&receive_migration_data).unwrap()
 }
 
-fn send_migration_data(url: &str, local: bool) -> String {
+fn send_migration_data(url: &str, local: bool, detach: bool, job: bool) -> String {
     let send_migration_data = vmm::api::VmSendMigrationData {
         destination_url: url.to_owned(),
         local,
+        detach,
+        job,
     };
 
     serde_json::to_string(&send_migration_data).unwrap()
 }
 
+fn job_data(id: &str) -> Result<String, Error> {
+    let job_data = vmm::api::VmmJobData {
+        id: id.parse().map_err(Error::InvalidJobId)?,
+    };
+
+    Ok(serde_json::to_string(&job_data).unwrap())
+}
+
 fn create_data(path: &str) -> Result<String, Error> {
     let mut data = String::default();
     if path == "-" {
@@ -907,6 +1286,39 @@ fn create_data(path: &str) -> Result<String, Error> {
     Ok(data)
 }
 
+// Connects to the HTTP API, exiting on failure.
+fn connect_http_api(matches: &ArgMatches) -> Box<dyn ApiSocket> {
+    #[cfg(feature = "tls_api")]
+    if let Some(addr) = matches.get_one::<String>("api-tcp") {
+        let ca = matches.get_one::<String>("tls-ca").unwrap();
+        let client_cert = matches
+            .get_one::<String>("tls-cert")
+            .zip(matches.get_one::<String>("tls-key"))
+            .map(|(cert, key)| (Path::new(cert), Path::new(key)));
+        let token = matches
+            .get_one::<String>("token-file")
+            .map(|path| std::fs::read_to_string(path).map(|token| token.trim().to_owned()))
+            .transpose()
+            .unwrap_or_else(|e| {
+                eprintln!("Error reading token file: {e}");
+                process::exit(1)
+            });
+
+        return Box::new(
+            TlsStream::connect(addr, Path::new(ca), client_cert, token).unwrap_or_else(|e| {
+                eprintln!("Error opening HTTP connection: {e}");
+                process::exit(1)
+            }),
+        );
+    }
+
+    let api_sock = matches.get_one::<String>("api-socket").unwrap();
+    Box::new(UnixStream::connect(api_sock).unwrap_or_else(|e| {
+        eprintln!("Error opening HTTP socket: {e}");
+        process::exit(1)
+    }))
+}
+
 fn main() {
     let app = Command::new("ch-remote")
         .author(env!("CARGO_PKG_AUTHORS"))
@@ -919,6 +1331,41 @@ fn main() {
                 .long("api-socket")
                 .help("HTTP API socket path (UNIX domain socket).")
                 .num_args(1),
+            #[cfg(feature = "tls_api")]
+            Arg::new("api-tcp")
+                .long("api-tcp")
+                .help("HTTP API over TLS address (<host>:<port>).")
+                .num_args(1)
+                .conflicts_with("api-socket")
+                .requires("tls-ca"),
+            #[cfg(feature = "tls_api")]
+            Arg::new("tls-ca")
+                .long("tls-ca")
+                .help("CA issuing the VMM certificate, for --api-tcp.")
+                .num_args(1)
+                .requires("api-tcp"),
+            #[cfg(feature = "tls_api")]
+            Arg::new("tls-cert")
+                .long("tls-cert")
+                .help("Client certificate, for --api-tcp.")
+                .num_args(1)
+                .requires_all(["api-tcp", "tls-key"]),
+            #[cfg(feature = "tls_api")]
+            Arg::new("tls-key")
+                .long("tls-key")
+                .help("Client private key, for --api-tcp.")
+                .num_args(1)
+                .requires_all(["api-tcp", "tls-cert"]),
+            #[cfg(feature = "tls_api")]
+            Arg::new("token-file")
+                .long("token-file")
+                .help("File holding the bearer token, for --api-tcp.")
+                .num_args(1)
+                .requires("api-tcp"),
+            Arg::new("vm")
+                .long("vm")
+                .help("Identifier of the VM to control, the default VM being used otherwise")
+                .num_args(1),
             #[cfg(feature = "dbus_api")]
             Arg::new("dbus-service-name")
                 .long("dbus-service-name")
@@ -996,7 +1443,12 @@ fn main() {
                 .arg(Arg::new("id").index(1).help("<device_id>")),
         )
         .subcommand(Command::new("info").about("Info on the VM"))
+        .subcommand(Command::new("list-vms").about("List the VMs managed by the VMM"))
         .subcommand(Command::new("counters").about("Counters from the VM"))
+        .subcommand(Command::new("vcpu-stats").about("Exit and run-time statistics of the vCPUs"))
+        .subcommand(
+            Command::new("balloon-stats").about("Memory statistics reported by the VM balloon"),
+        )
         .subcommand(Command::new("pause").about("Pause the VM"))
         .subcommand(Command::new("reboot").about("Reboot the VM"))
         .subcommand(Command::new("power-button").about("Trigger a power button in the VM"))
@@ -1020,6 +1472,13 @@ fn main() {
                         .long("balloon")
                         .help("New balloon size in bytes (supports K/M/G suffix)")
                         .num_args(1),
+                )
+                .arg(
+                    Arg::new("resize_job")
+                        .long("job")
+                        .help("Return a job identifier rather than waiting for the outcome")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue),
                 ),
         )
         .subcommand(
@@ -1038,6 +1497,36 @@ fn main() {
                         .num_args(1),
                 ),
         )
+        .subcommand(
+            Command::new("resize-disk")
+                .about("Grow a disk of the running VM")
+                .arg(
+                    Arg::new("id")
+                        .long("id")
+                        .help("Disk identifier")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("size")
+                        .long("size")
+                        .help("New disk size in bytes (supports K/M/G suffix)")
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("update-rate-limiter")
+                .about("Update the rate limiter of a device or rate limit group")
+                .arg(
+                    Arg::new("rate_limiter_config")
+                        .index(1)
+                        .help(RateLimiterGroupConfig::SYNTAX),
+                ),
+        )
+        .subcommand(
+            Command::new("update")
+                .about("Update the configuration of the VM from a partial JSON configuration")
+                .arg(Arg::new("path").index(1).default_value("-")),
+        )
         .subcommand(Command::new("resume").about("Resume the VM"))
         .subcommand(Command::new("boot").about("Boot a created VM"))
         .subcommand(Command::new("delete").about("Delete a VM"))
@@ -1049,6 +1538,13 @@ fn main() {
                     Arg::new("snapshot_config")
                         .index(1)
                         .help("<destination_url>"),
+                )
+                .arg(
+                    Arg::new("snapshot_job")
+                        .long("job")
+                        .help("Return a job identifier rather than waiting for the outcome")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue),
                 ),
         )
         .subcommand(
@@ -1058,12 +1554,26 @@ fn main() {
                     Arg::new("restore_config")
                         .index(1)
                         .help(RestoreConfig::SYNTAX),
+                )
+                .arg(
+                    Arg::new("restore_job")
+                        .long("job")
+                        .help("Return a job identifier rather than waiting for the outcome")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue),
                 ),
         )
         .subcommand(
             Command::new("coredump")
                 .about("Create a coredump from VM")
-                .arg(Arg::new("coredump_config").index(1).help("<file_path>")),
+                .arg(Arg::new("coredump_config").index(1).help("<file_path>"))
+                .arg(
+                    Arg::new("coredump_job")
+                        .long("job")
+                        .help("Return a job identifier rather than waiting for the outcome")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue),
+                ),
         )
         .subcommand(
             Command::new("send-migration")
@@ -1078,6 +1588,20 @@ fn main() {
                         .long("local")
                         .num_args(0)
                         .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("send_migration_detach")
+                        .long("detach")
+                        .help("Return once the migration is started")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("send_migration_job")
+                        .long("job")
+                        .help("Return a job identifier rather than waiting for the outcome")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue),
                 ),
         )
         .subcommand(
@@ -1087,44 +1611,70 @@ fn main() {
                     Arg::new("receive_migration_config")
                         .index(1)
                         .help("<receiver_url>"),
+                )
+                .arg(
+                    Arg::new("receive_migration_detach")
+                        .long("detach")
+                        .help("Return once the migration is started")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("receive_migration_job")
+                        .long("job")
+                        .help("Return a job identifier rather than waiting for the outcome")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue),
                 ),
         )
+        .subcommand(
+            Command::new("migration-status").about("Progress of the ongoing or last migration"),
+        )
+        .subcommand(Command::new("migration-cancel").about("Cancel the outgoing migration"))
+        .subcommand(Command::new("jobs").about("List the queued, running and finished jobs"))
+        .subcommand(
+            Command::new("job-status")
+                .about("Status of a job")
+                .arg(Arg::new("id").index(1).help("<job_id>")),
+        )
+        .subcommand(
+            Command::new("job-cancel")
+                .about("Cancel a queued job or a running migration")
+                .arg(Arg::new("id").index(1).help("<job_id>")),
+        )
         .subcommand(
             Command::new("create")
                 .about("Create VM from a JSON configuration")
                 .arg(Arg::new("path").index(1).default_value("-")),
         )
+        .subcommand(
+            Command::new("validate-config")
+                .about("Check a JSON VM configuration against the host without creating the VM")
+                .arg(Arg::new("path").index(1).default_value("-")),
+        )
         .subcommand(Command::new("ping").about("Ping the VMM to check for API server availability"))
         .subcommand(Command::new("shutdown-vmm").about("Shutdown the VMM"))
         .subcommand(Command::new("nmi").about("Trigger NMI"));
 
     let matches = app.get_matches();
 
+    let http_api = matches.get_one::<String>("api-socket").is_some();
+    #[cfg(feature = "tls_api")]
+    let http_api = http_api || matches.get_one::<String>("api-tcp").is_some();
+
     let mut target_api = match (
-        matches.get_one::<String>("api-socket"),
+        http_api,
         #[cfg(feature = "dbus_api")]
         matches.get_one::<String>("dbus-service-name"),
         #[cfg(feature = "dbus_api")]
         matches.get_one::<String>("dbus-object-path"),
     ) {
         #[cfg(not(feature = "dbus_api"))]
-        (Some(api_sock),) => TargetApi::HttpApi(
-            UnixStream::connect(api_sock).unwrap_or_else(|e| {
-                eprintln!("Error opening HTTP socket: {e}");
-                process::exit(1)
-            }),
-            PhantomData,
-        ),
+        (true,) => TargetApi::HttpApi(connect_http_api(&matches), PhantomData),
         #[cfg(feature = "dbus_api")]
-        (Some(api_sock), None, None) => TargetApi::HttpApi(
-            UnixStream::connect(api_sock).unwrap_or_else(|e| {
-                eprintln!("Error opening HTTP socket: {e}");
-                process::exit(1)
-            }),
-            PhantomData,
-        ),
+        (true, None, None) => TargetApi::HttpApi(connect_http_api(&matches), PhantomData),
         #[cfg(feature = "dbus_api")]
-        (None, Some(dbus_name), Some(dbus_path)) => TargetApi::DBusApi(
+        (false, Some(dbus_name), Some(dbus_path)) => TargetApi::DBusApi(
             DBusApi1ProxyBlocking::new_connection(
                 dbus_name,
                 dbus_path,
@@ -1137,7 +1687,7 @@ fn main() {
             }),
         ),
         #[cfg(feature = "dbus_api")]
-        (Some(_), Some(_) | None, Some(_) | None) => {
+        (true, _, _) => {
             println!(
                 "`api-socket` and (dbus-service-name or dbus-object-path) are mutually exclusive"
             );
@@ -4,6 +4,7 @@
 //
 
 use std::fs::File;
+use std::net::SocketAddr;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::sync::mpsc::channel;
 use std::sync::Mutex;
@@ -20,16 +21,20 @@ use thiserror::Error;
 #[cfg(feature = "dbus_api")]
 use vmm::api::dbus::{dbus_api_graceful_shutdown, DBusApiOptions};
 use vmm::api::http::http_api_graceful_shutdown;
+use vmm::api::http::metrics::MetricsListener;
+#[cfg(feature = "tls_api")]
+use vmm::api::http::tcp::HttpTcpOptions;
 use vmm::api::ApiAction;
 use vmm::config::{RestoreConfig, VmParams};
+use vmm::config_check::{check_config, ConfigCheck};
 use vmm::landlock::{Landlock, LandlockError};
 use vmm::vm_config;
 #[cfg(target_arch = "x86_64")]
 use vmm::vm_config::SgxEpcConfig;
 use vmm::vm_config::{
     BalloonConfig, DeviceConfig, DiskConfig, FsConfig, LandlockConfig, NetConfig, NumaConfig,
-    PciSegmentConfig, PmemConfig, RateLimiterGroupConfig, TpmConfig, UserDeviceConfig, VdpaConfig,
-    VmConfig, VsockConfig,
+    PciSegmentConfig, PmemConfig, RateLimiterGroupConfig, RngConfig, ShareConfig, TpmConfig,
+    UserDeviceConfig, VdpaConfig, VmConfig, VsockConfig,
 };
 use vmm_sys_util::eventfd::EventFd;
 use vmm_sys_util::signal::block_signal;
@@ -67,6 +72,12 @@ enum Error {
     VmmThread(#[source] vmm::Error),
     #[error("Error parsing --api-socket: {0}")]
     ParsingApiSocket(std::num::ParseIntError),
+    #[cfg(feature = "tls_api")]
+    #[error("Error parsing --api-tcp: {0}")]
+    ParsingApiTcp(option_parser::OptionParserError),
+    #[cfg(feature = "tls_api")]
+    #[error("Error parsing --api-tcp: listen, cert and key required")]
+    BareApiTcp,
     #[error("Error parsing --event-monitor: {0}")]
     ParsingEventMonitor(option_parser::OptionParserError),
     #[cfg(feature = "dbus_api")]
@@ -77,6 +88,12 @@ enum Error {
     MissingDBusServiceName,
     #[error("Error parsing --event-monitor: path or fd required")]
     BareEventMonitor,
+    #[error("Error parsing --metrics: {0}")]
+    ParsingMetrics(option_parser::OptionParserError),
+    #[error("Error parsing --metrics: path or tcp required")]
+    BareMetrics,
+    #[error("Error parsing --metrics: {0} is not a loopback address")]
+    MetricsNotLoopback(SocketAddr),
     #[error("Error doing event monitor I/O: {0}")]
     EventMonitorIo(std::io::Error),
     #[error("Event monitor thread failed: {0}")]
@@ -97,6 +114,10 @@ enum Error {
     CreateLandlock(#[source] LandlockError),
     #[error("Failed to apply Landlock: {0}")]
     ApplyLandlock(#[source] LandlockError),
+    #[error("Error serializing the configuration check report: {0}")]
+    DryRunReport(#[source] serde_json::Error),
+    #[error("The VM configuration check found {0} error(s)")]
+    DryRunFailed(usize),
 }
 
 #[derive(Error, Debug)]
@@ -189,6 +210,16 @@ fn get_cli_options_sorted(
             .help("HTTP API socket (UNIX domain socket): path=</path/to/a/file> or fd=<fd>.")
             .num_args(1)
             .group("vmm-config"),
+        #[cfg(feature = "tls_api")]
+        Arg::new("api-tcp")
+            .long("api-tcp")
+            .help(
+                "HTTP API over TLS: listen=<address:port>,cert=</path/to/server/cert>,\
+                 key=</path/to/server/key>,client_ca=</path/to/read-write/clients/ca>,\
+                 read_only_client_ca=</path/to/read-only/clients/ca>,tokens=</path/to/tokens>",
+            )
+            .num_args(1)
+            .group("vmm-config"),
         Arg::new("balloon")
             .long("balloon")
             .help(BalloonConfig::SYNTAX)
@@ -251,6 +282,11 @@ fn get_cli_options_sorted(
             .help(DiskConfig::SYNTAX)
             .num_args(1..)
             .group("vm-config"),
+        Arg::new("dry-run")
+            .long("dry-run")
+            .help("Check the VM configuration against the host, then exit without creating the VM")
+            .num_args(0)
+            .action(ArgAction::SetTrue),
         Arg::new("event-monitor")
             .long("event-monitor")
             .help("File to report events on: path=</path/to/a/file> or fd=<fd>")
@@ -343,6 +379,11 @@ fn get_cli_options_sorted(
             )
             .num_args(1..)
             .group("vm-config"),
+        Arg::new("metrics")
+            .long("metrics")
+            .help("OpenMetrics endpoint: path=</path/to/a/socket> or tcp=<loopback_address:port>")
+            .num_args(1)
+            .group("vmm-config"),
         Arg::new("net")
             .long("net")
             .help(NetConfig::SYNTAX)
@@ -361,7 +402,7 @@ fn get_cli_options_sorted(
         Arg::new("platform")
             .long("platform")
             .help(
-                "num_pci_segments=<num_pci_segments>,iommu_segments=<list_of_segments>,iommu_address_width=<bits>,serial_number=<dmi_device_serial_number>,uuid=<dmi_device_uuid>,oem_strings=<list_of_strings>"
+                "num_pci_segments=<num_pci_segments>,iommu_segments=<list_of_segments>,iommu_address_width=<bits>,iommu_max_domains=<max_domains>,serial_number=<dmi_device_serial_number>,uuid=<dmi_device_uuid>,oem_strings=<list_of_strings>"
             )
             .num_args(1)
             .group("vm-config"),
@@ -395,9 +436,7 @@ fn get_cli_options_sorted(
             .group("vmm-config"),
         Arg::new("rng")
             .long("rng")
-            .help(
-                "Random number generator parameters \"src=<entropy_source_path>,iommu=on|off\"",
-            )
+            .help(RngConfig::SYNTAX)
             .default_value(default_rng)
             .group("vm-config"),
         Arg::new("seccomp")
@@ -416,6 +455,11 @@ fn get_cli_options_sorted(
             .help(SgxEpcConfig::SYNTAX)
             .num_args(1..)
             .group("vm-config"),
+        Arg::new("share")
+            .long("share")
+            .help(ShareConfig::SYNTAX)
+            .num_args(1..)
+            .group("vm-config"),
         Arg::new("tpm")
             .long("tpm")
             .num_args(1)
@@ -479,6 +523,31 @@ fn create_app(default_vcpus: String, default_memory: String, default_rng: String
         .args(args)
 }
 
+fn dry_run(cmd_arguments: &ArgMatches) -> Result<(), Error> {
+    let vm_params = VmParams::from_arg_matches(cmd_arguments);
+    let vm_config = VmConfig::parse_unvalidated(vm_params).map_err(Error::ParsingConfig)?;
+
+    let hypervisor = hypervisor::new();
+    let mut report = check_config(&vm_config, hypervisor.as_deref().ok());
+    if let Err(e) = hypervisor {
+        report.error(
+            ConfigCheck::Hypervisor,
+            format!("Failed to open hypervisor interface: {e}"),
+        );
+    }
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).map_err(Error::DryRunReport)?
+    );
+
+    if report.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::DryRunFailed(report.errors.len()))
+    }
+}
+
 fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
     let log_level = match cmd_arguments.get_count("v") {
         0 => LevelFilter::Warn,
@@ -502,6 +571,10 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
     .map(|()| log::set_max_level(log_level))
     .map_err(Error::LoggerSetup)?;
 
+    if cmd_arguments.get_flag("dry-run") {
+        return dry_run(&cmd_arguments).map(|()| None);
+    }
+
     let (api_socket_path, api_socket_fd) =
         if let Some(socket_config) = cmd_arguments.get_one::<String>("api-socket") {
             let mut parser = OptionParser::new();
@@ -527,6 +600,71 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
             (None, None)
         };
 
+    let metrics = cmd_arguments
+        .get_one::<String>("metrics")
+        .map(|metrics_config| {
+            let mut parser = OptionParser::new();
+            parser.add("path").add("tcp");
+            parser
+                .parse(metrics_config)
+                .map_err(Error::ParsingMetrics)?;
+
+            if let Some(addr) = parser
+                .convert::<SocketAddr>("tcp")
+                .map_err(Error::ParsingMetrics)?
+            {
+                // The endpoint isn't authenticated, keep it local to the host.
+                if !addr.ip().is_loopback() {
+                    return Err(Error::MetricsNotLoopback(addr));
+                }
+                Ok(MetricsListener::Tcp(addr))
+            } else if let Some(path) = parser.get("path") {
+                Ok(MetricsListener::Unix(path.into()))
+            } else {
+                Err(Error::BareMetrics)
+            }
+        })
+        .transpose()?;
+    #[cfg(feature = "tls_api")]
+    let http_tcp = cmd_arguments
+        .get_one::<String>("api-tcp")
+        .map(|api_tcp_config| {
+            let mut parser = OptionParser::new();
+            parser
+                .add("listen")
+                .add("cert")
+                .add("key")
+                .add("client_ca")
+                .add("read_only_client_ca")
+                .add("tokens");
+            parser.parse(api_tcp_config).map_err(Error::ParsingApiTcp)?;
+
+            let (Some(addr), Some(cert), Some(key)) = (
+                parser
+                    .convert::<SocketAddr>("listen")
+                    .map_err(Error::ParsingApiTcp)?,
+                parser.get("cert"),
+                parser.get("key"),
+            ) else {
+                return Err(Error::BareApiTcp);
+            };
+
+            Ok(HttpTcpOptions {
+                addr,
+                cert: cert.into(),
+                key: key.into(),
+                client_ca: parser.get("client_ca").map(Into::into),
+                read_only_client_ca: parser.get("read_only_client_ca").map(Into::into),
+                tokens: parser.get("tokens").map(Into::into),
+            })
+        })
+        .transpose()?;
+
+    let metrics_path = match &metrics {
+        Some(MetricsListener::Unix(path)) => Some(path.clone()),
+        _ => None,
+    };
+
     let (api_request_sender, api_request_receiver) = channel();
     let api_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::CreateApiEventFd)?;
 
@@ -606,7 +744,6 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
     let exit_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::CreateExitEventFd)?;
     let landlock_enable = cmd_arguments.get_flag("landlock");
 
-    #[allow(unused_mut)]
     let mut event_monitor = cmd_arguments
         .get_one::<String>("event-monitor")
         .as_ref()
@@ -670,6 +807,21 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
         (None, None) => Ok(None),
     }?;
 
+    // Streaming events from the HTTP API needs a monitor as well, create one
+    // without file support if none was requested.
+    let event_subscribers = if api_socket_path.is_some() || api_socket_fd.is_some() {
+        let monitor = match event_monitor.take() {
+            Some(monitor) => monitor,
+            None => event_monitor::set_monitor(None).map_err(Error::EventMonitorIo)?,
+        };
+        let subscribers = monitor.subscribers();
+
+        event_monitor = Some(monitor);
+        Some(subscribers)
+    } else {
+        None
+    };
+
     if let Some(monitor) = event_monitor {
         vmm::start_event_monitor_thread(
             monitor,
@@ -687,6 +839,10 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
         vmm::VmmVersionInfo::new(env!("BUILD_VERSION"), env!("CARGO_PKG_VERSION")),
         &api_socket_path,
         api_socket_fd,
+        event_subscribers,
+        metrics,
+        #[cfg(feature = "tls_api")]
+        http_tcp,
         #[cfg(feature = "dbus_api")]
         dbus_options,
         api_evt.try_clone().unwrap(),
@@ -771,6 +927,10 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
         dbus_api_graceful_shutdown(chs);
     }
 
+    if let Some(path) = metrics_path {
+        std::fs::remove_file(path).ok();
+    }
+
     r.map(|_| api_socket_path)
 }
 
@@ -981,9 +1141,11 @@ mod unit_tests {
             rng: RngConfig {
                 src: PathBuf::from("/dev/urandom"),
                 iommu: false,
+                rate_limiter_config: None,
             },
             balloon: None,
             fs: None,
+            shares: None,
             pmem: None,
             serial: ConsoleConfig {
                 file: None,
@@ -1340,12 +1502,26 @@ mod unit_tests {
                 vec![
                     "cloud-hypervisor", "--kernel", "/path/to/kernel",
                     "--net",
-                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=5.6.7.8",
+                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=fd00::1,extra_ips=[fd01::1/48,fd02::1/64]",
                 ],
                 r#"{
                     "payload": {"kernel": "/path/to/kernel"},
                     "net": [
-                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "5.6.7.8"}
+                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "fd00::1", "extra_ips": ["fd01::1/48", "fd02::1/64"]}
+                    ]
+                }"#,
+                true,
+            ),
+            (
+                vec![
+                    "cloud-hypervisor", "--kernel", "/path/to/kernel",
+                    "--net",
+                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=255.255.255.0",
+                ],
+                r#"{
+                    "payload": {"kernel": "/path/to/kernel"},
+                    "net": [
+                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "255.255.255.0"}
                     ]
                 }"#,
                 true,
@@ -1355,13 +1531,13 @@ mod unit_tests {
                     "cloud-hypervisor", "--kernel", "/path/to/kernel",
                     "--cpus", "boot=2",
                     "--net",
-                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=5.6.7.8,num_queues=4",
+                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=255.255.255.0,num_queues=4",
                 ],
                 r#"{
                     "payload": {"kernel": "/path/to/kernel"},
                     "cpus": {"boot_vcpus": 2, "max_vcpus": 2},
                     "net": [
-                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "5.6.7.8", "num_queues": 4}
+                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "255.255.255.0", "num_queues": 4}
                     ]
                 }"#,
                 true,
@@ -1371,13 +1547,13 @@ mod unit_tests {
                     "cloud-hypervisor", "--kernel", "/path/to/kernel",
                     "--cpus", "boot=2",
                     "--net",
-                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=5.6.7.8,num_queues=4,queue_size=128",
+                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=255.255.255.0,num_queues=4,queue_size=128",
                 ],
                 r#"{
                     "payload": {"kernel": "/path/to/kernel"},
                     "cpus": {"boot_vcpus": 2, "max_vcpus": 2},
                     "net": [
-                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "5.6.7.8", "num_queues": 4, "queue_size": 128}
+                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "255.255.255.0", "num_queues": 4, "queue_size": 128}
                     ]
                 }"#,
                 true,
@@ -1386,12 +1562,12 @@ mod unit_tests {
                 vec![
                     "cloud-hypervisor", "--kernel", "/path/to/kernel",
                     "--net",
-                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=5.6.7.8,num_queues=2,queue_size=256",
+                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=255.255.255.0,num_queues=2,queue_size=256",
                 ],
                 r#"{
                     "payload": {"kernel": "/path/to/kernel"},
                     "net": [
-                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "5.6.7.8"}
+                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "255.255.255.0"}
                     ]
                 }"#,
                 true,
@@ -1400,12 +1576,12 @@ mod unit_tests {
                 vec![
                     "cloud-hypervisor", "--kernel", "/path/to/kernel",
                     "--net",
-                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=5.6.7.8",
+                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=255.255.255.0",
                 ],
                 r#"{
                     "payload": {"kernel": "/path/to/kernel"},
                     "net": [
-                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "5.6.7.8", "num_queues": 2, "queue_size": 256}
+                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "255.255.255.0", "num_queues": 2, "queue_size": 256}
                     ]
                 }"#,
                 true,
@@ -1415,12 +1591,12 @@ mod unit_tests {
                 vec![
                     "cloud-hypervisor", "--kernel", "/path/to/kernel",
                     "--net",
-                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=5.6.7.8,num_queues=2,queue_size=256,iommu=on",
+                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=255.255.255.0,num_queues=2,queue_size=256,iommu=on",
                 ],
                 r#"{
                     "payload": {"kernel": "/path/to/kernel"},
                     "net": [
-                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "5.6.7.8", "num_queues": 2, "queue_size": 256, "iommu": true}
+                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "255.255.255.0", "num_queues": 2, "queue_size": 256, "iommu": true}
                     ]
                 }"#,
                 false,
@@ -1430,12 +1606,12 @@ mod unit_tests {
                 vec![
                     "cloud-hypervisor", "--kernel", "/path/to/kernel",
                     "--net",
-                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=5.6.7.8,num_queues=2,queue_size=256,iommu=on",
+                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=255.255.255.0,num_queues=2,queue_size=256,iommu=on",
                 ],
                 r#"{
                     "payload": {"kernel": "/path/to/kernel"},
                     "net": [
-                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "5.6.7.8", "num_queues": 2, "queue_size": 256, "iommu": true}
+                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "255.255.255.0", "num_queues": 2, "queue_size": 256, "iommu": true}
                     ],
                     "iommu": true
                 }"#,
@@ -1445,12 +1621,12 @@ mod unit_tests {
                 vec![
                     "cloud-hypervisor", "--kernel", "/path/to/kernel",
                     "--net",
-                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=5.6.7.8,num_queues=2,queue_size=256,iommu=off",
+                    "mac=12:34:56:78:90:ab,host_mac=34:56:78:90:ab:cd,tap=tap0,ip=1.2.3.4,mask=255.255.255.0,num_queues=2,queue_size=256,iommu=off",
                 ],
                 r#"{
                     "payload": {"kernel": "/path/to/kernel"},
                     "net": [
-                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "5.6.7.8", "num_queues": 2, "queue_size": 256, "iommu": false}
+                        {"mac": "12:34:56:78:90:ab", "host_mac": "34:56:78:90:ab:cd", "tap": "tap0", "ip": "1.2.3.4", "mask": "255.255.255.0", "num_queues": 2, "queue_size": 256, "iommu": false}
                     ]
                 }"#,
                 true,
@@ -1572,6 +1748,50 @@ mod unit_tests {
         });
     }
 
+    #[test]
+    fn test_valid_vm_config_share() {
+        [
+            (
+                vec![
+                    "cloud-hypervisor",
+                    "--kernel",
+                    "/path/to/kernel",
+                    "--share",
+                    "path=/path/to/dir1,tag=share1",
+                    "path=/path/to/dir2,tag=share2,readonly=on",
+                ],
+                r#"{
+                    "payload": {"kernel": "/path/to/kernel"},
+                    "shares": [
+                        {"path": "/path/to/dir1", "tag": "share1"},
+                        {"path": "/path/to/dir2", "tag": "share2", "readonly": true}
+                    ]
+                }"#,
+                true,
+            ),
+            (
+                vec![
+                    "cloud-hypervisor",
+                    "--kernel",
+                    "/path/to/kernel",
+                    "--share",
+                    "path=/path/to/dir1,tag=share1,readonly=on",
+                ],
+                r#"{
+                    "payload": {"kernel": "/path/to/kernel"},
+                    "shares": [
+                        {"path": "/path/to/dir1", "tag": "share1"}
+                    ]
+                }"#,
+                false,
+            ),
+        ]
+        .iter()
+        .for_each(|(cli, openapi, equal)| {
+            compare_vm_config_cli_vs_json(cli, openapi, *equal);
+        });
+    }
+
     #[test]
     fn test_valid_vm_config_pmem() {
         [
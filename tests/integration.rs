@@ -6330,8 +6330,11 @@ mod common_parallel {
         use std::str::FromStr;
         let taps = net_util::open_tap(
             Some("chtap0"),
-            Some(std::net::Ipv4Addr::from_str(&guest.network.host_ip).unwrap()),
-            None,
+            &[net_util::IpNetwork::new(
+                std::net::IpAddr::from_str(&guest.network.host_ip).unwrap(),
+                24,
+            )
+            .unwrap()],
             &mut None,
             None,
             num_queue_pairs,
@@ -7597,8 +7600,11 @@ mod common_sequential {
         use std::str::FromStr;
         let taps = net_util::open_tap(
             Some(tap_name),
-            Some(std::net::Ipv4Addr::from_str(&guest.network.host_ip).unwrap()),
-            None,
+            &[net_util::IpNetwork::new(
+                std::net::IpAddr::from_str(&guest.network.host_ip).unwrap(),
+                24,
+            )
+            .unwrap()],
             &mut None,
             None,
             num_queue_pairs,
@@ -7695,8 +7701,11 @@ mod common_sequential {
 
         let taps = net_util::open_tap(
             Some(tap_name),
-            Some(std::net::Ipv4Addr::from_str(&guest.network.host_ip).unwrap()),
-            None,
+            &[net_util::IpNetwork::new(
+                std::net::IpAddr::from_str(&guest.network.host_ip).unwrap(),
+                24,
+            )
+            .unwrap()],
             &mut None,
             None,
             num_queue_pairs,
@@ -18,6 +18,7 @@ use serde::Serialize;
 #[derive(Debug)]
 struct Tracer {
     events: Arc<Mutex<HashMap<String, Vec<TraceEvent>>>>,
+    counters: Arc<Mutex<HashMap<String, HashMap<&'static str, u64>>>>,
     thread_depths: HashMap<String, Arc<AtomicU64>>,
     start: Instant,
 }
@@ -26,6 +27,7 @@ impl Tracer {
     fn new() -> Self {
         Self {
             events: Arc::new(Mutex::new(HashMap::default())),
+            counters: Arc::new(Mutex::new(HashMap::default())),
             start: Instant::now(),
             thread_depths: HashMap::default(),
         }
@@ -41,11 +43,13 @@ impl Tracer {
         struct TraceReport {
             duration: Duration,
             events: Arc<Mutex<HashMap<String, Vec<TraceEvent>>>>,
+            counters: Arc<Mutex<HashMap<String, HashMap<&'static str, u64>>>>,
         }
 
         let trace_report = TraceReport {
             duration: end.duration_since(self.start),
             events: self.events.clone(),
+            counters: self.counters.clone(),
         };
 
         serde_json::to_writer_pretty(&file, &trace_report).unwrap();
@@ -122,6 +126,12 @@ pub fn trace_point_log(event: &'static str) {
     }
 }
 
+pub fn trace_counters_log(name: String, counters: HashMap<&'static str, u64>) {
+    // SAFETY: counters are behind a lock shared by all threads
+    let tracer = unsafe { TRACER.get().unwrap() };
+    tracer.counters.lock().unwrap().insert(name, counters);
+}
+
 pub struct TraceBlock {
     start: Instant,
     event: &'static str,
@@ -166,6 +176,13 @@ macro_rules! trace_point {
     };
 }
 
+#[macro_export]
+macro_rules! trace_counters {
+    ($name:expr, $counters:expr) => {
+        $crate::trace_counters_log($name, $counters.into_iter().collect())
+    };
+}
+
 #[macro_export]
 macro_rules! trace_scoped {
     ($event:expr) => {
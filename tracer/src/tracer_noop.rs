@@ -13,5 +13,10 @@ macro_rules! trace_point {
     ($event:expr) => {};
 }
 
+#[macro_export]
+macro_rules! trace_counters {
+    ($name:expr, $counters:expr) => {};
+}
+
 pub fn end() {}
 pub fn start() {}
@@ -6,7 +6,7 @@
 //
 // SPDX-License-Identifier: (Apache-2.0 AND BSD-3-Clause)
 
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::ops::Deref;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::{Arc, Mutex, RwLock};
@@ -15,7 +15,8 @@ use std::{fmt, io, process};
 use libc::EFD_NONBLOCK;
 use log::*;
 use net_util::{
-    open_tap, MacAddr, NetCounters, NetQueuePair, OpenTapError, RxVirtio, Tap, TxVirtio,
+    open_tap, IpNetwork, IpNetworkError, MacAddr, NetCounters, NetQueuePair, OpenTapError,
+    RxVirtio, Tap, TxVirtio,
 };
 use option_parser::{OptionParser, OptionParserError, Toggle};
 use vhost::vhost_user::message::*;
@@ -45,6 +46,8 @@ pub enum Error {
     HandleEventNotEpollIn,
     /// Failed to handle unknown event.
     HandleEventUnknownEvent,
+    /// Invalid IP address and netmask combination.
+    InvalidIpConfig(IpNetworkError),
     /// Failed to open tap device.
     OpenTap(OpenTapError),
     /// No socket provided.
@@ -118,19 +121,20 @@ pub struct VhostUserNetBackend {
 impl VhostUserNetBackend {
     #[allow(clippy::too_many_arguments)]
     fn new(
-        ip_addr: Ipv4Addr,
+        ip_addr: IpAddr,
         host_mac: MacAddr,
-        netmask: Ipv4Addr,
+        netmask: IpAddr,
         mtu: Option<u16>,
         num_queues: usize,
         queue_size: u16,
         ifname: Option<&str>,
         mem: GuestMemoryAtomic<GuestMemoryMmap>,
     ) -> Result<Self> {
+        let ip_network =
+            IpNetwork::with_netmask(ip_addr, netmask).map_err(Error::InvalidIpConfig)?;
         let mut taps = open_tap(
             ifname,
-            Some(ip_addr),
-            Some(netmask),
+            &[ip_network],
             &mut Some(host_mac),
             mtu,
             num_queues / 2,
@@ -271,9 +275,9 @@ impl VhostUserBackendMut for VhostUserNetBackend {
 }
 
 pub struct VhostUserNetBackendConfig {
-    pub ip: Ipv4Addr,
+    pub ip: IpAddr,
     pub host_mac: MacAddr,
-    pub mask: Ipv4Addr,
+    pub mask: IpAddr,
     pub mtu: Option<u16>,
     pub socket: String,
     pub num_queues: usize,
@@ -303,7 +307,7 @@ impl VhostUserNetBackendConfig {
         let ip = parser
             .convert("ip")
             .map_err(Error::FailedConfigParse)?
-            .unwrap_or_else(|| Ipv4Addr::new(192, 168, 100, 1));
+            .unwrap_or(IpAddr::V4(Ipv4Addr::new(192, 168, 100, 1)));
         let host_mac = parser
             .convert("host_mac")
             .map_err(Error::FailedConfigParse)?
@@ -311,7 +315,11 @@ impl VhostUserNetBackendConfig {
         let mask = parser
             .convert("mask")
             .map_err(Error::FailedConfigParse)?
-            .unwrap_or_else(|| Ipv4Addr::new(255, 255, 255, 0));
+            .unwrap_or(if ip.is_ipv6() {
+                IpAddr::V6(Ipv6Addr::new(0xffff, 0xffff, 0xffff, 0xffff, 0, 0, 0, 0))
+            } else {
+                IpAddr::V4(Ipv4Addr::new(255, 255, 255, 0))
+            });
         let mtu = parser.convert("mtu").map_err(Error::FailedConfigParse)?;
         let queue_size = parser
             .convert("queue_size")
@@ -16,10 +16,12 @@
 
 use std::io::{self, Write};
 use std::mem::size_of;
+use std::num::Wrapping;
 use std::os::unix::io::AsRawFd;
 use std::result;
 use std::sync::atomic::AtomicBool;
-use std::sync::{Arc, Barrier};
+use std::sync::{Arc, Barrier, Condvar, Mutex};
+use std::time::Duration;
 
 use anyhow::anyhow;
 use seccompiler::SeccompAction;
@@ -31,19 +33,23 @@ use vm_memory::{
     Address, ByteValued, Bytes, GuestAddress, GuestAddressSpace, GuestMemory, GuestMemoryAtomic,
     GuestMemoryError, GuestMemoryRegion,
 };
+use vm_migration::protocol::MemoryRange;
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::timerfd::TimerFd;
 
 use crate::seccomp_filters::Thread;
 use crate::thread_helper::spawn_virtio_thread;
 use crate::{
-    ActivateResult, EpollHelper, EpollHelperError, EpollHelperHandler, GuestMemoryMmap,
-    VirtioCommon, VirtioDevice, VirtioDeviceType, VirtioInterrupt, VirtioInterruptType,
-    EPOLL_HELPER_EVENT_LAST, VIRTIO_F_VERSION_1,
+    ActivateError, ActivateResult, CountersReader, EpollHelper, EpollHelperError,
+    EpollHelperHandler, GuestMemoryMmap, VirtioCommon, VirtioDevice, VirtioDeviceType,
+    VirtioInterrupt, VirtioInterruptType, EPOLL_HELPER_EVENT_LAST, VIRTIO_F_VERSION_1,
 };
 
 const QUEUE_SIZE: u16 = 128;
 const REPORTING_QUEUE_SIZE: u16 = 32;
+const STATS_QUEUE_SIZE: u16 = 2;
+const FREE_PAGE_HINT_QUEUE_SIZE: u16 = 64;
 const MIN_NUM_QUEUES: usize = 2;
 
 // Inflate virtio queue event.
@@ -52,16 +58,43 @@ const INFLATE_QUEUE_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 1;
 const DEFLATE_QUEUE_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 2;
 // Reporting virtio queue event.
 const REPORTING_QUEUE_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 3;
+// Statistics virtio queue event.
+const STATS_QUEUE_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 4;
+// Free page hint virtio queue event.
+const FREE_PAGE_HINT_QUEUE_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 5;
+// Statistics polling timer event.
+const STATS_TIMER_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 6;
 
 // Size of a PFN in the balloon interface.
 const VIRTIO_BALLOON_PFN_SHIFT: u64 = 12;
 
+// Enable a virtqueue to let the guest report memory statistics.
+const VIRTIO_BALLOON_F_STATS_VQ: u64 = 1;
 // Deflate balloon on OOM
 const VIRTIO_BALLOON_F_DEFLATE_ON_OOM: u64 = 2;
+// Enable a virtqueue to let the guest hint about free pages upon request.
+const VIRTIO_BALLOON_F_FREE_PAGE_HINT: u64 = 3;
 // Enable an additional virtqueue to let the guest notify the host about free
 // pages.
 const VIRTIO_BALLOON_F_REPORTING: u64 = 5;
 
+// Memory statistics tags, as defined by the virtio specification.
+const VIRTIO_BALLOON_S_SWAP_IN: u16 = 0;
+const VIRTIO_BALLOON_S_SWAP_OUT: u16 = 1;
+const VIRTIO_BALLOON_S_MAJFLT: u16 = 2;
+const VIRTIO_BALLOON_S_MINFLT: u16 = 3;
+const VIRTIO_BALLOON_S_MEMFREE: u16 = 4;
+const VIRTIO_BALLOON_S_MEMTOT: u16 = 5;
+const VIRTIO_BALLOON_S_AVAIL: u16 = 6;
+const VIRTIO_BALLOON_S_CACHES: u16 = 7;
+const VIRTIO_BALLOON_S_HTLB_PGALLOC: u16 = 8;
+const VIRTIO_BALLOON_S_HTLB_PGFAIL: u16 = 9;
+
+// Free page hint command IDs with a special meaning. Any other value
+// identifies a hinting request from the device.
+const VIRTIO_BALLOON_CMD_ID_STOP: u32 = 0;
+const VIRTIO_BALLOON_CMD_ID_DONE: u32 = 1;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Guest gave us bad memory addresses.: {0}")]
@@ -86,6 +119,8 @@ pub enum Error {
     QueueAddUsed(virtio_queue::Error),
     #[error("Failed creating an iterator over the queue: {0}")]
     QueueIterator(virtio_queue::Error),
+    #[error("Failed to read the statistics timer: {0}")]
+    StatsTimerRead(io::Error),
 }
 
 // Got from include/uapi/linux/virtio_balloon.h
@@ -96,6 +131,157 @@ pub struct VirtioBalloonConfig {
     num_pages: u32,
     // Number of pages we've actually got in balloon.
     actual: u32,
+    // Free page hint command ID, read by the guest.
+    #[serde(default)]
+    free_page_hint_cmd_id: u32,
+    // Page poison value, unused as VIRTIO_BALLOON_F_PAGE_POISON is not offered.
+    #[serde(default)]
+    poison_val: u32,
+}
+
+// A single entry from the statistics virtqueue.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug, Default)]
+struct VirtioBalloonStat {
+    tag: u16,
+    val: u64,
+}
+
+// SAFETY: it only has data and has no implicit padding.
+unsafe impl ByteValued for VirtioBalloonStat {}
+
+/// Memory statistics reported by the guest through the statistics virtqueue.
+/// A field is `None` until the guest reported it at least once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalloonStatistics {
+    /// Target size of the balloon in bytes.
+    pub target_bytes: u64,
+    /// Actual size of the balloon in bytes.
+    pub actual_bytes: u64,
+    /// Amount of memory swapped in, in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_in: Option<u64>,
+    /// Amount of memory swapped out, in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_out: Option<u64>,
+    /// Number of major page faults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub major_faults: Option<u64>,
+    /// Number of minor page faults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minor_faults: Option<u64>,
+    /// Amount of unused memory, in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_memory: Option<u64>,
+    /// Total amount of memory available to the guest, in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_memory: Option<u64>,
+    /// Estimate of the memory available for starting new applications, in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available_memory: Option<u64>,
+    /// Amount of memory that can be quickly reclaimed from disk caches, in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_caches: Option<u64>,
+    /// Number of successful hugetlb page allocations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hugetlb_allocations: Option<u64>,
+    /// Number of failed hugetlb page allocations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hugetlb_failures: Option<u64>,
+}
+
+impl BalloonStatistics {
+    fn update(&mut self, tag: u16, val: u64) {
+        let field = match tag {
+            VIRTIO_BALLOON_S_SWAP_IN => &mut self.swap_in,
+            VIRTIO_BALLOON_S_SWAP_OUT => &mut self.swap_out,
+            VIRTIO_BALLOON_S_MAJFLT => &mut self.major_faults,
+            VIRTIO_BALLOON_S_MINFLT => &mut self.minor_faults,
+            VIRTIO_BALLOON_S_MEMFREE => &mut self.free_memory,
+            VIRTIO_BALLOON_S_MEMTOT => &mut self.total_memory,
+            VIRTIO_BALLOON_S_AVAIL => &mut self.available_memory,
+            VIRTIO_BALLOON_S_CACHES => &mut self.disk_caches,
+            VIRTIO_BALLOON_S_HTLB_PGALLOC => &mut self.hugetlb_allocations,
+            VIRTIO_BALLOON_S_HTLB_PGFAIL => &mut self.hugetlb_failures,
+            // Tags unknown to us are ignored as mandated by the specification.
+            _ => return,
+        };
+        *field = Some(val);
+    }
+
+    fn entries(&self) -> [(&'static str, Option<u64>); 10] {
+        [
+            ("swap_in", self.swap_in),
+            ("swap_out", self.swap_out),
+            ("major_faults", self.major_faults),
+            ("minor_faults", self.minor_faults),
+            ("free_memory", self.free_memory),
+            ("total_memory", self.total_memory),
+            ("available_memory", self.available_memory),
+            ("disk_caches", self.disk_caches),
+            ("hugetlb_allocations", self.hugetlb_allocations),
+            ("hugetlb_failures", self.hugetlb_failures),
+        ]
+    }
+}
+
+// State of the free page hinting protocol, shared between the device and
+// its epoll handler.
+#[derive(Default)]
+struct FreePageHints {
+    // Command ID of the current request, or 0 if none is in progress.
+    cmd_id: u32,
+    // The guest acknowledged the current command ID and is sending hints.
+    receiving: bool,
+    // The guest is done sending hints for the current command ID.
+    complete: bool,
+    ranges: Vec<MemoryRange>,
+}
+
+impl FreePageHints {
+    fn handle_cmd_id(&mut self, cmd_id: u32) {
+        if cmd_id == VIRTIO_BALLOON_CMD_ID_STOP {
+            if self.receiving {
+                self.receiving = false;
+                self.complete = true;
+            }
+        } else if cmd_id == self.cmd_id && !self.complete {
+            self.receiving = true;
+            self.ranges.clear();
+        } else {
+            debug!("Ignoring stale free page hint command ID {}", cmd_id);
+        }
+    }
+}
+
+// Free page hinting state, along with the condition notified when the guest
+// is done sending hints.
+type SharedFreePageHints = Arc<(Mutex<FreePageHints>, Condvar)>;
+
+/// Handle to wait for the guest to be done hinting about its free pages.
+pub struct FreePageHintsWaiter {
+    free_page_hints: SharedFreePageHints,
+    cmd_id: u32,
+}
+
+impl FreePageHintsWaiter {
+    /// Wait up to `timeout` for the free page ranges hinted by the guest.
+    /// Returns `None` if the guest did not complete the request in time, or
+    /// if hinting was stopped in the meantime.
+    pub fn wait(&self, timeout: Duration) -> Option<Vec<MemoryRange>> {
+        let (free_page_hints, cond) = &*self.free_page_hints;
+        let (free_page_hints, _) = cond
+            .wait_timeout_while(free_page_hints.lock().unwrap(), timeout, |hints| {
+                hints.cmd_id == self.cmd_id && !hints.complete
+            })
+            .unwrap();
+
+        if free_page_hints.cmd_id == self.cmd_id && free_page_hints.complete {
+            Some(free_page_hints.ranges.clone())
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -154,7 +340,14 @@ struct BalloonEpollHandler {
     interrupt_cb: Arc<dyn VirtioInterrupt>,
     inflate_queue_evt: EventFd,
     deflate_queue_evt: EventFd,
-    reporting_queue_evt: Option<EventFd>,
+    reporting_queue_evt: Option<(usize, EventFd)>,
+    stats_queue_evt: Option<(usize, EventFd)>,
+    free_page_hint_queue_evt: Option<(usize, EventFd)>,
+    stats_timer: Option<TimerFd>,
+    // Head of the statistics buffer held until the next polling period.
+    stats_desc_index: Option<u16>,
+    statistics: Arc<Mutex<BalloonStatistics>>,
+    free_page_hints: SharedFreePageHints,
     kill_evt: EventFd,
     pause_evt: EventFd,
     pbp: Option<PartiallyBalloonedPage>,
@@ -335,6 +528,121 @@ impl BalloonEpollHandler {
         }
     }
 
+    fn process_stats_queue(&mut self, queue_index: usize) -> result::Result<(), Error> {
+        let stat_size = size_of::<VirtioBalloonStat>();
+        let mut used_descs = false;
+        while let Some(mut desc_chain) =
+            self.queues[queue_index].pop_descriptor_chain(self.mem.memory())
+        {
+            let mut statistics = BalloonStatistics::default();
+            while let Some(desc) = desc_chain.next() {
+                if desc.is_write_only() {
+                    return Err(Error::UnexpectedWriteOnlyDescriptor);
+                }
+
+                let mut offset = 0u64;
+                while offset + stat_size as u64 <= desc.len() as u64 {
+                    let addr = desc
+                        .addr()
+                        .checked_add(offset)
+                        .ok_or(Error::InvalidRequest)?;
+                    let stat: VirtioBalloonStat = desc_chain
+                        .memory()
+                        .read_obj(addr)
+                        .map_err(Error::GuestMemory)?;
+                    statistics.update(stat.tag, stat.val);
+                    offset += stat_size as u64;
+                }
+            }
+
+            {
+                let mut current = self.statistics.lock().unwrap();
+                statistics.target_bytes = current.target_bytes;
+                statistics.actual_bytes = current.actual_bytes;
+                *current = statistics;
+            }
+
+            // The driver only uses a single buffer, but in case a previous
+            // one is still held, give it back before keeping the new one.
+            if let Some(head_index) = self.stats_desc_index.replace(desc_chain.head_index()) {
+                self.queues[queue_index]
+                    .add_used(desc_chain.memory(), head_index, 0)
+                    .map_err(Error::QueueAddUsed)?;
+                used_descs = true;
+            }
+        }
+
+        if used_descs {
+            self.signal(VirtioInterruptType::Queue(queue_index as u16))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Give the statistics buffer back to the guest so that it refills it
+    // with up to date values.
+    fn request_stats(&mut self, queue_index: usize) -> result::Result<(), Error> {
+        if let Some(timer) = self.stats_timer.as_mut() {
+            timer.wait().map_err(|e| Error::StatsTimerRead(e.into()))?;
+        }
+
+        if let Some(head_index) = self.stats_desc_index.take() {
+            let mem = self.mem.memory();
+            self.queues[queue_index]
+                .add_used(&*mem, head_index, 0)
+                .map_err(Error::QueueAddUsed)?;
+            self.signal(VirtioInterruptType::Queue(queue_index as u16))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn process_free_page_hint_queue(&mut self, queue_index: usize) -> result::Result<(), Error> {
+        let mut used_descs = false;
+        while let Some(mut desc_chain) =
+            self.queues[queue_index].pop_descriptor_chain(self.mem.memory())
+        {
+            while let Some(desc) = desc_chain.next() {
+                let (free_page_hints, cond) = &*self.free_page_hints;
+                let mut free_page_hints = free_page_hints.lock().unwrap();
+                if desc.is_write_only() {
+                    // A free page block the guest won't touch until it's
+                    // told the hinting is done.
+                    if free_page_hints.receiving {
+                        free_page_hints.ranges.push(MemoryRange {
+                            gpa: desc.addr().raw_value(),
+                            length: desc.len() as u64,
+                        });
+                    }
+                } else {
+                    if desc.len() as usize != size_of::<u32>() {
+                        error!("Invalid free page hint command size {}", desc.len());
+                        return Err(Error::InvalidRequest);
+                    }
+                    let cmd_id: u32 = desc_chain
+                        .memory()
+                        .read_obj(desc.addr())
+                        .map_err(Error::GuestMemory)?;
+                    free_page_hints.handle_cmd_id(cmd_id);
+                    if free_page_hints.complete {
+                        cond.notify_all();
+                    }
+                }
+            }
+
+            self.queues[queue_index]
+                .add_used(desc_chain.memory(), desc_chain.head_index(), 0)
+                .map_err(Error::QueueAddUsed)?;
+            used_descs = true;
+        }
+
+        if used_descs {
+            self.signal(VirtioInterruptType::Queue(queue_index as u16))
+        } else {
+            Ok(())
+        }
+    }
+
     fn run(
         &mut self,
         paused: Arc<AtomicBool>,
@@ -343,9 +651,21 @@ impl BalloonEpollHandler {
         let mut helper = EpollHelper::new(&self.kill_evt, &self.pause_evt)?;
         helper.add_event(self.inflate_queue_evt.as_raw_fd(), INFLATE_QUEUE_EVENT)?;
         helper.add_event(self.deflate_queue_evt.as_raw_fd(), DEFLATE_QUEUE_EVENT)?;
-        if let Some(reporting_queue_evt) = self.reporting_queue_evt.as_ref() {
+        if let Some((_, reporting_queue_evt)) = self.reporting_queue_evt.as_ref() {
             helper.add_event(reporting_queue_evt.as_raw_fd(), REPORTING_QUEUE_EVENT)?;
         }
+        if let Some((_, stats_queue_evt)) = self.stats_queue_evt.as_ref() {
+            helper.add_event(stats_queue_evt.as_raw_fd(), STATS_QUEUE_EVENT)?;
+        }
+        if let Some(stats_timer) = self.stats_timer.as_ref() {
+            helper.add_event(stats_timer.as_raw_fd(), STATS_TIMER_EVENT)?;
+        }
+        if let Some((_, free_page_hint_queue_evt)) = self.free_page_hint_queue_evt.as_ref() {
+            helper.add_event(
+                free_page_hint_queue_evt.as_raw_fd(),
+                FREE_PAGE_HINT_QUEUE_EVENT,
+            )?;
+        }
         helper.run(paused, paused_sync, self)?;
 
         Ok(())
@@ -389,14 +709,16 @@ impl EpollHelperHandler for BalloonEpollHandler {
                 })?;
             }
             REPORTING_QUEUE_EVENT => {
-                if let Some(reporting_queue_evt) = self.reporting_queue_evt.as_ref() {
+                if let Some((queue_index, reporting_queue_evt)) = self.reporting_queue_evt.as_ref()
+                {
+                    let queue_index = *queue_index;
                     reporting_queue_evt.read().map_err(|e| {
                         EpollHelperError::HandleEvent(anyhow!(
                             "Failed to get reporting queue event: {:?}",
                             e
                         ))
                     })?;
-                    self.process_reporting_queue(2).map_err(|e| {
+                    self.process_reporting_queue(queue_index).map_err(|e| {
                         EpollHelperError::HandleEvent(anyhow!(
                             "Failed to signal used inflate queue: {:?}",
                             e
@@ -408,6 +730,66 @@ impl EpollHelperHandler for BalloonEpollHandler {
                     )));
                 }
             }
+            STATS_QUEUE_EVENT => {
+                if let Some((queue_index, stats_queue_evt)) = self.stats_queue_evt.as_ref() {
+                    let queue_index = *queue_index;
+                    stats_queue_evt.read().map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Failed to get statistics queue event: {:?}",
+                            e
+                        ))
+                    })?;
+                    self.process_stats_queue(queue_index).map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Failed to process statistics queue: {:?}",
+                            e
+                        ))
+                    })?;
+                } else {
+                    return Err(EpollHelperError::HandleEvent(anyhow!(
+                        "Invalid statistics queue event as no eventfd registered"
+                    )));
+                }
+            }
+            STATS_TIMER_EVENT => {
+                if let Some((queue_index, _)) = self.stats_queue_evt.as_ref() {
+                    let queue_index = *queue_index;
+                    self.request_stats(queue_index).map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Failed to request statistics: {:?}",
+                            e
+                        ))
+                    })?;
+                } else {
+                    return Err(EpollHelperError::HandleEvent(anyhow!(
+                        "Invalid statistics timer event as no statistics queue registered"
+                    )));
+                }
+            }
+            FREE_PAGE_HINT_QUEUE_EVENT => {
+                if let Some((queue_index, free_page_hint_queue_evt)) =
+                    self.free_page_hint_queue_evt.as_ref()
+                {
+                    let queue_index = *queue_index;
+                    free_page_hint_queue_evt.read().map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Failed to get free page hint queue event: {:?}",
+                            e
+                        ))
+                    })?;
+                    self.process_free_page_hint_queue(queue_index)
+                        .map_err(|e| {
+                            EpollHelperError::HandleEvent(anyhow!(
+                                "Failed to process free page hint queue: {:?}",
+                                e
+                            ))
+                        })?;
+                } else {
+                    return Err(EpollHelperError::HandleEvent(anyhow!(
+                        "Invalid free page hint queue event as no eventfd registered"
+                    )));
+                }
+            }
             _ => {
                 return Err(EpollHelperError::HandleEvent(anyhow!(
                     "Unknown event for virtio-balloon"
@@ -434,15 +816,22 @@ pub struct Balloon {
     seccomp_action: SeccompAction,
     exit_evt: EventFd,
     interrupt_cb: Option<Arc<dyn VirtioInterrupt>>,
+    statistics_polling_interval: u64,
+    statistics: Arc<Mutex<BalloonStatistics>>,
+    free_page_hints: SharedFreePageHints,
+    next_free_page_hint_cmd_id: u32,
 }
 
 impl Balloon {
     // Create a new virtio-balloon.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: String,
         size: u64,
         deflate_on_oom: bool,
         free_page_reporting: bool,
+        statistics_polling_interval: u64,
+        free_page_hinting: bool,
         seccomp_action: SeccompAction,
         exit_evt: EventFd,
         state: Option<BalloonState>,
@@ -465,6 +854,12 @@ impl Balloon {
             if free_page_reporting {
                 avail_features |= 1u64 << VIRTIO_BALLOON_F_REPORTING;
             }
+            if statistics_polling_interval > 0 {
+                avail_features |= 1u64 << VIRTIO_BALLOON_F_STATS_VQ;
+            }
+            if free_page_hinting {
+                avail_features |= 1u64 << VIRTIO_BALLOON_F_FREE_PAGE_HINT;
+            }
 
             let config = VirtioBalloonConfig {
                 num_pages: (size >> VIRTIO_BALLOON_PFN_SHIFT) as u32,
@@ -474,6 +869,13 @@ impl Balloon {
             (avail_features, 0, config, false)
         };
 
+        // Optional queues follow the order of their feature bits.
+        if statistics_polling_interval > 0 {
+            queue_sizes.push(STATS_QUEUE_SIZE);
+        }
+        if free_page_hinting {
+            queue_sizes.push(FREE_PAGE_HINT_QUEUE_SIZE);
+        }
         if free_page_reporting {
             queue_sizes.push(REPORTING_QUEUE_SIZE);
         }
@@ -494,6 +896,10 @@ impl Balloon {
             seccomp_action,
             exit_evt,
             interrupt_cb: None,
+            statistics_polling_interval,
+            statistics: Arc::new(Mutex::new(BalloonStatistics::default())),
+            free_page_hints: Arc::new((Mutex::new(FreePageHints::default()), Condvar::new())),
+            next_free_page_hint_cmd_id: VIRTIO_BALLOON_CMD_ID_DONE + 1,
         })
     }
 
@@ -514,6 +920,66 @@ impl Balloon {
         (self.config.actual as u64) << VIRTIO_BALLOON_PFN_SHIFT
     }
 
+    // Get the latest memory statistics reported by the guest.
+    pub fn statistics(&self) -> BalloonStatistics {
+        let mut statistics = *self.statistics.lock().unwrap();
+        statistics.target_bytes = (self.config.num_pages as u64) << VIRTIO_BALLOON_PFN_SHIFT;
+        statistics.actual_bytes = self.get_actual();
+        statistics
+    }
+
+    fn update_free_page_hint_cmd_id(&mut self, cmd_id: u32) -> Result<(), Error> {
+        self.config.free_page_hint_cmd_id = cmd_id;
+
+        if let Some(interrupt_cb) = &self.interrupt_cb {
+            interrupt_cb
+                .trigger(VirtioInterruptType::Config)
+                .map_err(Error::FailedSignal)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Ask the guest to start hinting about its free pages, returning a
+    // handle to wait for the hinted ranges. Returns None if the guest driver
+    // did not negotiate free page hinting.
+    pub fn start_free_page_hinting(&mut self) -> Result<Option<FreePageHintsWaiter>, Error> {
+        if !self.common.feature_acked(VIRTIO_BALLOON_F_FREE_PAGE_HINT)
+            || self.interrupt_cb.is_none()
+        {
+            return Ok(None);
+        }
+
+        let cmd_id = self.next_free_page_hint_cmd_id;
+        self.next_free_page_hint_cmd_id = cmd_id
+            .checked_add(1)
+            .unwrap_or(VIRTIO_BALLOON_CMD_ID_DONE + 1);
+
+        *self.free_page_hints.0.lock().unwrap() = FreePageHints {
+            cmd_id,
+            ..Default::default()
+        };
+        self.update_free_page_hint_cmd_id(cmd_id)?;
+
+        Ok(Some(FreePageHintsWaiter {
+            free_page_hints: self.free_page_hints.clone(),
+            cmd_id,
+        }))
+    }
+
+    // Let the guest reuse the pages it hinted about. The hinted ranges must
+    // not be relied upon after this call, unless dirty pages are tracked.
+    pub fn stop_free_page_hinting(&mut self) -> Result<(), Error> {
+        let (free_page_hints, cond) = &*self.free_page_hints;
+        *free_page_hints.lock().unwrap() = FreePageHints::default();
+        cond.notify_all();
+        if self.config.free_page_hint_cmd_id == VIRTIO_BALLOON_CMD_ID_STOP {
+            return Ok(());
+        }
+
+        self.update_free_page_hint_cmd_id(VIRTIO_BALLOON_CMD_ID_DONE)
+    }
+
     fn state(&self) -> BalloonState {
         BalloonState {
             avail_features: self.common.avail_features,
@@ -607,15 +1073,51 @@ impl VirtioDevice for Balloon {
         let (_, queue, queue_evt) = queues.remove(0);
         virtqueues.push(queue);
         let deflate_queue_evt = queue_evt;
+        let stats_queue_evt =
+            if self.common.feature_acked(VIRTIO_BALLOON_F_STATS_VQ) && !queues.is_empty() {
+                let (_, mut queue, queue_evt) = queues.remove(0);
+                // A statistics buffer held when the device state was saved
+                // was never returned to the guest, fetch it again.
+                if queue.next_avail() != queue.next_used() {
+                    queue.set_next_avail(queue.next_used());
+                }
+                virtqueues.push(queue);
+                Some((virtqueues.len() - 1, queue_evt))
+            } else {
+                None
+            };
+        let free_page_hint_queue_evt =
+            if self.common.feature_acked(VIRTIO_BALLOON_F_FREE_PAGE_HINT) && !queues.is_empty() {
+                let (_, queue, queue_evt) = queues.remove(0);
+                virtqueues.push(queue);
+                Some((virtqueues.len() - 1, queue_evt))
+            } else {
+                None
+            };
         let reporting_queue_evt =
             if self.common.feature_acked(VIRTIO_BALLOON_F_REPORTING) && !queues.is_empty() {
                 let (_, queue, queue_evt) = queues.remove(0);
                 virtqueues.push(queue);
-                Some(queue_evt)
+                Some((virtqueues.len() - 1, queue_evt))
             } else {
                 None
             };
 
+        let stats_timer = if stats_queue_evt.is_some() {
+            let mut timer = TimerFd::new().map_err(|e| {
+                error!("Failed to create statistics timer: {}", e);
+                ActivateError::BadActivate
+            })?;
+            let interval = Duration::from_secs(self.statistics_polling_interval);
+            timer.reset(interval, Some(interval)).map_err(|e| {
+                error!("Failed to arm statistics timer: {}", e);
+                ActivateError::BadActivate
+            })?;
+            Some(timer)
+        } else {
+            None
+        };
+
         self.interrupt_cb = Some(interrupt_cb.clone());
 
         let mut handler = BalloonEpollHandler {
@@ -625,6 +1127,12 @@ impl VirtioDevice for Balloon {
             inflate_queue_evt,
             deflate_queue_evt,
             reporting_queue_evt,
+            stats_queue_evt,
+            free_page_hint_queue_evt,
+            stats_timer,
+            stats_desc_index: None,
+            statistics: self.statistics.clone(),
+            free_page_hints: self.free_page_hints.clone(),
             kill_evt,
             pause_evt,
             pbp: None,
@@ -653,6 +1161,23 @@ impl VirtioDevice for Balloon {
         event!("virtio-device", "reset", "id", &self.id);
         result
     }
+
+    fn counters_reader(&self) -> Option<CountersReader> {
+        if !self.common.feature_acked(VIRTIO_BALLOON_F_STATS_VQ) {
+            return None;
+        }
+
+        let statistics = self.statistics.clone();
+        Some(Arc::new(move || {
+            statistics
+                .lock()
+                .unwrap()
+                .entries()
+                .into_iter()
+                .filter_map(|(name, value)| value.map(|v| (name, Wrapping(v))))
+                .collect()
+        }))
+    }
 }
 
 impl Pausable for Balloon {
@@ -676,3 +1201,248 @@ impl Snapshottable for Balloon {
 }
 impl Transportable for Balloon {}
 impl Migratable for Balloon {}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use virtio_bindings::virtio_ring::{VRING_DESC_F_NEXT, VRING_DESC_F_WRITE};
+    use vm_virtio::queue::testing::VirtQueue as GuestQ;
+
+    use super::*;
+    use crate::vsock::tests::NoopVirtioInterrupt;
+
+    const MEM_SIZE: usize = 0x100_0000;
+    const QUEUE_ADDR: u64 = 0x10_0000;
+    const BUFFER_ADDR: u64 = 0x20_0000;
+
+    fn guest_memory() -> GuestMemoryMmap {
+        GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap()
+    }
+
+    fn epoll_handler(
+        mem: &GuestMemoryMmap,
+        queue: Queue,
+        statistics: Arc<Mutex<BalloonStatistics>>,
+        free_page_hints: SharedFreePageHints,
+    ) -> BalloonEpollHandler {
+        BalloonEpollHandler {
+            mem: GuestMemoryAtomic::new(mem.clone()),
+            queues: vec![queue],
+            interrupt_cb: Arc::new(NoopVirtioInterrupt {}),
+            inflate_queue_evt: EventFd::new(0).unwrap(),
+            deflate_queue_evt: EventFd::new(0).unwrap(),
+            reporting_queue_evt: None,
+            stats_queue_evt: None,
+            free_page_hint_queue_evt: None,
+            stats_timer: None,
+            stats_desc_index: None,
+            statistics,
+            free_page_hints,
+            kill_evt: EventFd::new(0).unwrap(),
+            pause_evt: EventFd::new(0).unwrap(),
+            pbp: None,
+        }
+    }
+
+    fn hinting_balloon() -> Balloon {
+        let mut balloon = Balloon::new(
+            String::from("balloon"),
+            0,
+            false,
+            false,
+            0,
+            true,
+            SeccompAction::Trap,
+            EventFd::new(0).unwrap(),
+            None,
+        )
+        .unwrap();
+        balloon.ack_features(1u64 << VIRTIO_BALLOON_F_FREE_PAGE_HINT);
+        balloon.interrupt_cb = Some(Arc::new(NoopVirtioInterrupt {}));
+        balloon
+    }
+
+    // Queue the descriptors the guest driver uses to answer a hinting
+    // request: the command ID, a free page block and the stop command.
+    fn queue_hints(mem: &GuestMemoryMmap, guest_q: &GuestQ, cmd_id: u32, hint: MemoryRange) {
+        mem.write_obj(cmd_id, GuestAddress(BUFFER_ADDR)).unwrap();
+        mem.write_obj(VIRTIO_BALLOON_CMD_ID_STOP, GuestAddress(BUFFER_ADDR + 4))
+            .unwrap();
+
+        guest_q.dtable[0].set(BUFFER_ADDR, 4, 0, 0);
+        guest_q.dtable[1].set(
+            hint.gpa,
+            hint.length as u32,
+            VRING_DESC_F_WRITE.try_into().unwrap(),
+            0,
+        );
+        guest_q.dtable[2].set(BUFFER_ADDR + 4, 4, 0, 0);
+        for i in 0..3 {
+            guest_q.avail.ring[i].set(i as u16);
+        }
+        guest_q.avail.idx.set(3);
+    }
+
+    #[test]
+    fn test_balloon_stats_queue() {
+        let mem = guest_memory();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, STATS_QUEUE_SIZE);
+        let statistics = Arc::new(Mutex::new(BalloonStatistics {
+            target_bytes: 1 << 20,
+            ..Default::default()
+        }));
+        let mut handler = epoll_handler(
+            &mem,
+            guest_q.create_queue(),
+            statistics.clone(),
+            Arc::new((Mutex::new(FreePageHints::default()), Condvar::new())),
+        );
+
+        // Statistics buffer split over two descriptors, with a tag unknown
+        // to the device which must be ignored.
+        let stats = [
+            VirtioBalloonStat {
+                tag: VIRTIO_BALLOON_S_MEMFREE,
+                val: 0x1000,
+            },
+            VirtioBalloonStat {
+                tag: VIRTIO_BALLOON_S_MEMTOT,
+                val: 0x4000,
+            },
+            VirtioBalloonStat { tag: 42, val: 1 },
+        ];
+        let stat_size = size_of::<VirtioBalloonStat>() as u64;
+        for (i, stat) in stats.iter().enumerate() {
+            mem.write_obj(*stat, GuestAddress(BUFFER_ADDR + i as u64 * stat_size))
+                .unwrap();
+        }
+        guest_q.dtable[0].set(
+            BUFFER_ADDR,
+            2 * stat_size as u32,
+            VRING_DESC_F_NEXT.try_into().unwrap(),
+            1,
+        );
+        guest_q.dtable[1].set(BUFFER_ADDR + 2 * stat_size, stat_size as u32, 0, 0);
+        guest_q.avail.ring[0].set(0);
+        guest_q.avail.idx.set(1);
+
+        handler.process_stats_queue(0).unwrap();
+
+        let current = *statistics.lock().unwrap();
+        assert_eq!(current.free_memory, Some(0x1000));
+        assert_eq!(current.total_memory, Some(0x4000));
+        assert_eq!(current.swap_in, None);
+        // Sizes are not reported by the guest
+        assert_eq!(current.target_bytes, 1 << 20);
+
+        // The buffer is held until the next polling period
+        assert_eq!(handler.stats_desc_index, Some(0));
+        assert_eq!(guest_q.used.idx.get(), 0);
+
+        handler.request_stats(0).unwrap();
+        assert_eq!(handler.stats_desc_index, None);
+        assert_eq!(guest_q.used.idx.get(), 1);
+
+        // Nothing to give back when no buffer is held
+        handler.request_stats(0).unwrap();
+        assert_eq!(guest_q.used.idx.get(), 1);
+    }
+
+    #[test]
+    fn test_balloon_stats_queue_write_only() {
+        let mem = guest_memory();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, STATS_QUEUE_SIZE);
+        let mut handler = epoll_handler(
+            &mem,
+            guest_q.create_queue(),
+            Arc::new(Mutex::new(BalloonStatistics::default())),
+            Arc::new((Mutex::new(FreePageHints::default()), Condvar::new())),
+        );
+
+        guest_q.dtable[0].set(BUFFER_ADDR, 10, VRING_DESC_F_WRITE.try_into().unwrap(), 0);
+        guest_q.avail.ring[0].set(0);
+        guest_q.avail.idx.set(1);
+
+        assert!(matches!(
+            handler.process_stats_queue(0),
+            Err(Error::UnexpectedWriteOnlyDescriptor)
+        ));
+    }
+
+    #[test]
+    fn test_balloon_free_page_hints() {
+        let mem = guest_memory();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, 4);
+        let mut balloon = hinting_balloon();
+        let mut handler = epoll_handler(
+            &mem,
+            guest_q.create_queue(),
+            balloon.statistics.clone(),
+            balloon.free_page_hints.clone(),
+        );
+
+        let waiter = balloon.start_free_page_hinting().unwrap().unwrap();
+        let cmd_id = balloon.config.free_page_hint_cmd_id;
+        assert!(cmd_id > VIRTIO_BALLOON_CMD_ID_DONE);
+        assert!(waiter.wait(Duration::ZERO).is_none());
+
+        let hint = MemoryRange {
+            gpa: 0x40_0000,
+            length: 0x10_0000,
+        };
+        queue_hints(&mem, &guest_q, cmd_id, hint.clone());
+
+        // The waiter is woken up by the queue being processed
+        let waiting = thread::spawn(move || waiter.wait(Duration::from_secs(10)));
+        handler.process_free_page_hint_queue(0).unwrap();
+        let hints = waiting.join().unwrap().unwrap();
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].gpa, hint.gpa);
+        assert_eq!(hints[0].length, hint.length);
+        assert_eq!(guest_q.used.idx.get(), 3);
+
+        balloon.stop_free_page_hinting().unwrap();
+        assert_eq!(
+            balloon.config.free_page_hint_cmd_id,
+            VIRTIO_BALLOON_CMD_ID_DONE
+        );
+    }
+
+    #[test]
+    fn test_balloon_free_page_hints_stale() {
+        let mem = guest_memory();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, 4);
+        let mut balloon = hinting_balloon();
+        let mut handler = epoll_handler(
+            &mem,
+            guest_q.create_queue(),
+            balloon.statistics.clone(),
+            balloon.free_page_hints.clone(),
+        );
+
+        let waiter = balloon.start_free_page_hinting().unwrap().unwrap();
+        let cmd_id = balloon.config.free_page_hint_cmd_id;
+
+        // Hints sent for a previous request are ignored
+        let hint = MemoryRange {
+            gpa: 0x40_0000,
+            length: 0x1000,
+        };
+        queue_hints(&mem, &guest_q, cmd_id - 1, hint);
+        handler.process_free_page_hint_queue(0).unwrap();
+        assert!(waiter.wait(Duration::ZERO).is_none());
+
+        // Stopping the hinting releases a waiter
+        let waiting = thread::spawn(move || waiter.wait(Duration::from_secs(10)));
+        balloon.stop_free_page_hinting().unwrap();
+        assert!(waiting.join().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_balloon_free_page_hinting_not_acked() {
+        let mut balloon = hinting_balloon();
+        balloon.common.acked_features = 0;
+        assert!(balloon.start_free_page_hinting().unwrap().is_none());
+    }
+}
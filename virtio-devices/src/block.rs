@@ -20,7 +20,7 @@ use std::{io, result};
 use anyhow::anyhow;
 use block::async_io::{AsyncIo, AsyncIoError, DiskFile};
 use block::{build_serial, Request, RequestType, VirtioBlockConfig};
-use rate_limiter::group::{RateLimiterGroup, RateLimiterGroupHandle};
+use rate_limiter::group::{HandleShare, RateLimiterGroup, RateLimiterGroupHandle};
 use rate_limiter::TokenType;
 use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
@@ -41,7 +41,7 @@ use super::{
 };
 use crate::seccomp_filters::Thread;
 use crate::thread_helper::spawn_virtio_thread;
-use crate::{GuestMemoryMmap, VirtioInterrupt};
+use crate::{CountersReader, GuestMemoryMmap, RateLimiterConfig, ThrottledTime, VirtioInterrupt};
 
 const SECTOR_SHIFT: u8 = 9;
 pub const SECTOR_SIZE: u64 = 0x01 << SECTOR_SHIFT;
@@ -122,7 +122,7 @@ struct BlockEpollHandler {
     queue: Queue,
     mem: GuestMemoryAtomic<GuestMemoryMmap>,
     disk_image: Box<dyn AsyncIo>,
-    disk_nsectors: u64,
+    disk_nsectors: Arc<AtomicU64>,
     interrupt_cb: Arc<dyn VirtioInterrupt>,
     serial: Vec<u8>,
     kill_evt: EventFd,
@@ -204,7 +204,7 @@ impl BlockEpollHandler {
             if request
                 .execute_async(
                     desc_chain.memory(),
-                    self.disk_nsectors,
+                    self.disk_nsectors.load(Ordering::Acquire),
                     self.disk_image.as_mut(),
                     &self.serial,
                     desc_chain.head_index() as u64,
@@ -558,12 +558,14 @@ pub struct Block {
     id: String,
     disk_image: Box<dyn DiskFile>,
     disk_path: PathBuf,
-    disk_nsectors: u64,
+    disk_nsectors: Arc<AtomicU64>,
     config: VirtioBlockConfig,
     writeback: Arc<AtomicBool>,
     counters: BlockCounters,
     seccomp_action: SeccompAction,
     rate_limiter: Option<Arc<RateLimiterGroup>>,
+    rate_limit_share: HandleShare,
+    throttled_time: ThrottledTime,
     exit_evt: EventFd,
     read_only: bool,
     serial: Vec<u8>,
@@ -593,6 +595,7 @@ impl Block {
         serial: Option<String>,
         seccomp_action: SeccompAction,
         rate_limiter: Option<Arc<RateLimiterGroup>>,
+        rate_limit_share: HandleShare,
         exit_evt: EventFd,
         state: Option<BlockState>,
         queue_affinity: BTreeMap<u16, Vec<usize>>,
@@ -690,12 +693,14 @@ impl Block {
             id,
             disk_image,
             disk_path,
-            disk_nsectors,
+            disk_nsectors: Arc::new(AtomicU64::new(disk_nsectors)),
             config,
             writeback: Arc::new(AtomicBool::new(true)),
             counters: BlockCounters::default(),
             seccomp_action,
             rate_limiter,
+            rate_limit_share,
+            throttled_time: ThrottledTime::default(),
             exit_evt,
             read_only,
             serial,
@@ -706,7 +711,7 @@ impl Block {
     fn state(&self) -> BlockState {
         BlockState {
             disk_path: self.disk_path.to_str().unwrap().to_owned(),
-            disk_nsectors: self.disk_nsectors,
+            disk_nsectors: self.disk_nsectors.load(Ordering::Acquire),
             avail_features: self.common.avail_features,
             acked_features: self.common.acked_features,
             config: self.config,
@@ -800,6 +805,7 @@ impl VirtioDevice for Block {
 
         let mut epoll_threads = Vec::new();
         let event_idx = self.common.feature_acked(VIRTIO_RING_F_EVENT_IDX.into());
+        self.throttled_time.reset();
 
         for i in 0..queues.len() {
             let (_, mut queue, queue_evt) = queues.remove(0);
@@ -809,6 +815,16 @@ impl VirtioDevice for Block {
             let (kill_evt, pause_evt) = self.common.dup_eventfds();
             let queue_idx = i as u16;
 
+            let rate_limiter = self
+                .rate_limiter
+                .as_ref()
+                .map(|r| r.new_handle_with_share(self.rate_limit_share))
+                .transpose()
+                .map_err(ActivateError::CreateRateLimiterGroupHandle)?;
+            if let Some(rate_limiter) = &rate_limiter {
+                self.throttled_time.add(rate_limiter.throttle_stats());
+            }
+
             let mut handler = BlockEpollHandler {
                 queue_index: queue_idx,
                 queue,
@@ -820,7 +836,7 @@ impl VirtioDevice for Block {
                         error!("failed to create new AsyncIo: {}", e);
                         ActivateError::BadActivate
                     })?,
-                disk_nsectors: self.disk_nsectors,
+                disk_nsectors: self.disk_nsectors.clone(),
                 interrupt_cb: interrupt_cb.clone(),
                 serial: self.serial.clone(),
                 kill_evt,
@@ -832,12 +848,7 @@ impl VirtioDevice for Block {
                 // This gives head room for systems with slower I/O without
                 // compromising the cost of the reallocation or memory overhead
                 inflight_requests: VecDeque::with_capacity(64),
-                rate_limiter: self
-                    .rate_limiter
-                    .as_ref()
-                    .map(|r| r.new_handle())
-                    .transpose()
-                    .unwrap(),
+                rate_limiter,
                 access_platform: self.common.access_platform.clone(),
                 read_only: self.read_only,
                 host_cpus: self.queue_affinity.get(&queue_idx).cloned(),
@@ -868,56 +879,115 @@ impl VirtioDevice for Block {
         result
     }
 
-    fn counters(&self) -> Option<HashMap<&'static str, Wrapping<u64>>> {
-        let mut counters = HashMap::new();
+    fn counters_reader(&self) -> Option<CountersReader> {
+        let stats = self.counters.clone();
+        let throttled_time = self
+            .rate_limiter
+            .is_some()
+            .then(|| self.throttled_time.clone());
 
-        counters.insert(
-            "read_bytes",
-            Wrapping(self.counters.read_bytes.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_bytes",
-            Wrapping(self.counters.write_bytes.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "read_ops",
-            Wrapping(self.counters.read_ops.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_ops",
-            Wrapping(self.counters.write_ops.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_latency_min",
-            Wrapping(self.counters.write_latency_min.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_latency_max",
-            Wrapping(self.counters.write_latency_max.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_latency_avg",
-            Wrapping(self.counters.write_latency_avg.load(Ordering::Acquire) / LATENCY_SCALE),
-        );
-        counters.insert(
-            "read_latency_min",
-            Wrapping(self.counters.read_latency_min.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "read_latency_max",
-            Wrapping(self.counters.read_latency_max.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "read_latency_avg",
-            Wrapping(self.counters.read_latency_avg.load(Ordering::Acquire) / LATENCY_SCALE),
-        );
+        Some(Arc::new(move || {
+            let mut counters = HashMap::new();
+
+            counters.insert(
+                "read_bytes",
+                Wrapping(stats.read_bytes.load(Ordering::Acquire)),
+            );
+            counters.insert(
+                "write_bytes",
+                Wrapping(stats.write_bytes.load(Ordering::Acquire)),
+            );
+            counters.insert("read_ops", Wrapping(stats.read_ops.load(Ordering::Acquire)));
+            counters.insert(
+                "write_ops",
+                Wrapping(stats.write_ops.load(Ordering::Acquire)),
+            );
+            counters.insert(
+                "write_latency_min",
+                Wrapping(stats.write_latency_min.load(Ordering::Acquire)),
+            );
+            counters.insert(
+                "write_latency_max",
+                Wrapping(stats.write_latency_max.load(Ordering::Acquire)),
+            );
+            counters.insert(
+                "write_latency_avg",
+                Wrapping(stats.write_latency_avg.load(Ordering::Acquire) / LATENCY_SCALE),
+            );
+            counters.insert(
+                "read_latency_min",
+                Wrapping(stats.read_latency_min.load(Ordering::Acquire)),
+            );
+            counters.insert(
+                "read_latency_max",
+                Wrapping(stats.read_latency_max.load(Ordering::Acquire)),
+            );
+            counters.insert(
+                "read_latency_avg",
+                Wrapping(stats.read_latency_avg.load(Ordering::Acquire) / LATENCY_SCALE),
+            );
+            if let Some(throttled_time) = &throttled_time {
+                counters.insert("throttled_time_us", Wrapping(throttled_time.total_us()));
+            }
 
-        Some(counters)
+            counters
+        }))
     }
 
     fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
         self.common.set_access_platform(access_platform)
     }
+
+    fn update_rate_limiter(
+        &mut self,
+        config: RateLimiterConfig,
+    ) -> result::Result<(), DeviceError> {
+        let rate_limiter = self
+            .rate_limiter
+            .as_ref()
+            .ok_or(DeviceError::NoRateLimiter)?;
+        let (bytes, ops) = config.bucket_updates();
+        // The handles held by the queue handlers share the group's buckets,
+        // so the new limits apply to every queue right away.
+        rate_limiter.update_buckets(bytes, ops);
+
+        Ok(())
+    }
+
+    fn resize_disk(&mut self, size: u64) -> result::Result<(), DeviceError> {
+        if size % SECTOR_SIZE != 0 {
+            return Err(DeviceError::InvalidDiskSize(size));
+        }
+        // The guest may have data past the new end of the disk.
+        if size < self.config.capacity * SECTOR_SIZE {
+            return Err(DeviceError::ShrinkDiskNotSupported(size));
+        }
+
+        self.disk_image
+            .resize(size)
+            .map_err(DeviceError::ResizeDisk)?;
+        // Block devices are not resized by the VMM, their actual size is
+        // exposed to the guest.
+        let disk_size = self.disk_image.size().map_err(DeviceError::ResizeDisk)?;
+        let disk_nsectors = disk_size / SECTOR_SIZE;
+
+        // Both are only ever updated with the device lock held, the one
+        // activate() and the config space accesses are serialized with, so
+        // that queue handlers and the guest never see different capacities.
+        // Requests are checked against the new capacity as soon as it is
+        // stored, before the guest is notified about it.
+        self.disk_nsectors.store(disk_nsectors, Ordering::Release);
+        self.config.capacity = disk_nsectors;
+
+        if let Some(interrupt_cb) = self.common.interrupt_cb.as_ref() {
+            interrupt_cb
+                .trigger(VirtioInterruptType::Config)
+                .map_err(DeviceError::FailedSignalingConfigChange)?;
+        }
+
+        event!("virtio-device", "resized", "id", &self.id);
+        Ok(())
+    }
 }
 
 impl Pausable for Block {
@@ -941,3 +1011,92 @@ impl Snapshottable for Block {
 }
 impl Transportable for Block {}
 impl Migratable for Block {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use block::raw_sync::RawFileDiskSync;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    // Record the interrupts raised by the device.
+    #[derive(Default)]
+    struct RecordingInterrupt {
+        triggered: Mutex<Vec<VirtioInterruptType>>,
+    }
+
+    impl VirtioInterrupt for RecordingInterrupt {
+        fn trigger(&self, int_type: VirtioInterruptType) -> result::Result<(), io::Error> {
+            self.triggered.lock().unwrap().push(int_type);
+            Ok(())
+        }
+    }
+
+    fn create_block(file: &TempFile) -> Block {
+        let disk_image = RawFileDiskSync::new(file.as_file().try_clone().unwrap());
+        Block::new(
+            String::from("disk0"),
+            Box::new(disk_image),
+            file.as_path().to_path_buf(),
+            false,
+            false,
+            1,
+            128,
+            None,
+            SeccompAction::Trap,
+            None,
+            HandleShare::default(),
+            EventFd::new(0).unwrap(),
+            None,
+            BTreeMap::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_block_resize_disk() {
+        let file = TempFile::new().unwrap();
+        file.as_file().set_len(0x10_0000).unwrap();
+        let mut block = create_block(&file);
+        let interrupt = Arc::new(RecordingInterrupt::default());
+        block.common.interrupt_cb = Some(interrupt.clone());
+
+        block.resize_disk(0x20_0000).unwrap();
+        assert_eq!(file.as_file().metadata().unwrap().len(), 0x20_0000);
+        assert_eq!({ block.config.capacity }, 0x20_0000 / SECTOR_SIZE);
+        assert_eq!(
+            block.disk_nsectors.load(Ordering::Acquire),
+            0x20_0000 / SECTOR_SIZE
+        );
+        // The guest is told to read the new capacity.
+        assert!(matches!(
+            interrupt.triggered.lock().unwrap().as_slice(),
+            [VirtioInterruptType::Config]
+        ));
+    }
+
+    #[test]
+    fn test_block_resize_disk_invalid() {
+        let file = TempFile::new().unwrap();
+        file.as_file().set_len(0x10_0000).unwrap();
+        let mut block = create_block(&file);
+        let interrupt = Arc::new(RecordingInterrupt::default());
+        block.common.interrupt_cb = Some(interrupt.clone());
+
+        assert!(matches!(
+            block.resize_disk(0x10_0100),
+            Err(DeviceError::InvalidDiskSize(0x10_0100))
+        ));
+        assert!(matches!(
+            block.resize_disk(0x8_0000),
+            Err(DeviceError::ShrinkDiskNotSupported(0x8_0000))
+        ));
+
+        // Neither the disk nor the capacity has changed.
+        assert_eq!(file.as_file().metadata().unwrap().len(), 0x10_0000);
+        assert_eq!({ block.config.capacity }, 0x10_0000 / SECTOR_SIZE);
+        assert!(interrupt.triggered.lock().unwrap().is_empty());
+    }
+}
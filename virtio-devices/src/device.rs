@@ -21,10 +21,13 @@ use vm_virtio::{AccessPlatform, VirtioDeviceType};
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::{
-    ActivateError, ActivateResult, Error, GuestMemoryMmap, GuestRegionMmap,
+    ActivateError, ActivateResult, Error, GuestMemoryMmap, GuestRegionMmap, RateLimiterConfig,
     VIRTIO_F_RING_INDIRECT_DESC,
 };
 
+/// Reads the counters of a device without going through the device lock.
+pub type CountersReader = Arc<dyn Fn() -> HashMap<&'static str, Wrapping<u64>> + Send + Sync>;
+
 pub enum VirtioInterruptType {
     Config,
     Queue(u16),
@@ -141,6 +144,13 @@ pub trait VirtioDevice: Send {
         Ok(())
     }
 
+    fn remove_memory_region(
+        &mut self,
+        _region: &Arc<GuestRegionMmap>,
+    ) -> std::result::Result<(), Error> {
+        Ok(())
+    }
+
     /// Returns the list of userspace mappings associated with this device.
     fn userspace_mappings(&self) -> Vec<UserspaceMapping> {
         Vec::new()
@@ -148,6 +158,12 @@ pub trait VirtioDevice: Send {
 
     /// Return the counters that this device exposes
     fn counters(&self) -> Option<HashMap<&'static str, Wrapping<u64>>> {
+        self.counters_reader().map(|read| read())
+    }
+
+    /// Return a reader of the counters that this device exposes, which keeps
+    /// working while the device is locked, e.g. by a snapshot
+    fn counters_reader(&self) -> Option<CountersReader> {
         None
     }
 
@@ -174,6 +190,16 @@ pub trait VirtioDevice: Send {
     /// Set the access platform trait to let the device perform address
     /// translations if needed.
     fn set_access_platform(&mut self, _access_platform: Arc<dyn AccessPlatform>) {}
+
+    /// Update the rate limiter of a running device with a new configuration.
+    fn update_rate_limiter(&mut self, _config: RateLimiterConfig) -> Result<(), Error> {
+        Err(Error::UpdateRateLimiterNotSupported)
+    }
+
+    /// Grow the disk of a running device to `size` bytes.
+    fn resize_disk(&mut self, _size: u64) -> Result<(), Error> {
+        Err(Error::ResizeDiskNotSupported)
+    }
 }
 
 /// Trait to define address translation for devices managed by virtio-iommu
@@ -0,0 +1,513 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Virtio 9P device sharing a host directory with the guest, without relying
+//! on an external daemon.
+
+mod protocol;
+mod server;
+
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Barrier, Mutex};
+use std::{io, result};
+
+use anyhow::anyhow;
+use seccompiler::SeccompAction;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use virtio_queue::{Queue, QueueT};
+use vm_memory::{Bytes, GuestAddressSpace, GuestMemoryAtomic};
+use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
+use vm_virtio::{AccessPlatform, Translatable};
+use vmm_sys_util::eventfd::EventFd;
+
+use self::server::{Server, MAX_MSIZE};
+use super::{
+    ActivateResult, EpollHelper, EpollHelperError, EpollHelperHandler, Error as DeviceError,
+    VirtioCommon, VirtioDevice, VirtioDeviceType, EPOLL_HELPER_EVENT_LAST, VIRTIO_F_IOMMU_PLATFORM,
+    VIRTIO_F_VERSION_1,
+};
+use crate::seccomp_filters::Thread;
+use crate::thread_helper::spawn_virtio_thread;
+use crate::{GuestMemoryMmap, VirtioInterrupt, VirtioInterruptType};
+
+const QUEUE_SIZE: u16 = 128;
+const QUEUE_SIZES: &[u16] = &[QUEUE_SIZE];
+
+// The mount tag is available in the device configuration space.
+const VIRTIO_9P_MOUNT_TAG: u64 = 0;
+
+/// Maximum length of the mount tag.
+pub const VIRTIO_9P_TAG_LEN: usize = 64;
+
+// New descriptors are pending on the virtio queue.
+const QUEUE_AVAIL_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 1;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("Failed to read from guest memory: {0}")]
+    GuestMemoryRead(vm_memory::guest_memory::Error),
+    #[error("Failed to write to guest memory: {0}")]
+    GuestMemoryWrite(vm_memory::guest_memory::Error),
+    #[error("Reply does not fit in the descriptor chain")]
+    ReplyTooLarge,
+    #[error("Failed adding used index: {0}")]
+    QueueAddUsed(virtio_queue::Error),
+}
+
+struct Fs9pEpollHandler {
+    mem: GuestMemoryAtomic<GuestMemoryMmap>,
+    queue: Queue,
+    server: Arc<Mutex<Server>>,
+    interrupt_cb: Arc<dyn VirtioInterrupt>,
+    queue_evt: EventFd,
+    kill_evt: EventFd,
+    pause_evt: EventFd,
+    access_platform: Option<Arc<dyn AccessPlatform>>,
+}
+
+impl Fs9pEpollHandler {
+    fn process_queue(&mut self) -> result::Result<bool, Error> {
+        let mem = self.mem.memory();
+        let mut used_descs = false;
+        while let Some(mut desc_chain) = self.queue.pop_descriptor_chain(self.mem.memory()) {
+            // The request is held by the readable descriptors, followed by the
+            // writable ones receiving the reply. No request can be larger
+            // than the message size negotiated with the guest, only the
+            // header of a larger one is read, to reject it.
+            let mut request = Vec::new();
+            let mut request_len = 0u64;
+            let mut reply_descs = Vec::new();
+            for desc in desc_chain.by_ref() {
+                let addr = desc
+                    .addr()
+                    .translate_gva(self.access_platform.as_ref(), desc.len() as usize);
+                if desc.is_write_only() {
+                    reply_descs.push((addr, desc.len() as usize));
+                } else {
+                    request_len += u64::from(desc.len());
+                    let start = request.len();
+                    let len = (desc.len() as usize).min(MAX_MSIZE as usize - start);
+                    request.resize(start + len, 0);
+                    mem.read_slice(&mut request[start..], addr)
+                        .map_err(Error::GuestMemoryRead)?;
+                }
+            }
+
+            let max_reply = reply_descs.iter().map(|(_, len)| len).sum();
+            let reply = if request_len > u64::from(MAX_MSIZE) {
+                warn!(
+                    "Rejecting 9p request of {} bytes, larger than {} bytes",
+                    request_len, MAX_MSIZE
+                );
+                Server::reject(&request, io::Error::from_raw_os_error(libc::EMSGSIZE))
+            } else {
+                self.server.lock().unwrap().handle(&request, max_reply)
+            };
+            if reply.len() > max_reply {
+                return Err(Error::ReplyTooLarge);
+            }
+
+            let mut written = 0;
+            for (addr, len) in reply_descs {
+                if written == reply.len() {
+                    break;
+                }
+                let len = len.min(reply.len() - written);
+                mem.write_slice(&reply[written..written + len], addr)
+                    .map_err(Error::GuestMemoryWrite)?;
+                written += len;
+            }
+
+            self.queue
+                .add_used(desc_chain.memory(), desc_chain.head_index(), written as u32)
+                .map_err(Error::QueueAddUsed)?;
+            used_descs = true;
+        }
+
+        Ok(used_descs)
+    }
+
+    fn signal_used_queue(&self) -> result::Result<(), DeviceError> {
+        self.interrupt_cb
+            .trigger(VirtioInterruptType::Queue(0))
+            .map_err(|e| {
+                error!("Failed to signal used queue: {:?}", e);
+                DeviceError::FailedSignalingUsedQueue(e)
+            })
+    }
+
+    fn run(
+        &mut self,
+        paused: Arc<AtomicBool>,
+        paused_sync: Arc<Barrier>,
+    ) -> result::Result<(), EpollHelperError> {
+        let mut helper = EpollHelper::new(&self.kill_evt, &self.pause_evt)?;
+        helper.add_event(self.queue_evt.as_raw_fd(), QUEUE_AVAIL_EVENT)?;
+        helper.run(paused, paused_sync, self)?;
+
+        Ok(())
+    }
+}
+
+impl EpollHelperHandler for Fs9pEpollHandler {
+    fn handle_event(
+        &mut self,
+        _helper: &mut EpollHelper,
+        event: &epoll::Event,
+    ) -> result::Result<(), EpollHelperError> {
+        let ev_type = event.data as u16;
+        match ev_type {
+            QUEUE_AVAIL_EVENT => {
+                self.queue_evt.read().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Failed to get queue event: {:?}", e))
+                })?;
+                let needs_notification = self.process_queue().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Failed to process queue : {:?}", e))
+                })?;
+                if needs_notification {
+                    self.signal_used_queue().map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Failed to signal used queue: {:?}",
+                            e
+                        ))
+                    })?;
+                }
+            }
+            _ => {
+                return Err(EpollHelperError::HandleEvent(anyhow!(
+                    "Unexpected event: {}",
+                    ev_type
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Virtio device sharing a host directory with the guest through 9P.
+pub struct Fs9p {
+    common: VirtioCommon,
+    id: String,
+    config: Vec<u8>,
+    server: Arc<Mutex<Server>>,
+    seccomp_action: SeccompAction,
+    exit_evt: EventFd,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Fs9pState {
+    pub avail_features: u64,
+    pub acked_features: u64,
+}
+
+impl Fs9p {
+    /// Create a new virtio 9P device sharing the directory at `path`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: String,
+        path: &Path,
+        tag: &str,
+        readonly: bool,
+        iommu: bool,
+        seccomp_action: SeccompAction,
+        exit_evt: EventFd,
+        state: Option<Fs9pState>,
+    ) -> io::Result<Fs9p> {
+        let server = Server::new(path, readonly)?;
+
+        let (avail_features, acked_features, paused) = if let Some(state) = state {
+            info!("Restoring virtio-9p {}", id);
+            (state.avail_features, state.acked_features, true)
+        } else {
+            let mut avail_features = (1u64 << VIRTIO_F_VERSION_1) | (1u64 << VIRTIO_9P_MOUNT_TAG);
+
+            if iommu {
+                avail_features |= 1u64 << VIRTIO_F_IOMMU_PLATFORM;
+            }
+
+            (avail_features, 0, false)
+        };
+
+        // The configuration space holds the length of the tag followed by
+        // the tag itself.
+        let tag = &tag.as_bytes()[..tag.len().min(VIRTIO_9P_TAG_LEN)];
+        let mut config = (tag.len() as u16).to_le_bytes().to_vec();
+        config.extend_from_slice(tag);
+
+        Ok(Fs9p {
+            common: VirtioCommon {
+                device_type: VirtioDeviceType::Fs9P as u32,
+                queue_sizes: QUEUE_SIZES.to_vec(),
+                paused_sync: Some(Arc::new(Barrier::new(2))),
+                avail_features,
+                acked_features,
+                min_queues: 1,
+                paused: Arc::new(AtomicBool::new(paused)),
+                ..Default::default()
+            },
+            id,
+            config,
+            server: Arc::new(Mutex::new(server)),
+            seccomp_action,
+            exit_evt,
+        })
+    }
+
+    fn state(&self) -> Fs9pState {
+        Fs9pState {
+            avail_features: self.common.avail_features,
+            acked_features: self.common.acked_features,
+        }
+    }
+
+    // The fids only live in the server, along with the host files they
+    // designate, none of which is part of the device state. A guest restored
+    // with fids in use would get EBADF for each of them.
+    fn check_no_fids(&self) -> result::Result<(), String> {
+        if self.server.lock().unwrap().has_fids() {
+            return Err(format!(
+                "virtio-9p device {} is in use by the guest, unmount it first",
+                self.id
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Fs9p {
+    fn drop(&mut self) {
+        if let Some(kill_evt) = self.common.kill_evt.take() {
+            // Ignore the result because there is nothing we can do about it.
+            let _ = kill_evt.write(1);
+        }
+        self.common.wait_for_epoll_threads();
+    }
+}
+
+impl VirtioDevice for Fs9p {
+    fn device_type(&self) -> u32 {
+        self.common.device_type
+    }
+
+    fn queue_max_sizes(&self) -> &[u16] {
+        &self.common.queue_sizes
+    }
+
+    fn features(&self) -> u64 {
+        self.common.avail_features
+    }
+
+    fn ack_features(&mut self, value: u64) {
+        self.common.ack_features(value)
+    }
+
+    fn read_config(&self, offset: u64, data: &mut [u8]) {
+        self.read_config_from_slice(&self.config, offset, data);
+    }
+
+    fn activate(
+        &mut self,
+        mem: GuestMemoryAtomic<GuestMemoryMmap>,
+        interrupt_cb: Arc<dyn VirtioInterrupt>,
+        mut queues: Vec<(usize, Queue, EventFd)>,
+    ) -> ActivateResult {
+        self.common.activate(&queues, &interrupt_cb)?;
+        let (kill_evt, pause_evt) = self.common.dup_eventfds();
+
+        let (_, queue, queue_evt) = queues.remove(0);
+
+        let mut handler = Fs9pEpollHandler {
+            mem,
+            queue,
+            server: self.server.clone(),
+            interrupt_cb,
+            queue_evt,
+            kill_evt,
+            pause_evt,
+            access_platform: self.common.access_platform.clone(),
+        };
+
+        let paused = self.common.paused.clone();
+        let paused_sync = self.common.paused_sync.clone();
+        let mut epoll_threads = Vec::new();
+        spawn_virtio_thread(
+            &self.id,
+            &self.seccomp_action,
+            Thread::VirtioFs9p,
+            &mut epoll_threads,
+            &self.exit_evt,
+            move || handler.run(paused, paused_sync.unwrap()),
+        )?;
+
+        self.common.epoll_threads = Some(epoll_threads);
+
+        event!("virtio-device", "activated", "id", &self.id);
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Option<Arc<dyn VirtioInterrupt>> {
+        let result = self.common.reset();
+        event!("virtio-device", "reset", "id", &self.id);
+        result
+    }
+
+    fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
+        self.common.set_access_platform(access_platform)
+    }
+}
+
+impl Pausable for Fs9p {
+    fn pause(&mut self) -> result::Result<(), MigratableError> {
+        self.common.pause()
+    }
+
+    fn resume(&mut self) -> result::Result<(), MigratableError> {
+        self.common.resume()
+    }
+}
+
+impl Snapshottable for Fs9p {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn snapshot(&mut self) -> std::result::Result<Snapshot, MigratableError> {
+        self.check_no_fids()
+            .map_err(|e| MigratableError::Snapshot(anyhow!(e)))?;
+        Snapshot::new_from_state(&self.state())
+    }
+}
+
+impl Transportable for Fs9p {}
+
+impl Migratable for Fs9p {
+    fn start_migration(&mut self) -> std::result::Result<(), MigratableError> {
+        self.check_no_fids()
+            .map_err(|e| MigratableError::StartMigration(anyhow!(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use virtio_bindings::virtio_ring::{VRING_DESC_F_NEXT, VRING_DESC_F_WRITE};
+    use vm_memory::GuestAddress;
+    use vm_virtio::queue::testing::VirtQueue as GuestQ;
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::protocol::*;
+    use super::*;
+    use crate::vsock::tests::NoopVirtioInterrupt;
+
+    const MEM_SIZE: usize = 0x100_0000;
+    const QUEUE_ADDR: u64 = 0x10_0000;
+    const REQUEST_ADDR: u64 = 0x20_0000;
+    const REPLY_ADDR: u64 = 0x40_0000;
+
+    fn create_fs9p(dir: &TempDir) -> Fs9p {
+        Fs9p::new(
+            String::from("fs9p"),
+            dir.as_path(),
+            "share",
+            false,
+            false,
+            SeccompAction::Trap,
+            EventFd::new(0).unwrap(),
+            None,
+        )
+        .unwrap()
+    }
+
+    fn create_handler(fs9p: &Fs9p, mem: &GuestMemoryMmap, queue: Queue) -> Fs9pEpollHandler {
+        Fs9pEpollHandler {
+            mem: GuestMemoryAtomic::new(mem.clone()),
+            queue,
+            server: fs9p.server.clone(),
+            interrupt_cb: Arc::new(NoopVirtioInterrupt {}),
+            queue_evt: EventFd::new(0).unwrap(),
+            kill_evt: EventFd::new(0).unwrap(),
+            pause_evt: EventFd::new(0).unwrap(),
+            access_platform: None,
+        }
+    }
+
+    fn attach(fs9p: &Fs9p) {
+        let mut server = fs9p.server.lock().unwrap();
+        let mut request = WireWriter::new(P9_TVERSION, 0);
+        request.u32(MAX_MSIZE);
+        request.string(P9_VERSION);
+        server.handle(&request.finish(), MAX_MSIZE as usize);
+
+        let mut request = WireWriter::new(P9_TATTACH, 1);
+        request.u32(0);
+        request.u32(u32::MAX);
+        request.string("root");
+        request.string("");
+        request.u32(0);
+        server.handle(&request.finish(), MAX_MSIZE as usize);
+    }
+
+    #[test]
+    fn test_fs9p_request_too_large() {
+        let dir = TempDir::new().unwrap();
+        let fs9p = create_fs9p(&dir);
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, QUEUE_SIZE);
+
+        // A single byte over the message size is enough for the request to
+        // be rejected.
+        let mut request = WireWriter::new(P9_TWRITE, 3);
+        request.u32(0);
+        mem.write_slice(&request.finish(), GuestAddress(REQUEST_ADDR))
+            .unwrap();
+        guest_q.dtable[0].set(
+            REQUEST_ADDR,
+            MAX_MSIZE,
+            VRING_DESC_F_NEXT.try_into().unwrap(),
+            1,
+        );
+        guest_q.dtable[1].set(
+            REQUEST_ADDR + u64::from(MAX_MSIZE),
+            1,
+            VRING_DESC_F_NEXT.try_into().unwrap(),
+            2,
+        );
+        guest_q.dtable[2].set(REPLY_ADDR, 64, VRING_DESC_F_WRITE.try_into().unwrap(), 0);
+        guest_q.avail.ring[0].set(0);
+        guest_q.avail.idx.set(1);
+
+        let mut handler = create_handler(&fs9p, &mem, guest_q.create_queue());
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 1);
+
+        let mut reply = [0u8; HEADER_SIZE + 4];
+        mem.read_slice(&mut reply, GuestAddress(REPLY_ADDR))
+            .unwrap();
+        let mut r = WireReader::new(&reply);
+        assert_eq!(r.u32().unwrap() as usize, reply.len());
+        assert_eq!(r.u8().unwrap(), P9_RLERROR);
+        assert_eq!(r.u16().unwrap(), 3);
+        assert_eq!(r.u32().unwrap(), libc::EMSGSIZE as u32);
+    }
+
+    #[test]
+    fn test_fs9p_snapshot_with_fids() {
+        let dir = TempDir::new().unwrap();
+        let mut fs9p = create_fs9p(&dir);
+        fs9p.snapshot().unwrap();
+        fs9p.start_migration().unwrap();
+
+        // The fids can't be carried over, the device can't be saved while
+        // the guest holds some.
+        attach(&fs9p);
+        assert!(matches!(fs9p.snapshot(), Err(MigratableError::Snapshot(_))));
+        assert!(matches!(
+            fs9p.start_migration(),
+            Err(MigratableError::StartMigration(_))
+        ));
+    }
+}
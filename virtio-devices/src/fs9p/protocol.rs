@@ -0,0 +1,213 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Wire format of the 9P2000.L protocol.
+//!
+//! Every message starts with a `size[4] type[1] tag[2]` header, followed by
+//! fields encoded in little endian. Strings are prefixed by their length on
+//! two bytes.
+
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+pub const P9_VERSION: &str = "9P2000.L";
+
+/// Size of the `size[4] type[1] tag[2]` header of every message.
+pub const HEADER_SIZE: usize = 7;
+/// Size of the header of Rread and Rreaddir, including the `count[4]` field.
+pub const IO_HEADER_SIZE: usize = HEADER_SIZE + 4;
+/// Size of an encoded qid.
+pub const QID_SIZE: usize = 13;
+
+// Request types, the reply type is always the request type + 1.
+pub const P9_RLERROR: u8 = 7;
+pub const P9_TSTATFS: u8 = 8;
+pub const P9_TLOPEN: u8 = 12;
+pub const P9_TLCREATE: u8 = 14;
+pub const P9_TSYMLINK: u8 = 16;
+pub const P9_TMKNOD: u8 = 18;
+pub const P9_TRENAME: u8 = 20;
+pub const P9_TREADLINK: u8 = 22;
+pub const P9_TGETATTR: u8 = 24;
+pub const P9_TSETATTR: u8 = 26;
+pub const P9_TXATTRWALK: u8 = 30;
+pub const P9_TXATTRCREATE: u8 = 32;
+pub const P9_TREADDIR: u8 = 40;
+pub const P9_TFSYNC: u8 = 50;
+pub const P9_TLOCK: u8 = 52;
+pub const P9_TGETLOCK: u8 = 54;
+pub const P9_TLINK: u8 = 70;
+pub const P9_TMKDIR: u8 = 72;
+pub const P9_TRENAMEAT: u8 = 74;
+pub const P9_TUNLINKAT: u8 = 76;
+pub const P9_TVERSION: u8 = 100;
+pub const P9_TAUTH: u8 = 102;
+pub const P9_TATTACH: u8 = 104;
+pub const P9_TFLUSH: u8 = 108;
+pub const P9_TWALK: u8 = 110;
+pub const P9_TREAD: u8 = 116;
+pub const P9_TWRITE: u8 = 118;
+pub const P9_TCLUNK: u8 = 120;
+pub const P9_TREMOVE: u8 = 122;
+
+// Qid types
+pub const P9_QTDIR: u8 = 0x80;
+pub const P9_QTSYMLINK: u8 = 0x02;
+pub const P9_QTFILE: u8 = 0x00;
+
+// Valid fields of Tgetattr/Rgetattr
+pub const P9_GETATTR_BASIC: u64 = 0x0000_07ff;
+
+// Valid fields of Tsetattr
+pub const P9_SETATTR_MODE: u32 = 0x0000_0001;
+pub const P9_SETATTR_UID: u32 = 0x0000_0002;
+pub const P9_SETATTR_GID: u32 = 0x0000_0004;
+pub const P9_SETATTR_SIZE: u32 = 0x0000_0008;
+pub const P9_SETATTR_ATIME: u32 = 0x0000_0010;
+pub const P9_SETATTR_MTIME: u32 = 0x0000_0020;
+pub const P9_SETATTR_ATIME_SET: u32 = 0x0000_0080;
+pub const P9_SETATTR_MTIME_SET: u32 = 0x0000_0100;
+
+// Status of Rlock
+pub const P9_LOCK_SUCCESS: u8 = 0;
+// Lock type of Rgetlock
+pub const P9_LOCK_TYPE_UNLCK: u8 = 2;
+
+/// Maximum number of path elements in a single Twalk.
+pub const P9_MAXWELEM: usize = 16;
+
+/// Server side identifier of a file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Qid {
+    pub type_: u8,
+    pub version: u32,
+    pub path: u64,
+}
+
+impl Qid {
+    pub fn from_stat(st: &libc::stat) -> Self {
+        let type_ = match st.st_mode & libc::S_IFMT {
+            libc::S_IFDIR => P9_QTDIR,
+            libc::S_IFLNK => P9_QTSYMLINK,
+            _ => P9_QTFILE,
+        };
+
+        Qid {
+            type_,
+            version: 0,
+            path: st.st_ino,
+        }
+    }
+}
+
+/// Decodes the fields of a request.
+pub struct WireReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> WireReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        WireReader { buf }
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        self.buf.read_u8()
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        self.buf.read_u16::<LittleEndian>()
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        self.buf.read_u32::<LittleEndian>()
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        self.buf.read_u64::<LittleEndian>()
+    }
+
+    pub fn string(&mut self) -> io::Result<String> {
+        let len = self.u16()? as usize;
+        let mut bytes = vec![0u8; len];
+        self.buf.read_exact(&mut bytes)?;
+        String::from_utf8(bytes).map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))
+    }
+
+    pub fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.buf.len() {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        let (bytes, remaining) = self.buf.split_at(len);
+        self.buf = remaining;
+        Ok(bytes)
+    }
+}
+
+/// Encodes the fields of a reply.
+#[derive(Default)]
+pub struct WireWriter {
+    buf: Vec<u8>,
+}
+
+impl WireWriter {
+    /// Start a reply of the given type, leaving room for its size.
+    pub fn new(type_: u8, tag: u16) -> Self {
+        let mut writer = WireWriter {
+            buf: Vec::with_capacity(HEADER_SIZE),
+        };
+        writer.u32(0);
+        writer.u8(type_);
+        writer.u16(tag);
+        writer
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.write_u8(v).unwrap();
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.buf.write_u16::<LittleEndian>(v).unwrap();
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.buf.write_u32::<LittleEndian>(v).unwrap();
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.buf.write_u64::<LittleEndian>(v).unwrap();
+    }
+
+    pub fn string(&mut self, s: &str) {
+        self.u16(s.len() as u16);
+        self.buf.write_all(s.as_bytes()).unwrap();
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.write_all(bytes).unwrap();
+    }
+
+    pub fn qid(&mut self, qid: &Qid) {
+        self.u8(qid.type_);
+        self.u32(qid.version);
+        self.u64(qid.path);
+    }
+
+    pub fn size(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Return the fields encoded so far, for writers not created with a
+    /// message header.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// Complete the reply by filling its size.
+    pub fn finish(mut self) -> Vec<u8> {
+        let size = self.buf.len() as u32;
+        self.buf[..4].copy_from_slice(&size.to_le_bytes());
+        self.buf
+    }
+}
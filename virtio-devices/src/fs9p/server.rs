@@ -0,0 +1,1490 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! 9P2000.L server exposing a host directory.
+//!
+//! Every fid holds an `O_PATH` handle on the file it designates, along with
+//! its path relative to the shared directory. Paths are always resolved one
+//! component at a time with `O_NOFOLLOW`, so that neither `..` nor symbolic
+//! links can be used to reach files outside of the shared directory.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use super::protocol::*;
+
+/// Largest message size negotiated with the guest.
+pub const MAX_MSIZE: u32 = 1 << 20;
+
+// Flags of Tlopen/Tlcreate, as defined by the protocol independently of the
+// host architecture.
+const P9_DOTL_ACCMODE: u32 = 0o3;
+const P9_DOTL_TRUNC: u32 = 0o1000;
+const P9_DOTL_APPEND: u32 = 0o2000;
+const P9_DOTL_EXCL: u32 = 0o200;
+
+// Flag of Tunlinkat
+const P9_DOTL_AT_REMOVEDIR: u32 = 0x200;
+
+struct DirEntry {
+    qid: Qid,
+    type_: u8,
+    name: Vec<u8>,
+}
+
+struct Fid {
+    // Path relative to the shared directory, empty for the directory itself.
+    path: PathBuf,
+    // O_PATH handle on the file.
+    file: File,
+    qid: Qid,
+    // File opened through Tlopen or Tlcreate.
+    open: Option<File>,
+    // Directory entries, read when the guest starts reading the directory.
+    entries: Option<Vec<DirEntry>>,
+}
+
+fn errno(e: i32) -> io::Error {
+    io::Error::from_raw_os_error(e)
+}
+
+fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn cstring(name: &[u8]) -> io::Result<CString> {
+    CString::new(name).map_err(|_| errno(libc::EINVAL))
+}
+
+// Only accept names designating an entry of a directory.
+fn check_name(name: &str) -> io::Result<CString> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(errno(libc::EINVAL));
+    }
+    cstring(name.as_bytes())
+}
+
+fn openat(dir: &File, name: &CStr, flags: i32, mode: u32) -> io::Result<File> {
+    // SAFETY: FFI call with a valid dirfd and NUL terminated name.
+    let fd = cvt(unsafe {
+        libc::openat(
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            flags | libc::O_CLOEXEC | libc::O_NOFOLLOW,
+            mode,
+        )
+    })?;
+    // SAFETY: fd is a valid file descriptor we own.
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn open_path(dir: &File, name: &CStr) -> io::Result<File> {
+    openat(dir, name, libc::O_PATH, 0)
+}
+
+fn stat_at(dir: &File, name: &CStr, flags: i32) -> io::Result<libc::stat> {
+    let mut st = MaybeUninit::<libc::stat>::zeroed();
+    // SAFETY: FFI call with valid arguments, the kernel fills the structure.
+    cvt(unsafe { libc::fstatat(dir.as_raw_fd(), name.as_ptr(), st.as_mut_ptr(), flags) })?;
+    // SAFETY: the structure has been initialized by a successful fstatat.
+    Ok(unsafe { st.assume_init() })
+}
+
+fn stat(file: &File) -> io::Result<libc::stat> {
+    stat_at(file, c"", libc::AT_EMPTY_PATH | libc::AT_SYMLINK_NOFOLLOW)
+}
+
+fn read_dir(dir: &File) -> io::Result<Vec<DirEntry>> {
+    // SAFETY: FFI call, fdopendir takes ownership of the duplicated fd.
+    let fd = cvt(unsafe { libc::fcntl(dir.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) })?;
+    // SAFETY: fd is a valid directory file descriptor.
+    let dirp = unsafe { libc::fdopendir(fd) };
+    if dirp.is_null() {
+        let e = io::Error::last_os_error();
+        // SAFETY: fd is owned by us since fdopendir failed.
+        unsafe { libc::close(fd) };
+        return Err(e);
+    }
+    // SAFETY: dirp is a valid directory stream.
+    unsafe { libc::rewinddir(dirp) };
+
+    let mut entries = Vec::new();
+    loop {
+        // SAFETY: dirp is a valid directory stream.
+        let entry = unsafe { libc::readdir(dirp) };
+        if entry.is_null() {
+            break;
+        }
+        // SAFETY: readdir returned a valid entry, valid until the next call.
+        let entry = unsafe { &*entry };
+        // SAFETY: d_name is NUL terminated.
+        let name = unsafe { CStr::from_ptr(entry.d_name.as_ptr()) };
+        let qid_type = match entry.d_type {
+            libc::DT_DIR => P9_QTDIR,
+            libc::DT_LNK => P9_QTSYMLINK,
+            _ => P9_QTFILE,
+        };
+        entries.push(DirEntry {
+            qid: Qid {
+                type_: qid_type,
+                version: 0,
+                path: entry.d_ino,
+            },
+            type_: entry.d_type,
+            name: name.to_bytes().to_vec(),
+        });
+    }
+    // SAFETY: dirp is a valid directory stream, closing it closes fd.
+    unsafe { libc::closedir(dirp) };
+
+    Ok(entries)
+}
+
+fn utime(set: bool, set_explicit: bool, sec: u64, nsec: u64) -> libc::timespec {
+    libc::timespec {
+        tv_sec: sec as libc::time_t,
+        tv_nsec: if !set {
+            libc::UTIME_OMIT
+        } else if !set_explicit {
+            libc::UTIME_NOW
+        } else {
+            nsec as libc::c_long
+        },
+    }
+}
+
+fn read_header(r: &mut WireReader) -> io::Result<(u8, u16)> {
+    let _size = r.u32()?;
+    Ok((r.u8()?, r.u16()?))
+}
+
+fn error_reply(tag: u16, e: io::Error) -> Vec<u8> {
+    let mut reply = WireWriter::new(P9_RLERROR, tag);
+    reply.u32(e.raw_os_error().unwrap_or(libc::EIO) as u32);
+    reply.finish()
+}
+
+pub struct Server {
+    root: File,
+    readonly: bool,
+    msize: u32,
+    fids: HashMap<u32, Fid>,
+}
+
+impl Server {
+    pub fn new(path: &Path, readonly: bool) -> io::Result<Self> {
+        let path = cstring(path.as_os_str().as_bytes())?;
+        // SAFETY: FFI call with a NUL terminated path.
+        let fd = cvt(unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        })?;
+
+        Ok(Server {
+            // SAFETY: fd is a valid file descriptor we own.
+            root: unsafe { File::from_raw_fd(fd) },
+            readonly,
+            msize: MAX_MSIZE,
+            fids: HashMap::new(),
+        })
+    }
+
+    /// Handle a request, returning the reply to send back to the guest, which
+    /// can't be larger than `max_reply`.
+    pub fn handle(&mut self, request: &[u8], max_reply: usize) -> Vec<u8> {
+        let mut reader = WireReader::new(request);
+        let Ok((type_, tag)) = read_header(&mut reader) else {
+            return Vec::new();
+        };
+
+        let max_reply = max_reply.min(self.msize as usize);
+        let mut reply = WireWriter::new(type_.wrapping_add(1), tag);
+        match self.handle_request(type_, &mut reader, &mut reply, max_reply) {
+            Ok(()) => reply.finish(),
+            Err(e) => error_reply(tag, e),
+        }
+    }
+
+    /// Reply to a request which can't be handled with the error `e`. Only the
+    /// header of the request is needed.
+    pub fn reject(request: &[u8], e: io::Error) -> Vec<u8> {
+        match read_header(&mut WireReader::new(request)) {
+            Ok((_, tag)) => error_reply(tag, e),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Whether the guest holds fids, which only live in this server.
+    pub fn has_fids(&self) -> bool {
+        !self.fids.is_empty()
+    }
+
+    fn handle_request(
+        &mut self,
+        type_: u8,
+        r: &mut WireReader,
+        w: &mut WireWriter,
+        max_reply: usize,
+    ) -> io::Result<()> {
+        let modifies = matches!(
+            type_,
+            P9_TLCREATE
+                | P9_TSYMLINK
+                | P9_TMKNOD
+                | P9_TRENAME
+                | P9_TSETATTR
+                | P9_TXATTRCREATE
+                | P9_TLINK
+                | P9_TMKDIR
+                | P9_TRENAMEAT
+                | P9_TUNLINKAT
+                | P9_TWRITE
+                | P9_TREMOVE
+        );
+        if modifies && self.readonly {
+            // Tremove clunks the fid even when failing.
+            if type_ == P9_TREMOVE {
+                self.fids.remove(&r.u32()?);
+            }
+            return Err(errno(libc::EROFS));
+        }
+
+        match type_ {
+            P9_TVERSION => self.version(r, w),
+            P9_TAUTH => Err(errno(libc::EOPNOTSUPP)),
+            P9_TATTACH => self.attach(r, w),
+            P9_TFLUSH => Ok(()),
+            P9_TWALK => self.walk(r, w),
+            P9_TLOPEN => self.lopen(r, w),
+            P9_TLCREATE => self.lcreate(r, w),
+            P9_TSYMLINK => self.symlink(r, w),
+            P9_TMKNOD => self.mknod(r, w),
+            P9_TRENAME => self.rename(r),
+            P9_TREADLINK => self.readlink(r, w),
+            P9_TGETATTR => self.getattr(r, w),
+            P9_TSETATTR => self.setattr(r),
+            P9_TXATTRWALK | P9_TXATTRCREATE => Err(errno(libc::EOPNOTSUPP)),
+            P9_TREADDIR => self.readdir(r, w, max_reply),
+            P9_TFSYNC => self.fsync(r),
+            P9_TLOCK => self.lock(r, w),
+            P9_TGETLOCK => self.getlock(r, w),
+            P9_TLINK => self.link(r),
+            P9_TMKDIR => self.mkdir(r, w),
+            P9_TRENAMEAT => self.renameat(r),
+            P9_TUNLINKAT => self.unlinkat(r),
+            P9_TREAD => self.read(r, w, max_reply),
+            P9_TWRITE => self.write(r, w),
+            P9_TCLUNK => self.clunk(r),
+            P9_TREMOVE => self.remove(r),
+            P9_TSTATFS => self.statfs(r, w),
+            _ => {
+                warn!("Unsupported 9p request type {}", type_);
+                Err(errno(libc::EOPNOTSUPP))
+            }
+        }
+    }
+
+    fn fid(&self, fid: u32) -> io::Result<&Fid> {
+        self.fids.get(&fid).ok_or_else(|| errno(libc::EBADF))
+    }
+
+    fn fid_mut(&mut self, fid: u32) -> io::Result<&mut Fid> {
+        self.fids.get_mut(&fid).ok_or_else(|| errno(libc::EBADF))
+    }
+
+    // Resolve a path relative to the shared directory, one component at a
+    // time so that symbolic links are never followed.
+    fn lookup(&self, path: &Path) -> io::Result<File> {
+        let mut file = open_path(&self.root, c".")?;
+        for component in path.iter() {
+            file = open_path(&file, &cstring(component.as_bytes())?)?;
+        }
+        Ok(file)
+    }
+
+    // Return the directory containing a fid and its name in this directory,
+    // which is needed by the *at() system calls.
+    fn parent(&self, fid: &Fid) -> io::Result<(File, CString)> {
+        match (fid.path.parent(), fid.path.file_name()) {
+            (Some(parent), Some(name)) => Ok((self.lookup(parent)?, cstring(name.as_bytes())?)),
+            _ => Ok((open_path(&self.root, c".")?, c".".to_owned())),
+        }
+    }
+
+    fn new_fid(&mut self, fid: u32, path: PathBuf, file: File) -> io::Result<Qid> {
+        let qid = Qid::from_stat(&stat(&file)?);
+        self.fids.insert(
+            fid,
+            Fid {
+                path,
+                file,
+                qid,
+                open: None,
+                entries: None,
+            },
+        );
+        Ok(qid)
+    }
+
+    // Keep the paths of the fids in sync after a rename.
+    fn renamed(&mut self, old: &Path, new: &Path) {
+        for fid in self.fids.values_mut() {
+            if let Ok(suffix) = fid.path.strip_prefix(old) {
+                fid.path = new.join(suffix);
+            }
+        }
+    }
+
+    fn version(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let msize = r.u32()?;
+        let version = r.string()?;
+
+        // A new session starts, aborting everything from the previous one.
+        self.fids.clear();
+        self.msize = msize.min(MAX_MSIZE);
+
+        w.u32(self.msize);
+        if version == P9_VERSION {
+            w.string(P9_VERSION);
+        } else {
+            w.string("unknown");
+        }
+        Ok(())
+    }
+
+    fn attach(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        let _afid = r.u32()?;
+        let _uname = r.string()?;
+        let _aname = r.string()?;
+        let _n_uname = r.u32()?;
+
+        if self.fids.contains_key(&fid) {
+            return Err(errno(libc::EBADF));
+        }
+
+        let file = open_path(&self.root, c".")?;
+        let qid = self.new_fid(fid, PathBuf::new(), file)?;
+        w.qid(&qid);
+        Ok(())
+    }
+
+    fn walk(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        let newfid = r.u32()?;
+        let nwname = r.u16()? as usize;
+        if nwname > P9_MAXWELEM {
+            return Err(errno(libc::EINVAL));
+        }
+        let mut names = Vec::with_capacity(nwname);
+        for _ in 0..nwname {
+            names.push(r.string()?);
+        }
+
+        if newfid != fid && self.fids.contains_key(&newfid) {
+            return Err(errno(libc::EBADF));
+        }
+
+        let from = self.fid(fid)?;
+        let mut path = from.path.clone();
+        let mut file = from.file.try_clone()?;
+        let mut qids = Vec::with_capacity(nwname);
+        for name in names.iter() {
+            let next = if name == ".." {
+                // Never go above the shared directory.
+                let mut parent = path.clone();
+                parent.pop();
+                self.lookup(&parent).map(|file| (parent, file))
+            } else {
+                check_name(name)
+                    .and_then(|cname| open_path(&file, &cname))
+                    .map(|file| (path.join(name), file))
+            };
+            let (next_path, next_file) = match next {
+                Ok(next) => next,
+                // The error is only reported if the first element can't be
+                // walked, otherwise the walked elements are returned.
+                Err(e) if qids.is_empty() => return Err(e),
+                Err(_) => break,
+            };
+            qids.push(Qid::from_stat(&stat(&next_file)?));
+            path = next_path;
+            file = next_file;
+        }
+
+        if qids.len() == nwname {
+            self.new_fid(newfid, path, file)?;
+        }
+
+        w.u16(qids.len() as u16);
+        for qid in qids.iter() {
+            w.qid(qid);
+        }
+        Ok(())
+    }
+
+    fn open_flags(&self, flags: u32) -> io::Result<i32> {
+        let mut oflags = match flags & P9_DOTL_ACCMODE {
+            0 => libc::O_RDONLY,
+            1 => libc::O_WRONLY,
+            2 => libc::O_RDWR,
+            _ => return Err(errno(libc::EINVAL)),
+        };
+        if flags & P9_DOTL_TRUNC != 0 {
+            oflags |= libc::O_TRUNC;
+        }
+        if flags & P9_DOTL_APPEND != 0 {
+            oflags |= libc::O_APPEND;
+        }
+
+        if self.readonly
+            && (oflags & libc::O_ACCMODE != libc::O_RDONLY || oflags & libc::O_TRUNC != 0)
+        {
+            return Err(errno(libc::EROFS));
+        }
+
+        Ok(oflags)
+    }
+
+    fn lopen(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        let flags = r.u32()?;
+
+        let mut oflags = self.open_flags(flags)?;
+        let f = self.fid(fid)?;
+        if f.open.is_some() {
+            return Err(errno(libc::EBADF));
+        }
+        if f.qid.type_ == P9_QTDIR {
+            oflags = libc::O_RDONLY | libc::O_DIRECTORY;
+        }
+        let (parent, name) = self.parent(f)?;
+        let file = openat(&parent, &name, oflags, 0)?;
+
+        let f = self.fid_mut(fid)?;
+        f.open = Some(file);
+        f.entries = None;
+        w.qid(&f.qid);
+        w.u32(0);
+        Ok(())
+    }
+
+    fn lcreate(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        let name = r.string()?;
+        let flags = r.u32()?;
+        let mode = r.u32()?;
+        let _gid = r.u32()?;
+
+        let cname = check_name(&name)?;
+        let mut oflags = self.open_flags(flags)? | libc::O_CREAT;
+        if flags & P9_DOTL_EXCL != 0 {
+            oflags |= libc::O_EXCL;
+        }
+        let dir = self.fid(fid)?;
+        let file = openat(&dir.file, &cname, oflags, mode & 0o7777)?;
+        let path = dir.path.join(&name);
+        let handle = open_path(&dir.file, &cname)?;
+
+        // The fid now designates the newly created file.
+        let qid = self.new_fid(fid, path, handle)?;
+        self.fid_mut(fid)?.open = Some(file);
+        w.qid(&qid);
+        w.u32(0);
+        Ok(())
+    }
+
+    fn symlink(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        let name = check_name(&r.string()?)?;
+        let target = cstring(r.string()?.as_bytes())?;
+        let _gid = r.u32()?;
+
+        let dir = self.fid(fid)?;
+        // SAFETY: FFI call with valid arguments.
+        cvt(unsafe { libc::symlinkat(target.as_ptr(), dir.file.as_raw_fd(), name.as_ptr()) })?;
+        w.qid(&Qid::from_stat(&stat_at(
+            &dir.file,
+            &name,
+            libc::AT_SYMLINK_NOFOLLOW,
+        )?));
+        Ok(())
+    }
+
+    fn mknod(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        let name = check_name(&r.string()?)?;
+        let mode = r.u32()?;
+        let _major = r.u32()?;
+        let _minor = r.u32()?;
+        let _gid = r.u32()?;
+
+        // Device nodes would give the guest access to host devices.
+        let file_type = mode & libc::S_IFMT;
+        if file_type != libc::S_IFIFO && file_type != libc::S_IFSOCK && file_type != libc::S_IFREG {
+            return Err(errno(libc::EPERM));
+        }
+
+        let dir = self.fid(fid)?;
+        // SAFETY: FFI call with valid arguments.
+        cvt(unsafe {
+            libc::mknodat(dir.file.as_raw_fd(), name.as_ptr(), mode as libc::mode_t, 0)
+        })?;
+        w.qid(&Qid::from_stat(&stat_at(
+            &dir.file,
+            &name,
+            libc::AT_SYMLINK_NOFOLLOW,
+        )?));
+        Ok(())
+    }
+
+    fn do_rename(
+        &mut self,
+        old_dir: &File,
+        old_path: PathBuf,
+        new_dir: &File,
+        new_path: PathBuf,
+    ) -> io::Result<()> {
+        let old_name = cstring(old_path.file_name().unwrap().as_bytes())?;
+        let new_name = cstring(new_path.file_name().unwrap().as_bytes())?;
+        // SAFETY: FFI call with valid arguments.
+        cvt(unsafe {
+            libc::renameat(
+                old_dir.as_raw_fd(),
+                old_name.as_ptr(),
+                new_dir.as_raw_fd(),
+                new_name.as_ptr(),
+            )
+        })?;
+        self.renamed(&old_path, &new_path);
+        Ok(())
+    }
+
+    fn rename(&mut self, r: &mut WireReader) -> io::Result<()> {
+        let fid = r.u32()?;
+        let dfid = r.u32()?;
+        let name = r.string()?;
+
+        check_name(&name)?;
+        let f = self.fid(fid)?;
+        if f.path.as_os_str().is_empty() {
+            return Err(errno(libc::EBUSY));
+        }
+        let old_path = f.path.clone();
+        let (old_dir, _) = self.parent(f)?;
+        let dir = self.fid(dfid)?;
+        let new_dir = dir.file.try_clone()?;
+        let new_path = dir.path.join(&name);
+        self.do_rename(&old_dir, old_path, &new_dir, new_path)
+    }
+
+    fn renameat(&mut self, r: &mut WireReader) -> io::Result<()> {
+        let old_dirfid = r.u32()?;
+        let old_name = r.string()?;
+        let new_dirfid = r.u32()?;
+        let new_name = r.string()?;
+
+        check_name(&old_name)?;
+        check_name(&new_name)?;
+        let old_dir = self.fid(old_dirfid)?;
+        let old_path = old_dir.path.join(&old_name);
+        let old_dir = old_dir.file.try_clone()?;
+        let new_dir = self.fid(new_dirfid)?;
+        let new_path = new_dir.path.join(&new_name);
+        let new_dir = new_dir.file.try_clone()?;
+        self.do_rename(&old_dir, old_path, &new_dir, new_path)
+    }
+
+    fn readlink(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+
+        let f = self.fid(fid)?;
+        let mut buf = vec![0u8; libc::PATH_MAX as usize];
+        // SAFETY: FFI call with a buffer of the given size.
+        let len = unsafe {
+            libc::readlinkat(
+                f.file.as_raw_fd(),
+                c"".as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.len(),
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(len as usize);
+        let target = String::from_utf8(buf).map_err(|_| errno(libc::EINVAL))?;
+        w.string(&target);
+        Ok(())
+    }
+
+    fn getattr(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        let _request_mask = r.u64()?;
+
+        let st = stat(&self.fid(fid)?.file)?;
+        w.u64(P9_GETATTR_BASIC);
+        w.qid(&Qid::from_stat(&st));
+        w.u32(st.st_mode);
+        w.u32(st.st_uid);
+        w.u32(st.st_gid);
+        w.u64(st.st_nlink as u64);
+        w.u64(st.st_rdev);
+        w.u64(st.st_size as u64);
+        w.u64(st.st_blksize as u64);
+        w.u64(st.st_blocks as u64);
+        w.u64(st.st_atime as u64);
+        w.u64(st.st_atime_nsec as u64);
+        w.u64(st.st_mtime as u64);
+        w.u64(st.st_mtime_nsec as u64);
+        w.u64(st.st_ctime as u64);
+        w.u64(st.st_ctime_nsec as u64);
+        // Birth time, generation and data version aren't reported.
+        for _ in 0..4 {
+            w.u64(0);
+        }
+        Ok(())
+    }
+
+    fn setattr(&mut self, r: &mut WireReader) -> io::Result<()> {
+        let fid = r.u32()?;
+        let valid = r.u32()?;
+        let mode = r.u32()?;
+        let uid = r.u32()?;
+        let gid = r.u32()?;
+        let size = r.u64()?;
+        let atime_sec = r.u64()?;
+        let atime_nsec = r.u64()?;
+        let mtime_sec = r.u64()?;
+        let mtime_nsec = r.u64()?;
+
+        let f = self.fid(fid)?;
+        let (dir, name) = self.parent(f)?;
+
+        if valid & P9_SETATTR_MODE != 0 {
+            // Symbolic links have no mode of their own, changing it would
+            // change the mode of their target instead.
+            if f.qid.type_ == P9_QTSYMLINK {
+                return Err(errno(libc::EOPNOTSUPP));
+            }
+            // fchmodat() can't be told not to follow symbolic links, so the
+            // mode is changed through the handle of the fid rather than its
+            // name, which could have been replaced by a symbolic link.
+            let path = cstring(format!("/proc/self/fd/{}", f.file.as_raw_fd()).as_bytes())?;
+            // SAFETY: FFI call with valid arguments.
+            cvt(unsafe {
+                libc::fchmodat(
+                    libc::AT_FDCWD,
+                    path.as_ptr(),
+                    (mode & 0o7777) as libc::mode_t,
+                    0,
+                )
+            })?;
+        }
+
+        if valid & (P9_SETATTR_UID | P9_SETATTR_GID) != 0 {
+            let uid = if valid & P9_SETATTR_UID != 0 {
+                uid
+            } else {
+                u32::MAX
+            };
+            let gid = if valid & P9_SETATTR_GID != 0 {
+                gid
+            } else {
+                u32::MAX
+            };
+            // SAFETY: FFI call with valid arguments.
+            cvt(unsafe {
+                libc::fchownat(
+                    dir.as_raw_fd(),
+                    name.as_ptr(),
+                    uid,
+                    gid,
+                    libc::AT_SYMLINK_NOFOLLOW,
+                )
+            })?;
+        }
+
+        if valid & P9_SETATTR_SIZE != 0 {
+            let file = openat(&dir, &name, libc::O_WRONLY, 0)?;
+            file.set_len(size)?;
+        }
+
+        if valid & (P9_SETATTR_ATIME | P9_SETATTR_MTIME) != 0 {
+            let times = [
+                utime(
+                    valid & P9_SETATTR_ATIME != 0,
+                    valid & P9_SETATTR_ATIME_SET != 0,
+                    atime_sec,
+                    atime_nsec,
+                ),
+                utime(
+                    valid & P9_SETATTR_MTIME != 0,
+                    valid & P9_SETATTR_MTIME_SET != 0,
+                    mtime_sec,
+                    mtime_nsec,
+                ),
+            ];
+            // SAFETY: FFI call with valid arguments.
+            cvt(unsafe {
+                libc::utimensat(
+                    dir.as_raw_fd(),
+                    name.as_ptr(),
+                    times.as_ptr(),
+                    libc::AT_SYMLINK_NOFOLLOW,
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn readdir(
+        &mut self,
+        r: &mut WireReader,
+        w: &mut WireWriter,
+        max_reply: usize,
+    ) -> io::Result<()> {
+        let fid = r.u32()?;
+        let offset = r.u64()?;
+        let count = r.u32()? as usize;
+
+        let f = self.fid_mut(fid)?;
+        let dir = f.open.as_ref().ok_or_else(|| errno(libc::EBADF))?;
+        // Read the directory again when the guest rewinds it.
+        if offset == 0 || f.entries.is_none() {
+            f.entries = Some(read_dir(dir)?);
+        }
+        let entries = f.entries.as_ref().unwrap();
+
+        let max = count.min(max_reply.saturating_sub(IO_HEADER_SIZE));
+        let mut data = WireWriter::default();
+        for (index, entry) in entries.iter().enumerate().skip(offset as usize) {
+            let size = QID_SIZE + 8 + 1 + 2 + entry.name.len();
+            if data.size() + size > max {
+                break;
+            }
+            data.qid(&entry.qid);
+            // The offset of the next entry.
+            data.u64(index as u64 + 1);
+            data.u8(entry.type_);
+            data.u16(entry.name.len() as u16);
+            data.bytes(&entry.name);
+        }
+
+        let data = data.into_bytes();
+        w.u32(data.len() as u32);
+        w.bytes(&data);
+        Ok(())
+    }
+
+    fn fsync(&mut self, r: &mut WireReader) -> io::Result<()> {
+        let fid = r.u32()?;
+        let datasync = r.u32()?;
+
+        let file = self
+            .fid(fid)?
+            .open
+            .as_ref()
+            .ok_or_else(|| errno(libc::EBADF))?;
+        if datasync != 0 {
+            file.sync_data()
+        } else {
+            file.sync_all()
+        }
+    }
+
+    // Locks are only advisory, and the shared directory isn't expected to be
+    // accessed concurrently, so they always succeed.
+    fn lock(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        self.fid(fid)?;
+        w.u8(P9_LOCK_SUCCESS);
+        Ok(())
+    }
+
+    fn getlock(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        let _type = r.u8()?;
+        let start = r.u64()?;
+        let length = r.u64()?;
+        let proc_id = r.u32()?;
+        let client_id = r.string()?;
+
+        self.fid(fid)?;
+        w.u8(P9_LOCK_TYPE_UNLCK);
+        w.u64(start);
+        w.u64(length);
+        w.u32(proc_id);
+        w.string(&client_id);
+        Ok(())
+    }
+
+    fn link(&mut self, r: &mut WireReader) -> io::Result<()> {
+        let dfid = r.u32()?;
+        let fid = r.u32()?;
+        let name = check_name(&r.string()?)?;
+
+        let f = self.fid(fid)?;
+        if f.qid.type_ == P9_QTDIR {
+            return Err(errno(libc::EPERM));
+        }
+        let (old_dir, old_name) = self.parent(f)?;
+        let dir = self.fid(dfid)?;
+        // SAFETY: FFI call with valid arguments.
+        cvt(unsafe {
+            libc::linkat(
+                old_dir.as_raw_fd(),
+                old_name.as_ptr(),
+                dir.file.as_raw_fd(),
+                name.as_ptr(),
+                0,
+            )
+        })?;
+        Ok(())
+    }
+
+    fn mkdir(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let dfid = r.u32()?;
+        let name = check_name(&r.string()?)?;
+        let mode = r.u32()?;
+        let _gid = r.u32()?;
+
+        let dir = self.fid(dfid)?;
+        // SAFETY: FFI call with valid arguments.
+        cvt(unsafe {
+            libc::mkdirat(
+                dir.file.as_raw_fd(),
+                name.as_ptr(),
+                (mode & 0o7777) as libc::mode_t,
+            )
+        })?;
+        w.qid(&Qid::from_stat(&stat_at(
+            &dir.file,
+            &name,
+            libc::AT_SYMLINK_NOFOLLOW,
+        )?));
+        Ok(())
+    }
+
+    fn unlink(dir: RawFd, name: &CStr, is_dir: bool) -> io::Result<()> {
+        let flags = if is_dir { libc::AT_REMOVEDIR } else { 0 };
+        // SAFETY: FFI call with valid arguments.
+        cvt(unsafe { libc::unlinkat(dir, name.as_ptr(), flags) })?;
+        Ok(())
+    }
+
+    fn unlinkat(&mut self, r: &mut WireReader) -> io::Result<()> {
+        let dirfid = r.u32()?;
+        let name = check_name(&r.string()?)?;
+        let flags = r.u32()?;
+
+        let dir = self.fid(dirfid)?;
+        Self::unlink(
+            dir.file.as_raw_fd(),
+            &name,
+            flags & P9_DOTL_AT_REMOVEDIR != 0,
+        )
+    }
+
+    fn read(&mut self, r: &mut WireReader, w: &mut WireWriter, max_reply: usize) -> io::Result<()> {
+        let fid = r.u32()?;
+        let offset = r.u64()?;
+        let count = r.u32()? as usize;
+
+        let file = self
+            .fid(fid)?
+            .open
+            .as_ref()
+            .ok_or_else(|| errno(libc::EBADF))?;
+        let mut buf = vec![0u8; count.min(max_reply.saturating_sub(IO_HEADER_SIZE))];
+        let len = file.read_at(&mut buf, offset)?;
+        w.u32(len as u32);
+        w.bytes(&buf[..len]);
+        Ok(())
+    }
+
+    fn write(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+        let offset = r.u64()?;
+        let count = r.u32()? as usize;
+        let data = r.bytes(count)?;
+
+        let file = self
+            .fid(fid)?
+            .open
+            .as_ref()
+            .ok_or_else(|| errno(libc::EBADF))?;
+        let len = file.write_at(data, offset)?;
+        w.u32(len as u32);
+        Ok(())
+    }
+
+    fn clunk(&mut self, r: &mut WireReader) -> io::Result<()> {
+        let fid = r.u32()?;
+        self.fids
+            .remove(&fid)
+            .map(|_| ())
+            .ok_or_else(|| errno(libc::EBADF))
+    }
+
+    fn remove(&mut self, r: &mut WireReader) -> io::Result<()> {
+        let fid = r.u32()?;
+
+        // The fid is clunked, whether the removal succeeds or not.
+        let f = self.fids.remove(&fid).ok_or_else(|| errno(libc::EBADF))?;
+        if f.path.as_os_str().is_empty() {
+            return Err(errno(libc::EBUSY));
+        }
+        let (dir, name) = self.parent(&f)?;
+        Self::unlink(dir.as_raw_fd(), &name, f.qid.type_ == P9_QTDIR)
+    }
+
+    fn statfs(&mut self, r: &mut WireReader, w: &mut WireWriter) -> io::Result<()> {
+        let fid = r.u32()?;
+
+        let f = self.fid(fid)?;
+        let mut st = MaybeUninit::<libc::statfs>::zeroed();
+        // SAFETY: FFI call with valid arguments, the kernel fills the structure.
+        cvt(unsafe { libc::fstatfs(f.file.as_raw_fd(), st.as_mut_ptr()) })?;
+        // SAFETY: the structure has been initialized by a successful fstatfs.
+        let st = unsafe { st.assume_init() };
+        // SAFETY: fsid is a plain pair of integers.
+        let fsid = unsafe { std::mem::transmute::<libc::fsid_t, [u32; 2]>(st.f_fsid) };
+
+        w.u32(st.f_type as u32);
+        w.u32(st.f_bsize as u32);
+        w.u64(st.f_blocks);
+        w.u64(st.f_bfree);
+        w.u64(st.f_bavail);
+        w.u64(st.f_files);
+        w.u64(st.f_ffree);
+        w.u64(fsid[0] as u64 | (fsid[1] as u64) << 32);
+        w.u32(st.f_namelen as u32);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    const ROOT_FID: u32 = 0;
+
+    fn create_server(dir: &TempDir, readonly: bool) -> Server {
+        let mut server = Server::new(dir.as_path(), readonly).unwrap();
+        call(&mut server, P9_TVERSION, |w| {
+            w.u32(MAX_MSIZE);
+            w.string(P9_VERSION);
+        })
+        .unwrap();
+        call(&mut server, P9_TATTACH, |w| {
+            w.u32(ROOT_FID);
+            w.u32(u32::MAX);
+            w.string("root");
+            w.string("");
+            w.u32(0);
+        })
+        .unwrap();
+        server
+    }
+
+    // Send a request, returning the fields of the reply or the error code.
+    fn call(
+        server: &mut Server,
+        type_: u8,
+        f: impl FnOnce(&mut WireWriter),
+    ) -> Result<Vec<u8>, i32> {
+        let mut request = WireWriter::new(type_, 1);
+        f(&mut request);
+        let reply = server.handle(&request.finish(), MAX_MSIZE as usize);
+
+        let mut r = WireReader::new(&reply);
+        assert_eq!(r.u32().unwrap() as usize, reply.len());
+        let reply_type = r.u8().unwrap();
+        assert_eq!(r.u16().unwrap(), 1);
+        let fields = reply[HEADER_SIZE..].to_vec();
+        if reply_type == P9_RLERROR {
+            Err(WireReader::new(&fields).u32().unwrap() as i32)
+        } else {
+            assert_eq!(reply_type, type_ + 1);
+            Ok(fields)
+        }
+    }
+
+    fn read_qid(r: &mut WireReader) -> Qid {
+        Qid {
+            type_: r.u8().unwrap(),
+            version: r.u32().unwrap(),
+            path: r.u64().unwrap(),
+        }
+    }
+
+    fn walk(server: &mut Server, fid: u32, newfid: u32, names: &[&str]) -> Result<Vec<Qid>, i32> {
+        let reply = call(server, P9_TWALK, |w| {
+            w.u32(fid);
+            w.u32(newfid);
+            w.u16(names.len() as u16);
+            for name in names {
+                w.string(name);
+            }
+        })?;
+        let mut r = WireReader::new(&reply);
+        let nwqid = r.u16().unwrap();
+        Ok((0..nwqid).map(|_| read_qid(&mut r)).collect())
+    }
+
+    fn lopen(server: &mut Server, fid: u32, flags: u32) -> Result<Qid, i32> {
+        let reply = call(server, P9_TLOPEN, |w| {
+            w.u32(fid);
+            w.u32(flags);
+        })?;
+        Ok(read_qid(&mut WireReader::new(&reply)))
+    }
+
+    fn lcreate(server: &mut Server, fid: u32, name: &str, flags: u32) -> Result<Qid, i32> {
+        let reply = call(server, P9_TLCREATE, |w| {
+            w.u32(fid);
+            w.string(name);
+            w.u32(flags);
+            w.u32(0o644);
+            w.u32(0);
+        })?;
+        Ok(read_qid(&mut WireReader::new(&reply)))
+    }
+
+    fn read(server: &mut Server, fid: u32, offset: u64, count: u32) -> Result<Vec<u8>, i32> {
+        let reply = call(server, P9_TREAD, |w| {
+            w.u32(fid);
+            w.u64(offset);
+            w.u32(count);
+        })?;
+        let mut r = WireReader::new(&reply);
+        let len = r.u32().unwrap() as usize;
+        Ok(r.bytes(len).unwrap().to_vec())
+    }
+
+    fn write(server: &mut Server, fid: u32, offset: u64, data: &[u8]) -> Result<u32, i32> {
+        let reply = call(server, P9_TWRITE, |w| {
+            w.u32(fid);
+            w.u64(offset);
+            w.u32(data.len() as u32);
+            w.bytes(data);
+        })?;
+        Ok(WireReader::new(&reply).u32().unwrap())
+    }
+
+    fn setattr(server: &mut Server, fid: u32, valid: u32, mode: u32, size: u64) -> Result<(), i32> {
+        call(server, P9_TSETATTR, |w| {
+            w.u32(fid);
+            w.u32(valid);
+            w.u32(mode);
+            w.u32(0);
+            w.u32(0);
+            w.u64(size);
+            for _ in 0..4 {
+                w.u64(0);
+            }
+        })
+        .map(|_| ())
+    }
+
+    // Return the names of the entries read starting at the given offset,
+    // along with the offset of the next entry.
+    fn readdir(server: &mut Server, fid: u32, offset: u64, count: u32) -> (Vec<String>, u64) {
+        let reply = call(server, P9_TREADDIR, |w| {
+            w.u32(fid);
+            w.u64(offset);
+            w.u32(count);
+        })
+        .unwrap();
+        let mut r = WireReader::new(&reply);
+        let len = r.u32().unwrap() as usize;
+        let mut r = WireReader::new(r.bytes(len).unwrap());
+        let mut names = Vec::new();
+        let mut next = offset;
+        while let Ok(type_) = r.u8() {
+            r.u32().unwrap();
+            r.u64().unwrap();
+            next = r.u64().unwrap();
+            r.u8().unwrap();
+            names.push(r.string().unwrap());
+            assert!(type_ == P9_QTDIR || type_ == P9_QTFILE || type_ == P9_QTSYMLINK);
+        }
+        (names, next)
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::symlink_metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn test_has_fids() {
+        let dir = TempDir::new().unwrap();
+        assert!(!Server::new(dir.as_path(), false).unwrap().has_fids());
+
+        let mut server = create_server(&dir, false);
+        assert!(server.has_fids());
+        call(&mut server, P9_TCLUNK, |w| w.u32(ROOT_FID)).unwrap();
+        assert!(!server.has_fids());
+
+        // Negotiating the version again drops every fid.
+        let mut server = create_server(&dir, false);
+        walk(&mut server, ROOT_FID, 1, &[]).unwrap();
+        call(&mut server, P9_TVERSION, |w| {
+            w.u32(MAX_MSIZE);
+            w.string(P9_VERSION);
+        })
+        .unwrap();
+        assert!(!server.has_fids());
+    }
+
+    #[test]
+    fn test_reject() {
+        let mut request = WireWriter::new(P9_TWRITE, 7);
+        request.u32(ROOT_FID);
+        let reply = Server::reject(&request.finish(), errno(libc::EMSGSIZE));
+
+        let mut r = WireReader::new(&reply);
+        assert_eq!(r.u32().unwrap() as usize, reply.len());
+        assert_eq!(r.u8().unwrap(), P9_RLERROR);
+        assert_eq!(r.u16().unwrap(), 7);
+        assert_eq!(r.u32().unwrap(), libc::EMSGSIZE as u32);
+
+        // Nothing can be answered without a tag.
+        assert!(Server::reject(&[0; 4], errno(libc::EMSGSIZE)).is_empty());
+    }
+
+    #[test]
+    fn test_walk() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.as_path().join("a/b")).unwrap();
+        fs::write(dir.as_path().join("a/b/file"), b"data").unwrap();
+        let mut server = create_server(&dir, false);
+
+        let qids = walk(&mut server, ROOT_FID, 1, &["a", "b", "file"]).unwrap();
+        assert_eq!(qids.len(), 3);
+        assert_eq!(qids[0].type_, P9_QTDIR);
+        assert_eq!(qids[1].type_, P9_QTDIR);
+        assert_eq!(qids[2].type_, P9_QTFILE);
+        assert_eq!(server.fid(1).unwrap().path, Path::new("a/b/file"));
+
+        // The fid can't be reused until it is clunked.
+        assert_eq!(walk(&mut server, ROOT_FID, 1, &[]), Err(libc::EBADF));
+
+        // Walking above the shared directory stays in the shared directory.
+        let root = walk(&mut server, ROOT_FID, 2, &[]).unwrap();
+        assert!(root.is_empty());
+        let qids = walk(&mut server, ROOT_FID, 3, &["..", ".."]).unwrap();
+        assert_eq!(qids[1], server.fid(ROOT_FID).unwrap().qid);
+        assert_eq!(server.fid(3).unwrap().path, Path::new(""));
+
+        // Names with a separator are refused.
+        assert_eq!(walk(&mut server, ROOT_FID, 4, &["a/b"]), Err(libc::EINVAL));
+
+        // Only the first failure is reported, otherwise the walked elements
+        // are returned and no fid is created.
+        assert_eq!(walk(&mut server, ROOT_FID, 4, &["x"]), Err(libc::ENOENT));
+        let qids = walk(&mut server, ROOT_FID, 4, &["a", "x"]).unwrap();
+        assert_eq!(qids.len(), 1);
+        assert!(server.fid(4).is_err());
+    }
+
+    #[test]
+    fn test_walk_symlink() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.as_path().join("secret"), b"secret").unwrap();
+        symlink(outside.as_path(), dir.as_path().join("link")).unwrap();
+        let mut server = create_server(&dir, false);
+
+        // The link itself can be walked to, but not through.
+        let qids = walk(&mut server, ROOT_FID, 1, &["link", "secret"]).unwrap();
+        assert_eq!(qids.len(), 1);
+        assert_eq!(qids[0].type_, P9_QTSYMLINK);
+        assert!(server.fid(1).is_err());
+
+        walk(&mut server, ROOT_FID, 1, &["link"]).unwrap();
+        assert_eq!(lopen(&mut server, 1, 0), Err(libc::ELOOP));
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.as_path().join("file"), b"hello world").unwrap();
+        let mut server = create_server(&dir, false);
+
+        walk(&mut server, ROOT_FID, 1, &["file"]).unwrap();
+        assert_eq!(read(&mut server, 1, 0, 5), Err(libc::EBADF));
+        let qid = lopen(&mut server, 1, 0).unwrap();
+        assert_eq!(qid.type_, P9_QTFILE);
+        assert_eq!(read(&mut server, 1, 6, 100).unwrap(), b"world");
+        // The file is opened read only.
+        assert_eq!(write(&mut server, 1, 0, b"bye"), Err(libc::EBADF));
+        // A fid can only be opened once.
+        assert_eq!(lopen(&mut server, 1, 0), Err(libc::EBADF));
+
+        walk(&mut server, ROOT_FID, 2, &["file"]).unwrap();
+        lopen(&mut server, 2, 2 | P9_DOTL_TRUNC).unwrap();
+        assert_eq!(write(&mut server, 2, 0, b"bye").unwrap(), 3);
+        assert_eq!(fs::read(dir.as_path().join("file")).unwrap(), b"bye");
+
+        // Directories are always opened read only.
+        walk(&mut server, ROOT_FID, 3, &[]).unwrap();
+        assert_eq!(lopen(&mut server, 3, 2).unwrap().type_, P9_QTDIR);
+    }
+
+    #[test]
+    fn test_open_readonly() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.as_path().join("file"), b"hello").unwrap();
+        let mut server = create_server(&dir, true);
+
+        walk(&mut server, ROOT_FID, 1, &["file"]).unwrap();
+        assert_eq!(lopen(&mut server, 1, 2), Err(libc::EROFS));
+        assert_eq!(lopen(&mut server, 1, P9_DOTL_TRUNC), Err(libc::EROFS));
+        lopen(&mut server, 1, 0).unwrap();
+        assert_eq!(write(&mut server, 1, 0, b"bye"), Err(libc::EROFS));
+        assert_eq!(fs::read(dir.as_path().join("file")).unwrap(), b"hello");
+
+        walk(&mut server, ROOT_FID, 2, &[]).unwrap();
+        assert_eq!(lcreate(&mut server, 2, "new", 2), Err(libc::EROFS));
+        assert!(!dir.as_path().join("new").exists());
+    }
+
+    #[test]
+    fn test_create() {
+        let dir = TempDir::new().unwrap();
+        let mut server = create_server(&dir, false);
+
+        walk(&mut server, ROOT_FID, 1, &[]).unwrap();
+        let qid = lcreate(&mut server, 1, "new", 2 | P9_DOTL_EXCL).unwrap();
+        assert_eq!(qid.type_, P9_QTFILE);
+        // The fid now designates the created file, opened for writing.
+        assert_eq!(server.fid(1).unwrap().path, Path::new("new"));
+        assert_eq!(write(&mut server, 1, 0, b"data").unwrap(), 4);
+        assert_eq!(read(&mut server, 1, 0, 4).unwrap(), b"data");
+        assert_eq!(mode(&dir.as_path().join("new")) & 0o777, 0o644 & !umask());
+
+        walk(&mut server, ROOT_FID, 2, &[]).unwrap();
+        assert_eq!(
+            lcreate(&mut server, 2, "new", 2 | P9_DOTL_EXCL),
+            Err(libc::EEXIST)
+        );
+        assert_eq!(lcreate(&mut server, 2, "..", 2), Err(libc::EINVAL));
+        assert_eq!(lcreate(&mut server, 2, "a/b", 2), Err(libc::EINVAL));
+
+        // Creating through a symbolic link is refused.
+        let outside = TempDir::new().unwrap();
+        symlink(
+            outside.as_path().join("created"),
+            dir.as_path().join("link"),
+        )
+        .unwrap();
+        assert_eq!(lcreate(&mut server, 2, "link", 2), Err(libc::ELOOP));
+        assert!(!outside.as_path().join("created").exists());
+    }
+
+    fn umask() -> u32 {
+        // SAFETY: FFI calls restoring the umask right away.
+        let mask = unsafe { libc::umask(0) };
+        // SAFETY: see above.
+        unsafe { libc::umask(mask) };
+        mask
+    }
+
+    #[test]
+    fn test_mkdir() {
+        let dir = TempDir::new().unwrap();
+        let mut server = create_server(&dir, false);
+
+        let reply = call(&mut server, P9_TMKDIR, |w| {
+            w.u32(ROOT_FID);
+            w.string("sub");
+            w.u32(0o755);
+            w.u32(0);
+        })
+        .unwrap();
+        let qid = read_qid(&mut WireReader::new(&reply));
+        assert_eq!(qid.type_, P9_QTDIR);
+        assert!(dir.as_path().join("sub").is_dir());
+        assert_eq!(walk(&mut server, ROOT_FID, 1, &["sub"]).unwrap(), vec![qid]);
+    }
+
+    #[test]
+    fn test_setattr() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("file");
+        fs::write(&path, b"hello world").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let mut server = create_server(&dir, false);
+
+        walk(&mut server, ROOT_FID, 1, &["file"]).unwrap();
+        setattr(&mut server, 1, P9_SETATTR_MODE, 0o640, 0).unwrap();
+        assert_eq!(mode(&path), 0o640);
+        setattr(&mut server, 1, P9_SETATTR_SIZE, 0, 5).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_setattr_symlink() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let secret = outside.as_path().join("secret");
+        fs::write(&secret, b"secret").unwrap();
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o600)).unwrap();
+        symlink(&secret, dir.as_path().join("link")).unwrap();
+        let mut server = create_server(&dir, false);
+
+        // Changing the attributes of a link never affects its target.
+        walk(&mut server, ROOT_FID, 1, &["link"]).unwrap();
+        assert_eq!(
+            setattr(&mut server, 1, P9_SETATTR_MODE, 0o777, 0),
+            Err(libc::EOPNOTSUPP)
+        );
+        assert_eq!(
+            setattr(&mut server, 1, P9_SETATTR_SIZE, 0, 0),
+            Err(libc::ELOOP)
+        );
+        assert_eq!(mode(&secret), 0o600);
+        assert_eq!(fs::read(&secret).unwrap(), b"secret");
+
+        // Neither does replacing a file by a link once it has been walked to.
+        let path = dir.as_path().join("file");
+        fs::write(&path, b"data").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        walk(&mut server, ROOT_FID, 2, &["file"]).unwrap();
+        fs::rename(&path, dir.as_path().join("moved")).unwrap();
+        symlink(&secret, &path).unwrap();
+        setattr(&mut server, 2, P9_SETATTR_MODE, 0o644, 0).unwrap();
+        assert_eq!(mode(&secret), 0o600);
+        assert_eq!(mode(&dir.as_path().join("moved")), 0o644);
+    }
+
+    #[test]
+    fn test_readdir() {
+        let dir = TempDir::new().unwrap();
+        for i in 0..10 {
+            fs::write(dir.as_path().join(format!("file{i}")), b"").unwrap();
+        }
+        fs::create_dir(dir.as_path().join("sub")).unwrap();
+        let mut server = create_server(&dir, false);
+
+        walk(&mut server, ROOT_FID, 1, &[]).unwrap();
+        // The directory must be opened first.
+        assert_eq!(
+            call(&mut server, P9_TREADDIR, |w| {
+                w.u32(1);
+                w.u64(0);
+                w.u32(4096);
+            }),
+            Err(libc::EBADF)
+        );
+        lopen(&mut server, 1, 0).unwrap();
+
+        let (mut all, next) = readdir(&mut server, 1, 0, 4096);
+        assert_eq!(next, 13);
+        all.sort();
+        let mut expected = vec![".".to_string(), "..".to_string(), "sub".to_string()];
+        expected.extend((0..10).map(|i| format!("file{i}")));
+        expected.sort();
+        assert_eq!(all, expected);
+
+        // Read the directory a few entries at a time.
+        let entry_size = (QID_SIZE + 8 + 1 + 2 + "file0".len()) as u32;
+        let mut names = Vec::new();
+        let mut offset = 0;
+        loop {
+            let (entries, next) = readdir(&mut server, 1, offset, 2 * entry_size);
+            if entries.is_empty() {
+                break;
+            }
+            assert!(entries.len() <= 2);
+            names.extend(entries);
+            offset = next;
+        }
+        names.sort();
+        assert_eq!(names, expected);
+
+        // Entries created in the meantime show up when rewinding.
+        fs::write(dir.as_path().join("late"), b"").unwrap();
+        let (names, _) = readdir(&mut server, 1, 0, 4096);
+        assert!(names.contains(&"late".to_string()));
+    }
+
+    #[test]
+    fn test_rename() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.as_path().join("a/b")).unwrap();
+        fs::create_dir(dir.as_path().join("c")).unwrap();
+        fs::write(dir.as_path().join("a/b/file"), b"data").unwrap();
+        let mut server = create_server(&dir, false);
+
+        walk(&mut server, ROOT_FID, 1, &["a"]).unwrap();
+        walk(&mut server, ROOT_FID, 2, &["a", "b", "file"]).unwrap();
+        walk(&mut server, ROOT_FID, 3, &["c"]).unwrap();
+
+        // Moving a directory updates the fids below it.
+        call(&mut server, P9_TRENAME, |w| {
+            w.u32(1);
+            w.u32(3);
+            w.string("d");
+        })
+        .unwrap();
+        assert!(dir.as_path().join("c/d/b/file").exists());
+        assert_eq!(server.fid(1).unwrap().path, Path::new("c/d"));
+        assert_eq!(server.fid(2).unwrap().path, Path::new("c/d/b/file"));
+        lopen(&mut server, 2, 0).unwrap();
+        assert_eq!(read(&mut server, 2, 0, 4).unwrap(), b"data");
+
+        call(&mut server, P9_TRENAMEAT, |w| {
+            w.u32(1);
+            w.string("b");
+            w.u32(ROOT_FID);
+            w.string("e");
+        })
+        .unwrap();
+        assert!(dir.as_path().join("e/file").exists());
+        assert_eq!(server.fid(2).unwrap().path, Path::new("e/file"));
+
+        // The shared directory itself can't be renamed, and names can't
+        // escape their directory.
+        assert_eq!(
+            call(&mut server, P9_TRENAME, |w| {
+                w.u32(ROOT_FID);
+                w.u32(3);
+                w.string("root");
+            }),
+            Err(libc::EBUSY)
+        );
+        assert_eq!(
+            call(&mut server, P9_TRENAMEAT, |w| {
+                w.u32(ROOT_FID);
+                w.string("e");
+                w.u32(3);
+                w.string("../escaped");
+            }),
+            Err(libc::EINVAL)
+        );
+        assert!(dir.as_path().join("e").exists());
+    }
+}
@@ -2,10 +2,11 @@
 //
 // SPDX-License-Identifier: Apache-2.0 AND BSD-3-Clause
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::mem::size_of;
+use std::num::Wrapping;
 use std::os::unix::io::AsRawFd;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Barrier, Mutex, RwLock};
 use std::{io, result};
 
@@ -21,7 +22,7 @@ use vm_memory::{
 };
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vm_virtio::AccessPlatform;
-use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::eventfd::{EventFd, EFD_NONBLOCK};
 
 use super::{
     ActivateResult, EpollHelper, EpollHelperError, EpollHelperHandler, Error as DeviceError,
@@ -29,7 +30,7 @@ use super::{
 };
 use crate::seccomp_filters::Thread;
 use crate::thread_helper::spawn_virtio_thread;
-use crate::{DmaRemapping, GuestMemoryMmap, VirtioInterrupt, VirtioInterruptType};
+use crate::{CountersReader, DmaRemapping, GuestMemoryMmap, VirtioInterrupt, VirtioInterruptType};
 
 /// Queues sizes
 const QUEUE_SIZE: u16 = 256;
@@ -43,21 +44,28 @@ const REQUEST_Q_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 1;
 /// New descriptors are pending on the event queue.
 /// "eventq" lets the device report any fault or other asynchronous event to
 /// the guest driver.
-const _EVENT_Q_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 2;
-
-/// PROBE properties size.
-/// This is the minimal size to provide at least one RESV_MEM property.
-/// Because virtio-iommu expects one MSI reserved region, we must provide it,
-/// otherwise the driver in the guest will define a predefined one between
-/// 0x8000000 and 0x80FFFFF, which is only relevant for ARM architecture, but
-/// will conflict with x86.
+const EVENT_Q_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 2;
+/// New faults are pending, waiting to be reported through the event queue.
+const FAULT_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 3;
+
+/// Size of a single RESV_MEM property.
+/// At least one is provided for every endpoint. Because virtio-iommu expects
+/// one MSI reserved region, we must provide it, otherwise the driver in the
+/// guest will define a predefined one between 0x8000000 and 0x80FFFFF, which
+/// is only relevant for ARM architecture, but will conflict with x86.
 const PROBE_PROP_SIZE: u32 =
     (size_of::<VirtioIommuProbeProperty>() + size_of::<VirtioIommuProbeResvMem>()) as u32;
+/// Maximum number of RESV_MEM properties reported for a single endpoint,
+/// including the MSI one.
+const MAX_PROBE_RESV_MEM: usize = 8;
+
+/// Maximum number of faults waiting for the guest to provide event buffers.
+/// Older faults are dropped first.
+const MAX_PENDING_FAULTS: usize = 64;
 
 /// Virtio IOMMU features
 #[allow(unused)]
 const VIRTIO_IOMMU_F_INPUT_RANGE: u32 = 0;
-#[allow(unused)]
 const VIRTIO_IOMMU_F_DOMAIN_RANGE: u32 = 1;
 #[allow(unused)]
 const VIRTIO_IOMMU_F_MAP_UNMAP: u32 = 2;
@@ -123,7 +131,6 @@ const VIRTIO_IOMMU_S_UNSUPP: u8 = 2;
 const VIRTIO_IOMMU_S_DEVERR: u8 = 3;
 #[allow(unused)]
 const VIRTIO_IOMMU_S_INVAL: u8 = 4;
-#[allow(unused)]
 const VIRTIO_IOMMU_S_RANGE: u8 = 5;
 #[allow(unused)]
 const VIRTIO_IOMMU_S_NOENT: u8 = 6;
@@ -218,7 +225,6 @@ struct VirtioIommuProbeProperty {
 }
 
 /// Virtio IOMMU request PROBE property RESV_MEM subtypes
-#[allow(unused)]
 const VIRTIO_IOMMU_RESV_MEM_T_RESERVED: u8 = 0;
 const VIRTIO_IOMMU_RESV_MEM_T_MSI: u8 = 1;
 
@@ -239,19 +245,15 @@ const VIRTIO_IOMMU_FAULT_F_READ: u32 = 1;
 const VIRTIO_IOMMU_FAULT_F_WRITE: u32 = 1 << 1;
 #[allow(unused)]
 const VIRTIO_IOMMU_FAULT_F_EXEC: u32 = 1 << 2;
-#[allow(unused)]
 const VIRTIO_IOMMU_FAULT_F_ADDRESS: u32 = 1 << 8;
 
 /// Virtio IOMMU fault reasons
 #[allow(unused)]
-const VIRTIO_IOMMU_FAULT_R_UNKNOWN: u32 = 0;
-#[allow(unused)]
-const VIRTIO_IOMMU_FAULT_R_DOMAIN: u32 = 1;
-#[allow(unused)]
-const VIRTIO_IOMMU_FAULT_R_MAPPING: u32 = 2;
+const VIRTIO_IOMMU_FAULT_R_UNKNOWN: u8 = 0;
+const VIRTIO_IOMMU_FAULT_R_DOMAIN: u8 = 1;
+const VIRTIO_IOMMU_FAULT_R_MAPPING: u8 = 2;
 
 /// Fault reporting through eventq
-#[allow(unused)]
 #[derive(Copy, Clone, Debug, Default)]
 #[repr(C, packed)]
 struct VirtioIommuFault {
@@ -322,6 +324,8 @@ enum Error {
     InvalidUnmapRequestMissingDomain,
     #[error("Guest sent us invalid PROBE request")]
     InvalidProbeRequest,
+    #[error("Guest gave us a read only event buffer")]
+    ReadOnlyEventBuffer,
     #[error("Failed to performing external mapping: {0}")]
     ExternalMapping(io::Error),
     #[error("Failed to performing external unmapping: {0}")]
@@ -346,6 +350,8 @@ impl Request {
         mapping: &Arc<IommuMapping>,
         ext_mapping: &BTreeMap<u32, Arc<dyn ExternalDmaMapping>>,
         msi_iova_space: (u64, u64),
+        domain_range: (u32, u32),
+        reserved_regions: &BTreeMap<u32, Vec<ReservedRegion>>,
     ) -> result::Result<usize, Error> {
         let desc = desc_chain
             .next()
@@ -376,6 +382,7 @@ impl Request {
         };
 
         let (msi_iova_start, msi_iova_end) = msi_iova_space;
+        let (domain_start, domain_end) = domain_range;
 
         // Create the reply
         let mut reply: Vec<u8> = Vec::new();
@@ -402,6 +409,17 @@ impl Request {
                     let bypass =
                         (req.flags & VIRTIO_IOMMU_ATTACH_F_BYPASS) == VIRTIO_IOMMU_ATTACH_F_BYPASS;
 
+                    // The domain must be within the range advertised through
+                    // VIRTIO_IOMMU_F_DOMAIN_RANGE.
+                    if domain_id < domain_start || domain_id > domain_end {
+                        warn!(
+                            "Domain {} out of range [{}, {}]",
+                            domain_id, domain_start, domain_end
+                        );
+                        status = VIRTIO_IOMMU_S_RANGE;
+                        return Ok(());
+                    }
+
                     let mut old_domain_id = domain_id;
                     if let Some(&id) = mapping.endpoints.read().unwrap().get(&endpoint) {
                         old_domain_id = id;
@@ -589,21 +607,39 @@ impl Request {
                         .map_err(Error::GuestMemory)?;
                     debug!("Probe request 0x{:x?}", req);
 
-                    let probe_prop = VirtioIommuProbeProperty {
-                        type_: VIRTIO_IOMMU_PROBE_T_RESV_MEM,
-                        length: size_of::<VirtioIommuProbeResvMem>() as u16,
-                    };
-                    reply.extend_from_slice(probe_prop.as_slice());
+                    // Copy the value to use it as a proper reference.
+                    let endpoint = req.endpoint;
 
-                    let resv_mem = VirtioIommuProbeResvMem {
+                    let mut resv_mems = vec![VirtioIommuProbeResvMem {
                         subtype: VIRTIO_IOMMU_RESV_MEM_T_MSI,
                         start: msi_iova_start,
                         end: msi_iova_end,
                         ..Default::default()
-                    };
-                    reply.extend_from_slice(resv_mem.as_slice());
+                    }];
+                    if let Some(regions) = reserved_regions.get(&endpoint) {
+                        resv_mems.extend(regions.iter().map(VirtioIommuProbeResvMem::from));
+                    }
+                    if resv_mems.len() > MAX_PROBE_RESV_MEM {
+                        warn!(
+                            "Too many reserved regions for endpoint {}, only reporting {}",
+                            endpoint, MAX_PROBE_RESV_MEM
+                        );
+                        resv_mems.truncate(MAX_PROBE_RESV_MEM);
+                    }
+
+                    for resv_mem in resv_mems {
+                        let probe_prop = VirtioIommuProbeProperty {
+                            type_: VIRTIO_IOMMU_PROBE_T_RESV_MEM,
+                            length: size_of::<VirtioIommuProbeResvMem>() as u16,
+                        };
+                        reply.extend_from_slice(probe_prop.as_slice());
+                        reply.extend_from_slice(resv_mem.as_slice());
+                    }
 
-                    hdr_len = PROBE_PROP_SIZE;
+                    // Fill the remaining space with zeroes, which terminates
+                    // the list of properties with a NONE one.
+                    hdr_len = PROBE_PROP_SIZE * MAX_PROBE_RESV_MEM as u32;
+                    reply.resize(hdr_len as usize, 0);
                 }
                 _ => {
                     status = VIRTIO_IOMMU_S_INVAL;
@@ -682,15 +718,17 @@ fn detach_endpoint_from_domain(
 struct IommuEpollHandler {
     mem: GuestMemoryAtomic<GuestMemoryMmap>,
     request_queue: Queue,
-    _event_queue: Queue,
+    event_queue: Queue,
     interrupt_cb: Arc<dyn VirtioInterrupt>,
     request_queue_evt: EventFd,
-    _event_queue_evt: EventFd,
+    event_queue_evt: EventFd,
     kill_evt: EventFd,
     pause_evt: EventFd,
     mapping: Arc<IommuMapping>,
     ext_mapping: Arc<Mutex<BTreeMap<u32, Arc<dyn ExternalDmaMapping>>>>,
     msi_iova_space: (u64, u64),
+    domain_range: (u32, u32),
+    reserved_regions: Arc<RwLock<BTreeMap<u32, Vec<ReservedRegion>>>>,
 }
 
 impl IommuEpollHandler {
@@ -703,6 +741,8 @@ impl IommuEpollHandler {
                 &self.mapping,
                 &self.ext_mapping.lock().unwrap(),
                 self.msi_iova_space,
+                self.domain_range,
+                &self.reserved_regions.read().unwrap(),
             )?;
 
             self.request_queue
@@ -715,6 +755,68 @@ impl IommuEpollHandler {
         Ok(used_descs)
     }
 
+    // Report the pending faults, as long as the guest provides buffers.
+    fn event_queue(&mut self) -> Result<bool, Error> {
+        let mut used_descs = false;
+        let mut faults = self.mapping.faults.lock().unwrap();
+        while !faults.is_empty() {
+            let Some(mut desc_chain) = self.event_queue.pop_descriptor_chain(self.mem.memory())
+            else {
+                break;
+            };
+
+            let desc = desc_chain.next().ok_or(Error::DescriptorChainTooShort)?;
+            let fault = faults.pop_front().unwrap();
+
+            // A buffer the fault can't be written to is a bug of the guest
+            // driver, which only costs it the fault: the buffer is given
+            // back untouched.
+            let invalid = if !desc.is_write_only() {
+                Some(Error::ReadOnlyEventBuffer)
+            } else if (desc.len() as usize) < size_of::<VirtioIommuFault>() {
+                Some(Error::BufferLengthTooSmall)
+            } else {
+                None
+            };
+            let len = if let Some(e) = invalid {
+                error!(
+                    "Dropping the fault of endpoint {}: {}",
+                    { fault.endpoint },
+                    e
+                );
+                self.mapping.dropped_faults.fetch_add(1, Ordering::Relaxed);
+                0
+            } else {
+                desc_chain
+                    .memory()
+                    .write_obj(fault, desc.addr())
+                    .map_err(Error::GuestMemory)?;
+                size_of::<VirtioIommuFault>() as u32
+            };
+
+            self.event_queue
+                .add_used(desc_chain.memory(), desc_chain.head_index(), len)
+                .map_err(Error::QueueAddUsed)?;
+
+            used_descs = true;
+        }
+
+        Ok(used_descs)
+    }
+
+    fn process_event_queue(&mut self) -> result::Result<(), EpollHelperError> {
+        let needs_notification = self.event_queue().map_err(|e| {
+            EpollHelperError::HandleEvent(anyhow!("Failed to process event queue : {:?}", e))
+        })?;
+        if needs_notification {
+            self.signal_used_queue(1).map_err(|e| {
+                EpollHelperError::HandleEvent(anyhow!("Failed to signal used queue: {:?}", e))
+            })?;
+        }
+
+        Ok(())
+    }
+
     fn signal_used_queue(&self, queue_index: u16) -> result::Result<(), DeviceError> {
         self.interrupt_cb
             .trigger(VirtioInterruptType::Queue(queue_index))
@@ -731,6 +833,8 @@ impl IommuEpollHandler {
     ) -> result::Result<(), EpollHelperError> {
         let mut helper = EpollHelper::new(&self.kill_evt, &self.pause_evt)?;
         helper.add_event(self.request_queue_evt.as_raw_fd(), REQUEST_Q_EVENT)?;
+        helper.add_event(self.event_queue_evt.as_raw_fd(), EVENT_Q_EVENT)?;
+        helper.add_event(self.mapping.fault_evt.as_raw_fd(), FAULT_EVENT)?;
         helper.run(paused, paused_sync, self)?;
 
         Ok(())
@@ -765,6 +869,18 @@ impl EpollHelperHandler for IommuEpollHandler {
                     })?;
                 }
             }
+            EVENT_Q_EVENT => {
+                self.event_queue_evt.read().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Failed to get queue event: {:?}", e))
+                })?;
+                self.process_event_queue()?;
+            }
+            FAULT_EVENT => {
+                self.mapping.fault_evt.read().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Failed to get fault event: {:?}", e))
+                })?;
+                self.process_event_queue()?;
+            }
             _ => {
                 return Err(EpollHelperError::HandleEvent(anyhow!(
                     "Unexpected event: {}",
@@ -797,61 +913,142 @@ pub struct IommuMapping {
     // Global flag indicating if endpoints that are not attached to any domain
     // are in bypass mode.
     bypass: AtomicBool,
+    // Faults waiting to be reported through the event queue.
+    faults: Mutex<VecDeque<VirtioIommuFault>>,
+    // Notified every time a fault is queued.
+    fault_evt: EventFd,
+    // Number of DMA faults per endpoint.
+    fault_counts: Mutex<BTreeMap<u32, u64>>,
+    // Number of faults dropped before the guest could be notified.
+    dropped_faults: AtomicU64,
 }
 
-impl DmaRemapping for IommuMapping {
-    fn translate_gva(&self, id: u32, addr: u64) -> std::result::Result<u64, std::io::Error> {
-        debug!("Translate GVA addr 0x{:x}", addr);
-        if let Some(domain_id) = self.endpoints.read().unwrap().get(&id) {
+impl IommuMapping {
+    /// Number of DMA faults that have been reported, per endpoint.
+    pub fn dma_faults(&self) -> BTreeMap<u32, u64> {
+        self.fault_counts.lock().unwrap().clone()
+    }
+
+    fn report_fault(&self, endpoint: u32, reason: u8, addr: u64) {
+        *self
+            .fault_counts
+            .lock()
+            .unwrap()
+            .entry(endpoint)
+            .or_insert(0) += 1;
+
+        let mut faults = self.faults.lock().unwrap();
+        if faults.len() >= MAX_PENDING_FAULTS {
+            faults.pop_front();
+            self.dropped_faults.fetch_add(1, Ordering::Relaxed);
+        }
+        faults.push_back(VirtioIommuFault {
+            reason,
+            flags: VIRTIO_IOMMU_FAULT_F_ADDRESS,
+            endpoint,
+            address: addr,
+            ..Default::default()
+        });
+        drop(faults);
+
+        if let Err(e) = self.fault_evt.write(1) {
+            error!("Failed to notify fault: {}", e);
+        }
+    }
+
+    // Translate an address from the given endpoint using the translation
+    // function on the matching mapping, reporting a fault on failure.
+    fn translate(
+        &self,
+        id: u32,
+        addr: u64,
+        translate: impl Fn(u64, &Mapping) -> Option<u64>,
+    ) -> Option<u64> {
+        let reason = if let Some(domain_id) = self.endpoints.read().unwrap().get(&id) {
             if let Some(domain) = self.domains.read().unwrap().get(domain_id) {
                 // Directly return identity mapping in case the domain is in
                 // bypass mode.
                 if domain.bypass {
-                    return Ok(addr);
+                    return Some(addr);
                 }
 
-                for (&key, &value) in domain.mappings.iter() {
-                    if addr >= key && addr < key + value.size {
-                        let new_addr = addr - key + value.gpa;
-                        debug!("Into GPA addr 0x{:x}", new_addr);
-                        return Ok(new_addr);
+                for (&key, value) in domain.mappings.iter() {
+                    if let Some(new_addr) = translate(key, value) {
+                        return Some(new_addr);
                     }
                 }
+
+                VIRTIO_IOMMU_FAULT_R_MAPPING
+            } else {
+                VIRTIO_IOMMU_FAULT_R_DOMAIN
             }
         } else if self.bypass.load(Ordering::Acquire) {
-            return Ok(addr);
-        }
+            return Some(addr);
+        } else {
+            VIRTIO_IOMMU_FAULT_R_DOMAIN
+        };
 
-        Err(io::Error::other(format!(
-            "failed to translate GVA addr 0x{addr:x}"
-        )))
+        self.report_fault(id, reason, addr);
+
+        None
+    }
+}
+
+impl DmaRemapping for IommuMapping {
+    fn translate_gva(&self, id: u32, addr: u64) -> std::result::Result<u64, std::io::Error> {
+        debug!("Translate GVA addr 0x{:x}", addr);
+        let new_addr = self
+            .translate(id, addr, |key, value| {
+                (addr >= key && addr < key + value.size).then(|| addr - key + value.gpa)
+            })
+            .ok_or_else(|| io::Error::other(format!("failed to translate GVA addr 0x{addr:x}")))?;
+        debug!("Into GPA addr 0x{:x}", new_addr);
+
+        Ok(new_addr)
     }
 
     fn translate_gpa(&self, id: u32, addr: u64) -> std::result::Result<u64, std::io::Error> {
         debug!("Translate GPA addr 0x{:x}", addr);
-        if let Some(domain_id) = self.endpoints.read().unwrap().get(&id) {
-            if let Some(domain) = self.domains.read().unwrap().get(domain_id) {
-                // Directly return identity mapping in case the domain is in
-                // bypass mode.
-                if domain.bypass {
-                    return Ok(addr);
-                }
+        let new_addr = self
+            .translate(id, addr, |key, value| {
+                (addr >= value.gpa && addr < value.gpa + value.size).then(|| addr - value.gpa + key)
+            })
+            .ok_or_else(|| io::Error::other(format!("failed to translate GPA addr 0x{addr:x}")))?;
+        debug!("Into GVA addr 0x{:x}", new_addr);
 
-                for (&key, &value) in domain.mappings.iter() {
-                    if addr >= value.gpa && addr < value.gpa + value.size {
-                        let new_addr = addr - value.gpa + key;
-                        debug!("Into GVA addr 0x{:x}", new_addr);
-                        return Ok(new_addr);
-                    }
-                }
-            }
-        } else if self.bypass.load(Ordering::Acquire) {
-            return Ok(addr);
-        }
+        Ok(new_addr)
+    }
+}
+
+/// Type of an IOVA range reserved for an endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReservedRegionType {
+    /// The guest must not map anything in the range.
+    Reserved,
+    /// The range is used for MSI doorbells.
+    Msi,
+}
+
+/// IOVA range reported to the guest for a specific endpoint through the
+/// PROBE request, in addition to the MSI window every endpoint gets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReservedRegion {
+    pub start: u64,
+    pub end: u64,
+    pub type_: ReservedRegionType,
+}
 
-        Err(io::Error::other(format!(
-            "failed to translate GPA addr 0x{addr:x}"
-        )))
+impl From<&ReservedRegion> for VirtioIommuProbeResvMem {
+    fn from(region: &ReservedRegion) -> Self {
+        VirtioIommuProbeResvMem {
+            subtype: match region.type_ {
+                ReservedRegionType::Reserved => VIRTIO_IOMMU_RESV_MEM_T_RESERVED,
+                ReservedRegionType::Msi => VIRTIO_IOMMU_RESV_MEM_T_MSI,
+            },
+            start: region.start,
+            end: region.end,
+            ..Default::default()
+        }
     }
 }
 
@@ -885,6 +1082,8 @@ pub struct Iommu {
     seccomp_action: SeccompAction,
     exit_evt: EventFd,
     msi_iova_space: (u64, u64),
+    domain_range: (u32, u32),
+    reserved_regions: Arc<RwLock<BTreeMap<u32, Vec<ReservedRegion>>>>,
 }
 
 type EndpointsState = Vec<(u32, u32)>;
@@ -905,6 +1104,7 @@ impl Iommu {
         exit_evt: EventFd,
         msi_iova_space: (u64, u64),
         address_width_bits: u8,
+        max_domains: Option<u32>,
         state: Option<IommuState>,
     ) -> io::Result<(Self, Arc<IommuMapping>)> {
         let (mut avail_features, acked_features, endpoints, domains, paused) =
@@ -933,17 +1133,26 @@ impl Iommu {
                 let avail_features = (1u64 << VIRTIO_F_VERSION_1)
                     | (1u64 << VIRTIO_IOMMU_F_MAP_UNMAP)
                     | (1u64 << VIRTIO_IOMMU_F_PROBE)
-                    | (1u64 << VIRTIO_IOMMU_F_BYPASS_CONFIG);
+                    | (1u64 << VIRTIO_IOMMU_F_BYPASS_CONFIG)
+                    | (1u64 << VIRTIO_IOMMU_F_DOMAIN_RANGE);
 
                 (avail_features, 0, BTreeMap::new(), BTreeMap::new(), false)
             };
 
         let mut config = VirtioIommuConfig {
             page_size_mask: VIRTIO_IOMMU_PAGE_SIZE_MASK,
-            probe_size: PROBE_PROP_SIZE,
+            probe_size: PROBE_PROP_SIZE * MAX_PROBE_RESV_MEM as u32,
             ..Default::default()
         };
 
+        let domain_range = (0, max_domains.map_or(u32::MAX, |max| max - 1));
+        if avail_features & (1u64 << VIRTIO_IOMMU_F_DOMAIN_RANGE) != 0 {
+            config.domain_range = VirtioIommuRange32 {
+                start: domain_range.0,
+                end: domain_range.1,
+            };
+        }
+
         if address_width_bits < 64 {
             avail_features |= 1u64 << VIRTIO_IOMMU_F_INPUT_RANGE;
             config.input_range = VirtioIommuRange64 {
@@ -956,6 +1165,10 @@ impl Iommu {
             endpoints: Arc::new(RwLock::new(endpoints)),
             domains: Arc::new(RwLock::new(domains)),
             bypass: AtomicBool::new(true),
+            faults: Mutex::new(VecDeque::new()),
+            fault_evt: EventFd::new(EFD_NONBLOCK)?,
+            fault_counts: Mutex::new(BTreeMap::new()),
+            dropped_faults: AtomicU64::new(0),
         });
 
         Ok((
@@ -977,6 +1190,8 @@ impl Iommu {
                 seccomp_action,
                 exit_evt,
                 msi_iova_space,
+                domain_range,
+                reserved_regions: Arc::new(RwLock::new(BTreeMap::new())),
             },
             mapping,
         ))
@@ -1024,6 +1239,28 @@ impl Iommu {
         self.ext_mapping.lock().unwrap().insert(device_id, mapping);
     }
 
+    /// Report an additional reserved IOVA range to the guest when it probes
+    /// the given endpoint.
+    pub fn add_reserved_region(&mut self, endpoint: u32, region: ReservedRegion) {
+        self.reserved_regions
+            .write()
+            .unwrap()
+            .entry(endpoint)
+            .or_default()
+            .push(region);
+    }
+
+    /// Stop reporting the reserved IOVA ranges of the given endpoint, once
+    /// the device behind it has been removed.
+    pub fn remove_reserved_regions(&mut self, endpoint: u32) {
+        self.reserved_regions.write().unwrap().remove(&endpoint);
+    }
+
+    /// Number of DMA faults that have been reported, per endpoint.
+    pub fn dma_faults(&self) -> BTreeMap<u32, u64> {
+        self.mapping.dma_faults()
+    }
+
     #[cfg(fuzzing)]
     pub fn wait_for_epoll_threads(&mut self) {
         self.common.wait_for_epoll_threads();
@@ -1089,20 +1326,22 @@ impl VirtioDevice for Iommu {
         let (kill_evt, pause_evt) = self.common.dup_eventfds();
 
         let (_, request_queue, request_queue_evt) = queues.remove(0);
-        let (_, _event_queue, _event_queue_evt) = queues.remove(0);
+        let (_, event_queue, event_queue_evt) = queues.remove(0);
 
         let mut handler = IommuEpollHandler {
             mem,
             request_queue,
-            _event_queue,
+            event_queue,
             interrupt_cb,
             request_queue_evt,
-            _event_queue_evt,
+            event_queue_evt,
             kill_evt,
             pause_evt,
             mapping: self.mapping.clone(),
             ext_mapping: self.ext_mapping.clone(),
             msi_iova_space: self.msi_iova_space,
+            domain_range: self.domain_range,
+            reserved_regions: self.reserved_regions.clone(),
         };
 
         let paused = self.common.paused.clone();
@@ -1128,6 +1367,22 @@ impl VirtioDevice for Iommu {
         event!("virtio-device", "reset", "id", &self.id);
         result
     }
+
+    fn counters_reader(&self) -> Option<CountersReader> {
+        let mapping = self.mapping.clone();
+        Some(Arc::new(move || {
+            let mut counters = HashMap::new();
+
+            let dma_faults = mapping.dma_faults().values().sum();
+            counters.insert("dma_faults", Wrapping(dma_faults));
+            counters.insert(
+                "dropped_fault_events",
+                Wrapping(mapping.dropped_faults.load(Ordering::Relaxed)),
+            );
+
+            counters
+        }))
+    }
 }
 
 impl Pausable for Iommu {
@@ -1151,3 +1406,305 @@ impl Snapshottable for Iommu {
 }
 impl Transportable for Iommu {}
 impl Migratable for Iommu {}
+
+#[cfg(test)]
+mod tests {
+    use virtio_bindings::virtio_ring::{VRING_DESC_F_NEXT, VRING_DESC_F_WRITE};
+    use vm_virtio::queue::testing::VirtQueue as GuestQ;
+
+    use super::*;
+    use crate::vsock::tests::NoopVirtioInterrupt;
+
+    const MEM_SIZE: usize = 0x100_0000;
+    const QUEUE_ADDR: u64 = 0x10_0000;
+    const REQUEST_ADDR: u64 = 0x20_0000;
+    const REPLY_ADDR: u64 = 0x30_0000;
+    const MSI_IOVA_SPACE: (u64, u64) = (0xfee0_0000, 0xfeef_ffff);
+
+    fn create_iommu(max_domains: Option<u32>) -> (Iommu, Arc<IommuMapping>) {
+        Iommu::new(
+            String::from("iommu"),
+            SeccompAction::Trap,
+            EventFd::new(0).unwrap(),
+            MSI_IOVA_SPACE,
+            48,
+            max_domains,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn create_handler(
+        iommu: &Iommu,
+        mem: &GuestMemoryMmap,
+        event_queue: Queue,
+    ) -> IommuEpollHandler {
+        IommuEpollHandler {
+            mem: GuestMemoryAtomic::new(mem.clone()),
+            request_queue: Queue::new(QUEUE_SIZE).unwrap(),
+            event_queue,
+            interrupt_cb: Arc::new(NoopVirtioInterrupt {}),
+            request_queue_evt: EventFd::new(0).unwrap(),
+            event_queue_evt: EventFd::new(0).unwrap(),
+            kill_evt: EventFd::new(0).unwrap(),
+            pause_evt: EventFd::new(0).unwrap(),
+            mapping: iommu.mapping.clone(),
+            ext_mapping: iommu.ext_mapping.clone(),
+            msi_iova_space: iommu.msi_iova_space,
+            domain_range: iommu.domain_range,
+            reserved_regions: iommu.reserved_regions.clone(),
+        }
+    }
+
+    // Process a single request through the request queue, returning the
+    // reply written by the device, including the tail.
+    fn request<T: ByteValued>(iommu: &Iommu, type_: u8, req: T, reply_len: usize) -> Vec<u8> {
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, QUEUE_SIZE);
+
+        let head = VirtioIommuReqHead {
+            type_,
+            ..Default::default()
+        };
+        mem.write_obj(head, GuestAddress(REQUEST_ADDR)).unwrap();
+        mem.write_obj(
+            req,
+            GuestAddress(REQUEST_ADDR + size_of::<VirtioIommuReqHead>() as u64),
+        )
+        .unwrap();
+        guest_q.dtable[0].set(
+            REQUEST_ADDR,
+            (size_of::<VirtioIommuReqHead>() + size_of::<T>()) as u32,
+            VRING_DESC_F_NEXT.try_into().unwrap(),
+            1,
+        );
+        guest_q.dtable[1].set(
+            REPLY_ADDR,
+            reply_len as u32,
+            VRING_DESC_F_WRITE.try_into().unwrap(),
+            0,
+        );
+        guest_q.avail.ring[0].set(0);
+        guest_q.avail.idx.set(1);
+
+        let mut handler = create_handler(iommu, &mem, Queue::new(QUEUE_SIZE).unwrap());
+        handler.request_queue = guest_q.create_queue();
+        // Errors are reported to the guest through the status
+        let _ = handler.request_queue();
+        assert_eq!(guest_q.used.idx.get(), 1);
+
+        let mut reply = vec![0u8; reply_len];
+        mem.read_slice(&mut reply, GuestAddress(REPLY_ADDR))
+            .unwrap();
+        reply
+    }
+
+    fn attach(iommu: &Iommu, domain: u32, endpoint: u32) -> u8 {
+        let req = VirtioIommuReqAttach {
+            domain,
+            endpoint,
+            ..Default::default()
+        };
+        request(
+            iommu,
+            VIRTIO_IOMMU_T_ATTACH,
+            req,
+            size_of::<VirtioIommuReqTail>(),
+        )[0]
+    }
+
+    fn probe(iommu: &Iommu, endpoint: u32) -> Vec<VirtioIommuProbeResvMem> {
+        let req = VirtioIommuReqProbe {
+            endpoint,
+            ..Default::default()
+        };
+        let probe_size = PROBE_PROP_SIZE as usize * MAX_PROBE_RESV_MEM;
+        let reply = request(
+            iommu,
+            VIRTIO_IOMMU_T_PROBE,
+            req,
+            probe_size + size_of::<VirtioIommuReqTail>(),
+        );
+        assert_eq!(reply[probe_size], VIRTIO_IOMMU_S_OK);
+
+        let mut resv_mems = Vec::new();
+        for prop in reply[..probe_size].chunks(PROBE_PROP_SIZE as usize) {
+            let (header, resv_mem) = prop.split_at(size_of::<VirtioIommuProbeProperty>());
+            let header = VirtioIommuProbeProperty::from_slice(header).unwrap();
+            if header.type_ != VIRTIO_IOMMU_PROBE_T_RESV_MEM {
+                break;
+            }
+            resv_mems.push(*VirtioIommuProbeResvMem::from_slice(resv_mem).unwrap());
+        }
+        resv_mems
+    }
+
+    #[test]
+    fn test_iommu_domain_range() {
+        let (iommu, mapping) = create_iommu(Some(4));
+        let mut config = VirtioIommuConfig::default();
+        iommu.read_config(0, config.as_mut_slice());
+        let domain_range = config.domain_range;
+        assert_eq!({ domain_range.start }, 0);
+        assert_eq!({ domain_range.end }, 3);
+
+        assert_eq!(attach(&iommu, 3, 8), VIRTIO_IOMMU_S_OK);
+        assert_eq!(mapping.endpoints.read().unwrap().get(&8), Some(&3));
+
+        assert_eq!(attach(&iommu, 4, 16), VIRTIO_IOMMU_S_RANGE);
+        assert!(!mapping.endpoints.read().unwrap().contains_key(&16));
+    }
+
+    #[test]
+    fn test_iommu_probe_reserved_regions() {
+        let (mut iommu, _) = create_iommu(None);
+        iommu.add_reserved_region(
+            8,
+            ReservedRegion {
+                start: 0x1000_0000,
+                end: 0x1fff_ffff,
+                type_: ReservedRegionType::Reserved,
+            },
+        );
+
+        // Every endpoint gets the MSI window
+        let resv_mems = probe(&iommu, 16);
+        assert_eq!(resv_mems.len(), 1);
+        assert_eq!(resv_mems[0].subtype, VIRTIO_IOMMU_RESV_MEM_T_MSI);
+        assert_eq!({ resv_mems[0].start }, MSI_IOVA_SPACE.0);
+        assert_eq!({ resv_mems[0].end }, MSI_IOVA_SPACE.1);
+
+        // The reserved regions are only reported for their own endpoint
+        let resv_mems = probe(&iommu, 8);
+        assert_eq!(resv_mems.len(), 2);
+        assert_eq!(resv_mems[0].subtype, VIRTIO_IOMMU_RESV_MEM_T_MSI);
+        assert_eq!(resv_mems[1].subtype, VIRTIO_IOMMU_RESV_MEM_T_RESERVED);
+        assert_eq!({ resv_mems[1].start }, 0x1000_0000);
+        assert_eq!({ resv_mems[1].end }, 0x1fff_ffff);
+
+        // The list is truncated to what the probe buffer can hold
+        for i in 0..MAX_PROBE_RESV_MEM as u64 {
+            iommu.add_reserved_region(
+                8,
+                ReservedRegion {
+                    start: (i + 2) << 28,
+                    end: ((i + 3) << 28) - 1,
+                    type_: ReservedRegionType::Msi,
+                },
+            );
+        }
+        assert_eq!(probe(&iommu, 8).len(), MAX_PROBE_RESV_MEM);
+
+        // Nothing is left behind for the next device plugged at the same
+        // endpoint.
+        iommu.remove_reserved_regions(8);
+        let resv_mems = probe(&iommu, 8);
+        assert_eq!(resv_mems.len(), 1);
+        assert_eq!(resv_mems[0].subtype, VIRTIO_IOMMU_RESV_MEM_T_MSI);
+    }
+
+    #[test]
+    fn test_iommu_translate_faults() {
+        let (iommu, mapping) = create_iommu(None);
+
+        // Endpoints not attached to any domain bypass the IOMMU by default
+        assert_eq!(mapping.translate_gva(8, 0x1000).unwrap(), 0x1000);
+        assert!(iommu.dma_faults().is_empty());
+
+        mapping.bypass.store(false, Ordering::Release);
+        mapping.translate_gva(8, 0x1000).unwrap_err();
+
+        attach(&iommu, 1, 16);
+        mapping
+            .domains
+            .write()
+            .unwrap()
+            .get_mut(&1)
+            .unwrap()
+            .mappings
+            .insert(
+                0x10_0000,
+                Mapping {
+                    gpa: 0x80_0000,
+                    size: 0x1000,
+                },
+            );
+        assert_eq!(mapping.translate_gva(16, 0x10_0010).unwrap(), 0x80_0010);
+        assert_eq!(mapping.translate_gpa(16, 0x80_0010).unwrap(), 0x10_0010);
+        mapping.translate_gva(16, 0x10_1000).unwrap_err();
+        mapping.translate_gpa(16, 0x10_0010).unwrap_err();
+
+        assert_eq!(iommu.dma_faults(), BTreeMap::from([(8, 1), (16, 2)]));
+        assert_eq!(mapping.fault_evt.read().unwrap(), 3);
+
+        let faults = mapping.faults.lock().unwrap();
+        assert_eq!(faults.len(), 3);
+        assert_eq!(faults[0].reason, VIRTIO_IOMMU_FAULT_R_DOMAIN);
+        assert_eq!({ faults[0].endpoint }, 8);
+        assert_eq!({ faults[0].address }, 0x1000);
+        assert_eq!(faults[1].reason, VIRTIO_IOMMU_FAULT_R_MAPPING);
+        assert_eq!({ faults[1].endpoint }, 16);
+        assert_eq!({ faults[1].address }, 0x10_1000);
+        assert_eq!({ faults[1].flags }, VIRTIO_IOMMU_FAULT_F_ADDRESS);
+    }
+
+    #[test]
+    fn test_iommu_fault_event_queue() {
+        let (iommu, mapping) = create_iommu(None);
+        mapping.bypass.store(false, Ordering::Release);
+
+        // Older faults are dropped once too many are pending
+        for i in 0..MAX_PENDING_FAULTS as u64 + 2 {
+            mapping.translate_gva(8, i << 12).unwrap_err();
+        }
+        assert_eq!(mapping.dropped_faults.load(Ordering::Relaxed), 2);
+        assert_eq!(iommu.dma_faults(), BTreeMap::from([(8, 66)]));
+
+        // Only as many faults as buffers provided by the guest are reported
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, QUEUE_SIZE);
+        for i in 0..2u16 {
+            guest_q.dtable[i as usize].set(
+                REPLY_ADDR + u64::from(i) * 0x100,
+                size_of::<VirtioIommuFault>() as u32,
+                VRING_DESC_F_WRITE.try_into().unwrap(),
+                0,
+            );
+            guest_q.avail.ring[i as usize].set(i);
+        }
+        guest_q.avail.idx.set(2);
+
+        let mut handler = create_handler(&iommu, &mem, guest_q.create_queue());
+        assert!(handler.event_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 2);
+        assert_eq!(mapping.faults.lock().unwrap().len(), MAX_PENDING_FAULTS - 2);
+
+        for i in 0..2u64 {
+            let fault: VirtioIommuFault =
+                mem.read_obj(GuestAddress(REPLY_ADDR + i * 0x100)).unwrap();
+            assert_eq!(fault.reason, VIRTIO_IOMMU_FAULT_R_DOMAIN);
+            assert_eq!({ fault.endpoint }, 8);
+            assert_eq!({ fault.address }, (i + 2) << 12);
+        }
+
+        // Nothing is reported until the guest provides more buffers
+        assert!(!handler.event_queue().unwrap());
+
+        // Faults can't be written to read only or short buffers, they are
+        // dropped and the buffers given back.
+        guest_q.dtable[2].set(REPLY_ADDR, size_of::<VirtioIommuFault>() as u32, 0, 0);
+        guest_q.dtable[3].set(
+            REPLY_ADDR + 0x100,
+            size_of::<VirtioIommuFault>() as u32 - 1,
+            VRING_DESC_F_WRITE.try_into().unwrap(),
+            0,
+        );
+        guest_q.avail.ring[2].set(2);
+        guest_q.avail.ring[3].set(3);
+        guest_q.avail.idx.set(4);
+        assert!(handler.event_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 4);
+        assert_eq!(mapping.faults.lock().unwrap().len(), MAX_PENDING_FAULTS - 4);
+        assert_eq!(mapping.dropped_faults.load(Ordering::Relaxed), 4);
+    }
+}
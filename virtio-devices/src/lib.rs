@@ -16,6 +16,8 @@ extern crate event_monitor;
 extern crate log;
 
 use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -26,6 +28,7 @@ pub mod balloon;
 pub mod block;
 mod console;
 pub mod epoll_helper;
+mod fs9p;
 mod iommu;
 pub mod mem;
 pub mod net;
@@ -43,21 +46,24 @@ use vm_memory::bitmap::AtomicBitmap;
 use vm_memory::{GuestAddress, GuestMemory};
 use vm_virtio::VirtioDeviceType;
 
-pub use self::balloon::Balloon;
+pub use self::balloon::{Balloon, BalloonStatistics, FreePageHintsWaiter};
 pub use self::block::{Block, BlockState};
 pub use self::console::{Console, ConsoleResizer, Endpoint};
 pub use self::device::{
-    DmaRemapping, UserspaceMapping, VirtioCommon, VirtioDevice, VirtioInterrupt,
-    VirtioInterruptType, VirtioSharedMemoryList,
+    CountersReader, DmaRemapping, UserspaceMapping, VirtioCommon, VirtioDevice, VirtioInterrupt,
+    VirtioInterruptType, VirtioSharedMemory, VirtioSharedMemoryList,
 };
 pub use self::epoll_helper::{
     EpollHelper, EpollHelperError, EpollHelperHandler, EPOLL_HELPER_EVENT_LAST,
 };
-pub use self::iommu::{AccessPlatformMapping, Iommu, IommuMapping};
+pub use self::fs9p::{Fs9p, Fs9pState, VIRTIO_9P_TAG_LEN};
+pub use self::iommu::{
+    AccessPlatformMapping, Iommu, IommuMapping, ReservedRegion, ReservedRegionType,
+};
 pub use self::mem::{BlocksState, Mem, VirtioMemMappingSource, VIRTIO_MEM_ALIGN_SIZE};
 pub use self::net::{Net, NetCtrlEpollHandler};
 pub use self::pmem::Pmem;
-pub use self::rng::Rng;
+pub use self::rng::{Rng, EGD_SOURCE_PREFIX};
 pub use self::vdpa::{Vdpa, VdpaDmaMapping};
 pub use self::vsock::Vsock;
 pub use self::watchdog::Watchdog;
@@ -99,6 +105,8 @@ pub enum ActivateError {
     CreateSeccompFilter(seccompiler::Error),
     #[error("Failed to create rate limiter: {0}")]
     CreateRateLimiter(std::io::Error),
+    #[error("Failed to create rate limiter group handle: {0}")]
+    CreateRateLimiterGroupHandle(rate_limiter::group::Error),
     #[error("Failed to activate the vDPA device: {0}")]
     ActivateVdpa(vdpa::Error),
 }
@@ -117,6 +125,8 @@ pub enum Error {
     VhostUserUpdateMemory(vhost_user::Error),
     #[error("Failed to add memory region vhost-user: {0}")]
     VhostUserAddMemoryRegion(vhost_user::Error),
+    #[error("Failed to remove memory region vhost-user: {0}")]
+    VhostUserRemoveMemoryRegion(vhost_user::Error),
     #[error("Failed to set shared memory region")]
     SetShmRegionsNotSupported,
     #[error("Failed to process net queue: {0}")]
@@ -125,6 +135,20 @@ pub enum Error {
     QueueAddUsed(virtio_queue::Error),
     #[error("Failed to : {0}")]
     QueueIterator(virtio_queue::Error),
+    #[error("Updating the rate limiter is not supported by this device")]
+    UpdateRateLimiterNotSupported,
+    #[error("Device has no rate limiter to update")]
+    NoRateLimiter,
+    #[error("Resizing the disk is not supported by this device")]
+    ResizeDiskNotSupported,
+    #[error("Invalid disk size {0}, must be a multiple of 512 bytes")]
+    InvalidDiskSize(u64),
+    #[error("Invalid disk size {0}, shrinking the disk is not supported")]
+    ShrinkDiskNotSupported(u64),
+    #[error("Failed to resize the disk: {0}")]
+    ResizeDisk(::block::async_io::DiskFileError),
+    #[error("Failed to signal config change: {0}")]
+    FailedSignalingConfigChange(io::Error),
 }
 
 #[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
@@ -158,6 +182,98 @@ impl TryInto<rate_limiter::RateLimiter> for RateLimiterConfig {
     }
 }
 
+/// Share of the budget of a rate limit group a device gets when it
+/// competes with the other members of the group.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitShareConfig {
+    #[serde(default = "default_ratelimitshareconfig_weight")]
+    pub weight: u64,
+    /// Guaranteed bandwidth, in bytes per second.
+    #[serde(default)]
+    pub min_bandwidth: u64,
+    /// Guaranteed operations per second.
+    #[serde(default)]
+    pub min_ops: u64,
+}
+
+pub const DEFAULT_RATE_LIMIT_SHARE_WEIGHT: u64 = 1;
+
+fn default_ratelimitshareconfig_weight() -> u64 {
+    DEFAULT_RATE_LIMIT_SHARE_WEIGHT
+}
+
+impl Default for RateLimitShareConfig {
+    fn default() -> Self {
+        RateLimitShareConfig {
+            weight: DEFAULT_RATE_LIMIT_SHARE_WEIGHT,
+            min_bandwidth: 0,
+            min_ops: 0,
+        }
+    }
+}
+
+impl From<RateLimitShareConfig> for rate_limiter::group::HandleShare {
+    fn from(config: RateLimitShareConfig) -> Self {
+        rate_limiter::group::HandleShare {
+            weight: config.weight,
+            min_bytes_rate: config.min_bandwidth,
+            min_ops_rate: config.min_ops,
+        }
+    }
+}
+
+// Time spent throttled by the rate limiters of a device, accumulated over
+// the successive activations of the device.
+#[derive(Clone, Default)]
+pub(crate) struct ThrottledTime {
+    previous: Duration,
+    stats: Vec<Arc<rate_limiter::ThrottleStats>>,
+}
+
+impl ThrottledTime {
+    // Folds the accounting of the rate limiters of the previous activation.
+    pub(crate) fn reset(&mut self) {
+        self.previous += self
+            .stats
+            .drain(..)
+            .map(|stats| stats.throttled_time())
+            .sum::<Duration>();
+    }
+
+    pub(crate) fn add(&mut self, stats: Arc<rate_limiter::ThrottleStats>) {
+        self.stats.push(stats);
+    }
+
+    pub(crate) fn total_us(&self) -> u64 {
+        let current: Duration = self.stats.iter().map(|stats| stats.throttled_time()).sum();
+        (self.previous + current).as_micros() as u64
+    }
+}
+
+impl RateLimiterConfig {
+    /// Translate this configuration into the bucket updates that should be
+    /// applied to an existing rate limiter. A missing or empty bucket
+    /// disables rate limiting for the matching token type.
+    pub fn bucket_updates(&self) -> (rate_limiter::BucketUpdate, rate_limiter::BucketUpdate) {
+        let update = |tb: Option<TokenBucketConfig>| {
+            tb.and_then(|tb| {
+                rate_limiter::TokenBucket::new(
+                    tb.size,
+                    tb.one_time_burst.unwrap_or(0),
+                    tb.refill_time,
+                )
+            })
+            .map_or(
+                rate_limiter::BucketUpdate::Disabled,
+                rate_limiter::BucketUpdate::Update,
+            )
+        };
+
+        (update(self.bandwidth), update(self.ops))
+    }
+}
+
 /// Return the host virtual address corresponding to the given guest address range
 ///
 /// Convert an absolute address into an address space (GuestMemory)
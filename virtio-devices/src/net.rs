@@ -6,7 +6,6 @@
 // found in the THIRD-PARTY file.
 
 use std::collections::HashMap;
-use std::net::Ipv4Addr;
 use std::num::Wrapping;
 use std::ops::Deref;
 use std::os::unix::io::{AsRawFd, RawFd};
@@ -18,9 +17,11 @@ use anyhow::anyhow;
 #[cfg(not(fuzzing))]
 use net_util::virtio_features_to_tap_offload;
 use net_util::{
-    build_net_config_space, build_net_config_space_with_mq, open_tap, CtrlQueue, MacAddr,
-    NetCounters, NetQueuePair, OpenTapError, RxVirtio, Tap, TapError, TxVirtio, VirtioNetConfig,
+    build_net_config_space, build_net_config_space_with_mq, open_tap, CtrlQueue, IpNetwork,
+    MacAddr, NetCounters, NetQueuePair, NetRateLimiter, OpenTapError, RxVirtio, Tap, TapError,
+    TxVirtio, VirtioNetConfig,
 };
+use rate_limiter::group::{HandleShare, RateLimiterGroup};
 use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -40,7 +41,7 @@ use super::{
 };
 use crate::seccomp_filters::Thread;
 use crate::thread_helper::spawn_virtio_thread;
-use crate::{GuestMemoryMmap, VirtioInterrupt};
+use crate::{CountersReader, GuestMemoryMmap, ThrottledTime, VirtioInterrupt};
 
 /// Control queue
 // Event available on the control queue.
@@ -416,6 +417,12 @@ pub struct Net {
     counters: NetCounters,
     seccomp_action: SeccompAction,
     rate_limiter_config: Option<RateLimiterConfig>,
+    rate_limiters: Vec<Arc<rate_limiter::RateLimiter>>,
+    rx_rate_limit_group: Option<Arc<RateLimiterGroup>>,
+    tx_rate_limit_group: Option<Arc<RateLimiterGroup>>,
+    rate_limit_share: HandleShare,
+    rx_throttled_time: ThrottledTime,
+    tx_throttled_time: ThrottledTime,
     exit_evt: EventFd,
 }
 
@@ -439,6 +446,9 @@ impl Net {
         queue_size: u16,
         seccomp_action: SeccompAction,
         rate_limiter_config: Option<RateLimiterConfig>,
+        rx_rate_limit_group: Option<Arc<RateLimiterGroup>>,
+        tx_rate_limit_group: Option<Arc<RateLimiterGroup>>,
+        rate_limit_share: HandleShare,
         exit_evt: EventFd,
         state: Option<NetState>,
         offload_tso: bool,
@@ -537,18 +547,23 @@ impl Net {
             counters: NetCounters::default(),
             seccomp_action,
             rate_limiter_config,
+            rate_limiters: Vec::new(),
+            rx_rate_limit_group,
+            tx_rate_limit_group,
+            rate_limit_share,
+            rx_throttled_time: ThrottledTime::default(),
+            tx_throttled_time: ThrottledTime::default(),
             exit_evt,
         })
     }
 
-    /// Create a new virtio network device with the given IP address and
-    /// netmask.
+    /// Create a new virtio network device with the given host-side IP
+    /// addresses.
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: String,
         if_name: Option<&str>,
-        ip_addr: Option<Ipv4Addr>,
-        netmask: Option<Ipv4Addr>,
+        ip_addrs: &[IpNetwork],
         guest_mac: Option<MacAddr>,
         host_mac: &mut Option<MacAddr>,
         mtu: Option<u16>,
@@ -557,22 +572,17 @@ impl Net {
         queue_size: u16,
         seccomp_action: SeccompAction,
         rate_limiter_config: Option<RateLimiterConfig>,
+        rx_rate_limit_group: Option<Arc<RateLimiterGroup>>,
+        tx_rate_limit_group: Option<Arc<RateLimiterGroup>>,
+        rate_limit_share: HandleShare,
         exit_evt: EventFd,
         state: Option<NetState>,
         offload_tso: bool,
         offload_ufo: bool,
         offload_csum: bool,
     ) -> Result<Self> {
-        let taps = open_tap(
-            if_name,
-            ip_addr,
-            netmask,
-            host_mac,
-            mtu,
-            num_queues / 2,
-            None,
-        )
-        .map_err(Error::OpenTap)?;
+        let taps = open_tap(if_name, ip_addrs, host_mac, mtu, num_queues / 2, None)
+            .map_err(Error::OpenTap)?;
 
         Self::new_with_tap(
             id,
@@ -583,6 +593,9 @@ impl Net {
             queue_size,
             seccomp_action,
             rate_limiter_config,
+            rx_rate_limit_group,
+            tx_rate_limit_group,
+            rate_limit_share,
             exit_evt,
             state,
             offload_tso,
@@ -601,6 +614,9 @@ impl Net {
         queue_size: u16,
         seccomp_action: SeccompAction,
         rate_limiter_config: Option<RateLimiterConfig>,
+        rx_rate_limit_group: Option<Arc<RateLimiterGroup>>,
+        tx_rate_limit_group: Option<Arc<RateLimiterGroup>>,
+        rate_limit_share: HandleShare,
         exit_evt: EventFd,
         state: Option<NetState>,
         offload_tso: bool,
@@ -636,6 +652,9 @@ impl Net {
             queue_size,
             seccomp_action,
             rate_limiter_config,
+            rx_rate_limit_group,
+            tx_rate_limit_group,
+            rate_limit_share,
             exit_evt,
             state,
             offload_tso,
@@ -644,6 +663,31 @@ impl Net {
         )
     }
 
+    fn new_rate_limiter(
+        &mut self,
+        rate_limit_group: Option<Arc<RateLimiterGroup>>,
+    ) -> result::Result<Option<NetRateLimiter>, ActivateError> {
+        if let Some(rate_limit_group) = rate_limit_group {
+            return rate_limit_group
+                .new_handle_with_share(self.rate_limit_share)
+                .map(|handle| Some(NetRateLimiter::Group(handle)))
+                .map_err(ActivateError::CreateRateLimiterGroupHandle);
+        }
+
+        let rate_limiter: Option<Arc<rate_limiter::RateLimiter>> = self
+            .rate_limiter_config
+            .map(RateLimiterConfig::try_into)
+            .transpose()
+            .map_err(ActivateError::CreateRateLimiter)?
+            .map(Arc::new);
+
+        // Keep a reference to each limiter so they can be updated while
+        // the queue handlers are running.
+        self.rate_limiters.extend(rate_limiter.clone());
+
+        Ok(rate_limiter.map(NetRateLimiter::Queue))
+    }
+
     fn state(&self) -> NetState {
         NetState {
             avail_features: self.common.avail_features,
@@ -746,6 +790,9 @@ impl VirtioDevice for Net {
 
         let mut epoll_threads = Vec::new();
         let mut taps = self.taps.clone();
+        self.rate_limiters.clear();
+        self.rx_throttled_time.reset();
+        self.tx_throttled_time.reset();
         for i in 0..queues.len() / 2 {
             let rx = RxVirtio::new();
             let tx = TxVirtio::new();
@@ -761,17 +808,14 @@ impl VirtioDevice for Net {
 
             let (kill_evt, pause_evt) = self.common.dup_eventfds();
 
-            let rx_rate_limiter: Option<rate_limiter::RateLimiter> = self
-                .rate_limiter_config
-                .map(RateLimiterConfig::try_into)
-                .transpose()
-                .map_err(ActivateError::CreateRateLimiter)?;
-
-            let tx_rate_limiter: Option<rate_limiter::RateLimiter> = self
-                .rate_limiter_config
-                .map(RateLimiterConfig::try_into)
-                .transpose()
-                .map_err(ActivateError::CreateRateLimiter)?;
+            let rx_rate_limiter = self.new_rate_limiter(self.rx_rate_limit_group.clone())?;
+            let tx_rate_limiter = self.new_rate_limiter(self.tx_rate_limit_group.clone())?;
+            if let Some(rate_limiter) = &rx_rate_limiter {
+                self.rx_throttled_time.add(rate_limiter.throttle_stats());
+            }
+            if let Some(rate_limiter) = &tx_rate_limiter {
+                self.tx_throttled_time.add(rate_limiter.throttle_stats());
+            }
 
             let tap = taps.remove(0);
             #[cfg(not(fuzzing))]
@@ -833,32 +877,62 @@ impl VirtioDevice for Net {
         result
     }
 
-    fn counters(&self) -> Option<HashMap<&'static str, Wrapping<u64>>> {
-        let mut counters = HashMap::new();
-
-        counters.insert(
-            "rx_bytes",
-            Wrapping(self.counters.rx_bytes.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "rx_frames",
-            Wrapping(self.counters.rx_frames.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "tx_bytes",
-            Wrapping(self.counters.tx_bytes.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "tx_frames",
-            Wrapping(self.counters.tx_frames.load(Ordering::Acquire)),
-        );
-
-        Some(counters)
+    fn counters_reader(&self) -> Option<CountersReader> {
+        let stats = self.counters.clone();
+        let rx_throttled_time = (self.rate_limiter_config.is_some()
+            || self.rx_rate_limit_group.is_some())
+        .then(|| self.rx_throttled_time.clone());
+        let tx_throttled_time = (self.rate_limiter_config.is_some()
+            || self.tx_rate_limit_group.is_some())
+        .then(|| self.tx_throttled_time.clone());
+
+        Some(Arc::new(move || {
+            let mut counters = HashMap::new();
+
+            counters.insert("rx_bytes", Wrapping(stats.rx_bytes.load(Ordering::Acquire)));
+            counters.insert(
+                "rx_frames",
+                Wrapping(stats.rx_frames.load(Ordering::Acquire)),
+            );
+            counters.insert("tx_bytes", Wrapping(stats.tx_bytes.load(Ordering::Acquire)));
+            counters.insert(
+                "tx_frames",
+                Wrapping(stats.tx_frames.load(Ordering::Acquire)),
+            );
+            if let Some(throttled_time) = &rx_throttled_time {
+                counters.insert("rx_throttled_time_us", Wrapping(throttled_time.total_us()));
+            }
+            if let Some(throttled_time) = &tx_throttled_time {
+                counters.insert("tx_throttled_time_us", Wrapping(throttled_time.total_us()));
+            }
+
+            counters
+        }))
     }
 
     fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
         self.common.set_access_platform(access_platform)
     }
+
+    fn update_rate_limiter(
+        &mut self,
+        config: RateLimiterConfig,
+    ) -> result::Result<(), DeviceError> {
+        // Rate limiting can't be enabled on the fly since the queue handlers
+        // only monitor the limiter events when created with one.
+        if self.rate_limiter_config.is_none() {
+            return Err(DeviceError::NoRateLimiter);
+        }
+
+        let (bytes, ops) = config.bucket_updates();
+        for rate_limiter in self.rate_limiters.iter() {
+            rate_limiter.update_buckets(bytes.clone(), ops.clone());
+        }
+        // Limiters created on the next activation pick up the new values.
+        self.rate_limiter_config = Some(config);
+
+        Ok(())
+    }
 }
 
 impl Pausable for Net {
@@ -29,7 +29,8 @@ use vmm_sys_util::eventfd::EventFd;
 use super::{
     ActivateError, ActivateResult, EpollHelper, EpollHelperError, EpollHelperHandler,
     Error as DeviceError, UserspaceMapping, VirtioCommon, VirtioDevice, VirtioDeviceType,
-    EPOLL_HELPER_EVENT_LAST, VIRTIO_F_IOMMU_PLATFORM, VIRTIO_F_VERSION_1,
+    VirtioSharedMemory, VirtioSharedMemoryList, EPOLL_HELPER_EVENT_LAST, VIRTIO_F_IOMMU_PLATFORM,
+    VIRTIO_F_VERSION_1,
 };
 use crate::seccomp_filters::Thread;
 use crate::thread_helper::spawn_virtio_thread;
@@ -38,6 +39,9 @@ use crate::{GuestMemoryMmap, MmapRegion, VirtioInterrupt, VirtioInterruptType};
 const QUEUE_SIZE: u16 = 256;
 const QUEUE_SIZES: &[u16] = &[QUEUE_SIZE];
 
+// Feature bits
+const VIRTIO_PMEM_F_SHMEM_REGION: u64 = 0;
+
 const VIRTIO_PMEM_REQ_TYPE_FLUSH: u32 = 0;
 const VIRTIO_PMEM_RESP_TYPE_OK: u32 = 0;
 const VIRTIO_PMEM_RESP_TYPE_EIO: u32 = 1;
@@ -153,6 +157,7 @@ struct PmemEpollHandler {
     mem: GuestMemoryAtomic<GuestMemoryMmap>,
     queue: Queue,
     disk: File,
+    discard_writes: bool,
     interrupt_cb: Arc<dyn VirtioInterrupt>,
     queue_evt: EventFd,
     kill_evt: EventFd,
@@ -166,11 +171,17 @@ impl PmemEpollHandler {
         while let Some(mut desc_chain) = self.queue.pop_descriptor_chain(self.mem.memory()) {
             let len = match Request::parse(&mut desc_chain, self.access_platform.as_ref()) {
                 Ok(ref req) if (req.type_ == RequestType::Flush) => {
-                    let status_code = match self.disk.sync_all() {
-                        Ok(()) => VIRTIO_PMEM_RESP_TYPE_OK,
-                        Err(e) => {
-                            error!("failed flushing disk image: {}", e);
-                            VIRTIO_PMEM_RESP_TYPE_EIO
+                    // Guest writes only land in private copy-on-write pages
+                    // when discarding writes, there is nothing to persist.
+                    let status_code = if self.discard_writes {
+                        VIRTIO_PMEM_RESP_TYPE_OK
+                    } else {
+                        match self.disk.sync_all() {
+                            Ok(()) => VIRTIO_PMEM_RESP_TYPE_OK,
+                            Err(e) => {
+                                error!("failed flushing disk image: {}", e);
+                                VIRTIO_PMEM_RESP_TYPE_EIO
+                            }
                         }
                     };
 
@@ -266,6 +277,7 @@ pub struct Pmem {
     common: VirtioCommon,
     id: String,
     disk: Option<File>,
+    discard_writes: bool,
     config: VirtioPmemConfig,
     mapping: UserspaceMapping,
     seccomp_action: SeccompAction,
@@ -291,6 +303,8 @@ impl Pmem {
         addr: GuestAddress,
         mapping: UserspaceMapping,
         _region: MmapRegion,
+        discard_writes: bool,
+        shmem_region: bool,
         iommu: bool,
         seccomp_action: SeccompAction,
         exit_evt: EventFd,
@@ -312,6 +326,10 @@ impl Pmem {
 
             let mut avail_features = 1u64 << VIRTIO_F_VERSION_1;
 
+            if shmem_region {
+                avail_features |= 1u64 << VIRTIO_PMEM_F_SHMEM_REGION;
+            }
+
             if iommu {
                 avail_features |= 1u64 << VIRTIO_F_IOMMU_PLATFORM;
             }
@@ -331,6 +349,7 @@ impl Pmem {
             },
             id,
             disk: Some(disk),
+            discard_writes,
             config,
             mapping,
             seccomp_action,
@@ -404,6 +423,7 @@ impl VirtioDevice for Pmem {
                 mem,
                 queue,
                 disk,
+                discard_writes: self.discard_writes,
                 interrupt_cb,
                 queue_evt,
                 kill_evt,
@@ -438,6 +458,36 @@ impl VirtioDevice for Pmem {
         result
     }
 
+    fn get_shm_regions(&self) -> Option<VirtioSharedMemoryList> {
+        if self.common.avail_features & (1u64 << VIRTIO_PMEM_F_SHMEM_REGION) == 0 {
+            return None;
+        }
+
+        // The whole mapping is exposed through a single region, whose index
+        // in the list is the shared memory region ID.
+        Some(VirtioSharedMemoryList {
+            host_addr: self.mapping.host_addr,
+            mem_slot: self.mapping.mem_slot,
+            addr: self.mapping.addr,
+            len: self.mapping.len,
+            region_list: vec![VirtioSharedMemory {
+                offset: 0,
+                len: self.mapping.len,
+            }],
+        })
+    }
+
+    fn set_shm_regions(
+        &mut self,
+        shm_regions: VirtioSharedMemoryList,
+    ) -> std::result::Result<(), crate::Error> {
+        // The guest moved the BAR holding the shared memory region, keep the
+        // legacy configuration fields in sync for drivers ignoring it.
+        self.mapping.addr = shm_regions.addr;
+        self.config.start = shm_regions.addr.raw_value().to_le();
+        Ok(())
+    }
+
     fn userspace_mappings(&self) -> Vec<UserspaceMapping> {
         vec![self.mapping.clone()]
     }
@@ -469,3 +519,135 @@ impl Snapshottable for Pmem {
 
 impl Transportable for Pmem {}
 impl Migratable for Pmem {}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+
+    use virtio_bindings::virtio_ring::{VRING_DESC_F_NEXT, VRING_DESC_F_WRITE};
+    use vm_virtio::queue::testing::VirtQueue as GuestQ;
+
+    use super::*;
+    use crate::vsock::tests::NoopVirtioInterrupt;
+
+    const MEM_SIZE: usize = 0x100_0000;
+    const QUEUE_ADDR: u64 = 0x10_0000;
+    const BUFFER_ADDR: u64 = 0x20_0000;
+    const PMEM_ADDR: u64 = 0x1_0000_0000;
+    const PMEM_SIZE: u64 = 2 << 20;
+
+    // Syncing /dev/null fails, telling whether the device flushed the disk
+    fn disk() -> File {
+        OpenOptions::new().read(true).open("/dev/null").unwrap()
+    }
+
+    fn create_pmem(shmem_region: bool) -> Pmem {
+        Pmem::new(
+            String::from("pmem"),
+            disk(),
+            GuestAddress(PMEM_ADDR),
+            UserspaceMapping {
+                host_addr: 0,
+                mem_slot: 0,
+                addr: GuestAddress(PMEM_ADDR),
+                len: PMEM_SIZE,
+                mergeable: false,
+            },
+            MmapRegion::new(PMEM_SIZE as usize).unwrap(),
+            false,
+            shmem_region,
+            false,
+            SeccompAction::Trap,
+            EventFd::new(0).unwrap(),
+            None,
+        )
+        .unwrap()
+    }
+
+    fn read_config(pmem: &Pmem) -> VirtioPmemConfig {
+        let mut config = VirtioPmemConfig::default();
+        pmem.read_config(0, config.as_mut_slice());
+        config
+    }
+
+    // Process a single flush request and return the status of the response
+    fn flush(discard_writes: bool) -> u32 {
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, QUEUE_SIZE);
+
+        let req = VirtioPmemReq {
+            type_: VIRTIO_PMEM_REQ_TYPE_FLUSH,
+        };
+        mem.write_obj(req, GuestAddress(BUFFER_ADDR)).unwrap();
+        mem.write_obj(0xffu32, GuestAddress(BUFFER_ADDR + 0x100))
+            .unwrap();
+        guest_q.dtable[0].set(
+            BUFFER_ADDR,
+            size_of::<VirtioPmemReq>() as u32,
+            VRING_DESC_F_NEXT.try_into().unwrap(),
+            1,
+        );
+        guest_q.dtable[1].set(
+            BUFFER_ADDR + 0x100,
+            size_of::<VirtioPmemResp>() as u32,
+            VRING_DESC_F_WRITE.try_into().unwrap(),
+            0,
+        );
+        guest_q.avail.ring[0].set(0);
+        guest_q.avail.idx.set(1);
+
+        let mut handler = PmemEpollHandler {
+            mem: GuestMemoryAtomic::new(mem.clone()),
+            queue: guest_q.create_queue(),
+            disk: disk(),
+            discard_writes,
+            interrupt_cb: Arc::new(NoopVirtioInterrupt {}),
+            queue_evt: EventFd::new(0).unwrap(),
+            kill_evt: EventFd::new(0).unwrap(),
+            pause_evt: EventFd::new(0).unwrap(),
+            access_platform: None,
+        };
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 1);
+
+        mem.read_obj(GuestAddress(BUFFER_ADDR + 0x100)).unwrap()
+    }
+
+    #[test]
+    fn test_pmem_flush() {
+        assert_eq!(flush(false), VIRTIO_PMEM_RESP_TYPE_EIO);
+        // Nothing is persisted when guest writes are discarded
+        assert_eq!(flush(true), VIRTIO_PMEM_RESP_TYPE_OK);
+    }
+
+    #[test]
+    fn test_pmem_shmem_region() {
+        let pmem = create_pmem(false);
+        assert_eq!(pmem.features() & (1u64 << VIRTIO_PMEM_F_SHMEM_REGION), 0);
+        assert!(pmem.get_shm_regions().is_none());
+
+        let mut pmem = create_pmem(true);
+        assert_ne!(pmem.features() & (1u64 << VIRTIO_PMEM_F_SHMEM_REGION), 0);
+        let shm_regions = pmem.get_shm_regions().unwrap();
+        assert_eq!(shm_regions.addr, GuestAddress(PMEM_ADDR));
+        assert_eq!(shm_regions.len, PMEM_SIZE);
+        assert_eq!(shm_regions.region_list.len(), 1);
+        assert_eq!(shm_regions.region_list[0].offset, 0);
+        assert_eq!(shm_regions.region_list[0].len, PMEM_SIZE);
+
+        let config = read_config(&pmem);
+        assert_eq!(config.start, PMEM_ADDR);
+        assert_eq!(config.size, PMEM_SIZE);
+
+        // Moving the BAR moves the legacy configuration along
+        let new_addr = GuestAddress(2 * PMEM_ADDR);
+        pmem.set_shm_regions(VirtioSharedMemoryList {
+            addr: new_addr,
+            ..shm_regions
+        })
+        .unwrap();
+        assert_eq!(pmem.get_shm_regions().unwrap().addr, new_addr);
+        assert_eq!(read_config(&pmem).start, new_addr.raw_value());
+        assert_eq!(pmem.userspace_mappings()[0].addr, new_addr);
+    }
+}
@@ -4,36 +4,53 @@
 //
 // SPDX-License-Identifier: Apache-2.0 AND BSD-3-Clause
 
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::{Read, Write};
+use std::num::Wrapping;
 use std::os::unix::io::AsRawFd;
-use std::sync::atomic::AtomicBool;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Barrier};
 use std::{io, result};
 
 use anyhow::anyhow;
+use rate_limiter::{RateLimiter, TokenType};
 use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use virtio_queue::{Queue, QueueT};
-use vm_memory::{GuestAddressSpace, GuestMemory, GuestMemoryAtomic};
+use virtio_queue::{Queue, QueueOwnedT, QueueT};
+use vm_memory::{Address, Bytes, GuestAddress, GuestAddressSpace, GuestMemory, GuestMemoryAtomic};
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vm_virtio::{AccessPlatform, Translatable};
 use vmm_sys_util::eventfd::EventFd;
 
 use super::{
     ActivateError, ActivateResult, EpollHelper, EpollHelperError, EpollHelperHandler,
-    Error as DeviceError, VirtioCommon, VirtioDevice, VirtioDeviceType, EPOLL_HELPER_EVENT_LAST,
-    VIRTIO_F_IOMMU_PLATFORM, VIRTIO_F_VERSION_1,
+    Error as DeviceError, RateLimiterConfig, ThrottledTime, VirtioCommon, VirtioDevice,
+    VirtioDeviceType, EPOLL_HELPER_EVENT_LAST, VIRTIO_F_IOMMU_PLATFORM, VIRTIO_F_VERSION_1,
 };
 use crate::seccomp_filters::Thread;
 use crate::thread_helper::spawn_virtio_thread;
-use crate::{GuestMemoryMmap, VirtioInterrupt, VirtioInterruptType};
+use crate::{CountersReader, GuestMemoryMmap, VirtioInterrupt, VirtioInterruptType};
 
 const QUEUE_SIZE: u16 = 256;
 const QUEUE_SIZES: &[u16] = &[QUEUE_SIZE];
 
 // New descriptors are pending on the virtio queue.
 const QUEUE_AVAIL_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 1;
+// The rate limiter has been replenished.
+const RATE_LIMITER_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 2;
+
+/// Prefix of a source designating the UNIX socket of an entropy gathering
+/// daemon rather than a file.
+pub const EGD_SOURCE_PREFIX: &str = "egd:";
+
+// Blocking read command of the EGD protocol, followed by the number of bytes
+// requested, at most 255.
+const EGD_CMD_READ_BLOCKING: u8 = 0x02;
+const EGD_MAX_READ: usize = 255;
 
 #[derive(Error, Debug)]
 enum Error {
@@ -47,15 +64,119 @@ enum Error {
     QueueAddUsed(virtio_queue::Error),
 }
 
+/// Host source the entropy is read from.
+enum EntropySource {
+    File(File),
+    Egd(EgdSource),
+}
+
+impl EntropySource {
+    fn open(path: &str) -> io::Result<Self> {
+        if let Some(socket) = path.strip_prefix(EGD_SOURCE_PREFIX) {
+            Ok(EntropySource::Egd(EgdSource {
+                socket: PathBuf::from(socket),
+                stream: Some(UnixStream::connect(socket)?),
+            }))
+        } else {
+            Ok(EntropySource::File(File::open(path)?))
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            EntropySource::File(file) => EntropySource::File(file.try_clone()?),
+            EntropySource::Egd(egd) => EntropySource::Egd(EgdSource {
+                socket: egd.socket.clone(),
+                stream: egd.stream.as_ref().map(UnixStream::try_clone).transpose()?,
+            }),
+        })
+    }
+}
+
+/// Connection to an entropy gathering daemon, re-established after the daemon
+/// went away so that a restart does not take the device down.
+struct EgdSource {
+    socket: PathBuf,
+    stream: Option<UnixStream>,
+}
+
+impl EgdSource {
+    // Fill `len` bytes of guest memory at `addr` with entropy from the daemon,
+    // one protocol sized chunk at a time. Nothing is provided while the daemon
+    // is unreachable, in which case 0 is returned.
+    fn fill(
+        &mut self,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        len: usize,
+    ) -> result::Result<usize, Error> {
+        if self.stream.is_none() {
+            match UnixStream::connect(&self.socket) {
+                Ok(stream) => {
+                    info!("Reconnected to the entropy daemon {:?}", self.socket);
+                    self.stream = Some(stream);
+                }
+                Err(e) => {
+                    debug!(
+                        "Cannot reconnect to the entropy daemon {:?}: {}",
+                        self.socket, e
+                    );
+                    return Ok(0);
+                }
+            }
+        }
+        let stream = self.stream.as_mut().unwrap();
+
+        let mut chunk = [0u8; EGD_MAX_READ];
+        let mut offset = 0;
+        while offset < len {
+            let size = std::cmp::min(len - offset, EGD_MAX_READ);
+            if let Err(e) = egd_read(stream, &mut chunk[..size]) {
+                warn!(
+                    "Lost the connection to the entropy daemon {:?}: {}",
+                    self.socket, e
+                );
+                self.stream = None;
+                return Ok(0);
+            }
+            let chunk_addr = addr
+                .checked_add(offset as u64)
+                .ok_or(Error::InvalidDescriptor)?;
+            mem.write_slice(&chunk[..size], chunk_addr)
+                .map_err(Error::GuestMemoryWrite)?;
+            offset += size;
+        }
+        Ok(len)
+    }
+}
+
+// Fill the buffer with entropy from the daemon, asking for at most 255 bytes
+// at a time as mandated by the protocol.
+fn egd_read(stream: &mut UnixStream, buf: &mut [u8]) -> io::Result<()> {
+    for chunk in buf.chunks_mut(EGD_MAX_READ) {
+        stream.write_all(&[EGD_CMD_READ_BLOCKING, chunk.len() as u8])?;
+        stream.read_exact(chunk)?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct RngCounters {
+    bytes: AtomicU64,
+    requests: AtomicU64,
+}
+
 struct RngEpollHandler {
     mem: GuestMemoryAtomic<GuestMemoryMmap>,
     queue: Queue,
-    random_file: File,
+    source: EntropySource,
     interrupt_cb: Arc<dyn VirtioInterrupt>,
     queue_evt: EventFd,
     kill_evt: EventFd,
     pause_evt: EventFd,
     access_platform: Option<Arc<dyn AccessPlatform>>,
+    rate_limiter: Option<RateLimiter>,
+    counters: Arc<RngCounters>,
 }
 
 impl RngEpollHandler {
@@ -71,16 +192,44 @@ impl RngEpollHandler {
                 return Err(Error::InvalidDescriptor);
             }
 
-            // Fill the read with data from the random device on the host.
-            let len = desc_chain
-                .memory()
-                .read_volatile_from(
-                    desc.addr()
-                        .translate_gva(self.access_platform.as_ref(), desc.len() as usize),
-                    &mut self.random_file,
-                    desc.len() as usize,
-                )
-                .map_err(Error::GuestMemoryWrite)?;
+            if let Some(rate_limiter) = &mut self.rate_limiter {
+                // If limiter.consume() fails it means there is no more TokenType::Ops
+                // budget and rate limiting is in effect.
+                if !rate_limiter.consume(1, TokenType::Ops) {
+                    // Stop processing the queue and return this descriptor chain to the
+                    // avail ring, for later processing.
+                    queue.go_to_previous_position();
+                    break;
+                }
+                // If limiter.consume() fails it means there is no more TokenType::Bytes
+                // budget and rate limiting is in effect.
+                if !rate_limiter.consume(desc.len() as u64, TokenType::Bytes) {
+                    // Revert the OPS consume().
+                    rate_limiter.manual_replenish(1, TokenType::Ops);
+                    // Stop processing the queue and return this descriptor chain to the
+                    // avail ring, for later processing.
+                    queue.go_to_previous_position();
+                    break;
+                }
+            }
+
+            let addr = desc
+                .addr()
+                .translate_gva(self.access_platform.as_ref(), desc.len() as usize);
+
+            // Fill the read with data from the entropy source on the host.
+            let len = match &mut self.source {
+                EntropySource::File(file) => desc_chain
+                    .memory()
+                    .read_volatile_from(addr, file, desc.len() as usize)
+                    .map_err(Error::GuestMemoryWrite)?,
+                EntropySource::Egd(egd) => {
+                    egd.fill(desc_chain.memory(), addr, desc.len() as usize)?
+                }
+            };
+
+            self.counters.bytes.fetch_add(len as u64, Ordering::AcqRel);
+            self.counters.requests.fetch_add(1, Ordering::AcqRel);
 
             queue
                 .add_used(desc_chain.memory(), desc_chain.head_index(), len as u32)
@@ -91,6 +240,18 @@ impl RngEpollHandler {
         Ok(used_descs)
     }
 
+    fn process_queue_and_signal(&mut self) -> result::Result<(), EpollHelperError> {
+        let needs_notification = self.process_queue().map_err(|e| {
+            EpollHelperError::HandleEvent(anyhow!("Failed to process queue : {:?}", e))
+        })?;
+        if needs_notification {
+            self.signal_used_queue().map_err(|e| {
+                EpollHelperError::HandleEvent(anyhow!("Failed to signal used queue: {:?}", e))
+            })?;
+        }
+        Ok(())
+    }
+
     fn signal_used_queue(&self) -> result::Result<(), DeviceError> {
         self.interrupt_cb
             .trigger(VirtioInterruptType::Queue(0))
@@ -107,6 +268,9 @@ impl RngEpollHandler {
     ) -> result::Result<(), EpollHelperError> {
         let mut helper = EpollHelper::new(&self.kill_evt, &self.pause_evt)?;
         helper.add_event(self.queue_evt.as_raw_fd(), QUEUE_AVAIL_EVENT)?;
+        if let Some(rate_limiter) = &self.rate_limiter {
+            helper.add_event(rate_limiter.as_raw_fd(), RATE_LIMITER_EVENT)?;
+        }
         helper.run(paused, paused_sync, self)?;
 
         Ok(())
@@ -125,16 +289,24 @@ impl EpollHelperHandler for RngEpollHandler {
                 self.queue_evt.read().map_err(|e| {
                     EpollHelperError::HandleEvent(anyhow!("Failed to get queue event: {:?}", e))
                 })?;
-                let needs_notification = self.process_queue().map_err(|e| {
-                    EpollHelperError::HandleEvent(anyhow!("Failed to process queue : {:?}", e))
-                })?;
-                if needs_notification {
-                    self.signal_used_queue().map_err(|e| {
+                self.process_queue_and_signal()?;
+            }
+            RATE_LIMITER_EVENT => {
+                if let Some(rate_limiter) = &mut self.rate_limiter {
+                    // Upon rate limiter event, call the rate limiter handler
+                    // and restart processing the queue.
+                    rate_limiter.event_handler().map_err(|e| {
                         EpollHelperError::HandleEvent(anyhow!(
-                            "Failed to signal used queue: {:?}",
+                            "Failed to process rate limiter event: {:?}",
                             e
                         ))
                     })?;
+
+                    self.process_queue_and_signal()?;
+                } else {
+                    return Err(EpollHelperError::HandleEvent(anyhow!(
+                        "Unexpected 'RATE_LIMITER_EVENT' when rate_limiter is not enabled."
+                    )));
                 }
             }
             _ => {
@@ -152,7 +324,10 @@ impl EpollHelperHandler for RngEpollHandler {
 pub struct Rng {
     common: VirtioCommon,
     id: String,
-    random_file: Option<File>,
+    source: EntropySource,
+    rate_limiter_config: Option<RateLimiterConfig>,
+    throttled_time: ThrottledTime,
+    counters: Arc<RngCounters>,
     seccomp_action: SeccompAction,
     exit_evt: EventFd,
 }
@@ -164,16 +339,19 @@ pub struct RngState {
 }
 
 impl Rng {
-    /// Create a new virtio rng device that gets random data from /dev/urandom.
+    /// Create a new virtio rng device that gets random data from the file at
+    /// `path`, or from an entropy gathering daemon if `path` is the UNIX
+    /// socket of the daemon prefixed with `egd:`.
     pub fn new(
         id: String,
         path: &str,
+        rate_limiter_config: Option<RateLimiterConfig>,
         iommu: bool,
         seccomp_action: SeccompAction,
         exit_evt: EventFd,
         state: Option<RngState>,
     ) -> io::Result<Rng> {
-        let random_file = File::open(path)?;
+        let source = EntropySource::open(path)?;
 
         let (avail_features, acked_features, paused) = if let Some(state) = state {
             info!("Restoring virtio-rng {}", id);
@@ -200,7 +378,10 @@ impl Rng {
                 ..Default::default()
             },
             id,
-            random_file: Some(random_file),
+            source,
+            rate_limiter_config,
+            throttled_time: ThrottledTime::default(),
+            counters: Arc::new(RngCounters::default()),
             seccomp_action,
             exit_evt,
         })
@@ -255,43 +436,52 @@ impl VirtioDevice for Rng {
         self.common.activate(&queues, &interrupt_cb)?;
         let (kill_evt, pause_evt) = self.common.dup_eventfds();
 
-        if let Some(file) = self.random_file.as_ref() {
-            let random_file = file.try_clone().map_err(|e| {
-                error!("failed cloning rng source: {}", e);
-                ActivateError::BadActivate
-            })?;
+        let source = self.source.try_clone().map_err(|e| {
+            error!("failed cloning rng source: {}", e);
+            ActivateError::BadActivate
+        })?;
+
+        let rate_limiter: Option<RateLimiter> = self
+            .rate_limiter_config
+            .map(RateLimiterConfig::try_into)
+            .transpose()
+            .map_err(ActivateError::CreateRateLimiter)?;
+        self.throttled_time.reset();
+        if let Some(rate_limiter) = &rate_limiter {
+            self.throttled_time.add(rate_limiter.throttle_stats());
+        }
 
-            let (_, queue, queue_evt) = queues.remove(0);
-
-            let mut handler = RngEpollHandler {
-                mem,
-                queue,
-                random_file,
-                interrupt_cb,
-                queue_evt,
-                kill_evt,
-                pause_evt,
-                access_platform: self.common.access_platform.clone(),
-            };
+        let (_, queue, queue_evt) = queues.remove(0);
+
+        let mut handler = RngEpollHandler {
+            mem,
+            queue,
+            source,
+            interrupt_cb,
+            queue_evt,
+            kill_evt,
+            pause_evt,
+            access_platform: self.common.access_platform.clone(),
+            rate_limiter,
+            counters: self.counters.clone(),
+        };
 
-            let paused = self.common.paused.clone();
-            let paused_sync = self.common.paused_sync.clone();
-            let mut epoll_threads = Vec::new();
-            spawn_virtio_thread(
-                &self.id,
-                &self.seccomp_action,
-                Thread::VirtioRng,
-                &mut epoll_threads,
-                &self.exit_evt,
-                move || handler.run(paused, paused_sync.unwrap()),
-            )?;
-
-            self.common.epoll_threads = Some(epoll_threads);
-
-            event!("virtio-device", "activated", "id", &self.id);
-            return Ok(());
-        }
-        Err(ActivateError::BadActivate)
+        let paused = self.common.paused.clone();
+        let paused_sync = self.common.paused_sync.clone();
+        let mut epoll_threads = Vec::new();
+        spawn_virtio_thread(
+            &self.id,
+            &self.seccomp_action,
+            Thread::VirtioRng,
+            &mut epoll_threads,
+            &self.exit_evt,
+            move || handler.run(paused, paused_sync.unwrap()),
+        )?;
+
+        self.common.epoll_threads = Some(epoll_threads);
+
+        event!("virtio-device", "activated", "id", &self.id);
+        Ok(())
     }
 
     fn reset(&mut self) -> Option<Arc<dyn VirtioInterrupt>> {
@@ -300,6 +490,26 @@ impl VirtioDevice for Rng {
         result
     }
 
+    fn counters_reader(&self) -> Option<CountersReader> {
+        let stats = self.counters.clone();
+        let throttled_time = self
+            .rate_limiter_config
+            .is_some()
+            .then(|| self.throttled_time.clone());
+
+        Some(Arc::new(move || {
+            let mut counters = HashMap::new();
+
+            counters.insert("bytes", Wrapping(stats.bytes.load(Ordering::Acquire)));
+            counters.insert("requests", Wrapping(stats.requests.load(Ordering::Acquire)));
+            if let Some(throttled_time) = &throttled_time {
+                counters.insert("throttled_time_us", Wrapping(throttled_time.total_us()));
+            }
+
+            counters
+        }))
+    }
+
     fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
         self.common.set_access_platform(access_platform)
     }
@@ -327,3 +537,271 @@ impl Snapshottable for Rng {
 
 impl Transportable for Rng {}
 impl Migratable for Rng {}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use virtio_bindings::virtio_ring::VRING_DESC_F_WRITE;
+    use vm_virtio::queue::testing::VirtQueue as GuestQ;
+    use vmm_sys_util::tempdir::TempDir;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+    use crate::vsock::tests::NoopVirtioInterrupt;
+    use crate::TokenBucketConfig;
+
+    const MEM_SIZE: usize = 0x10_0000;
+    const QUEUE_ADDR: u64 = 0x1000;
+    const BUFFER_ADDR: u64 = 0x8_0000;
+
+    // Answer the blocking read commands of the EGD protocol with bytes of the
+    // given value, returning the sizes requested once the stream is closed.
+    fn egd_daemon(mut stream: UnixStream, value: u8) -> Vec<usize> {
+        let mut requests = Vec::new();
+        let mut cmd = [0u8; 2];
+        while stream.read_exact(&mut cmd).is_ok() {
+            assert_eq!(cmd[0], EGD_CMD_READ_BLOCKING);
+            requests.push(cmd[1] as usize);
+            stream.write_all(&vec![value; cmd[1] as usize]).unwrap();
+        }
+        requests
+    }
+
+    fn create_handler(
+        mem: &GuestMemoryMmap,
+        queue: Queue,
+        source: EntropySource,
+        rate_limiter: Option<RateLimiterConfig>,
+    ) -> RngEpollHandler {
+        RngEpollHandler {
+            mem: GuestMemoryAtomic::new(mem.clone()),
+            queue,
+            source,
+            interrupt_cb: Arc::new(NoopVirtioInterrupt {}),
+            queue_evt: EventFd::new(0).unwrap(),
+            kill_evt: EventFd::new(0).unwrap(),
+            pause_evt: EventFd::new(0).unwrap(),
+            access_platform: None,
+            rate_limiter: rate_limiter.map(|config| config.try_into().unwrap()),
+            counters: Arc::new(RngCounters::default()),
+        }
+    }
+
+    // Make the given number of write only buffers of `len` bytes available.
+    fn add_buffers(guest_q: &GuestQ, count: u16, len: u32) {
+        for i in 0..count {
+            guest_q.dtable[i as usize].set(
+                BUFFER_ADDR + (i as u64) * len as u64,
+                len,
+                VRING_DESC_F_WRITE.try_into().unwrap(),
+                0,
+            );
+            guest_q.avail.ring[i as usize].set(i);
+        }
+        guest_q.avail.idx.set(count);
+    }
+
+    #[test]
+    fn test_egd_read() {
+        let (mut stream, daemon) = UnixStream::pair().unwrap();
+        let daemon = thread::spawn(move || egd_daemon(daemon, 0xa5));
+
+        let mut buf = vec![0u8; 600];
+        egd_read(&mut stream, &mut buf).unwrap();
+        assert!(buf.iter().all(|b| *b == 0xa5));
+        drop(stream);
+
+        // At most 255 bytes can be asked for at once.
+        assert_eq!(daemon.join().unwrap(), vec![255, 255, 90]);
+    }
+
+    #[test]
+    fn test_egd_read_closed() {
+        let (mut stream, daemon) = UnixStream::pair().unwrap();
+        drop(daemon);
+
+        let mut buf = vec![0u8; 16];
+        egd_read(&mut stream, &mut buf).unwrap_err();
+    }
+
+    #[test]
+    fn test_rng_egd_source() {
+        let dir = TempDir::new().unwrap();
+        let socket = dir.as_path().join("egd.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let daemon = thread::spawn(move || egd_daemon(listener.accept().unwrap().0, 0x5a));
+
+        let rng = Rng::new(
+            String::from("rng"),
+            &format!("{}{}", EGD_SOURCE_PREFIX, socket.to_str().unwrap()),
+            None,
+            false,
+            SeccompAction::Trap,
+            EventFd::new(0).unwrap(),
+            None,
+        )
+        .unwrap();
+
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, 16);
+        add_buffers(&guest_q, 2, 300);
+        let source = rng.source.try_clone().unwrap();
+        let mut handler = create_handler(&mem, guest_q.create_queue(), source, None);
+        handler.counters = rng.counters.clone();
+
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 2);
+        let mut buf = vec![0u8; 600];
+        mem.read_slice(&mut buf, GuestAddress(BUFFER_ADDR)).unwrap();
+        assert!(buf.iter().all(|b| *b == 0x5a));
+
+        let counters = rng.counters().unwrap();
+        assert_eq!(counters["bytes"], Wrapping(600));
+        assert_eq!(counters["requests"], Wrapping(2));
+        assert!(!counters.contains_key("throttled_time_us"));
+
+        drop(handler);
+        drop(rng);
+        assert_eq!(daemon.join().unwrap(), vec![255, 45, 255, 45]);
+    }
+
+    #[test]
+    fn test_rng_egd_reconnect() {
+        let dir = TempDir::new().unwrap();
+        let socket = dir.as_path().join("egd.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        // The first connection is closed as if the daemon was restarting.
+        let daemon = thread::spawn(move || {
+            drop(listener.accept().unwrap());
+            egd_daemon(listener.accept().unwrap().0, 0x3c)
+        });
+
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, 16);
+        add_buffers(&guest_q, 1, 16);
+        let source = EntropySource::open(&format!(
+            "{}{}",
+            EGD_SOURCE_PREFIX,
+            socket.to_str().unwrap()
+        ))
+        .unwrap();
+        let mut handler = create_handler(&mem, guest_q.create_queue(), source, None);
+
+        // The descriptor is completed empty instead of stopping the device.
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 1);
+        assert_eq!(handler.counters.bytes.load(Ordering::Acquire), 0);
+
+        // The next request is served through a new connection.
+        guest_q.dtable[1].set(BUFFER_ADDR, 16, VRING_DESC_F_WRITE.try_into().unwrap(), 0);
+        guest_q.avail.ring[1].set(1);
+        guest_q.avail.idx.set(2);
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 2);
+        assert_eq!(handler.counters.bytes.load(Ordering::Acquire), 16);
+        let mut buf = [0u8; 16];
+        mem.read_slice(&mut buf, GuestAddress(BUFFER_ADDR)).unwrap();
+        assert_eq!(buf, [0x3c; 16]);
+
+        drop(handler);
+        assert_eq!(daemon.join().unwrap(), vec![16]);
+    }
+
+    #[test]
+    fn test_rng_file_source() {
+        let file = TempFile::new().unwrap();
+        file.as_file().write_all(&[0x42; 64]).unwrap();
+        let path = file.as_path().to_str().unwrap();
+
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, 16);
+        add_buffers(&guest_q, 1, 32);
+        let source = EntropySource::open(path).unwrap();
+        let mut handler = create_handler(&mem, guest_q.create_queue(), source, None);
+
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 1);
+        let mut buf = [0u8; 32];
+        mem.read_slice(&mut buf, GuestAddress(BUFFER_ADDR)).unwrap();
+        assert_eq!(buf, [0x42; 32]);
+    }
+
+    #[test]
+    fn test_rng_rate_limiting_ops() {
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, 16);
+        add_buffers(&guest_q, 3, 16);
+        let source = EntropySource::open("/dev/urandom").unwrap();
+        let config = RateLimiterConfig {
+            bandwidth: None,
+            ops: Some(TokenBucketConfig {
+                size: 2,
+                one_time_burst: None,
+                refill_time: 100,
+            }),
+        };
+        let mut handler = create_handler(&mem, guest_q.create_queue(), source, Some(config));
+
+        // Only two requests are allowed, the last one is left for later.
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 2);
+        assert!(!handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 2);
+        assert_eq!(handler.counters.requests.load(Ordering::Acquire), 2);
+
+        // The request is processed once the budget has been replenished.
+        thread::sleep(Duration::from_millis(200));
+        handler
+            .rate_limiter
+            .as_mut()
+            .unwrap()
+            .event_handler()
+            .unwrap();
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 3);
+        assert_eq!(handler.counters.requests.load(Ordering::Acquire), 3);
+        assert_eq!(handler.counters.bytes.load(Ordering::Acquire), 48);
+    }
+
+    #[test]
+    fn test_rng_rate_limiting_bandwidth() {
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap();
+        let guest_q = GuestQ::new(GuestAddress(QUEUE_ADDR), &mem, 16);
+        add_buffers(&guest_q, 2, 64);
+        let source = EntropySource::open("/dev/urandom").unwrap();
+        let config = RateLimiterConfig {
+            bandwidth: Some(TokenBucketConfig {
+                size: 100,
+                one_time_burst: None,
+                refill_time: 100,
+            }),
+            ops: Some(TokenBucketConfig {
+                size: 10,
+                one_time_burst: None,
+                refill_time: 100,
+            }),
+        };
+        let mut handler = create_handler(&mem, guest_q.create_queue(), source, Some(config));
+
+        // The second buffer exceeds the bandwidth budget.
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 1);
+        assert_eq!(handler.counters.bytes.load(Ordering::Acquire), 64);
+        let stats = handler.rate_limiter.as_ref().unwrap().throttle_stats();
+        assert_eq!(stats.throttled_count(), 1);
+
+        thread::sleep(Duration::from_millis(200));
+        handler
+            .rate_limiter
+            .as_mut()
+            .unwrap()
+            .event_handler()
+            .unwrap();
+        assert!(handler.process_queue().unwrap());
+        assert_eq!(guest_q.used.idx.get(), 2);
+        assert_eq!(handler.counters.bytes.load(Ordering::Acquire), 128);
+    }
+}
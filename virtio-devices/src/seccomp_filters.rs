@@ -14,6 +14,7 @@ pub enum Thread {
     VirtioBalloon,
     VirtioBlock,
     VirtioConsole,
+    VirtioFs9p,
     VirtioIommu,
     VirtioMem,
     VirtioNet,
@@ -171,10 +172,46 @@ fn virtio_pmem_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     vec![(libc::SYS_fsync, vec![])]
 }
 
+fn virtio_fs9p_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
+    vec![
+        (libc::SYS_fchmodat, vec![]),
+        (libc::SYS_fchownat, vec![]),
+        (libc::SYS_fcntl, vec![]),
+        (libc::SYS_fdatasync, vec![]),
+        (libc::SYS_fstatfs, vec![]),
+        (libc::SYS_fsync, vec![]),
+        (libc::SYS_ftruncate, vec![]),
+        (libc::SYS_getdents64, vec![]),
+        (libc::SYS_linkat, vec![]),
+        (libc::SYS_lseek, vec![]),
+        (libc::SYS_mkdirat, vec![]),
+        (libc::SYS_mknodat, vec![]),
+        (libc::SYS_newfstatat, vec![]),
+        (libc::SYS_pread64, vec![]),
+        (libc::SYS_pwrite64, vec![]),
+        (libc::SYS_readlinkat, vec![]),
+        #[cfg(target_arch = "x86_64")]
+        (libc::SYS_renameat, vec![]),
+        (libc::SYS_renameat2, vec![]),
+        (libc::SYS_sched_getaffinity, vec![]),
+        (libc::SYS_set_robust_list, vec![]),
+        (libc::SYS_statx, vec![]),
+        (libc::SYS_symlinkat, vec![]),
+        (libc::SYS_unlinkat, vec![]),
+        (libc::SYS_utimensat, vec![]),
+        #[cfg(feature = "sev_snp")]
+        (libc::SYS_ioctl, create_mshv_sev_snp_ioctl_seccomp_rule()),
+    ]
+}
+
 fn virtio_rng_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     vec![
+        (libc::SYS_connect, vec![]),
+        (libc::SYS_recvfrom, vec![]),
         (libc::SYS_sched_getaffinity, vec![]),
         (libc::SYS_set_robust_list, vec![]),
+        (libc::SYS_socket, vec![]),
+        (libc::SYS_timerfd_settime, vec![]),
         #[cfg(feature = "sev_snp")]
         (libc::SYS_ioctl, create_mshv_sev_snp_ioctl_seccomp_rule()),
     ]
@@ -185,6 +222,7 @@ fn virtio_vhost_fs_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
         (libc::SYS_clock_nanosleep, vec![]),
         (libc::SYS_connect, vec![]),
         (libc::SYS_nanosleep, vec![]),
+        (libc::SYS_msync, vec![]),
         (libc::SYS_pread64, vec![]),
         (libc::SYS_pwrite64, vec![]),
         (libc::SYS_recvmsg, vec![]),
@@ -264,6 +302,7 @@ fn get_seccomp_rules(thread_type: Thread) -> Vec<(i64, Vec<SeccompRule>)> {
         Thread::VirtioBalloon => virtio_balloon_thread_rules(),
         Thread::VirtioBlock => virtio_block_thread_rules(),
         Thread::VirtioConsole => virtio_console_thread_rules(),
+        Thread::VirtioFs9p => virtio_fs9p_thread_rules(),
         Thread::VirtioIommu => virtio_iommu_thread_rules(),
         Thread::VirtioMem => virtio_mem_thread_rules(),
         Thread::VirtioNet => virtio_net_thread_rules(),
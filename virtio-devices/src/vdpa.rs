@@ -13,22 +13,36 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use vhost::vdpa::{VhostVdpa, VhostVdpaIovaRange};
 use vhost::vhost_kern::vdpa::VhostKernVdpa;
-use vhost::vhost_kern::vhost_binding::VHOST_BACKEND_F_SUSPEND;
+use vhost::vhost_kern::vhost_binding::{
+    VHOST_BACKEND_F_SUSPEND, VHOST_F_LOG_ALL, VHOST_VRING_F_LOG,
+};
 use vhost::vhost_kern::VhostKernFeatures;
 use vhost::{VhostBackend, VringConfigData};
 use virtio_queue::{Descriptor, Queue, QueueT};
 use vm_device::dma_mapping::ExternalDmaMapping;
-use vm_memory::{GuestAddress, GuestAddressSpace, GuestMemory, GuestMemoryAtomic};
+use vm_memory::{Address, GuestAddress, GuestAddressSpace, GuestMemory, GuestMemoryAtomic};
+use vm_migration::protocol::MemoryRangeTable;
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vm_virtio::{AccessPlatform, Translatable};
 use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::ioctl::ioctl;
+use vmm_sys_util::{ioctl_io_nr, ioctl_ioc_nr};
 
 use crate::{
-    ActivateError, ActivateResult, GuestMemoryMmap, VirtioCommon, VirtioDevice, VirtioInterrupt,
-    VirtioInterruptType, DEVICE_ACKNOWLEDGE, DEVICE_DRIVER, DEVICE_DRIVER_OK, DEVICE_FEATURES_OK,
-    VIRTIO_F_IOMMU_PLATFORM,
+    ActivateError, ActivateResult, GuestMemoryMmap, MmapRegion, VirtioCommon, VirtioDevice,
+    VirtioInterrupt, VirtioInterruptType, DEVICE_ACKNOWLEDGE, DEVICE_DRIVER, DEVICE_DRIVER_OK,
+    DEVICE_FEATURES_OK, VIRTIO_F_IOMMU_PLATFORM,
 };
 
+// The device can be resumed after being suspended.
+const VHOST_BACKEND_F_RESUME: u64 = 0x5;
+
+const VHOST_VIRTIO: u32 = 0xaf;
+ioctl_io_nr!(VHOST_VDPA_RESUME, VHOST_VIRTIO, 0x7e);
+
+// Size of a dirty page for vhost.
+const VHOST_LOG_PAGE: u64 = 0x1000;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Failed to create vhost-vdpa: {0}")]
@@ -51,18 +65,28 @@ pub enum Error {
     GetFeatures(vhost::Error),
     #[error("Failed to get the IOVA range: {0}")]
     GetIovaRange(vhost::Error),
+    #[error("Failed to get vring base: {0}")]
+    GetVringBase(vhost::Error),
     #[error("Failed to get queue size: {0}")]
     GetVringNum(vhost::Error),
     #[error("Invalid IOVA range: {0}-{1}")]
     InvalidIovaRange(u64, u64),
     #[error("Missing VIRTIO_F_ACCESS_PLATFORM feature")]
     MissingAccessPlatformVirtioFeature,
+    #[error("Dirty page logging is not supported by the device")]
+    MigrationNotSupported,
+    #[error("Failed to create the dirty log region: {0}")]
+    NewMmapRegion(vm_memory::mmap::MmapRegionError),
     #[error("Failed to reset owner: {0}")]
     ResetOwner(vhost::Error),
+    #[error("Failed to resume the device: {0}")]
+    Resume(io::Error),
     #[error("Failed to set backend specific features: {0}")]
     SetBackendFeatures(vhost::Error),
     #[error("Failed to set backend configuration: {0}")]
     SetConfig(vhost::Error),
+    #[error("Failed to set the dirty log base: {0}")]
+    SetLogBase(vhost::Error),
     #[error("Failed to set eventfd notifying about a configuration change: {0}")]
     SetConfigCall(vhost::Error),
     #[error("Failed to set virtio features: {0}")]
@@ -85,6 +109,8 @@ pub enum Error {
     SetVringKick(vhost::Error),
     #[error("Failed to set vring size: {0}")]
     SetVringNum(vhost::Error),
+    #[error("Failed to suspend the device: {0}")]
+    Suspend(vhost::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -99,16 +125,35 @@ pub struct VdpaState {
     pub config: Vec<u8>,
     pub queue_sizes: Vec<u16>,
     pub backend_features: u64,
+    /// Index of the next available descriptor the device processes, for
+    /// each activated queue.
+    #[serde(default)]
+    pub vring_bases: BTreeMap<usize, u16>,
+}
+
+// Configuration of an activated virtqueue, along with the guest address of
+// its used ring, needed to enable the logging of the writes to the ring.
+#[derive(Clone, Copy)]
+struct VringInfo {
+    config_data: VringConfigData,
+    used_guest_addr: u64,
 }
 
 pub struct Vdpa {
     common: VirtioCommon,
     id: String,
     vhost: Option<VhostKernVdpa<GuestMemoryAtomic<GuestMemoryMmap>>>,
+    mem: GuestMemoryAtomic<GuestMemoryMmap>,
     iova_range: VhostVdpaIovaRange,
     enabled_queues: BTreeMap<usize, bool>,
+    vrings: BTreeMap<usize, VringInfo>,
+    vring_bases: BTreeMap<usize, u16>,
     backend_features: u64,
     migrating: bool,
+    suspended: bool,
+    // Bitmap the device logs the guest pages it writes to in, one bit per
+    // page, while the dirty pages are tracked.
+    dirty_log: Option<MmapRegion>,
 }
 
 impl Vdpa {
@@ -119,7 +164,8 @@ impl Vdpa {
         num_queues: u16,
         state: Option<VdpaState>,
     ) -> Result<Self> {
-        let mut vhost = VhostKernVdpa::new(device_path, mem).map_err(Error::CreateVhostVdpa)?;
+        let mut vhost =
+            VhostKernVdpa::new(device_path, mem.clone()).map_err(Error::CreateVhostVdpa)?;
         vhost.set_owner().map_err(Error::SetOwner)?;
 
         let (
@@ -129,6 +175,7 @@ impl Vdpa {
             queue_sizes,
             iova_range,
             backend_features,
+            vring_bases,
             paused,
         ) = if let Some(state) = state {
             info!("Restoring vDPA {}", id);
@@ -148,6 +195,7 @@ impl Vdpa {
                     last: state.iova_range_last,
                 },
                 state.backend_features,
+                state.vring_bases,
                 false,
             )
         } else {
@@ -172,6 +220,7 @@ impl Vdpa {
                 vec![queue_size; num_queues as usize],
                 iova_range,
                 backend_features,
+                BTreeMap::new(),
                 false,
             )
         };
@@ -188,13 +237,194 @@ impl Vdpa {
             },
             id,
             vhost: Some(vhost),
+            mem,
             iova_range,
             enabled_queues: BTreeMap::new(),
+            vrings: BTreeMap::new(),
+            vring_bases,
             backend_features,
             migrating: false,
+            suspended: false,
+            dirty_log: None,
         })
     }
 
+    fn activated(&self) -> bool {
+        self.common.interrupt_cb.is_some()
+    }
+
+    fn suspend(&mut self) -> Result<()> {
+        if self.suspended || !self.activated() {
+            return Ok(());
+        }
+
+        assert!(self.vhost.is_some());
+        self.vhost
+            .as_ref()
+            .unwrap()
+            .suspend()
+            .map_err(Error::Suspend)?;
+        self.suspended = true;
+
+        Ok(())
+    }
+
+    fn resume_vdpa(&mut self) -> Result<()> {
+        if !self.suspended {
+            return Ok(());
+        }
+
+        assert!(self.vhost.is_some());
+        // SAFETY: FFI call with a valid vhost-vdpa file descriptor.
+        let ret = unsafe { ioctl(self.vhost.as_ref().unwrap(), VHOST_VDPA_RESUME()) };
+        if ret < 0 {
+            return Err(Error::Resume(io::Error::last_os_error()));
+        }
+        self.suspended = false;
+
+        Ok(())
+    }
+
+    // Index of the next available descriptor the device processes for each
+    // activated queue, only stable while the device is suspended.
+    fn vring_bases(&self) -> Result<BTreeMap<usize, u16>> {
+        let mut vring_bases = BTreeMap::new();
+        if !self.activated() {
+            return Ok(vring_bases);
+        }
+
+        assert!(self.vhost.is_some());
+        for queue_index in self.enabled_queues.keys() {
+            let base = self
+                .vhost
+                .as_ref()
+                .unwrap()
+                .get_vring_base(*queue_index)
+                .map_err(Error::GetVringBase)?;
+            vring_bases.insert(*queue_index, base as u16);
+        }
+
+        Ok(vring_bases)
+    }
+
+    // Give the device a new bitmap to log the pages it writes to, covering
+    // all guest pages from address 0 to the last address of guest RAM, and
+    // return the previous one.
+    fn update_log_base(&mut self) -> Result<Option<MmapRegion>> {
+        let last_ram_addr = self.mem.memory().last_addr().raw_value();
+        let size = (last_ram_addr / (VHOST_LOG_PAGE * 8) + 1).next_multiple_of(8);
+        let region = MmapRegion::new(size as usize).map_err(Error::NewMmapRegion)?;
+
+        assert!(self.vhost.is_some());
+        self.vhost
+            .as_ref()
+            .unwrap()
+            .set_log_base(region.as_ptr() as u64, None)
+            .map_err(Error::SetLogBase)?;
+
+        Ok(self.dirty_log.replace(region))
+    }
+
+    fn set_vring_logging(&mut self, enable: bool) -> Result<()> {
+        assert!(self.vhost.is_some());
+
+        for (queue_index, vring) in self.vrings.iter() {
+            let mut config_data = vring.config_data;
+            if enable {
+                config_data.flags = 1 << VHOST_VRING_F_LOG;
+                config_data.log_addr = Some(vring.used_guest_addr);
+            }
+            self.vhost
+                .as_ref()
+                .unwrap()
+                .set_vring_addr(*queue_index, &config_data)
+                .map_err(Error::SetVringAddr)?;
+        }
+
+        Ok(())
+    }
+
+    fn start_dirty_log(&mut self) -> Result<()> {
+        // The memory written through DMA can't be tracked by the hypervisor,
+        // the device must log it itself.
+        if self.common.avail_features & (1 << VHOST_F_LOG_ALL) == 0 {
+            return Err(Error::MigrationNotSupported);
+        }
+
+        self.update_log_base()?;
+
+        let result = self
+            .vhost
+            .as_ref()
+            .unwrap()
+            .set_features(self.common.acked_features | (1 << VHOST_F_LOG_ALL))
+            .map_err(Error::SetFeatures)
+            .and_then(|_| self.set_vring_logging(true));
+        if result.is_err() {
+            // Never leave the device with a log it might write to once the
+            // region has been released.
+            let _ = self.stop_dirty_log();
+        }
+
+        result
+    }
+
+    fn stop_dirty_log(&mut self) -> Result<()> {
+        // Nothing is logged anymore once the vDPA file has been closed.
+        if self.dirty_log.is_none() || self.vhost.is_none() {
+            self.dirty_log = None;
+            return Ok(());
+        }
+
+        self.set_vring_logging(false)?;
+        self.vhost
+            .as_ref()
+            .unwrap()
+            .set_features(self.common.acked_features)
+            .map_err(Error::SetFeatures)?;
+        self.dirty_log = None;
+
+        Ok(())
+    }
+
+    // The device is given a new bitmap, so that the previous one can be read
+    // without missing any write happening in the meantime.
+    fn dirty_log(&mut self) -> Result<MemoryRangeTable> {
+        if self.dirty_log.is_none() {
+            return Ok(MemoryRangeTable::default());
+        }
+
+        let region = self.update_log_base()?.unwrap();
+        let len = region.size() / 8;
+        // SAFETY: the region is a mapping of `len` u64 that the device no
+        // longer writes to.
+        let bitmap = unsafe { std::slice::from_raw_parts(region.as_ptr() as *const u64, len) };
+        Ok(MemoryRangeTable::from_bitmap(
+            bitmap.to_vec(),
+            0,
+            VHOST_LOG_PAGE,
+        ))
+    }
+
+    // Index of the first available descriptor the device processes once
+    // activated. A restored device resumes processing the available ring
+    // where it stopped on the source.
+    fn vring_base(
+        &mut self,
+        mem: &GuestMemoryMmap,
+        queue_index: usize,
+        queue: &Queue,
+    ) -> Result<u16> {
+        if let Some(base) = self.vring_bases.remove(&queue_index) {
+            return Ok(base);
+        }
+
+        Ok(queue
+            .avail_idx(mem, Ordering::Acquire)
+            .map_err(Error::GetAvailableIndex)?
+            .0)
+    }
+
     fn enable_vrings(&mut self, enable: bool) -> Result<()> {
         assert!(self.vhost.is_some());
 
@@ -218,11 +448,19 @@ impl Vdpa {
         virtio_interrupt: &Arc<dyn VirtioInterrupt>,
         queues: Vec<(usize, Queue, EventFd)>,
     ) -> Result<()> {
+        // Keep logging the dirty pages if the device is reset and activated
+        // again during a migration.
+        let logging = self.dirty_log.is_some();
+        let mut features = self.common.acked_features;
+        if logging {
+            features |= 1 << VHOST_F_LOG_ALL;
+        }
+
         assert!(self.vhost.is_some());
         self.vhost
             .as_ref()
             .unwrap()
-            .set_features(self.common.acked_features)
+            .set_features(features)
             .map_err(Error::SetFeatures)?;
         self.vhost
             .as_mut()
@@ -239,7 +477,7 @@ impl Vdpa {
                 .set_vring_num(*queue_index, queue_size)
                 .map_err(Error::SetVringNum)?;
 
-            let config_data = VringConfigData {
+            let mut config_data = VringConfigData {
                 queue_max_size,
                 queue_size,
                 flags: 0u32,
@@ -257,24 +495,29 @@ impl Vdpa {
                 ),
                 log_addr: None,
             };
+            let vring = VringInfo {
+                config_data,
+                used_guest_addr: queue.used_ring(),
+            };
+            if logging {
+                config_data.flags = 1 << VHOST_VRING_F_LOG;
+                config_data.log_addr = Some(vring.used_guest_addr);
+            }
 
             self.vhost
                 .as_ref()
                 .unwrap()
                 .set_vring_addr(*queue_index, &config_data)
                 .map_err(Error::SetVringAddr)?;
+            let base = self.vring_base(mem, *queue_index, queue)?;
             self.vhost
                 .as_ref()
                 .unwrap()
-                .set_vring_base(
-                    *queue_index,
-                    queue
-                        .avail_idx(mem, Ordering::Acquire)
-                        .map_err(Error::GetAvailableIndex)?
-                        .0,
-                )
+                .set_vring_base(*queue_index, base)
                 .map_err(Error::SetVringBase)?;
 
+            self.vrings.insert(*queue_index, vring);
+
             if let Some(eventfd) =
                 virtio_interrupt.notifier(VirtioInterruptType::Queue(*queue_index as u16))
             {
@@ -316,6 +559,8 @@ impl Vdpa {
 
     fn reset_vdpa(&mut self) -> Result<()> {
         self.enable_vrings(false)?;
+        self.vrings.clear();
+        self.suspended = false;
 
         assert!(self.vhost.is_some());
         self.vhost
@@ -379,6 +624,7 @@ impl Vdpa {
             iova_range_last: self.iova_range.last,
             config,
             backend_features: self.backend_features,
+            vring_bases: self.vring_bases()?,
         })
     }
 }
@@ -449,13 +695,18 @@ impl VirtioDevice for Vdpa {
 
 impl Pausable for Vdpa {
     fn pause(&mut self) -> std::result::Result<(), MigratableError> {
-        if !self.migrating {
-            Err(MigratableError::Pause(anyhow!(
-                "Can't pause a vDPA device outside live migration"
-            )))
-        } else {
-            Ok(())
+        if self.backend_features & (1 << VHOST_BACKEND_F_SUSPEND) == 0 {
+            return Err(MigratableError::Pause(anyhow!(
+                "vDPA device can't be suspended"
+            )));
         }
+
+        self.suspend().map_err(|e| {
+            MigratableError::Pause(anyhow!("Error suspending vDPA device: {:?}", e))
+        })?;
+        self.common.paused.store(true, Ordering::SeqCst);
+
+        Ok(())
     }
 
     fn resume(&mut self) -> std::result::Result<(), MigratableError> {
@@ -463,13 +714,17 @@ impl Pausable for Vdpa {
             return Ok(());
         }
 
-        if !self.migrating {
-            Err(MigratableError::Resume(anyhow!(
-                "Can't resume a vDPA device outside live migration"
-            )))
-        } else {
-            Ok(())
+        if self.suspended && self.backend_features & (1 << VHOST_BACKEND_F_RESUME) == 0 {
+            return Err(MigratableError::Resume(anyhow!(
+                "vDPA device can't be resumed"
+            )));
         }
+
+        self.resume_vdpa()
+            .map_err(|e| MigratableError::Resume(anyhow!("Error resuming vDPA device: {:?}", e)))?;
+        self.common.paused.store(false, Ordering::SeqCst);
+
+        Ok(())
     }
 }
 
@@ -479,9 +734,11 @@ impl Snapshottable for Vdpa {
     }
 
     fn snapshot(&mut self) -> std::result::Result<Snapshot, MigratableError> {
-        if !self.migrating {
+        // The vring state is only stable once the device stopped processing
+        // the virtqueues.
+        if self.activated() && !self.suspended {
             return Err(MigratableError::Snapshot(anyhow!(
-                "Can't snapshot a vDPA device outside live migration"
+                "Can't snapshot a vDPA device that is not suspended"
             )));
         }
 
@@ -489,10 +746,12 @@ impl Snapshottable for Vdpa {
             MigratableError::Snapshot(anyhow!("Error snapshotting vDPA device: {:?}", e))
         })?)?;
 
-        // Force the vhost handler to be dropped in order to close the vDPA
-        // file. This will ensure the device can be accessed if the VM is
-        // migrated on the same host machine.
-        self.vhost.take();
+        if self.migrating {
+            // Force the vhost handler to be dropped in order to close the
+            // vDPA file. This will ensure the device can be accessed if the
+            // VM is migrated on the same host machine.
+            self.vhost.take();
+        }
 
         Ok(snapshot)
     }
@@ -502,19 +761,38 @@ impl Transportable for Vdpa {}
 
 impl Migratable for Vdpa {
     fn start_migration(&mut self) -> std::result::Result<(), MigratableError> {
-        self.migrating = true;
-        // Given there's no way to track dirty pages, we must suspend the
-        // device as soon as the migration process starts.
-        if self.backend_features & (1 << VHOST_BACKEND_F_SUSPEND) != 0 {
-            assert!(self.vhost.is_some());
-            self.vhost.as_ref().unwrap().suspend().map_err(|e| {
-                MigratableError::StartMigration(anyhow!("Error suspending vDPA device: {:?}", e))
-            })
-        } else {
-            Err(MigratableError::StartMigration(anyhow!(
+        if self.backend_features & (1 << VHOST_BACKEND_F_SUSPEND) == 0 {
+            return Err(MigratableError::StartMigration(anyhow!(
                 "vDPA device can't be suspended"
-            )))
+            )));
         }
+
+        self.migrating = true;
+        Ok(())
+    }
+
+    fn start_dirty_log(&mut self) -> std::result::Result<(), MigratableError> {
+        Vdpa::start_dirty_log(self).map_err(|e| {
+            MigratableError::StartDirtyLog(anyhow!(
+                "Error starting dirty page logging for vDPA device: {:?}",
+                e
+            ))
+        })
+    }
+
+    fn stop_dirty_log(&mut self) -> std::result::Result<(), MigratableError> {
+        Vdpa::stop_dirty_log(self).map_err(|e| {
+            MigratableError::StopDirtyLog(anyhow!(
+                "Error stopping dirty page logging for vDPA device: {:?}",
+                e
+            ))
+        })
+    }
+
+    fn dirty_log(&mut self) -> std::result::Result<MemoryRangeTable, MigratableError> {
+        Vdpa::dirty_log(self).map_err(|e| {
+            MigratableError::DirtyLog(anyhow!("Error retrieving vDPA dirty pages: {:?}", e))
+        })
     }
 
     fn complete_migration(&mut self) -> std::result::Result<(), MigratableError> {
@@ -577,3 +855,200 @@ impl<M: GuestAddressSpace + Sync + Send> ExternalDmaMapping for VdpaDmaMapping<M
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use vm_virtio::queue::testing::VirtQueue as GuestQ;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+    use crate::vsock::tests::NoopVirtioInterrupt;
+
+    const MEM_SIZE: usize = 0x10_0000;
+    const QUEUE_SIZE: u16 = 16;
+
+    // The vhost-vdpa handle is backed by a regular file, every ioctl then
+    // fails as it would with a vDPA device not implementing it.
+    fn create_vdpa(
+        mem: &GuestMemoryMmap,
+        avail_features: u64,
+        backend_features: u64,
+        vring_bases: BTreeMap<usize, u16>,
+    ) -> Vdpa {
+        let mem = GuestMemoryAtomic::new(mem.clone());
+        let file = TempFile::new().unwrap().into_file();
+        Vdpa {
+            common: VirtioCommon {
+                avail_features,
+                queue_sizes: vec![QUEUE_SIZE; 2],
+                paused: Arc::new(AtomicBool::new(false)),
+                ..Default::default()
+            },
+            id: String::from("vdpa"),
+            vhost: Some(VhostKernVdpa::with(file, mem.clone(), backend_features)),
+            mem,
+            iova_range: VhostVdpaIovaRange {
+                first: 0,
+                last: u64::MAX,
+            },
+            enabled_queues: BTreeMap::new(),
+            vrings: BTreeMap::new(),
+            vring_bases,
+            backend_features,
+            migrating: false,
+            suspended: false,
+            dirty_log: None,
+        }
+    }
+
+    fn create_mem() -> GuestMemoryMmap {
+        GuestMemoryMmap::from_ranges(&[(GuestAddress(0), MEM_SIZE)]).unwrap()
+    }
+
+    // Make the device look activated, with a single enabled queue.
+    fn activate(vdpa: &mut Vdpa) {
+        vdpa.common.interrupt_cb = Some(Arc::new(NoopVirtioInterrupt {}));
+        vdpa.enabled_queues.insert(0, true);
+    }
+
+    #[test]
+    fn test_vdpa_pause_unsupported() {
+        let mem = create_mem();
+        let mut vdpa = create_vdpa(&mem, 0, 0, BTreeMap::new());
+
+        assert!(matches!(vdpa.pause(), Err(MigratableError::Pause(_))));
+        assert!(matches!(
+            vdpa.start_migration(),
+            Err(MigratableError::StartMigration(_))
+        ));
+        assert!(!vdpa.common.paused.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_vdpa_pause_resume() {
+        let mem = create_mem();
+        let mut vdpa = create_vdpa(
+            &mem,
+            0,
+            (1 << VHOST_BACKEND_F_SUSPEND) | (1 << VHOST_BACKEND_F_RESUME),
+            BTreeMap::new(),
+        );
+
+        // A device that hasn't been activated has nothing to suspend.
+        vdpa.pause().unwrap();
+        assert!(vdpa.common.paused.load(Ordering::SeqCst));
+        assert!(!vdpa.suspended);
+        vdpa.resume().unwrap();
+        assert!(!vdpa.common.paused.load(Ordering::SeqCst));
+
+        // Failing to suspend an activated device leaves it running.
+        activate(&mut vdpa);
+        assert!(matches!(vdpa.pause(), Err(MigratableError::Pause(_))));
+        assert!(!vdpa.common.paused.load(Ordering::SeqCst));
+        assert!(!vdpa.suspended);
+
+        // Failing to resume a suspended device leaves it paused.
+        vdpa.common.paused.store(true, Ordering::SeqCst);
+        vdpa.suspended = true;
+        assert!(matches!(vdpa.resume(), Err(MigratableError::Resume(_))));
+        assert!(vdpa.common.paused.load(Ordering::SeqCst));
+        assert!(vdpa.suspended);
+    }
+
+    #[test]
+    fn test_vdpa_resume_unsupported() {
+        let mem = create_mem();
+        let mut vdpa = create_vdpa(&mem, 0, 1 << VHOST_BACKEND_F_SUSPEND, BTreeMap::new());
+        activate(&mut vdpa);
+
+        vdpa.common.paused.store(true, Ordering::SeqCst);
+        vdpa.suspended = true;
+        let Err(MigratableError::Resume(e)) = vdpa.resume() else {
+            panic!("resuming must fail");
+        };
+        assert!(e.to_string().contains("can't be resumed"));
+        assert!(vdpa.common.paused.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_vdpa_snapshot_not_suspended() {
+        let mem = create_mem();
+        let mut vdpa = create_vdpa(&mem, 0, 1 << VHOST_BACKEND_F_SUSPEND, BTreeMap::new());
+        activate(&mut vdpa);
+
+        let Err(MigratableError::Snapshot(e)) = vdpa.snapshot() else {
+            panic!("snapshotting must fail");
+        };
+        assert!(e.to_string().contains("not suspended"));
+        assert!(vdpa.vhost.is_some());
+    }
+
+    #[test]
+    fn test_vdpa_vring_bases() {
+        let mem = create_mem();
+        let mut vdpa = create_vdpa(&mem, 0, 0, BTreeMap::from([(0, 3)]));
+        assert!(vdpa.vring_bases().unwrap().is_empty());
+
+        let guest_q = GuestQ::new(GuestAddress(0x1000), &mem, QUEUE_SIZE);
+        guest_q.avail.idx.set(5);
+        let queue = guest_q.create_queue();
+
+        // The saved base is only used for the first activation, afterwards
+        // the device starts from the current available index.
+        assert_eq!(vdpa.vring_base(&mem, 0, &queue).unwrap(), 3);
+        assert_eq!(vdpa.vring_base(&mem, 0, &queue).unwrap(), 5);
+        assert_eq!(vdpa.vring_base(&mem, 1, &queue).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_vdpa_state_vring_bases() {
+        let state = VdpaState {
+            avail_features: 0,
+            acked_features: 0,
+            device_type: 2,
+            iova_range_first: 0,
+            iova_range_last: u64::MAX,
+            config: vec![0; 8],
+            queue_sizes: vec![QUEUE_SIZE; 2],
+            backend_features: 0,
+            vring_bases: BTreeMap::from([(0, 7), (1, 65535)]),
+        };
+        let json = serde_json::to_string(&state).unwrap();
+        let restored: VdpaState = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.vring_bases, state.vring_bases);
+
+        // Snapshots taken before the vring bases were saved can be restored.
+        let mut value = serde_json::to_value(&state).unwrap();
+        value.as_object_mut().unwrap().remove("vring_bases");
+        let restored: VdpaState = serde_json::from_value(value).unwrap();
+        assert!(restored.vring_bases.is_empty());
+    }
+
+    #[test]
+    fn test_vdpa_dirty_log() {
+        let mem = create_mem();
+
+        // Devices unable to log their writes can't be live migrated.
+        let mut vdpa = create_vdpa(&mem, 0, 0, BTreeMap::new());
+        let Err(MigratableError::StartDirtyLog(e)) = Migratable::start_dirty_log(&mut vdpa) else {
+            panic!("starting the dirty log must fail");
+        };
+        assert!(e.to_string().contains("MigrationNotSupported"));
+        assert!(vdpa.dirty_log.is_none());
+
+        // Neither can devices offering the feature without implementing it.
+        let mut vdpa = create_vdpa(&mem, 1 << VHOST_F_LOG_ALL, 0, BTreeMap::new());
+        assert!(matches!(
+            Migratable::start_dirty_log(&mut vdpa),
+            Err(MigratableError::StartDirtyLog(_))
+        ));
+        assert!(vdpa.dirty_log.is_none());
+
+        // Nothing is reported while the dirty pages aren't tracked.
+        assert!(Migratable::dirty_log(&mut vdpa)
+            .unwrap()
+            .regions()
+            .is_empty());
+        Migratable::stop_dirty_log(&mut vdpa).unwrap();
+    }
+}
@@ -1,29 +1,34 @@
 // Copyright 2019 Intel Corporation. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Barrier, Mutex};
-use std::{result, thread};
+use std::{io, result, thread};
 
 use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, Bytes};
 use vhost::vhost_user::message::{VhostUserProtocolFeatures, VhostUserVirtioFeatures};
-use vhost::vhost_user::{FrontendReqHandler, VhostUserFrontend, VhostUserFrontendReqHandler};
+use vhost::vhost_user::VhostUserFrontend;
 use virtio_queue::Queue;
 use vm_memory::{ByteValued, GuestMemoryAtomic};
 use vm_migration::protocol::MemoryRangeTable;
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::sock_ctrl_msg::ScmSocket;
 
 use super::vu_common_ctrl::VhostUserHandle;
-use super::{Error, Result, DEFAULT_VIRTIO_FEATURES};
+use super::{BackendReqChannel, Error, Result, DEFAULT_VIRTIO_FEATURES};
 use crate::seccomp_filters::Thread;
 use crate::thread_helper::spawn_virtio_thread;
 use crate::vhost_user::VhostUserCommon;
 use crate::{
-    ActivateResult, GuestMemoryMmap, GuestRegionMmap, MmapRegion, UserspaceMapping, VirtioCommon,
-    VirtioDevice, VirtioDeviceType, VirtioInterrupt, VirtioSharedMemoryList,
+    ActivateError, ActivateResult, GuestMemoryMmap, GuestRegionMmap, MmapRegion, UserspaceMapping,
+    VirtioCommon, VirtioDevice, VirtioDeviceType, VirtioInterrupt, VirtioSharedMemoryList,
     VIRTIO_F_IOMMU_PLATFORM,
 };
 
@@ -40,8 +45,264 @@ pub struct State {
     pub backend_req_support: bool,
 }
 
-struct BackendReqHandler {}
-impl VhostUserFrontendReqHandler for BackendReqHandler {}
+// Requests sent by the backend through the backend request channel. The
+// cache window ones are only understood by virtio-fs backends with DAX
+// support and are not part of the vhost crate.
+const BACKEND_REQ_CONFIG_CHANGE: u32 = 2;
+const BACKEND_REQ_FS_MAP: u32 = 6;
+const BACKEND_REQ_FS_UNMAP: u32 = 7;
+const BACKEND_REQ_FS_SYNC: u32 = 8;
+
+const VHOST_USER_VERSION: u32 = 0x1;
+const VHOST_USER_VERSION_MASK: u32 = 0x3;
+const VHOST_USER_REPLY_FLAG: u32 = 0x4;
+const VHOST_USER_NEED_REPLY_FLAG: u32 = 0x8;
+
+pub const VHOST_USER_FS_BACKEND_ENTRIES: usize = 8;
+pub const VHOST_USER_FS_FLAG_MAP_R: u64 = 0x1;
+pub const VHOST_USER_FS_FLAG_MAP_W: u64 = 0x2;
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+struct BackendReqHeader {
+    request: u32,
+    flags: u32,
+    size: u32,
+}
+
+// SAFETY: only a series of integers
+unsafe impl ByteValued for BackendReqHeader {}
+
+/// Payload of the cache window requests, describing up to
+/// `VHOST_USER_FS_BACKEND_ENTRIES` ranges.
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct VhostUserFsBackendMsg {
+    pub fd_offset: [u64; VHOST_USER_FS_BACKEND_ENTRIES],
+    pub cache_offset: [u64; VHOST_USER_FS_BACKEND_ENTRIES],
+    pub len: [u64; VHOST_USER_FS_BACKEND_ENTRIES],
+    pub flags: [u64; VHOST_USER_FS_BACKEND_ENTRIES],
+}
+
+// SAFETY: only a series of integers
+unsafe impl ByteValued for VhostUserFsBackendMsg {}
+
+/// Backend request channel of a virtio-fs device, mapping the file ranges
+/// the backend asks for into the cache window.
+pub struct FsBackendReqChannel {
+    sock: UnixStream,
+    backend_sock: UnixStream,
+    reply_ack: bool,
+    cache_size: u64,
+    mmap_cache_addr: u64,
+}
+
+impl FsBackendReqChannel {
+    fn new(cache_size: u64, mmap_cache_addr: u64, reply_ack: bool) -> io::Result<Self> {
+        let (sock, backend_sock) = UnixStream::pair()?;
+
+        Ok(FsBackendReqChannel {
+            sock,
+            backend_sock,
+            reply_ack,
+            cache_size,
+            mmap_cache_addr,
+        })
+    }
+
+    // Make sure the request is within the cache range
+    fn is_req_valid(&self, offset: u64, len: u64) -> bool {
+        match offset.checked_add(len) {
+            Some(end) => offset < self.cache_size && end <= self.cache_size,
+            None => false,
+        }
+    }
+
+    // Validate the entries of a request, returning the index, host address
+    // and length of each of them.
+    fn entries(&self, fs: &VhostUserFsBackendMsg) -> io::Result<Vec<(usize, u64, u64)>> {
+        let mut entries = Vec::new();
+        for i in 0..VHOST_USER_FS_BACKEND_ENTRIES {
+            let offset = fs.cache_offset[i];
+            let mut len = fs.len[i];
+
+            // Ignore if the length is 0.
+            if len == 0 {
+                continue;
+            }
+
+            // The backend asks for the whole cache window to be covered.
+            if len == u64::MAX {
+                len = self.cache_size.saturating_sub(offset);
+            }
+
+            if !self.is_req_valid(offset, len) {
+                return Err(io::Error::from_raw_os_error(libc::EINVAL));
+            }
+
+            entries.push((i, self.mmap_cache_addr + offset, len));
+        }
+
+        Ok(entries)
+    }
+
+    fn map(&self, fs: &VhostUserFsBackendMsg, file: Option<&File>) -> io::Result<()> {
+        let file = file.ok_or_else(|| io::Error::from_raw_os_error(libc::EBADF))?;
+
+        for (i, addr, len) in self.entries(fs)? {
+            let mut prot = 0;
+            if fs.flags[i] & VHOST_USER_FS_FLAG_MAP_R != 0 {
+                prot |= libc::PROT_READ;
+            }
+            if fs.flags[i] & VHOST_USER_FS_FLAG_MAP_W != 0 {
+                prot |= libc::PROT_WRITE;
+            }
+
+            // SAFETY: the range has been validated against the cache window,
+            // which is reserved for the lifetime of the device.
+            let ret = unsafe {
+                libc::mmap(
+                    addr as *mut libc::c_void,
+                    len as usize,
+                    prot,
+                    libc::MAP_SHARED | libc::MAP_FIXED,
+                    file.as_raw_fd(),
+                    fs.fd_offset[i] as libc::off_t,
+                )
+            };
+            if ret == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unmap(&self, fs: &VhostUserFsBackendMsg) -> io::Result<()> {
+        for (_, addr, len) in self.entries(fs)? {
+            // Replace the file mapping with an inaccessible anonymous one,
+            // so that the range stays reserved for the cache window.
+            // SAFETY: the range has been validated against the cache window.
+            let ret = unsafe {
+                libc::mmap(
+                    addr as *mut libc::c_void,
+                    len as usize,
+                    libc::PROT_NONE,
+                    libc::MAP_ANONYMOUS | libc::MAP_PRIVATE | libc::MAP_FIXED,
+                    -1,
+                    0,
+                )
+            };
+            if ret == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sync(&self, fs: &VhostUserFsBackendMsg) -> io::Result<()> {
+        for (_, addr, len) in self.entries(fs)? {
+            // SAFETY: the range has been validated against the cache window.
+            let ret =
+                unsafe { libc::msync(addr as *mut libc::c_void, len as usize, libc::MS_SYNC) };
+            if ret == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_fs_request(
+        &self,
+        hdr: &BackendReqHeader,
+        body: &[u8],
+        file: Option<&File>,
+    ) -> io::Result<()> {
+        let fs = VhostUserFsBackendMsg::from_slice(body)
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL))?;
+
+        match hdr.request {
+            BACKEND_REQ_FS_MAP => self.map(fs, file),
+            BACKEND_REQ_FS_UNMAP => self.unmap(fs),
+            _ => self.sync(fs),
+        }
+    }
+
+    // Acknowledge the request if the backend asked for it, sending 0 on
+    // success and the negated errno otherwise.
+    fn reply(&mut self, hdr: &BackendReqHeader, res: &io::Result<()>) -> io::Result<()> {
+        if !self.reply_ack || hdr.flags & VHOST_USER_NEED_REPLY_FLAG == 0 {
+            return Ok(());
+        }
+
+        let reply_hdr = BackendReqHeader {
+            request: hdr.request,
+            flags: VHOST_USER_VERSION | VHOST_USER_REPLY_FLAG,
+            size: std::mem::size_of::<u64>() as u32,
+        };
+        let val = match res {
+            Ok(()) => 0,
+            Err(e) => -(e.raw_os_error().unwrap_or(libc::EINVAL) as i64) as u64,
+        };
+
+        let mut reply = reply_hdr.as_slice().to_vec();
+        reply.extend_from_slice(val.as_slice());
+        self.sock.write_all(&reply)
+    }
+}
+
+impl AsRawFd for FsBackendReqChannel {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+impl BackendReqChannel for FsBackendReqChannel {
+    fn backend_fd(&self) -> RawFd {
+        self.backend_sock.as_raw_fd()
+    }
+
+    fn handle_request(&mut self) -> io::Result<()> {
+        let mut hdr = BackendReqHeader::default();
+        let (len, file) = self.sock.recv_with_fd(hdr.as_mut_slice())?;
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "backend disconnected",
+            ));
+        }
+        if len != std::mem::size_of::<BackendReqHeader>()
+            || hdr.flags & VHOST_USER_VERSION_MASK != VHOST_USER_VERSION
+            || hdr.size as usize > std::mem::size_of::<VhostUserFsBackendMsg>()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid backend request header",
+            ));
+        }
+
+        let mut body = vec![0u8; hdr.size as usize];
+        self.sock.read_exact(&mut body)?;
+
+        let res = match hdr.request {
+            BACKEND_REQ_CONFIG_CHANGE => Ok(()),
+            BACKEND_REQ_FS_MAP | BACKEND_REQ_FS_UNMAP | BACKEND_REQ_FS_SYNC => {
+                self.handle_fs_request(&hdr, &body, file.as_ref())
+            }
+            _ => Err(io::Error::from_raw_os_error(libc::ENOSYS)),
+        };
+        if let Err(e) = &res {
+            error!(
+                "Failed handling vhost-user-fs backend request {}: {}",
+                hdr.request, e
+            );
+        }
+
+        self.reply(&hdr, &res)
+    }
+}
 
 pub const VIRTIO_FS_TAG_LEN: usize = 36;
 #[serde_as]
@@ -128,15 +389,29 @@ impl Fs {
             // Filling device and vring features VMM supports.
             let avail_features = DEFAULT_VIRTIO_FEATURES;
 
-            let avail_protocol_features = VhostUserProtocolFeatures::MQ
+            let mut avail_protocol_features = VhostUserProtocolFeatures::MQ
                 | VhostUserProtocolFeatures::CONFIGURE_MEM_SLOTS
                 | VhostUserProtocolFeatures::REPLY_ACK
                 | VhostUserProtocolFeatures::INFLIGHT_SHMFD
                 | VhostUserProtocolFeatures::LOG_SHMFD;
+            let backend_protocol_features =
+                VhostUserProtocolFeatures::BACKEND_REQ | VhostUserProtocolFeatures::BACKEND_SEND_FD;
+            if cache.is_some() {
+                avail_protocol_features |= backend_protocol_features;
+            }
 
             let (acked_features, acked_protocol_features) =
                 vu.negotiate_features_vhost_user(avail_features, avail_protocol_features)?;
 
+            // The cache window can only be populated through backend requests.
+            if cache.is_some()
+                && acked_protocol_features & backend_protocol_features.bits()
+                    != backend_protocol_features.bits()
+            {
+                error!("vhost-user-fs backend does not support DAX");
+                return Err(Error::BackendReqNotSupported);
+            }
+
             let backend_num_queues =
                 if acked_protocol_features & VhostUserProtocolFeatures::MQ.bits() != 0 {
                     vu.socket_handle()
@@ -215,7 +490,7 @@ impl Fs {
             config: self.config,
             acked_protocol_features: self.vu_common.acked_protocol_features,
             vu_num_queues: self.vu_common.vu_num_queues,
-            backend_req_support: false,
+            backend_req_support: self.cache.is_some(),
         }
     }
 }
@@ -269,7 +544,18 @@ impl VirtioDevice for Fs {
         self.common.activate(&queues, &interrupt_cb)?;
         self.guest_memory = Some(mem.clone());
 
-        let backend_req_handler: Option<FrontendReqHandler<BackendReqHandler>> = None;
+        let backend_req_handler = if let Some(cache) = self.cache.as_ref() {
+            let reply_ack = self.vu_common.acked_protocol_features
+                & VhostUserProtocolFeatures::REPLY_ACK.bits()
+                != 0;
+            let channel = FsBackendReqChannel::new(cache.0.len, cache.0.host_addr, reply_ack)
+                .map_err(|e| {
+                    ActivateError::VhostUserFsSetup(Error::BackendReqChannelCreation(e))
+                })?;
+            Some(channel)
+        } else {
+            None
+        };
         // Run a dedicated thread for handling potential reconnections with
         // the backend.
         let (kill_evt, pause_evt) = self.common.dup_eventfds();
@@ -353,6 +639,14 @@ impl VirtioDevice for Fs {
         self.vu_common.add_memory_region(&self.guest_memory, region)
     }
 
+    fn remove_memory_region(
+        &mut self,
+        region: &Arc<GuestRegionMmap>,
+    ) -> std::result::Result<(), crate::Error> {
+        self.vu_common
+            .remove_memory_region(&self.guest_memory, region)
+    }
+
     fn userspace_mappings(&self) -> Vec<UserspaceMapping> {
         let mut mappings = Vec::new();
         if let Some(cache) = self.cache.as_ref() {
@@ -419,3 +713,216 @@ impl Migratable for Fs {
             .complete_migration(self.common.kill_evt.take())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    const CACHE_SIZE: u64 = 0x10000;
+    const PAGE_SIZE: u64 = 0x1000;
+
+    struct Cache {
+        addr: u64,
+    }
+
+    impl Cache {
+        // Reserve an inaccessible range the same way the cache window is.
+        fn new() -> Self {
+            // SAFETY: anonymous mapping, checked below
+            let addr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    CACHE_SIZE as usize,
+                    libc::PROT_NONE,
+                    libc::MAP_ANONYMOUS | libc::MAP_PRIVATE | libc::MAP_NORESERVE,
+                    -1,
+                    0,
+                )
+            };
+            assert_ne!(addr, libc::MAP_FAILED);
+
+            Cache { addr: addr as u64 }
+        }
+    }
+
+    impl Drop for Cache {
+        fn drop(&mut self) {
+            // SAFETY: the range was mapped by Cache::new()
+            unsafe { libc::munmap(self.addr as *mut libc::c_void, CACHE_SIZE as usize) };
+        }
+    }
+
+    fn msg(cache_offset: u64, len: u64, fd_offset: u64, flags: u64) -> VhostUserFsBackendMsg {
+        let mut msg = VhostUserFsBackendMsg::default();
+        msg.cache_offset[0] = cache_offset;
+        msg.len[0] = len;
+        msg.fd_offset[0] = fd_offset;
+        msg.flags[0] = flags;
+        msg
+    }
+
+    // Send a request from the backend side and return the reply, if any.
+    fn request(
+        channel: &mut FsBackendReqChannel,
+        request: u32,
+        msg: &VhostUserFsBackendMsg,
+        file: Option<&File>,
+    ) -> Option<i64> {
+        let backend = channel.backend_sock.try_clone().unwrap();
+        let hdr = BackendReqHeader {
+            request,
+            flags: VHOST_USER_VERSION | VHOST_USER_NEED_REPLY_FLAG,
+            size: std::mem::size_of::<VhostUserFsBackendMsg>() as u32,
+        };
+        let mut buf = hdr.as_slice().to_vec();
+        buf.extend_from_slice(msg.as_slice());
+        match file {
+            Some(file) => {
+                backend.send_with_fd(&buf[..], file.as_raw_fd()).unwrap();
+            }
+            None => (&backend).write_all(&buf).unwrap(),
+        }
+
+        channel.handle_request().unwrap();
+
+        backend.set_nonblocking(true).unwrap();
+        let mut reply_hdr = BackendReqHeader::default();
+        if (&backend).read_exact(reply_hdr.as_mut_slice()).is_err() {
+            return None;
+        }
+        assert_eq!(reply_hdr.request, request);
+        assert_eq!(reply_hdr.flags, VHOST_USER_VERSION | VHOST_USER_REPLY_FLAG);
+        let mut val = 0u64;
+        (&backend).read_exact(val.as_mut_slice()).unwrap();
+
+        Some(val as i64)
+    }
+
+    fn backing_file(len: u64) -> TempFile {
+        let file = TempFile::new().unwrap();
+        let content: Vec<u8> = (0..len).map(|i| (i / PAGE_SIZE) as u8 + 1).collect();
+        file.as_file().write_all(&content).unwrap();
+        file
+    }
+
+    fn is_file_mapped(addr: u64, file: &TempFile) -> bool {
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+        let path = file.as_path().to_str().unwrap();
+        maps.lines().any(|line| {
+            let range = line.split_whitespace().next().unwrap();
+            let (start, end) = range.split_once('-').unwrap();
+            let start = u64::from_str_radix(start, 16).unwrap();
+            let end = u64::from_str_radix(end, 16).unwrap();
+            start <= addr && addr < end && line.ends_with(path)
+        })
+    }
+
+    #[test]
+    fn test_fs_backend_map_unmap() {
+        let cache = Cache::new();
+        let mut channel = FsBackendReqChannel::new(CACHE_SIZE, cache.addr, true).unwrap();
+        let file = backing_file(2 * PAGE_SIZE);
+
+        // Map the second page of the file at the second page of the window
+        let map = msg(PAGE_SIZE, PAGE_SIZE, PAGE_SIZE, VHOST_USER_FS_FLAG_MAP_R);
+        assert_eq!(
+            request(&mut channel, BACKEND_REQ_FS_MAP, &map, Some(file.as_file())),
+            Some(0)
+        );
+        assert!(is_file_mapped(cache.addr + PAGE_SIZE, &file));
+        // SAFETY: the page has just been mapped readable
+        let byte = unsafe { *((cache.addr + PAGE_SIZE) as *const u8) };
+        assert_eq!(byte, 2);
+
+        assert_eq!(
+            request(&mut channel, BACKEND_REQ_FS_SYNC, &map, None),
+            Some(0)
+        );
+
+        assert_eq!(
+            request(&mut channel, BACKEND_REQ_FS_UNMAP, &map, None),
+            Some(0)
+        );
+        assert!(!is_file_mapped(cache.addr + PAGE_SIZE, &file));
+    }
+
+    #[test]
+    fn test_fs_backend_whole_window() {
+        let cache = Cache::new();
+        let mut channel = FsBackendReqChannel::new(CACHE_SIZE, cache.addr, true).unwrap();
+        let file = backing_file(CACHE_SIZE);
+
+        let map = msg(
+            0,
+            u64::MAX,
+            0,
+            VHOST_USER_FS_FLAG_MAP_R | VHOST_USER_FS_FLAG_MAP_W,
+        );
+        assert_eq!(
+            request(&mut channel, BACKEND_REQ_FS_MAP, &map, Some(file.as_file())),
+            Some(0)
+        );
+        assert!(is_file_mapped(cache.addr, &file));
+        assert!(is_file_mapped(cache.addr + CACHE_SIZE - PAGE_SIZE, &file));
+
+        assert_eq!(
+            request(&mut channel, BACKEND_REQ_FS_UNMAP, &map, None),
+            Some(0)
+        );
+        assert!(!is_file_mapped(cache.addr, &file));
+    }
+
+    #[test]
+    fn test_fs_backend_invalid_requests() {
+        let cache = Cache::new();
+        let mut channel = FsBackendReqChannel::new(CACHE_SIZE, cache.addr, true).unwrap();
+        let file = backing_file(PAGE_SIZE);
+        let einval = -(libc::EINVAL as i64);
+
+        // Beyond the end of the window
+        let map = msg(
+            CACHE_SIZE - PAGE_SIZE,
+            2 * PAGE_SIZE,
+            0,
+            VHOST_USER_FS_FLAG_MAP_R,
+        );
+        assert_eq!(
+            request(&mut channel, BACKEND_REQ_FS_MAP, &map, Some(file.as_file())),
+            Some(einval)
+        );
+
+        // Overflowing range
+        let unmap = msg(PAGE_SIZE, u64::MAX - 1, 0, 0);
+        assert_eq!(
+            request(&mut channel, BACKEND_REQ_FS_UNMAP, &unmap, None),
+            Some(einval)
+        );
+
+        // Mapping without a file descriptor
+        let map = msg(0, PAGE_SIZE, 0, VHOST_USER_FS_FLAG_MAP_R);
+        assert_eq!(
+            request(&mut channel, BACKEND_REQ_FS_MAP, &map, None),
+            Some(-(libc::EBADF as i64))
+        );
+
+        // Unknown request
+        assert_eq!(
+            request(&mut channel, 42, &map, None),
+            Some(-(libc::ENOSYS as i64))
+        );
+    }
+
+    #[test]
+    fn test_fs_backend_no_reply_ack() {
+        let cache = Cache::new();
+        let mut channel = FsBackendReqChannel::new(CACHE_SIZE, cache.addr, false).unwrap();
+
+        let sync = msg(0, PAGE_SIZE, 0, 0);
+        assert_eq!(
+            request(&mut channel, BACKEND_REQ_FS_SYNC, &sync, None),
+            None
+        );
+    }
+}
@@ -3,7 +3,7 @@
 
 use std::io;
 use std::ops::Deref;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Barrier, Mutex};
 
@@ -112,6 +112,8 @@ pub enum Error {
     VhostUserSetBackendRequestFd(vhost::Error),
     #[error("Add memory region failed: {0}")]
     VhostUserAddMemReg(VhostError),
+    #[error("Remove memory region failed: {0}")]
+    VhostUserRemoveMemReg(VhostError),
     #[error("Failed getting the configuration: {0}")]
     VhostUserGetConfig(VhostError),
     #[error("Failed setting the configuration: {0}")]
@@ -144,6 +146,10 @@ pub enum Error {
     NewMmapRegion(MmapRegionError),
     #[error("Could not find the shm log region")]
     MissingShmLogRegion,
+    #[error("Backend does not support the requests needed for the cache window")]
+    BackendReqNotSupported,
+    #[error("Failed to create the backend request channel: {0}")]
+    BackendReqChannelCreation(io::Error),
 }
 type Result<T> = std::result::Result<T, Error>;
 
@@ -158,13 +164,34 @@ pub const DEFAULT_VIRTIO_FEATURES: u64 = (1 << VIRTIO_F_RING_INDIRECT_DESC)
 const HUP_CONNECTION_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 1;
 const BACKEND_REQ_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 2;
 
+/// Channel through which a vhost-user backend sends its requests.
+pub trait BackendReqChannel: AsRawFd {
+    /// File descriptor handed over to the backend to send requests.
+    fn backend_fd(&self) -> RawFd;
+
+    /// Handle a request pending on the channel.
+    fn handle_request(&mut self) -> io::Result<()>;
+}
+
+impl<S: VhostUserFrontendReqHandler> BackendReqChannel for FrontendReqHandler<S> {
+    fn backend_fd(&self) -> RawFd {
+        self.get_tx_raw_fd()
+    }
+
+    fn handle_request(&mut self) -> io::Result<()> {
+        FrontendReqHandler::handle_request(self)
+            .map(|_| ())
+            .map_err(|e| io::Error::other(format!("{e:?}")))
+    }
+}
+
 #[derive(Default)]
 pub struct Inflight {
     pub info: VhostUserInflight,
     pub fd: Option<std::fs::File>,
 }
 
-pub struct VhostUserEpollHandler<S: VhostUserFrontendReqHandler> {
+pub struct VhostUserEpollHandler<S: BackendReqChannel> {
     pub vu: Arc<Mutex<VhostUserHandle>>,
     pub mem: GuestMemoryAtomic<GuestMemoryMmap>,
     pub kill_evt: EventFd,
@@ -175,11 +202,11 @@ pub struct VhostUserEpollHandler<S: VhostUserFrontendReqHandler> {
     pub acked_protocol_features: u64,
     pub socket_path: String,
     pub server: bool,
-    pub backend_req_handler: Option<FrontendReqHandler<S>>,
+    pub backend_req_handler: Option<S>,
     pub inflight: Option<Inflight>,
 }
 
-impl<S: VhostUserFrontendReqHandler> VhostUserEpollHandler<S> {
+impl<S: BackendReqChannel> VhostUserEpollHandler<S> {
     pub fn run(
         &mut self,
         paused: Arc<AtomicBool>,
@@ -254,7 +281,7 @@ impl<S: VhostUserFrontendReqHandler> VhostUserEpollHandler<S> {
     }
 }
 
-impl<S: VhostUserFrontendReqHandler> EpollHelperHandler for VhostUserEpollHandler<S> {
+impl<S: BackendReqChannel> EpollHelperHandler for VhostUserEpollHandler<S> {
     fn handle_event(
         &mut self,
         helper: &mut EpollHelper,
@@ -303,13 +330,13 @@ pub struct VhostUserCommon {
 
 impl VhostUserCommon {
     #[allow(clippy::too_many_arguments)]
-    pub fn activate<T: VhostUserFrontendReqHandler>(
+    pub fn activate<T: BackendReqChannel>(
         &mut self,
         mem: GuestMemoryAtomic<GuestMemoryMmap>,
         queues: Vec<(usize, Queue, EventFd)>,
         interrupt_cb: Arc<dyn VirtioInterrupt>,
         acked_features: u64,
-        backend_req_handler: Option<FrontendReqHandler<T>>,
+        backend_req_handler: Option<T>,
         kill_evt: EventFd,
         pause_evt: EventFd,
     ) -> std::result::Result<VhostUserEpollHandler<T>, ActivateError> {
@@ -409,6 +436,33 @@ impl VhostUserCommon {
         Ok(())
     }
 
+    pub fn remove_memory_region(
+        &mut self,
+        guest_memory: &Option<GuestMemoryAtomic<GuestMemoryMmap>>,
+        region: &Arc<GuestRegionMmap>,
+    ) -> std::result::Result<(), crate::Error> {
+        if let Some(vu) = &self.vu {
+            if self.acked_protocol_features & VhostUserProtocolFeatures::CONFIGURE_MEM_SLOTS.bits()
+                != 0
+            {
+                return vu
+                    .lock()
+                    .unwrap()
+                    .remove_memory_region(region)
+                    .map_err(crate::Error::VhostUserRemoveMemoryRegion);
+            } else if let Some(guest_memory) = guest_memory {
+                // The region is already gone from the guest memory, sending
+                // the whole table again is enough.
+                return vu
+                    .lock()
+                    .unwrap()
+                    .update_mem_table(guest_memory.memory().deref())
+                    .map_err(crate::Error::VhostUserUpdateMemory);
+            }
+        }
+        Ok(())
+    }
+
     pub fn pause(&mut self) -> std::result::Result<(), MigratableError> {
         if let Some(vu) = &self.vu {
             vu.lock().unwrap().pause_vhost_user().map_err(|e| {
@@ -408,6 +408,14 @@ impl VirtioDevice for Net {
     ) -> std::result::Result<(), crate::Error> {
         self.vu_common.add_memory_region(&self.guest_memory, region)
     }
+
+    fn remove_memory_region(
+        &mut self,
+        region: &Arc<GuestRegionMmap>,
+    ) -> std::result::Result<(), crate::Error> {
+        self.vu_common
+            .remove_memory_region(&self.guest_memory, region)
+    }
 }
 
 impl Pausable for Net {
@@ -14,9 +14,7 @@ use vhost::vhost_kern::vhost_binding::{VHOST_F_LOG_ALL, VHOST_VRING_F_LOG};
 use vhost::vhost_user::message::{
     VhostUserHeaderFlag, VhostUserInflight, VhostUserProtocolFeatures, VhostUserVirtioFeatures,
 };
-use vhost::vhost_user::{
-    Frontend, FrontendReqHandler, VhostUserFrontend, VhostUserFrontendReqHandler,
-};
+use vhost::vhost_user::{Frontend, VhostUserFrontend};
 use vhost::{VhostBackend, VhostUserDirtyLogRegion, VhostUserMemoryRegionInfo, VringConfigData};
 use virtio_queue::{Descriptor, Queue, QueueT};
 use vm_memory::{
@@ -26,7 +24,7 @@ use vm_migration::protocol::MemoryRangeTable;
 use vmm_sys_util::eventfd::EventFd;
 
 use super::{Error, Result};
-use crate::vhost_user::Inflight;
+use crate::vhost_user::{BackendReqChannel, Inflight};
 use crate::{
     get_host_address_range, GuestMemoryMmap, GuestRegionMmap, MmapRegion, VirtioInterrupt,
     VirtioInterruptType,
@@ -105,6 +103,25 @@ impl VhostUserHandle {
             .map_err(Error::VhostUserAddMemReg)
     }
 
+    pub fn remove_memory_region(&mut self, region: &Arc<GuestRegionMmap>) -> Result<()> {
+        let (mmap_handle, mmap_offset) = match region.file_offset() {
+            Some(file_offset) => (file_offset.file().as_raw_fd(), file_offset.start()),
+            None => return Err(Error::MissingRegionFd),
+        };
+
+        let region = VhostUserMemoryRegionInfo {
+            guest_phys_addr: region.start_addr().raw_value(),
+            memory_size: region.len(),
+            userspace_addr: region.as_ptr() as u64,
+            mmap_offset,
+            mmap_handle,
+        };
+
+        self.vu
+            .remove_mem_region(&region)
+            .map_err(Error::VhostUserRemoveMemReg)
+    }
+
     pub fn negotiate_features_vhost_user(
         &mut self,
         avail_features: u64,
@@ -151,13 +168,13 @@ impl VhostUserHandle {
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn setup_vhost_user<S: VhostUserFrontendReqHandler>(
+    pub fn setup_vhost_user<S: BackendReqChannel>(
         &mut self,
         mem: &GuestMemoryMmap,
         queues: Vec<(usize, Queue, EventFd)>,
         virtio_interrupt: &Arc<dyn VirtioInterrupt>,
         acked_features: u64,
-        backend_req_handler: &Option<FrontendReqHandler<S>>,
+        backend_req_handler: &Option<S>,
         inflight: Option<&mut Inflight>,
     ) -> Result<()> {
         self.vu
@@ -271,7 +288,7 @@ impl VhostUserHandle {
 
         if let Some(backend_req_handler) = backend_req_handler {
             self.vu
-                .set_backend_request_fd(&backend_req_handler.get_tx_raw_fd())
+                .set_backend_request_fd(&backend_req_handler.backend_fd())
                 .map_err(Error::VhostUserSetBackendRequestFd)?;
         }
 
@@ -336,14 +353,14 @@ impl VhostUserHandle {
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn reinitialize_vhost_user<S: VhostUserFrontendReqHandler>(
+    pub fn reinitialize_vhost_user<S: BackendReqChannel>(
         &mut self,
         mem: &GuestMemoryMmap,
         queues: Vec<(usize, Queue, EventFd)>,
         virtio_interrupt: &Arc<dyn VirtioInterrupt>,
         acked_features: u64,
         acked_protocol_features: u64,
-        backend_req_handler: &Option<FrontendReqHandler<S>>,
+        backend_req_handler: &Option<S>,
         inflight: Option<&mut Inflight>,
     ) -> Result<()> {
         self.set_protocol_features_vhost_user(acked_features, acked_protocol_features)?;
@@ -22,6 +22,19 @@
 //!   consume it.  If that data can't be forwarded straight to the host stream, we'll
 //!   have to store it in a buffer (and flush it at a later time). Vsock flow control
 //!   ensures that our TX buffer doesn't overflow.
+//!
+//! Seqpacket connections (VSOCK_TYPE_SEQPACKET) follow the same state machine, but must also
+//! preserve message boundaries:
+//! - guest messages may be split across several RW packets, the last one being flagged with
+//!   VSOCK_FLAGS_SEQ_EOM. Fragments are gathered until the whole message is available, and it
+//!   is then written to the host socket in one go. Records (VSOCK_FLAGS_SEQ_EOR) have no
+//!   equivalent on Unix sockets, where every message stands on its own;
+//! - host messages are read whole, and then fragmented into as many RW packets as needed, the
+//!   last one being flagged with VSOCK_FLAGS_SEQ_EOM and VSOCK_FLAGS_SEQ_EOR.
+//!
+//! A message which can't fit in the receiving buffer (`buf_alloc` for guest messages, the
+//! peer `buf_alloc` for host messages) could never be received whole. It is dropped, but the
+//! connection is kept alive. Linux guests fail sending such messages with EMSGSIZE anyway.
 //
 // The code in this file is best read with a fresh memory of the vsock protocol inner-workings.
 // To help with that, here is a
@@ -80,7 +93,8 @@
 //             it thinks its peer's information is out of date.
 //          Our implementation uses the proactive approach.
 //
-use std::io::{ErrorKind, Read, Write};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
 use std::num::Wrapping;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::time::{Duration, Instant};
@@ -105,10 +119,23 @@ pub struct VsockConnection<S: Read + Write + AsRawFd> {
     local_port: u32,
     /// The peer (guest) port.
     peer_port: u32,
+    /// The vsock socket type of this connection (VSOCK_TYPE_STREAM or VSOCK_TYPE_SEQPACKET).
+    type_: u16,
     /// The (connected) host-side stream.
     stream: S,
     /// The TX buffer for this connection.
     tx_buf: TxBuf,
+    /// Seqpacket only: the guest message currently being gathered from RW packets.
+    tx_msg: Vec<u8>,
+    /// Seqpacket only: the guest message currently being gathered is too large, and its
+    /// remaining fragments are dropped.
+    tx_msg_dropped: bool,
+    /// Seqpacket only: complete guest messages waiting for the host socket to become writable.
+    tx_msg_queue: VecDeque<Vec<u8>>,
+    /// Seqpacket only: the host message currently being fragmented into RW packets.
+    rx_msg: Vec<u8>,
+    /// Seqpacket only: how much of `rx_msg` has already been sent to the peer.
+    rx_msg_off: usize,
     /// Total number of bytes that have been successfully written to `self.stream`, either
     /// directly, or flushed from `self.tx_buf`.
     fwd_cnt: Wrapping<u32>,
@@ -210,8 +237,17 @@ where
             let max_len = std::cmp::min(buf.len(), self.peer_avail_credit());
 
             // Read data from the stream straight to the RX buffer, for maximum throughput.
-            match self.stream.read(&mut buf[..max_len]) {
-                Ok(read_cnt) => {
+            // Seqpacket messages go through `self.rx_msg` instead, since they have to be read
+            // whole.
+            let read_res = if self.type_ == uapi::VSOCK_TYPE_SEQPACKET {
+                self.read_msg_fragment(&mut buf[..max_len])
+            } else {
+                self.stream
+                    .read(&mut buf[..max_len])
+                    .map(|read_cnt| (read_cnt, false))
+            };
+            match read_res {
+                Ok((read_cnt, eom)) => {
                     if read_cnt == 0 {
                         // A 0-length read means the host stream was closed down. In that case,
                         // we'll ask our peer to shut down the connection. We can neither send nor
@@ -227,11 +263,28 @@ where
                         // On a successful data read, we fill in the packet with the RW op, and
                         // length of the read data.
                         pkt.set_op(uapi::VSOCK_OP_RW).set_len(read_cnt as u32);
+                        // Host messages are complete records.
+                        if eom {
+                            pkt.set_flag(uapi::VSOCK_FLAGS_SEQ_EOM)
+                                .set_flag(uapi::VSOCK_FLAGS_SEQ_EOR);
+                        }
+                        // The rest of a partially sent message can't wait for the next EPOLLIN,
+                        // since the whole message has already been read from the host socket.
+                        if !self.rx_msg.is_empty() {
+                            self.pending_rx.insert(PendingRx::Rw);
+                        }
                     }
                     self.rx_cnt += Wrapping(pkt.len());
                     self.last_fwd_cnt_to_peer = self.fwd_cnt;
                     return Ok(());
                 }
+                Err(err) if err.raw_os_error() == Some(libc::EMSGSIZE) => {
+                    // The host message was dropped, the next one may fit.
+                    warn!(
+                        "vsock: dropping host message too large for the peer: lp={}, pp={}",
+                        self.local_port, self.peer_port
+                    );
+                }
                 Err(err) if err.kind() == ErrorKind::WouldBlock => {
                     // This shouldn't actually happen (receiving EWOULDBLOCK after EPOLLIN), but
                     // apparently it does, so we need to handle it gracefully.
@@ -282,6 +335,12 @@ where
         self.peer_buf_alloc = pkt.buf_alloc();
         self.peer_fwd_cnt = Wrapping(pkt.fwd_cnt());
 
+        // If we were waiting for credit to send the rest of a seqpacket message, we can go on
+        // now.
+        if !self.rx_msg.is_empty() && !self.need_credit_update_from_peer() {
+            self.pending_rx.insert(PendingRx::Rw);
+        }
+
         match self.state {
             // Most frequent case: this is an established connection that needs to forward some
             // data to the host stream. Also works for a connection that has begun shutting
@@ -299,7 +358,12 @@ where
 
                 // Unwrapping here is safe, since we just checked `pkt.buf()` above.
                 let buf_slice = &pkt.buf().unwrap()[..(pkt.len() as usize)];
-                if let Err(err) = self.send_bytes(buf_slice) {
+                let res = if self.type_ == uapi::VSOCK_TYPE_SEQPACKET {
+                    self.send_msg_fragment(buf_slice, pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM != 0)
+                } else {
+                    self.send_bytes(buf_slice)
+                };
+                if let Err(err) = res {
                     // If we can't write to the host stream, that's an unrecoverable error, so
                     // we'll terminate this connection.
                     warn!(
@@ -335,7 +399,7 @@ where
                 let send_off = pkt.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_SEND != 0;
                 self.state = ConnState::PeerClosed(recv_off, send_off);
                 if recv_off && send_off {
-                    if self.tx_buf.is_empty() {
+                    if self.tx_is_empty() {
                         self.pending_rx.insert(PendingRx::Rst);
                     } else {
                         self.expiry = Some(
@@ -352,7 +416,7 @@ where
             {
                 *recv_off = *recv_off || (pkt.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_RCV != 0);
                 *send_off = *send_off || (pkt.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_SEND != 0);
-                if *recv_off && *send_off && self.tx_buf.is_empty() {
+                if *recv_off && *send_off && self.tx_is_empty() {
                     self.pending_rx.insert(PendingRx::Rst);
                 }
             }
@@ -414,7 +478,7 @@ where
     ///
     fn get_polled_evset(&self) -> epoll::Events {
         let mut evset = epoll::Events::empty();
-        if !self.tx_buf.is_empty() {
+        if !self.tx_is_empty() {
             // There's data waiting in the TX buffer, so we are interested in being notified
             // when writing to the host stream wouldn't block.
             evset.insert(epoll::Events::EPOLLOUT);
@@ -441,32 +505,34 @@ where
         if evset.contains(epoll::Events::EPOLLOUT) {
             // Data can be written to the host stream. Time to flush out the TX buffer.
             //
-            if self.tx_buf.is_empty() {
+            if self.tx_is_empty() {
                 info!("vsock: connection received unexpected EPOLLOUT event");
                 return;
             }
-            let flushed = self
-                .tx_buf
-                .flush_to(&mut self.stream)
-                .unwrap_or_else(|err| {
-                    warn!(
-                        "vsock: error flushing TX buf for (lp={}, pp={}): {:?}",
-                        self.local_port, self.peer_port, err
-                    );
-                    match err {
-                        Error::TxBufFlush(inner) if inner.kind() == ErrorKind::WouldBlock => {
-                            // This should never happen (EWOULDBLOCK after EPOLLOUT), but
-                            // it does, so let's absorb it.
-                        }
-                        _ => self.kill(),
-                    };
-                    0
-                });
+            let flush_res = if self.type_ == uapi::VSOCK_TYPE_SEQPACKET {
+                self.flush_tx_msg_queue()
+            } else {
+                self.tx_buf.flush_to(&mut self.stream)
+            };
+            let flushed = flush_res.unwrap_or_else(|err| {
+                warn!(
+                    "vsock: error flushing TX buf for (lp={}, pp={}): {:?}",
+                    self.local_port, self.peer_port, err
+                );
+                match err {
+                    Error::TxBufFlush(inner) if inner.kind() == ErrorKind::WouldBlock => {
+                        // This should never happen (EWOULDBLOCK after EPOLLOUT), but
+                        // it does, so let's absorb it.
+                    }
+                    _ => self.kill(),
+                };
+                0
+            });
             self.fwd_cnt += Wrapping(flushed as u32);
 
             // If this connection was shutting down, but is waiting to drain the TX buffer
             // before forceful termination, the wait might be over.
-            if self.state == ConnState::PeerClosed(true, true) && self.tx_buf.is_empty() {
+            if self.state == ConnState::PeerClosed(true, true) && self.tx_is_empty() {
                 self.pending_rx.insert(PendingRx::Rst);
             } else if self.peer_needs_credit_update() {
                 // If we've freed up some more buffer space, we may need to let the peer know it
@@ -490,15 +556,22 @@ where
         local_port: u32,
         peer_port: u32,
         peer_buf_alloc: u32,
+        type_: u16,
     ) -> Self {
         Self {
             local_cid,
             peer_cid,
             local_port,
             peer_port,
+            type_,
             stream,
             state: ConnState::PeerInit,
             tx_buf: TxBuf::new(),
+            tx_msg: Vec::new(),
+            tx_msg_dropped: false,
+            tx_msg_queue: VecDeque::new(),
+            rx_msg: Vec::new(),
+            rx_msg_off: 0,
             fwd_cnt: Wrapping(0),
             peer_buf_alloc,
             peer_fwd_cnt: Wrapping(0),
@@ -517,15 +590,22 @@ where
         peer_cid: u64,
         local_port: u32,
         peer_port: u32,
+        type_: u16,
     ) -> Self {
         Self {
             local_cid,
             peer_cid,
             local_port,
             peer_port,
+            type_,
             stream,
             state: ConnState::LocalInit,
             tx_buf: TxBuf::new(),
+            tx_msg: Vec::new(),
+            tx_msg_dropped: false,
+            tx_msg_queue: VecDeque::new(),
+            rx_msg: Vec::new(),
+            rx_msg_off: 0,
             fwd_cnt: Wrapping(0),
             peer_buf_alloc: 0,
             peer_fwd_cnt: Wrapping(0),
@@ -627,6 +707,127 @@ where
         Ok(())
     }
 
+    /// Gather a fragment of a guest seqpacket message, and send the message to the host socket
+    /// once its last fragment (`eom`) has arrived.
+    ///
+    /// Seqpacket sockets write whole messages or nothing, so a message that can't be written
+    /// right away is queued until the host socket becomes writable again.
+    ///
+    fn send_msg_fragment(&mut self, buf: &[u8], eom: bool) -> Result<()> {
+        // A message larger than our buffer size can't be gathered. It is dropped, and
+        // accounted for as forwarded so that the peer gets its credit back.
+        if self.tx_msg_dropped || self.tx_msg.len() + buf.len() > defs::CONN_TX_BUF_SIZE as usize {
+            if !self.tx_msg_dropped {
+                warn!(
+                    "vsock: dropping guest message larger than {} bytes: lp={}, pp={}",
+                    defs::CONN_TX_BUF_SIZE,
+                    self.local_port,
+                    self.peer_port
+                );
+            }
+            self.fwd_cnt += Wrapping((self.tx_msg.len() + buf.len()) as u32);
+            self.tx_msg.clear();
+            self.tx_msg_dropped = !eom;
+            return Ok(());
+        }
+
+        // Flow control guarantees that the peer doesn't send more than our buffer size worth
+        // of data that hasn't been forwarded yet.
+        let pending_len =
+            self.tx_msg.len() + self.tx_msg_queue.iter().map(Vec::len).sum::<usize>() + buf.len();
+        if pending_len > defs::CONN_TX_BUF_SIZE as usize {
+            return Err(Error::TxBufFull);
+        }
+
+        self.tx_msg.extend_from_slice(buf);
+        if !eom {
+            return Ok(());
+        }
+
+        let msg = std::mem::take(&mut self.tx_msg);
+        // Keep messages ordered: if some are already waiting, this one has to wait too.
+        if !self.tx_msg_queue.is_empty() {
+            self.tx_msg_queue.push_back(msg);
+            return Ok(());
+        }
+
+        match self.stream.write(&msg) {
+            Ok(_) => self.fwd_cnt += Wrapping(msg.len() as u32),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => self.tx_msg_queue.push_back(msg),
+            Err(e) => return Err(Error::StreamWrite(e)),
+        }
+
+        Ok(())
+    }
+
+    /// Write as many queued seqpacket messages as possible to the host socket.
+    /// Returns: the number of bytes written.
+    ///
+    fn flush_tx_msg_queue(&mut self) -> Result<usize> {
+        let mut flushed = 0;
+        while let Some(msg) = self.tx_msg_queue.front() {
+            match self.stream.write(msg) {
+                Ok(_) => {
+                    flushed += msg.len();
+                    self.tx_msg_queue.pop_front();
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(Error::TxBufFlush(e)),
+            }
+        }
+        Ok(flushed)
+    }
+
+    /// Fill `buf` with the next fragment of a host seqpacket message, reading a new message
+    /// from the host socket if the previous one has been entirely sent to the peer.
+    /// Returns: the fragment length, and whether this is the last fragment of the message.
+    ///
+    /// Fails with EMSGSIZE if the message is too large for the peer, in which case it is
+    /// dropped.
+    ///
+    fn read_msg_fragment(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        if self.rx_msg.is_empty() {
+            // The peer only gets a message once it's whole in its buffer. Reading one more
+            // byte than that tells apart a message which just fits from a truncated one.
+            let max_len = std::cmp::min(self.peer_buf_alloc as usize, defs::MAX_SEQPACKET_MSG_SIZE);
+            self.rx_msg.resize(max_len + 1, 0);
+            let len = match self.stream.read(&mut self.rx_msg) {
+                Ok(len) => len,
+                Err(e) => {
+                    self.rx_msg.clear();
+                    return Err(e);
+                }
+            };
+            if len > max_len {
+                self.rx_msg.clear();
+                return Err(io::Error::from_raw_os_error(libc::EMSGSIZE));
+            }
+            if len == 0 {
+                self.rx_msg.clear();
+                return Ok((0, false));
+            }
+            self.rx_msg.truncate(len);
+            self.rx_msg_off = 0;
+        }
+
+        let len = std::cmp::min(buf.len(), self.rx_msg.len() - self.rx_msg_off);
+        buf[..len].copy_from_slice(&self.rx_msg[self.rx_msg_off..self.rx_msg_off + len]);
+        self.rx_msg_off += len;
+
+        let eom = self.rx_msg_off == self.rx_msg.len();
+        if eom {
+            self.rx_msg.clear();
+            self.rx_msg_off = 0;
+        }
+        Ok((len, eom))
+    }
+
+    /// Check if there is no guest data waiting to be written to the host stream.
+    ///
+    fn tx_is_empty(&self) -> bool {
+        self.tx_buf.is_empty() && self.tx_msg_queue.is_empty()
+    }
+
     /// Check if the credit information the peer has last received from us is outdated.
     ///
     fn peer_needs_credit_update(&self) -> bool {
@@ -663,7 +864,7 @@ where
             .set_dst_cid(self.peer_cid)
             .set_src_port(self.local_port)
             .set_dst_port(self.peer_port)
-            .set_type(uapi::VSOCK_TYPE_STREAM)
+            .set_type(self.type_)
             .set_buf_alloc(defs::CONN_TX_BUF_SIZE)
             .set_fwd_cnt(self.fwd_cnt.0)
     }
@@ -811,6 +1012,10 @@ mod tests {
         }
 
         fn new(conn_state: ConnState) -> Self {
+            Self::new_with_type(conn_state, uapi::VSOCK_TYPE_STREAM)
+        }
+
+        fn new_with_type(conn_state: ConnState, type_: u16) -> Self {
             let vsock_test_ctx = TestContext::new();
             let mut handler_ctx = vsock_test_ctx.create_epoll_handler_context();
             let stream = TestStream::new();
@@ -831,9 +1036,10 @@ mod tests {
                     LOCAL_PORT,
                     PEER_PORT,
                     PEER_BUF_ALLOC,
+                    type_,
                 ),
                 ConnState::LocalInit => VsockConnection::<TestStream>::new_local_init(
-                    stream, LOCAL_CID, PEER_CID, LOCAL_PORT, PEER_PORT,
+                    stream, LOCAL_CID, PEER_CID, LOCAL_PORT, PEER_PORT, type_,
                 ),
                 ConnState::Established => {
                     let mut conn = VsockConnection::<TestStream>::new_peer_init(
@@ -843,6 +1049,7 @@ mod tests {
                         LOCAL_PORT,
                         PEER_PORT,
                         PEER_BUF_ALLOC,
+                        type_,
                     );
                     assert!(conn.has_pending_rx());
                     conn.recv_pkt(&mut pkt).unwrap();
@@ -1245,4 +1452,135 @@ mod tests {
         ctx.recv();
         assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
     }
+
+    #[test]
+    fn test_seqpacket_tx() {
+        let mut ctx =
+            CsmTestContext::new_with_type(ConnState::Established, uapi::VSOCK_TYPE_SEQPACKET);
+
+        // A message fragment without EOM must not reach the host socket.
+        ctx.init_data_pkt(&[1, 2, 3]);
+        ctx.send();
+        assert!(ctx.conn.stream.write_buf.is_empty());
+        assert_eq!(ctx.conn.fwd_cnt.0, 0);
+
+        // Once the last fragment arrives, the whole message is written at once.
+        ctx.init_data_pkt(&[4, 5]);
+        ctx.pkt.set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        assert_eq!(ctx.conn.stream.write_buf, vec![1, 2, 3, 4, 5]);
+        assert_eq!(ctx.conn.fwd_cnt.0, 5);
+
+        // A message that can't be written right away is kept until EPOLLOUT.
+        let mut stream = TestStream::new();
+        stream.write_state = StreamState::WouldBlock;
+        ctx.set_stream(stream);
+        ctx.init_data_pkt(&[6, 7]);
+        ctx.pkt.set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        assert!(ctx
+            .conn
+            .get_polled_evset()
+            .contains(epoll::Events::EPOLLOUT));
+
+        ctx.conn.stream.write_state = StreamState::Ready;
+        ctx.notify_epollout();
+        assert_eq!(ctx.conn.stream.write_buf, vec![6, 7]);
+        assert_eq!(ctx.conn.fwd_cnt.0, 7);
+        assert!(!ctx
+            .conn
+            .get_polled_evset()
+            .contains(epoll::Events::EPOLLOUT));
+    }
+
+    #[test]
+    fn test_seqpacket_rx() {
+        let mut ctx =
+            CsmTestContext::new_with_type(ConnState::Established, uapi::VSOCK_TYPE_SEQPACKET);
+        let data = [1u8, 2, 3, 4, 5];
+        ctx.set_stream(TestStream::new_with_read_buf(&data));
+
+        // With only enough credit for part of the message, the first fragment shouldn't carry
+        // EOM.
+        ctx.set_peer_credit(3);
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_eq!(ctx.pkt.len(), 3);
+        assert_eq!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert_eq!(&ctx.pkt.buf().unwrap()[..3], &data[..3]);
+
+        // The rest of the message is pending, but we're out of credit.
+        assert!(ctx.conn.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_CREDIT_REQUEST);
+
+        // A credit update from the peer lets us send the last fragment.
+        ctx.init_pkt(uapi::VSOCK_OP_CREDIT_UPDATE, 0)
+            .set_fwd_cnt(PEER_BUF_ALLOC);
+        ctx.send();
+        assert!(ctx.conn.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.pkt.len(), 2);
+        assert_ne!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert_ne!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOR, 0);
+        assert_eq!(&ctx.pkt.buf().unwrap()[..2], &data[3..]);
+        assert!(!ctx.conn.has_pending_rx());
+    }
+
+    #[test]
+    fn test_seqpacket_rx_too_large() {
+        let mut ctx =
+            CsmTestContext::new_with_type(ConnState::Established, uapi::VSOCK_TYPE_SEQPACKET);
+
+        // A message which can't fit in the peer buffer is dropped.
+        ctx.set_stream(TestStream::new_with_read_buf(
+            &[0u8; PEER_BUF_ALLOC as usize + 1],
+        ));
+        ctx.notify_epollin();
+        ctx.conn.recv_pkt(&mut ctx.pkt).unwrap_err();
+        assert!(ctx.conn.stream.read_buf.is_empty());
+        assert_eq!(ctx.conn.rx_cnt.0, 0);
+
+        // The connection carries on with the next message.
+        ctx.conn.stream.read_buf = vec![1, 2];
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.pkt.len(), 2);
+        assert_ne!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert_eq!(ctx.conn.state, ConnState::Established);
+    }
+
+    #[test]
+    fn test_seqpacket_tx_too_large() {
+        let mut ctx =
+            CsmTestContext::new_with_type(ConnState::Established, uapi::VSOCK_TYPE_SEQPACKET);
+        let frag = vec![0xaau8; ctx.pkt.buf().unwrap().len()];
+
+        // Send fragments until the message exceeds our buffer size, and end it.
+        let mut sent = 0;
+        while sent <= csm_defs::CONN_TX_BUF_SIZE as usize {
+            ctx.init_data_pkt(&frag);
+            ctx.send();
+            sent += frag.len();
+        }
+        ctx.init_data_pkt(&frag);
+        ctx.pkt.set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        sent += frag.len();
+
+        // The message is dropped, but the peer gets its credit back.
+        assert!(ctx.conn.stream.write_buf.is_empty());
+        assert_eq!(ctx.conn.fwd_cnt.0 as usize, sent);
+        assert_eq!(ctx.conn.state, ConnState::Established);
+
+        // The next message goes through.
+        ctx.init_data_pkt(&[1, 2, 3]);
+        ctx.pkt.set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        assert_eq!(ctx.conn.stream.write_buf, vec![1, 2, 3]);
+    }
 }
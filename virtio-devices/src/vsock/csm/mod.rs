@@ -24,6 +24,10 @@ pub mod defs {
 
     /// Connection graceful shutdown timeout, in millis.
     pub const CONN_SHUTDOWN_TIMEOUT_MS: u64 = 2000;
+
+    /// Largest seqpacket message that can be read from a host socket. Larger messages
+    /// wouldn't fit in any reasonably sized guest socket buffer.
+    pub const MAX_SEQPACKET_MSG_SIZE: usize = 1024 * 1024;
 }
 
 #[derive(Debug, Error)]
@@ -43,6 +43,7 @@ use vmm_sys_util::eventfd::EventFd;
 /// - an event queue FD; and
 /// - a backend FD.
 ///
+use super::defs::uapi;
 use super::{VsockBackend, VsockPacket};
 use crate::seccomp_filters::Thread;
 use crate::thread_helper::spawn_virtio_thread;
@@ -336,6 +337,7 @@ where
         path: PathBuf,
         backend: B,
         iommu: bool,
+        seqpacket: bool,
         seccomp_action: SeccompAction,
         exit_evt: EventFd,
         state: Option<VsockState>,
@@ -346,6 +348,10 @@ where
         } else {
             let mut avail_features = (1u64 << VIRTIO_F_VERSION_1) | (1u64 << VIRTIO_F_IN_ORDER);
 
+            if seqpacket {
+                avail_features |= 1u64 << uapi::VIRTIO_VSOCK_F_SEQPACKET;
+            }
+
             if iommu {
                 avail_features |= 1u64 << VIRTIO_F_IOMMU_PLATFORM;
             }
@@ -487,6 +493,13 @@ where
 
     fn shutdown(&mut self) {
         std::fs::remove_file(&self.path).ok();
+        // The Unix backend also listens for SOCK_SEQPACKET host connections, next to the
+        // main socket.
+        if self.common.avail_features & (1u64 << uapi::VIRTIO_VSOCK_F_SEQPACKET) != 0 {
+            let mut seqpacket_path = self.path.clone().into_os_string();
+            seqpacket_path.push("_seqpacket");
+            std::fs::remove_file(seqpacket_path).ok();
+        }
     }
 
     fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
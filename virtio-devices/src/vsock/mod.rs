@@ -27,6 +27,12 @@ mod defs {
 
     pub mod uapi {
 
+        /// Vsock device feature bits.
+        /// Defined in `/include/uapi/linux/virtio_vsock.h`.
+        ///
+        /// SOCK_SEQPACKET sockets are supported.
+        pub const VIRTIO_VSOCK_F_SEQPACKET: u64 = 1;
+
         /// Vsock packet operation IDs.
         /// Defined in `/include/uapi/linux/virtio_vsock.h`.
         ///
@@ -52,12 +58,21 @@ mod defs {
         pub const VSOCK_FLAGS_SHUTDOWN_RCV: u32 = 1;
         /// Valid with a VSOCK_OP_SHUTDOWN packet: the packet sender will send no more data.
         pub const VSOCK_FLAGS_SHUTDOWN_SEND: u32 = 2;
+        /// Valid with a VSOCK_OP_RW packet on a seqpacket connection: the packet carries the
+        /// last fragment of a message.
+        pub const VSOCK_FLAGS_SEQ_EOM: u32 = 1;
+        /// Valid with a VSOCK_OP_RW packet on a seqpacket connection: the message ends a
+        /// record (i.e. it was sent with MSG_EOR).
+        pub const VSOCK_FLAGS_SEQ_EOR: u32 = 2;
 
         /// Vsock packet type.
         /// Defined in `/include/uapi/linux/virtio_vsock.h`.
         ///
-        /// Stream / connection-oriented packet (the only currently valid type).
+        /// Stream / connection-oriented packet.
         pub const VSOCK_TYPE_STREAM: u16 = 1;
+        /// Seqpacket / connection-oriented packet, preserving message boundaries. Only valid
+        /// if VIRTIO_VSOCK_F_SEQPACKET has been negotiated.
+        pub const VSOCK_TYPE_SEQPACKET: u16 = 2;
 
         pub const VSOCK_HOST_CID: u64 = 2;
     }
@@ -278,6 +293,7 @@ pub mod tests {
                     PathBuf::from("/test/sock"),
                     TestBackend::new(),
                     false,
+                    false,
                     seccompiler::SeccompAction::Trap,
                     EventFd::new(EFD_NONBLOCK).unwrap(),
                     None,
@@ -37,11 +37,19 @@
 //!
 //! To route all these events to their handlers, the muxer uses another `HashMap` object,
 //! mapping `RawFd`s to `EpollListener`s.
+//!
+//! ## Seqpacket connections
+//!
+//! When enabled, guest SOCK_SEQPACKET connections are forwarded to host-side SOCK_SEQPACKET
+//! Unix sockets, listening at the same per-port paths as stream sockets. Host-initiated
+//! seqpacket connections are accepted on a second listening socket, at
+//! "\<host socket path>_seqpacket", and expect the "connect \<port>" command to be sent as a
+//! single message.
 
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, ErrorKind, Read};
-use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
 
 use super::super::csm::ConnState;
@@ -69,8 +77,12 @@ pub struct ConnMapKey {
 pub enum MuxerRx {
     /// The packet must be fetched from the connection identified by `ConnMapKey`.
     ConnRx(ConnMapKey),
-    /// The muxer must produce an RST packet.
-    RstPkt { local_port: u32, peer_port: u32 },
+    /// The muxer must produce an RST packet, of the given vsock socket type.
+    RstPkt {
+        local_port: u32,
+        peer_port: u32,
+        type_: u16,
+    },
 }
 
 /// An epoll listener, registered under the muxer's nested epoll FD.
@@ -85,9 +97,13 @@ enum EpollListener {
     },
     /// A listener interested in new host-initiated connections.
     HostSock,
+    /// A listener interested in new host-initiated seqpacket connections.
+    HostSeqpacketSock,
     /// A listener interested in reading host "connect \<port>" commands from a freshly
     /// connected host socket.
     LocalStream(UnixStream),
+    /// Same as `LocalStream`, for a freshly connected host seqpacket socket.
+    LocalSeqpacket(UnixStream),
 }
 
 /// A partially read "CONNECT" command.
@@ -121,6 +137,9 @@ pub struct VsockMuxer {
     killq: MuxerKillQ,
     /// The Unix socket, through which host-initiated connections are accepted.
     host_sock: UnixListener,
+    /// The SOCK_SEQPACKET Unix socket, through which host-initiated seqpacket connections are
+    /// accepted. Only set if seqpacket connections are enabled.
+    host_seqpacket_sock: Option<UnixListener>,
     /// The file system path of the host-side Unix socket. This is used to figure out the path
     /// to Unix sockets listening on specific ports. I.e. "\<this path>_\<port number>".
     host_sock_path: String,
@@ -156,6 +175,7 @@ impl VsockChannel for VsockMuxer {
                 MuxerRx::RstPkt {
                     local_port,
                     peer_port,
+                    type_,
                 } => {
                     pkt.set_op(uapi::VSOCK_OP_RST)
                         .set_src_cid(uapi::VSOCK_HOST_CID)
@@ -163,7 +183,7 @@ impl VsockChannel for VsockMuxer {
                         .set_src_port(local_port)
                         .set_dst_port(peer_port)
                         .set_len(0)
-                        .set_type(uapi::VSOCK_TYPE_STREAM)
+                        .set_type(type_)
                         .set_flags(0)
                         .set_buf_alloc(0)
                         .set_fwd_cnt(0);
@@ -227,10 +247,16 @@ impl VsockChannel for VsockMuxer {
             pkt.hdr()
         );
 
-        // If this packet has an unsupported type (!=stream), we must send back an RST.
+        // If this packet has an unsupported type (neither stream nor enabled seqpacket), we must
+        // send back an RST.
         //
-        if pkt.type_() != uapi::VSOCK_TYPE_STREAM {
-            self.enq_rst(pkt.dst_port(), pkt.src_port());
+        let seqpacket = self.host_seqpacket_sock.is_some();
+        if pkt.type_() == uapi::VSOCK_TYPE_SEQPACKET && !seqpacket {
+            self.enq_rst(pkt.dst_port(), pkt.src_port(), uapi::VSOCK_TYPE_SEQPACKET);
+            return Ok(());
+        }
+        if pkt.type_() != uapi::VSOCK_TYPE_STREAM && pkt.type_() != uapi::VSOCK_TYPE_SEQPACKET {
+            self.enq_rst(pkt.dst_port(), pkt.src_port(), uapi::VSOCK_TYPE_STREAM);
             return Ok(());
         }
 
@@ -253,7 +279,7 @@ impl VsockChannel for VsockMuxer {
                 self.handle_peer_request_pkt(pkt);
             } else {
                 // Send back an RST, to let the drive know we weren't expecting this packet.
-                self.enq_rst(pkt.dst_port(), pkt.src_port());
+                self.enq_rst(pkt.dst_port(), pkt.src_port(), pkt.type_());
             }
             return Ok(());
         }
@@ -343,9 +369,9 @@ impl VsockEpollListener for VsockMuxer {
 impl VsockBackend for VsockMuxer {}
 
 impl VsockMuxer {
-    /// Muxer constructor.
+    /// Muxer constructor. Seqpacket connections are only supported if `seqpacket` is set.
     ///
-    pub fn new(cid: u32, host_sock_path: String) -> Result<Self> {
+    pub fn new(cid: u32, host_sock_path: String, seqpacket: bool) -> Result<Self> {
         // Create the nested epoll FD. This FD will be added to the VMM `EpollContext`, at
         // device activation time.
         let epoll_fd = epoll::create(true).map_err(Error::EpollFdCreate)?;
@@ -359,14 +385,24 @@ impl VsockMuxer {
             .and_then(|sock| sock.set_nonblocking(true).map(|_| sock))
             .map_err(Error::UnixBind)?;
 
+        let host_seqpacket_sock = if seqpacket {
+            let sock = seqpacket_listen(&format!("{host_sock_path}_seqpacket"))
+                .and_then(|sock| sock.set_nonblocking(true).map(|_| sock))
+                .map_err(Error::UnixBind)?;
+            Some(sock)
+        } else {
+            None
+        };
+
         let mut muxer = Self {
             cid: cid.into(),
             host_sock,
+            host_seqpacket_sock,
             host_sock_path,
             epoll_file,
             rxq: MuxerRxQ::new(),
             conn_map: HashMap::with_capacity(defs::MAX_CONNECTIONS),
-            listener_map: HashMap::with_capacity(defs::MAX_CONNECTIONS + 1),
+            listener_map: HashMap::with_capacity(defs::MAX_CONNECTIONS + 2),
             partial_command_map: Default::default(),
             killq: MuxerKillQ::new(),
             local_port_last: (1u32 << 30) - 1,
@@ -374,6 +410,13 @@ impl VsockMuxer {
         };
 
         muxer.add_listener(muxer.host_sock.as_raw_fd(), EpollListener::HostSock)?;
+        if let Some(fd) = muxer
+            .host_seqpacket_sock
+            .as_ref()
+            .map(|sock| sock.as_raw_fd())
+        {
+            muxer.add_listener(fd, EpollListener::HostSeqpacketSock)?;
+        }
         Ok(muxer)
     }
 
@@ -402,15 +445,26 @@ impl VsockMuxer {
 
             // A new host-initiated connection is ready to be accepted.
             //
-            Some(EpollListener::HostSock) => {
+            Some(EpollListener::HostSock) | Some(EpollListener::HostSeqpacketSock) => {
+                let seqpacket = matches!(
+                    self.listener_map.get(&fd),
+                    Some(EpollListener::HostSeqpacketSock)
+                );
+                let host_sock = if seqpacket {
+                    // It's safe to unwrap here, since this listener is only registered when
+                    // the seqpacket socket exists.
+                    self.host_seqpacket_sock.as_ref().unwrap()
+                } else {
+                    &self.host_sock
+                };
                 if self.conn_map.len() == defs::MAX_CONNECTIONS {
                     // If we're already maxed-out on connections, we'll just accept and
                     // immediately discard this potentially new one.
                     warn!("vsock: connection limit reached; refusing new host connection");
-                    self.host_sock.accept().map(|_| 0).unwrap_or(0);
+                    host_sock.accept().map(|_| 0).unwrap_or(0);
                     return;
                 }
-                self.host_sock
+                host_sock
                     .accept()
                     .map_err(Error::UnixAccept)
                     .and_then(|(stream, _)| {
@@ -424,7 +478,12 @@ impl VsockMuxer {
                         // the guest side, we need to know the destination port. We'll read
                         // that port from a "connect" command received on this socket, so the
                         // next step is to ask to be notified the moment we can read from it.
-                        self.add_listener(stream.as_raw_fd(), EpollListener::LocalStream(stream))
+                        let fd = stream.as_raw_fd();
+                        if seqpacket {
+                            self.add_listener(fd, EpollListener::LocalSeqpacket(stream))
+                        } else {
+                            self.add_listener(fd, EpollListener::LocalStream(stream))
+                        }
                     })
                     .unwrap_or_else(|err| {
                         warn!("vsock: unable to accept local connection: {:?}", err);
@@ -433,42 +492,52 @@ impl VsockMuxer {
 
             // Data is ready to be read from a host-initiated connection. That would be the
             // "connect" command that we're expecting.
-            Some(EpollListener::LocalStream(_)) => {
-                if let Some(EpollListener::LocalStream(stream)) = self.listener_map.get_mut(&fd) {
-                    let port = Self::read_local_stream_port(&mut self.partial_command_map, stream);
+            Some(EpollListener::LocalStream(_)) | Some(EpollListener::LocalSeqpacket(_)) => {
+                let (port, type_) = match self.listener_map.get_mut(&fd) {
+                    Some(EpollListener::LocalStream(stream)) => (
+                        Self::read_local_stream_port(&mut self.partial_command_map, stream),
+                        uapi::VSOCK_TYPE_STREAM,
+                    ),
+                    Some(EpollListener::LocalSeqpacket(stream)) => (
+                        Self::read_local_seqpacket_port(stream),
+                        uapi::VSOCK_TYPE_SEQPACKET,
+                    ),
+                    _ => unreachable!(),
+                };
 
-                    if let Err(Error::UnixRead(ref e)) = port {
-                        if e.kind() == ErrorKind::WouldBlock {
-                            return;
-                        }
+                if let Err(Error::UnixRead(ref e)) = port {
+                    if e.kind() == ErrorKind::WouldBlock {
+                        return;
                     }
-
-                    let stream = match self.remove_listener(fd) {
-                        Some(EpollListener::LocalStream(s)) => s,
-                        _ => unreachable!(),
-                    };
-
-                    port.and_then(|peer_port| {
-                        let local_port = self.allocate_local_port();
-
-                        self.add_connection(
-                            ConnMapKey {
-                                local_port,
-                                peer_port,
-                            },
-                            MuxerConnection::new_local_init(
-                                stream,
-                                uapi::VSOCK_HOST_CID,
-                                self.cid,
-                                local_port,
-                                peer_port,
-                            ),
-                        )
-                    })
-                    .unwrap_or_else(|err| {
-                        info!("vsock: error adding local-init connection: {:?}", err);
-                    })
                 }
+
+                let stream = match self.remove_listener(fd) {
+                    Some(EpollListener::LocalStream(s))
+                    | Some(EpollListener::LocalSeqpacket(s)) => s,
+                    _ => unreachable!(),
+                };
+
+                port.and_then(|peer_port| {
+                    let local_port = self.allocate_local_port();
+
+                    self.add_connection(
+                        ConnMapKey {
+                            local_port,
+                            peer_port,
+                        },
+                        MuxerConnection::new_local_init(
+                            stream,
+                            uapi::VSOCK_HOST_CID,
+                            self.cid,
+                            local_port,
+                            peer_port,
+                            type_,
+                        ),
+                    )
+                })
+                .unwrap_or_else(|err| {
+                    info!("vsock: error adding local-init connection: {:?}", err);
+                })
             }
 
             _ => {
@@ -509,7 +578,23 @@ impl VsockMuxer {
 
         let command = partial_command_map.remove(&stream.as_raw_fd()).unwrap();
 
-        let mut word_iter = std::str::from_utf8(&command.buf[..command.len])
+        Self::parse_connect_command(&command.buf[..command.len])
+    }
+
+    /// Parse a host "connect" command, received as a single message on a seqpacket socket,
+    /// and extract the destination vsock port.
+    ///
+    fn read_local_seqpacket_port(stream: &mut UnixStream) -> Result<u32> {
+        let mut buf = [0u8; 32];
+        let len = stream.read(&mut buf).map_err(Error::UnixRead)?;
+
+        Self::parse_connect_command(&buf[..len])
+    }
+
+    /// Extract the destination vsock port out of a "connect \<port>" command.
+    ///
+    fn parse_connect_command(command: &[u8]) -> Result<u32> {
+        let mut word_iter = std::str::from_utf8(command)
             .map_err(Error::ConvertFromUtf8)?
             .split_whitespace();
 
@@ -598,8 +683,10 @@ impl VsockMuxer {
     fn add_listener(&mut self, fd: RawFd, listener: EpollListener) -> Result<()> {
         let evset = match listener {
             EpollListener::Connection { evset, .. } => evset,
-            EpollListener::LocalStream(_) => epoll::Events::EPOLLIN,
-            EpollListener::HostSock => epoll::Events::EPOLLIN,
+            EpollListener::LocalStream(_) | EpollListener::LocalSeqpacket(_) => {
+                epoll::Events::EPOLLIN
+            }
+            EpollListener::HostSock | EpollListener::HostSeqpacketSock => epoll::Events::EPOLLIN,
         };
 
         epoll::ctl(
@@ -665,14 +752,21 @@ impl VsockMuxer {
     /// Handle a new connection request coming from our peer (the guest vsock driver).
     ///
     /// This will attempt to connect to a host-side Unix socket, expected to be listening at
-    /// the file system path corresponding to the destination port. If successful, a new
+    /// the file system path corresponding to the destination port. The Unix socket type
+    /// (SOCK_STREAM or SOCK_SEQPACKET) matches the vsock one. If successful, a new
     /// connection object will be created and added to the connection pool. On failure, a new
     /// RST packet will be scheduled for delivery to the guest.
     ///
     fn handle_peer_request_pkt(&mut self, pkt: &VsockPacket) {
         let port_path = format!("{}_{}", self.host_sock_path, pkt.dst_port());
 
-        UnixStream::connect(port_path)
+        let stream = if pkt.type_() == uapi::VSOCK_TYPE_SEQPACKET {
+            seqpacket_connect(&port_path)
+        } else {
+            UnixStream::connect(port_path)
+        };
+
+        stream
             .and_then(|stream| stream.set_nonblocking(true).map(|_| stream))
             .map_err(Error::UnixConnect)
             .and_then(|stream| {
@@ -688,10 +782,11 @@ impl VsockMuxer {
                         pkt.dst_port(),
                         pkt.src_port(),
                         pkt.buf_alloc(),
+                        pkt.type_(),
                     ),
                 )
             })
-            .unwrap_or_else(|_| self.enq_rst(pkt.dst_port(), pkt.src_port()));
+            .unwrap_or_else(|_| self.enq_rst(pkt.dst_port(), pkt.src_port(), pkt.type_()));
     }
 
     /// Perform an action that might mutate a connection's state.
@@ -831,10 +926,11 @@ impl VsockMuxer {
     /// handle them. We do, however, log a warning, since not being able to enqueue an RST
     /// packet means we have to drop it, which is not normal operation.
     ///
-    fn enq_rst(&mut self, local_port: u32, peer_port: u32) {
+    fn enq_rst(&mut self, local_port: u32, peer_port: u32, type_: u16) {
         let pushed = self.rxq.push(MuxerRx::RstPkt {
             local_port,
             peer_port,
+            type_,
         });
         if !pushed {
             warn!(
@@ -845,6 +941,90 @@ impl VsockMuxer {
     }
 }
 
+/// Build a Unix socket address out of a file system path.
+///
+fn sockaddr_un(path: &str) -> io::Result<(libc::sockaddr_un, libc::socklen_t)> {
+    // SAFETY: an all-zero sockaddr_un is a valid (unnamed) address.
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+    // Leave room for the NUL terminator.
+    let path = path.as_bytes();
+    if path.len() >= addr.sun_path.len() {
+        return Err(io::Error::from(ErrorKind::InvalidInput));
+    }
+    for (dst, src) in addr.sun_path.iter_mut().zip(path) {
+        *dst = *src as libc::c_char;
+    }
+
+    let len = std::mem::size_of::<libc::sa_family_t>() + path.len() + 1;
+    Ok((addr, len as libc::socklen_t))
+}
+
+/// Create a new SOCK_SEQPACKET Unix socket.
+///
+fn seqpacket_socket() -> io::Result<OwnedFd> {
+    // SAFETY: FFI call with valid arguments, and we check the return value.
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: fd is a valid file descriptor, that nothing else owns.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Connect to a SOCK_SEQPACKET Unix socket listening at `path`.
+///
+/// The returned `UnixStream` reads and writes whole messages.
+///
+fn seqpacket_connect(path: &str) -> io::Result<UnixStream> {
+    let fd = seqpacket_socket()?;
+    let (addr, len) = sockaddr_un(path)?;
+
+    // SAFETY: addr is a valid sockaddr_un, of size len.
+    let ret = unsafe {
+        libc::connect(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(UnixStream::from(fd))
+}
+
+/// Create a SOCK_SEQPACKET Unix socket listening at `path`.
+///
+/// The connections accepted through the returned `UnixListener` read and write whole messages.
+///
+fn seqpacket_listen(path: &str) -> io::Result<UnixListener> {
+    let fd = seqpacket_socket()?;
+    let (addr, len) = sockaddr_un(path)?;
+
+    // SAFETY: addr is a valid sockaddr_un, of size len.
+    let ret = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: FFI call on a valid, bound socket, and we check the return value.
+    if unsafe { libc::listen(fd.as_raw_fd(), libc::SOMAXCONN) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(UnixListener::from(fd))
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Write;
@@ -868,11 +1048,18 @@ mod tests {
     impl Drop for MuxerTestContext {
         fn drop(&mut self) {
             std::fs::remove_file(self.muxer.host_sock_path.as_str()).unwrap();
+            if self.muxer.host_seqpacket_sock.is_some() {
+                std::fs::remove_file(format!("{}_seqpacket", self.muxer.host_sock_path)).unwrap();
+            }
         }
     }
 
     impl MuxerTestContext {
         fn new(name: &str) -> Self {
+            Self::new_with_seqpacket(name, false)
+        }
+
+        fn new_with_seqpacket(name: &str, seqpacket: bool) -> Self {
             let vsock_test_ctx = VsockTestContext::new();
             let mut handler_ctx = vsock_test_ctx.create_epoll_handler_context();
             let pkt = VsockPacket::from_rx_virtq_head(
@@ -885,7 +1072,7 @@ mod tests {
             )
             .unwrap();
             let uds_path = format!("test_vsock_{name}.sock");
-            let muxer = VsockMuxer::new(PEER_CID, uds_path).unwrap();
+            let muxer = VsockMuxer::new(PEER_CID, uds_path, seqpacket).unwrap();
 
             Self {
                 _vsock_test_ctx: vsock_test_ctx,
@@ -950,6 +1137,10 @@ mod tests {
             LocalListener::new(format!("{}_{}", self.muxer.host_sock_path, port))
         }
 
+        fn create_local_seqpacket_listener(&self, port: u32) -> LocalListener {
+            LocalListener::new_seqpacket(format!("{}_{}", self.muxer.host_sock_path, port))
+        }
+
         fn local_connect(&mut self, peer_port: u32) -> (UnixStream, u32) {
             let (init_local_lsn_count, init_conn_lsn_count) = self.count_epoll_listeners();
 
@@ -1018,6 +1209,14 @@ mod tests {
                 sock,
             }
         }
+        fn new_seqpacket(path: String) -> Self {
+            let sock = seqpacket_listen(&path).unwrap();
+            sock.set_nonblocking(true).unwrap();
+            Self {
+                path: PathBuf::from(path),
+                sock,
+            }
+        }
         fn accept(&mut self) -> UnixStream {
             let (stream, _) = self.sock.accept().unwrap();
             stream.set_nonblocking(true).unwrap();
@@ -1041,7 +1240,7 @@ mod tests {
     fn test_bad_peer_pkt() {
         const LOCAL_PORT: u32 = 1026;
         const PEER_PORT: u32 = 1025;
-        const SOCK_DGRAM: u16 = 2;
+        const SOCK_DGRAM: u16 = 3;
 
         let mut ctx = MuxerTestContext::new("bad_peer_pkt");
         ctx.init_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
@@ -1049,7 +1248,7 @@ mod tests {
         ctx.send();
 
         // The guest sent a SOCK_DGRAM packet. Per the vsock spec, we need to reply with an RST
-        // packet, since vsock only supports stream sockets.
+        // packet, since we only support stream and seqpacket sockets.
         assert!(ctx.muxer.has_pending_rx());
         ctx.recv();
         assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
@@ -1451,4 +1650,112 @@ mod tests {
         // not be any pending RX in the muxer.
         assert!(!ctx.muxer.has_pending_rx());
     }
+
+    #[test]
+    fn test_peer_seqpacket_connection() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new_with_seqpacket("peer_seqpacket_connection", true);
+
+        // A refused seqpacket connection should be reset with a seqpacket RST.
+        ctx.init_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        let mut listener = ctx.create_local_seqpacket_listener(LOCAL_PORT);
+        ctx.init_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        let mut stream = listener.accept();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        // Test guest -> host data flow: both messages should be received separately.
+        for data in [[1u8, 2, 3].as_slice(), [4u8, 5].as_slice()] {
+            ctx.init_data_pkt(LOCAL_PORT, PEER_PORT, data)
+                .set_type(uapi::VSOCK_TYPE_SEQPACKET)
+                .set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+            ctx.send();
+        }
+        let mut buf = [0u8; 16];
+        assert_eq!(stream.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf[..3], [1, 2, 3]);
+        assert_eq!(stream.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf[..2], [4, 5]);
+
+        // Test host -> guest data flow.
+        let data = [6u8, 7, 8, 9];
+        stream.write_all(&data).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_ne!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert_eq!(ctx.pkt.len() as usize, data.len());
+        assert_eq!(ctx.pkt.buf().unwrap()[..data.len()], data);
+
+        assert!(!ctx.muxer.has_pending_rx());
+    }
+
+    #[test]
+    fn test_seqpacket_disabled() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("seqpacket_disabled");
+        assert!(!std::path::Path::new(&format!("{}_seqpacket", ctx.muxer.host_sock_path)).exists());
+
+        // Even with a listener, seqpacket connections must be refused.
+        let _listener = ctx.create_local_seqpacket_listener(LOCAL_PORT);
+        ctx.init_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert!(ctx.muxer.conn_map.is_empty());
+    }
+
+    #[test]
+    fn test_local_seqpacket_connection() {
+        let mut ctx = MuxerTestContext::new_with_seqpacket("local_seqpacket_connection", true);
+        let peer_port = 1025;
+
+        let mut stream =
+            seqpacket_connect(&format!("{}_seqpacket", ctx.muxer.host_sock_path)).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        ctx.notify_muxer();
+
+        stream
+            .write_all(format!("CONNECT {peer_port}\n").as_bytes())
+            .unwrap();
+        ctx.notify_muxer();
+
+        let local_port = ctx.muxer.local_port_last;
+        let key = ConnMapKey {
+            local_port,
+            peer_port,
+        };
+        assert!(ctx.muxer.conn_map.contains_key(&key));
+
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_REQUEST);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_eq!(ctx.pkt.dst_port(), peer_port);
+
+        ctx.init_pkt(local_port, peer_port, uapi::VSOCK_OP_RESPONSE)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+
+        let mut buf = [0u8; 32];
+        let len = stream.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], format!("OK {local_port}\n").as_bytes());
+    }
 }
@@ -9,7 +9,10 @@
 
 use std::cmp::Ordering;
 use std::collections::btree_map::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 use std::sync::{Arc, Barrier, Mutex, RwLock, Weak};
+#[cfg(feature = "bus_stats")]
+use std::time::{Duration, Instant};
 use std::{convert, error, fmt, io, result};
 
 /// Trait for devices that respond to reads or writes in an arbitrary address space.
@@ -114,13 +117,52 @@ impl PartialOrd for BusRange {
     }
 }
 
+/// Snapshot of the accesses routed to a single range of a `Bus`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BusAccessCounters {
+    pub reads: u64,
+    pub writes: u64,
+    /// Time spent in the device handlers, in nanoseconds.
+    pub time_ns: u64,
+}
+
+#[derive(Default)]
+struct BusAccessStats {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    time_ns: AtomicU64,
+}
+
+impl BusAccessStats {
+    #[cfg(feature = "bus_stats")]
+    fn account(&self, counter: &AtomicU64, elapsed: Duration) {
+        counter.fetch_add(1, AtomicOrdering::Relaxed);
+        self.time_ns
+            .fetch_add(elapsed.as_nanos() as u64, AtomicOrdering::Relaxed);
+    }
+
+    fn counters(&self) -> BusAccessCounters {
+        BusAccessCounters {
+            reads: self.reads.load(AtomicOrdering::Relaxed),
+            writes: self.writes.load(AtomicOrdering::Relaxed),
+            time_ns: self.time_ns.load(AtomicOrdering::Relaxed),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct BusEntry {
+    device: Weak<dyn BusDeviceSync>,
+    stats: Arc<BusAccessStats>,
+}
+
 /// A device container for routing reads and writes over some address space.
 ///
 /// This doesn't have any restrictions on what kind of device or address space this applies to. The
 /// only restriction is that no two devices can overlap in this address space.
 #[derive(Default)]
 pub struct Bus {
-    devices: RwLock<BTreeMap<BusRange, Weak<dyn BusDeviceSync>>>,
+    devices: RwLock<BTreeMap<BusRange, BusEntry>>,
 }
 
 impl Bus {
@@ -133,10 +175,10 @@ impl Bus {
 
     fn first_before(&self, addr: u64) -> Option<(BusRange, Arc<dyn BusDeviceSync>)> {
         let devices = self.devices.read().unwrap();
-        let (range, dev) = devices
+        let (range, entry) = devices
             .range(..=BusRange { base: addr, len: 1 })
             .next_back()?;
-        dev.upgrade().map(|d| (*range, d.clone()))
+        entry.device.upgrade().map(|d| (*range, d))
     }
 
     #[allow(clippy::type_complexity)]
@@ -150,7 +192,29 @@ impl Bus {
         None
     }
 
+    // Accounts for an access to the range starting at `base`, unless the
+    // device was removed while handling it. The bus lock can't be held
+    // across the access, as the device may update the bus.
+    #[cfg(feature = "bus_stats")]
+    fn account(&self, base: u64, counter: fn(&BusAccessStats) -> &AtomicU64, start: Instant) {
+        let elapsed = start.elapsed();
+        if let Some(entry) = self.devices.read().unwrap().get(&BusRange { base, len: 1 }) {
+            entry.stats.account(counter(&entry.stats), elapsed);
+        }
+    }
+
     pub fn insert(&self, device: Arc<dyn BusDeviceSync>, base: u64, len: u64) -> Result<()> {
+        self.insert_entry(
+            BusEntry {
+                device: Arc::downgrade(&device),
+                stats: Arc::new(BusAccessStats::default()),
+            },
+            base,
+            len,
+        )
+    }
+
+    fn insert_entry(&self, entry: BusEntry, base: u64, len: u64) -> Result<()> {
         if len == 0 {
             return Err(Error::ZeroSizedRange);
         }
@@ -170,7 +234,7 @@ impl Bus {
             .devices
             .write()
             .unwrap()
-            .insert(BusRange { base, len }, Arc::downgrade(&device))
+            .insert(BusRange { base, len }, entry)
             .is_some()
         {
             return Err(Error::Overlap);
@@ -200,7 +264,7 @@ impl Bus {
         let mut remove_key_list = Vec::new();
 
         for (key, value) in device_list.iter() {
-            if Arc::ptr_eq(&value.upgrade().unwrap(), device) {
+            if Arc::ptr_eq(&value.device.upgrade().unwrap(), device) {
                 remove_key_list.push(*key);
             }
         }
@@ -220,9 +284,10 @@ impl Bus {
         new_base: u64,
         new_len: u64,
     ) -> Result<()> {
-        // Retrieve the device corresponding to the range
-        let device = if let Some((_, _, dev)) = self.resolve(old_base) {
-            dev.clone()
+        // Retrieve the device corresponding to the range, keeping its
+        // access counters across the move.
+        let entry = if let Some((range, _)) = self.first_before(old_base) {
+            self.devices.read().unwrap()[&range].clone()
         } else {
             return Err(Error::MissingAddressRange);
         };
@@ -231,7 +296,34 @@ impl Bus {
         self.remove(old_base, old_len)?;
 
         // Insert the new address range
-        self.insert(device, new_base, new_len)
+        self.insert_entry(entry, new_base, new_len)
+    }
+
+    /// Returns the accesses routed to the range starting at `base`, or `None`
+    /// when the accesses aren't accounted (`bus_stats` feature).
+    pub fn access_counters(&self, base: u64) -> Option<BusAccessCounters> {
+        if !cfg!(feature = "bus_stats") {
+            return None;
+        }
+        self.devices
+            .read()
+            .unwrap()
+            .get(&BusRange { base, len: 1 })
+            .map(|entry| entry.stats.counters())
+    }
+
+    /// Returns the accesses routed to every range of the bus, if they are
+    /// accounted.
+    pub fn all_access_counters(&self) -> Vec<(BusRange, BusAccessCounters)> {
+        if !cfg!(feature = "bus_stats") {
+            return Vec::new();
+        }
+        self.devices
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(range, entry)| (*range, entry.stats.counters()))
+            .collect()
     }
 
     /// Reads data from the device that owns the range containing `addr` and puts it into `data`.
@@ -239,8 +331,12 @@ impl Bus {
     /// Returns true on success, otherwise `data` is untouched.
     pub fn read(&self, addr: u64, data: &mut [u8]) -> Result<()> {
         if let Some((base, offset, dev)) = self.resolve(addr) {
+            #[cfg(feature = "bus_stats")]
+            let start = Instant::now();
             // OK to unwrap as lock() failing is a serious error condition and should panic.
             dev.read(base, offset, data);
+            #[cfg(feature = "bus_stats")]
+            self.account(base, |stats| &stats.reads, start);
             Ok(())
         } else {
             Err(Error::MissingAddressRange)
@@ -252,8 +348,13 @@ impl Bus {
     /// Returns true on success, otherwise `data` is untouched.
     pub fn write(&self, addr: u64, data: &[u8]) -> Result<Option<Arc<Barrier>>> {
         if let Some((base, offset, dev)) = self.resolve(addr) {
+            #[cfg(feature = "bus_stats")]
+            let start = Instant::now();
             // OK to unwrap as lock() failing is a serious error condition and should panic.
-            Ok(dev.write(base, offset, data))
+            let barrier = dev.write(base, offset, data);
+            #[cfg(feature = "bus_stats")]
+            self.account(base, |stats| &stats.writes, start);
+            Ok(barrier)
         } else {
             Err(Error::MissingAddressRange)
         }
@@ -359,6 +460,45 @@ mod tests {
         assert_eq!(data, [1, 2, 3, 4]);
     }
 
+    #[test]
+    #[cfg(feature = "bus_stats")]
+    fn bus_access_counters() {
+        let bus = Bus::new();
+        let dummy: Arc<dyn BusDeviceSync> = Arc::new(DummyDevice);
+        bus.insert(dummy.clone(), 0x10, 0x10).unwrap();
+        bus.insert(dummy.clone(), 0x20, 0x10).unwrap();
+
+        bus.read(0x12, &mut [0, 0]).unwrap();
+        bus.read(0x14, &mut [0, 0]).unwrap();
+        bus.write(0x10, &[0]).unwrap();
+        bus.write(0x24, &[0]).unwrap();
+        bus.read(0x40, &mut [0]).unwrap_err();
+
+        let counters = bus.access_counters(0x10).unwrap();
+        assert_eq!((counters.reads, counters.writes), (2, 1));
+        let counters = bus.access_counters(0x20).unwrap();
+        assert_eq!((counters.reads, counters.writes), (0, 1));
+        assert!(bus.access_counters(0x40).is_none());
+
+        // Counters follow the device when its range is moved.
+        bus.update_range(0x10, 0x10, 0x30, 0x10).unwrap();
+        let counters = bus.access_counters(0x30).unwrap();
+        assert_eq!((counters.reads, counters.writes), (2, 1));
+        assert_eq!(bus.all_access_counters().len(), 2);
+    }
+
+    #[test]
+    #[cfg(not(feature = "bus_stats"))]
+    fn bus_access_counters_disabled() {
+        let bus = Bus::new();
+        let dummy: Arc<dyn BusDeviceSync> = Arc::new(DummyDevice);
+        bus.insert(dummy.clone(), 0x10, 0x10).unwrap();
+        bus.read(0x12, &mut [0, 0]).unwrap();
+
+        assert!(bus.access_counters(0x10).is_none());
+        assert!(bus.all_access_counters().is_empty());
+    }
+
     #[test]
     fn bus_range_overlap() {
         let a = BusRange {
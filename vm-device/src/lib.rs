@@ -9,7 +9,9 @@ mod bus;
 pub mod dma_mapping;
 pub mod interrupt;
 
-pub use self::bus::{Bus, BusDevice, BusDeviceSync, Error as BusError};
+pub use self::bus::{
+    Bus, BusAccessCounters, BusDevice, BusDeviceSync, BusRange, Error as BusError,
+};
 
 /// Type of Message Signalled Interrupt
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -310,4 +310,44 @@ impl MemoryRangeTable {
         }
         Self { data }
     }
+
+    /// Remove the given ranges from the table, splitting the existing
+    /// entries where needed.
+    pub fn subtract(&mut self, ranges: &[MemoryRange]) {
+        let mut ranges = ranges.to_vec();
+        ranges.sort_by_key(|r| r.gpa);
+
+        let mut data = Vec::new();
+        for entry in self.data.drain(..) {
+            let mut start = entry.gpa;
+            let end = entry.gpa + entry.length;
+            for range in ranges.iter() {
+                let range_end = range.gpa + range.length;
+                if range_end <= start {
+                    continue;
+                }
+                if range.gpa >= end {
+                    break;
+                }
+                if range.gpa > start {
+                    data.push(MemoryRange {
+                        gpa: start,
+                        length: range.gpa - start,
+                    });
+                }
+                start = range_end;
+                if start >= end {
+                    break;
+                }
+            }
+            if start < end {
+                data.push(MemoryRange {
+                    gpa: start,
+                    length: end - start,
+                });
+            }
+        }
+
+        self.data = data;
+    }
 }
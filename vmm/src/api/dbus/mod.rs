@@ -21,9 +21,12 @@ use super::{ApiAction, ApiRequest};
 use crate::api::VmCoredump;
 use crate::api::{
     AddDisk, Body, VmAddDevice, VmAddFs, VmAddNet, VmAddPmem, VmAddUserDevice, VmAddVdpa,
-    VmAddVsock, VmBoot, VmCounters, VmCreate, VmDelete, VmInfo, VmPause, VmPowerButton, VmReboot,
-    VmReceiveMigration, VmRemoveDevice, VmResize, VmResizeZone, VmRestore, VmResume,
-    VmSendMigration, VmShutdown, VmSnapshot, VmmPing, VmmShutdown,
+    VmAddVsock, VmBalloonStatistics, VmBoot, VmCounters, VmCreate, VmDelete, VmInfo,
+    VmMigrationCancel, VmMigrationStatus, VmPause, VmPowerButton, VmReboot, VmReceiveMigration,
+    VmRemoveDevice, VmResize, VmResizeDisk, VmResizeZone, VmRestore, VmResume, VmSendMigration,
+    VmShutdown, VmSnapshot, VmUpdate, VmUpdateRateLimiter, VmVcpuStats, VmmCreateVm,
+    VmmCreateVmData, VmmJobCancel, VmmJobStatus, VmmJobs, VmmListVms, VmmPing, VmmShutdown,
+    VmmValidateConfig, DEFAULT_VM_ID,
 };
 use crate::seccomp_filters::{get_seccomp_filter, Thread};
 use crate::{Error as VmmError, NetConfig, Result as VmmResult, VmConfig};
@@ -198,6 +201,14 @@ impl DBusApi {
         self.vm_action(&VmCounters, ()).await
     }
 
+    async fn vm_balloon_statistics(&self) -> Result<Optional<String>> {
+        self.vm_action(&VmBalloonStatistics, ()).await
+    }
+
+    async fn vm_vcpu_stats(&self) -> Result<Optional<String>> {
+        self.vm_action(&VmVcpuStats, ()).await
+    }
+
     async fn vm_create(&self, vm_config: String) -> Result<()> {
         let api_sender = self.clone_api_sender().await;
         let api_notifier = self.clone_api_notifier()?;
@@ -220,6 +231,59 @@ impl DBusApi {
         Ok(())
     }
 
+    /// Creates a VM besides the default one. An empty `id` lets the VMM pick
+    /// one, and the id is returned either way.
+    async fn vmm_create_vm(&self, id: String, vm_config: String) -> Result<Optional<String>> {
+        let mut config: Box<VmConfig> = serde_json::from_str(&vm_config).map_err(api_error)?;
+
+        if let Some(ref mut nets) = config.net {
+            if nets.iter().any(|net| net.fds.is_some()) {
+                warn!("Ignoring FDs sent via the D-Bus request body");
+            }
+            for net in nets {
+                net.fds = None;
+            }
+        }
+
+        let id = (!id.is_empty()).then_some(id);
+        self.vm_action(&VmmCreateVm, VmmCreateVmData { id, config })
+            .await
+    }
+
+    /// Checks a VM configuration against the host without creating the VM.
+    async fn vmm_validate_config(&self, vm_config: String) -> Result<Optional<String>> {
+        let mut config: Box<VmConfig> = serde_json::from_str(&vm_config).map_err(api_error)?;
+
+        if let Some(ref mut nets) = config.net {
+            if nets.iter().any(|net| net.fds.is_some()) {
+                warn!("Ignoring FDs sent via the D-Bus request body");
+            }
+            for net in nets {
+                net.fds = None;
+            }
+        }
+
+        self.vm_action(&VmmValidateConfig, config).await
+    }
+
+    async fn vmm_list_vms(&self) -> Result<Optional<String>> {
+        self.vm_action(&VmmListVms, ()).await
+    }
+
+    async fn vmm_jobs(&self) -> Result<Optional<String>> {
+        self.vm_action(&VmmJobs, ()).await
+    }
+
+    async fn vmm_job_status(&self, job_data: String) -> Result<Optional<String>> {
+        let job_data = serde_json::from_str(&job_data).map_err(api_error)?;
+        self.vm_action(&VmmJobStatus, job_data).await
+    }
+
+    async fn vmm_job_cancel(&self, job_data: String) -> Result<()> {
+        let job_data = serde_json::from_str(&job_data).map_err(api_error)?;
+        self.vm_action(&VmmJobCancel, job_data).await.map(|_| ())
+    }
+
     async fn vm_delete(&self) -> Result<()> {
         self.vm_action(&VmDelete, ()).await.map(|_| ())
     }
@@ -234,6 +298,17 @@ impl DBusApi {
         serde_json::to_string(&result).map_err(api_error)
     }
 
+    async fn vm_migration_cancel(&self) -> Result<()> {
+        self.vm_action(&VmMigrationCancel, DEFAULT_VM_ID.to_owned())
+            .await
+            .map(|_| ())
+    }
+
+    async fn vm_migration_status(&self) -> Result<Optional<String>> {
+        self.vm_action(&VmMigrationStatus, DEFAULT_VM_ID.to_owned())
+            .await
+    }
+
     async fn vm_pause(&self) -> Result<()> {
         self.vm_action(&VmPause, ()).await.map(|_| ())
     }
@@ -265,6 +340,26 @@ impl DBusApi {
             .map(|_| ())
     }
 
+    async fn vm_resize_disk(&self, vm_resize_disk: String) -> Result<()> {
+        let vm_resize_disk = serde_json::from_str(&vm_resize_disk).map_err(api_error)?;
+        self.vm_action(&VmResizeDisk, vm_resize_disk)
+            .await
+            .map(|_| ())
+    }
+
+    async fn vm_update_rate_limiter(&self, vm_update_rate_limiter: String) -> Result<()> {
+        let vm_update_rate_limiter =
+            serde_json::from_str(&vm_update_rate_limiter).map_err(api_error)?;
+        self.vm_action(&VmUpdateRateLimiter, vm_update_rate_limiter)
+            .await
+            .map(|_| ())
+    }
+
+    async fn vm_update(&self, vm_update: String) -> Result<Optional<String>> {
+        let vm_update = serde_json::from_str(&vm_update).map_err(api_error)?;
+        self.vm_action(&VmUpdate, vm_update).await
+    }
+
     async fn vm_restore(&self, restore_config: String) -> Result<()> {
         let restore_config = serde_json::from_str(&restore_config).map_err(api_error)?;
         self.vm_action(&VmRestore, restore_config).await.map(|_| ())
@@ -0,0 +1,245 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Streaming of the VMM events over HTTP.
+//!
+//! `micro_http` only deals with complete request/response pairs, which does
+//! not fit a response body growing for as long as the client is connected.
+//! Once routed, the connection of a `vmm.events` request is thus handed over
+//! to this module, which keeps writing newline-delimited JSON events until
+//! the client disconnects.
+
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use event_monitor::Subscribers;
+use flume::RecvTimeoutError;
+use micro_http::{Method, Request};
+
+use super::raw::{write_error, write_response_head, RequestError};
+use super::HTTP_ROOT;
+
+pub(super) const EVENTS_ENDPOINT: &str = "/vmm.events";
+// How often to check whether the client is still there when no event is
+// published.
+const CLIENT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// How long a client may stall reading the events before being disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether the request is a `vmm.events` request, query included.
+pub(super) fn is_events_request(request: &Request) -> bool {
+    let path = request.uri().get_abs_path();
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    path.strip_prefix(HTTP_ROOT) == Some(EVENTS_ENDPOINT)
+}
+
+/// Parses the request and returns whether the backlog should be replayed
+/// before streaming new events.
+fn parse_events_request(request: &Request) -> std::result::Result<bool, RequestError> {
+    if request.method() != Method::Get {
+        return Err(RequestError::MethodNotAllowed);
+    }
+    if request.body.is_some() {
+        return Err(RequestError::BadRequest("Unexpected body"));
+    }
+
+    let query = request
+        .uri()
+        .get_abs_path()
+        .split_once('?')
+        .map_or("", |(_, query)| query);
+    let mut replay = false;
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+        match param.split_once('=').unwrap_or((param, "true")) {
+            ("replay", "true") => replay = true,
+            ("replay", "false") => replay = false,
+            _ => return Err(RequestError::BadRequest("Invalid query parameter")),
+        }
+    }
+
+    Ok(replay)
+}
+
+// Whether the client closed the connection or is gone, without waiting for
+// the next event to fail writing to it.
+fn client_gone(stream: &UnixStream) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd: stream.as_raw_fd(),
+        events: libc::POLLRDHUP,
+        revents: 0,
+    };
+    // SAFETY: FFI call with a valid pollfd, not waiting
+    let ret = unsafe { libc::poll(&mut pollfd, 1, 0) };
+
+    ret < 0 || pollfd.revents & (libc::POLLRDHUP | libc::POLLHUP | libc::POLLERR) != 0
+}
+
+/// Serves a `vmm.events` request, streaming the events until the client goes
+/// away or can't keep up with them.
+pub(super) fn handle_events_request(
+    mut stream: UnixStream,
+    request: &Request,
+    subscribers: Option<&Subscribers>,
+) {
+    let Some(subscribers) = subscribers else {
+        write_error(&mut stream, &RequestError::NotFound);
+        return;
+    };
+    let replay = match parse_events_request(request) {
+        Ok(replay) => replay,
+        Err(e) => {
+            write_error(&mut stream, &e);
+            return;
+        }
+    };
+    let Some(events) = subscribers.subscribe(replay) else {
+        write_error(&mut stream, &RequestError::ServiceUnavailable);
+        return;
+    };
+
+    if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err()
+        || write_response_head(&mut stream, "application/x-ndjson", None).is_err()
+    {
+        return;
+    }
+
+    // Dropping the receiver unregisters the subscriber, while the event
+    // monitor disconnects the subscribers falling too far behind.
+    loop {
+        match events.recv_timeout(CLIENT_CHECK_INTERVAL) {
+            Ok(line) => {
+                if stream.write_all(line.as_bytes()).is_err() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if client_gone(&stream) {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read};
+    use std::thread;
+
+    use super::*;
+
+    fn request(request: &str) -> Request {
+        Request::try_from(request.as_bytes(), None).unwrap()
+    }
+
+    fn events_request(
+        subscribers: &Subscribers,
+        request_line: &str,
+    ) -> (BufReader<UnixStream>, thread::JoinHandle<()>) {
+        let (client, server) = UnixStream::pair().unwrap();
+        let request = request(request_line);
+        let subscribers = subscribers.clone();
+        let handle =
+            thread::spawn(move || handle_events_request(server, &request, Some(&subscribers)));
+
+        (BufReader::new(client), handle)
+    }
+
+    fn read_line(reader: &mut impl BufRead) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    }
+
+    fn read_response_head(reader: &mut impl BufRead) -> String {
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            head.push_str(&read_line(reader));
+        }
+        head
+    }
+
+    fn parse(request_line: &str) -> std::result::Result<bool, RequestError> {
+        parse_events_request(&request(request_line))
+    }
+
+    #[test]
+    fn test_is_events_request() {
+        assert!(is_events_request(&request(
+            "GET /api/v1/vmm.events HTTP/1.1\r\n\r\n"
+        )));
+        assert!(is_events_request(&request(
+            "GET /api/v1/vmm.events?replay HTTP/1.1\r\n\r\n"
+        )));
+        assert!(!is_events_request(&request(
+            "GET /api/v1/vmm.eventsx HTTP/1.1\r\n\r\n"
+        )));
+        assert!(!is_events_request(&request(
+            "GET /api/v1/vmm.ping HTTP/1.1\r\n\r\n"
+        )));
+    }
+
+    #[test]
+    fn test_parse_events_request() {
+        assert!(!parse("GET /api/v1/vmm.events HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap());
+        assert!(parse("GET /api/v1/vmm.events?replay HTTP/1.1\r\n\r\n").unwrap());
+        assert!(parse("GET /api/v1/vmm.events?replay=true HTTP/1.1\r\n\r\n").unwrap());
+        assert!(!parse("GET /api/v1/vmm.events?replay=false HTTP/1.1\r\n\r\n").unwrap());
+
+        assert!(matches!(
+            parse("PUT /api/v1/vmm.events HTTP/1.1\r\n\r\n"),
+            Err(RequestError::MethodNotAllowed)
+        ));
+        assert!(matches!(
+            parse("GET /api/v1/vmm.events?foo=bar HTTP/1.1\r\n\r\n"),
+            Err(RequestError::BadRequest(_))
+        ));
+        assert!(matches!(
+            parse("GET /api/v1/vmm.events HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}"),
+            Err(RequestError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_events_stream() {
+        let subscribers = Subscribers::default();
+        subscribers.publish("{\n  \"event\": \"booting\"\n}");
+
+        let (mut client, handle) = events_request(
+            &subscribers,
+            "GET /api/v1/vmm.events?replay HTTP/1.1\r\n\r\n",
+        );
+        let head = read_response_head(&mut client);
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: application/x-ndjson\r\n"));
+        assert_eq!(read_line(&mut client), "{\"event\":\"booting\"}\n");
+
+        subscribers.publish("{\"event\": \"booted\"}");
+        assert_eq!(read_line(&mut client), "{\"event\":\"booted\"}\n");
+
+        // The stream ends once the client is gone, even without any event
+        // to write.
+        drop(client);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_events_too_many_subscribers() {
+        let subscribers = Subscribers::default();
+        let _events: Vec<_> = (0..event_monitor::MAX_SUBSCRIBERS)
+            .map(|_| subscribers.subscribe(false).unwrap())
+            .collect();
+
+        let (mut client, handle) =
+            events_request(&subscribers, "GET /api/v1/vmm.events HTTP/1.1\r\n\r\n");
+        handle.join().unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    }
+}
@@ -16,12 +16,31 @@ use crate::api::http::{error_response, EndpointHandler, HttpError};
 use crate::api::VmCoredump;
 use crate::api::{
     AddDisk, ApiAction, ApiRequest, NetConfig, VmAddDevice, VmAddFs, VmAddNet, VmAddPmem,
-    VmAddUserDevice, VmAddVdpa, VmAddVsock, VmBoot, VmConfig, VmCounters, VmDelete, VmNmi, VmPause,
-    VmPowerButton, VmReboot, VmReceiveMigration, VmRemoveDevice, VmResize, VmResizeZone, VmRestore,
-    VmResume, VmSendMigration, VmShutdown, VmSnapshot,
+    VmAddUserDevice, VmAddVdpa, VmAddVsock, VmBalloonStatistics, VmBoot, VmConfig, VmCounters,
+    VmDelete, VmNmi, VmPause, VmPowerButton, VmReboot, VmReceiveMigration, VmRemoveDevice,
+    VmResize, VmResizeDisk, VmResizeZone, VmRestore, VmResume, VmSendMigration, VmShutdown,
+    VmSnapshot, VmUpdate, VmUpdateRateLimiter, VmVcpuStats, VmmCreateVmData, VmmJobCancel, VmmJobs,
+    VmmListVms, DEFAULT_VM_ID,
 };
 use crate::config::RestoreConfig;
 
+fn parse_vm_config(body: &Body) -> std::result::Result<Box<VmConfig>, HttpError> {
+    // Deserialize into a VmConfig
+    let mut vm_config: Box<VmConfig> =
+        serde_json::from_slice(body.raw()).map_err(HttpError::SerdeJsonDeserialize)?;
+
+    if let Some(ref mut nets) = vm_config.net {
+        if nets.iter().any(|net| net.fds.is_some()) {
+            warn!("Ignoring FDs sent via the HTTP request body");
+        }
+        for net in nets {
+            net.fds = None;
+        }
+    }
+
+    Ok(vm_config)
+}
+
 // /api/v1/vm.create handler
 pub struct VmCreate {}
 
@@ -33,44 +52,142 @@ impl EndpointHandler for VmCreate {
         api_sender: Sender<ApiRequest>,
     ) -> Response {
         match req.method() {
-            Method::Put => {
-                match &req.body {
-                    Some(body) => {
-                        // Deserialize into a VmConfig
-                        let mut vm_config: Box<VmConfig> = match serde_json::from_slice(body.raw())
-                            .map_err(HttpError::SerdeJsonDeserialize)
-                        {
-                            Ok(config) => config,
-                            Err(e) => return error_response(e, StatusCode::BadRequest),
-                        };
-
-                        if let Some(ref mut nets) = vm_config.net {
-                            if nets.iter().any(|net| net.fds.is_some()) {
-                                warn!("Ignoring FDs sent via the HTTP request body");
-                            }
-                            for net in nets {
-                                net.fds = None;
-                            }
-                        }
-
-                        match crate::api::VmCreate
-                            .send(api_notifier, api_sender, vm_config)
-                            .map_err(HttpError::ApiError)
-                        {
-                            Ok(_) => Response::new(Version::Http11, StatusCode::NoContent),
-                            Err(e) => error_response(e, StatusCode::InternalServerError),
-                        }
+            Method::Put => match &req.body {
+                Some(body) => {
+                    let vm_config = match parse_vm_config(body) {
+                        Ok(config) => config,
+                        Err(e) => return error_response(e, StatusCode::BadRequest),
+                    };
+
+                    match crate::api::VmCreate
+                        .send(api_notifier, api_sender, vm_config)
+                        .map_err(HttpError::ApiError)
+                    {
+                        Ok(_) => Response::new(Version::Http11, StatusCode::NoContent),
+                        Err(e) => error_response(e, StatusCode::InternalServerError),
                     }
-
-                    None => Response::new(Version::Http11, StatusCode::BadRequest),
                 }
-            }
+
+                None => Response::new(Version::Http11, StatusCode::BadRequest),
+            },
 
             _ => error_response(HttpError::BadRequest, StatusCode::BadRequest),
         }
     }
 }
 
+// /api/v1/vmm.create-vm and /api/v1/vms/<id>/vm.create handler
+pub struct VmmCreateVm {
+    pub id: Option<String>,
+}
+
+impl EndpointHandler for VmmCreateVm {
+    fn put_handler(
+        &self,
+        api_notifier: EventFd,
+        api_sender: Sender<ApiRequest>,
+        body: &Option<Body>,
+        _files: Vec<File>,
+    ) -> std::result::Result<Option<Body>, HttpError> {
+        let config = parse_vm_config(body.as_ref().ok_or(HttpError::BadRequest)?)?;
+
+        crate::api::VmmCreateVm
+            .send(
+                api_notifier,
+                api_sender,
+                VmmCreateVmData {
+                    id: self.id.clone(),
+                    config,
+                },
+            )
+            .map_err(HttpError::ApiError)
+    }
+}
+
+// /api/v1/vm.migration-status and /api/v1/vms/<id>/vm.migration-status handler
+pub struct VmMigrationStatus {
+    pub id: Option<String>,
+}
+
+impl EndpointHandler for VmMigrationStatus {
+    fn get_handler(
+        &self,
+        api_notifier: EventFd,
+        api_sender: Sender<ApiRequest>,
+        _body: &Option<Body>,
+    ) -> std::result::Result<Option<Body>, HttpError> {
+        let id = self.id.clone().unwrap_or_else(|| DEFAULT_VM_ID.to_owned());
+        crate::api::VmMigrationStatus
+            .send(api_notifier, api_sender, id)
+            .map_err(HttpError::ApiError)
+    }
+}
+
+// /api/v1/vm.migration-cancel and /api/v1/vms/<id>/vm.migration-cancel handler
+pub struct VmMigrationCancel {
+    pub id: Option<String>,
+}
+
+impl EndpointHandler for VmMigrationCancel {
+    fn put_handler(
+        &self,
+        api_notifier: EventFd,
+        api_sender: Sender<ApiRequest>,
+        body: &Option<Body>,
+        _files: Vec<File>,
+    ) -> std::result::Result<Option<Body>, HttpError> {
+        if body.is_some() {
+            return Err(HttpError::BadRequest);
+        }
+
+        let id = self.id.clone().unwrap_or_else(|| DEFAULT_VM_ID.to_owned());
+        crate::api::VmMigrationCancel
+            .send(api_notifier, api_sender, id)
+            .map_err(HttpError::ApiError)
+    }
+}
+
+// /api/v1/vmm.validate-config handler
+pub struct VmmValidateConfig {}
+
+impl EndpointHandler for VmmValidateConfig {
+    fn put_handler(
+        &self,
+        api_notifier: EventFd,
+        api_sender: Sender<ApiRequest>,
+        body: &Option<Body>,
+        _files: Vec<File>,
+    ) -> std::result::Result<Option<Body>, HttpError> {
+        let config = parse_vm_config(body.as_ref().ok_or(HttpError::BadRequest)?)?;
+
+        crate::api::VmmValidateConfig
+            .send(api_notifier, api_sender, config)
+            .map_err(HttpError::ApiError)
+    }
+}
+
+// /api/v1/vmm.job-status handler
+pub struct VmmJobStatus {}
+
+impl EndpointHandler for VmmJobStatus {
+    fn get_handler(
+        &self,
+        api_notifier: EventFd,
+        api_sender: Sender<ApiRequest>,
+        body: &Option<Body>,
+    ) -> std::result::Result<Option<Body>, HttpError> {
+        let body = body.as_ref().ok_or(HttpError::BadRequest)?;
+
+        crate::api::VmmJobStatus
+            .send(
+                api_notifier,
+                api_sender,
+                serde_json::from_slice(body.raw())?,
+            )
+            .map_err(HttpError::ApiError)
+    }
+}
+
 pub trait GetHandler {
     fn handle_request(
         &'static self,
@@ -165,6 +282,10 @@ macro_rules! vm_action_put_handler_body {
 }
 
 vm_action_get_handler!(VmCounters);
+vm_action_get_handler!(VmBalloonStatistics);
+vm_action_get_handler!(VmVcpuStats);
+vm_action_get_handler!(VmmListVms);
+vm_action_get_handler!(VmmJobs);
 
 vm_action_put_handler!(VmBoot);
 vm_action_put_handler!(VmDelete);
@@ -185,9 +306,13 @@ vm_action_put_handler_body!(VmAddUserDevice);
 vm_action_put_handler_body!(VmRemoveDevice);
 vm_action_put_handler_body!(VmResize);
 vm_action_put_handler_body!(VmResizeZone);
+vm_action_put_handler_body!(VmResizeDisk);
+vm_action_put_handler_body!(VmUpdateRateLimiter);
+vm_action_put_handler_body!(VmUpdate);
 vm_action_put_handler_body!(VmSnapshot);
 vm_action_put_handler_body!(VmReceiveMigration);
 vm_action_put_handler_body!(VmSendMigration);
+vm_action_put_handler_body!(VmmJobCancel);
 
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 vm_action_put_handler_body!(VmCoredump);
@@ -0,0 +1,432 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! OpenMetrics exporter for the VMM and VM statistics.
+//!
+//! Scrapes are served from a dedicated listener, either a UNIX socket or a
+//! loopback TCP socket, so that they don't compete with the API. The VM
+//! statistics are collected from the VMM thread, which is only waited for a
+//! limited amount of time to keep scrapes from hanging while it is busy.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::net::UnixListener;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::Duration;
+
+use hypervisor::HypervisorType;
+use seccompiler::{apply_filter, SeccompAction};
+use vmm_sys_util::eventfd::EventFd;
+
+use super::raw::{
+    read_request_head, write_error, write_response_head, RequestError, MAX_REQUEST_HEAD_SIZE,
+};
+use crate::api::{
+    ApiAction, ApiRequest, ApiResponsePayload, VmMetrics, VmMetricsResponse, DEFAULT_VM_ID,
+};
+use crate::landlock::Landlock;
+use crate::migration::{migration_status, MigrationStatus};
+use crate::seccomp_filters::{get_seccomp_filter, Thread};
+use crate::{Error as VmmError, Result};
+
+const METRICS_ENDPOINT: &str = "/metrics";
+const METRICS_PREFIX: &str = "cloud_hypervisor";
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+// How long a scrape waits for the VMM thread before leaving the VM metrics out.
+const VMM_RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub enum MetricsListener {
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+enum MetricType {
+    Counter,
+    Gauge,
+}
+
+struct MetricFamily {
+    metric_type: MetricType,
+    unit: Option<&'static str>,
+    help: String,
+    samples: Vec<(Vec<(&'static str, String)>, u64)>,
+}
+
+impl MetricFamily {
+    fn new(metric_type: MetricType, unit: Option<&'static str>, help: &str) -> Self {
+        MetricFamily {
+            metric_type,
+            unit,
+            help: help.to_string(),
+            samples: Vec::new(),
+        }
+    }
+
+    fn sample(mut self, value: u64) -> Self {
+        self.samples.push((Vec::new(), value));
+        self
+    }
+
+    fn labeled_sample(&mut self, labels: Vec<(&'static str, String)>, value: u64) {
+        self.samples.push((labels, value));
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Everything but the latencies accumulates over the lifetime of the device.
+fn device_counter_family(name: &str) -> MetricFamily {
+    let help = format!("Device counter {name}");
+    if name.contains("latency") {
+        MetricFamily::new(MetricType::Gauge, None, &help)
+    } else {
+        MetricFamily::new(MetricType::Counter, None, &help)
+    }
+}
+
+fn render_metrics(vm: Option<&VmMetricsResponse>, migration: &MigrationStatus) -> String {
+    let mut families: BTreeMap<String, MetricFamily> = BTreeMap::new();
+
+    families.insert(
+        "vm_up".to_string(),
+        MetricFamily::new(
+            MetricType::Gauge,
+            None,
+            "Whether a running VM provided its metrics",
+        )
+        .sample(vm.is_some() as u64),
+    );
+
+    if let Some(vm) = vm {
+        families.insert(
+            "memory_size_bytes".to_string(),
+            MetricFamily::new(MetricType::Gauge, Some("bytes"), "Guest memory size")
+                .sample(vm.memory_total_size),
+        );
+        families.insert(
+            "memory_actual_size_bytes".to_string(),
+            MetricFamily::new(
+                MetricType::Gauge,
+                Some("bytes"),
+                "Guest memory size minus the balloon",
+            )
+            .sample(vm.memory_actual_size),
+        );
+        families.insert(
+            "balloon_size_bytes".to_string(),
+            MetricFamily::new(MetricType::Gauge, Some("bytes"), "Balloon size")
+                .sample(vm.balloon_size),
+        );
+
+        for (id, counters) in vm.counters.iter() {
+            for (name, value) in counters.iter() {
+                families
+                    .entry(format!("device_{name}"))
+                    .or_insert_with(|| device_counter_family(name))
+                    .labeled_sample(vec![("id", id.clone())], *value);
+            }
+        }
+
+        for (id, counters) in vm.vcpu_counters.iter() {
+            let vcpu = id.trim_start_matches("vcpu").to_string();
+            for (name, value) in counters.iter() {
+                families
+                    .entry(format!("vcpu_{name}"))
+                    .or_insert_with(|| {
+                        MetricFamily::new(
+                            MetricType::Counter,
+                            None,
+                            &format!("vCPU counter {name}"),
+                        )
+                    })
+                    .labeled_sample(vec![("vcpu", vcpu.clone())], *value);
+            }
+        }
+    }
+
+    let mut phase = MetricFamily::new(
+        MetricType::Gauge,
+        None,
+        "Phase of the ongoing or last migration",
+    );
+    phase.labeled_sample(vec![("phase", migration.phase.as_str().to_string())], 1);
+    families.insert("migration_phase".to_string(), phase);
+    for (name, unit, help, value) in [
+        (
+            "migration_iteration",
+            None,
+            "Passes over the guest memory",
+            migration.iteration,
+        ),
+        (
+            "migration_transferred_bytes",
+            Some("bytes"),
+            "Memory transferred",
+            migration.transferred_bytes,
+        ),
+        (
+            "migration_remaining_bytes",
+            Some("bytes"),
+            "Estimated memory left to transfer",
+            migration.remaining_bytes,
+        ),
+        (
+            "migration_dirty_rate_bytes",
+            Some("bytes"),
+            "Bytes per second dirtied by the guest",
+            migration.dirty_rate,
+        ),
+        (
+            "migration_transfer_rate_bytes",
+            Some("bytes"),
+            "Bytes per second transferred",
+            migration.transfer_rate,
+        ),
+        (
+            "migration_expected_downtime_milliseconds",
+            Some("milliseconds"),
+            "Estimated downtime",
+            migration.expected_downtime_ms,
+        ),
+    ] {
+        families.insert(
+            name.to_string(),
+            MetricFamily::new(MetricType::Gauge, unit, help).sample(value),
+        );
+    }
+
+    let mut output = String::new();
+    for (name, family) in families.iter() {
+        let name = format!("{METRICS_PREFIX}_{name}");
+        let (metric_type, suffix) = match family.metric_type {
+            MetricType::Counter => ("counter", "_total"),
+            MetricType::Gauge => ("gauge", ""),
+        };
+        writeln!(output, "# TYPE {name} {metric_type}").unwrap();
+        if let Some(unit) = family.unit {
+            writeln!(output, "# UNIT {name} {unit}").unwrap();
+        }
+        writeln!(output, "# HELP {name} {}", family.help).unwrap();
+        for (labels, value) in family.samples.iter() {
+            let labels = labels
+                .iter()
+                .map(|(key, value)| format!("{key}=\"{}\"", escape_label_value(value)))
+                .collect::<Vec<_>>()
+                .join(",");
+            if labels.is_empty() {
+                writeln!(output, "{name}{suffix} {value}").unwrap();
+            } else {
+                writeln!(output, "{name}{suffix}{{{labels}}} {value}").unwrap();
+            }
+        }
+    }
+    output.push_str("# EOF\n");
+
+    output
+}
+
+fn vm_metrics(
+    api_notifier: &EventFd,
+    api_sender: &Sender<ApiRequest>,
+) -> Option<VmMetricsResponse> {
+    let (response_sender, response_receiver) = channel();
+    api_sender
+        .send(VmMetrics.request((), response_sender))
+        .ok()?;
+    api_notifier.write(1).ok()?;
+
+    match response_receiver.recv_timeout(VMM_RESPONSE_TIMEOUT) {
+        Ok(Ok(ApiResponsePayload::VmMetrics(metrics))) => Some(metrics),
+        _ => None,
+    }
+}
+
+fn handle_metrics_connection<S: Read + Write>(
+    stream: S,
+    api_notifier: &EventFd,
+    api_sender: &Sender<ApiRequest>,
+) {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_HEAD_SIZE));
+    let request = read_request_head(&mut reader).and_then(|head| {
+        if head.path != METRICS_ENDPOINT {
+            Err(RequestError::NotFound)
+        } else if head.method != "GET" {
+            Err(RequestError::MethodNotAllowed)
+        } else {
+            Ok(())
+        }
+    });
+    let mut stream = reader.into_inner().into_inner();
+
+    if let Err(e) = request {
+        write_error(&mut stream, &e);
+        return;
+    }
+
+    let vm = vm_metrics(api_notifier, api_sender);
+    let body = render_metrics(vm.as_ref(), &migration_status(DEFAULT_VM_ID));
+    if write_response_head(&mut stream, OPENMETRICS_CONTENT_TYPE, Some(body.len())).is_ok() {
+        stream.write_all(body.as_bytes()).ok();
+    }
+}
+
+pub fn start_metrics_thread(
+    listener: MetricsListener,
+    api_notifier: EventFd,
+    api_sender: Sender<ApiRequest>,
+    seccomp_action: &SeccompAction,
+    exit_evt: EventFd,
+    hypervisor_type: HypervisorType,
+    landlock_enable: bool,
+) -> Result<thread::JoinHandle<Result<()>>> {
+    // Retrieve seccomp filter for the metrics thread
+    let seccomp_filter = get_seccomp_filter(seccomp_action, Thread::HttpMetrics, hypervisor_type)
+        .map_err(VmmError::CreateSeccompFilter)?;
+
+    enum Listener {
+        Unix(UnixListener),
+        Tcp(TcpListener),
+    }
+    let listener = match listener {
+        MetricsListener::Unix(path) => {
+            Listener::Unix(UnixListener::bind(path).map_err(VmmError::CreateApiServerSocket)?)
+        }
+        MetricsListener::Tcp(addr) => {
+            Listener::Tcp(TcpListener::bind(addr).map_err(VmmError::CreateApiServerSocket)?)
+        }
+    };
+
+    thread::Builder::new()
+        .name("http-metrics".to_string())
+        .spawn(move || {
+            if !seccomp_filter.is_empty() {
+                apply_filter(&seccomp_filter)
+                    .map_err(VmmError::ApplySeccompFilter)
+                    .map_err(|e| {
+                        error!("Error applying seccomp filter: {:?}", e);
+                        exit_evt.write(1).ok();
+                        e
+                    })?;
+            }
+
+            if landlock_enable {
+                Landlock::new()
+                    .map_err(VmmError::CreateLandlock)?
+                    .restrict_self()
+                    .map_err(VmmError::ApplyLandlock)
+                    .map_err(|e| {
+                        error!("Error applying landlock to http-metrics thread: {:?}", e);
+                        exit_evt.write(1).ok();
+                        e
+                    })?;
+            }
+
+            std::panic::catch_unwind(AssertUnwindSafe(move || match listener {
+                Listener::Unix(listener) => {
+                    for stream in listener.incoming() {
+                        match stream {
+                            Ok(stream) => {
+                                handle_metrics_connection(stream, &api_notifier, &api_sender)
+                            }
+                            Err(e) => error!("Error accepting metrics connection: {}", e),
+                        }
+                    }
+                }
+                Listener::Tcp(listener) => {
+                    for stream in listener.incoming() {
+                        match stream {
+                            Ok(stream) => {
+                                handle_metrics_connection(stream, &api_notifier, &api_sender)
+                            }
+                            Err(e) => error!("Error accepting metrics connection: {}", e),
+                        }
+                    }
+                }
+            }))
+            .map_err(|_| {
+                error!("http-metrics thread panicked");
+                exit_evt.write(1).ok()
+            })
+            .ok();
+
+            Ok(())
+        })
+        .map_err(VmmError::HttpThreadSpawn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::MigrationPhase;
+    use crate::vm::VmState;
+
+    #[test]
+    fn test_render_metrics() {
+        let migration = MigrationStatus::default();
+        let output = render_metrics(None, &migration);
+        assert!(output.contains("cloud_hypervisor_vm_up 0\n"));
+        assert!(!output.contains("cloud_hypervisor_memory_size_bytes"));
+        assert!(output.ends_with("# EOF\n"));
+
+        let mut counters = BTreeMap::new();
+        counters.insert(
+            "_disk0".to_string(),
+            BTreeMap::from([
+                ("read_bytes".to_string(), 4096),
+                ("read_latency_avg".to_string(), 12),
+            ]),
+        );
+        counters.insert(
+            "net\"0".to_string(),
+            BTreeMap::from([("rx_bytes".to_string(), 42)]),
+        );
+        let vm = VmMetricsResponse {
+            state: VmState::Running,
+            memory_total_size: 1 << 30,
+            memory_actual_size: (1 << 30) - (1 << 20),
+            balloon_size: 1 << 20,
+            counters,
+            vcpu_counters: BTreeMap::from([(
+                "vcpu0".to_string(),
+                BTreeMap::from([("mmio_write_exits".to_string(), 7)]),
+            )]),
+        };
+        let migration = MigrationStatus {
+            phase: MigrationPhase::DirtyMemory,
+            ..Default::default()
+        };
+        let output = render_metrics(Some(&vm), &migration);
+
+        assert!(output.contains("cloud_hypervisor_vm_up 1\n"));
+        assert!(output.contains("# TYPE cloud_hypervisor_memory_size_bytes gauge\n"));
+        assert!(output.contains("# UNIT cloud_hypervisor_memory_size_bytes bytes\n"));
+        assert!(output.contains("cloud_hypervisor_memory_size_bytes 1073741824\n"));
+        assert!(output.contains("cloud_hypervisor_balloon_size_bytes 1048576\n"));
+        assert!(output.contains("# TYPE cloud_hypervisor_device_read_bytes counter\n"));
+        assert!(output.contains("cloud_hypervisor_device_read_bytes_total{id=\"_disk0\"} 4096\n"));
+        assert!(output.contains("# TYPE cloud_hypervisor_device_read_latency_avg gauge\n"));
+        assert!(output.contains("cloud_hypervisor_device_read_latency_avg{id=\"_disk0\"} 12\n"));
+        assert!(output.contains("cloud_hypervisor_device_rx_bytes_total{id=\"net\\\"0\"} 42\n"));
+        assert!(output.contains("cloud_hypervisor_vcpu_mmio_write_exits_total{vcpu=\"0\"} 7\n"));
+        assert!(output.contains("cloud_hypervisor_migration_phase{phase=\"dirty-memory\"} 1\n"));
+        assert_eq!(
+            output
+                .matches("# TYPE cloud_hypervisor_device_read_bytes ")
+                .count(),
+            1
+        );
+        assert!(output.ends_with("# EOF\n"));
+    }
+}
@@ -7,39 +7,61 @@ use core::fmt;
 use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::fs::File;
-use std::os::unix::io::{IntoRawFd, RawFd};
-use std::os::unix::net::UnixListener;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
 use std::panic::AssertUnwindSafe;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
 use std::thread;
 
+use event_monitor::Subscribers;
 use hypervisor::HypervisorType;
 use micro_http::{
-    Body, HttpServer, MediaType, Method, Request, Response, ServerError, StatusCode, Version,
+    Body, ConnectionError, HttpConnection, MediaType, Method, Request, Response, StatusCode,
+    Version,
 };
 use once_cell::sync::Lazy;
 use seccompiler::{apply_filter, SeccompAction};
 use serde_json::Error as SerdeError;
 use vmm_sys_util::eventfd::EventFd;
 
-use self::http_endpoint::{VmActionHandler, VmCreate, VmInfo, VmmPing, VmmShutdown};
+use self::events::{handle_events_request, is_events_request};
+use self::http_endpoint::{
+    VmActionHandler, VmCreate, VmInfo, VmMigrationCancel, VmMigrationStatus, VmmCreateVm,
+    VmmJobStatus, VmmPing, VmmShutdown, VmmValidateConfig,
+};
+use self::raw::{write_error, RequestError};
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use crate::api::VmCoredump;
 use crate::api::{
-    AddDisk, ApiError, ApiRequest, VmAddDevice, VmAddFs, VmAddNet, VmAddPmem, VmAddUserDevice,
-    VmAddVdpa, VmAddVsock, VmBoot, VmCounters, VmDelete, VmNmi, VmPause, VmPowerButton, VmReboot,
-    VmReceiveMigration, VmRemoveDevice, VmResize, VmResizeZone, VmRestore, VmResume,
-    VmSendMigration, VmShutdown, VmSnapshot,
+    vm_api_sender, AddDisk, ApiError, ApiRequest, VmAddDevice, VmAddFs, VmAddNet, VmAddPmem,
+    VmAddUserDevice, VmAddVdpa, VmAddVsock, VmBalloonStatistics, VmBoot, VmCounters, VmDelete,
+    VmNmi, VmPause, VmPowerButton, VmReboot, VmReceiveMigration, VmRemoveDevice, VmResize,
+    VmResizeDisk, VmResizeZone, VmRestore, VmResume, VmSendMigration, VmShutdown, VmSnapshot,
+    VmUpdate, VmUpdateRateLimiter, VmVcpuStats, VmmJobCancel, VmmJobs, VmmListVms, DEFAULT_VM_ID,
 };
+use crate::jobs;
 use crate::landlock::Landlock;
 use crate::seccomp_filters::{get_seccomp_filter, Thread};
+use crate::vm::Error as VmError;
 use crate::{Error as VmmError, Result};
 
+pub mod events;
 pub mod http_endpoint;
+pub mod metrics;
+mod raw;
+#[cfg(feature = "tls_api")]
+pub mod tcp;
 
 pub type HttpApiHandle = (thread::JoinHandle<Result<()>>, EventFd);
 
+// Maximum number of connections an API server serves at once, the events
+// streams included.
+const MAX_CONNECTIONS: usize = 64;
+
 /// Errors associated with VMM management
 #[derive(Debug)]
 pub enum HttpError {
@@ -79,6 +101,7 @@ impl From<serde_json::Error> for HttpError {
 }
 
 const HTTP_ROOT: &str = "/api/v1";
+const HTTP_VMS_ROOT: &str = "/api/v1/vms/";
 
 pub fn error_response(error: HttpError, status: StatusCode) -> Response {
     let mut response = Response::new(Version::Http11, status);
@@ -199,6 +222,10 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.add-vsock"),
         Box::new(VmActionHandler::new(&VmAddVsock)),
     );
+    r.routes.insert(
+        endpoint!("/vm.balloon-stats"),
+        Box::new(VmActionHandler::new(&VmBalloonStatistics)),
+    );
     r.routes.insert(
         endpoint!("/vm.boot"),
         Box::new(VmActionHandler::new(&VmBoot)),
@@ -207,6 +234,10 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.counters"),
         Box::new(VmActionHandler::new(&VmCounters)),
     );
+    r.routes.insert(
+        endpoint!("/vm.vcpu-stats"),
+        Box::new(VmActionHandler::new(&VmVcpuStats)),
+    );
     r.routes
         .insert(endpoint!("/vm.create"), Box::new(VmCreate {}));
     r.routes.insert(
@@ -214,6 +245,14 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         Box::new(VmActionHandler::new(&VmDelete)),
     );
     r.routes.insert(endpoint!("/vm.info"), Box::new(VmInfo {}));
+    r.routes.insert(
+        endpoint!("/vm.migration-cancel"),
+        Box::new(VmMigrationCancel { id: None }),
+    );
+    r.routes.insert(
+        endpoint!("/vm.migration-status"),
+        Box::new(VmMigrationStatus { id: None }),
+    );
     r.routes.insert(
         endpoint!("/vm.pause"),
         Box::new(VmActionHandler::new(&VmPause)),
@@ -242,6 +281,10 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.resize-zone"),
         Box::new(VmActionHandler::new(&VmResizeZone)),
     );
+    r.routes.insert(
+        endpoint!("/vm.resize-disk"),
+        Box::new(VmActionHandler::new(&VmResizeDisk)),
+    );
     r.routes.insert(
         endpoint!("/vm.restore"),
         Box::new(VmActionHandler::new(&VmRestore)),
@@ -254,6 +297,14 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.send-migration"),
         Box::new(VmActionHandler::new(&VmSendMigration)),
     );
+    r.routes.insert(
+        endpoint!("/vm.update"),
+        Box::new(VmActionHandler::new(&VmUpdate)),
+    );
+    r.routes.insert(
+        endpoint!("/vm.update-rate-limiter"),
+        Box::new(VmActionHandler::new(&VmUpdateRateLimiter)),
+    );
     r.routes.insert(
         endpoint!("/vm.shutdown"),
         Box::new(VmActionHandler::new(&VmShutdown)),
@@ -271,27 +322,127 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         .insert(endpoint!("/vmm.ping"), Box::new(VmmPing {}));
     r.routes
         .insert(endpoint!("/vmm.shutdown"), Box::new(VmmShutdown {}));
+    r.routes.insert(
+        endpoint!("/vmm.create-vm"),
+        Box::new(VmmCreateVm { id: None }),
+    );
+    r.routes.insert(
+        endpoint!("/vmm.validate-config"),
+        Box::new(VmmValidateConfig {}),
+    );
+    r.routes.insert(
+        endpoint!("/vmm.list-vms"),
+        Box::new(VmActionHandler::new(&VmmListVms)),
+    );
+    r.routes.insert(
+        endpoint!("/vmm.jobs"),
+        Box::new(VmActionHandler::new(&VmmJobs)),
+    );
+    r.routes
+        .insert(endpoint!("/vmm.job-status"), Box::new(VmmJobStatus {}));
+    r.routes.insert(
+        endpoint!("/vmm.job-cancel"),
+        Box::new(VmActionHandler::new(&VmmJobCancel)),
+    );
     r.routes
         .insert(endpoint!("/vm.nmi"), Box::new(VmActionHandler::new(&VmNmi)));
 
     r
 });
 
+/// Splits `/api/v1/vms/<id>/vm.<action>` into the VM id and the path of
+/// the matching unscoped route.
+fn split_vm_path(path: &str) -> Option<(&str, String)> {
+    let (id, action) = path.strip_prefix(HTTP_VMS_ROOT)?.split_once('/')?;
+    if id.is_empty() || !action.starts_with("vm.") {
+        return None;
+    }
+
+    Some((id, format!("{HTTP_ROOT}/{action}")))
+}
+
+/// Answers the read-only requests about the VM `id` while a job keeps the
+/// VMM thread busy, or returns `None` to let the VMM thread handle them.
+fn running_job_response(request: &Request, id: &str, route: &str) -> Option<Response> {
+    if !matches!(request.method(), Method::Get)
+        || (route != endpoint!("/vm.info") && route != endpoint!("/vm.counters"))
+    {
+        return None;
+    }
+
+    let vm = jobs::running_job_vm(id)?;
+    let body = if route == endpoint!("/vm.info") {
+        vm.ok_or(VmError::VmNotCreated)
+            .and_then(|vm| vm.info())
+            .and_then(|info| serde_json::to_vec(&info).map_err(VmError::SerializeJson))
+    } else {
+        vm.ok_or(VmError::VmNotRunning)
+            .and_then(|vm| vm.counters())
+            .and_then(|counters| serde_json::to_vec(&counters).map_err(VmError::SerializeJson))
+    };
+
+    Some(match body {
+        Ok(body) => {
+            let mut response = Response::new(Version::Http11, StatusCode::OK);
+            response.set_body(Body::new(body));
+            response
+        }
+        Err(e) => error_response(
+            HttpError::ApiError(ApiError::VmInfo(e)),
+            StatusCode::InternalServerError,
+        ),
+    })
+}
+
 fn handle_http_request(
     request: &Request,
     api_notifier: &EventFd,
     api_sender: &Sender<ApiRequest>,
 ) -> Response {
     let path = request.uri().get_abs_path().to_string();
-    let mut response = match HTTP_ROUTES.routes.get(&path) {
-        Some(route) => match api_notifier.try_clone() {
-            Ok(notifier) => route.handle_request(request, notifier, api_sender.clone()),
-            Err(_) => error_response(
-                HttpError::InternalServerError,
-                StatusCode::InternalServerError,
-            ),
+    let mut response = match api_notifier.try_clone() {
+        Ok(notifier) => match split_vm_path(&path) {
+            // Creating a VM under a given id is a VMM request.
+            Some((id, route)) if route == endpoint!("/vm.create") => VmmCreateVm {
+                id: Some(id.to_owned()),
+            }
+            .handle_request(request, notifier, api_sender.clone()),
+            Some((id, route)) => running_job_response(request, id, &route).unwrap_or_else(|| {
+                let sender = if id == DEFAULT_VM_ID {
+                    Some(api_sender.clone())
+                } else {
+                    vm_api_sender(id)
+                };
+                match (HTTP_ROUTES.routes.get(&route), sender) {
+                    // The migration progress is shared by the VMs, it must
+                    // be told which of them the request is about.
+                    (Some(_), Some(sender)) if route == endpoint!("/vm.migration-status") => {
+                        VmMigrationStatus {
+                            id: Some(id.to_owned()),
+                        }
+                        .handle_request(request, notifier, sender)
+                    }
+                    (Some(_), Some(sender)) if route == endpoint!("/vm.migration-cancel") => {
+                        VmMigrationCancel {
+                            id: Some(id.to_owned()),
+                        }
+                        .handle_request(request, notifier, sender)
+                    }
+                    (Some(route), Some(sender)) => route.handle_request(request, notifier, sender),
+                    _ => error_response(HttpError::NotFound, StatusCode::NotFound),
+                }
+            }),
+            None => running_job_response(request, DEFAULT_VM_ID, &path).unwrap_or_else(|| {
+                match HTTP_ROUTES.routes.get(&path) {
+                    Some(route) => route.handle_request(request, notifier, api_sender.clone()),
+                    None => error_response(HttpError::NotFound, StatusCode::NotFound),
+                }
+            }),
         },
-        None => error_response(HttpError::NotFound, StatusCode::NotFound),
+        Err(_) => error_response(
+            HttpError::InternalServerError,
+            StatusCode::InternalServerError,
+        ),
     };
 
     response.set_server("Cloud Hypervisor API");
@@ -299,8 +450,173 @@ fn handle_http_request(
     response
 }
 
+// Frees its slot once the connection is closed.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn take(connections: &Arc<AtomicUsize>) -> Option<Self> {
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        Some(ConnectionSlot(connections.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Serves the requests of a connection until the client closes it, unless a
+/// `vmm.events` request takes the connection over.
+///
+/// `HttpServer` can't give a connection back once it has accepted it, so the
+/// connections are driven through the `HttpConnection` it would use for them.
+fn handle_connection(
+    stream: UnixStream,
+    subscribers: Option<&Subscribers>,
+    api_notifier: &EventFd,
+    api_sender: &Sender<ApiRequest>,
+) {
+    let events_stream = match stream.try_clone() {
+        Ok(events_stream) => events_stream,
+        Err(e) => {
+            error!("Error cloning HTTP API connection: {}", e);
+            return;
+        }
+    };
+    let mut connection = HttpConnection::new(stream);
+
+    loop {
+        match connection.try_read() {
+            Ok(()) => {}
+            Err(ConnectionError::ParseError(e)) => {
+                // Same answer as `HttpServer` for a request it can't parse.
+                let mut response = Response::new(Version::Http11, StatusCode::BadRequest);
+                response.set_body(Body::new(format!("{{ \"error\": \"{e}\" }}")));
+                connection.enqueue_response(response);
+                flush_responses(&mut connection);
+                return;
+            }
+            Err(_) => return,
+        }
+
+        while let Some(request) = connection.pop_parsed_request() {
+            if is_events_request(&request) {
+                // Whatever the client sent past this request is dropped.
+                if flush_responses(&mut connection) {
+                    handle_events_request(events_stream, &request, subscribers);
+                }
+                return;
+            }
+            connection.enqueue_response(handle_http_request(&request, api_notifier, api_sender));
+        }
+        if !flush_responses(&mut connection) {
+            return;
+        }
+    }
+}
+
+// Writes the queued responses, returning `false` if the client is gone.
+fn flush_responses(connection: &mut HttpConnection<UnixStream>) -> bool {
+    while connection.pending_write() {
+        if let Err(e) = connection.try_write() {
+            warn!("Error writing HTTP API response: {:?}", e);
+            return false;
+        }
+    }
+    true
+}
+
+// Waits for a new connection, returning `false` when the server is shut down.
+fn wait_for_connection(listener: &UnixListener, shutdown: &EventFd) -> io::Result<bool> {
+    let mut pollfds = [
+        libc::pollfd {
+            fd: listener.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: shutdown.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    loop {
+        // SAFETY: FFI call with valid pollfds
+        let ret = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, -1) };
+        if ret >= 0 {
+            return Ok(pollfds[1].revents == 0);
+        }
+
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+fn serve_connections(
+    listener: UnixListener,
+    shutdown: EventFd,
+    subscribers: Option<Subscribers>,
+    api_notifier: EventFd,
+    api_sender: Sender<ApiRequest>,
+) {
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    loop {
+        match wait_for_connection(&listener, &shutdown) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                error!("Error waiting for HTTP API connections: {}", e);
+                return;
+            }
+        }
+
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => {
+                error!("Error accepting HTTP API connection: {}", e);
+                continue;
+            }
+        };
+
+        let Some(slot) = ConnectionSlot::take(&connections) else {
+            write_error(&mut stream, &RequestError::ServiceUnavailable);
+            continue;
+        };
+        let subscribers = subscribers.clone();
+        let api_notifier = match api_notifier.try_clone() {
+            Ok(api_notifier) => api_notifier,
+            Err(e) => {
+                error!("Error cloning the API notifier: {}", e);
+                continue;
+            }
+        };
+        let api_sender = api_sender.clone();
+        if let Err(e) = thread::Builder::new()
+            .name("http-client".to_string())
+            .spawn(move || {
+                let _slot = slot;
+                handle_connection(stream, subscribers.as_ref(), &api_notifier, &api_sender)
+            })
+        {
+            error!("Error spawning HTTP API connection thread: {}", e);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn start_http_thread(
-    mut server: HttpServer,
+    listener: UnixListener,
+    subscribers: Option<Subscribers>,
     api_notifier: EventFd,
     api_sender: Sender<ApiRequest>,
     seccomp_action: &SeccompAction,
@@ -308,16 +624,18 @@ fn start_http_thread(
     hypervisor_type: HypervisorType,
     landlock_enable: bool,
 ) -> Result<HttpApiHandle> {
-    // Retrieve seccomp filter for API thread
+    // Retrieve seccomp filter for API thread, inherited by the connection
+    // threads.
     let api_seccomp_filter = get_seccomp_filter(seccomp_action, Thread::HttpApi, hypervisor_type)
         .map_err(VmmError::CreateSeccompFilter)?;
 
     let api_shutdown_fd = EventFd::new(libc::EFD_NONBLOCK).map_err(VmmError::EventFdCreate)?;
     let api_shutdown_fd_clone = api_shutdown_fd.try_clone().unwrap();
 
-    server
-        .add_kill_switch(api_shutdown_fd_clone)
-        .map_err(VmmError::CreateApiServer)?;
+    // Connections going away before being accepted mustn't block the thread.
+    listener
+        .set_nonblocking(true)
+        .map_err(VmmError::CreateApiServerSocket)?;
 
     let thread = thread::Builder::new()
         .name("http-server".to_string())
@@ -346,30 +664,13 @@ fn start_http_thread(
             }
 
             std::panic::catch_unwind(AssertUnwindSafe(move || {
-                server.start_server().unwrap();
-                loop {
-                    match server.requests() {
-                        Ok(request_vec) => {
-                            for server_request in request_vec {
-                                if let Err(e) = server.respond(server_request.process(|request| {
-                                    handle_http_request(request, &api_notifier, &api_sender)
-                                })) {
-                                    error!("HTTP server error on response: {}", e);
-                                }
-                            }
-                        }
-                        Err(ServerError::ShutdownEvent) => {
-                            server.flush_outgoing_writes();
-                            return;
-                        }
-                        Err(e) => {
-                            error!(
-                                "HTTP server error on retrieving incoming request. Error: {}",
-                                e
-                            );
-                        }
-                    }
-                }
+                serve_connections(
+                    listener,
+                    api_shutdown_fd_clone,
+                    subscribers,
+                    api_notifier,
+                    api_sender,
+                )
             }))
             .map_err(|_| {
                 error!("http-server thread panicked");
@@ -384,8 +685,10 @@ fn start_http_thread(
     Ok((thread, api_shutdown_fd))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn start_http_path_thread(
     path: &str,
+    subscribers: Option<Subscribers>,
     api_notifier: EventFd,
     api_sender: Sender<ApiRequest>,
     seccomp_action: &SeccompAction,
@@ -394,13 +697,11 @@ pub fn start_http_path_thread(
     landlock_enable: bool,
 ) -> Result<HttpApiHandle> {
     let socket_path = PathBuf::from(path);
-    let socket_fd = UnixListener::bind(socket_path).map_err(VmmError::CreateApiServerSocket)?;
-    // SAFETY: Valid FD just opened
-    let server = unsafe { HttpServer::new_from_fd(socket_fd.into_raw_fd()) }
-        .map_err(VmmError::CreateApiServer)?;
+    let listener = UnixListener::bind(socket_path).map_err(VmmError::CreateApiServerSocket)?;
 
     start_http_thread(
-        server,
+        listener,
+        subscribers,
         api_notifier,
         api_sender,
         seccomp_action,
@@ -410,8 +711,10 @@ pub fn start_http_path_thread(
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn start_http_fd_thread(
     fd: RawFd,
+    subscribers: Option<Subscribers>,
     api_notifier: EventFd,
     api_sender: Sender<ApiRequest>,
     seccomp_action: &SeccompAction,
@@ -420,9 +723,10 @@ pub fn start_http_fd_thread(
     landlock_enable: bool,
 ) -> Result<HttpApiHandle> {
     // SAFETY: Valid FD
-    let server = unsafe { HttpServer::new_from_fd(fd) }.map_err(VmmError::CreateApiServer)?;
+    let listener = unsafe { UnixListener::from_raw_fd(fd) };
     start_http_thread(
-        server,
+        listener,
+        subscribers,
         api_notifier,
         api_sender,
         seccomp_action,
@@ -438,3 +742,115 @@ pub fn http_api_graceful_shutdown(http_handle: HttpApiHandle) -> Result<()> {
     api_shutdown_fd.write(1).unwrap();
     api_thread.join().map_err(VmmError::ThreadCleanup)?
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    use super::*;
+
+    #[test]
+    fn test_split_vm_path() {
+        assert_eq!(
+            split_vm_path("/api/v1/vms/vm1/vm.info"),
+            Some(("vm1", "/api/v1/vm.info".to_string()))
+        );
+        assert_eq!(
+            split_vm_path("/api/v1/vms/default/vm.create"),
+            Some(("default", "/api/v1/vm.create".to_string()))
+        );
+        assert_eq!(split_vm_path("/api/v1/vm.info"), None);
+        assert_eq!(split_vm_path("/api/v1/vms//vm.info"), None);
+        assert_eq!(split_vm_path("/api/v1/vms/vm1/vmm.shutdown"), None);
+        assert_eq!(split_vm_path("/api/v1/vms/vm1"), None);
+    }
+
+    fn serve(request: &[u8], subscribers: &Subscribers) -> (UnixStream, thread::JoinHandle<()>) {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(request).unwrap();
+        let subscribers = subscribers.clone();
+        let handle = thread::spawn(move || {
+            let (api_sender, _api_receiver) = std::sync::mpsc::channel();
+            handle_connection(
+                server,
+                Some(&subscribers),
+                &EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+                &api_sender,
+            )
+        });
+
+        (client, handle)
+    }
+
+    #[test]
+    fn test_handle_connection_events() {
+        let subscribers = Subscribers::default();
+        let (client, handle) = serve(b"GET /api/v1/vmm.events HTTP/1.1\r\n\r\n", &subscribers);
+
+        let mut client = BufReader::new(client);
+        let mut line = String::new();
+        client.read_line(&mut line).unwrap();
+        assert_eq!(line, "HTTP/1.1 200 OK\r\n");
+        while line != "\r\n" {
+            line.clear();
+            client.read_line(&mut line).unwrap();
+        }
+
+        subscribers.publish("{\"event\": \"booted\"}");
+        line.clear();
+        client.read_line(&mut line).unwrap();
+        assert_eq!(line, "{\"event\":\"booted\"}\n");
+
+        drop(client);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_handle_connection_events_after_request() {
+        let subscribers = Subscribers::default();
+        let (client, handle) = serve(
+            b"GET /api/v1/vmm.unknown HTTP/1.1\r\n\r\nGET /api/v1/vmm.events HTTP/1.1\r\n\r\n",
+            &subscribers,
+        );
+
+        // The pending responses are written before the connection is handed
+        // over to the events stream.
+        let mut client = BufReader::new(client);
+        let mut line = String::new();
+        client.read_line(&mut line).unwrap();
+        assert!(line.starts_with("HTTP/1.1 404"));
+        // The body of the error response isn't newline terminated.
+        while !line.ends_with("HTTP/1.1 200 OK\r\n") {
+            line.clear();
+            client.read_line(&mut line).unwrap();
+        }
+
+        drop(client);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_handle_connection_bad_request() {
+        let (mut client, handle) = serve(b"GET /api/v1/vmm.ping\r\n\r\n", &Subscribers::default());
+        handle.join().unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+
+    #[test]
+    fn test_connection_slots() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let mut slots: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| ConnectionSlot::take(&connections).unwrap())
+            .collect();
+        assert!(ConnectionSlot::take(&connections).is_none());
+        assert_eq!(connections.load(Ordering::SeqCst), MAX_CONNECTIONS);
+
+        slots.pop();
+        assert!(ConnectionSlot::take(&connections).is_some());
+        drop(slots);
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+    }
+}
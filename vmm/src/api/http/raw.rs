@@ -0,0 +1,134 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Bare HTTP/1.1 handling for the endpoints served outside of `micro_http`,
+//! every connection carrying a single request.
+
+use std::io::{self, BufRead, Write};
+
+// Upper bound on the size of the request line and headers.
+pub(super) const MAX_REQUEST_HEAD_SIZE: u64 = 8192;
+
+#[derive(Debug)]
+pub(super) enum RequestError {
+    BadRequest(&'static str),
+    #[cfg(feature = "tls_api")]
+    Unauthorized,
+    #[cfg(feature = "tls_api")]
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    ServiceUnavailable,
+}
+
+impl RequestError {
+    fn status(&self) -> &'static str {
+        match self {
+            RequestError::BadRequest(_) => "400 Bad Request",
+            #[cfg(feature = "tls_api")]
+            RequestError::Unauthorized => "401 Unauthorized",
+            #[cfg(feature = "tls_api")]
+            RequestError::Forbidden => "403 Forbidden",
+            RequestError::NotFound => "404 Not Found",
+            RequestError::MethodNotAllowed => "405 Method Not Allowed",
+            RequestError::ServiceUnavailable => "503 Service Unavailable",
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            RequestError::BadRequest(msg) => msg,
+            #[cfg(feature = "tls_api")]
+            RequestError::Unauthorized => "Unauthorized",
+            #[cfg(feature = "tls_api")]
+            RequestError::Forbidden => "Read-only access",
+            RequestError::NotFound => "Not Found",
+            RequestError::MethodNotAllowed => "Method Not Allowed",
+            RequestError::ServiceUnavailable => "Too many clients",
+        }
+    }
+
+    fn headers(&self) -> &'static str {
+        // Tells the client how to authenticate.
+        #[cfg(feature = "tls_api")]
+        if matches!(self, RequestError::Unauthorized) {
+            return "WWW-Authenticate: Bearer\r\n";
+        }
+
+        ""
+    }
+}
+
+pub(super) struct RequestHead {
+    pub method: String,
+    pub path: String,
+}
+
+/// Reads the request line, skipping the headers as none of them changes how
+/// these endpoints behave.
+pub(super) fn read_request_head(
+    stream: &mut impl BufRead,
+) -> std::result::Result<RequestHead, RequestError> {
+    let mut request_line = String::new();
+    stream
+        .read_line(&mut request_line)
+        .map_err(|_| RequestError::BadRequest("Invalid request line"))?;
+
+    loop {
+        let mut header = String::new();
+        match stream.read_line(&mut header) {
+            Ok(0) => return Err(RequestError::BadRequest("Incomplete request")),
+            Ok(_) if header.trim_end().is_empty() => break,
+            Ok(_) => {}
+            Err(_) => return Err(RequestError::BadRequest("Invalid header")),
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(RequestError::BadRequest("Invalid request line"));
+    };
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+
+    Ok(RequestHead {
+        method: method.to_string(),
+        path: path.to_string(),
+    })
+}
+
+/// Writes the status line and headers. Without a content length, the body
+/// lasts until the connection is closed.
+pub(super) fn write_response_head(
+    stream: &mut impl Write,
+    content_type: &str,
+    content_length: Option<usize>,
+) -> io::Result<()> {
+    let content_length = content_length
+        .map(|len| format!("Content-Length: {len}\r\n"))
+        .unwrap_or_default();
+    stream.write_all(
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\n{content_length}Cache-Control: no-cache\r\nConnection: close\r\n\r\n"
+        )
+        .as_bytes(),
+    )
+}
+
+pub(super) fn write_error(stream: &mut impl Write, error: &RequestError) {
+    let body = format!("{}\n", error.message());
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.1 {}\r\n{}Content-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                error.status(),
+                error.headers(),
+                body.len(),
+                body
+            )
+            .as_bytes(),
+        )
+        .ok();
+}
@@ -0,0 +1,613 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! HTTP API served over TCP, for the VMM to be driven remotely.
+//!
+//! Connections are secured with TLS and clients authenticate either with a
+//! certificate issued by one of the client CAs or with a bearer token. Each
+//! client is granted read-only or read-write access: the `GET` endpoints only
+//! report on the VMM and the VMs, all the other ones require read-write
+//! access. Every connection carries a single request, and is served from its
+//! own thread.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hypervisor::HypervisorType;
+use micro_http::{Method, Request};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::{VerifierBuilderError, WebPkiClientVerifier};
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use seccompiler::{apply_filter, SeccompAction};
+use thiserror::Error;
+use vmm_sys_util::eventfd::EventFd;
+
+use super::raw::{write_error, RequestError, MAX_REQUEST_HEAD_SIZE};
+use super::{handle_http_request, ConnectionSlot};
+use crate::api::ApiRequest;
+use crate::landlock::Landlock;
+use crate::seccomp_filters::{get_seccomp_filter, Thread};
+use crate::{Error as VmmError, Result};
+
+// Upper bound on the size of a request body, VM configurations included.
+const MAX_REQUEST_BODY_SIZE: u64 = 1 << 20;
+// How long a client may take to complete the handshake and send its request,
+// however slowly it trickles the bytes in.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// How long a client may stall reading the response.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Errors associated with the configuration of the HTTP API over TCP
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Error reading {0}: {1}")]
+    ReadFile(PathBuf, #[source] io::Error),
+
+    #[error("No certificate found in {0}")]
+    NoCertificate(PathBuf),
+
+    #[error("No private key found in {0}")]
+    NoPrivateKey(PathBuf),
+
+    #[error("Invalid token at {0}:{1}, expected `read-only|read-write <token>`")]
+    InvalidToken(PathBuf, usize),
+
+    #[error("Neither a client CA nor a token authenticates the clients")]
+    NoAuthentication,
+
+    #[error("Invalid client CA: {0}")]
+    ClientCa(#[source] VerifierBuilderError),
+
+    #[error("Invalid TLS configuration: {0}")]
+    Tls(#[source] rustls::Error),
+}
+
+pub struct HttpTcpOptions {
+    pub addr: SocketAddr,
+    /// Certificate chain of the server, in PEM format
+    pub cert: PathBuf,
+    /// Private key of the server, in PEM format
+    pub key: PathBuf,
+    /// CA issuing the certificates of the read-write clients
+    pub client_ca: Option<PathBuf>,
+    /// CA issuing the certificates of the read-only clients
+    pub read_only_client_ca: Option<PathBuf>,
+    /// File listing the accepted bearer tokens
+    pub tokens: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Access {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl FromStr for Access {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "read-only" => Ok(Access::ReadOnly),
+            "read-write" => Ok(Access::ReadWrite),
+            _ => Err(()),
+        }
+    }
+}
+
+struct Authenticator {
+    // Only the read-write CA, the handshake having already checked the client
+    // certificates against both CAs.
+    read_write_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    tokens: Vec<(Access, String)>,
+}
+
+impl Authenticator {
+    fn certificate_access(&self, certs: Option<&[CertificateDer]>) -> Option<Access> {
+        let (end_entity, intermediates) = certs?.split_first()?;
+        let read_write = self.read_write_verifier.as_ref().is_some_and(|verifier| {
+            verifier
+                .verify_client_cert(end_entity, intermediates, UnixTime::now())
+                .is_ok()
+        });
+
+        Some(if read_write {
+            Access::ReadWrite
+        } else {
+            Access::ReadOnly
+        })
+    }
+
+    fn token_access(&self, authorization: Option<&str>) -> Option<Access> {
+        let token = authorization?.strip_prefix("Bearer ")?.trim();
+        self.tokens
+            .iter()
+            .filter(|(_, t)| constant_time_eq(t.as_bytes(), token.as_bytes()))
+            .map(|(access, _)| *access)
+            .max()
+    }
+
+    /// Returns the access granted by the certificate or the token, whichever
+    /// is the widest, or `None` if the client isn't authenticated.
+    fn access(
+        &self,
+        certs: Option<&[CertificateDer]>,
+        authorization: Option<&str>,
+    ) -> Option<Access> {
+        self.certificate_access(certs)
+            .max(self.token_access(authorization))
+    }
+}
+
+// Doesn't leak how much of a token was guessed right through its timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Parses the tokens, one `read-only|read-write <token>` entry per line.
+/// Empty lines and lines starting with `#` are skipped.
+fn parse_tokens(path: &Path, tokens: &str) -> std::result::Result<Vec<(Access, String)>, Error> {
+    tokens
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            let (access, token) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| Error::InvalidToken(path.to_owned(), line_number))?;
+            let access = access
+                .parse()
+                .map_err(|_| Error::InvalidToken(path.to_owned(), line_number))?;
+
+            Ok((access, token.trim().to_owned()))
+        })
+        .collect()
+}
+
+fn read_file(path: &Path) -> std::result::Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|e| Error::ReadFile(path.to_owned(), e))
+}
+
+fn load_certs(path: &Path) -> std::result::Result<Vec<CertificateDer<'static>>, Error> {
+    let pem = read_file(path)?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|e| Error::ReadFile(path.to_owned(), e))?;
+    if certs.is_empty() {
+        return Err(Error::NoCertificate(path.to_owned()));
+    }
+
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> std::result::Result<PrivateKeyDer<'static>, Error> {
+    let pem = read_file(path)?;
+    rustls_pemfile::private_key(&mut pem.as_slice())
+        .map_err(|e| Error::ReadFile(path.to_owned(), e))?
+        .ok_or_else(|| Error::NoPrivateKey(path.to_owned()))
+}
+
+fn add_roots(roots: &mut RootCertStore, path: &Path) -> std::result::Result<(), Error> {
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(Error::Tls)?;
+    }
+
+    Ok(())
+}
+
+fn tls_config(
+    options: &HttpTcpOptions,
+) -> std::result::Result<(Arc<ServerConfig>, Authenticator), Error> {
+    let tokens = match &options.tokens {
+        Some(path) => parse_tokens(path, &String::from_utf8_lossy(&read_file(path)?))?,
+        None => Vec::new(),
+    };
+
+    let mut read_write_roots = RootCertStore::empty();
+    if let Some(path) = &options.client_ca {
+        add_roots(&mut read_write_roots, path)?;
+    }
+    let mut roots = read_write_roots.clone();
+    if let Some(path) = &options.read_only_client_ca {
+        add_roots(&mut roots, path)?;
+    }
+    if roots.is_empty() && tokens.is_empty() {
+        return Err(Error::NoAuthentication);
+    }
+
+    let read_write_verifier = if read_write_roots.is_empty() {
+        None
+    } else {
+        Some(
+            WebPkiClientVerifier::builder(Arc::new(read_write_roots))
+                .build()
+                .map_err(Error::ClientCa)?,
+        )
+    };
+
+    let builder = ServerConfig::builder();
+    let builder = if roots.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+        // Clients without a certificate may still present a token.
+        let verifier = if tokens.is_empty() {
+            verifier
+        } else {
+            verifier.allow_unauthenticated()
+        };
+        builder.with_client_cert_verifier(verifier.build().map_err(Error::ClientCa)?)
+    };
+    let config = builder
+        .with_single_cert(load_certs(&options.cert)?, load_key(&options.key)?)
+        .map_err(Error::Tls)?;
+
+    Ok((
+        Arc::new(config),
+        Authenticator {
+            read_write_verifier,
+            tokens,
+        },
+    ))
+}
+
+/// TCP stream failing the reads once its deadline has passed.
+struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Option<Instant>,
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            self.stream.set_read_timeout(Some(timeout))?;
+        }
+
+        self.stream.read(buf)
+    }
+}
+
+impl Write for DeadlineStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+struct RequestHead {
+    // Request line and headers, less the credentials
+    raw: Vec<u8>,
+    authorization: Option<String>,
+    content_length: u64,
+}
+
+fn read_request_head(stream: &mut impl BufRead) -> std::result::Result<RequestHead, RequestError> {
+    let mut request = RequestHead {
+        raw: Vec::new(),
+        authorization: None,
+        content_length: 0,
+    };
+
+    loop {
+        let mut line = String::new();
+        match stream.read_line(&mut line) {
+            Ok(0) => return Err(RequestError::BadRequest("Incomplete request")),
+            Ok(_) => {}
+            Err(_) => return Err(RequestError::BadRequest("Invalid header")),
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("authorization") {
+                request.authorization = Some(value.trim().to_owned());
+                continue;
+            }
+            if name.eq_ignore_ascii_case("content-length") {
+                request.content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| RequestError::BadRequest("Invalid Content-Length"))?;
+            }
+        }
+
+        request.raw.extend_from_slice(line.as_bytes());
+        if line.trim_end().is_empty() {
+            return Ok(request);
+        }
+    }
+}
+
+fn read_request(
+    stream: &mut impl Read,
+) -> std::result::Result<(Request, Option<String>), RequestError> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_HEAD_SIZE));
+    let head = read_request_head(&mut reader)?;
+    if head.content_length > MAX_REQUEST_BODY_SIZE {
+        return Err(RequestError::BadRequest("Request body too large"));
+    }
+
+    reader.get_mut().set_limit(head.content_length);
+    let mut body = Vec::new();
+    reader
+        .take(head.content_length)
+        .read_to_end(&mut body)
+        .map_err(|_| RequestError::BadRequest("Invalid body"))?;
+    if body.len() as u64 != head.content_length {
+        return Err(RequestError::BadRequest("Incomplete request"));
+    }
+
+    let request = Request::try_from(&[head.raw, body].concat(), None)
+        .map_err(|_| RequestError::BadRequest("Invalid request"))?;
+
+    Ok((request, head.authorization))
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    tls_config: &Arc<ServerConfig>,
+    authenticator: &Authenticator,
+    api_notifier: &EventFd,
+    api_sender: &Sender<ApiRequest>,
+) -> io::Result<()> {
+    stream.set_write_timeout(Some(RESPONSE_TIMEOUT))?;
+
+    let connection = ServerConnection::new(tls_config.clone()).map_err(io::Error::other)?;
+    let stream = DeadlineStream {
+        stream,
+        deadline: Some(Instant::now() + REQUEST_TIMEOUT),
+    };
+    let mut stream = StreamOwned::new(connection, stream);
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+
+    let request =
+        read_request(&mut stream).and_then(|(request, authorization)| {
+            match authenticator.access(stream.conn.peer_certificates(), authorization.as_deref()) {
+                None => Err(RequestError::Unauthorized),
+                Some(Access::ReadOnly) if !matches!(request.method(), Method::Get) => {
+                    Err(RequestError::Forbidden)
+                }
+                Some(_) => Ok(request),
+            }
+        });
+    // The VMM may take a while to handle the request, only the client is
+    // bounded in time.
+    stream.sock.deadline = None;
+
+    match request {
+        Ok(request) => {
+            let response = handle_http_request(&request, api_notifier, api_sender);
+            response
+                .write_all(&mut stream)
+                .map_err(|e| io::Error::other(format!("{e:?}")))?;
+        }
+        Err(e) => write_error(&mut stream, &e),
+    }
+    stream.flush()?;
+
+    stream.conn.send_close_notify();
+    while stream.conn.wants_write() {
+        stream.conn.write_tls(&mut stream.sock)?;
+    }
+
+    Ok(())
+}
+
+pub fn start_http_tcp_thread(
+    options: HttpTcpOptions,
+    api_notifier: EventFd,
+    api_sender: Sender<ApiRequest>,
+    seccomp_action: &SeccompAction,
+    exit_evt: EventFd,
+    hypervisor_type: HypervisorType,
+    landlock_enable: bool,
+) -> Result<thread::JoinHandle<Result<()>>> {
+    // Retrieve seccomp filter for the TCP API thread
+    let seccomp_filter = get_seccomp_filter(seccomp_action, Thread::HttpTcpApi, hypervisor_type)
+        .map_err(VmmError::CreateSeccompFilter)?;
+
+    let (tls_config, authenticator) = tls_config(&options).map_err(VmmError::HttpTcpApiConfig)?;
+    let listener = TcpListener::bind(options.addr).map_err(VmmError::CreateApiServerSocket)?;
+
+    thread::Builder::new()
+        .name("http-tcp-server".to_string())
+        .spawn(move || {
+            if !seccomp_filter.is_empty() {
+                apply_filter(&seccomp_filter)
+                    .map_err(VmmError::ApplySeccompFilter)
+                    .map_err(|e| {
+                        error!("Error applying seccomp filter: {:?}", e);
+                        exit_evt.write(1).ok();
+                        e
+                    })?;
+            }
+
+            if landlock_enable {
+                Landlock::new()
+                    .map_err(VmmError::CreateLandlock)?
+                    .restrict_self()
+                    .map_err(VmmError::ApplyLandlock)
+                    .map_err(|e| {
+                        error!("Error applying landlock to http-tcp-server thread: {:?}", e);
+                        exit_evt.write(1).ok();
+                        e
+                    })?;
+            }
+
+            std::panic::catch_unwind(AssertUnwindSafe(move || {
+                let authenticator = Arc::new(authenticator);
+                let connections = Arc::new(AtomicUsize::new(0));
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            error!("Error accepting HTTP API connection: {}", e);
+                            continue;
+                        }
+                    };
+
+                    // There is no way to answer before the handshake, the
+                    // connection is simply closed.
+                    let Some(slot) = ConnectionSlot::take(&connections) else {
+                        warn!("Too many HTTP API connections, closing the new one");
+                        continue;
+                    };
+                    let api_notifier = match api_notifier.try_clone() {
+                        Ok(api_notifier) => api_notifier,
+                        Err(e) => {
+                            error!("Error cloning the API notifier: {}", e);
+                            continue;
+                        }
+                    };
+                    let tls_config = tls_config.clone();
+                    let authenticator = authenticator.clone();
+                    let api_sender = api_sender.clone();
+                    if let Err(e) = thread::Builder::new()
+                        .name("http-tcp-client".to_string())
+                        .spawn(move || {
+                            let _slot = slot;
+                            if let Err(e) = handle_connection(
+                                stream,
+                                &tls_config,
+                                &authenticator,
+                                &api_notifier,
+                                &api_sender,
+                            ) {
+                                warn!("Error serving HTTP API connection: {}", e);
+                            }
+                        })
+                    {
+                        error!("Error spawning HTTP API connection thread: {}", e);
+                    }
+                }
+            }))
+            .map_err(|_| {
+                error!("http-tcp-server thread panicked");
+                exit_evt.write(1).ok()
+            })
+            .ok();
+
+            Ok(())
+        })
+        .map_err(VmmError::HttpThreadSpawn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tokens() {
+        let path = Path::new("tokens");
+        let tokens = parse_tokens(
+            path,
+            "# Monitoring\nread-only  s3cret \n\nread-write admin-token\n",
+        )
+        .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (Access::ReadOnly, "s3cret".to_string()),
+                (Access::ReadWrite, "admin-token".to_string()),
+            ]
+        );
+
+        assert!(matches!(
+            parse_tokens(path, "read-only a\nadmin b\n"),
+            Err(Error::InvalidToken(_, 2))
+        ));
+        assert!(matches!(
+            parse_tokens(path, "read-write\n"),
+            Err(Error::InvalidToken(_, 1))
+        ));
+    }
+
+    #[test]
+    fn test_token_access() {
+        let authenticator = Authenticator {
+            read_write_verifier: None,
+            tokens: vec![
+                (Access::ReadOnly, "shared".to_string()),
+                (Access::ReadOnly, "monitoring".to_string()),
+                (Access::ReadWrite, "shared".to_string()),
+            ],
+        };
+
+        assert_eq!(authenticator.access(None, None), None);
+        assert_eq!(authenticator.access(None, Some("Bearer wrong")), None);
+        assert_eq!(authenticator.access(None, Some("Basic monitoring")), None);
+        assert_eq!(
+            authenticator.access(None, Some("Bearer monitoring")),
+            Some(Access::ReadOnly)
+        );
+        assert_eq!(
+            authenticator.access(None, Some("Bearer shared")),
+            Some(Access::ReadWrite)
+        );
+        assert_eq!(
+            authenticator.access(Some(&[][..]), Some("Bearer monitoring")),
+            Some(Access::ReadOnly)
+        );
+    }
+
+    #[test]
+    fn test_deadline_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let start = Instant::now();
+        let mut stream = DeadlineStream {
+            stream,
+            deadline: Some(start + Duration::from_millis(300)),
+        };
+
+        // A client trickling the bytes in doesn't get more time.
+        let trickle = thread::spawn(move || {
+            for _ in 0..10 {
+                if client.write_all(b"x").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        });
+        let mut buf = [0u8; 1];
+        let error = loop {
+            if let Err(e) = stream.read(&mut buf) {
+                break e;
+            }
+        };
+        assert!(matches!(
+            error.kind(),
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+        ));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(300));
+        assert!(elapsed < Duration::from_millis(900));
+
+        // Without a deadline, the reads wait for the client.
+        stream.deadline = None;
+        stream.stream.set_read_timeout(None).unwrap();
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"x");
+        drop(stream);
+        trickle.join().unwrap();
+    }
+}
@@ -28,32 +28,69 @@
 //! 4. The thread reads the response back from the VMM API server, from the
 //!    response channel Receiver.
 //! 5. The thread handles the response and forwards potential errors.
+//!
+//! Every VM created through [`VmmCreateVm`] apart from the default one gets
+//! its own API channel, looked up with [`vm_api_sender`]. Requests sent on it
+//! are handled against that VM, while the main channel always targets the
+//! default VM. All channels share the same API event file descriptor.
+//!
+//! The long running actions can also be queued as jobs, in which case the
+//! caller gets the job id back at once and follows its progress through the
+//! [`jobs`](crate::jobs) registry rather than waiting for the response.
 
 #[cfg(feature = "dbus_api")]
 pub mod dbus;
 pub mod http;
 
 use core::fmt;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
 use std::io;
 use std::sync::mpsc::{channel, RecvError, SendError, Sender};
+use std::sync::Mutex;
 
 use micro_http::Body;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use virtio_devices::RateLimiterConfig;
 use vm_migration::MigratableError;
 use vmm_sys_util::eventfd::EventFd;
 
 #[cfg(feature = "dbus_api")]
 pub use self::dbus::start_dbus_thread;
+pub use self::http::metrics::start_metrics_thread;
+#[cfg(feature = "tls_api")]
+pub use self::http::tcp::start_http_tcp_thread;
 pub use self::http::{start_http_fd_thread, start_http_path_thread};
 use crate::config::RestoreConfig;
+use crate::config_check::ConfigCheckReport;
 use crate::device_tree::DeviceTree;
-use crate::vm::{Error as VmError, VmState};
+use crate::vm::{Error as VmError, VmState, VmView};
 use crate::vm_config::{
     DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, UserDeviceConfig, VdpaConfig,
     VmConfig, VsockConfig,
 };
-use crate::Error as VmmError;
+use crate::{jobs, migration, Error as VmmError};
+
+/// Identifier of the VM targeted by the unscoped `vm.*` requests.
+pub const DEFAULT_VM_ID: &str = "default";
+
+// Senders of the VM scoped API channels, indexed by VM identifier. The VMM
+// thread owns the matching receivers and keeps this in sync with its VMs.
+static VM_API_SENDERS: Lazy<Mutex<HashMap<String, Sender<ApiRequest>>>> = Lazy::new(Mutex::default);
+
+/// Returns the sender of the API channel of the VM `id`, if it exists.
+pub fn vm_api_sender(id: &str) -> Option<Sender<ApiRequest>> {
+    VM_API_SENDERS.lock().unwrap().get(id).cloned()
+}
+
+pub(crate) fn register_vm_api_sender(id: &str, sender: Sender<ApiRequest>) {
+    VM_API_SENDERS.lock().unwrap().insert(id.to_owned(), sender);
+}
+
+pub(crate) fn unregister_vm_api_sender(id: &str) {
+    VM_API_SENDERS.lock().unwrap().remove(id);
+}
 
 /// API errors are sent back from the VMM API server through the ApiResponse.
 #[derive(Debug)]
@@ -112,12 +149,39 @@ pub enum ApiError {
     /// The VMM could not shutdown.
     VmmShutdown(VmError),
 
+    /// The VMM could not create a new VM.
+    VmmCreateVm(VmError),
+
+    /// The VM configuration check could not be reported.
+    VmmValidateConfig(VmError),
+
+    /// The VMM could not list its VMs.
+    VmmListVms(VmError),
+
+    /// The jobs could not be reported.
+    VmmJobs(VmError),
+
+    /// The job status is not available.
+    VmmJobStatus(jobs::Error),
+
+    /// The job could not be cancelled.
+    VmmJobCancel(jobs::Error),
+
     /// The VM could not be resized
     VmResize(VmError),
 
     /// The memory zone could not be resized.
     VmResizeZone(VmError),
 
+    /// The rate limiter could not be updated.
+    VmUpdateRateLimiter(VmError),
+
+    /// The disk could not be resized.
+    VmResizeDisk(VmError),
+
+    /// The VM configuration could not be updated.
+    VmUpdate(VmError),
+
     /// The device could not be added to the VM.
     VmAddDevice(VmError),
 
@@ -157,6 +221,18 @@ pub enum ApiError {
     /// Error starting migration sender
     VmSendMigration(MigratableError),
 
+    /// Error retrieving the migration status
+    VmMigrationStatus(VmError),
+
+    /// Error cancelling the migration
+    VmMigrationCancel(MigratableError),
+
+    /// Error collecting the VM metrics
+    VmMetrics(VmError),
+
+    /// Error collecting the vCPU statistics
+    VmVcpuStats(VmError),
+
     /// Error triggering power button
     VmPowerButton(VmError),
 
@@ -187,8 +263,17 @@ impl Display for ApiError {
             VmRestore(vm_error) => write!(f, "{}", vm_error),
             VmCoredump(vm_error) => write!(f, "{}", vm_error),
             VmmShutdown(vm_error) => write!(f, "{}", vm_error),
+            VmmCreateVm(vm_error) => write!(f, "{}", vm_error),
+            VmmValidateConfig(vm_error) => write!(f, "{}", vm_error),
+            VmmListVms(vm_error) => write!(f, "{}", vm_error),
+            VmmJobs(vm_error) => write!(f, "{}", vm_error),
+            VmmJobStatus(jobs_error) => write!(f, "{}", jobs_error),
+            VmmJobCancel(jobs_error) => write!(f, "{}", jobs_error),
             VmResize(vm_error) => write!(f, "{}", vm_error),
             VmResizeZone(vm_error) => write!(f, "{}", vm_error),
+            VmUpdateRateLimiter(vm_error) => write!(f, "{}", vm_error),
+            VmResizeDisk(vm_error) => write!(f, "{}", vm_error),
+            VmUpdate(vm_error) => write!(f, "{}", vm_error),
             VmAddDevice(vm_error) => write!(f, "{}", vm_error),
             VmAddUserDevice(vm_error) => write!(f, "{}", vm_error),
             VmRemoveDevice(vm_error) => write!(f, "{}", vm_error),
@@ -202,6 +287,10 @@ impl Display for ApiError {
             VmAddVsock(vm_error) => write!(f, "{}", vm_error),
             VmReceiveMigration(migratable_error) => write!(f, "{}", migratable_error),
             VmSendMigration(migratable_error) => write!(f, "{}", migratable_error),
+            VmMigrationStatus(vm_error) => write!(f, "{}", vm_error),
+            VmMigrationCancel(migratable_error) => write!(f, "{}", migratable_error),
+            VmMetrics(vm_error) => write!(f, "{}", vm_error),
+            VmVcpuStats(vm_error) => write!(f, "{}", vm_error),
             VmPowerButton(vm_error) => write!(f, "{}", vm_error),
             VmNmi(vm_error) => write!(f, "{}", vm_error),
         }
@@ -216,6 +305,28 @@ pub struct VmInfoResponse {
     pub device_tree: Option<DeviceTree>,
 }
 
+/// Fields changed through `vm.update`.
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmUpdateResponse {
+    /// Fields applied to the running VM
+    pub applied: Vec<String>,
+    /// Fields stored in the configuration, taking effect on the next boot
+    pub pending: Vec<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct VmMetricsResponse {
+    pub state: VmState,
+    pub memory_total_size: u64,
+    pub memory_actual_size: u64,
+    pub balloon_size: u64,
+    /// Device counters, indexed by device identifier
+    pub counters: BTreeMap<String, BTreeMap<String, u64>>,
+    /// vCPU counters, indexed by `vcpu<id>`
+    #[serde(default)]
+    pub vcpu_counters: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct VmmPingResponse {
     pub build_version: String,
@@ -224,11 +335,42 @@ pub struct VmmPingResponse {
     pub features: Vec<String>,
 }
 
+#[derive(Clone, Deserialize, Serialize)]
+pub struct VmmCreateVmResponse {
+    pub id: String,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct VmmVmSummary {
+    pub id: String,
+    pub state: VmState,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct VmmJobResponse {
+    pub job_id: u64,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmmJobData {
+    pub id: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct VmmCreateVmData {
+    /// Identifier of the new VM, generated by the VMM when not provided
+    pub id: Option<String>,
+    pub config: Box<VmConfig>,
+}
+
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
 pub struct VmResizeData {
     pub desired_vcpus: Option<u8>,
     pub desired_ram: Option<u64>,
     pub desired_balloon: Option<u64>,
+    /// Return a job id at once rather than waiting for the outcome
+    #[serde(default)]
+    pub job: bool,
 }
 
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
@@ -237,6 +379,27 @@ pub struct VmResizeZoneData {
     pub desired_ram: u64,
 }
 
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmUpdateRateLimiterData {
+    /// Identifier of the rate limiter group or of the device to update
+    pub id: String,
+    pub rate_limiter_config: RateLimiterConfig,
+}
+
+/// Partial VM configuration, made of the fields to change only.
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+#[serde(transparent)]
+pub struct VmUpdateData {
+    pub config: serde_json::Value,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmResizeDiskData {
+    pub id: String,
+    /// New size of the disk in bytes
+    pub desired_size: u64,
+}
+
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
 pub struct VmRemoveDeviceData {
     pub id: String,
@@ -246,18 +409,30 @@ pub struct VmRemoveDeviceData {
 pub struct VmSnapshotConfig {
     /// The snapshot destination URL
     pub destination_url: String,
+    /// Return a job id at once rather than waiting for the outcome
+    #[serde(default)]
+    pub job: bool,
 }
 
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
 pub struct VmCoredumpData {
     /// The coredump destination file
     pub destination_url: String,
+    /// Return a job id at once rather than waiting for the outcome
+    #[serde(default)]
+    pub job: bool,
 }
 
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
 pub struct VmReceiveMigrationData {
     /// URL for the reception of migration state
     pub receiver_url: String,
+    /// Return once the migration is started rather than completed
+    #[serde(default)]
+    pub detach: bool,
+    /// Return a job id at once rather than waiting for the outcome
+    #[serde(default)]
+    pub job: bool,
 }
 
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
@@ -267,6 +442,12 @@ pub struct VmSendMigrationData {
     /// Send memory across socket without copying
     #[serde(default)]
     pub local: bool,
+    /// Return once the migration is started rather than completed
+    #[serde(default)]
+    pub detach: bool,
+    /// Return a job id at once rather than waiting for the outcome
+    #[serde(default)]
+    pub job: bool,
 }
 
 pub enum ApiResponsePayload {
@@ -281,6 +462,9 @@ pub enum ApiResponsePayload {
 
     /// Vm action response
     VmAction(Option<Vec<u8>>),
+
+    /// Metrics exported by the VM
+    VmMetrics(VmMetricsResponse),
 }
 
 /// This is the response sent by the VMM API server through the mpsc channel.
@@ -314,6 +498,18 @@ pub trait RequestHandler {
 
     fn vmm_shutdown(&mut self) -> Result<(), VmError>;
 
+    fn vmm_create_vm(
+        &mut self,
+        id: Option<String>,
+        config: Box<VmConfig>,
+    ) -> Result<String, VmError>;
+
+    fn vmm_validate_config(&self, config: Box<VmConfig>) -> ConfigCheckReport;
+
+    fn vmm_list_vms(&self) -> Result<Option<Vec<u8>>, VmError>;
+
+    fn vm_views(&self) -> HashMap<String, VmView>;
+
     fn vm_resize(
         &mut self,
         desired_vcpus: Option<u8>,
@@ -323,6 +519,16 @@ pub trait RequestHandler {
 
     fn vm_resize_zone(&mut self, id: String, desired_ram: u64) -> Result<(), VmError>;
 
+    fn vm_update_rate_limiter(
+        &mut self,
+        id: String,
+        rate_limiter_config: RateLimiterConfig,
+    ) -> Result<(), VmError>;
+
+    fn vm_resize_disk(&mut self, id: String, desired_size: u64) -> Result<(), VmError>;
+
+    fn vm_update(&mut self, update: VmUpdateData) -> Result<Option<Vec<u8>>, VmError>;
+
     fn vm_add_device(&mut self, device_cfg: DeviceConfig) -> Result<Option<Vec<u8>>, VmError>;
 
     fn vm_add_user_device(
@@ -346,6 +552,10 @@ pub trait RequestHandler {
 
     fn vm_counters(&mut self) -> Result<Option<Vec<u8>>, VmError>;
 
+    fn vm_balloon_statistics(&mut self) -> Result<Option<Vec<u8>>, VmError>;
+
+    fn vm_vcpu_stats(&mut self) -> Result<Option<Vec<u8>>, VmError>;
+
     fn vm_power_button(&mut self) -> Result<(), VmError>;
 
     fn vm_receive_migration(
@@ -359,6 +569,12 @@ pub trait RequestHandler {
     ) -> Result<(), MigratableError>;
 
     fn vm_nmi(&mut self) -> Result<(), VmError>;
+
+    fn vm_migration_status(&mut self) -> Result<Option<Vec<u8>>, VmError>;
+
+    fn vm_migration_cancel(&mut self) -> Result<(), MigratableError>;
+
+    fn vm_metrics(&mut self) -> Result<VmMetricsResponse, VmError>;
 }
 
 /// It would be nice if we could pass around an object like this:
@@ -409,6 +625,49 @@ fn get_response_body<Action: ApiAction<ResponseBody = Option<Body>>>(
     Ok(body)
 }
 
+/// Queues the action as a job and answers with the job id, the outcome
+/// being reported through `vmm.job-status`.
+fn send_job<Action: ApiAction>(
+    action: &Action,
+    name: &str,
+    api_evt: EventFd,
+    api_sender: Sender<ApiRequest>,
+    data: Action::RequestBody,
+) -> ApiResult<Option<Body>> {
+    let (response_sender, response_receiver) = channel();
+    let request = action.request(data, response_sender);
+    let id = jobs::job_create(name);
+
+    let job: ApiRequest = Box::new(move |vmm| {
+        if !jobs::job_start(id, vmm.vmm_ping(), vmm.vm_views()) {
+            return Ok(false);
+        }
+
+        let exit = request(vmm);
+        let result = match response_receiver.try_recv() {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        jobs::job_finish(id, result);
+
+        exit
+    });
+
+    let queued = api_sender
+        .send(job)
+        .map_err(ApiError::RequestSend)
+        .and_then(|_| api_evt.write(1).map_err(ApiError::EventFdWrite));
+    if let Err(e) = queued {
+        jobs::job_finish(id, Err(e.to_string()));
+        return Err(e);
+    }
+
+    serde_json::to_vec(&VmmJobResponse { job_id: id })
+        .map(|response| Some(Body::new(response)))
+        .map_err(|e| ApiError::VmmJobs(VmError::SerializeJson(e)))
+}
+
 pub trait ApiAction: Send + Sync {
     type RequestBody: Send + Sync + Sized;
     type ResponseBody: Send + Sized;
@@ -787,6 +1046,10 @@ impl ApiAction for VmCoredump {
         api_sender: Sender<ApiRequest>,
         data: Self::RequestBody,
     ) -> ApiResult<Self::ResponseBody> {
+        if data.job {
+            return send_job(self, "vm.coredump", api_evt, api_sender, data);
+        }
+
         get_response_body(self, api_evt, api_sender, data)
     }
 }
@@ -824,6 +1087,72 @@ impl ApiAction for VmCounters {
     }
 }
 
+pub struct VmBalloonStatistics;
+
+impl ApiAction for VmBalloonStatistics {
+    type RequestBody = ();
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmBalloonStatistics");
+
+            let response = vmm
+                .vm_balloon_statistics()
+                .map_err(ApiError::VmInfo)
+                .map(ApiResponsePayload::VmAction);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmVcpuStats;
+
+impl ApiAction for VmVcpuStats {
+    type RequestBody = ();
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmVcpuStats");
+
+            let response = vmm
+                .vm_vcpu_stats()
+                .map_err(ApiError::VmVcpuStats)
+                .map(ApiResponsePayload::VmAction);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
 pub struct VmCreate;
 
 impl ApiAction for VmCreate {
@@ -934,6 +1263,42 @@ impl ApiAction for VmInfo {
     }
 }
 
+pub struct VmMetrics;
+
+impl ApiAction for VmMetrics {
+    type RequestBody = ();
+    type ResponseBody = VmMetricsResponse;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmMetrics");
+
+            let response = vmm
+                .vm_metrics()
+                .map_err(ApiError::VmMetrics)
+                .map(ApiResponsePayload::VmMetrics);
+
+            // The metrics listener doesn't wait forever for the VMM thread,
+            // so the receiving end may be gone already.
+            response_sender.send(response).ok();
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: (),
+    ) -> ApiResult<VmMetricsResponse> {
+        match get_response(self, api_evt, api_sender, data)? {
+            ApiResponsePayload::VmMetrics(metrics) => Ok(metrics),
+            _ => Err(ApiError::ResponsePayloadType),
+        }
+    }
+}
+
 pub struct VmPause;
 
 impl ApiAction for VmPause {
@@ -1043,6 +1408,16 @@ impl ApiAction for VmReceiveMigration {
         Box::new(move |vmm| {
             info!("API request event: VmReceiveMigration {:?}", data);
 
+            // When detached, the outcome is only reported through
+            // vm.migration-status. Jobs report it themselves.
+            if data.detach && !data.job {
+                response_sender
+                    .send(Ok(ApiResponsePayload::Empty))
+                    .map_err(VmmError::ApiResponseSend)?;
+                vmm.vm_receive_migration(data).ok();
+                return Ok(false);
+            }
+
             let response = vmm
                 .vm_receive_migration(data)
                 .map_err(ApiError::VmReceiveMigration)
@@ -1062,6 +1437,10 @@ impl ApiAction for VmReceiveMigration {
         api_sender: Sender<ApiRequest>,
         data: Self::RequestBody,
     ) -> ApiResult<Self::ResponseBody> {
+        if data.job {
+            return send_job(self, "vm.receive-migration", api_evt, api_sender, data);
+        }
+
         get_response_body(self, api_evt, api_sender, data)
     }
 }
@@ -1140,6 +1519,10 @@ impl ApiAction for VmResize {
         api_sender: Sender<ApiRequest>,
         data: Self::RequestBody,
     ) -> ApiResult<Self::ResponseBody> {
+        if data.job {
+            return send_job(self, "vm.resize", api_evt, api_sender, data);
+        }
+
         get_response_body(self, api_evt, api_sender, data)
     }
 }
@@ -1181,6 +1564,123 @@ impl ApiAction for VmResizeZone {
     }
 }
 
+pub struct VmUpdateRateLimiter;
+
+impl ApiAction for VmUpdateRateLimiter {
+    type RequestBody = VmUpdateRateLimiterData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        update_rate_limiter_data: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!(
+                "API request event: VmUpdateRateLimiter {:?}",
+                update_rate_limiter_data
+            );
+
+            let response = vmm
+                .vm_update_rate_limiter(
+                    update_rate_limiter_data.id,
+                    update_rate_limiter_data.rate_limiter_config,
+                )
+                .map_err(ApiError::VmUpdateRateLimiter)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmResizeDisk;
+
+impl ApiAction for VmResizeDisk {
+    type RequestBody = VmResizeDiskData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        resize_disk_data: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmResizeDisk {:?}", resize_disk_data);
+
+            let response = vmm
+                .vm_resize_disk(resize_disk_data.id, resize_disk_data.desired_size)
+                .map_err(ApiError::VmResizeDisk)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmUpdate;
+
+impl ApiAction for VmUpdate {
+    type RequestBody = VmUpdateData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        update: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmUpdate {:?}", update);
+
+            let response = vmm
+                .vm_update(update)
+                .map_err(ApiError::VmUpdate)
+                .map(ApiResponsePayload::VmAction);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
 pub struct VmRestore;
 
 impl ApiAction for VmRestore {
@@ -1214,6 +1714,10 @@ impl ApiAction for VmRestore {
         api_sender: Sender<ApiRequest>,
         data: Self::RequestBody,
     ) -> ApiResult<Self::ResponseBody> {
+        if data.job {
+            return send_job(self, "vm.restore", api_evt, api_sender, data);
+        }
+
         get_response_body(self, api_evt, api_sender, data)
     }
 }
@@ -1261,6 +1765,16 @@ impl ApiAction for VmSendMigration {
         Box::new(move |vmm| {
             info!("API request event: VmSendMigration {:?}", data);
 
+            // When detached, the outcome is only reported through
+            // vm.migration-status. Jobs report it themselves.
+            if data.detach && !data.job {
+                response_sender
+                    .send(Ok(ApiResponsePayload::Empty))
+                    .map_err(VmmError::ApiResponseSend)?;
+                vmm.vm_send_migration(data).ok();
+                return Ok(false);
+            }
+
             let response = vmm
                 .vm_send_migration(data)
                 .map_err(ApiError::VmSendMigration)
@@ -1280,10 +1794,88 @@ impl ApiAction for VmSendMigration {
         api_sender: Sender<ApiRequest>,
         data: Self::RequestBody,
     ) -> ApiResult<Self::ResponseBody> {
+        if data.job {
+            return send_job(self, "vm.send-migration", api_evt, api_sender, data);
+        }
+
         get_response_body(self, api_evt, api_sender, data)
     }
 }
 
+pub struct VmMigrationStatus;
+
+impl ApiAction for VmMigrationStatus {
+    /// Id of the VM
+    type RequestBody = String;
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmMigrationStatus");
+
+            let response = vmm
+                .vm_migration_status()
+                .map_err(ApiError::VmMigrationStatus)
+                .map(ApiResponsePayload::VmAction);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        _api_evt: EventFd,
+        _api_sender: Sender<ApiRequest>,
+        id: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        // The VMM thread is busy for as long as the migration lasts, so
+        // answer straight from the shared migration progress.
+        serde_json::to_vec(&migration::migration_status(&id))
+            .map(|status| Some(Body::new(status)))
+            .map_err(|e| ApiError::VmMigrationStatus(VmError::SerializeJson(e)))
+    }
+}
+
+pub struct VmMigrationCancel;
+
+impl ApiAction for VmMigrationCancel {
+    /// Id of the VM
+    type RequestBody = String;
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmMigrationCancel");
+
+            let response = vmm
+                .vm_migration_cancel()
+                .map_err(ApiError::VmMigrationCancel)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        _api_evt: EventFd,
+        _api_sender: Sender<ApiRequest>,
+        id: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        // Same as for the status, the VMM thread is busy migrating.
+        migration::migration_cancel(&id).map_err(ApiError::VmMigrationCancel)?;
+        Ok(None)
+    }
+}
+
 pub struct VmShutdown;
 
 impl ApiAction for VmShutdown {
@@ -1354,6 +1946,10 @@ impl ApiAction for VmSnapshot {
         api_sender: Sender<ApiRequest>,
         data: Self::RequestBody,
     ) -> ApiResult<Self::ResponseBody> {
+        if data.job {
+            return send_job(self, "vm.snapshot", api_evt, api_sender, data);
+        }
+
         get_response_body(self, api_evt, api_sender, data)
     }
 }
@@ -1384,6 +1980,11 @@ impl ApiAction for VmmPing {
         api_sender: Sender<ApiRequest>,
         data: (),
     ) -> ApiResult<VmmPingResponse> {
+        // Don't wait for the VMM thread to be done with a job.
+        if let Some(pong) = jobs::running_job_ping() {
+            return Ok(pong);
+        }
+
         let vmm_pong = get_response(self, api_evt, api_sender, data)?;
 
         match vmm_pong {
@@ -1423,6 +2024,223 @@ impl ApiAction for VmmShutdown {
     }
 }
 
+pub struct VmmCreateVm;
+
+impl ApiAction for VmmCreateVm {
+    type RequestBody = VmmCreateVmData;
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, data: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmmCreateVm {:?}", data);
+
+            let response = vmm
+                .vmm_create_vm(data.id, data.config)
+                .map_err(ApiError::VmmCreateVm)
+                .and_then(|id| {
+                    serde_json::to_vec(&VmmCreateVmResponse { id })
+                        .map_err(|e| ApiError::VmmCreateVm(VmError::SerializeJson(e)))
+                })
+                .map(|body| ApiResponsePayload::VmAction(Some(body)));
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmmValidateConfig;
+
+impl ApiAction for VmmValidateConfig {
+    type RequestBody = Box<VmConfig>;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        config: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmmValidateConfig {:?}", config);
+
+            let report = vmm.vmm_validate_config(config);
+            let response = serde_json::to_vec(&report)
+                .map_err(|e| ApiError::VmmValidateConfig(VmError::SerializeJson(e)))
+                .map(|body| ApiResponsePayload::VmAction(Some(body)));
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmmListVms;
+
+impl ApiAction for VmmListVms {
+    type RequestBody = ();
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmmListVms");
+
+            let response = vmm
+                .vmm_list_vms()
+                .map_err(ApiError::VmmListVms)
+                .map(ApiResponsePayload::VmAction);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmmJobs;
+
+impl ApiAction for VmmJobs {
+    type RequestBody = ();
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |_| {
+            info!("API request event: VmmJobs");
+
+            let response = serde_json::to_vec(&jobs::job_list())
+                .map(|jobs| ApiResponsePayload::VmAction(Some(jobs)))
+                .map_err(|e| ApiError::VmmJobs(VmError::SerializeJson(e)));
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        _api_evt: EventFd,
+        _api_sender: Sender<ApiRequest>,
+        _data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        // The jobs are there for the VMM thread to be busy, so answer
+        // straight from the shared job registry.
+        serde_json::to_vec(&jobs::job_list())
+            .map(|jobs| Some(Body::new(jobs)))
+            .map_err(|e| ApiError::VmmJobs(VmError::SerializeJson(e)))
+    }
+}
+
+pub struct VmmJobStatus;
+
+impl ApiAction for VmmJobStatus {
+    type RequestBody = VmmJobData;
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, data: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |_| {
+            info!("API request event: VmmJobStatus {:?}", data);
+
+            let response = jobs::job_status(data.id)
+                .map_err(ApiError::VmmJobStatus)
+                .and_then(|status| {
+                    serde_json::to_vec(&status)
+                        .map_err(|e| ApiError::VmmJobs(VmError::SerializeJson(e)))
+                })
+                .map(|status| ApiResponsePayload::VmAction(Some(status)));
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        _api_evt: EventFd,
+        _api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        let status = jobs::job_status(data.id).map_err(ApiError::VmmJobStatus)?;
+        serde_json::to_vec(&status)
+            .map(|status| Some(Body::new(status)))
+            .map_err(|e| ApiError::VmmJobs(VmError::SerializeJson(e)))
+    }
+}
+
+pub struct VmmJobCancel;
+
+impl ApiAction for VmmJobCancel {
+    type RequestBody = VmmJobData;
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, data: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |_| {
+            info!("API request event: VmmJobCancel {:?}", data);
+
+            let response = jobs::job_cancel(data.id)
+                .map_err(ApiError::VmmJobCancel)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        _api_evt: EventFd,
+        _api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        // Same as for the listing, the VMM thread is likely busy with the
+        // job to cancel.
+        jobs::job_cancel(data.id).map_err(ApiError::VmmJobCancel)?;
+        Ok(None)
+    }
+}
+
 pub struct VmNmi;
 
 impl ApiAction for VmNmi {
@@ -9,13 +9,14 @@ use std::str::FromStr;
 use std::{fmt, result};
 
 use clap::ArgMatches;
+use net_util::IpNetwork;
 use option_parser::{
     ByteSized, IntegerList, OptionParser, OptionParserError, StringList, Toggle, Tuple,
 };
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use virtio_devices::block::MINIMUM_BLOCK_QUEUE_SIZE;
-use virtio_devices::{RateLimiterConfig, TokenBucketConfig};
+use virtio_devices::{RateLimitShareConfig, RateLimiterConfig, TokenBucketConfig};
 
 use crate::landlock::LandlockAccess;
 use crate::vm_config::*;
@@ -34,6 +35,12 @@ pub enum Error {
     ParseFsSockMissing,
     /// Missing persistent memory file parameter.
     ParsePmemFileMissing,
+    /// Missing shared directory path parameter.
+    ParseSharePathMissing,
+    /// Shared directory tag is missing
+    ParseShareTagMissing,
+    /// Shared directory tag is too long
+    ParseShareTagTooLong,
     /// Missing vsock socket path parameter.
     ParseVsockSockMissing,
     /// Missing vsock cid parameter.
@@ -64,6 +71,8 @@ pub enum Error {
     ParseFileSystem(OptionParserError),
     /// Error parsing persistent memory parameters
     ParsePersistentMemory(OptionParserError),
+    /// Error parsing shared directory parameters
+    ParseShare(OptionParserError),
     /// Failed parsing console
     ParseConsole(OptionParserError),
     #[cfg(target_arch = "x86_64")]
@@ -118,6 +127,10 @@ pub enum Error {
     ParseLandlockRules(OptionParserError),
     /// Missing fields in Landlock rules
     ParseLandlockMissingFields,
+    /// Failed applying a partial configuration
+    ParseUpdate(serde_json::Error),
+    /// No device with the given identifier in the configuration
+    UpdateUnknownId(String),
 }
 
 #[derive(Debug, PartialEq, Eq, Error)]
@@ -172,6 +185,8 @@ pub enum ValidationError {
     TooManyQueues,
     /// Invalid queue size
     InvalidQueueSize(u16),
+    /// Invalid virtio-fs cache window size
+    InvalidFsCacheSize(u64),
     /// Need shared memory for vfio-user
     UserDevicesRequireSharedMemory,
     /// VSOCK Context Identifier has a special meaning, unsuitable for a VM.
@@ -186,6 +201,8 @@ pub enum ValidationError {
     InvalidPciSegmentApertureWeight(u32),
     /// Invalid IOMMU address width in bits
     InvalidIommuAddressWidthBits(u8),
+    /// Invalid maximum number of IOMMU domains
+    InvalidIommuMaxDomains(u32),
     /// Balloon too big
     BalloonLargerThanRam(u64, u64),
     /// On a IOMMU segment but not behind IOMMU
@@ -202,12 +219,24 @@ pub enum ValidationError {
     DuplicateDevicePath(String),
     /// Provided MTU is lower than what the VIRTIO specification expects
     InvalidMtu(u16),
+    /// The IP address and netmask of a network device don't match
+    InvalidNetIpConfig(String),
+    /// More than one IPv4 address assigned to a network device
+    MultipleNetIpv4Addresses,
     /// PCI segment is reused across NUMA nodes
     PciSegmentReused(u16, u32, u32),
     /// Default PCI segment is assigned to NUMA node other than 0.
     DefaultPciSegmentInvalidNode(u32),
     /// Invalid rate-limiter group
     InvalidRateLimiterGroup,
+    /// No rate-limiter group or device with the given identifier
+    UnknownRateLimiterId(String),
+    /// Device is rate-limited through a rate-limiter group
+    RateLimiterInGroup(String),
+    /// Device was not created with a rate-limiter
+    NoRateLimiter(String),
+    /// Rate-limit share without a rate-limiter group, or with a null weight
+    InvalidRateLimitShare,
     /// The specified I/O port was invalid. It should be provided in hex, such as `0xe9`.
     #[cfg(target_arch = "x86_64")]
     InvalidIoPortHex(String),
@@ -279,6 +308,12 @@ impl fmt::Display for ValidationError {
             TooManyQueues => {
                 write!(f, "Number of vCPUs is insufficient for number of queues")
             }
+            InvalidFsCacheSize(s) => {
+                write!(
+                    f,
+                    "Virtio-fs cache size ({s}) must be a power of 2 and at least 2MiB"
+                )
+            }
             InvalidQueueSize(s) => {
                 write!(
                     f,
@@ -315,6 +350,12 @@ impl fmt::Display for ValidationError {
             InvalidIommuAddressWidthBits(iommu_address_width_bits) => {
                 write!(f, "IOMMU address width in bits ({iommu_address_width_bits}) should be less than or equal to {MAX_IOMMU_ADDRESS_WIDTH_BITS}")
             }
+            InvalidIommuMaxDomains(iommu_max_domains) => {
+                write!(
+                    f,
+                    "Invalid maximum number of IOMMU domains: {iommu_max_domains}"
+                )
+            }
             BalloonLargerThanRam(balloon_size, ram_size) => {
                 write!(
                     f,
@@ -349,6 +390,15 @@ impl fmt::Display for ValidationError {
                     "Provided MTU {mtu} is lower than 1280 (expected by VIRTIO specification)"
                 )
             }
+            InvalidNetIpConfig(s) => {
+                write!(f, "Invalid IP configuration for network device: {s}")
+            }
+            MultipleNetIpv4Addresses => {
+                write!(
+                    f,
+                    "Only a single IPv4 address can be assigned to a network device"
+                )
+            }
             PciSegmentReused(pci_segment, u1, u2) => {
                 write!(
                     f,
@@ -361,6 +411,24 @@ impl fmt::Display for ValidationError {
             InvalidRateLimiterGroup => {
                 write!(f, "Invalid rate-limiter group")
             }
+            UnknownRateLimiterId(s) => {
+                write!(f, "No rate-limiter group or device with identifier: {s}")
+            }
+            RateLimiterInGroup(s) => {
+                write!(
+                    f,
+                    "Device {s} uses a rate-limiter group, the group should be updated instead"
+                )
+            }
+            NoRateLimiter(s) => {
+                write!(f, "Device {s} was not created with a rate-limiter")
+            }
+            InvalidRateLimitShare => {
+                write!(
+                    f,
+                    "Rate-limit share requires a rate-limiter group and a non-zero weight"
+                )
+            }
             #[cfg(target_arch = "x86_64")]
             InvalidIoPortHex(s) => {
                 write!(
@@ -419,6 +487,14 @@ impl fmt::Display for Error {
             ),
             ParsePersistentMemory(o) => write!(f, "Error parsing --pmem: {o}"),
             ParsePmemFileMissing => write!(f, "Error parsing --pmem: file missing"),
+            ParseShare(o) => write!(f, "Error parsing --share: {o}"),
+            ParseSharePathMissing => write!(f, "Error parsing --share: path missing"),
+            ParseShareTagMissing => write!(f, "Error parsing --share: tag missing"),
+            ParseShareTagTooLong => write!(
+                f,
+                "Error parsing --share: max tag length is {}",
+                virtio_devices::VIRTIO_9P_TAG_LEN
+            ),
             ParseVsock(o) => write!(f, "Error parsing --vsock: {o}"),
             ParseVsockCidMissing => write!(f, "Error parsing --vsock: cid missing"),
             ParseVsockSockMissing => write!(f, "Error parsing --vsock: socket missing"),
@@ -461,6 +537,11 @@ impl fmt::Display for Error {
                 f,
                 "Error parsing --landlock-rules: path/access field missing"
             ),
+            ParseUpdate(e) => write!(f, "Error applying configuration update: {e}"),
+            UpdateUnknownId(id) => write!(
+                f,
+                "Error applying configuration update: no device with identifier {id}"
+            ),
         }
     }
 }
@@ -489,6 +570,7 @@ pub struct VmParams<'a> {
     pub rng: &'a str,
     pub balloon: Option<&'a str>,
     pub fs: Option<Vec<&'a str>>,
+    pub shares: Option<Vec<&'a str>>,
     pub pmem: Option<Vec<&'a str>>,
     pub serial: &'a str,
     pub console: &'a str,
@@ -548,6 +630,9 @@ impl<'a> VmParams<'a> {
         let fs: Option<Vec<&str>> = args
             .get_many::<String>("fs")
             .map(|x| x.map(|y| y as &str).collect());
+        let shares: Option<Vec<&str>> = args
+            .get_many::<String>("share")
+            .map(|x| x.map(|y| y as &str).collect());
         let pmem: Option<Vec<&str>> = args
             .get_many::<String>("pmem")
             .map(|x| x.map(|y| y as &str).collect());
@@ -602,6 +687,7 @@ impl<'a> VmParams<'a> {
             rng,
             balloon,
             fs,
+            shares,
             pmem,
             serial,
             console,
@@ -824,6 +910,7 @@ impl PlatformConfig {
             .add("num_pci_segments")
             .add("iommu_segments")
             .add("iommu_address_width")
+            .add("iommu_max_domains")
             .add("serial_number")
             .add("uuid")
             .add("oem_strings");
@@ -845,6 +932,9 @@ impl PlatformConfig {
             .convert("iommu_address_width")
             .map_err(Error::ParsePlatform)?
             .unwrap_or(MAX_IOMMU_ADDRESS_WIDTH_BITS);
+        let iommu_max_domains = parser
+            .convert("iommu_max_domains")
+            .map_err(Error::ParsePlatform)?;
         let serial_number = parser
             .convert("serial_number")
             .map_err(Error::ParsePlatform)?;
@@ -869,6 +959,7 @@ impl PlatformConfig {
             num_pci_segments,
             iommu_segments,
             iommu_address_width_bits,
+            iommu_max_domains,
             serial_number,
             uuid,
             oem_strings,
@@ -900,6 +991,10 @@ impl PlatformConfig {
             ));
         }
 
+        if self.iommu_max_domains == Some(0) {
+            return Err(ValidationError::InvalidIommuMaxDomains(0));
+        }
+
         Ok(())
     }
 }
@@ -1076,11 +1171,42 @@ impl MemoryConfig {
     }
 }
 
+// Parses the `<prefix>weight`, `<prefix>min_bw` and `<prefix>min_ops`
+// options describing the share of a rate-limiter group a member gets.
+fn parse_rate_limit_share(
+    parser: &OptionParser,
+    prefix: &str,
+) -> result::Result<Option<RateLimitShareConfig>, OptionParserError> {
+    let weight = parser.convert(&format!("{prefix}weight"))?;
+    let min_bandwidth = parser.convert(&format!("{prefix}min_bw"))?;
+    let min_ops = parser.convert(&format!("{prefix}min_ops"))?;
+
+    if weight.is_none() && min_bandwidth.is_none() && min_ops.is_none() {
+        return Ok(None);
+    }
+
+    let default = RateLimitShareConfig::default();
+    Ok(Some(RateLimitShareConfig {
+        weight: weight.unwrap_or(default.weight),
+        min_bandwidth: min_bandwidth.unwrap_or(default.min_bandwidth),
+        min_ops: min_ops.unwrap_or(default.min_ops),
+    }))
+}
+
+fn validate_rate_limit_share(share: &Option<RateLimitShareConfig>) -> ValidationResult<()> {
+    if share.is_some_and(|share| share.weight == 0) {
+        return Err(ValidationError::InvalidRateLimitShare);
+    }
+
+    Ok(())
+}
+
 impl RateLimiterGroupConfig {
     pub const SYNTAX: &'static str = "Rate Limit Group parameters \
         \"bw_size=<bytes>,bw_one_time_burst=<bytes>,bw_refill_time=<ms>,\
         ops_size=<io_ops>,ops_one_time_burst=<io_ops>,ops_refill_time=<ms>,\
-        id=<device_id>\"";
+        id=<device_id>,parent=<group_id>,weight=<weight_in_parent>,\
+        min_bw=<guaranteed_bytes_per_second>,min_ops=<guaranteed_ops_per_second>\"";
 
     pub fn parse(rate_limit_group: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
@@ -1091,7 +1217,11 @@ impl RateLimiterGroupConfig {
             .add("ops_size")
             .add("ops_one_time_burst")
             .add("ops_refill_time")
-            .add("id");
+            .add("id")
+            .add("parent")
+            .add("weight")
+            .add("min_bw")
+            .add("min_ops");
         parser
             .parse(rate_limit_group)
             .map_err(Error::ParseRateLimiterGroup)?;
@@ -1121,6 +1251,8 @@ impl RateLimiterGroupConfig {
             .convert("ops_refill_time")
             .map_err(Error::ParseRateLimiterGroup)?
             .unwrap_or_default();
+        let parent = parser.get("parent");
+        let share = parse_rate_limit_share(&parser, "").map_err(Error::ParseRateLimiterGroup)?;
 
         let bw_tb_config = if bw_size != 0 && bw_refill_time != 0 {
             Some(TokenBucketConfig {
@@ -1147,6 +1279,8 @@ impl RateLimiterGroupConfig {
                 bandwidth: bw_tb_config,
                 ops: ops_tb_config,
             },
+            parent,
+            share,
         })
     }
 
@@ -1155,10 +1289,15 @@ impl RateLimiterGroupConfig {
             return Err(ValidationError::InvalidRateLimiterGroup);
         }
 
-        if self.id.is_empty() {
+        if self.id.is_empty() || self.parent.as_ref() == Some(&self.id) {
             return Err(ValidationError::InvalidRateLimiterGroup);
         }
 
+        if self.share.is_some() && self.parent.is_none() {
+            return Err(ValidationError::InvalidRateLimitShare);
+        }
+        validate_rate_limit_share(&self.share)?;
+
         Ok(())
     }
 }
@@ -1171,6 +1310,8 @@ impl DiskConfig {
          bw_size=<bytes>,bw_one_time_burst=<bytes>,bw_refill_time=<ms>,\
          ops_size=<io_ops>,ops_one_time_burst=<io_ops>,ops_refill_time=<ms>,\
          id=<device_id>,pci_segment=<segment_id>,rate_limit_group=<group_id>,\
+         rate_limit_weight=<weight_in_group>,rate_limit_min_bw=<guaranteed_bytes_per_second>,\
+         rate_limit_min_ops=<guaranteed_ops_per_second>,\
          queue_affinity=<list_of_queue_indices_with_their_associated_cpuset>,\
          serial=<serial_number>";
 
@@ -1197,6 +1338,9 @@ impl DiskConfig {
             .add("pci_segment")
             .add("serial")
             .add("rate_limit_group")
+            .add("rate_limit_weight")
+            .add("rate_limit_min_bw")
+            .add("rate_limit_min_ops")
             .add("queue_affinity");
         parser.parse(disk).map_err(Error::ParseDisk)?;
 
@@ -1246,6 +1390,8 @@ impl DiskConfig {
             .map_err(Error::ParseDisk)?
             .unwrap_or_default();
         let rate_limit_group = parser.get("rate_limit_group");
+        let rate_limit_share =
+            parse_rate_limit_share(&parser, "rate_limit_").map_err(Error::ParseDisk)?;
         let bw_size = parser
             .convert("bw_size")
             .map_err(Error::ParseDisk)?
@@ -1319,6 +1465,7 @@ impl DiskConfig {
             vhost_user,
             vhost_socket,
             rate_limit_group,
+            rate_limit_share,
             rate_limiter_config,
             id,
             disable_io_uring,
@@ -1358,6 +1505,11 @@ impl DiskConfig {
             return Err(ValidationError::InvalidRateLimiterGroup);
         }
 
+        if self.rate_limit_share.is_some() && self.rate_limit_group.is_none() {
+            return Err(ValidationError::InvalidRateLimitShare);
+        }
+        validate_rate_limit_share(&self.rate_limit_share)?;
+
         Ok(())
     }
 }
@@ -1381,11 +1533,15 @@ impl FromStr for VhostMode {
 
 impl NetConfig {
     pub const SYNTAX: &'static str = "Network parameters \
-    \"tap=<if_name>,ip=<ip_addr>,mask=<net_mask>,mac=<mac_addr>,fd=<fd1,fd2...>,iommu=on|off,\
+    \"tap=<if_name>,ip=<ip_addr>,mask=<net_mask>,extra_ips=[<ip_addr>/<prefix_len>,...],\
+    mac=<mac_addr>,fd=<fd1,fd2...>,iommu=on|off,\
     num_queues=<number_of_queues>,queue_size=<size_of_each_queue>,id=<device_id>,\
     vhost_user=<vhost_user_enable>,socket=<vhost_user_socket_path>,vhost_mode=client|server,\
     bw_size=<bytes>,bw_one_time_burst=<bytes>,bw_refill_time=<ms>,\
-    ops_size=<io_ops>,ops_one_time_burst=<io_ops>,ops_refill_time=<ms>,pci_segment=<segment_id>\
+    ops_size=<io_ops>,ops_one_time_burst=<io_ops>,ops_refill_time=<ms>,\
+    rx_rate_limit_group=<group_id>,tx_rate_limit_group=<group_id>,\
+    rate_limit_weight=<weight_in_groups>,rate_limit_min_bw=<guaranteed_bytes_per_second>,\
+    rate_limit_min_ops=<guaranteed_ops_per_second>,pci_segment=<segment_id>\
     offload_tso=on|off,offload_ufo=on|off,offload_csum=on|off\"";
 
     pub fn parse(net: &str) -> Result<Self> {
@@ -1395,6 +1551,7 @@ impl NetConfig {
             .add("tap")
             .add("ip")
             .add("mask")
+            .add("extra_ips")
             .add("mac")
             .add("host_mac")
             .add("offload_tso")
@@ -1415,6 +1572,11 @@ impl NetConfig {
             .add("ops_size")
             .add("ops_one_time_burst")
             .add("ops_refill_time")
+            .add("rx_rate_limit_group")
+            .add("tx_rate_limit_group")
+            .add("rate_limit_weight")
+            .add("rate_limit_min_bw")
+            .add("rate_limit_min_ops")
             .add("pci_segment");
         parser.parse(net).map_err(Error::ParseNetwork)?;
 
@@ -1423,10 +1585,23 @@ impl NetConfig {
             .convert("ip")
             .map_err(Error::ParseNetwork)?
             .unwrap_or_else(default_netconfig_ip);
-        let mask = parser
-            .convert("mask")
+        let mask = parser.convert("mask").map_err(Error::ParseNetwork)?;
+        let extra_ips = parser
+            .convert::<StringList>("extra_ips")
             .map_err(Error::ParseNetwork)?
-            .unwrap_or_else(default_netconfig_mask);
+            .map(|l| {
+                l.0.iter()
+                    .map(|s| {
+                        s.parse::<IpNetwork>().map_err(|_| {
+                            Error::ParseNetwork(OptionParserError::Conversion(
+                                "extra_ips".to_owned(),
+                                s.to_owned(),
+                            ))
+                        })
+                    })
+                    .collect::<Result<Vec<IpNetwork>>>()
+            })
+            .transpose()?;
         let mac = parser
             .convert("mac")
             .map_err(Error::ParseNetwork)?
@@ -1530,11 +1705,16 @@ impl NetConfig {
         } else {
             None
         };
+        let rx_rate_limit_group = parser.get("rx_rate_limit_group");
+        let tx_rate_limit_group = parser.get("tx_rate_limit_group");
+        let rate_limit_share =
+            parse_rate_limit_share(&parser, "rate_limit_").map_err(Error::ParseNetwork)?;
 
         let config = NetConfig {
             tap,
             ip,
             mask,
+            extra_ips,
             mac,
             host_mac,
             mtu,
@@ -1547,6 +1727,9 @@ impl NetConfig {
             id,
             fds,
             rate_limiter_config,
+            rx_rate_limit_group,
+            tx_rate_limit_group,
+            rate_limit_share,
             pci_segment,
             offload_tso,
             offload_ufo,
@@ -1602,14 +1785,62 @@ impl NetConfig {
             return Err(ValidationError::NoHardwareChecksumOffload);
         }
 
+        if self.rate_limiter_config.is_some()
+            && (self.rx_rate_limit_group.is_some() || self.tx_rate_limit_group.is_some())
+        {
+            return Err(ValidationError::InvalidRateLimiterGroup);
+        }
+
+        if self.rate_limit_share.is_some()
+            && self.rx_rate_limit_group.is_none()
+            && self.tx_rate_limit_group.is_none()
+        {
+            return Err(ValidationError::InvalidRateLimitShare);
+        }
+        validate_rate_limit_share(&self.rate_limit_share)?;
+
+        let ip_addrs = self
+            .ip_addrs()
+            .map_err(|e| ValidationError::InvalidNetIpConfig(e.to_string()))?;
+        if ip_addrs.iter().filter(|n| n.addr().is_ipv4()).count() > 1 {
+            return Err(ValidationError::MultipleNetIpv4Addresses);
+        }
+
         Ok(())
     }
+
+    /// All host-side addresses to assign to the TAP interface, the one
+    /// described by `ip` and `mask` coming first.
+    pub fn ip_addrs(&self) -> std::result::Result<Vec<IpNetwork>, net_util::IpNetworkError> {
+        let mask = self
+            .mask
+            .unwrap_or_else(|| default_netconfig_mask(&self.ip));
+        let mut ip_addrs = vec![IpNetwork::with_netmask(self.ip, mask)?];
+        if let Some(extra_ips) = &self.extra_ips {
+            ip_addrs.extend(extra_ips.iter().copied());
+        }
+
+        Ok(ip_addrs)
+    }
 }
 
 impl RngConfig {
+    pub const SYNTAX: &'static str = "Random number generator parameters \
+    \"src=<entropy_source_path>|egd:<entropy_daemon_socket_path>,iommu=on|off,\
+    bw_size=<bytes>,bw_one_time_burst=<bytes>,bw_refill_time=<ms>,\
+    ops_size=<io_ops>,ops_one_time_burst=<io_ops>,ops_refill_time=<ms>\"";
+
     pub fn parse(rng: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
-        parser.add("src").add("iommu");
+        parser
+            .add("src")
+            .add("iommu")
+            .add("bw_size")
+            .add("bw_one_time_burst")
+            .add("bw_refill_time")
+            .add("ops_size")
+            .add("ops_one_time_burst")
+            .add("ops_refill_time");
         parser.parse(rng).map_err(Error::ParseRng)?;
 
         let src = PathBuf::from(
@@ -1622,21 +1853,78 @@ impl RngConfig {
             .map_err(Error::ParseRng)?
             .unwrap_or(Toggle(false))
             .0;
+        let bw_size = parser
+            .convert("bw_size")
+            .map_err(Error::ParseRng)?
+            .unwrap_or_default();
+        let bw_one_time_burst = parser
+            .convert("bw_one_time_burst")
+            .map_err(Error::ParseRng)?
+            .unwrap_or_default();
+        let bw_refill_time = parser
+            .convert("bw_refill_time")
+            .map_err(Error::ParseRng)?
+            .unwrap_or_default();
+        let ops_size = parser
+            .convert("ops_size")
+            .map_err(Error::ParseRng)?
+            .unwrap_or_default();
+        let ops_one_time_burst = parser
+            .convert("ops_one_time_burst")
+            .map_err(Error::ParseRng)?
+            .unwrap_or_default();
+        let ops_refill_time = parser
+            .convert("ops_refill_time")
+            .map_err(Error::ParseRng)?
+            .unwrap_or_default();
+        let bw_tb_config = if bw_size != 0 && bw_refill_time != 0 {
+            Some(TokenBucketConfig {
+                size: bw_size,
+                one_time_burst: Some(bw_one_time_burst),
+                refill_time: bw_refill_time,
+            })
+        } else {
+            None
+        };
+        let ops_tb_config = if ops_size != 0 && ops_refill_time != 0 {
+            Some(TokenBucketConfig {
+                size: ops_size,
+                one_time_burst: Some(ops_one_time_burst),
+                refill_time: ops_refill_time,
+            })
+        } else {
+            None
+        };
+        let rate_limiter_config = if bw_tb_config.is_some() || ops_tb_config.is_some() {
+            Some(RateLimiterConfig {
+                bandwidth: bw_tb_config,
+                ops: ops_tb_config,
+            })
+        } else {
+            None
+        };
 
-        Ok(RngConfig { src, iommu })
+        Ok(RngConfig {
+            src,
+            iommu,
+            rate_limiter_config,
+        })
     }
 }
 
 impl BalloonConfig {
     pub const SYNTAX: &'static str =
         "Balloon parameters \"size=<balloon_size>,deflate_on_oom=on|off,\
-        free_page_reporting=on|off\"";
+        free_page_reporting=on|off,statistics_polling_interval=<seconds>,\
+        free_page_hinting=on|off\"";
 
     pub fn parse(balloon: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
         parser.add("size");
         parser.add("deflate_on_oom");
         parser.add("free_page_reporting");
+        parser.add("statistics_polling_interval");
+        parser.add("free_page_hinting");
         parser.parse(balloon).map_err(Error::ParseBalloon)?;
 
         let size = parser
@@ -1657,10 +1945,23 @@ impl BalloonConfig {
             .unwrap_or(Toggle(false))
             .0;
 
+        let statistics_polling_interval = parser
+            .convert("statistics_polling_interval")
+            .map_err(Error::ParseBalloon)?
+            .unwrap_or(0);
+
+        let free_page_hinting = parser
+            .convert::<Toggle>("free_page_hinting")
+            .map_err(Error::ParseBalloon)?
+            .unwrap_or(Toggle(false))
+            .0;
+
         Ok(BalloonConfig {
             size,
             deflate_on_oom,
             free_page_reporting,
+            statistics_polling_interval,
+            free_page_hinting,
         })
     }
 }
@@ -1668,7 +1969,8 @@ impl BalloonConfig {
 impl FsConfig {
     pub const SYNTAX: &'static str = "virtio-fs parameters \
     \"tag=<tag_name>,socket=<socket_path>,num_queues=<number_of_queues>,\
-    queue_size=<size_of_each_queue>,id=<device_id>,pci_segment=<segment_id>\"";
+    queue_size=<size_of_each_queue>,cache_size=<dax_cache_window_size>,id=<device_id>,\
+    pci_segment=<segment_id>\"";
 
     pub fn parse(fs: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
@@ -1676,6 +1978,7 @@ impl FsConfig {
             .add("tag")
             .add("queue_size")
             .add("num_queues")
+            .add("cache_size")
             .add("socket")
             .add("id")
             .add("pci_segment");
@@ -1695,6 +1998,10 @@ impl FsConfig {
             .convert("num_queues")
             .map_err(Error::ParseFileSystem)?
             .unwrap_or_else(default_fsconfig_num_queues);
+        let cache_size = parser
+            .convert::<ByteSized>("cache_size")
+            .map_err(Error::ParseFileSystem)?
+            .map(|v| v.0);
 
         let id = parser.get("id");
 
@@ -1708,6 +2015,7 @@ impl FsConfig {
             socket,
             num_queues,
             queue_size,
+            cache_size,
             id,
             pci_segment,
         })
@@ -1718,6 +2026,14 @@ impl FsConfig {
             return Err(ValidationError::TooManyQueues);
         }
 
+        if let Some(cache_size) = self.cache_size {
+            // The cache window is exposed through a PCI BAR, and must be
+            // large enough to hold at least one 2MiB DAX mapping.
+            if !cache_size.is_power_of_two() || cache_size < 0x20_0000 {
+                return Err(ValidationError::InvalidFsCacheSize(cache_size));
+            }
+        }
+
         if let Some(platform_config) = vm_config.platform.as_ref() {
             if self.pci_segment >= platform_config.num_pci_segments {
                 return Err(ValidationError::InvalidPciSegment(self.pci_segment));
@@ -1736,10 +2052,74 @@ impl FsConfig {
     }
 }
 
+impl ShareConfig {
+    pub const SYNTAX: &'static str = "Shared directory parameters \
+    \"path=<shared_directory_path>,tag=<tag_name>,readonly=on|off,iommu=on|off,\
+    id=<device_id>,pci_segment=<segment_id>\"";
+
+    pub fn parse(share: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser
+            .add("path")
+            .add("tag")
+            .add("readonly")
+            .add("iommu")
+            .add("id")
+            .add("pci_segment");
+        parser.parse(share).map_err(Error::ParseShare)?;
+
+        let path = PathBuf::from(parser.get("path").ok_or(Error::ParseSharePathMissing)?);
+        let tag = parser.get("tag").ok_or(Error::ParseShareTagMissing)?;
+        if tag.len() > virtio_devices::VIRTIO_9P_TAG_LEN {
+            return Err(Error::ParseShareTagTooLong);
+        }
+        let readonly = parser
+            .convert::<Toggle>("readonly")
+            .map_err(Error::ParseShare)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let iommu = parser
+            .convert::<Toggle>("iommu")
+            .map_err(Error::ParseShare)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let id = parser.get("id");
+        let pci_segment = parser
+            .convert("pci_segment")
+            .map_err(Error::ParseShare)?
+            .unwrap_or_default();
+
+        Ok(ShareConfig {
+            path,
+            tag,
+            readonly,
+            iommu,
+            id,
+            pci_segment,
+        })
+    }
+
+    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
+        if let Some(platform_config) = vm_config.platform.as_ref() {
+            if self.pci_segment >= platform_config.num_pci_segments {
+                return Err(ValidationError::InvalidPciSegment(self.pci_segment));
+            }
+
+            if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
+                if iommu_segments.contains(&self.pci_segment) && !self.iommu {
+                    return Err(ValidationError::OnIommuSegment(self.pci_segment));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl PmemConfig {
     pub const SYNTAX: &'static str = "Persistent memory parameters \
     \"file=<backing_file_path>,size=<persistent_memory_size>,iommu=on|off,\
-    discard_writes=on|off,id=<device_id>,pci_segment=<segment_id>\"";
+    discard_writes=on|off,shmem_region=on|off,id=<device_id>,pci_segment=<segment_id>\"";
 
     pub fn parse(pmem: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
@@ -1748,6 +2128,7 @@ impl PmemConfig {
             .add("file")
             .add("iommu")
             .add("discard_writes")
+            .add("shmem_region")
             .add("id")
             .add("pci_segment");
         parser.parse(pmem).map_err(Error::ParsePersistentMemory)?;
@@ -1767,6 +2148,11 @@ impl PmemConfig {
             .map_err(Error::ParsePersistentMemory)?
             .unwrap_or(Toggle(false))
             .0;
+        let shmem_region = parser
+            .convert::<Toggle>("shmem_region")
+            .map_err(Error::ParsePersistentMemory)?
+            .unwrap_or(Toggle(false))
+            .0;
         let id = parser.get("id");
         let pci_segment = parser
             .convert("pci_segment")
@@ -1778,6 +2164,7 @@ impl PmemConfig {
             size,
             iommu,
             discard_writes,
+            shmem_region,
             id,
             pci_segment,
         })
@@ -1965,11 +2352,16 @@ impl DeviceConfig {
 
 impl UserDeviceConfig {
     pub const SYNTAX: &'static str =
-        "Userspace device socket=<socket_path>,id=<device_id>,pci_segment=<segment_id>\"";
+        "Userspace device socket=<socket_path>,id=<device_id>,pci_segment=<segment_id>,\
+        migratable=on|off\"";
 
     pub fn parse(user_device: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
-        parser.add("socket").add("id").add("pci_segment");
+        parser
+            .add("socket")
+            .add("id")
+            .add("pci_segment")
+            .add("migratable");
         parser.parse(user_device).map_err(Error::ParseUserDevice)?;
 
         let socket = parser
@@ -1981,11 +2373,17 @@ impl UserDeviceConfig {
             .convert::<u16>("pci_segment")
             .map_err(Error::ParseUserDevice)?
             .unwrap_or_default();
+        let migratable = parser
+            .convert::<Toggle>("migratable")
+            .map_err(Error::ParseUserDevice)?
+            .unwrap_or(Toggle(false))
+            .0;
 
         Ok(UserDeviceConfig {
             socket,
             id,
             pci_segment,
+            migratable,
         })
     }
 
@@ -2070,7 +2468,8 @@ impl VdpaConfig {
 
 impl VsockConfig {
     pub const SYNTAX: &'static str = "Virtio VSOCK parameters \
-        \"cid=<context_id>,socket=<socket_path>,iommu=on|off,id=<device_id>,pci_segment=<segment_id>\"";
+        \"cid=<context_id>,socket=<socket_path>,iommu=on|off,seqpacket=on|off,id=<device_id>,\
+        pci_segment=<segment_id>\"";
 
     pub fn parse(vsock: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
@@ -2078,6 +2477,7 @@ impl VsockConfig {
             .add("socket")
             .add("cid")
             .add("iommu")
+            .add("seqpacket")
             .add("id")
             .add("pci_segment");
         parser.parse(vsock).map_err(Error::ParseVsock)?;
@@ -2091,6 +2491,11 @@ impl VsockConfig {
             .map_err(Error::ParseVsock)?
             .unwrap_or(Toggle(false))
             .0;
+        let seqpacket = parser
+            .convert::<Toggle>("seqpacket")
+            .map_err(Error::ParseVsock)?
+            .unwrap_or(Toggle(false))
+            .0;
         let cid = parser
             .convert("cid")
             .map_err(Error::ParseVsock)?
@@ -2105,6 +2510,7 @@ impl VsockConfig {
             cid,
             socket,
             iommu,
+            seqpacket,
             id,
             pci_segment,
         })
@@ -2266,6 +2672,9 @@ pub struct RestoreConfig {
     pub prefault: bool,
     #[serde(default)]
     pub net_fds: Option<Vec<RestoredNetConfig>>,
+    /// Return a job id at once rather than waiting for the outcome
+    #[serde(default)]
+    pub job: bool,
 }
 
 impl RestoreConfig {
@@ -2308,6 +2717,7 @@ impl RestoreConfig {
             source_url,
             prefault,
             net_fds,
+            job: false,
         })
     }
 
@@ -2517,9 +2927,16 @@ impl VmConfig {
         }
 
         if let Some(rate_limit_groups) = &self.rate_limit_groups {
-            for rate_limit_group in rate_limit_groups {
+            for (i, rate_limit_group) in rate_limit_groups.iter().enumerate() {
                 rate_limit_group.validate(self)?;
 
+                // Parents must be declared first, which also rules out cycles.
+                if let Some(parent) = &rate_limit_group.parent {
+                    if !rate_limit_groups[..i].iter().any(|cfg| &cfg.id == parent) {
+                        return Err(ValidationError::InvalidRateLimiterGroup);
+                    }
+                }
+
                 Self::validate_identifier(&mut id_list, &Some(rate_limit_group.id.clone()))?;
             }
         }
@@ -2560,6 +2977,19 @@ impl VmConfig {
                 if net.vhost_user && !self.backed_by_shared_memory() {
                     return Err(ValidationError::VhostUserRequiresSharedMemory);
                 }
+                for rate_limit_group in [&net.rx_rate_limit_group, &net.tx_rate_limit_group]
+                    .into_iter()
+                    .flatten()
+                {
+                    if !self
+                        .rate_limit_groups
+                        .iter()
+                        .flatten()
+                        .any(|cfg| &cfg.id == rate_limit_group)
+                    {
+                        return Err(ValidationError::InvalidRateLimiterGroup);
+                    }
+                }
                 net.validate(self)?;
                 self.iommu |= net.iommu;
 
@@ -2578,6 +3008,15 @@ impl VmConfig {
             }
         }
 
+        if let Some(shares) = &self.shares {
+            for share in shares {
+                share.validate(self)?;
+                self.iommu |= share.iommu;
+
+                Self::validate_identifier(&mut id_list, &share.id)?;
+            }
+        }
+
         if let Some(pmems) = &self.pmem {
             for pmem in pmems {
                 pmem.validate(self)?;
@@ -2774,6 +3213,14 @@ impl VmConfig {
     }
 
     pub fn parse(vm_params: VmParams) -> Result<Self> {
+        let mut config = Self::parse_unvalidated(vm_params)?;
+        config.validate().map_err(Error::Validation)?;
+        Ok(config)
+    }
+
+    /// Build the configuration from the command line parameters, leaving
+    /// its validation to the caller.
+    pub fn parse_unvalidated(vm_params: VmParams) -> Result<Self> {
         let mut rate_limit_groups: Option<Vec<RateLimiterGroupConfig>> = None;
         if let Some(rate_limit_group_list) = &vm_params.rate_limit_groups {
             let mut rate_limit_group_config_list = Vec::new();
@@ -2825,6 +3272,15 @@ impl VmConfig {
             fs = Some(fs_config_list);
         }
 
+        let mut shares: Option<Vec<ShareConfig>> = None;
+        if let Some(share_list) = &vm_params.shares {
+            let mut share_config_list = Vec::new();
+            for item in share_list.iter() {
+                share_config_list.push(ShareConfig::parse(item)?);
+            }
+            shares = Some(share_config_list);
+        }
+
         let mut pmem: Option<Vec<PmemConfig>> = None;
         if let Some(pmem_list) = &vm_params.pmem {
             let mut pmem_config_list = Vec::new();
@@ -2955,7 +3411,7 @@ impl VmConfig {
             );
         }
 
-        let mut config = VmConfig {
+        Ok(VmConfig {
             cpus: CpusConfig::parse(vm_params.cpus)?,
             memory: MemoryConfig::parse(vm_params.memory, vm_params.memory_zones)?,
             payload,
@@ -2965,6 +3421,7 @@ impl VmConfig {
             rng,
             balloon,
             fs,
+            shares,
             pmem,
             serial,
             console,
@@ -2990,9 +3447,90 @@ impl VmConfig {
             preserved_fds: None,
             landlock_enable: vm_params.landlock_enable,
             landlock_rules,
-        };
-        config.validate().map_err(Error::Validation)?;
-        Ok(config)
+        })
+    }
+
+    /// Check the rate-limiter identified by `id`, either a rate-limiter
+    /// group or a device, can be updated with the given configuration.
+    pub fn validate_rate_limiter_update(
+        &self,
+        id: &str,
+        rate_limiter_config: &RateLimiterConfig,
+    ) -> ValidationResult<()> {
+        if let Some(rate_limit_groups) = &self.rate_limit_groups {
+            if rate_limit_groups.iter().any(|cfg| cfg.id == id) {
+                if rate_limiter_config.bandwidth.is_none() && rate_limiter_config.ops.is_none() {
+                    return Err(ValidationError::InvalidRateLimiterGroup);
+                }
+                return Ok(());
+            }
+        }
+
+        if let Some(disk) = self
+            .disks
+            .iter()
+            .flatten()
+            .find(|dev| dev.id.as_deref() == Some(id))
+        {
+            if disk.rate_limit_group.is_some() {
+                return Err(ValidationError::RateLimiterInGroup(id.to_owned()));
+            }
+            if disk.rate_limiter_config.is_none() {
+                return Err(ValidationError::NoRateLimiter(id.to_owned()));
+            }
+            return Ok(());
+        }
+
+        if let Some(net) = self
+            .net
+            .iter()
+            .flatten()
+            .find(|dev| dev.id.as_deref() == Some(id))
+        {
+            if net.rx_rate_limit_group.is_some() || net.tx_rate_limit_group.is_some() {
+                return Err(ValidationError::RateLimiterInGroup(id.to_owned()));
+            }
+            if net.rate_limiter_config.is_none() {
+                return Err(ValidationError::NoRateLimiter(id.to_owned()));
+            }
+            return Ok(());
+        }
+
+        Err(ValidationError::UnknownRateLimiterId(id.to_owned()))
+    }
+
+    /// Replace the configuration of the rate-limiter identified by `id`.
+    pub fn update_rate_limiter(
+        &mut self,
+        id: &str,
+        rate_limiter_config: RateLimiterConfig,
+    ) -> ValidationResult<()> {
+        self.validate_rate_limiter_update(id, &rate_limiter_config)?;
+
+        if let Some(group) = self
+            .rate_limit_groups
+            .iter_mut()
+            .flatten()
+            .find(|cfg| cfg.id == id)
+        {
+            group.rate_limiter_config = rate_limiter_config;
+        } else if let Some(disk) = self
+            .disks
+            .iter_mut()
+            .flatten()
+            .find(|dev| dev.id.as_deref() == Some(id))
+        {
+            disk.rate_limiter_config = Some(rate_limiter_config);
+        } else if let Some(net) = self
+            .net
+            .iter_mut()
+            .flatten()
+            .find(|dev| dev.id.as_deref() == Some(id))
+        {
+            net.rate_limiter_config = Some(rate_limiter_config);
+        }
+
+        Ok(())
     }
 
     pub fn remove_device(&mut self, id: &str) -> bool {
@@ -3033,6 +3571,13 @@ impl VmConfig {
             removed |= net.len() != len;
         }
 
+        // Remove if shared directory device
+        if let Some(shares) = self.shares.as_mut() {
+            let len = shares.len();
+            shares.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
+            removed |= shares.len() != len;
+        }
+
         // Remove if pmem device
         if let Some(pmem) = self.pmem.as_mut() {
             let len = pmem.len();
@@ -3073,10 +3618,109 @@ impl VmConfig {
         self.preserved_fds = Some(fds);
     }
 
-    #[cfg(feature = "tdx")]
-    pub fn is_tdx_enabled(&self) -> bool {
-        self.platform.as_ref().map(|p| p.tdx).unwrap_or(false)
-    }
+    /// Apply the partial configuration `update`, returning the resulting
+    /// configuration along with the fields it changes. Lists of devices are
+    /// updated element by element, based on their identifier.
+    pub fn apply_update(&self, update: &serde_json::Value) -> Result<ConfigUpdate> {
+        let old = serde_json::to_value(self).map_err(Error::ParseUpdate)?;
+        let mut value = old.clone();
+        merge_update(&mut value, update)?;
+        let mut config: VmConfig = serde_json::from_value(value).map_err(Error::ParseUpdate)?;
+
+        // FDs don't survive the serialization, carry them over from the
+        // current configuration.
+        if let (Some(old_net), Some(new_net)) = (&self.net, &mut config.net) {
+            for (index, net) in new_net.iter_mut().enumerate() {
+                let old = match &net.id {
+                    Some(id) => old_net.iter().find(|old| old.id.as_ref() == Some(id)),
+                    None => old_net.get(index),
+                };
+                if let Some(old) = old {
+                    net.fds.clone_from(&old.fds);
+                }
+            }
+        }
+
+        let new = serde_json::to_value(&config).map_err(Error::ParseUpdate)?;
+        let mut changes = Vec::new();
+        changed_fields(&mut Vec::new(), &old, &new, &mut changes);
+
+        let mut live = Vec::new();
+        let mut on_reboot = Vec::new();
+        let mut unsupported = Vec::new();
+        for field in changes {
+            if REBOOT_UPDATE_FIELDS
+                .iter()
+                .any(|pattern| field_matches(pattern, &field))
+            {
+                on_reboot.push(format_field(&field));
+            } else if self.live_update_supported(&config, &field) {
+                live.push(format_field(&field));
+            } else {
+                unsupported.push(format_field(&field));
+            }
+        }
+
+        Ok(ConfigUpdate {
+            config,
+            live,
+            on_reboot,
+            unsupported,
+        })
+    }
+
+    /// Whether `field`, changed from the current configuration to `new`,
+    /// can be applied to the running VM. The devices were created from the
+    /// current configuration, which is the one deciding what they support.
+    fn live_update_supported(&self, new: &VmConfig, field: &[String]) -> bool {
+        if !LIVE_UPDATE_FIELDS
+            .iter()
+            .any(|pattern| field_matches(pattern, field))
+        {
+            return false;
+        }
+
+        match field[0].as_str() {
+            // The output of a console can only be moved to another file when
+            // it is written to a file, before and after the update.
+            "serial" => {
+                self.serial.mode == ConsoleOutputMode::File
+                    && new.serial.mode == ConsoleOutputMode::File
+            }
+            "console" => {
+                self.console.mode == ConsoleOutputMode::File
+                    && new.console.mode == ConsoleOutputMode::File
+            }
+            #[cfg(target_arch = "x86_64")]
+            "debug_console" => {
+                self.debug_console.mode == ConsoleOutputMode::File
+                    && new.debug_console.mode == ConsoleOutputMode::File
+            }
+            // Rate limiters can be updated but neither added nor removed.
+            "disks" | "net" if field[2] == "rate_limiter_config" => {
+                let id = field[1].trim_start_matches('[').trim_end_matches(']');
+                self.has_rate_limiter(id) && new.has_rate_limiter(id)
+            }
+            _ => true,
+        }
+    }
+
+    fn has_rate_limiter(&self, id: &str) -> bool {
+        self.disks
+            .iter()
+            .flatten()
+            .any(|disk| disk.id.as_deref() == Some(id) && disk.rate_limiter_config.is_some())
+            || self
+                .net
+                .iter()
+                .flatten()
+                .any(|net| net.id.as_deref() == Some(id) && net.rate_limiter_config.is_some())
+    }
+
+    #[cfg(feature = "tdx")]
+    pub fn is_tdx_enabled(&self) -> bool {
+        self.platform.as_ref().map(|p| p.tdx).unwrap_or(false)
+    }
 
     #[cfg(feature = "sev_snp")]
     pub fn is_sev_snp_enabled(&self) -> bool {
@@ -3084,6 +3728,146 @@ impl VmConfig {
     }
 }
 
+/// Fields of the configuration which can be changed while the VM is running,
+/// along with everything nested under them. `[*]` stands for any element of
+/// a list.
+const LIVE_UPDATE_FIELDS: &[&[&str]] = &[
+    &["balloon", "size"],
+    &["console", "file"],
+    &["cpus", "affinity"],
+    &["debug_console", "file"],
+    &["disks", "[*]", "rate_limiter_config"],
+    &["net", "[*]", "rate_limiter_config"],
+    &["rate_limit_groups", "[*]", "rate_limiter_config"],
+    &["serial", "file"],
+];
+
+/// Fields of the configuration which can be changed while the VM is running,
+/// but only take effect once it reboots. The guest only reads the MTU of a
+/// network device when its driver probes it, changing the TAP interface
+/// underneath a running guest would leave both sides disagreeing.
+const REBOOT_UPDATE_FIELDS: &[&[&str]] = &[&["net", "[*]", "mtu"], &["vsock", "cid"]];
+
+/// Outcome of applying a partial configuration through
+/// [`VmConfig::apply_update`].
+pub struct ConfigUpdate {
+    /// The updated configuration
+    pub config: VmConfig,
+    /// Changed fields which can be applied to the running VM
+    pub live: Vec<String>,
+    /// Changed fields which only take effect once the VM reboots
+    pub on_reboot: Vec<String>,
+    /// Changed fields which can't be updated while the VM is running
+    pub unsupported: Vec<String>,
+}
+
+fn value_id(value: &serde_json::Value) -> Option<&str> {
+    value.get("id").and_then(|id| id.as_str())
+}
+
+// Merges `update` into `target` following the JSON merge patch semantics
+// (RFC 7396), except for lists whose elements all have an "id": each element
+// of `update` is merged into the element of `target` with the same identifier.
+fn merge_update(target: &mut serde_json::Value, update: &serde_json::Value) -> Result<()> {
+    use serde_json::Value;
+
+    match (target, update) {
+        (Value::Object(target), Value::Object(update)) => {
+            for (key, value) in update {
+                if value.is_null() {
+                    target.remove(key);
+                } else {
+                    merge_update(target.entry(key.clone()).or_insert(Value::Null), value)?;
+                }
+            }
+        }
+        (Value::Array(target), Value::Array(update))
+            if !update.is_empty() && update.iter().all(|value| value_id(value).is_some()) =>
+        {
+            for value in update {
+                let id = value_id(value).unwrap();
+                let element = target
+                    .iter_mut()
+                    .find(|element| value_id(element) == Some(id))
+                    .ok_or_else(|| Error::UpdateUnknownId(id.to_owned()))?;
+                merge_update(element, value)?;
+            }
+        }
+        (target, update) if update.is_object() => {
+            *target = Value::Object(Default::default());
+            merge_update(target, update)?;
+        }
+        (target, update) => *target = update.clone(),
+    }
+
+    Ok(())
+}
+
+// Collects the fields which differ between `old` and `new`. Elements of lists
+// are designated by their "id" when they have one, by their index otherwise.
+fn changed_fields(
+    field: &mut Vec<String>,
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+    changes: &mut Vec<Vec<String>>,
+) {
+    use serde_json::Value;
+
+    if old == new {
+        return;
+    }
+
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+            for key in keys {
+                field.push(key.clone());
+                changed_fields(
+                    field,
+                    old.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+                field.pop();
+            }
+        }
+        (Value::Array(old), Value::Array(new))
+            if old.len() == new.len()
+                && old.iter().zip(new).all(|(o, n)| value_id(o) == value_id(n)) =>
+        {
+            for (index, (old, new)) in old.iter().zip(new).enumerate() {
+                let element = value_id(new).map_or_else(|| index.to_string(), str::to_owned);
+                field.push(format!("[{element}]"));
+                changed_fields(field, old, new, changes);
+                field.pop();
+            }
+        }
+        _ => changes.push(field.clone()),
+    }
+}
+
+fn field_matches(pattern: &[&str], field: &[String]) -> bool {
+    field.len() >= pattern.len()
+        && pattern.iter().zip(field).all(|(pattern, segment)| {
+            if *pattern == "[*]" {
+                segment.starts_with('[')
+            } else {
+                *pattern == segment.as_str()
+            }
+        })
+}
+
+fn format_field(field: &[String]) -> String {
+    let mut name = String::new();
+    for segment in field {
+        if !name.is_empty() && !segment.starts_with('[') {
+            name.push('.');
+        }
+        name.push_str(segment);
+    }
+    name
+}
+
 impl Clone for VmConfig {
     fn clone(&self) -> Self {
         VmConfig {
@@ -3098,6 +3882,7 @@ impl Clone for VmConfig {
             #[cfg(feature = "pvmemcontrol")]
             pvmemcontrol: self.pvmemcontrol.clone(),
             fs: self.fs.clone(),
+            shares: self.shares.clone(),
             pmem: self.pmem.clone(),
             serial: self.serial.clone(),
             console: self.console.clone(),
@@ -3138,7 +3923,7 @@ impl Drop for VmConfig {
 #[cfg(test)]
 mod tests {
     use std::fs::File;
-    use std::net::Ipv4Addr;
+    use std::net::{IpAddr, Ipv4Addr};
     use std::os::unix::io::AsRawFd;
 
     use net_util::MacAddr;
@@ -3290,7 +4075,9 @@ mod tests {
                         refill_time: 100,
                     }),
                     ops: None,
-                }
+                },
+                parent: None,
+                share: None,
             }
         );
         assert_eq!(
@@ -3304,7 +4091,31 @@ mod tests {
                         one_time_burst: Some(0),
                         refill_time: 100,
                     }),
-                }
+                },
+                parent: None,
+                share: None,
+            }
+        );
+        assert_eq!(
+            RateLimiterGroupConfig::parse(
+                "id=group1,bw_size=1000,bw_refill_time=100,parent=group0,weight=2,min_bw=500"
+            )?,
+            RateLimiterGroupConfig {
+                id: "group1".to_string(),
+                rate_limiter_config: RateLimiterConfig {
+                    bandwidth: Some(TokenBucketConfig {
+                        size: 1000,
+                        one_time_burst: Some(0),
+                        refill_time: 100,
+                    }),
+                    ops: None,
+                },
+                parent: Some("group0".to_string()),
+                share: Some(RateLimitShareConfig {
+                    weight: 2,
+                    min_bandwidth: 500,
+                    min_ops: 0,
+                }),
             }
         );
         Ok(())
@@ -3364,6 +4175,7 @@ mod tests {
             disable_io_uring: false,
             disable_aio: false,
             rate_limit_group: None,
+            rate_limit_share: None,
             rate_limiter_config: None,
             pci_segment: 0,
             serial: None,
@@ -3438,6 +4250,20 @@ mod tests {
                 ..disk_fixture()
             }
         );
+        assert_eq!(
+            DiskConfig::parse(
+                "path=/path/to_file,rate_limit_group=group0,rate_limit_weight=4,rate_limit_min_ops=100"
+            )?,
+            DiskConfig {
+                rate_limit_group: Some("group0".to_string()),
+                rate_limit_share: Some(RateLimitShareConfig {
+                    weight: 4,
+                    min_bandwidth: 0,
+                    min_ops: 100,
+                }),
+                ..disk_fixture()
+            }
+        );
         assert_eq!(
             DiskConfig::parse("path=/path/to_file,queue_affinity=[0@[1],1@[2],2@[3,4],3@[5-8]]")?,
             DiskConfig {
@@ -3468,8 +4294,9 @@ mod tests {
     fn net_fixture() -> NetConfig {
         NetConfig {
             tap: None,
-            ip: Ipv4Addr::new(192, 168, 249, 1),
-            mask: Ipv4Addr::new(255, 255, 255, 0),
+            ip: IpAddr::V4(Ipv4Addr::new(192, 168, 249, 1)),
+            mask: None,
+            extra_ips: None,
             mac: MacAddr::parse_str("de:ad:be:ef:12:34").unwrap(),
             host_mac: Some(MacAddr::parse_str("12:34:de:ad:be:ef").unwrap()),
             mtu: None,
@@ -3482,6 +4309,9 @@ mod tests {
             id: None,
             fds: None,
             rate_limiter_config: None,
+            rx_rate_limit_group: None,
+            tx_rate_limit_group: None,
+            rate_limit_share: None,
             pci_segment: 0,
             offload_tso: true,
             offload_ufo: true,
@@ -3512,11 +4342,38 @@ mod tests {
             NetConfig {
                 tap: Some("tap0".to_owned()),
                 ip: "192.168.100.1".parse().unwrap(),
-                mask: "255.255.255.128".parse().unwrap(),
+                mask: Some("255.255.255.128".parse().unwrap()),
                 ..net_fixture()
             }
         );
 
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,tap=tap0,ip=fd00::1"
+            )?,
+            NetConfig {
+                tap: Some("tap0".to_owned()),
+                ip: "fd00::1".parse().unwrap(),
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,tap=tap0,extra_ips=[fd00::1/64,fd01::1/48]"
+            )?,
+            NetConfig {
+                tap: Some("tap0".to_owned()),
+                extra_ips: Some(vec![
+                    "fd00::1/64".parse().unwrap(),
+                    "fd01::1/48".parse().unwrap()
+                ]),
+                ..net_fixture()
+            }
+        );
+
+        NetConfig::parse("mac=de:ad:be:ef:12:34,extra_ips=[fd00::1/129]").unwrap_err();
+
         assert_eq!(
             NetConfig::parse(
                 "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,vhost_user=true,socket=/tmp/sock"
@@ -3548,6 +4405,49 @@ mod tests {
             }
         );
 
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,rx_rate_limit_group=group0,tx_rate_limit_group=group1"
+            )?,
+            NetConfig {
+                rx_rate_limit_group: Some("group0".to_owned()),
+                tx_rate_limit_group: Some("group1".to_owned()),
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,rx_rate_limit_group=group0,rate_limit_min_bw=1000"
+            )?,
+            NetConfig {
+                rx_rate_limit_group: Some("group0".to_owned()),
+                rate_limit_share: Some(RateLimitShareConfig {
+                    weight: 1,
+                    min_bandwidth: 1000,
+                    min_ops: 0,
+                }),
+                ..net_fixture()
+            }
+        );
+
+        // The default mask follows the family of the address, whether the
+        // configuration comes from the command line or from the API
+        let cli = NetConfig::parse("tap=tap0,ip=fd00::1")?;
+        let api: NetConfig = serde_json::from_str(r#"{"tap": "tap0", "ip": "fd00::1"}"#).unwrap();
+        for net in [cli, api] {
+            assert_eq!(net.mask, None);
+            assert_eq!(
+                net.ip_addrs().unwrap(),
+                vec!["fd00::1/64".parse::<IpNetwork>().unwrap()]
+            );
+        }
+        let api: NetConfig = serde_json::from_str(r#"{"ip": "192.168.10.1"}"#).unwrap();
+        assert_eq!(
+            api.ip_addrs().unwrap(),
+            vec!["192.168.10.1/24".parse::<IpNetwork>().unwrap()]
+        );
+
         Ok(())
     }
 
@@ -3566,6 +4466,22 @@ mod tests {
             RngConfig {
                 src: PathBuf::from("/dev/random"),
                 iommu: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            RngConfig::parse("src=egd:/run/egd-pool,bw_size=1000,bw_refill_time=100")?,
+            RngConfig {
+                src: PathBuf::from("egd:/run/egd-pool"),
+                rate_limiter_config: Some(RateLimiterConfig {
+                    bandwidth: Some(TokenBucketConfig {
+                        size: 1000,
+                        one_time_burst: Some(0),
+                        refill_time: 100,
+                    }),
+                    ops: None,
+                }),
+                ..Default::default()
             }
         );
         assert_eq!(
@@ -3578,12 +4494,41 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_balloon() -> Result<()> {
+        assert_eq!(
+            BalloonConfig::parse("size=1G")?,
+            BalloonConfig {
+                size: 1 << 30,
+                deflate_on_oom: false,
+                free_page_reporting: false,
+                statistics_polling_interval: 0,
+                free_page_hinting: false,
+            }
+        );
+        assert_eq!(
+            BalloonConfig::parse(
+                "size=1G,deflate_on_oom=on,statistics_polling_interval=5,free_page_hinting=on"
+            )?,
+            BalloonConfig {
+                size: 1 << 30,
+                deflate_on_oom: true,
+                free_page_reporting: false,
+                statistics_polling_interval: 5,
+                free_page_hinting: true,
+            }
+        );
+        BalloonConfig::parse("size=1G,statistics_polling_interval=-1").unwrap_err();
+        Ok(())
+    }
+
     fn fs_fixture() -> FsConfig {
         FsConfig {
             socket: PathBuf::from("/tmp/sock"),
             tag: "mytag".to_owned(),
             num_queues: 1,
             queue_size: 1024,
+            cache_size: None,
             id: None,
             pci_segment: 0,
         }
@@ -3604,6 +4549,13 @@ mod tests {
                 ..fs_fixture()
             }
         );
+        assert_eq!(
+            FsConfig::parse("tag=mytag,socket=/tmp/sock,cache_size=1G")?,
+            FsConfig {
+                cache_size: Some(1 << 30),
+                ..fs_fixture()
+            }
+        );
 
         Ok(())
     }
@@ -3614,6 +4566,7 @@ mod tests {
             size: Some(128 << 20),
             iommu: false,
             discard_writes: false,
+            shmem_region: false,
             id: None,
             pci_segment: 0,
         }
@@ -3643,6 +4596,47 @@ mod tests {
                 ..pmem_fixture()
             }
         );
+        assert_eq!(
+            PmemConfig::parse("file=/tmp/pmem,size=128M,shmem_region=on")?,
+            PmemConfig {
+                shmem_region: true,
+                ..pmem_fixture()
+            }
+        );
+
+        Ok(())
+    }
+
+    fn share_fixture() -> ShareConfig {
+        ShareConfig {
+            path: PathBuf::from("/tmp/share"),
+            tag: "myshare".to_owned(),
+            readonly: false,
+            iommu: false,
+            id: None,
+            pci_segment: 0,
+        }
+    }
+
+    #[test]
+    fn test_share_parsing() -> Result<()> {
+        // Must always give a path and a tag
+        ShareConfig::parse("").unwrap_err();
+        ShareConfig::parse("path=/tmp/share").unwrap_err();
+        ShareConfig::parse("tag=myshare").unwrap_err();
+        ShareConfig::parse(&format!("path=/tmp/share,tag={}", "a".repeat(65))).unwrap_err();
+        assert_eq!(
+            ShareConfig::parse("path=/tmp/share,tag=myshare")?,
+            share_fixture()
+        );
+        assert_eq!(
+            ShareConfig::parse("path=/tmp/share,tag=myshare,readonly=on,id=myshare0")?,
+            ShareConfig {
+                readonly: true,
+                id: Some("myshare0".to_owned()),
+                ..share_fixture()
+            }
+        );
 
         Ok(())
     }
@@ -3791,6 +4785,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_user_device_parsing() -> Result<()> {
+        // socket is required
+        UserDeviceConfig::parse("").unwrap_err();
+        assert_eq!(
+            UserDeviceConfig::parse("socket=/tmp/vfio-user.sock")?,
+            UserDeviceConfig {
+                socket: PathBuf::from("/tmp/vfio-user.sock"),
+                id: None,
+                pci_segment: 0,
+                migratable: false,
+            }
+        );
+        assert_eq!(
+            UserDeviceConfig::parse("socket=/tmp/vfio-user.sock,id=dev0,migratable=on")?,
+            UserDeviceConfig {
+                socket: PathBuf::from("/tmp/vfio-user.sock"),
+                id: Some("dev0".to_owned()),
+                pci_segment: 0,
+                migratable: true,
+            }
+        );
+        UserDeviceConfig::parse("socket=/tmp/vfio-user.sock,migratable=yes").unwrap_err();
+        Ok(())
+    }
+
     #[test]
     fn test_tpm_parsing() -> Result<()> {
         // path is required
@@ -3814,6 +4834,7 @@ mod tests {
                 cid: 3,
                 socket: PathBuf::from("/tmp/sock"),
                 iommu: false,
+                seqpacket: false,
                 id: None,
                 pci_segment: 0,
             }
@@ -3824,6 +4845,18 @@ mod tests {
                 cid: 3,
                 socket: PathBuf::from("/tmp/sock"),
                 iommu: true,
+                seqpacket: false,
+                id: None,
+                pci_segment: 0,
+            }
+        );
+        assert_eq!(
+            VsockConfig::parse("socket=/tmp/sock,cid=3,seqpacket=on")?,
+            VsockConfig {
+                cid: 3,
+                socket: PathBuf::from("/tmp/sock"),
+                iommu: false,
+                seqpacket: true,
                 id: None,
                 pci_segment: 0,
             }
@@ -3839,6 +4872,7 @@ mod tests {
                 source_url: PathBuf::from("/path/to/snapshot"),
                 prefault: false,
                 net_fds: None,
+                job: false,
             }
         );
         assert_eq!(
@@ -3860,6 +4894,7 @@ mod tests {
                         fds: Some(vec![5, 6, 7, 8]),
                     }
                 ]),
+                job: false,
             }
         );
         // Parsing should fail as source_url is a required field
@@ -3879,6 +4914,7 @@ mod tests {
             rng: RngConfig::default(),
             balloon: None,
             fs: None,
+            shares: None,
             pmem: None,
             serial: default_serial(),
             console: default_console(),
@@ -3940,6 +4976,7 @@ mod tests {
                     fds: Some(vec![7, 8]),
                 },
             ]),
+            job: false,
         };
         valid_config.validate(&snapshot_vm_config).unwrap();
 
@@ -4003,6 +5040,7 @@ mod tests {
             source_url: PathBuf::from("/path/to/snapshot"),
             prefault: false,
             net_fds: None,
+            job: false,
         };
         snapshot_vm_config.net = Some(vec![NetConfig {
             id: Some("net2".to_owned()),
@@ -4017,6 +5055,7 @@ mod tests {
             num_pci_segments: MAX_NUM_PCI_SEGMENTS,
             iommu_segments: None,
             iommu_address_width_bits: MAX_IOMMU_ADDRESS_WIDTH_BITS,
+            iommu_max_domains: None,
             serial_number: None,
             uuid: None,
             oem_strings: None,
@@ -4078,9 +5117,11 @@ mod tests {
             rng: RngConfig {
                 src: PathBuf::from("/dev/urandom"),
                 iommu: false,
+                rate_limiter_config: None,
             },
             balloon: None,
             fs: None,
+            shares: None,
             pmem: None,
             serial: ConsoleConfig {
                 file: None,
@@ -4246,6 +5287,37 @@ mod tests {
             Err(ValidationError::NoHardwareChecksumOffload)
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            ip: "fd00::1".parse().unwrap(),
+            mask: Some("255.255.255.0".parse().unwrap()),
+            ..net_fixture()
+        }]);
+        assert!(matches!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidNetIpConfig(_))
+        ));
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            extra_ips: Some(vec!["10.0.0.1/24".parse().unwrap()]),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::MultipleNetIpv4Addresses)
+        );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.net = Some(vec![NetConfig {
+            extra_ips: Some(vec![
+                "fd00::1/64".parse().unwrap(),
+                "fd01::1/64".parse().unwrap(),
+            ]),
+            ..net_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
         let mut invalid_config = valid_config.clone();
         invalid_config.fs = Some(vec![fs_fixture()]);
         assert_eq!(
@@ -4327,6 +5399,16 @@ mod tests {
             ))
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.platform = Some(PlatformConfig {
+            iommu_max_domains: Some(0),
+            ..platform_fixture()
+        });
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidIommuMaxDomains(0))
+        );
+
         let mut still_valid_config = valid_config.clone();
         still_valid_config.platform = Some(PlatformConfig {
             iommu_segments: Some(vec![1, 2, 3]),
@@ -4385,6 +5467,7 @@ mod tests {
             socket: PathBuf::new(),
             id: None,
             iommu: true,
+            seqpacket: false,
             pci_segment: 1,
         });
         still_valid_config.validate().unwrap();
@@ -4461,6 +5544,7 @@ mod tests {
             socket: PathBuf::new(),
             id: None,
             iommu: false,
+            seqpacket: false,
             pci_segment: 1,
         });
         assert_eq!(
@@ -4478,6 +5562,7 @@ mod tests {
             pci_segment: 1,
             socket: PathBuf::new(),
             id: None,
+            migratable: false,
         }]);
         assert_eq!(
             invalid_config.validate(),
@@ -4513,6 +5598,17 @@ mod tests {
             Err(ValidationError::IommuNotSupportedOnSegment(1))
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.fs = Some(vec![FsConfig {
+            cache_size: Some(3 << 20),
+            ..fs_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidFsCacheSize(3 << 20))
+        );
+
         let mut invalid_config = valid_config.clone();
         invalid_config.platform = Some(PlatformConfig {
             num_pci_segments: 2,
@@ -4602,6 +5698,167 @@ mod tests {
             Err(ValidationError::InvalidRateLimiterGroup)
         );
 
+        let rate_limiter_config = RateLimiterConfig {
+            bandwidth: Some(TokenBucketConfig {
+                size: 1000,
+                one_time_burst: None,
+                refill_time: 100,
+            }),
+            ops: None,
+        };
+        let mut rate_limited_config = valid_config.clone();
+        rate_limited_config.rate_limit_groups = Some(vec![RateLimiterGroupConfig {
+            id: "group0".to_owned(),
+            rate_limiter_config,
+            parent: None,
+            share: None,
+        }]);
+        rate_limited_config.disks = Some(vec![
+            DiskConfig {
+                id: Some("disk0".to_owned()),
+                rate_limit_group: Some("group0".to_owned()),
+                ..disk_fixture()
+            },
+            DiskConfig {
+                id: Some("disk1".to_owned()),
+                path: Some(PathBuf::from("/path/to_file1")),
+                rate_limiter_config: Some(rate_limiter_config),
+                ..disk_fixture()
+            },
+        ]);
+        rate_limited_config.net = Some(vec![NetConfig {
+            id: Some("net0".to_owned()),
+            ..net_fixture()
+        }]);
+        rate_limited_config.validate().unwrap();
+        let new_rate_limiter_config = RateLimiterConfig {
+            bandwidth: None,
+            ops: Some(TokenBucketConfig {
+                size: 10,
+                one_time_burst: Some(5),
+                refill_time: 100,
+            }),
+        };
+        rate_limited_config
+            .update_rate_limiter("group0", new_rate_limiter_config)
+            .unwrap();
+        assert_eq!(
+            rate_limited_config.rate_limit_groups.as_ref().unwrap()[0].rate_limiter_config,
+            new_rate_limiter_config
+        );
+        rate_limited_config
+            .update_rate_limiter("disk1", new_rate_limiter_config)
+            .unwrap();
+        assert_eq!(
+            rate_limited_config.disks.as_ref().unwrap()[1].rate_limiter_config,
+            Some(new_rate_limiter_config)
+        );
+        assert_eq!(
+            rate_limited_config.update_rate_limiter("group0", RateLimiterConfig::default()),
+            Err(ValidationError::InvalidRateLimiterGroup)
+        );
+        assert_eq!(
+            rate_limited_config.update_rate_limiter("disk0", new_rate_limiter_config),
+            Err(ValidationError::RateLimiterInGroup("disk0".to_owned()))
+        );
+        assert_eq!(
+            rate_limited_config.update_rate_limiter("net0", new_rate_limiter_config),
+            Err(ValidationError::NoRateLimiter("net0".to_owned()))
+        );
+        assert_eq!(
+            rate_limited_config.update_rate_limiter("foo", new_rate_limiter_config),
+            Err(ValidationError::UnknownRateLimiterId("foo".to_owned()))
+        );
+        rate_limited_config.validate().unwrap();
+
+        let mut net_config = rate_limited_config.clone();
+        net_config.net = Some(vec![NetConfig {
+            id: Some("net1".to_owned()),
+            rx_rate_limit_group: Some("group0".to_owned()),
+            ..net_fixture()
+        }]);
+        net_config.validate().unwrap();
+        assert_eq!(
+            net_config.update_rate_limiter("net1", new_rate_limiter_config),
+            Err(ValidationError::RateLimiterInGroup("net1".to_owned()))
+        );
+        net_config.net = Some(vec![NetConfig {
+            tx_rate_limit_group: Some("foo".to_owned()),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            net_config.validate(),
+            Err(ValidationError::InvalidRateLimiterGroup)
+        );
+        net_config.net = Some(vec![NetConfig {
+            rate_limiter_config: Some(rate_limiter_config),
+            tx_rate_limit_group: Some("group0".to_owned()),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            net_config.validate(),
+            Err(ValidationError::InvalidRateLimiterGroup)
+        );
+        net_config.net = Some(vec![NetConfig {
+            rate_limit_share: Some(RateLimitShareConfig::default()),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            net_config.validate(),
+            Err(ValidationError::InvalidRateLimitShare)
+        );
+
+        let mut nested_config = rate_limited_config.clone();
+        let vm_group = RateLimiterGroupConfig {
+            id: "vm".to_owned(),
+            rate_limiter_config,
+            parent: None,
+            share: None,
+        };
+        let disk_group = RateLimiterGroupConfig {
+            id: "group0".to_owned(),
+            rate_limiter_config,
+            parent: Some("vm".to_owned()),
+            share: Some(RateLimitShareConfig {
+                weight: 3,
+                ..Default::default()
+            }),
+        };
+        nested_config.rate_limit_groups = Some(vec![vm_group.clone(), disk_group.clone()]);
+        nested_config.disks.as_mut().unwrap()[0].rate_limit_share = Some(RateLimitShareConfig {
+            weight: 2,
+            min_bandwidth: 100,
+            min_ops: 0,
+        });
+        nested_config.validate().unwrap();
+        nested_config.rate_limit_groups = Some(vec![disk_group.clone(), vm_group.clone()]);
+        assert_eq!(
+            nested_config.validate(),
+            Err(ValidationError::InvalidRateLimiterGroup)
+        );
+        nested_config.rate_limit_groups = Some(vec![
+            vm_group.clone(),
+            RateLimiterGroupConfig {
+                share: Some(RateLimitShareConfig {
+                    weight: 0,
+                    ..Default::default()
+                }),
+                ..disk_group
+            },
+        ]);
+        assert_eq!(
+            nested_config.validate(),
+            Err(ValidationError::InvalidRateLimitShare)
+        );
+        nested_config.rate_limit_groups = Some(vec![RateLimiterGroupConfig {
+            share: Some(RateLimitShareConfig::default()),
+            ..vm_group
+        }]);
+        assert_eq!(
+            nested_config.validate(),
+            Err(ValidationError::InvalidRateLimitShare)
+        );
+
         let mut still_valid_config = valid_config.clone();
         still_valid_config.devices = Some(vec![
             DeviceConfig {
@@ -4685,6 +5942,153 @@ mod tests {
         }
         let _still_valid_config = still_valid_config.clone();
     }
+    #[test]
+    fn test_apply_update() {
+        let config: VmConfig = serde_json::from_value(serde_json::json!({
+            "cpus": {"boot_vcpus": 2, "max_vcpus": 2},
+            "disks": [
+                {"path": "/path/to_file0", "id": "disk0"},
+                {
+                    "path": "/path/to_file1",
+                    "id": "disk1",
+                    "rate_limiter_config": {"bandwidth": {"size": 1000, "refill_time": 100}}
+                }
+            ],
+            "net": [{"id": "net0"}],
+            "serial": {"mode": "File", "file": "/tmp/serial.log"},
+            "vsock": {"cid": 3, "socket": "/tmp/vsock"}
+        }))
+        .unwrap();
+
+        let update = config
+            .apply_update(&serde_json::json!({
+                "cpus": {"affinity": [{"vcpu": 0, "host_cpus": [1]}]},
+                "disks": [{
+                    "id": "disk1",
+                    "rate_limiter_config": {"bandwidth": {"size": 2000, "refill_time": 100}}
+                }],
+                "net": [{"id": "net0", "mtu": 9000}],
+                "serial": {"file": "/tmp/serial.1.log"},
+                "vsock": {"cid": 4}
+            }))
+            .unwrap();
+        assert_eq!(
+            update.live,
+            vec![
+                "cpus.affinity",
+                "disks[disk1].rate_limiter_config.bandwidth.size",
+                "serial.file",
+            ]
+        );
+        assert_eq!(update.on_reboot, vec!["net[net0].mtu", "vsock.cid"]);
+        assert!(update.unsupported.is_empty());
+        assert_eq!(
+            update.config.serial.file,
+            Some(PathBuf::from("/tmp/serial.1.log"))
+        );
+        assert_eq!(update.config.net.as_ref().unwrap()[0].mtu, Some(9000));
+        assert_eq!(update.config.vsock.as_ref().unwrap().cid, 4);
+        assert_eq!(
+            update.config.disks.as_ref().unwrap()[1]
+                .rate_limiter_config
+                .unwrap()
+                .bandwidth
+                .unwrap()
+                .size,
+            2000
+        );
+        assert_eq!(
+            update.config.disks.as_ref().unwrap()[0],
+            config.disks.as_ref().unwrap()[0]
+        );
+
+        let update = config
+            .apply_update(&serde_json::json!({
+                "memory": {"size": 1073741824},
+                "disks": [
+                    {"id": "disk0", "readonly": true},
+                    {"id": "disk1", "rate_limiter_config": null}
+                ],
+                "console": {"file": "/tmp/console.log"}
+            }))
+            .unwrap();
+        assert!(update.live.is_empty());
+        assert_eq!(
+            update.unsupported,
+            vec![
+                "console.file",
+                "disks[disk0].readonly",
+                "disks[disk1].rate_limiter_config",
+                "memory.size",
+            ]
+        );
+
+        // What can be changed live depends on the current configuration,
+        // the devices having been created from it.
+        let update = config
+            .apply_update(&serde_json::json!({
+                "disks": [{
+                    "id": "disk0",
+                    "rate_limiter_config": {"bandwidth": {"size": 1000, "refill_time": 100}}
+                }],
+                "serial": {"mode": "Tty", "file": "/tmp/serial.1.log"}
+            }))
+            .unwrap();
+        assert!(update.live.is_empty());
+        assert_eq!(
+            update.unsupported,
+            vec![
+                "disks[disk0].rate_limiter_config",
+                "serial.file",
+                "serial.mode",
+            ]
+        );
+
+        let tty_config = config
+            .apply_update(&serde_json::json!({"serial": {"mode": "Tty", "file": null}}))
+            .unwrap()
+            .config;
+        let update = tty_config
+            .apply_update(&serde_json::json!({
+                "serial": {"mode": "File", "file": "/tmp/serial.log"}
+            }))
+            .unwrap();
+        assert!(update.live.is_empty());
+        assert_eq!(update.unsupported, vec!["serial.file", "serial.mode"]);
+
+        let update = config
+            .apply_update(&serde_json::json!({
+                "balloon": {"size": 0},
+                "rate_limit_groups": [{
+                    "id": "group0",
+                    "rate_limiter_config": {"ops": {"size": 10, "refill_time": 100}}
+                }]
+            }))
+            .unwrap();
+        assert!(update.live.is_empty());
+        assert_eq!(update.unsupported, vec!["balloon", "rate_limit_groups"]);
+
+        // Unchanged values are not reported.
+        let update = config
+            .apply_update(&serde_json::json!({
+                "serial": {"file": "/tmp/serial.log"},
+                "vsock": {"cid": 3}
+            }))
+            .unwrap();
+        assert!(update.live.is_empty());
+        assert!(update.on_reboot.is_empty());
+        assert!(update.unsupported.is_empty());
+
+        assert!(matches!(
+            config.apply_update(&serde_json::json!({"disks": [{"id": "disk2"}]})),
+            Err(Error::UpdateUnknownId(id)) if id == "disk2"
+        ));
+        assert!(matches!(
+            config.apply_update(&serde_json::json!({"cpus": {"boot_vcpus": "two"}})),
+            Err(Error::ParseUpdate(_))
+        ));
+    }
+
     #[test]
     fn test_landlock_parsing() -> Result<()> {
         // should not be empty
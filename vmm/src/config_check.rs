@@ -0,0 +1,359 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Dry run checks of a VM configuration.
+//!
+//! The configuration goes through the same validation as when a VM is
+//! created, then the host is probed for what the configuration relies on.
+//! Nothing is created: the outcome is a report of errors, which would make
+//! the VM creation or boot fail, and warnings, which would not.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+use hypervisor::Hypervisor;
+use serde::{Deserialize, Serialize};
+
+use crate::vm_config::VmConfig;
+
+const HUGEPAGES_SYSFS_DIR: &str = "/sys/kernel/mm/hugepages";
+const CAP_NET_ADMIN: u32 = 12;
+
+/// Kind of check an issue was raised by.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigCheck {
+    Validation,
+    File,
+    Hypervisor,
+    IoUring,
+    Hugepages,
+    Vfio,
+    Tap,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ConfigIssue {
+    pub check: ConfigCheck,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ConfigCheckReport {
+    pub errors: Vec<ConfigIssue>,
+    pub warnings: Vec<ConfigIssue>,
+}
+
+impl ConfigCheckReport {
+    pub fn error(&mut self, check: ConfigCheck, message: String) {
+        self.errors.push(ConfigIssue { check, message });
+    }
+
+    pub fn warning(&mut self, check: ConfigCheck, message: String) {
+        self.warnings.push(ConfigIssue { check, message });
+    }
+}
+
+/// Check `config` without creating a VM. The hypervisor capabilities are
+/// only probed when a `hypervisor` is provided.
+pub fn check_config(config: &VmConfig, hypervisor: Option<&dyn Hypervisor>) -> ConfigCheckReport {
+    let mut report = ConfigCheckReport::default();
+
+    // Validation fills in the device identifiers, work on a copy
+    let mut validated = config.clone();
+    if let Err(e) = validated.validate() {
+        report.error(ConfigCheck::Validation, e.to_string());
+    }
+
+    check_files(&mut report, config);
+    if let Some(hypervisor) = hypervisor {
+        check_hypervisor(&mut report, config, hypervisor);
+    }
+    #[cfg(feature = "io_uring")]
+    check_io_uring(&mut report, config);
+    check_hugepages(&mut report, config);
+    check_vfio(&mut report, config);
+    check_tap(&mut report, config);
+
+    report
+}
+
+fn check_file(report: &mut ConfigCheckReport, what: &str, path: &Path, write: bool) {
+    if let Err(e) = OpenOptions::new().read(true).write(write).open(path) {
+        report.error(
+            ConfigCheck::File,
+            format!("Cannot open {what} {}: {e}", path.display()),
+        );
+    }
+}
+
+// Sockets are provided by backends which may not be started yet
+fn check_socket(report: &mut ConfigCheckReport, what: &str, path: &Path) {
+    if !path.exists() {
+        report.warning(
+            ConfigCheck::File,
+            format!("{what} socket {} does not exist yet", path.display()),
+        );
+    }
+}
+
+fn check_files(report: &mut ConfigCheckReport, config: &VmConfig) {
+    if let Some(payload) = &config.payload {
+        if let Some(firmware) = &payload.firmware {
+            check_file(report, "firmware", firmware, false);
+        }
+        if let Some(kernel) = &payload.kernel {
+            check_file(report, "kernel", kernel, false);
+        }
+        if let Some(initramfs) = &payload.initramfs {
+            check_file(report, "initramfs", initramfs, false);
+        }
+        #[cfg(feature = "igvm")]
+        if let Some(igvm) = &payload.igvm {
+            check_file(report, "IGVM file", igvm, false);
+        }
+    }
+
+    for disk in config.disks.iter().flatten() {
+        if disk.vhost_user {
+            if let Some(socket) = &disk.vhost_socket {
+                check_socket(report, "vhost-user disk", Path::new(socket));
+            }
+        } else if let Some(path) = &disk.path {
+            check_file(report, "disk image", path, !disk.readonly);
+        }
+    }
+
+    for pmem in config.pmem.iter().flatten() {
+        // A directory gets a temporary backing file created in it
+        if !pmem.file.is_dir() {
+            check_file(report, "pmem file", &pmem.file, !pmem.discard_writes);
+        }
+    }
+
+    check_file(report, "entropy source", &config.rng.src, false);
+
+    for fs_config in config.fs.iter().flatten() {
+        check_socket(report, "virtio-fs", &fs_config.socket);
+    }
+    for user_device in config.user_devices.iter().flatten() {
+        check_socket(report, "vfio-user", &user_device.socket);
+    }
+    for vdpa in config.vdpa.iter().flatten() {
+        check_file(report, "vDPA device", &vdpa.path, true);
+    }
+    if let Some(tpm) = &config.tpm {
+        check_socket(report, "TPM", &tpm.socket);
+    }
+}
+
+fn check_hypervisor(
+    report: &mut ConfigCheckReport,
+    config: &VmConfig,
+    hypervisor: &dyn Hypervisor,
+) {
+    if let Err(e) = hypervisor.check_required_extensions() {
+        report.error(
+            ConfigCheck::Hypervisor,
+            format!("Missing required hypervisor extensions: {e}"),
+        );
+    }
+
+    let max_vcpus = hypervisor.get_max_vcpus();
+    if u32::from(config.cpus.max_vcpus) > max_vcpus {
+        report.error(
+            ConfigCheck::Hypervisor,
+            format!(
+                "{} vCPUs requested but the hypervisor supports at most {max_vcpus}",
+                config.cpus.max_vcpus
+            ),
+        );
+    }
+
+    #[cfg(feature = "mshv")]
+    if config.cpus.kvm_hyperv
+        && matches!(
+            hypervisor.hypervisor_type(),
+            hypervisor::HypervisorType::Mshv
+        )
+    {
+        report.warning(
+            ConfigCheck::Hypervisor,
+            "kvm_hyperv is ignored when running on MSHV".to_string(),
+        );
+    }
+}
+
+#[cfg(feature = "io_uring")]
+fn check_io_uring(report: &mut ConfigCheckReport, config: &VmConfig) {
+    let needs_io_uring = config
+        .disks
+        .iter()
+        .flatten()
+        .any(|disk| !disk.vhost_user && !disk.disable_io_uring);
+
+    if needs_io_uring && !block::block_io_uring_is_supported() {
+        report.warning(
+            ConfigCheck::IoUring,
+            "io_uring is not usable on this host, disks will fall back to a slower I/O backend"
+                .to_string(),
+        );
+    }
+}
+
+// Size in bytes of the default hugepages, as reported by /proc/meminfo
+fn default_hugepage_size() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("Hugepagesize:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+
+    Some(kib << 10)
+}
+
+fn check_hugepages(report: &mut ConfigCheckReport, config: &VmConfig) {
+    // Bytes of memory needed for each hugepage size
+    let mut needed: BTreeMap<Option<u64>, u64> = BTreeMap::new();
+    if config.memory.hugepages && config.memory.size > 0 {
+        *needed.entry(config.memory.hugepage_size).or_default() += config.memory.size;
+    }
+    for zone in config.memory.zones.iter().flatten() {
+        if zone.hugepages && zone.file.is_none() {
+            *needed.entry(zone.hugepage_size).or_default() += zone.size;
+        }
+    }
+
+    for (hugepage_size, size) in needed {
+        let Some(hugepage_size) = hugepage_size.or_else(default_hugepage_size) else {
+            report.error(
+                ConfigCheck::Hugepages,
+                "Hugepages are not supported by this host".to_string(),
+            );
+            continue;
+        };
+
+        let kib = hugepage_size >> 10;
+        let path = format!("{HUGEPAGES_SYSFS_DIR}/hugepages-{kib}kB/free_hugepages");
+        let Some(free) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+        else {
+            report.error(
+                ConfigCheck::Hugepages,
+                format!("Hugepages of {kib} KiB are not supported by this host"),
+            );
+            continue;
+        };
+
+        let pages = size.div_ceil(hugepage_size);
+        if pages > free {
+            report.error(
+                ConfigCheck::Hugepages,
+                format!("{pages} hugepages of {kib} KiB needed but only {free} are free"),
+            );
+        }
+    }
+}
+
+fn check_vfio(report: &mut ConfigCheckReport, config: &VmConfig) {
+    let Some(devices) = &config.devices else {
+        return;
+    };
+
+    if !devices.is_empty() {
+        check_file(report, "VFIO container", Path::new("/dev/vfio/vfio"), true);
+    }
+
+    for device in devices {
+        let path = device.path.display();
+
+        let Some(group) = fs::read_link(device.path.join("iommu_group"))
+            .ok()
+            .and_then(|link| link.file_name().map(|name| name.to_owned()))
+        else {
+            report.error(
+                ConfigCheck::Vfio,
+                format!("Device {path} does not belong to an IOMMU group"),
+            );
+            continue;
+        };
+
+        let driver = fs::read_link(device.path.join("driver"))
+            .ok()
+            .and_then(|link| link.file_name().map(|name| name.to_owned()));
+        if driver.as_deref() != Some(OsStr::new("vfio-pci")) {
+            report.error(
+                ConfigCheck::Vfio,
+                format!("Device {path} is not bound to the vfio-pci driver"),
+            );
+        }
+
+        if let Err(e) = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(Path::new("/dev/vfio").join(&group))
+        {
+            report.error(
+                ConfigCheck::Vfio,
+                format!(
+                    "Cannot open VFIO group {} of device {path}: {e}",
+                    group.to_string_lossy()
+                ),
+            );
+        }
+    }
+}
+
+// Whether the effective capabilities of the process include `cap`
+fn has_capability(cap: u32) -> bool {
+    let Ok(status) = fs::read_to_string("/proc/self/status") else {
+        return false;
+    };
+
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << cap) != 0)
+}
+
+fn check_tap(report: &mut ConfigCheckReport, config: &VmConfig) {
+    // Taps passed as file descriptors are already opened
+    let taps: Vec<_> = config
+        .net
+        .iter()
+        .flatten()
+        .filter(|net| !net.vhost_user && net.fds.is_none())
+        .collect();
+    if taps.is_empty() {
+        return;
+    }
+
+    check_file(report, "TUN device", Path::new("/dev/net/tun"), true);
+
+    if has_capability(CAP_NET_ADMIN) {
+        return;
+    }
+
+    for net in taps {
+        match &net.tap {
+            Some(tap) if Path::new("/sys/class/net").join(tap).exists() => report.warning(
+                ConfigCheck::Tap,
+                format!("Configuring TAP interface {tap} may require CAP_NET_ADMIN"),
+            ),
+            Some(tap) => report.error(
+                ConfigCheck::Tap,
+                format!("Creating TAP interface {tap} requires CAP_NET_ADMIN"),
+            ),
+            None => report.error(
+                ConfigCheck::Tap,
+                "Creating a TAP interface requires CAP_NET_ADMIN".to_string(),
+            ),
+        }
+    }
+}
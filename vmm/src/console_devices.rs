@@ -15,7 +15,7 @@ use std::mem::zeroed;
 use std::os::fd::{AsRawFd, FromRawFd, RawFd};
 use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::net::UnixListener;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::{io, result};
 
@@ -51,6 +51,10 @@ pub enum ConsoleDeviceError {
     /// Error starting sigwinch listener
     #[error("Error starting sigwinch listener: {0}")]
     StartSigwinchListener(#[source] std::io::Error),
+
+    /// Console output is not written to a file
+    #[error("Console output is not written to a file")]
+    NotFileOutput,
 }
 
 type ConsoleDeviceResult<T> = result::Result<T, ConsoleDeviceError>;
@@ -176,6 +180,36 @@ fn dup_stdout() -> vmm_sys_util::errno::Result<File> {
     Ok(unsafe { File::from_raw_fd(stdout) })
 }
 
+/// Open the file at `path`, created or truncated, for the output of a
+/// console currently written to a file, see `switch_console_file()`.
+pub(crate) fn open_console_file(output: &ConsoleOutput, path: &Path) -> ConsoleDeviceResult<File> {
+    if !matches!(output, ConsoleOutput::File(_)) {
+        return Err(ConsoleDeviceError::NotFileOutput);
+    }
+
+    File::create(path).map_err(ConsoleDeviceError::CreateConsoleDevice)
+}
+
+/// Move the output of a console written to a file over to `new_file`. The
+/// console devices keep writing through the same file descriptor, which is
+/// now backed by the new file.
+pub(crate) fn switch_console_file(
+    output: &ConsoleOutput,
+    new_file: &File,
+) -> ConsoleDeviceResult<()> {
+    let ConsoleOutput::File(file) = output else {
+        return Err(ConsoleDeviceError::NotFileOutput);
+    };
+
+    // SAFETY: FFI call with valid FDs
+    let ret = unsafe { libc::dup3(new_file.as_raw_fd(), file.as_raw_fd(), libc::O_CLOEXEC) };
+    if ret == -1 {
+        return Err(ConsoleDeviceError::DupFd(vmm_sys_util::errno::Error::last()));
+    }
+
+    Ok(())
+}
+
 pub(crate) fn pre_create_console_devices(vmm: &mut Vmm) -> ConsoleDeviceResult<ConsoleInfo> {
     let vm_config = vmm.vm_config.as_mut().unwrap().clone();
     let mut vmconfig = vm_config.lock().unwrap();
@@ -297,3 +331,38 @@ pub(crate) fn pre_create_console_devices(vmm: &mut Vmm) -> ConsoleDeviceResult<C
 
     Ok(console_info)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+    use std::io::Write;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_switch_console_file() {
+        let dir = TempDir::new_with_prefix("/tmp/ch").unwrap();
+        let old_path = dir.as_path().join("serial.log");
+        let new_path = dir.as_path().join("serial.1.log");
+
+        let file = Arc::new(File::create(&old_path).unwrap());
+        let output = ConsoleOutput::File(file.clone());
+        (&*file).write_all(b"before\n").unwrap();
+
+        // Nothing is created for an output which isn't a file.
+        assert!(matches!(
+            open_console_file(&ConsoleOutput::Null, &new_path),
+            Err(ConsoleDeviceError::NotFileOutput)
+        ));
+        assert!(!new_path.exists());
+
+        let new_file = open_console_file(&output, &new_path).unwrap();
+        switch_console_file(&output, &new_file).unwrap();
+        (&*file).write_all(b"after\n").unwrap();
+
+        assert_eq!(read_to_string(&old_path).unwrap(), "before\n");
+        assert_eq!(read_to_string(&new_path).unwrap(), "after\n");
+    }
+}
@@ -11,14 +11,16 @@
 // SPDX-License-Identifier: Apache-2.0 AND BSD-3-Clause
 //
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use std::io::Write;
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use std::mem::size_of;
+use std::num::Wrapping;
 use std::os::unix::thread::JoinHandleExt;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Barrier, Mutex};
+use std::time::{Duration, Instant};
 use std::{cmp, io, result, thread};
 
 #[cfg(not(target_arch = "riscv64"))]
@@ -53,13 +55,13 @@ use hypervisor::CpuVendor;
 use hypervisor::HypervisorType;
 #[cfg(feature = "guest_debug")]
 use hypervisor::StandardRegisters;
-use hypervisor::{CpuState, HypervisorCpuError, VmExit, VmOps};
+use hypervisor::{CpuState, HypervisorCpuError, HypervisorVmError, VmExit, VmOps};
 use libc::{c_void, siginfo_t};
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use linux_loader::elf::Elf64_Nhdr;
 use seccompiler::{apply_filter, SeccompAction};
 use thiserror::Error;
-use tracer::trace_scoped;
+use tracer::{trace_counters, trace_scoped};
 use vm_device::BusDevice;
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use vm_memory::ByteValued;
@@ -87,7 +89,7 @@ use crate::memory_manager::MemoryManager;
 use crate::seccomp_filters::{get_seccomp_filter, Thread};
 #[cfg(target_arch = "x86_64")]
 use crate::vm::physical_bits;
-use crate::vm_config::CpusConfig;
+use crate::vm_config::{CpuAffinity, CpusConfig};
 use crate::{GuestMemoryMmap, CPU_MANAGER_SNAPSHOT_ID};
 
 #[cfg(all(target_arch = "aarch64", feature = "guest_debug"))]
@@ -201,6 +203,9 @@ pub enum Error {
     #[cfg(target_arch = "x86_64")]
     #[error("Failed to inject NMI")]
     NmiError(hypervisor::HypervisorCpuError),
+
+    #[error("Error setting the affinity of the vCPUs: {0}")]
+    SetAffinity(#[source] io::Error),
 }
 pub type Result<T> = result::Result<T, Error>;
 
@@ -327,6 +332,159 @@ macro_rules! round_up {
     };
 }
 
+/// Reasons a vCPU returned to userspace for, as accounted by `VcpuStats`.
+#[derive(Clone, Copy)]
+enum VcpuExitReason {
+    PioRead,
+    PioWrite,
+    MmioRead,
+    MmioWrite,
+    IoapicEoi,
+    Hyperv,
+    Debug,
+    Tdx,
+    Reset,
+    Shutdown,
+    // Exits with nothing to handle, such as the ones caused by a signal.
+    Other,
+}
+
+const VCPU_EXIT_REASONS: [(VcpuExitReason, &str); 11] = [
+    (VcpuExitReason::PioRead, "pio_read_exits"),
+    (VcpuExitReason::PioWrite, "pio_write_exits"),
+    (VcpuExitReason::MmioRead, "mmio_read_exits"),
+    (VcpuExitReason::MmioWrite, "mmio_write_exits"),
+    (VcpuExitReason::IoapicEoi, "ioapic_eoi_exits"),
+    (VcpuExitReason::Hyperv, "hyperv_exits"),
+    (VcpuExitReason::Debug, "debug_exits"),
+    (VcpuExitReason::Tdx, "tdx_exits"),
+    (VcpuExitReason::Reset, "reset_exits"),
+    (VcpuExitReason::Shutdown, "shutdown_exits"),
+    (VcpuExitReason::Other, "other_exits"),
+];
+
+/// Exit and run-time accounting of a single vCPU.
+///
+/// PIO and MMIO exits are handled from within `hypervisor::Vcpu::run()`
+/// through `VmOps` and only surface as `VmExit::Ignore`, so they are
+/// accounted by `VcpuVmOps` while the other exits are accounted from the
+/// vCPU run loop.
+#[derive(Default)]
+pub struct VcpuStats {
+    exits: [AtomicU64; VCPU_EXIT_REASONS.len()],
+    run_time_ns: AtomicU64,
+    exit_handling_time_ns: AtomicU64,
+    // Time spent handling PIO and MMIO accesses during the ongoing run.
+    pending_io_time_ns: AtomicU64,
+    pending_io: AtomicBool,
+}
+
+impl VcpuStats {
+    fn account_io<T>(&self, reason: VcpuExitReason, handler: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let ret = handler();
+        self.exits[reason as usize].fetch_add(1, Ordering::Relaxed);
+        self.pending_io_time_ns
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.pending_io.store(true, Ordering::Relaxed);
+        ret
+    }
+
+    /// Accounts a return from `Vcpu::run()` which lasted `elapsed`.
+    fn account_run(&self, elapsed: Duration, exit: Option<&VmExit>) {
+        let io_time_ns = self.pending_io_time_ns.swap(0, Ordering::Relaxed);
+        let io = self.pending_io.swap(false, Ordering::Relaxed);
+        self.run_time_ns.fetch_add(
+            (elapsed.as_nanos() as u64).saturating_sub(io_time_ns),
+            Ordering::Relaxed,
+        );
+        self.exit_handling_time_ns
+            .fetch_add(io_time_ns, Ordering::Relaxed);
+
+        let reason = match exit {
+            #[cfg(target_arch = "x86_64")]
+            Some(VmExit::IoapicEoi(_)) => VcpuExitReason::IoapicEoi,
+            Some(VmExit::Hyperv) => VcpuExitReason::Hyperv,
+            #[cfg(feature = "kvm")]
+            Some(VmExit::Debug) => VcpuExitReason::Debug,
+            #[cfg(feature = "tdx")]
+            Some(VmExit::Tdx) => VcpuExitReason::Tdx,
+            Some(VmExit::Reset) => VcpuExitReason::Reset,
+            Some(VmExit::Shutdown) => VcpuExitReason::Shutdown,
+            // Already accounted by VcpuVmOps.
+            Some(VmExit::Ignore) if io => return,
+            Some(VmExit::Ignore) | None => VcpuExitReason::Other,
+        };
+        self.exits[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn account_exit_handling(&self, elapsed: Duration) {
+        self.exit_handling_time_ns
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn counters(&self) -> HashMap<&'static str, Wrapping<u64>> {
+        let mut counters = HashMap::new();
+        let mut total = 0;
+        for (reason, name) in VCPU_EXIT_REASONS {
+            let exits = self.exits[reason as usize].load(Ordering::Relaxed);
+            total += exits;
+            counters.insert(name, Wrapping(exits));
+        }
+        counters.insert("exits", Wrapping(total));
+        counters.insert(
+            "run_time_us",
+            Wrapping(self.run_time_ns.load(Ordering::Relaxed) / 1000),
+        );
+        counters.insert(
+            "exit_handling_time_us",
+            Wrapping(self.exit_handling_time_ns.load(Ordering::Relaxed) / 1000),
+        );
+        counters
+    }
+}
+
+/// `VmOps` handed over to a single vCPU, accounting its PIO and MMIO exits.
+struct VcpuVmOps {
+    vm_ops: Arc<dyn VmOps>,
+    stats: Arc<VcpuStats>,
+}
+
+impl VmOps for VcpuVmOps {
+    fn guest_mem_write(&self, gpa: u64, buf: &[u8]) -> result::Result<usize, HypervisorVmError> {
+        self.vm_ops.guest_mem_write(gpa, buf)
+    }
+
+    fn guest_mem_read(&self, gpa: u64, buf: &mut [u8]) -> result::Result<usize, HypervisorVmError> {
+        self.vm_ops.guest_mem_read(gpa, buf)
+    }
+
+    fn mmio_read(&self, gpa: u64, data: &mut [u8]) -> result::Result<(), HypervisorVmError> {
+        self.stats.account_io(VcpuExitReason::MmioRead, || {
+            self.vm_ops.mmio_read(gpa, data)
+        })
+    }
+
+    fn mmio_write(&self, gpa: u64, data: &[u8]) -> result::Result<(), HypervisorVmError> {
+        self.stats.account_io(VcpuExitReason::MmioWrite, || {
+            self.vm_ops.mmio_write(gpa, data)
+        })
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn pio_read(&self, port: u64, data: &mut [u8]) -> result::Result<(), HypervisorVmError> {
+        self.stats
+            .account_io(VcpuExitReason::PioRead, || self.vm_ops.pio_read(port, data))
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn pio_write(&self, port: u64, data: &[u8]) -> result::Result<(), HypervisorVmError> {
+        self.stats.account_io(VcpuExitReason::PioWrite, || {
+            self.vm_ops.pio_write(port, data)
+        })
+    }
+}
+
 /// A wrapper around creating and using a kvm-based VCPU.
 pub struct Vcpu {
     // The hypervisor abstracted CPU.
@@ -608,6 +766,7 @@ struct VcpuState {
     kill: Arc<AtomicBool>,
     vcpu_run_interrupted: Arc<AtomicBool>,
     paused: Arc<AtomicBool>,
+    stats: Arc<VcpuStats>,
 }
 
 impl VcpuState {
@@ -648,6 +807,19 @@ impl VcpuState {
     }
 }
 
+// Builds the CPU set made of the given host CPUs.
+fn cpuset(host_cpus: &[usize]) -> libc::cpu_set_t {
+    // SAFETY: all zeros is a valid pattern
+    let mut cpuset: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    // SAFETY: FFI call, trivially safe
+    unsafe { libc::CPU_ZERO(&mut cpuset) };
+    for host_cpu in host_cpus {
+        // SAFETY: FFI call, trivially safe
+        unsafe { libc::CPU_SET(*host_cpu, &mut cpuset) };
+    }
+    cpuset
+}
+
 impl CpuManager {
     #[allow(unused_variables)]
     #[allow(clippy::too_many_arguments)]
@@ -803,11 +975,15 @@ impl CpuManager {
         #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
         let x2apic_id = cpu_id as u32;
 
+        let vm_ops: Arc<dyn VmOps> = Arc::new(VcpuVmOps {
+            vm_ops: self.vm_ops.clone(),
+            stats: self.vcpu_states[usize::from(cpu_id)].stats.clone(),
+        });
         let mut vcpu = Vcpu::new(
             cpu_id,
             x2apic_id as u8,
             &self.vm,
-            Some(self.vm_ops.clone()),
+            Some(vm_ops),
             #[cfg(target_arch = "x86_64")]
             self.hypervisor.get_cpu_vendor(),
         )?;
@@ -959,19 +1135,13 @@ impl CpuManager {
             .clone();
         let panic_vcpu_run_interrupted = vcpu_run_interrupted.clone();
         let vcpu_paused = self.vcpu_states[usize::from(vcpu_id)].paused.clone();
+        let vcpu_stats = self.vcpu_states[usize::from(vcpu_id)].stats.clone();
 
         // Prepare the CPU set the current vCPU is expected to run onto.
-        let cpuset = self.affinity.get(&vcpu_id).map(|host_cpus| {
-            // SAFETY: all zeros is a valid pattern
-            let mut cpuset: libc::cpu_set_t = unsafe { std::mem::zeroed() };
-            // SAFETY: FFI call, trivially safe
-            unsafe { libc::CPU_ZERO(&mut cpuset) };
-            for host_cpu in host_cpus {
-                // SAFETY: FFI call, trivially safe
-                unsafe { libc::CPU_SET(*host_cpu, &mut cpuset) };
-            }
-            cpuset
-        });
+        let cpuset = self
+            .affinity
+            .get(&vcpu_id)
+            .map(|host_cpus| cpuset(host_cpus));
 
         // Retrieve seccomp filter for vcpu thread
         let vcpu_seccomp_filter = get_seccomp_filter(
@@ -1101,7 +1271,14 @@ impl CpuManager {
                             #[cfg(not(feature = "tdx"))]
                             let vcpu = vcpu.lock().unwrap();
                             // vcpu.run() returns false on a triple-fault so trigger a reset
-                            match vcpu.run() {
+                            let run_start = Instant::now();
+                            let run_result = vcpu.run();
+                            let handling_start = Instant::now();
+                            vcpu_stats.account_run(
+                                handling_start.duration_since(run_start),
+                                run_result.as_ref().ok(),
+                            );
+                            match run_result {
                                 Ok(run) => match run {
                                     #[cfg(feature = "kvm")]
                                     VmExit::Debug => {
@@ -1167,6 +1344,7 @@ impl CpuManager {
                                     break;
                                 }
                             }
+                            vcpu_stats.account_exit_handling(handling_start.elapsed());
 
                             // We've been told to terminate
                             if vcpu_kill_signalled.load(Ordering::SeqCst)
@@ -1176,6 +1354,14 @@ impl CpuManager {
                                 break;
                             }
                         }
+
+                        trace_counters!(
+                            format!("vcpu{vcpu_id}"),
+                            vcpu_stats
+                                .counters()
+                                .into_iter()
+                                .map(|(name, value)| (name, value.0))
+                        );
                     })
                     .or_else(|_| {
                         panic_vcpu_run_interrupted.store(true, Ordering::SeqCst);
@@ -1320,6 +1506,52 @@ impl CpuManager {
         }
     }
 
+    /// Move the running vCPUs onto the host CPUs given by `affinity`. vCPUs
+    /// without any affinity can run on any host CPU the VMM can run on.
+    pub fn set_affinity(&mut self, affinity: Option<&[CpuAffinity]>) -> Result<()> {
+        let host_cpus_per_vcpu: BTreeMap<u8, Vec<usize>> = affinity
+            .into_iter()
+            .flatten()
+            .map(|a| (a.vcpu, a.host_cpus.clone()))
+            .collect();
+
+        // SAFETY: all zeros is a valid pattern
+        let mut default_cpuset: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        // SAFETY: FFI call with correct arguments
+        let ret = unsafe {
+            libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut default_cpuset)
+        };
+        if ret != 0 {
+            return Err(Error::SetAffinity(io::Error::last_os_error()));
+        }
+
+        for (vcpu_id, state) in self.vcpu_states.iter().enumerate() {
+            let Some(handle) = state.handle.as_ref() else {
+                continue;
+            };
+            let cpuset = host_cpus_per_vcpu
+                .get(&(vcpu_id as u8))
+                .map_or(default_cpuset, |host_cpus| cpuset(host_cpus));
+            // SAFETY: FFI call with a running thread and a valid CPU set
+            let ret = unsafe {
+                libc::pthread_setaffinity_np(
+                    handle.as_pthread_t() as _,
+                    size_of::<libc::cpu_set_t>(),
+                    &cpuset,
+                )
+            };
+            if ret != 0 {
+                return Err(Error::SetAffinity(io::Error::from_raw_os_error(ret)));
+            }
+        }
+
+        // vCPUs hotplugged later on are placed according to the new affinity.
+        self.affinity = host_cpus_per_vcpu;
+        self.config.affinity = affinity.map(<[CpuAffinity]>::to_vec);
+
+        Ok(())
+    }
+
     pub fn shutdown(&mut self) -> Result<()> {
         // Tell the vCPUs to stop themselves next time they go through the loop
         self.vcpus_kill_signalled.store(true, Ordering::SeqCst);
@@ -1379,6 +1611,25 @@ impl CpuManager {
             .fold(0, |acc, state| acc + state.active() as u8)
     }
 
+    /// Exit and run-time counters of the present vCPUs, indexed by `vcpu<id>`.
+    pub fn counters(&self) -> HashMap<String, HashMap<&'static str, Wrapping<u64>>> {
+        self.vcpu_stats()
+            .into_iter()
+            .map(|(id, stats)| (id, stats.counters()))
+            .collect()
+    }
+
+    /// Handles on the statistics of the present vCPUs, indexed by `vcpu<id>`,
+    /// which can be read without locking the CPU manager.
+    pub fn vcpu_stats(&self) -> Vec<(String, Arc<VcpuStats>)> {
+        self.vcpu_states
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| state.active())
+            .map(|(id, state)| (format!("vcpu{id}"), Arc::clone(&state.stats)))
+            .collect()
+    }
+
     #[cfg(target_arch = "aarch64")]
     pub fn get_mpidrs(&self) -> Vec<u64> {
         self.vcpus
@@ -62,12 +62,12 @@ use devices::pvmemcontrol::{PvmemcontrolBusDevice, PvmemcontrolPciDevice};
 use devices::{interrupt_controller, AcpiNotificationFlags};
 use hypervisor::IoEventAddress;
 use libc::{
-    tcsetattr, termios, MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED, O_TMPFILE, PROT_READ, PROT_WRITE,
-    TCSANOW,
+    tcsetattr, termios, MAP_ANONYMOUS, MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED, O_TMPFILE,
+    PROT_NONE, PROT_READ, PROT_WRITE, TCSANOW,
 };
 use pci::{
     DeviceRelocation, MmioRegion, PciBarRegionType, PciBdf, PciDevice, VfioDmaMapping,
-    VfioPciDevice, VfioUserDmaMapping, VfioUserPciDevice, VfioUserPciDeviceError,
+    VfioPciDevice, VfioUserClient, VfioUserDmaMapping, VfioUserPciDevice, VfioUserPciDeviceError,
 };
 use rate_limiter::group::RateLimiterGroup;
 use seccompiler::SeccompAction;
@@ -77,15 +77,17 @@ use vfio_ioctls::{VfioContainer, VfioDevice, VfioDeviceFd};
 use virtio_devices::transport::{VirtioPciDevice, VirtioPciDeviceActivator, VirtioTransport};
 use virtio_devices::vhost_user::VhostUserConfig;
 use virtio_devices::{
-    AccessPlatformMapping, ActivateError, Endpoint, IommuMapping, VdpaDmaMapping,
-    VirtioMemMappingSource,
+    AccessPlatformMapping, ActivateError, CountersReader, Endpoint, IommuMapping,
+    RateLimiterConfig, ReservedRegion, ReservedRegionType, VdpaDmaMapping, VirtioMemMappingSource,
+    VirtioSharedMemory, VirtioSharedMemoryList,
 };
 use vm_allocator::{AddressAllocator, SystemAllocator};
 use vm_device::dma_mapping::ExternalDmaMapping;
 use vm_device::interrupt::{
     InterruptIndex, InterruptManager, LegacyIrqGroupConfig, MsiIrqGroupConfig,
 };
-use vm_device::{Bus, BusDevice, BusDeviceSync, Resource};
+use vm_device::{Bus, BusAccessCounters, BusDevice, BusDeviceSync, Resource};
+use vm_memory::bitmap::AtomicBitmap;
 use vm_memory::guest_memory::FileOffset;
 use vm_memory::{Address, GuestAddress, GuestMemoryRegion, GuestUsize, MmapRegion};
 #[cfg(target_arch = "x86_64")]
@@ -106,9 +108,9 @@ use crate::memory_manager::{Error as MemoryManagerError, MemoryManager, MEMORY_M
 use crate::pci_segment::PciSegment;
 use crate::serial_manager::{Error as SerialManagerError, SerialManager};
 use crate::vm_config::{
-    ConsoleOutputMode, DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, UserDeviceConfig,
-    VdpaConfig, VhostMode, VmConfig, VsockConfig, DEFAULT_IOMMU_ADDRESS_WIDTH_BITS,
-    DEFAULT_PCI_SEGMENT_APERTURE_WEIGHT,
+    ConsoleOutputMode, DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, ShareConfig,
+    UserDeviceConfig, VdpaConfig, VhostMode, VmConfig, VsockConfig,
+    DEFAULT_IOMMU_ADDRESS_WIDTH_BITS, DEFAULT_PCI_SEGMENT_APERTURE_WEIGHT,
 };
 use crate::{device_node, GuestRegionMmap, PciDeviceInfo, DEVICE_MANAGER_SNAPSHOT_ID};
 
@@ -137,6 +139,7 @@ const DISK_DEVICE_NAME_PREFIX: &str = "_disk";
 const FS_DEVICE_NAME_PREFIX: &str = "_fs";
 const NET_DEVICE_NAME_PREFIX: &str = "_net";
 const PMEM_DEVICE_NAME_PREFIX: &str = "_pmem";
+const SHARE_DEVICE_NAME_PREFIX: &str = "_share";
 const VDPA_DEVICE_NAME_PREFIX: &str = "_vdpa";
 const VSOCK_DEVICE_NAME_PREFIX: &str = "_vsock";
 const WATCHDOG_DEVICE_NAME: &str = "__watchdog";
@@ -162,6 +165,9 @@ pub enum DeviceManagerError {
     /// Cannot create virtio-net device
     CreateVirtioNet(virtio_devices::net::Error),
 
+    /// Invalid host-side IP configuration for virtio-net device
+    InvalidNetIpConfig(net_util::IpNetworkError),
+
     /// Cannot create virtio-console device
     CreateVirtioConsole(io::Error),
 
@@ -180,6 +186,9 @@ pub enum DeviceManagerError {
     /// Cannot create virtio-pmem device
     CreateVirtioPmem(io::Error),
 
+    /// Cannot create virtio-9p device
+    CreateVirtioFs9p(io::Error),
+
     /// Cannot create vDPA device
     CreateVdpa(virtio_devices::vdpa::Error),
 
@@ -398,12 +407,25 @@ pub enum DeviceManagerError {
     /// Failed to update guest memory for VFIO PCI device.
     UpdateMemoryForVfioPciDevice(vfio_ioctls::VfioError),
 
+    /// Failed to remove guest memory from virtio device.
+    RemoveMemoryForVirtioDevice(virtio_devices::Error),
+
+    /// Failed to remove guest memory from VFIO PCI device.
+    RemoveMemoryForVfioPciDevice(vfio_ioctls::VfioError),
+
     /// Trying to use a directory for pmem but no size specified
     PmemWithDirectorySizeMissing,
 
     /// Trying to use a size that is not multiple of 2MiB
     PmemSizeNotAligned,
 
+    /// Trying to expose persistent memory through a shared memory region
+    /// whose size is not a power of 2
+    PmemShmemRegionSizeNotPowerOfTwo,
+
+    /// Trying to use a backing file smaller than the persistent memory size
+    PmemFileTooSmall,
+
     /// Could not find the node in the device tree.
     MissingNode,
 
@@ -428,6 +450,9 @@ pub enum DeviceManagerError {
     /// Missing virtio-balloon, can't proceed as expected.
     MissingVirtioBalloon,
 
+    /// Failed to request free page hints from virtio-balloon
+    VirtioBalloonFreePageHint(virtio_devices::balloon::Error),
+
     /// Missing virtual IOMMU device
     MissingVirtualIommu,
 
@@ -507,6 +532,15 @@ pub enum DeviceManagerError {
     /// Cannot create a RateLimiterGroup
     RateLimiterGroupCreate(rate_limiter::group::Error),
 
+    /// Invalid rate-limiter update
+    InvalidRateLimiterUpdate(crate::config::ValidationError),
+
+    /// Cannot update the rate-limiter of a virtio device
+    UpdateRateLimiter(virtio_devices::Error),
+
+    /// Cannot resize the disk of a virtio device
+    ResizeDisk(virtio_devices::Error),
+
     /// Cannot start sigwinch listener
     StartSigwinchListener(std::io::Error),
 
@@ -785,6 +819,125 @@ impl AccessPlatform for SevSnpPageAccessProxy {
     }
 }
 
+/// Lock-free handles on the counters of the devices, so that they can be
+/// read while the device manager is kept locked by a running job.
+#[derive(Clone)]
+pub struct DeviceCounters {
+    devices: Vec<(String, CountersReader)>,
+    iommu_mapping: Option<Arc<IommuMapping>>,
+    device_tree: Arc<DeviceTree>,
+    io_bus: Arc<Bus>,
+    mmio_bus: Arc<Bus>,
+}
+
+impl DeviceCounters {
+    #[cfg(test)]
+    pub(crate) fn from_devices(devices: Vec<(String, CountersReader)>) -> Self {
+        DeviceCounters {
+            devices,
+            iommu_mapping: None,
+            device_tree: Arc::new(DeviceTree::new()),
+            io_bus: Arc::new(Bus::new()),
+            mmio_bus: Arc::new(Bus::new()),
+        }
+    }
+
+    pub fn read(&self) -> HashMap<String, HashMap<&'static str, Wrapping<u64>>> {
+        let mut counters: HashMap<_, _> = self
+            .devices
+            .iter()
+            .map(|(id, read)| (id.clone(), read()))
+            .collect();
+
+        // Attribute the DMA faults reported by the virtual IOMMU to the
+        // devices behind each endpoint.
+        if let Some(mapping) = &self.iommu_mapping {
+            for (endpoint, faults) in mapping.dma_faults() {
+                let Some(node) = self
+                    .device_tree
+                    .iter()
+                    .find(|(_, node)| node.pci_bdf.map(u32::from) == Some(endpoint))
+                    .map(|(_, node)| node)
+                else {
+                    continue;
+                };
+                // Virtio devices are reported under their own identifier
+                // rather than the one of their PCI transport.
+                let id = node.children.first().unwrap_or(&node.id).clone();
+                counters
+                    .entry(id)
+                    .or_insert_with(HashMap::new)
+                    .insert("iommu_dma_faults", Wrapping(faults));
+            }
+        }
+
+        self.add_bus_access_counters(&mut counters);
+
+        counters
+    }
+
+    // Reports the PIO and MMIO accesses handled by each device, based on the
+    // ranges recorded in the device tree. Accesses to ranges owned by devices
+    // missing from the device tree are reported per range.
+    fn add_bus_access_counters(
+        &self,
+        counters: &mut HashMap<String, HashMap<&'static str, Wrapping<u64>>>,
+    ) {
+        fn add(
+            device_counters: &mut HashMap<&'static str, Wrapping<u64>>,
+            pio: bool,
+            access: BusAccessCounters,
+        ) {
+            let (reads, writes) = if pio {
+                ("pio_reads", "pio_writes")
+            } else {
+                ("mmio_reads", "mmio_writes")
+            };
+            *device_counters.entry(reads).or_default() += Wrapping(access.reads);
+            *device_counters.entry(writes).or_default() += Wrapping(access.writes);
+            *device_counters.entry("exit_handling_time_us").or_default() +=
+                Wrapping(access.time_ns / 1000);
+        }
+
+        let io_bus = &self.io_bus;
+        let mmio_bus = &self.mmio_bus;
+        let mut claimed: Vec<(bool, u64)> = Vec::new();
+
+        for (_, node) in self.device_tree.iter() {
+            for resource in node.resources.iter() {
+                let (pio, base) = match resource {
+                    Resource::PioAddressRange { base, .. } => (true, *base as u64),
+                    Resource::MmioAddressRange { base, .. } => (false, *base),
+                    Resource::PciBar { base, type_, .. } => (
+                        PciBarRegionType::from(*type_) == PciBarRegionType::IoRegion,
+                        *base,
+                    ),
+                    _ => continue,
+                };
+                let bus = if pio { io_bus } else { mmio_bus };
+                let Some(access) = bus.access_counters(base) else {
+                    continue;
+                };
+                claimed.push((pio, base));
+                // Virtio devices are reported under their own identifier
+                // rather than the one of their PCI transport.
+                let id = node.children.first().unwrap_or(&node.id).clone();
+                add(counters.entry(id).or_default(), pio, access);
+            }
+        }
+
+        for (pio, bus) in [(true, io_bus), (false, mmio_bus)] {
+            for (range, access) in bus.all_access_counters() {
+                if claimed.contains(&(pio, range.base)) || access.reads + access.writes == 0 {
+                    continue;
+                }
+                let id = format!("{}@{:#x}", if pio { "pio" } else { "mmio" }, range.base);
+                add(counters.entry(id).or_default(), pio, access);
+            }
+        }
+    }
+}
+
 pub struct DeviceManager {
     // Manage address space related to devices
     address_manager: Arc<AddressManager>,
@@ -1137,6 +1290,20 @@ impl DeviceManager {
                 )
                 .map_err(DeviceManagerError::RateLimiterGroupCreate)?;
 
+                // Parents are declared first, so they already exist.
+                if let Some(parent) = rate_limit_group_cfg
+                    .parent
+                    .as_ref()
+                    .and_then(|id| rate_limit_groups.get(id))
+                {
+                    rate_limit_group
+                        .set_parent(
+                            parent,
+                            rate_limit_group_cfg.share.unwrap_or_default().into(),
+                        )
+                        .map_err(DeviceManagerError::RateLimiterGroupCreate)?;
+                }
+
                 let exit_evt = exit_evt.try_clone().map_err(DeviceManagerError::EventFd)?;
 
                 rate_limit_group.start_thread(exit_evt).unwrap();
@@ -1366,11 +1533,14 @@ impl DeviceManager {
     ) -> DeviceManagerResult<()> {
         let iommu_id = String::from(IOMMU_DEVICE_NAME);
 
-        let iommu_address_width_bits =
+        let (iommu_address_width_bits, iommu_max_domains) =
             if let Some(ref platform) = self.config.lock().unwrap().platform {
-                platform.iommu_address_width_bits
+                (
+                    platform.iommu_address_width_bits,
+                    platform.iommu_max_domains,
+                )
             } else {
-                DEFAULT_IOMMU_ADDRESS_WIDTH_BITS
+                (DEFAULT_IOMMU_ADDRESS_WIDTH_BITS, None)
             };
 
         let iommu_device = if self.config.lock().unwrap().iommu {
@@ -1382,6 +1552,7 @@ impl DeviceManager {
                     .map_err(DeviceManagerError::EventFd)?,
                 self.get_msi_iova_space(),
                 iommu_address_width_bits,
+                iommu_max_domains,
                 state_from_id(self.snapshot.as_ref(), iommu_id.as_str())
                     .map_err(DeviceManagerError::RestoreGetState)?,
             )
@@ -2296,6 +2467,9 @@ impl DeviceManager {
         // Add virtio-fs if required
         devices.append(&mut self.make_virtio_fs_devices()?);
 
+        // Add virtio-9p shared directories if required
+        devices.append(&mut self.make_virtio_fs9p_devices()?);
+
         // Add virtio-pmem if required
         devices.append(&mut self.make_virtio_pmem_devices()?);
 
@@ -2525,6 +2699,7 @@ impl DeviceManager {
                     disk_cfg.serial.clone(),
                     self.seccomp_action.clone(),
                     rate_limit_group,
+                    disk_cfg.rate_limit_share.unwrap_or_default().into(),
                     self.exit_evt
                         .try_clone()
                         .map_err(DeviceManagerError::EventFd)?,
@@ -2628,13 +2803,24 @@ impl DeviceManager {
         } else {
             let state = state_from_id(self.snapshot.as_ref(), id.as_str())
                 .map_err(DeviceManagerError::RestoreGetState)?;
+            let ip_addrs = net_cfg
+                .ip_addrs()
+                .map_err(DeviceManagerError::InvalidNetIpConfig)?;
+            let rx_rate_limit_group = net_cfg
+                .rx_rate_limit_group
+                .as_ref()
+                .and_then(|id| self.rate_limit_groups.get(id).cloned());
+            let tx_rate_limit_group = net_cfg
+                .tx_rate_limit_group
+                .as_ref()
+                .and_then(|id| self.rate_limit_groups.get(id).cloned());
+            let rate_limit_share = net_cfg.rate_limit_share.unwrap_or_default().into();
             let virtio_net = if let Some(ref tap_if_name) = net_cfg.tap {
                 Arc::new(Mutex::new(
                     virtio_devices::Net::new(
                         id.clone(),
                         Some(tap_if_name),
-                        Some(net_cfg.ip),
-                        Some(net_cfg.mask),
+                        &ip_addrs,
                         Some(net_cfg.mac),
                         &mut net_cfg.host_mac,
                         net_cfg.mtu,
@@ -2643,6 +2829,9 @@ impl DeviceManager {
                         net_cfg.queue_size,
                         self.seccomp_action.clone(),
                         net_cfg.rate_limiter_config,
+                        rx_rate_limit_group,
+                        tx_rate_limit_group,
+                        rate_limit_share,
                         self.exit_evt
                             .try_clone()
                             .map_err(DeviceManagerError::EventFd)?,
@@ -2663,6 +2852,9 @@ impl DeviceManager {
                     net_cfg.queue_size,
                     self.seccomp_action.clone(),
                     net_cfg.rate_limiter_config,
+                    rx_rate_limit_group,
+                    tx_rate_limit_group,
+                    rate_limit_share,
                     self.exit_evt
                         .try_clone()
                         .map_err(DeviceManagerError::EventFd)?,
@@ -2684,8 +2876,7 @@ impl DeviceManager {
                     virtio_devices::Net::new(
                         id.clone(),
                         None,
-                        Some(net_cfg.ip),
-                        Some(net_cfg.mask),
+                        &ip_addrs,
                         Some(net_cfg.mac),
                         &mut net_cfg.host_mac,
                         net_cfg.mtu,
@@ -2694,6 +2885,9 @@ impl DeviceManager {
                         net_cfg.queue_size,
                         self.seccomp_action.clone(),
                         net_cfg.rate_limiter_config,
+                        rx_rate_limit_group,
+                        tx_rate_limit_group,
+                        rate_limit_share,
                         self.exit_evt
                             .try_clone()
                             .map_err(DeviceManagerError::EventFd)?,
@@ -2756,6 +2950,7 @@ impl DeviceManager {
                 virtio_devices::Rng::new(
                     id.clone(),
                     rng_path,
+                    rng_config.rate_limiter_config,
                     self.force_iommu | rng_config.iommu,
                     self.seccomp_action.clone(),
                     self.exit_evt
@@ -2804,6 +2999,12 @@ impl DeviceManager {
         let mut node = device_node!(id);
 
         if let Some(fs_socket) = fs_cfg.socket.to_str() {
+            let cache = if let Some(cache_size) = fs_cfg.cache_size {
+                Some(self.make_virtio_fs_cache(&id, &mut node, cache_size, fs_cfg.pci_segment)?)
+            } else {
+                None
+            };
+
             let virtio_fs_device = Arc::new(Mutex::new(
                 virtio_devices::vhost_user::Fs::new(
                     id.clone(),
@@ -2811,7 +3012,7 @@ impl DeviceManager {
                     &fs_cfg.tag,
                     fs_cfg.num_queues,
                     fs_cfg.queue_size,
-                    None,
+                    cache,
                     self.seccomp_action.clone(),
                     self.exit_evt
                         .try_clone()
@@ -2840,6 +3041,76 @@ impl DeviceManager {
         }
     }
 
+    // Reserve the DAX cache window of a virtio-fs device. The whole window is
+    // mapped into the guest but left inaccessible until the backend asks for
+    // file ranges to be mapped into it.
+    fn make_virtio_fs_cache(
+        &mut self,
+        id: &str,
+        node: &mut DeviceNode,
+        cache_size: u64,
+        pci_segment: u16,
+    ) -> DeviceManagerResult<(VirtioSharedMemoryList, MmapRegion<AtomicBitmap>)> {
+        let mut cache_base = None;
+        if let Some(restored_node) = self.device_tree.lock().unwrap().get(id) {
+            for resource in restored_node.resources.iter() {
+                if let Resource::MmioAddressRange { base, .. } = resource {
+                    cache_base = Some(GuestAddress(*base));
+                }
+            }
+        }
+
+        // A PCI BAR must be sized and aligned to a power of 2.
+        let cache_addr = self.pci_segments[pci_segment as usize]
+            .mem64_allocator
+            .lock()
+            .unwrap()
+            .allocate(cache_base, cache_size as GuestUsize, Some(cache_size))
+            .ok_or(DeviceManagerError::FsRangeAllocation)?;
+
+        let mmap_region = MmapRegion::build(
+            None,
+            cache_size as usize,
+            PROT_NONE,
+            MAP_ANONYMOUS | MAP_PRIVATE | MAP_NORESERVE,
+        )
+        .map_err(DeviceManagerError::NewMmapRegion)?;
+        let host_addr: u64 = mmap_region.as_ptr() as u64;
+
+        let mem_slot = self
+            .memory_manager
+            .lock()
+            .unwrap()
+            .create_userspace_mapping(
+                cache_addr.raw_value(),
+                cache_size,
+                host_addr,
+                false,
+                false,
+                false,
+            )
+            .map_err(DeviceManagerError::MemoryManager)?;
+
+        node.resources.push(Resource::MmioAddressRange {
+            base: cache_addr.raw_value(),
+            size: cache_size,
+        });
+
+        Ok((
+            VirtioSharedMemoryList {
+                host_addr,
+                mem_slot,
+                addr: cache_addr,
+                len: cache_size as GuestUsize,
+                region_list: vec![VirtioSharedMemory {
+                    offset: 0,
+                    len: cache_size,
+                }],
+            },
+            mmap_region,
+        ))
+    }
+
     fn make_virtio_fs_devices(&mut self) -> DeviceManagerResult<Vec<MetaVirtioDevice>> {
         let mut devices = Vec::new();
 
@@ -2854,6 +3125,69 @@ impl DeviceManager {
         Ok(devices)
     }
 
+    fn make_virtio_fs9p_device(
+        &mut self,
+        share_cfg: &mut ShareConfig,
+    ) -> DeviceManagerResult<MetaVirtioDevice> {
+        let id = if let Some(id) = &share_cfg.id {
+            id.clone()
+        } else {
+            let id = self.next_device_name(SHARE_DEVICE_NAME_PREFIX)?;
+            share_cfg.id = Some(id.clone());
+            id
+        };
+
+        info!("Creating virtio-9p device: {:?}", share_cfg);
+
+        let virtio_fs9p_device = Arc::new(Mutex::new(
+            virtio_devices::Fs9p::new(
+                id.clone(),
+                &share_cfg.path,
+                &share_cfg.tag,
+                share_cfg.readonly,
+                self.force_iommu | share_cfg.iommu,
+                self.seccomp_action.clone(),
+                self.exit_evt
+                    .try_clone()
+                    .map_err(DeviceManagerError::EventFd)?,
+                state_from_id(self.snapshot.as_ref(), id.as_str())
+                    .map_err(DeviceManagerError::RestoreGetState)?,
+            )
+            .map_err(DeviceManagerError::CreateVirtioFs9p)?,
+        ));
+
+        // Fill the device tree with a new node. In case of restore, we
+        // know there is nothing to do, so we can simply override the
+        // existing entry.
+        self.device_tree
+            .lock()
+            .unwrap()
+            .insert(id.clone(), device_node!(id, virtio_fs9p_device));
+
+        Ok(MetaVirtioDevice {
+            virtio_device: Arc::clone(&virtio_fs9p_device)
+                as Arc<Mutex<dyn virtio_devices::VirtioDevice>>,
+            iommu: share_cfg.iommu,
+            id,
+            pci_segment: share_cfg.pci_segment,
+            dma_handler: None,
+        })
+    }
+
+    fn make_virtio_fs9p_devices(&mut self) -> DeviceManagerResult<Vec<MetaVirtioDevice>> {
+        let mut devices = Vec::new();
+
+        let mut share_devices = self.config.lock().unwrap().shares.clone();
+        if let Some(share_list_cfg) = &mut share_devices {
+            for share_cfg in share_list_cfg.iter_mut() {
+                devices.push(self.make_virtio_fs9p_device(share_cfg)?);
+            }
+        }
+        self.config.lock().unwrap().shares = share_devices;
+
+        Ok(devices)
+    }
+
     fn make_virtio_pmem_device(
         &mut self,
         pmem_cfg: &mut PmemConfig,
@@ -2900,40 +3234,24 @@ impl DeviceManager {
             None
         };
 
-        let (custom_flags, set_len) = if pmem_cfg.file.is_dir() {
-            if pmem_cfg.size.is_none() {
-                return Err(DeviceManagerError::PmemWithDirectorySizeMissing);
-            }
-            (O_TMPFILE, true)
-        } else {
-            (0, false)
-        };
+        let (file, size) = open_pmem_file(pmem_cfg)?;
+
+        if size % 0x20_0000 != 0 {
+            return Err(DeviceManagerError::PmemSizeNotAligned);
+        }
 
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(!pmem_cfg.discard_writes)
-            .custom_flags(custom_flags)
-            .open(&pmem_cfg.file)
-            .map_err(DeviceManagerError::PmemFileOpen)?;
-
-        let size = if let Some(size) = pmem_cfg.size {
-            if set_len {
-                file.set_len(size)
-                    .map_err(DeviceManagerError::PmemFileSetLen)?;
+        // A PCI BAR must be sized and aligned to a power of 2, otherwise the
+        // memory needs to be 2MiB aligned in order to support hugepages.
+        let alignment = if pmem_cfg.shmem_region {
+            if !size.is_power_of_two() {
+                return Err(DeviceManagerError::PmemShmemRegionSizeNotPowerOfTwo);
             }
             size
         } else {
-            file.seek(SeekFrom::End(0))
-                .map_err(DeviceManagerError::PmemFileSetLen)?
+            0x0020_0000
         };
 
-        if size % 0x20_0000 != 0 {
-            return Err(DeviceManagerError::PmemSizeNotAligned);
-        }
-
         let (region_base, region_size) = if let Some((base, size)) = region_range {
-            // The memory needs to be 2MiB aligned in order to support
-            // hugepages.
             self.pci_segments[pmem_cfg.pci_segment as usize]
                 .mem64_allocator
                 .lock()
@@ -2941,37 +3259,23 @@ impl DeviceManager {
                 .allocate(
                     Some(GuestAddress(base)),
                     size as GuestUsize,
-                    Some(0x0020_0000),
+                    Some(alignment),
                 )
                 .ok_or(DeviceManagerError::PmemRangeAllocation)?;
 
             (base, size)
         } else {
-            // The memory needs to be 2MiB aligned in order to support
-            // hugepages.
             let base = self.pci_segments[pmem_cfg.pci_segment as usize]
                 .mem64_allocator
                 .lock()
                 .unwrap()
-                .allocate(None, size as GuestUsize, Some(0x0020_0000))
+                .allocate(None, size as GuestUsize, Some(alignment))
                 .ok_or(DeviceManagerError::PmemRangeAllocation)?;
 
             (base.raw_value(), size)
         };
 
-        let cloned_file = file.try_clone().map_err(DeviceManagerError::CloneFile)?;
-        let mmap_region = MmapRegion::build(
-            Some(FileOffset::new(cloned_file, 0)),
-            region_size as usize,
-            PROT_READ | PROT_WRITE,
-            MAP_NORESERVE
-                | if pmem_cfg.discard_writes {
-                    MAP_PRIVATE
-                } else {
-                    MAP_SHARED
-                },
-        )
-        .map_err(DeviceManagerError::NewMmapRegion)?;
+        let mmap_region = map_pmem_file(&file, region_size, pmem_cfg.discard_writes)?;
         let host_addr: u64 = mmap_region.as_ptr() as u64;
 
         let mem_slot = self
@@ -2996,6 +3300,8 @@ impl DeviceManager {
                 GuestAddress(region_base),
                 mapping,
                 mmap_region,
+                pmem_cfg.discard_writes,
+                pmem_cfg.shmem_region,
                 self.force_iommu | pmem_cfg.iommu,
                 self.seccomp_action.clone(),
                 self.exit_evt
@@ -3058,9 +3364,12 @@ impl DeviceManager {
             .socket
             .to_str()
             .ok_or(DeviceManagerError::CreateVsockConvertPath)?;
-        let backend =
-            virtio_devices::vsock::VsockUnixBackend::new(vsock_cfg.cid, socket_path.to_string())
-                .map_err(DeviceManagerError::CreateVsockBackend)?;
+        let backend = virtio_devices::vsock::VsockUnixBackend::new(
+            vsock_cfg.cid,
+            socket_path.to_string(),
+            vsock_cfg.seqpacket,
+        )
+        .map_err(DeviceManagerError::CreateVsockBackend)?;
 
         let vsock_device = Arc::new(Mutex::new(
             virtio_devices::Vsock::new(
@@ -3069,6 +3378,7 @@ impl DeviceManager {
                 vsock_cfg.socket.clone(),
                 backend,
                 self.force_iommu | vsock_cfg.iommu,
+                vsock_cfg.seqpacket,
                 self.seccomp_action.clone(),
                 self.exit_evt
                     .try_clone()
@@ -3223,6 +3533,8 @@ impl DeviceManager {
                     balloon_config.size,
                     balloon_config.deflate_on_oom,
                     balloon_config.free_page_reporting,
+                    balloon_config.statistics_polling_interval,
+                    balloon_config.free_page_hinting,
                     self.seccomp_action.clone(),
                     self.exit_evt
                         .try_clone()
@@ -3449,10 +3761,14 @@ impl DeviceManager {
             ));
 
             if let Some(iommu) = &self.iommu_device {
-                iommu
-                    .lock()
-                    .unwrap()
-                    .add_external_mapping(pci_device_bdf.into(), vfio_mapping);
+                let mut iommu = iommu.lock().unwrap();
+                iommu.add_external_mapping(pci_device_bdf.into(), vfio_mapping);
+
+                // Let the guest know about the IOVA ranges the host IOMMU
+                // can't remap for this device.
+                for region in vfio_reserved_regions(&device_cfg.path) {
+                    iommu.add_reserved_region(pci_device_bdf.into(), region);
+                }
             } else {
                 return Err(DeviceManagerError::MissingVirtualIommu);
             }
@@ -3682,7 +3998,7 @@ impl DeviceManager {
             };
 
         let client = Arc::new(Mutex::new(
-            vfio_user::Client::new(&device_cfg.socket)
+            VfioUserClient::new(&device_cfg.socket)
                 .map_err(DeviceManagerError::VfioUserCreateClient)?,
         ));
 
@@ -3692,6 +4008,7 @@ impl DeviceManager {
             vfio_user_name.clone(),
             &self.address_manager.vm,
             client.clone(),
+            device_cfg.migratable,
             self.msi_interrupt_manager.clone(),
             legacy_interrupt_group,
             pci_device_bdf,
@@ -4065,6 +4382,54 @@ impl DeviceManager {
         Ok(())
     }
 
+    pub fn remove_memory(&self, old_region: &Arc<GuestRegionMmap>) -> DeviceManagerResult<()> {
+        for handle in self.virtio_devices.iter() {
+            handle
+                .virtio_device
+                .lock()
+                .unwrap()
+                .remove_memory_region(old_region)
+                .map_err(DeviceManagerError::RemoveMemoryForVirtioDevice)?;
+
+            if let Some(dma_handler) = &handle.dma_handler {
+                if !handle.iommu {
+                    let gpa = old_region.start_addr().0;
+                    let size = old_region.len();
+                    dma_handler
+                        .unmap(gpa, size)
+                        .map_err(DeviceManagerError::VirtioDmaUnmap)?;
+                }
+            }
+        }
+
+        // Take care of removing the memory from VFIO PCI devices.
+        if let Some(vfio_container) = &self.vfio_container {
+            vfio_container
+                .vfio_dma_unmap(old_region.start_addr().raw_value(), old_region.len())
+                .map_err(DeviceManagerError::RemoveMemoryForVfioPciDevice)?;
+        }
+
+        // Take care of removing the memory from vfio-user devices.
+        {
+            let device_tree = self.device_tree.lock().unwrap();
+            for pci_device_node in device_tree.pci_devices() {
+                if let PciDeviceHandle::VfioUser(vfio_user_pci_device) = pci_device_node
+                    .pci_device_handle
+                    .as_ref()
+                    .ok_or(DeviceManagerError::MissingPciDevice)?
+                {
+                    vfio_user_pci_device
+                        .lock()
+                        .unwrap()
+                        .dma_unmap(old_region)
+                        .map_err(DeviceManagerError::VfioUserDmaUnmap)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn activate_virtio_devices(&self) -> DeviceManagerResult<()> {
         for mut activator in self.pending_activations.lock().unwrap().drain(..) {
             activator
@@ -4247,6 +4612,15 @@ impl DeviceManager {
                         .retain(|x| x.start != mmio_region.start)
                 }
 
+                // The ranges reserved by the host IOMMU belong to this
+                // device, not to the next one plugged at the same BDF.
+                if let Some(iommu) = &self.iommu_device {
+                    iommu
+                        .lock()
+                        .unwrap()
+                        .remove_reserved_regions(pci_device_bdf.into());
+                }
+
                 (
                     Arc::clone(&vfio_pci_device) as Arc<Mutex<dyn PciDevice>>,
                     Arc::clone(&vfio_pci_device) as Arc<dyn BusDeviceSync>,
@@ -4359,10 +4733,10 @@ impl DeviceManager {
 
         // Shutdown and remove the underlying virtio-device if present
         if let Some(virtio_device) = virtio_device {
+            let shm_regions = virtio_device.lock().unwrap().get_shm_regions();
             for mapping in virtio_device.lock().unwrap().userspace_mappings() {
-                self.memory_manager
-                    .lock()
-                    .unwrap()
+                let mut memory_manager = self.memory_manager.lock().unwrap();
+                memory_manager
                     .remove_userspace_mapping(
                         mapping.addr.raw_value(),
                         mapping.len,
@@ -4371,6 +4745,22 @@ impl DeviceManager {
                         mapping.mem_slot,
                     )
                     .map_err(DeviceManagerError::MemoryManager)?;
+                memory_manager
+                    .memory_slot_allocator()
+                    .free_memory_slot(mapping.mem_slot);
+
+                // Release the guest address range, unless it was exposed
+                // through the shared memory BAR which is already freed.
+                if shm_regions
+                    .as_ref()
+                    .is_none_or(|shm_regions| shm_regions.addr != mapping.addr)
+                {
+                    self.pci_segments[pci_segment_id as usize]
+                        .mem64_allocator
+                        .lock()
+                        .unwrap()
+                        .free(mapping.addr, mapping.len);
+                }
             }
 
             virtio_device.lock().unwrap().shutdown();
@@ -4502,17 +4892,98 @@ impl DeviceManager {
         self.hotplug_virtio_pci_device(device)
     }
 
+    /// Handles on the counters of the devices, which can be read later on
+    /// without locking the device manager.
+    pub fn device_counters(&self) -> DeviceCounters {
+        DeviceCounters {
+            devices: self
+                .virtio_devices
+                .iter()
+                .filter_map(|handle| {
+                    let reader = handle.virtio_device.lock().unwrap().counters_reader()?;
+                    Some((handle.id.clone(), reader))
+                })
+                .collect(),
+            iommu_mapping: self.iommu_mapping.clone(),
+            device_tree: Arc::new(self.device_tree.lock().unwrap().clone()),
+            io_bus: Arc::clone(&self.address_manager.io_bus),
+            mmio_bus: Arc::clone(&self.address_manager.mmio_bus),
+        }
+    }
+
     pub fn counters(&self) -> HashMap<String, HashMap<&'static str, Wrapping<u64>>> {
-        let mut counters = HashMap::new();
+        self.device_counters().read()
+    }
 
-        for handle in &self.virtio_devices {
-            let virtio_device = handle.virtio_device.lock().unwrap();
-            if let Some(device_counters) = virtio_device.counters() {
-                counters.insert(handle.id.clone(), device_counters.clone());
-            }
+    /// Check that `update_rate_limiter()` can apply `rate_limiter_config`
+    /// to the device or group `id`.
+    pub fn validate_rate_limiter_update(
+        &self,
+        id: &str,
+        rate_limiter_config: &RateLimiterConfig,
+    ) -> DeviceManagerResult<()> {
+        self.config
+            .lock()
+            .unwrap()
+            .validate_rate_limiter_update(id, rate_limiter_config)
+            .map_err(DeviceManagerError::InvalidRateLimiterUpdate)?;
+
+        if !self.rate_limit_groups.contains_key(id)
+            && !self.virtio_devices.iter().any(|handle| handle.id == id)
+        {
+            return Err(DeviceManagerError::UnknownDeviceId(id.to_owned()));
         }
 
-        counters
+        Ok(())
+    }
+
+    pub fn update_rate_limiter(
+        &mut self,
+        id: &str,
+        rate_limiter_config: RateLimiterConfig,
+    ) -> DeviceManagerResult<()> {
+        self.validate_rate_limiter_update(id, &rate_limiter_config)?;
+
+        if let Some(rate_limit_group) = self.rate_limit_groups.get(id) {
+            let (bytes, ops) = rate_limiter_config.bucket_updates();
+            rate_limit_group.update_buckets(bytes, ops);
+        } else {
+            let handle = self
+                .virtio_devices
+                .iter()
+                .find(|handle| handle.id == id)
+                .ok_or_else(|| DeviceManagerError::UnknownDeviceId(id.to_owned()))?;
+            handle
+                .virtio_device
+                .lock()
+                .unwrap()
+                .update_rate_limiter(rate_limiter_config)
+                .map_err(DeviceManagerError::UpdateRateLimiter)?;
+        }
+
+        // Persist the new values so they survive snapshot/restore and
+        // live migration.
+        self.config
+            .lock()
+            .unwrap()
+            .update_rate_limiter(id, rate_limiter_config)
+            .map_err(DeviceManagerError::InvalidRateLimiterUpdate)
+    }
+
+    pub fn resize_disk(&mut self, id: &str, size: u64) -> DeviceManagerResult<()> {
+        let handle = self
+            .virtio_devices
+            .iter()
+            .find(|handle| handle.id == id)
+            .ok_or_else(|| DeviceManagerError::UnknownDeviceId(id.to_owned()))?;
+        // The new size is part of the device state, it is carried over by
+        // snapshot/restore and live migration.
+        handle
+            .virtio_device
+            .lock()
+            .unwrap()
+            .resize_disk(size)
+            .map_err(DeviceManagerError::ResizeDisk)
     }
 
     pub fn resize_balloon(&mut self, size: u64) -> DeviceManagerResult<()> {
@@ -4528,6 +4999,10 @@ impl DeviceManager {
         Err(DeviceManagerError::MissingVirtioBalloon)
     }
 
+    pub fn balloon(&self) -> Option<Arc<Mutex<virtio_devices::Balloon>>> {
+        self.balloon.clone()
+    }
+
     pub fn balloon_size(&self) -> u64 {
         if let Some(balloon) = &self.balloon {
             return balloon.lock().unwrap().get_actual();
@@ -4536,6 +5011,42 @@ impl DeviceManager {
         0
     }
 
+    pub fn balloon_statistics(&self) -> DeviceManagerResult<virtio_devices::BalloonStatistics> {
+        if let Some(balloon) = &self.balloon {
+            return Ok(balloon.lock().unwrap().statistics());
+        }
+
+        Err(DeviceManagerError::MissingVirtioBalloon)
+    }
+
+    // Returns false if there is no balloon or if the guest does not support
+    // free page hinting.
+    pub fn start_free_page_hinting(
+        &self,
+    ) -> DeviceManagerResult<Option<virtio_devices::FreePageHintsWaiter>> {
+        if let Some(balloon) = &self.balloon {
+            return balloon
+                .lock()
+                .unwrap()
+                .start_free_page_hinting()
+                .map_err(DeviceManagerError::VirtioBalloonFreePageHint);
+        }
+
+        Ok(None)
+    }
+
+    pub fn stop_free_page_hinting(&self) -> DeviceManagerResult<()> {
+        if let Some(balloon) = &self.balloon {
+            return balloon
+                .lock()
+                .unwrap()
+                .stop_free_page_hinting()
+                .map_err(DeviceManagerError::VirtioBalloonFreePageHint);
+        }
+
+        Ok(())
+    }
+
     pub fn device_tree(&self) -> Arc<Mutex<DeviceTree>> {
         self.device_tree.clone()
     }
@@ -4600,6 +5111,106 @@ impl DeviceManager {
     }
 }
 
+// Open the backing file of a persistent memory device and figure out the
+// size of the device.
+fn open_pmem_file(pmem_cfg: &PmemConfig) -> DeviceManagerResult<(File, u64)> {
+    let (custom_flags, tmpfile) = if pmem_cfg.file.is_dir() {
+        if pmem_cfg.size.is_none() {
+            return Err(DeviceManagerError::PmemWithDirectorySizeMissing);
+        }
+        (O_TMPFILE, true)
+    } else {
+        (0, false)
+    };
+
+    // A temporary file can only be created writable. It is private to this
+    // VM anyway, and guest writes are still discarded through the private
+    // mapping if requested.
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(tmpfile || !pmem_cfg.discard_writes)
+        .custom_flags(custom_flags)
+        .open(&pmem_cfg.file)
+        .map_err(DeviceManagerError::PmemFileOpen)?;
+
+    let file_size = file
+        .seek(SeekFrom::End(0))
+        .map_err(DeviceManagerError::PmemFileSetLen)?;
+    let size = pmem_cfg.size.unwrap_or(file_size);
+
+    if tmpfile {
+        // Growing the file only creates a hole, so that the backing file
+        // stays sparse until the guest actually writes to it.
+        file.set_len(size)
+            .map_err(DeviceManagerError::PmemFileSetLen)?;
+    } else if size > file_size {
+        // Files provided by the user are never modified behind their back,
+        // and mapping past the end of the file would fault on guest access.
+        return Err(DeviceManagerError::PmemFileTooSmall);
+    }
+
+    Ok((file, size))
+}
+
+// Map the backing file of a persistent memory device. Guest writes go to
+// private copy-on-write pages when they must be discarded.
+fn map_pmem_file(
+    file: &File,
+    size: u64,
+    discard_writes: bool,
+) -> DeviceManagerResult<MmapRegion<AtomicBitmap>> {
+    let cloned_file = file.try_clone().map_err(DeviceManagerError::CloneFile)?;
+    MmapRegion::build(
+        Some(FileOffset::new(cloned_file, 0)),
+        size as usize,
+        PROT_READ | PROT_WRITE,
+        MAP_NORESERVE
+            | if discard_writes {
+                MAP_PRIVATE
+            } else {
+                MAP_SHARED
+            },
+    )
+    .map_err(DeviceManagerError::NewMmapRegion)
+}
+
+// Retrieve the IOVA ranges reserved by the host IOMMU for the IOMMU group of
+// a VFIO device, as exposed through sysfs.
+fn vfio_reserved_regions(device_path: &std::path::Path) -> Vec<ReservedRegion> {
+    let path = device_path.join("iommu_group/reserved_regions");
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("Could not read reserved regions from {:?}: {}", path, e);
+            return Vec::new();
+        }
+    };
+
+    let mut regions = Vec::new();
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [start, end, type_] = fields[..] else {
+            warn!("Invalid reserved region: {}", line);
+            continue;
+        };
+        let (Ok(start), Ok(end)) = (
+            u64::from_str_radix(start.trim_start_matches("0x"), 16),
+            u64::from_str_radix(end.trim_start_matches("0x"), 16),
+        ) else {
+            warn!("Invalid reserved region: {}", line);
+            continue;
+        };
+        let type_ = if type_ == "msi" {
+            ReservedRegionType::Msi
+        } else {
+            ReservedRegionType::Reserved
+        };
+        regions.push(ReservedRegion { start, end, type_ });
+    }
+
+    regions
+}
+
 fn numa_node_id_from_memory_zone_id(numa_nodes: &NumaNodes, memory_zone_id: &str) -> Option<u32> {
     for (numa_node_id, numa_node) in numa_nodes.iter() {
         if numa_node.memory_zones.contains(&memory_zone_id.to_owned()) {
@@ -5047,6 +5658,12 @@ impl Drop for DeviceManager {
 
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
+    use std::os::unix::fs::{FileExt, MetadataExt};
+
+    use vmm_sys_util::tempdir::TempDir;
+    use vmm_sys_util::tempfile::TempFile;
+
     use super::*;
 
     #[test]
@@ -5100,4 +5717,111 @@ mod tests {
             vm_memory::GuestAddress(0x3fffff)
         );
     }
+
+    fn pmem_config(file: &std::path::Path, size: Option<u64>, discard_writes: bool) -> PmemConfig {
+        PmemConfig {
+            file: file.to_path_buf(),
+            size,
+            iommu: false,
+            discard_writes,
+            shmem_region: false,
+            id: None,
+            pci_segment: 0,
+        }
+    }
+
+    #[test]
+    fn test_vfio_reserved_regions() {
+        let device = TempDir::new_with_prefix("/tmp/ch").unwrap();
+        assert!(vfio_reserved_regions(device.as_path()).is_empty());
+
+        let iommu_group = device.as_path().join("iommu_group");
+        std::fs::create_dir(&iommu_group).unwrap();
+        std::fs::write(
+            iommu_group.join("reserved_regions"),
+            "0x00000000fee00000 0x00000000feefffff msi\n\
+             0x0000000008000000 0x00000000080fffff direct\n\
+             invalid\n\
+             0xzz 0x00000000080fffff reserved\n",
+        )
+        .unwrap();
+        assert_eq!(
+            vfio_reserved_regions(device.as_path()),
+            vec![
+                ReservedRegion {
+                    start: 0xfee0_0000,
+                    end: 0xfeef_ffff,
+                    type_: ReservedRegionType::Msi,
+                },
+                ReservedRegion {
+                    start: 0x800_0000,
+                    end: 0x80f_ffff,
+                    type_: ReservedRegionType::Reserved,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_pmem_file() {
+        const SIZE: u64 = 4 << 20;
+
+        let file = TempFile::new().unwrap();
+        file.as_file().set_len(SIZE).unwrap();
+        let path = file.as_path();
+
+        // The size defaults to the one of the file
+        let (_, size) = open_pmem_file(&pmem_config(path, None, false)).unwrap();
+        assert_eq!(size, SIZE);
+        let (_, size) = open_pmem_file(&pmem_config(path, Some(SIZE / 2), true)).unwrap();
+        assert_eq!(size, SIZE / 2);
+
+        // A file provided by the user is never grown
+        for discard_writes in [false, true] {
+            assert!(matches!(
+                open_pmem_file(&pmem_config(path, Some(2 * SIZE), discard_writes)),
+                Err(DeviceManagerError::PmemFileTooSmall)
+            ));
+        }
+        assert_eq!(file.as_file().metadata().unwrap().len(), SIZE);
+
+        // A temporary file is created in a directory, which needs a size
+        let dir = TempDir::new().unwrap();
+        assert!(matches!(
+            open_pmem_file(&pmem_config(dir.as_path(), None, false)),
+            Err(DeviceManagerError::PmemWithDirectorySizeMissing)
+        ));
+        let (file, size) = open_pmem_file(&pmem_config(dir.as_path(), Some(SIZE), true)).unwrap();
+        assert_eq!(size, SIZE);
+        let metadata = file.metadata().unwrap();
+        assert_eq!(metadata.len(), SIZE);
+        // No block gets allocated until the guest writes to the file
+        assert_eq!(metadata.blocks(), 0);
+        assert_eq!(std::fs::read_dir(dir.as_path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_map_pmem_file_discard_writes() {
+        const SIZE: u64 = 2 << 20;
+
+        let file = TempFile::new().unwrap();
+        file.as_file().write_all(&[0xaa; SIZE as usize]).unwrap();
+        let path = file.as_path();
+
+        for discard_writes in [true, false] {
+            let (file, size) = open_pmem_file(&pmem_config(path, None, discard_writes)).unwrap();
+            let region = map_pmem_file(&file, size, discard_writes).unwrap();
+            // SAFETY: the mapping is SIZE bytes long and owned by region
+            let value = unsafe {
+                region.as_ptr().write(0x55);
+                region.as_ptr().read()
+            };
+            assert_eq!(value, 0x55);
+
+            let mut first = [0u8; 1];
+            file.read_exact_at(&mut first, 0).unwrap();
+            let expected = if discard_writes { 0xaa } else { 0x55 };
+            assert_eq!(first[0], expected);
+        }
+    }
 }
@@ -0,0 +1,277 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use vm_migration::MigratableError;
+
+use crate::api::VmmPingResponse;
+use crate::migration;
+use crate::vm::VmView;
+
+// The VMM thread is busy for the whole duration of a job, so the jobs are
+// kept here for the API threads to report on them directly.
+static JOBS: Lazy<Mutex<Jobs>> = Lazy::new(Mutex::default);
+
+// Number of finished jobs whose outcome can still be queried
+const MAX_FINISHED_JOBS: usize = 64;
+
+/// Errors associated with the management of the jobs
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No job with id {0}")]
+    NotFound(u64),
+
+    #[error("Job {0} is already finished")]
+    Finished(u64),
+
+    #[error("Job {0} cannot be cancelled while running")]
+    NotCancellable(u64),
+
+    #[error("Cannot cancel job {0}: {1}")]
+    Cancel(u64, #[source] MigratableError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobState {
+    /// Waiting for the VMM thread to run it
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Succeeded => "succeeded",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+
+    fn finished(&self) -> bool {
+        matches!(
+            self,
+            JobState::Succeeded | JobState::Failed | JobState::Cancelled
+        )
+    }
+}
+
+/// Progress of an API action run as a job, or its outcome.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JobStatus {
+    pub id: u64,
+    /// API action run by the job, e.g. `vm.snapshot`
+    pub action: String,
+    pub state: JobState,
+    pub elapsed_ms: u64,
+    pub error: Option<String>,
+}
+
+struct Job {
+    status: JobStatus,
+    start: Option<Instant>,
+    cancel: bool,
+}
+
+// What the API threads answer with while the VMM thread runs a job
+struct Busy {
+    ping: VmmPingResponse,
+    // VMs existing when the job started, indexed by VM id
+    vms: HashMap<String, VmView>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    last_id: u64,
+    jobs: BTreeMap<u64, Job>,
+    busy: Option<Busy>,
+}
+
+fn job_event(id: u64, action: &str, state: JobState) {
+    let event = format!("job-{}", state.as_str());
+    let id = id.to_string();
+    event!("vmm", &event, "id", &id, "action", action);
+}
+
+pub(crate) fn job_create(action: &str) -> u64 {
+    let mut jobs = JOBS.lock().unwrap();
+    jobs.last_id += 1;
+    let id = jobs.last_id;
+
+    let finished: Vec<u64> = jobs
+        .jobs
+        .values()
+        .filter(|job| job.status.state.finished())
+        .map(|job| job.status.id)
+        .collect();
+    for old in finished
+        .iter()
+        .take(finished.len().saturating_sub(MAX_FINISHED_JOBS - 1))
+    {
+        jobs.jobs.remove(old);
+    }
+
+    jobs.jobs.insert(
+        id,
+        Job {
+            status: JobStatus {
+                id,
+                action: action.to_owned(),
+                state: JobState::Queued,
+                elapsed_ms: 0,
+                error: None,
+            },
+            start: None,
+            cancel: false,
+        },
+    );
+    job_event(id, action, JobState::Queued);
+
+    id
+}
+
+/// Marks the job as running, unless it was cancelled while queued. The
+/// `ping` and `vms` are served to the API threads until the job is finished.
+pub(crate) fn job_start(id: u64, ping: VmmPingResponse, vms: HashMap<String, VmView>) -> bool {
+    let mut jobs = JOBS.lock().unwrap();
+    let Some(job) = jobs.jobs.get_mut(&id) else {
+        return false;
+    };
+    if job.status.state != JobState::Queued {
+        return false;
+    }
+
+    job.status.state = JobState::Running;
+    job.start = Some(Instant::now());
+    job_event(id, &job.status.action, JobState::Running);
+    jobs.busy = Some(Busy { ping, vms });
+
+    true
+}
+
+pub(crate) fn job_finish(id: u64, result: Result<(), String>) {
+    let mut jobs = JOBS.lock().unwrap();
+    jobs.busy = None;
+    let Some(job) = jobs.jobs.get_mut(&id) else {
+        return;
+    };
+
+    let state = match result {
+        Err(_) if job.cancel => JobState::Cancelled,
+        Ok(()) => JobState::Succeeded,
+        Err(e) => {
+            job.status.error = Some(e);
+            JobState::Failed
+        }
+    };
+    if let Some(start) = job.start.take() {
+        job.status.elapsed_ms = start.elapsed().as_millis() as u64;
+    }
+    job.status.state = state;
+    job_event(id, &job.status.action, state);
+}
+
+pub fn job_status(id: u64) -> Result<JobStatus, Error> {
+    let jobs = JOBS.lock().unwrap();
+    let job = jobs.jobs.get(&id).ok_or(Error::NotFound(id))?;
+    let mut status = job.status.clone();
+    if let Some(start) = job.start {
+        status.elapsed_ms = start.elapsed().as_millis() as u64;
+    }
+
+    Ok(status)
+}
+
+pub fn job_list() -> Vec<JobStatus> {
+    let ids: Vec<u64> = JOBS.lock().unwrap().jobs.keys().copied().collect();
+    ids.into_iter()
+        .filter_map(|id| job_status(id).ok())
+        .collect()
+}
+
+pub fn job_cancel(id: u64) -> Result<(), Error> {
+    let mut jobs = JOBS.lock().unwrap();
+    let job = jobs.jobs.get_mut(&id).ok_or(Error::NotFound(id))?;
+    match job.status.state {
+        JobState::Queued => {
+            job.status.state = JobState::Cancelled;
+            job_event(id, &job.status.action, JobState::Cancelled);
+            Ok(())
+        }
+        // Migrations are the only actions checking for cancellation while
+        // they run.
+        JobState::Running if job.status.action == "vm.send-migration" => {
+            migration::migration_cancel_ongoing().map_err(|e| Error::Cancel(id, e))?;
+            job.cancel = true;
+            Ok(())
+        }
+        JobState::Running => Err(Error::NotCancellable(id)),
+        _ => Err(Error::Finished(id)),
+    }
+}
+
+/// Returns the VMM ping response while a job keeps the VMM thread busy.
+pub fn running_job_ping() -> Option<VmmPingResponse> {
+    let jobs = JOBS.lock().unwrap();
+    jobs.busy.as_ref().map(|busy| busy.ping.clone())
+}
+
+/// Returns the VM `id`, if it existed when the running job started, or `None`
+/// when no job keeps the VMM thread busy.
+pub fn running_job_vm(id: &str) -> Option<Option<VmView>> {
+    let jobs = JOBS.lock().unwrap();
+    jobs.busy.as_ref().map(|busy| busy.vms.get(id).cloned())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn ping() -> VmmPingResponse {
+        VmmPingResponse {
+            build_version: String::new(),
+            version: String::new(),
+            pid: 0,
+            features: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_job_lifecycle() {
+        let cancelled = job_create("vm.snapshot");
+        let id = job_create("vm.coredump");
+        assert_eq!(job_status(id).unwrap().state, JobState::Queued);
+
+        job_cancel(cancelled).unwrap();
+        assert_eq!(job_status(cancelled).unwrap().state, JobState::Cancelled);
+        assert!(!job_start(cancelled, ping(), HashMap::new()));
+        assert!(matches!(job_cancel(cancelled), Err(Error::Finished(_))));
+
+        assert!(job_start(id, ping(), HashMap::new()));
+        assert_eq!(job_status(id).unwrap().state, JobState::Running);
+        assert!(running_job_vm("default").is_some_and(|vm| vm.is_none()));
+        assert!(matches!(job_cancel(id), Err(Error::NotCancellable(_))));
+
+        job_finish(id, Err("no space left".to_owned()));
+        let status = job_status(id).unwrap();
+        assert_eq!(status.state, JobState::Failed);
+        assert_eq!(status.error.as_deref(), Some("no space left"));
+        assert!(running_job_vm("default").is_none());
+        assert!(running_job_ping().is_none());
+
+        assert!(matches!(job_status(u64::MAX), Err(Error::NotFound(_))));
+    }
+}
@@ -8,24 +8,28 @@ extern crate event_monitor;
 #[macro_use]
 extern crate log;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::{stdout, Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::num::Wrapping;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::panic::AssertUnwindSafe;
 use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::mpsc::{Receiver, RecvError, SendError, Sender};
+use std::sync::mpsc::{channel, Receiver, RecvError, SendError, Sender};
 use std::sync::{Arc, Mutex};
 #[cfg(not(target_arch = "riscv64"))]
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{io, result, thread};
 
 use anyhow::anyhow;
 #[cfg(feature = "dbus_api")]
 use api::dbus::{DBusApiOptions, DBusApiShutdownChannels};
+use api::http::metrics::MetricsListener;
+#[cfg(feature = "tls_api")]
+use api::http::tcp::HttpTcpOptions;
 use api::http::HttpApiHandle;
 use console_devices::{pre_create_console_devices, ConsoleInfo};
 use landlock::LandlockError;
@@ -38,6 +42,7 @@ use serde::{Deserialize, Serialize};
 use signal_hook::iterator::{Handle, Signals};
 use thiserror::Error;
 use tracer::trace_scoped;
+use virtio_devices::RateLimiterConfig;
 use vm_memory::bitmap::{AtomicBitmap, BitmapSlice};
 use vm_memory::{ReadVolatile, VolatileMemoryError, VolatileSlice, WriteVolatile};
 use vm_migration::protocol::*;
@@ -47,19 +52,25 @@ use vmm_sys_util::signal::unblock_signal;
 use vmm_sys_util::sock_ctrl_msg::ScmSocket;
 
 use crate::api::{
-    ApiRequest, ApiResponse, RequestHandler, VmInfoResponse, VmReceiveMigrationData,
-    VmSendMigrationData, VmmPingResponse,
+    ApiRequest, ApiResponse, RequestHandler, VmInfoResponse, VmMetricsResponse,
+    VmReceiveMigrationData, VmSendMigrationData, VmUpdateData, VmUpdateResponse, VmmPingResponse,
+    VmmVmSummary, DEFAULT_VM_ID,
 };
-use crate::config::{add_to_config, RestoreConfig};
+use crate::config::{add_to_config, ConfigUpdate, RestoreConfig};
+use crate::config_check::ConfigCheckReport;
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use crate::coredump::GuestDebuggable;
 use crate::landlock::Landlock;
 use crate::memory_manager::MemoryManager;
 #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
 use crate::migration::get_vm_snapshot;
-use crate::migration::{recv_vm_config, recv_vm_state};
+use crate::migration::{
+    memory_table_bytes, migration_abandoned, migration_cancelled, migration_commit,
+    migration_finish, migration_memory_pass, migration_memory_received, migration_set_phase,
+    migration_start, recv_vm_config, recv_vm_state, MigrationPhase, MigrationRole,
+};
 use crate::seccomp_filters::{get_seccomp_filter, Thread};
-use crate::vm::{Error as VmError, Vm, VmState};
+use crate::vm::{Error as VmError, Vm, VmState, VmView};
 use crate::vm_config::{
     DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, UserDeviceConfig, VdpaConfig,
     VmConfig, VsockConfig,
@@ -70,6 +81,7 @@ mod acpi;
 pub mod api;
 mod clone3;
 pub mod config;
+pub mod config_check;
 pub mod console_devices;
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 mod coredump;
@@ -81,6 +93,7 @@ mod gdb;
 #[cfg(feature = "igvm")]
 mod igvm;
 pub mod interrupt;
+pub mod jobs;
 pub mod landlock;
 pub mod memory_manager;
 pub mod migration;
@@ -175,14 +188,15 @@ pub enum Error {
     #[error("Error activating virtio devices: {0:?}")]
     ActivateVirtioDevices(VmError),
 
-    /// Error creating API server
-    #[error("Error creating API server {0:?}")]
-    CreateApiServer(micro_http::ServerError),
-
     /// Error binding API server socket
     #[error("Error creation API server's socket {0:?}")]
     CreateApiServerSocket(#[source] io::Error),
 
+    /// Invalid configuration of the HTTP API over TCP
+    #[cfg(feature = "tls_api")]
+    #[error("Error configuring the HTTP API over TCP: {0}")]
+    HttpTcpApiConfig(#[source] api::http::tcp::Error),
+
     #[cfg(feature = "guest_debug")]
     #[error("Failed to start the GDB thread: {0}")]
     GdbThreadSpawn(io::Error),
@@ -221,18 +235,25 @@ pub enum EpollDispatch {
     Api = 2,
     ActivateVirtioDevices = 3,
     Debug = 4,
+    MemoryUnplug = 5,
     Unknown,
 }
 
+// The epoll data of the events belonging to a VM other than the default one
+// carries the token of that VM above the dispatch value.
+const VM_TOKEN_SHIFT: u64 = 8;
+const EPOLL_DISPATCH_MASK: u64 = (1 << VM_TOKEN_SHIFT) - 1;
+
 impl From<u64> for EpollDispatch {
     fn from(v: u64) -> Self {
         use EpollDispatch::*;
-        match v {
+        match v & EPOLL_DISPATCH_MASK {
             0 => Exit,
             1 => Reset,
             2 => Api,
             3 => ActivateVirtioDevices,
             4 => Debug,
+            5 => MemoryUnplug,
             _ => Unknown,
         }
     }
@@ -339,7 +360,21 @@ impl EpollContext {
     where
         T: AsRawFd,
     {
-        let dispatch_index = token as u64;
+        self.add_vm_event(fd, token, 0)
+    }
+
+    /// Registers an event of the VM identified by `vm_token`, 0 standing for
+    /// the default VM.
+    pub fn add_vm_event<T>(
+        &mut self,
+        fd: &T,
+        token: EpollDispatch,
+        vm_token: u64,
+    ) -> result::Result<(), io::Error>
+    where
+        T: AsRawFd,
+    {
+        let dispatch_index = token as u64 | (vm_token << VM_TOKEN_SHIFT);
         epoll::ctl(
             self.epoll_file.as_raw_fd(),
             epoll::ControlOptions::EPOLL_CTL_ADD,
@@ -350,6 +385,20 @@ impl EpollContext {
         Ok(())
     }
 
+    pub fn remove_event<T>(&mut self, fd: &T) -> result::Result<(), io::Error>
+    where
+        T: AsRawFd,
+    {
+        epoll::ctl(
+            self.epoll_file.as_raw_fd(),
+            epoll::ControlOptions::EPOLL_CTL_DEL,
+            fd.as_raw_fd(),
+            epoll::Event::new(epoll::Events::empty(), 0),
+        )?;
+
+        Ok(())
+    }
+
     #[cfg(fuzzing)]
     pub fn add_event_custom<T>(
         &mut self,
@@ -399,6 +448,8 @@ impl Serialize for PciDeviceInfo {
 
 pub fn feature_list() -> Vec<String> {
     vec![
+        #[cfg(feature = "bus_stats")]
+        "bus_stats".to_string(),
         #[cfg(feature = "dbus_api")]
         "dbus_api".to_string(),
         #[cfg(feature = "dhat-heap")]
@@ -417,6 +468,8 @@ pub fn feature_list() -> Vec<String> {
         "sev_snp".to_string(),
         #[cfg(feature = "tdx")]
         "tdx".to_string(),
+        #[cfg(feature = "tls_api")]
+        "tls_api".to_string(),
         #[cfg(feature = "tracing")]
         "tracing".to_string(),
     ]
@@ -470,6 +523,8 @@ pub fn start_event_monitor_thread(
                     for tx in monitor.broadcast.iter() {
                         tx.send(event.clone()).ok();
                     }
+
+                    monitor.subscribers.publish(&event);
                 }
             }))
             .map_err(|_| {
@@ -489,6 +544,9 @@ pub fn start_vmm_thread(
     vmm_version: VmmVersionInfo,
     http_path: &Option<String>,
     http_fd: Option<RawFd>,
+    event_subscribers: Option<event_monitor::Subscribers>,
+    metrics: Option<MetricsListener>,
+    #[cfg(feature = "tls_api")] http_tcp: Option<HttpTcpOptions>,
     #[cfg(feature = "dbus_api")] dbus_options: Option<DBusApiOptions>,
     api_event: EventFd,
     api_sender: Sender<ApiRequest>,
@@ -569,9 +627,35 @@ pub fn start_vmm_thread(
         None => None,
     };
 
+    if let Some(metrics) = metrics {
+        api::start_metrics_thread(
+            metrics,
+            api_event_clone.try_clone().map_err(Error::EventFdClone)?,
+            api_sender.clone(),
+            seccomp_action,
+            exit_event.try_clone().map_err(Error::EventFdClone)?,
+            hypervisor_type,
+            landlock_enable,
+        )?;
+    }
+
+    #[cfg(feature = "tls_api")]
+    if let Some(http_tcp) = http_tcp {
+        api::start_http_tcp_thread(
+            http_tcp,
+            api_event_clone.try_clone().map_err(Error::EventFdClone)?,
+            api_sender.clone(),
+            seccomp_action,
+            exit_event.try_clone().map_err(Error::EventFdClone)?,
+            hypervisor_type,
+            landlock_enable,
+        )?;
+    }
+
     let http_api_handle = if let Some(http_path) = http_path {
         Some(api::start_http_path_thread(
             http_path,
+            event_subscribers,
             api_event_clone,
             api_sender,
             seccomp_action,
@@ -582,6 +666,7 @@ pub fn start_vmm_thread(
     } else if let Some(http_fd) = http_fd {
         Some(api::start_http_fd_thread(
             http_fd,
+            event_subscribers,
             api_event_clone,
             api_sender,
             seccomp_action,
@@ -645,6 +730,23 @@ pub struct VmmThreadHandle {
     pub http_api_handle: Option<HttpApiHandle>,
 }
 
+/// State of a VM other than the default one. While that VM is selected, its
+/// state lives in the `Vmm` fields and the slot holds the default VM's one.
+struct VmSlot {
+    token: u64,
+    api_receiver: Receiver<ApiRequest>,
+    exit_evt: EventFd,
+    reset_evt: EventFd,
+    #[cfg(feature = "guest_debug")]
+    vm_debug_evt: EventFd,
+    activate_evt: EventFd,
+    memory_unplug_evt: EventFd,
+    vm: Option<Vm>,
+    vm_config: Option<Arc<Mutex<VmConfig>>>,
+    console_resize_pipe: Option<Arc<File>>,
+    console_info: Option<ConsoleInfo>,
+}
+
 pub struct Vmm {
     epoll: EpollContext,
     exit_evt: EventFd,
@@ -660,11 +762,17 @@ pub struct Vmm {
     seccomp_action: SeccompAction,
     hypervisor: Arc<dyn hypervisor::Hypervisor>,
     activate_evt: EventFd,
+    memory_unplug_evt: EventFd,
     signals: Option<Handle>,
     threads: Vec<thread::JoinHandle<()>>,
     original_termios_opt: Arc<Mutex<Option<termios>>>,
     console_resize_pipe: Option<Arc<File>>,
     console_info: Option<ConsoleInfo>,
+    // VM whose state is swapped into the fields above, None standing for
+    // the default VM
+    selected_vm: Option<String>,
+    vms: BTreeMap<String, VmSlot>,
+    next_vm_token: u64,
 }
 
 impl Vmm {
@@ -775,6 +883,7 @@ impl Vmm {
         let mut epoll = EpollContext::new().map_err(Error::Epoll)?;
         let reset_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?;
         let activate_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?;
+        let memory_unplug_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?;
 
         epoll
             .add_event(&exit_evt, EpollDispatch::Exit)
@@ -788,6 +897,10 @@ impl Vmm {
             .add_event(&activate_evt, EpollDispatch::ActivateVirtioDevices)
             .map_err(Error::Epoll)?;
 
+        epoll
+            .add_event(&memory_unplug_evt, EpollDispatch::MemoryUnplug)
+            .map_err(Error::Epoll)?;
+
         epoll
             .add_event(&api_evt, EpollDispatch::Api)
             .map_err(Error::Epoll)?;
@@ -812,14 +925,254 @@ impl Vmm {
             seccomp_action,
             hypervisor,
             activate_evt,
+            memory_unplug_evt,
             signals: None,
             threads: vec![],
             original_termios_opt: Arc::new(Mutex::new(None)),
             console_resize_pipe: None,
             console_info: None,
+            selected_vm: None,
+            vms: BTreeMap::new(),
+            next_vm_token: 1,
         })
     }
 
+    fn add_vm(&mut self, id: &str) -> result::Result<(), VmError> {
+        let token = self.next_vm_token;
+        let (api_sender, api_receiver) = channel();
+        let slot = VmSlot {
+            token,
+            api_receiver,
+            exit_evt: EventFd::new(EFD_NONBLOCK).map_err(VmError::EventFdCreate)?,
+            reset_evt: EventFd::new(EFD_NONBLOCK).map_err(VmError::EventFdCreate)?,
+            // The GDB stub only drives the default VM.
+            #[cfg(feature = "guest_debug")]
+            vm_debug_evt: EventFd::new(EFD_NONBLOCK).map_err(VmError::EventFdCreate)?,
+            activate_evt: EventFd::new(EFD_NONBLOCK).map_err(VmError::EventFdCreate)?,
+            memory_unplug_evt: EventFd::new(EFD_NONBLOCK).map_err(VmError::EventFdCreate)?,
+            vm: None,
+            vm_config: None,
+            console_resize_pipe: None,
+            console_info: None,
+        };
+
+        for (fd, dispatch) in [
+            (&slot.exit_evt, EpollDispatch::Exit),
+            (&slot.reset_evt, EpollDispatch::Reset),
+            (&slot.activate_evt, EpollDispatch::ActivateVirtioDevices),
+            (&slot.memory_unplug_evt, EpollDispatch::MemoryUnplug),
+        ] {
+            if let Err(e) = self.epoll.add_vm_event(fd, dispatch, token) {
+                Self::remove_vm_events(&mut self.epoll, &slot);
+                return Err(VmError::VmEventRegister(e));
+            }
+        }
+
+        self.next_vm_token += 1;
+        self.vms.insert(id.to_owned(), slot);
+        api::register_vm_api_sender(id, api_sender);
+
+        Ok(())
+    }
+
+    fn remove_vm_events(epoll: &mut EpollContext, slot: &VmSlot) {
+        for fd in [
+            &slot.exit_evt,
+            &slot.reset_evt,
+            &slot.activate_evt,
+            &slot.memory_unplug_evt,
+        ] {
+            epoll.remove_event(fd).ok();
+        }
+    }
+
+    // Must not be called on the selected VM, whose slot holds the state of
+    // the default one.
+    fn remove_vm(&mut self, id: &str) {
+        if let Some(slot) = self.vms.remove(id) {
+            api::unregister_vm_api_sender(id);
+            Self::remove_vm_events(&mut self.epoll, &slot);
+            info!("VM {} removed", id);
+        }
+    }
+
+    // Named VMs only exist as long as they have a configuration.
+    fn reap_vm(&mut self, id: &str) {
+        if self.selected_vm.as_deref() != Some(id)
+            && self
+                .vms
+                .get(id)
+                .is_some_and(|slot| slot.vm_config.is_none())
+        {
+            self.remove_vm(id);
+        }
+    }
+
+    fn swap_vm_state(&mut self, id: &str) {
+        let Some(slot) = self.vms.get_mut(id) else {
+            return;
+        };
+
+        std::mem::swap(&mut self.exit_evt, &mut slot.exit_evt);
+        std::mem::swap(&mut self.reset_evt, &mut slot.reset_evt);
+        #[cfg(feature = "guest_debug")]
+        std::mem::swap(&mut self.vm_debug_evt, &mut slot.vm_debug_evt);
+        std::mem::swap(&mut self.activate_evt, &mut slot.activate_evt);
+        std::mem::swap(&mut self.memory_unplug_evt, &mut slot.memory_unplug_evt);
+        std::mem::swap(&mut self.vm, &mut slot.vm);
+        std::mem::swap(&mut self.vm_config, &mut slot.vm_config);
+        std::mem::swap(&mut self.console_resize_pipe, &mut slot.console_resize_pipe);
+        std::mem::swap(&mut self.console_info, &mut slot.console_info);
+    }
+
+    /// Id of the VM targeted by the `RequestHandler` methods.
+    fn selected_vm_id(&self) -> &str {
+        self.selected_vm.as_deref().unwrap_or(DEFAULT_VM_ID)
+    }
+
+    /// Makes the VM `id` the target of the `RequestHandler` methods.
+    fn select_vm(&mut self, id: &str) {
+        if self.selected_vm_id() == id {
+            return;
+        }
+
+        if let Some(current) = self.selected_vm.take() {
+            self.swap_vm_state(&current);
+        }
+
+        if id != DEFAULT_VM_ID && self.vms.contains_key(id) {
+            self.swap_vm_state(id);
+            self.selected_vm = Some(id.to_owned());
+        }
+    }
+
+    fn vm_token_id(&self, vm_token: u64) -> Option<String> {
+        self.vms
+            .iter()
+            .find(|(_, slot)| slot.token == vm_token)
+            .map(|(id, _)| id.clone())
+    }
+
+    /// Lists the created VMs by id, whichever of them is selected.
+    fn vm_entries(&self) -> impl Iterator<Item = (&str, Option<&Vm>, &Arc<Mutex<VmConfig>>)> {
+        let selected = self.selected_vm.as_deref();
+        let current = (selected.unwrap_or(DEFAULT_VM_ID), &self.vm, &self.vm_config);
+        let parked = self.vms.iter().map(move |(id, slot)| {
+            let id = if Some(id.as_str()) == selected {
+                DEFAULT_VM_ID
+            } else {
+                id.as_str()
+            };
+            (id, &slot.vm, &slot.vm_config)
+        });
+
+        std::iter::once(current)
+            .chain(parked)
+            .filter_map(|(id, vm, vm_config)| Some((id, vm.as_ref(), vm_config.as_ref()?)))
+    }
+
+    fn generate_vm_id(&self) -> String {
+        let mut index = self.next_vm_token;
+        loop {
+            let id = format!("vm{index}");
+            if !self.vms.contains_key(&id) {
+                return id;
+            }
+            index += 1;
+        }
+    }
+
+    // VM ids end up in the API URLs.
+    fn valid_vm_id(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= 64
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    }
+
+    fn handle_vm_api_requests(&mut self) -> Result<bool> {
+        let ids: Vec<String> = self.vms.keys().cloned().collect();
+        for id in ids {
+            while let Some(api_request) = self
+                .vms
+                .get(&id)
+                .and_then(|slot| slot.api_receiver.try_recv().ok())
+            {
+                self.select_vm(&id);
+                let r = api_request(self);
+                self.select_vm(DEFAULT_VM_ID);
+                self.reap_vm(&id);
+
+                if r? {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn handle_vm_event(&mut self, vm_token: u64, dispatch_event: EpollDispatch) -> Result<()> {
+        let Some(id) = self.vm_token_id(vm_token) else {
+            warn!("Event for unknown VM token: {}", vm_token);
+            return Ok(());
+        };
+
+        self.select_vm(&id);
+        let r = self.handle_selected_vm_event(&id, dispatch_event);
+        self.select_vm(DEFAULT_VM_ID);
+        self.reap_vm(&id);
+
+        r
+    }
+
+    // Failures are only logged, as they must not bring down the other VMs.
+    fn handle_selected_vm_event(&mut self, id: &str, dispatch_event: EpollDispatch) -> Result<()> {
+        match dispatch_event {
+            EpollDispatch::Exit => {
+                info!("VM {} exit event", id);
+                // Consume the event.
+                self.exit_evt.read().map_err(Error::EventFdRead)?;
+                if let Err(e) = self.vm_shutdown() {
+                    error!("Error shutting down VM {}: {:?}", id, e);
+                }
+            }
+            EpollDispatch::Reset => {
+                info!("VM {} reset event", id);
+                // Consume the event.
+                self.reset_evt.read().map_err(Error::EventFdRead)?;
+                if let Err(e) = self.vm_reboot() {
+                    error!("Error rebooting VM {}: {:?}", id, e);
+                }
+            }
+            EpollDispatch::ActivateVirtioDevices => {
+                if let Some(ref vm) = self.vm {
+                    let count = self.activate_evt.read().map_err(Error::EventFdRead)?;
+                    info!(
+                        "Trying to activate pending virtio devices of VM {}: count = {}",
+                        id, count
+                    );
+                    if let Err(e) = vm.activate_virtio_devices() {
+                        error!("Error activating virtio devices of VM {}: {:?}", id, e);
+                    }
+                }
+            }
+            EpollDispatch::MemoryUnplug => {
+                // Consume the event.
+                self.memory_unplug_evt.read().map_err(Error::EventFdRead)?;
+                if let Some(ref vm) = self.vm {
+                    if let Err(e) = vm.remove_unplugged_memory() {
+                        error!("Error removing unplugged memory of VM {}: {:?}", id, e);
+                    }
+                }
+            }
+            _ => warn!("Unexpected event for VM {}: {:?}", id, dispatch_event),
+        }
+
+        Ok(())
+    }
+
     fn vm_receive_config<T>(
         &mut self,
         req: &Request,
@@ -941,6 +1294,9 @@ impl Vmm {
         let activate_evt = self.activate_evt.try_clone().map_err(|e| {
             MigratableError::MigrateReceive(anyhow!("Error cloning activate EventFd: {}", e))
         })?;
+        let memory_unplug_evt = self.memory_unplug_evt.try_clone().map_err(|e| {
+            MigratableError::MigrateReceive(anyhow!("Error cloning memory unplug EventFd: {}", e))
+        })?;
 
         #[cfg(not(target_arch = "riscv64"))]
         let timestamp = Instant::now();
@@ -956,6 +1312,7 @@ impl Vmm {
             &self.seccomp_action,
             self.hypervisor.clone(),
             activate_evt,
+            memory_unplug_evt,
             #[cfg(not(target_arch = "riscv64"))]
             timestamp,
             self.console_info.clone(),
@@ -990,6 +1347,7 @@ impl Vmm {
     {
         // Read table
         let table = MemoryRangeTable::read_from(socket, req.length())?;
+        migration_memory_received(memory_table_bytes(&table));
 
         // And then read the memory itself
         memory_manager
@@ -1083,6 +1441,7 @@ impl Vmm {
             return Ok(false);
         }
 
+        let pass_start = Instant::now();
         Request::memory(table.length()).write_to(socket).unwrap();
         table.write_to(socket)?;
         // And then the memory itself
@@ -1091,10 +1450,37 @@ impl Vmm {
             socket,
             MigratableError::MigrateSend(anyhow!("Error during dirty memory migration")),
         )?;
+        migration_memory_pass(memory_table_bytes(&table), pass_start.elapsed());
 
         Ok(true)
     }
 
+    // Abandons the migration if it was cancelled through the API
+    fn check_migration_cancelled(socket: &mut SocketStream) -> result::Result<(), MigratableError> {
+        if !migration_cancelled() {
+            return Ok(());
+        }
+        Self::abandon_cancelled_migration(socket)
+    }
+
+    // Last chance to abandon the migration, cancellations are refused from
+    // now on as the destination is about to take over.
+    fn commit_migration(socket: &mut SocketStream) -> result::Result<(), MigratableError> {
+        if !migration_commit() {
+            return Ok(());
+        }
+        Self::abandon_cancelled_migration(socket)
+    }
+
+    fn abandon_cancelled_migration(
+        socket: &mut SocketStream,
+    ) -> result::Result<(), MigratableError> {
+        info!("Migration cancelled");
+        Request::abandon().write_to(socket)?;
+        Response::read_from(socket)?;
+        Err(MigratableError::MigrateSend(anyhow!("Migration cancelled")))
+    }
+
     fn send_migration(
         vm: &mut Vm,
         #[cfg(all(feature = "kvm", target_arch = "x86_64"))] hypervisor: Arc<
@@ -1172,18 +1558,26 @@ impl Vmm {
             MigratableError::MigrateSend(anyhow!("Error during config migration")),
         )?;
 
+        Self::check_migration_cancelled(&mut socket)?;
+
         // Let every Migratable object know about the migration being started.
         vm.start_migration()?;
 
         if send_data_migration.local {
             // Now pause VM
+            migration_set_phase(MigrationPhase::StopAndCopy);
             vm.pause()?;
         } else {
             // Start logging dirty pages
             vm.start_dirty_log()?;
 
-            // Send memory table
-            let table = vm.memory_range_table()?;
+            // Send memory table, leaving out the pages the guest hinted as
+            // free. Any of them reused by the guest from now on is dirty.
+            migration_set_phase(MigrationPhase::Memory);
+            const FREE_PAGE_HINT_TIMEOUT: Duration = Duration::from_secs(5);
+            let mut table = vm.memory_range_table()?;
+            table.subtract(&vm.free_page_hints(FREE_PAGE_HINT_TIMEOUT)?);
+            let pass_start = Instant::now();
             Request::memory(table.length())
                 .write_to(&mut socket)
                 .unwrap();
@@ -1194,22 +1588,29 @@ impl Vmm {
                 &mut socket,
                 MigratableError::MigrateSend(anyhow!("Error during dirty memory migration")),
             )?;
+            migration_memory_pass(memory_table_bytes(&table), pass_start.elapsed());
 
             // Try at most 5 passes of dirty memory sending
+            migration_set_phase(MigrationPhase::DirtyMemory);
             const MAX_DIRTY_MIGRATIONS: usize = 5;
             for i in 0..MAX_DIRTY_MIGRATIONS {
+                Self::check_migration_cancelled(&mut socket)?;
                 info!("Dirty memory migration {} of {}", i, MAX_DIRTY_MIGRATIONS);
                 if !Self::vm_maybe_send_dirty_pages(vm, &mut socket)? {
                     break;
                 }
             }
+            Self::check_migration_cancelled(&mut socket)?;
 
             // Now pause VM
+            migration_set_phase(MigrationPhase::StopAndCopy);
             vm.pause()?;
 
             // Send last batch of dirty pages
             Self::vm_maybe_send_dirty_pages(vm, &mut socket)?;
         }
+        Self::commit_migration(&mut socket)?;
+
         // Capture snapshot and send it
         let vm_snapshot = vm.snapshot()?;
         let snapshot_data = serde_json::to_vec(&vm_snapshot).unwrap();
@@ -1239,79 +1640,264 @@ impl Vmm {
         vm.complete_migration()
     }
 
-    #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
-    fn vm_check_cpuid_compatibility(
-        &self,
-        src_vm_config: &Arc<Mutex<VmConfig>>,
-        src_vm_cpuid: &[hypervisor::arch::x86::CpuIdEntry],
+    fn receive_migration(
+        &mut self,
+        receive_data_migration: VmReceiveMigrationData,
     ) -> result::Result<(), MigratableError> {
-        #[cfg(feature = "tdx")]
-        if src_vm_config.lock().unwrap().is_tdx_enabled() {
-            return Err(MigratableError::MigrateReceive(anyhow!(
-                "Live Migration is not supported when TDX is enabled"
-            )));
-        };
-
-        // We check the `CPUID` compatibility of between the source vm and destination, which is
-        // mostly about feature compatibility and "topology/sgx" leaves are not relevant.
-        let dest_cpuid = &{
-            let vm_config = &src_vm_config.lock().unwrap();
-
-            let phys_bits = vm::physical_bits(&self.hypervisor, vm_config.cpus.max_phys_bits);
-            arch::generate_common_cpuid(
-                &self.hypervisor.clone(),
-                &arch::CpuidConfig {
-                    sgx_epc_sections: None,
-                    phys_bits,
-                    kvm_hyperv: vm_config.cpus.kvm_hyperv,
-                    #[cfg(feature = "tdx")]
-                    tdx: false,
-                    amx: vm_config.cpus.features.amx,
-                },
-            )
-            .map_err(|e| {
-                MigratableError::MigrateReceive(anyhow!("Error generating common cpuid: {:?}", e))
-            })?
-        };
-        arch::CpuidFeatureEntry::check_cpuid_compatibility(src_vm_cpuid, dest_cpuid).map_err(|e| {
-            MigratableError::MigrateReceive(anyhow!(
-                "Error checking cpu feature compatibility': {:?}",
-                e
-            ))
-        })
-    }
+        // Accept the connection and get the socket
+        let mut socket = Vmm::receive_migration_socket(&receive_data_migration.receiver_url)?;
 
-    fn vm_restore(
-        &mut self,
-        source_url: &str,
-        vm_config: Arc<Mutex<VmConfig>>,
-        prefault: bool,
-    ) -> std::result::Result<(), VmError> {
-        let snapshot = recv_vm_state(source_url).map_err(VmError::Restore)?;
-        #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
-        let vm_snapshot = get_vm_snapshot(&snapshot).map_err(VmError::Restore)?;
+        let mut started = false;
+        let mut memory_manager: Option<Arc<Mutex<MemoryManager>>> = None;
+        let mut existing_memory_files = None;
+        loop {
+            let req = Request::read_from(&mut socket)?;
+            match req.command() {
+                Command::Invalid => info!("Invalid Command Received"),
+                Command::Start => {
+                    info!("Start Command Received");
+                    started = true;
 
-        #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
-        self.vm_check_cpuid_compatibility(&vm_config, &vm_snapshot.common_cpuid)
-            .map_err(VmError::Restore)?;
+                    Response::ok().write_to(&mut socket)?;
+                }
+                Command::Config => {
+                    info!("Config Command Received");
 
-        self.vm_config = Some(Arc::clone(&vm_config));
+                    if !started {
+                        warn!("Migration not started yet");
+                        Response::error().write_to(&mut socket)?;
+                        continue;
+                    }
+                    memory_manager = Some(self.vm_receive_config(
+                        &req,
+                        &mut socket,
+                        existing_memory_files.take(),
+                    )?);
+                }
+                Command::State => {
+                    info!("State Command Received");
 
-        // Always re-populate the 'console_info' based on the new 'vm_config'
-        self.console_info =
-            Some(pre_create_console_devices(self).map_err(VmError::CreateConsoleDevices)?);
+                    if !started {
+                        warn!("Migration not started yet");
+                        Response::error().write_to(&mut socket)?;
+                        continue;
+                    }
+                    if let Some(mm) = memory_manager.take() {
+                        migration_set_phase(MigrationPhase::StopAndCopy);
+                        self.vm_receive_state(&req, &mut socket, mm)?;
+                    } else {
+                        warn!("Configuration not sent yet");
+                        Response::error().write_to(&mut socket)?;
+                    }
+                }
+                Command::Memory => {
+                    info!("Memory Command Received");
 
-        let exit_evt = self.exit_evt.try_clone().map_err(VmError::EventFdClone)?;
-        let reset_evt = self.reset_evt.try_clone().map_err(VmError::EventFdClone)?;
-        #[cfg(feature = "guest_debug")]
-        let debug_evt = self
-            .vm_debug_evt
-            .try_clone()
-            .map_err(VmError::EventFdClone)?;
+                    if !started {
+                        warn!("Migration not started yet");
+                        Response::error().write_to(&mut socket)?;
+                        continue;
+                    }
+                    if let Some(mm) = memory_manager.as_ref() {
+                        self.vm_receive_memory(&req, &mut socket, &mut mm.lock().unwrap())?;
+                    } else {
+                        warn!("Configuration not sent yet");
+                        Response::error().write_to(&mut socket)?;
+                    }
+                }
+                Command::MemoryFd => {
+                    info!("MemoryFd Command Received");
+
+                    if !started {
+                        warn!("Migration not started yet");
+                        Response::error().write_to(&mut socket)?;
+                        continue;
+                    }
+
+                    match &mut socket {
+                        SocketStream::Unix(unix_socket) => {
+                            let mut buf = [0u8; 4];
+                            let (_, file) = unix_socket.recv_with_fd(&mut buf).map_err(|e| {
+                                MigratableError::MigrateReceive(anyhow!(
+                                    "Error receiving slot from socket: {}",
+                                    e
+                                ))
+                            })?;
+
+                            if existing_memory_files.is_none() {
+                                existing_memory_files = Some(HashMap::default())
+                            }
+
+                            if let Some(ref mut existing_memory_files) = existing_memory_files {
+                                let slot = u32::from_le_bytes(buf);
+                                existing_memory_files.insert(slot, file.unwrap());
+                            }
+
+                            Response::ok().write_to(&mut socket)?;
+                        }
+                        SocketStream::Tcp(_tcp_socket) => {
+                            // For TCP sockets, we cannot transfer file descriptors
+                            warn!(
+                                "MemoryFd command received over TCP socket, which is not supported"
+                            );
+                            Response::error().write_to(&mut socket)?;
+                        }
+                    }
+                }
+                Command::Complete => {
+                    info!("Complete Command Received");
+                    if let Some(ref mut vm) = self.vm.as_mut() {
+                        vm.resume()?;
+                        Response::ok().write_to(&mut socket)?;
+                    } else {
+                        warn!("VM not created yet");
+                        Response::error().write_to(&mut socket)?;
+                    }
+                    break;
+                }
+                Command::Abandon => {
+                    info!("Abandon Command Received");
+                    migration_abandoned();
+                    self.vm = None;
+                    self.vm_config = None;
+                    Response::ok().write_to(&mut socket).ok();
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_migration_and_shutdown(
+        &mut self,
+        send_data_migration: VmSendMigrationData,
+    ) -> result::Result<(), MigratableError> {
+        let Some(vm_config) = self.vm_config.as_ref() else {
+            return Err(MigratableError::MigrateSend(anyhow!("VM is not running")));
+        };
+
+        if !vm_config.lock().unwrap().backed_by_shared_memory() && send_data_migration.local {
+            return Err(MigratableError::MigrateSend(anyhow!(
+                "Local migration requires shared memory or hugepages enabled"
+            )));
+        }
+
+        if let Some(vm) = self.vm.as_mut() {
+            Self::send_migration(
+                vm,
+                #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
+                self.hypervisor.clone(),
+                send_data_migration.clone(),
+            )
+            .map_err(|migration_err| {
+                error!("Migration failed: {:?}", migration_err);
+
+                // Stop logging dirty pages only for non-local migrations
+                if !send_data_migration.local {
+                    if let Err(e) = vm.stop_dirty_log() {
+                        return e;
+                    }
+                }
+
+                if vm.get_state().unwrap() == VmState::Paused {
+                    if let Err(e) = vm.resume() {
+                        return e;
+                    }
+                }
+
+                migration_err
+            })?;
+
+            // Shutdown the VM after the migration succeeded
+            self.exit_evt.write(1).map_err(|e| {
+                MigratableError::MigrateSend(anyhow!(
+                    "Failed shutting down the VM after migration: {:?}",
+                    e
+                ))
+            })
+        } else {
+            Err(MigratableError::MigrateSend(anyhow!("VM is not running")))
+        }
+    }
+
+    #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
+    fn vm_check_cpuid_compatibility(
+        &self,
+        src_vm_config: &Arc<Mutex<VmConfig>>,
+        src_vm_cpuid: &[hypervisor::arch::x86::CpuIdEntry],
+    ) -> result::Result<(), MigratableError> {
+        #[cfg(feature = "tdx")]
+        if src_vm_config.lock().unwrap().is_tdx_enabled() {
+            return Err(MigratableError::MigrateReceive(anyhow!(
+                "Live Migration is not supported when TDX is enabled"
+            )));
+        };
+
+        // We check the `CPUID` compatibility of between the source vm and destination, which is
+        // mostly about feature compatibility and "topology/sgx" leaves are not relevant.
+        let dest_cpuid = &{
+            let vm_config = &src_vm_config.lock().unwrap();
+
+            let phys_bits = vm::physical_bits(&self.hypervisor, vm_config.cpus.max_phys_bits);
+            arch::generate_common_cpuid(
+                &self.hypervisor.clone(),
+                &arch::CpuidConfig {
+                    sgx_epc_sections: None,
+                    phys_bits,
+                    kvm_hyperv: vm_config.cpus.kvm_hyperv,
+                    #[cfg(feature = "tdx")]
+                    tdx: false,
+                    amx: vm_config.cpus.features.amx,
+                },
+            )
+            .map_err(|e| {
+                MigratableError::MigrateReceive(anyhow!("Error generating common cpuid: {:?}", e))
+            })?
+        };
+        arch::CpuidFeatureEntry::check_cpuid_compatibility(src_vm_cpuid, dest_cpuid).map_err(|e| {
+            MigratableError::MigrateReceive(anyhow!(
+                "Error checking cpu feature compatibility': {:?}",
+                e
+            ))
+        })
+    }
+
+    fn vm_restore(
+        &mut self,
+        source_url: &str,
+        vm_config: Arc<Mutex<VmConfig>>,
+        prefault: bool,
+    ) -> std::result::Result<(), VmError> {
+        let snapshot = recv_vm_state(source_url).map_err(VmError::Restore)?;
+        #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
+        let vm_snapshot = get_vm_snapshot(&snapshot).map_err(VmError::Restore)?;
+
+        #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
+        self.vm_check_cpuid_compatibility(&vm_config, &vm_snapshot.common_cpuid)
+            .map_err(VmError::Restore)?;
+
+        self.vm_config = Some(Arc::clone(&vm_config));
+
+        // Always re-populate the 'console_info' based on the new 'vm_config'
+        self.console_info =
+            Some(pre_create_console_devices(self).map_err(VmError::CreateConsoleDevices)?);
+
+        let exit_evt = self.exit_evt.try_clone().map_err(VmError::EventFdClone)?;
+        let reset_evt = self.reset_evt.try_clone().map_err(VmError::EventFdClone)?;
+        #[cfg(feature = "guest_debug")]
+        let debug_evt = self
+            .vm_debug_evt
+            .try_clone()
+            .map_err(VmError::EventFdClone)?;
         let activate_evt = self
             .activate_evt
             .try_clone()
             .map_err(VmError::EventFdClone)?;
+        let memory_unplug_evt = self
+            .memory_unplug_evt
+            .try_clone()
+            .map_err(VmError::EventFdClone)?;
 
         let vm = Vm::new(
             vm_config,
@@ -1322,6 +1908,7 @@ impl Vmm {
             &self.seccomp_action,
             self.hypervisor.clone(),
             activate_evt,
+            memory_unplug_evt,
             self.console_info.clone(),
             self.console_resize_pipe.clone(),
             Arc::clone(&self.original_termios_opt),
@@ -1381,6 +1968,12 @@ impl Vmm {
 
             for event in events.iter().take(num_events) {
                 let dispatch_event: EpollDispatch = event.data.into();
+                let vm_token = event.data >> VM_TOKEN_SHIFT;
+                if vm_token != 0 {
+                    self.handle_vm_event(vm_token, dispatch_event)?;
+                    continue;
+                }
+
                 match dispatch_event {
                     EpollDispatch::Unknown => {
                         let event = event.data;
@@ -1411,16 +2004,31 @@ impl Vmm {
                                 .map_err(Error::ActivateVirtioDevices)?;
                         }
                     }
+                    EpollDispatch::MemoryUnplug => {
+                        // Consume the event.
+                        self.memory_unplug_evt.read().map_err(Error::EventFdRead)?;
+                        if let Some(ref vm) = self.vm {
+                            // The guest already ejected the memory, failing
+                            // to release it must not take the VMM down.
+                            if let Err(e) = vm.remove_unplugged_memory() {
+                                error!("Error removing unplugged memory: {:?}", e);
+                            }
+                        }
+                    }
                     EpollDispatch::Api => {
-                        // Consume the events.
-                        for _ in 0..self.api_evt.read().map_err(Error::EventFdRead)? {
-                            // Read from the API receiver channel
-                            let api_request = api_receiver.recv().map_err(Error::ApiRequestRecv)?;
+                        // Consume the events. They are shared by all the API
+                        // channels, so drain each of them.
+                        self.api_evt.read().map_err(Error::EventFdRead)?;
 
+                        while let Ok(api_request) = api_receiver.try_recv() {
                             if api_request(self)? {
                                 break 'outer;
                             }
                         }
+
+                        if self.handle_vm_api_requests()? {
+                            break 'outer;
+                        }
                     }
                     #[cfg(feature = "guest_debug")]
                     EpollDispatch::Debug => {
@@ -1523,6 +2131,10 @@ impl RequestHandler for Vmm {
                     .activate_evt
                     .try_clone()
                     .map_err(VmError::EventFdClone)?;
+                let memory_unplug_evt = self
+                    .memory_unplug_evt
+                    .try_clone()
+                    .map_err(VmError::EventFdClone)?;
 
                 if let Some(ref vm_config) = self.vm_config {
                     let vm = Vm::new(
@@ -1534,6 +2146,7 @@ impl RequestHandler for Vmm {
                         &self.seccomp_action,
                         self.hypervisor.clone(),
                         activate_evt,
+                        memory_unplug_evt,
                         self.console_info.clone(),
                         self.console_resize_pipe.clone(),
                         Arc::clone(&self.original_termios_opt),
@@ -1689,6 +2302,10 @@ impl RequestHandler for Vmm {
             .activate_evt
             .try_clone()
             .map_err(VmError::EventFdClone)?;
+        let memory_unplug_evt = self
+            .memory_unplug_evt
+            .try_clone()
+            .map_err(VmError::EventFdClone)?;
 
         // The Linux kernel fires off an i8042 reset after doing the ACPI reset so there may be
         // an event sitting in the shared reset_evt. Without doing this we get very early reboots
@@ -1710,6 +2327,7 @@ impl RequestHandler for Vmm {
             &self.seccomp_action,
             self.hypervisor.clone(),
             activate_evt,
+            memory_unplug_evt,
             self.console_info.clone(),
             self.console_resize_pipe.clone(),
             Arc::clone(&self.original_termios_opt),
@@ -1730,30 +2348,7 @@ impl RequestHandler for Vmm {
 
     fn vm_info(&self) -> result::Result<VmInfoResponse, VmError> {
         match &self.vm_config {
-            Some(vm_config) => {
-                let state = match &self.vm {
-                    Some(vm) => vm.get_state()?,
-                    None => VmState::Created,
-                };
-                let config = vm_config.lock().unwrap().clone();
-
-                let mut memory_actual_size = config.memory.total_size();
-                if let Some(vm) = &self.vm {
-                    memory_actual_size -= vm.balloon_size();
-                }
-
-                let device_tree = self
-                    .vm
-                    .as_ref()
-                    .map(|vm| vm.device_tree().lock().unwrap().clone());
-
-                Ok(VmInfoResponse {
-                    config: Box::new(config),
-                    state,
-                    memory_actual_size,
-                    device_tree,
-                })
-            }
+            Some(vm_config) => VmView::new(Arc::clone(vm_config), self.vm.as_ref()).info(),
             None => Err(VmError::VmNotCreated),
         }
     }
@@ -1790,11 +2385,88 @@ impl RequestHandler for Vmm {
     }
 
     fn vmm_shutdown(&mut self) -> result::Result<(), VmError> {
+        self.select_vm(DEFAULT_VM_ID);
+        let ids: Vec<String> = self.vms.keys().cloned().collect();
+        for id in ids {
+            self.select_vm(&id);
+            let r = self.vm_delete();
+            self.select_vm(DEFAULT_VM_ID);
+            if let Err(e) = r {
+                error!("Error deleting VM {}: {:?}", id, e);
+            }
+            self.remove_vm(&id);
+        }
+
         self.vm_delete()?;
         event!("vmm", "shutdown");
         Ok(())
     }
 
+    fn vmm_create_vm(
+        &mut self,
+        id: Option<String>,
+        config: Box<VmConfig>,
+    ) -> result::Result<String, VmError> {
+        let id = match id {
+            Some(id) if !Self::valid_vm_id(&id) => return Err(VmError::InvalidVmId(id)),
+            Some(id) => id,
+            None => self.generate_vm_id(),
+        };
+
+        let previous = self
+            .selected_vm
+            .clone()
+            .unwrap_or_else(|| DEFAULT_VM_ID.to_owned());
+        if id != DEFAULT_VM_ID {
+            if self.vms.contains_key(&id) {
+                return Err(VmError::VmAlreadyCreated);
+            }
+            self.add_vm(&id)?;
+        }
+
+        self.select_vm(&id);
+        let r = self.vm_create(config);
+        self.select_vm(&previous);
+        if r.is_err() && id != DEFAULT_VM_ID {
+            self.remove_vm(&id);
+        }
+        r?;
+
+        info!("VM {} created", id);
+
+        Ok(id)
+    }
+
+    fn vmm_validate_config(&self, config: Box<VmConfig>) -> ConfigCheckReport {
+        config_check::check_config(&config, Some(self.hypervisor.as_ref()))
+    }
+
+    fn vmm_list_vms(&self) -> result::Result<Option<Vec<u8>>, VmError> {
+        let mut vms = Vec::new();
+        for (id, vm, _) in self.vm_entries() {
+            let state = match vm {
+                Some(vm) => vm.get_state()?,
+                None => VmState::Created,
+            };
+            vms.push(VmmVmSummary {
+                id: id.to_owned(),
+                state,
+            });
+        }
+        // Keep the default VM first, followed by the others in id order
+        vms.sort_by(|a, b| (a.id != DEFAULT_VM_ID, &a.id).cmp(&(b.id != DEFAULT_VM_ID, &b.id)));
+
+        serde_json::to_vec(&vms)
+            .map(Some)
+            .map_err(VmError::SerializeJson)
+    }
+
+    fn vm_views(&self) -> HashMap<String, VmView> {
+        self.vm_entries()
+            .map(|(id, vm, vm_config)| (id.to_owned(), VmView::new(Arc::clone(vm_config), vm)))
+            .collect()
+    }
+
     fn vm_resize(
         &mut self,
         desired_vcpus: Option<u8>,
@@ -1855,6 +2527,94 @@ impl RequestHandler for Vmm {
         }
     }
 
+    fn vm_update_rate_limiter(
+        &mut self,
+        id: String,
+        rate_limiter_config: RateLimiterConfig,
+    ) -> result::Result<(), VmError> {
+        self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
+
+        if let Some(ref mut vm) = self.vm {
+            if let Err(e) = vm.update_rate_limiter(&id, rate_limiter_config) {
+                error!("Error when updating rate limiter: {:?}", e);
+                Err(e)
+            } else {
+                Ok(())
+            }
+        } else {
+            // Update VmConfig so the new values are used once the VM boots.
+            self.vm_config
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .update_rate_limiter(&id, rate_limiter_config)
+                .map_err(VmError::ConfigValidation)
+        }
+    }
+
+    fn vm_update(&mut self, update: VmUpdateData) -> result::Result<Option<Vec<u8>>, VmError> {
+        self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
+
+        let ConfigUpdate {
+            mut config,
+            live,
+            on_reboot,
+            unsupported,
+        } = self
+            .vm_config
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_update(&update.config)
+            .map_err(VmError::InvalidUpdate)?;
+        config.validate().map_err(VmError::ConfigValidation)?;
+
+        let response = if let Some(ref mut vm) = self.vm {
+            if !unsupported.is_empty() {
+                return Err(VmError::UpdateRequiresReboot(unsupported));
+            }
+
+            vm.update(&config, self.console_info.as_ref())
+                .inspect_err(|e| error!("Error when updating VM: {:?}", e))?;
+
+            VmUpdateResponse {
+                applied: live,
+                pending: on_reboot,
+            }
+        } else {
+            // Anything can be changed until the VM boots.
+            let mut vm_config = self.vm_config.as_ref().unwrap().lock().unwrap();
+            config.preserved_fds = vm_config.preserved_fds.take();
+            *vm_config = config;
+
+            let mut pending = [live, on_reboot, unsupported].concat();
+            pending.sort();
+            VmUpdateResponse {
+                applied: Vec::new(),
+                pending,
+            }
+        };
+
+        serde_json::to_vec(&response)
+            .map(Some)
+            .map_err(VmError::SerializeJson)
+    }
+
+    fn vm_resize_disk(&mut self, id: String, desired_size: u64) -> result::Result<(), VmError> {
+        self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
+
+        // Only a running VM has disk devices whose capacity can be updated.
+        let vm = self.vm.as_mut().ok_or(VmError::VmNotRunning)?;
+        if let Err(e) = vm.resize_disk(&id, desired_size) {
+            error!("Error when resizing disk: {:?}", e);
+            Err(e)
+        } else {
+            Ok(())
+        }
+    }
+
     fn vm_add_device(
         &mut self,
         device_cfg: DeviceConfig,
@@ -2108,6 +2868,34 @@ impl RequestHandler for Vmm {
         }
     }
 
+    fn vm_balloon_statistics(&mut self) -> result::Result<Option<Vec<u8>>, VmError> {
+        if let Some(ref vm) = self.vm {
+            let statistics = vm.balloon_statistics().map_err(|e| {
+                error!("Error when getting balloon statistics from the VM: {:?}", e);
+                e
+            })?;
+            serde_json::to_vec(&statistics)
+                .map(Some)
+                .map_err(VmError::SerializeJson)
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_vcpu_stats(&mut self) -> result::Result<Option<Vec<u8>>, VmError> {
+        if let Some(ref vm) = self.vm {
+            let stats = vm.vcpu_stats().map_err(|e| {
+                error!("Error when getting vCPU statistics from the VM: {:?}", e);
+                e
+            })?;
+            serde_json::to_vec(&stats)
+                .map(Some)
+                .map_err(VmError::SerializeJson)
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
     fn vm_power_button(&mut self) -> result::Result<(), VmError> {
         if let Some(ref mut vm) = self.vm {
             vm.power_button()
@@ -2133,127 +2921,10 @@ impl RequestHandler for Vmm {
             receive_data_migration.receiver_url
         );
 
-        // Accept the connection and get the socket
-        let mut socket = Vmm::receive_migration_socket(&receive_data_migration.receiver_url)?;
-
-        let mut started = false;
-        let mut memory_manager: Option<Arc<Mutex<MemoryManager>>> = None;
-        let mut existing_memory_files = None;
-        loop {
-            let req = Request::read_from(&mut socket)?;
-            match req.command() {
-                Command::Invalid => info!("Invalid Command Received"),
-                Command::Start => {
-                    info!("Start Command Received");
-                    started = true;
-
-                    Response::ok().write_to(&mut socket)?;
-                }
-                Command::Config => {
-                    info!("Config Command Received");
-
-                    if !started {
-                        warn!("Migration not started yet");
-                        Response::error().write_to(&mut socket)?;
-                        continue;
-                    }
-                    memory_manager = Some(self.vm_receive_config(
-                        &req,
-                        &mut socket,
-                        existing_memory_files.take(),
-                    )?);
-                }
-                Command::State => {
-                    info!("State Command Received");
-
-                    if !started {
-                        warn!("Migration not started yet");
-                        Response::error().write_to(&mut socket)?;
-                        continue;
-                    }
-                    if let Some(mm) = memory_manager.take() {
-                        self.vm_receive_state(&req, &mut socket, mm)?;
-                    } else {
-                        warn!("Configuration not sent yet");
-                        Response::error().write_to(&mut socket)?;
-                    }
-                }
-                Command::Memory => {
-                    info!("Memory Command Received");
-
-                    if !started {
-                        warn!("Migration not started yet");
-                        Response::error().write_to(&mut socket)?;
-                        continue;
-                    }
-                    if let Some(mm) = memory_manager.as_ref() {
-                        self.vm_receive_memory(&req, &mut socket, &mut mm.lock().unwrap())?;
-                    } else {
-                        warn!("Configuration not sent yet");
-                        Response::error().write_to(&mut socket)?;
-                    }
-                }
-                Command::MemoryFd => {
-                    info!("MemoryFd Command Received");
-
-                    if !started {
-                        warn!("Migration not started yet");
-                        Response::error().write_to(&mut socket)?;
-                        continue;
-                    }
-
-                    match &mut socket {
-                        SocketStream::Unix(unix_socket) => {
-                            let mut buf = [0u8; 4];
-                            let (_, file) = unix_socket.recv_with_fd(&mut buf).map_err(|e| {
-                                MigratableError::MigrateReceive(anyhow!(
-                                    "Error receiving slot from socket: {}",
-                                    e
-                                ))
-                            })?;
-
-                            if existing_memory_files.is_none() {
-                                existing_memory_files = Some(HashMap::default())
-                            }
-
-                            if let Some(ref mut existing_memory_files) = existing_memory_files {
-                                let slot = u32::from_le_bytes(buf);
-                                existing_memory_files.insert(slot, file.unwrap());
-                            }
-
-                            Response::ok().write_to(&mut socket)?;
-                        }
-                        SocketStream::Tcp(_tcp_socket) => {
-                            // For TCP sockets, we cannot transfer file descriptors
-                            warn!(
-                                "MemoryFd command received over TCP socket, which is not supported"
-                            );
-                            Response::error().write_to(&mut socket)?;
-                        }
-                    }
-                }
-                Command::Complete => {
-                    info!("Complete Command Received");
-                    if let Some(ref mut vm) = self.vm.as_mut() {
-                        vm.resume()?;
-                        Response::ok().write_to(&mut socket)?;
-                    } else {
-                        warn!("VM not created yet");
-                        Response::error().write_to(&mut socket)?;
-                    }
-                    break;
-                }
-                Command::Abandon => {
-                    info!("Abandon Command Received");
-                    self.vm = None;
-                    self.vm_config = None;
-                    Response::ok().write_to(&mut socket).ok();
-                    break;
-                }
-            }
-        }
-
-        Ok(())
+        migration_start(self.selected_vm_id(), MigrationRole::Destination);
+        let result = self.receive_migration(receive_data_migration);
+        migration_finish(&result);
+        result
     }
 
     fn vm_send_migration(
@@ -2265,56 +2936,55 @@ impl RequestHandler for Vmm {
             send_data_migration.destination_url, send_data_migration.local
         );
 
-        if !self
-            .vm_config
-            .as_ref()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .backed_by_shared_memory()
-            && send_data_migration.local
-        {
-            return Err(MigratableError::MigrateSend(anyhow!(
-                "Local migration requires shared memory or hugepages enabled"
-            )));
-        }
-
-        if let Some(vm) = self.vm.as_mut() {
-            Self::send_migration(
-                vm,
-                #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
-                self.hypervisor.clone(),
-                send_data_migration.clone(),
-            )
-            .map_err(|migration_err| {
-                error!("Migration failed: {:?}", migration_err);
+        migration_start(self.selected_vm_id(), MigrationRole::Source);
+        let result = self.send_migration_and_shutdown(send_data_migration);
+        migration_finish(&result);
+        result
+    }
 
-                // Stop logging dirty pages only for non-local migrations
-                if !send_data_migration.local {
-                    if let Err(e) = vm.stop_dirty_log() {
-                        return e;
-                    }
-                }
+    fn vm_migration_status(&mut self) -> result::Result<Option<Vec<u8>>, VmError> {
+        serde_json::to_vec(&migration::migration_status(self.selected_vm_id()))
+            .map(Some)
+            .map_err(VmError::SerializeJson)
+    }
 
-                if vm.get_state().unwrap() == VmState::Paused {
-                    if let Err(e) = vm.resume() {
-                        return e;
-                    }
-                }
+    fn vm_migration_cancel(&mut self) -> result::Result<(), MigratableError> {
+        migration::migration_cancel(self.selected_vm_id())
+    }
 
-                migration_err
-            })?;
+    fn vm_metrics(&mut self) -> result::Result<VmMetricsResponse, VmError> {
+        let Some(vm) = &self.vm else {
+            return Err(VmError::VmNotRunning);
+        };
 
-            // Shutdown the VM after the migration succeeded
-            self.exit_evt.write(1).map_err(|e| {
-                MigratableError::MigrateSend(anyhow!(
-                    "Failed shutting down the VM after migration: {:?}",
-                    e
-                ))
-            })
-        } else {
-            Err(MigratableError::MigrateSend(anyhow!("VM is not running")))
-        }
+        let memory_total_size = vm.get_config().lock().unwrap().memory.total_size();
+        let balloon_size = vm.balloon_size();
+        let to_metrics = |counters: HashMap<&'static str, Wrapping<u64>>| {
+            counters
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.0))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let vcpu_stats = vm.vcpu_stats()?;
+        let counters = vm
+            .counters()?
+            .into_iter()
+            .filter(|(id, _)| !vcpu_stats.contains_key(id))
+            .map(|(id, counters)| (id, to_metrics(counters)))
+            .collect();
+        let vcpu_counters = vcpu_stats
+            .into_iter()
+            .map(|(id, counters)| (id, to_metrics(counters)))
+            .collect();
+
+        Ok(VmMetricsResponse {
+            state: vm.get_state()?,
+            memory_total_size,
+            memory_actual_size: memory_total_size.saturating_sub(balloon_size),
+            balloon_size,
+            counters,
+            vcpu_counters,
+        })
     }
 }
 
@@ -2325,6 +2995,7 @@ const DEVICE_MANAGER_SNAPSHOT_ID: &str = "device-manager";
 #[cfg(test)]
 mod unit_tests {
     use super::*;
+    use crate::config_check::ConfigCheck;
     #[cfg(target_arch = "x86_64")]
     use crate::vm_config::DebugConsoleConfig;
     use crate::vm_config::{
@@ -2387,9 +3058,11 @@ mod unit_tests {
             rng: RngConfig {
                 src: PathBuf::from("/dev/urandom"),
                 iommu: false,
+                rate_limiter_config: None,
             },
             balloon: None,
             fs: None,
+            shares: None,
             pmem: None,
             serial: ConsoleConfig {
                 file: None,
@@ -2440,6 +3113,59 @@ mod unit_tests {
         ));
     }
 
+    #[test]
+    fn test_vmm_create_vm() {
+        let mut vmm = create_dummy_vmm();
+        let config = create_dummy_vm_config();
+
+        assert!(matches!(
+            vmm.vmm_create_vm(Some("bad/id".to_owned()), config.clone()),
+            Err(VmError::InvalidVmId(_))
+        ));
+        assert_eq!(
+            vmm.vmm_create_vm(Some("test-create-vm".to_owned()), config.clone())
+                .unwrap(),
+            "test-create-vm"
+        );
+        assert!(matches!(
+            vmm.vmm_create_vm(Some("test-create-vm".to_owned()), config.clone()),
+            Err(VmError::VmAlreadyCreated)
+        ));
+        assert!(api::vm_api_sender("test-create-vm").is_some());
+
+        // The default VM is left untouched
+        assert!(vmm.vm_config.is_none());
+        assert!(matches!(vmm.vm_create(config), Ok(())));
+
+        vmm.select_vm("test-create-vm");
+        assert!(vmm.vm_delete().is_ok());
+        vmm.select_vm(DEFAULT_VM_ID);
+        vmm.reap_vm("test-create-vm");
+        assert!(vmm.vms.is_empty());
+        assert!(api::vm_api_sender("test-create-vm").is_none());
+        assert!(vmm.vm_config.is_some());
+    }
+
+    #[test]
+    fn test_vmm_validate_config() {
+        let vmm = create_dummy_vmm();
+        let mut config = create_dummy_vm_config();
+        config.cpus.boot_vcpus = 2;
+
+        let report = vmm.vmm_validate_config(config);
+        assert!(report
+            .errors
+            .iter()
+            .any(|issue| issue.check == ConfigCheck::Validation));
+        assert!(report
+            .errors
+            .iter()
+            .any(|issue| issue.check == ConfigCheck::File && issue.message.contains("kernel")));
+
+        // Nothing is created by the check
+        assert!(vmm.vm_config.is_none());
+    }
+
     #[test]
     fn test_vmm_vm_cold_add_device() {
         let mut vmm = create_dummy_vmm();
@@ -47,6 +47,7 @@ use vm_migration::protocol::{MemoryRange, MemoryRangeTable};
 use vm_migration::{
     Migratable, MigratableError, Pausable, Snapshot, SnapshotData, Snapshottable, Transportable,
 };
+use vmm_sys_util::eventfd::EventFd;
 
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use crate::coredump::{
@@ -89,6 +90,9 @@ struct HotPlugState {
     active: bool,
     inserting: bool,
     removing: bool,
+    // The VMM asked the guest to eject this DIMM.
+    #[serde(default)]
+    unplugging: bool,
 }
 
 pub struct VirtioMemZone {
@@ -200,6 +204,11 @@ pub struct MemoryManager {
     pub acpi_address: Option<GuestAddress>,
     #[cfg(target_arch = "aarch64")]
     uefi_flash: Option<GuestMemoryAtomic<GuestMemoryMmap>>,
+
+    // Regions ejected by the guest, waiting for the devices to stop
+    // referencing them before they can be unmapped from the VMM.
+    unplugged_regions: Vec<Arc<GuestRegionMmap>>,
+    memory_unplug_evt: Option<EventFd>,
 }
 
 #[derive(Debug)]
@@ -228,6 +237,12 @@ pub enum Error {
     /// The requested hotplug memory addition is not a valid size
     InvalidSize,
 
+    /// The requested memory removal does not match whole hotplugged DIMMs
+    InvalidHotunplugSize,
+
+    /// Could not find the guest RAM mapping of a hotplugged DIMM
+    MissingGuestRamMapping(u64),
+
     /// Failed to create the user memory region.
     CreateUserMemoryRegion(hypervisor::HypervisorVmError),
 
@@ -496,7 +511,12 @@ impl BusDevice for MemoryManager {
                     }
                     // Trigger removal of "DIMM"
                     if data[0] & (1 << EJECT_FLAG) == 1 << EJECT_FLAG {
-                        warn!("Ejection of memory not currently supported");
+                        if let Err(e) = self.hotunplug_ram_region(self.selected_slot) {
+                            error!(
+                                "Failed ejecting memory slot {}: {:?}",
+                                self.selected_slot, e
+                            );
+                        }
                     }
                 } else {
                     warn!("Out of range memory slot: {}", self.selected_slot);
@@ -1232,6 +1252,8 @@ impl MemoryManager {
             #[cfg(target_arch = "aarch64")]
             uefi_flash: None,
             thp: config.thp,
+            unplugged_regions: Vec::new(),
+            memory_unplug_evt: None,
         };
 
         #[cfg(target_arch = "aarch64")]
@@ -1663,6 +1685,14 @@ impl MemoryManager {
         Ok(region)
     }
 
+    fn update_next_hotplug_slot(&mut self) {
+        self.next_hotplug_slot = self
+            .hotplug_slots
+            .iter()
+            .position(|slot| !slot.active)
+            .unwrap_or(HOTPLUG_COUNT);
+    }
+
     fn hotplug_ram_region(&mut self, size: usize) -> Result<Arc<GuestRegionMmap>, Error> {
         info!("Hotplugging new RAM: {}", size);
 
@@ -1706,11 +1736,141 @@ impl MemoryManager {
         slot.base = region.start_addr().0;
         slot.length = region.len();
 
-        self.next_hotplug_slot += 1;
+        self.update_next_hotplug_slot();
 
         Ok(region)
     }
 
+    // Ask the guest to eject the most recently hotplugged DIMMs until the
+    // RAM size matches the desired one. Returns true if any DIMM is being
+    // unplugged.
+    fn request_hotunplug_ram_regions(&mut self, desired_ram: u64) -> Result<bool, Error> {
+        let unplugging: u64 = self
+            .hotplug_slots
+            .iter()
+            .filter(|slot| slot.unplugging)
+            .map(|slot| slot.length)
+            .sum();
+        let target_ram = self.current_ram - unplugging;
+        if desired_ram >= target_ram {
+            return Ok(false);
+        }
+        if desired_ram < self.boot_ram {
+            return Err(Error::InvalidHotunplugSize);
+        }
+
+        let mut candidates: Vec<usize> = (0..self.hotplug_slots.len())
+            .filter(|i| self.hotplug_slots[*i].active && !self.hotplug_slots[*i].unplugging)
+            .collect();
+        candidates.sort_by_key(|i| std::cmp::Reverse(self.hotplug_slots[*i].base));
+
+        let mut remaining = target_ram - desired_ram;
+        let mut slots = Vec::new();
+        for i in candidates {
+            if remaining < self.hotplug_slots[i].length {
+                break;
+            }
+            remaining -= self.hotplug_slots[i].length;
+            slots.push(i);
+        }
+        if remaining != 0 {
+            return Err(Error::InvalidHotunplugSize);
+        }
+
+        for i in slots {
+            info!("Requesting removal of memory slot {}", i);
+            let slot = &mut self.hotplug_slots[i];
+            slot.removing = true;
+            slot.unplugging = true;
+        }
+
+        Ok(true)
+    }
+
+    // Remove a DIMM ejected by the guest from the guest address space. The
+    // devices are notified through the memory unplug event so that they
+    // stop referencing the region.
+    fn hotunplug_ram_region(&mut self, slot_id: usize) -> Result<(), Error> {
+        let slot = &self.hotplug_slots[slot_id];
+        if !slot.active || !slot.unplugging {
+            warn!(
+                "Ignoring ejection of memory slot {} not being unplugged",
+                slot_id
+            );
+            return Ok(());
+        }
+        let (base, length) = (slot.base, slot.length);
+        info!("Hot-unplugging RAM: {:x} {}", base, length);
+
+        let mapping_index = self
+            .guest_ram_mappings
+            .iter()
+            .position(|mapping| mapping.gpa == base && mapping.size == length)
+            .ok_or(Error::MissingGuestRamMapping(base))?;
+        let userspace_addr = self
+            .guest_memory
+            .memory()
+            .find_region(GuestAddress(base))
+            .map(|region| region.as_ptr() as u64)
+            .ok_or(Error::MissingGuestRamMapping(base))?;
+
+        // Remove it from the hypervisor first so that the guest can't access
+        // the memory anymore.
+        let memory_slot = self.guest_ram_mappings[mapping_index].slot;
+        self.remove_userspace_mapping(base, length, userspace_addr, self.mergeable, memory_slot)?;
+        self.memory_slot_allocator().free_memory_slot(memory_slot);
+        self.guest_ram_mappings.remove(mapping_index);
+
+        let (guest_memory, region) = self
+            .guest_memory
+            .memory()
+            .remove_region(GuestAddress(base), length)
+            .map_err(Error::GuestMemory)?;
+        self.guest_memory.lock().unwrap().replace(guest_memory);
+
+        if let Some(memory_zone) = self.memory_zones.get_mut(DEFAULT_MEMORY_ZONE) {
+            memory_zone
+                .regions
+                .retain(|r| r.start_addr() != region.start_addr());
+        }
+        self.ram_allocator.free(GuestAddress(base), length);
+
+        self.hotplug_slots[slot_id] = HotPlugState::default();
+        self.update_next_hotplug_slot();
+        self.current_ram -= length;
+
+        self.unplugged_regions.push(region);
+        if let Some(memory_unplug_evt) = &self.memory_unplug_evt {
+            memory_unplug_evt.write(1).map_err(Error::EventfdError)?;
+        }
+
+        event!(
+            "vm",
+            "memory-unplugged",
+            "base",
+            format!("{base:#x}"),
+            "length",
+            length.to_string()
+        );
+
+        Ok(())
+    }
+
+    pub fn set_memory_unplug_evt(&mut self, memory_unplug_evt: EventFd) {
+        self.memory_unplug_evt = Some(memory_unplug_evt);
+    }
+
+    /// Take the regions ejected by the guest, once dropped they are unmapped
+    /// from the VMM address space.
+    pub fn take_unplugged_regions(&mut self) -> Vec<Arc<GuestRegionMmap>> {
+        std::mem::take(&mut self.unplugged_regions)
+    }
+
+    /// Whether the guest still has to be notified about DIMMs to eject.
+    pub fn hotunplug_pending(&self) -> bool {
+        self.hotplug_slots.iter().any(|slot| slot.removing)
+    }
+
     pub fn guest_memory(&self) -> GuestMemoryAtomic<GuestMemoryMmap> {
         self.guest_memory.clone()
     }
@@ -1930,6 +2090,14 @@ impl MemoryManager {
                     region =
                         Some(self.hotplug_ram_region((desired_ram - self.current_ram) as usize)?);
                     self.current_ram = desired_ram;
+                } else if desired_ram < self.current_ram {
+                    if !self.dynamic {
+                        return Ok(region);
+                    }
+
+                    // The RAM size is only updated once the guest ejects
+                    // the DIMMs.
+                    self.request_hotunplug_ram_regions(desired_ram)?;
                 }
             }
         }
@@ -2322,6 +2490,14 @@ impl Aml for MemorySlot {
                         vec![&self.slot_id],
                     ))],
                 ),
+                // Trigger memory ejection
+                &aml::Method::new(
+                    "_EJ0".into(),
+                    1,
+                    false,
+                    // Call into MDEJ method which will actually eject device
+                    vec![&aml::MethodCall::new("MDEJ".into(), vec![&self.slot_id])],
+                ),
             ],
         )
         .to_aml_bytes(sink)
@@ -2429,6 +2605,24 @@ impl Aml for MemoryMethods {
         )
         .to_aml_bytes(sink);
 
+        // Memory eject method
+        aml::Method::new(
+            "MDEJ".into(),
+            1,
+            true,
+            vec![
+                // Take lock defined above
+                &aml::Acquire::new("MLCK".into(), 0xffff),
+                // Write slot number (in first argument) to I/O port via field
+                &aml::Store::new(&aml::Path::new("\\_SB_.MHPC.MSEL"), &aml::Arg(0)),
+                // Set MEJ0 bit
+                &aml::Store::new(&aml::Path::new("\\_SB_.MHPC.MEJ0"), &aml::ONE),
+                // Release lock
+                &aml::Release::new("MLCK".into()),
+            ],
+        )
+        .to_aml_bytes(sink);
+
         // Memory range method
         aml::Method::new(
             "MCRS".into(),
@@ -2796,3 +2990,141 @@ impl Migratable for MemoryManager {
         Ok(table)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1 << 20;
+    const BOOT_RAM: u64 = 128 * MIB;
+    const DIMM_SIZE: u64 = 128 * MIB;
+
+    fn create_memory_manager() -> Arc<Mutex<MemoryManager>> {
+        let hv = hypervisor::new().unwrap();
+        let vm = hv.create_vm().unwrap();
+        let config = MemoryConfig {
+            size: BOOT_RAM,
+            hotplug_size: Some(4 * DIMM_SIZE),
+            ..Default::default()
+        };
+
+        MemoryManager::new(
+            vm,
+            &config,
+            None,
+            46,
+            #[cfg(feature = "tdx")]
+            false,
+            None,
+            None,
+            #[cfg(target_arch = "x86_64")]
+            None,
+        )
+        .unwrap()
+    }
+
+    // Hotplug `count` DIMMs, one at a time
+    fn hotplug_dimms(memory_manager: &mut MemoryManager, count: u64) {
+        for i in 1..=count {
+            assert!(memory_manager
+                .resize(BOOT_RAM + i * DIMM_SIZE)
+                .unwrap()
+                .is_some());
+        }
+    }
+
+    fn eject(memory_manager: &mut MemoryManager, slot: u8) {
+        memory_manager.write(0, SELECTION_OFFSET, &[slot]);
+        memory_manager.write(0, STATUS_OFFSET, &[1 << EJECT_FLAG]);
+    }
+
+    #[test]
+    fn test_hotunplug_slot_choice() {
+        let memory_manager = create_memory_manager();
+        let mut memory_manager = memory_manager.lock().unwrap();
+        hotplug_dimms(&mut memory_manager, 3);
+
+        // Only whole DIMMs can be removed
+        assert!(matches!(
+            memory_manager.resize(BOOT_RAM + DIMM_SIZE + DIMM_SIZE / 2),
+            Err(Error::InvalidHotunplugSize)
+        ));
+        // The boot RAM can't be removed
+        assert!(matches!(
+            memory_manager.resize(BOOT_RAM / 2),
+            Err(Error::InvalidHotunplugSize)
+        ));
+        assert!(!memory_manager.hotunplug_pending());
+
+        // The most recently hotplugged DIMMs go first
+        assert!(memory_manager
+            .resize(BOOT_RAM + DIMM_SIZE)
+            .unwrap()
+            .is_none());
+        let unplugging: Vec<bool> = memory_manager
+            .hotplug_slots
+            .iter()
+            .take(3)
+            .map(|slot| slot.unplugging && slot.removing)
+            .collect();
+        assert_eq!(unplugging, vec![false, true, true]);
+        assert!(memory_manager.hotunplug_pending());
+        // Nothing changes until the guest ejects the DIMMs
+        assert_eq!(memory_manager.current_ram, BOOT_RAM + 3 * DIMM_SIZE);
+
+        // DIMMs already being unplugged are accounted for
+        memory_manager.resize(BOOT_RAM).unwrap();
+        assert!(memory_manager.hotplug_slots[0].unplugging);
+        memory_manager.resize(BOOT_RAM + DIMM_SIZE).unwrap();
+        assert!(memory_manager.hotplug_slots[0].unplugging);
+    }
+
+    #[test]
+    fn test_hotunplug_eject() {
+        let memory_manager = create_memory_manager();
+        let mut memory_manager = memory_manager.lock().unwrap();
+        hotplug_dimms(&mut memory_manager, 2);
+        let base = memory_manager.hotplug_slots[1].base;
+
+        // Ejecting a DIMM which was not requested is ignored
+        eject(&mut memory_manager, 1);
+        assert!(memory_manager.hotplug_slots[1].active);
+        assert_eq!(memory_manager.current_ram, BOOT_RAM + 2 * DIMM_SIZE);
+        assert!(memory_manager.take_unplugged_regions().is_empty());
+
+        memory_manager.resize(BOOT_RAM + DIMM_SIZE).unwrap();
+        // The guest acknowledges the removal request, then ejects
+        memory_manager.write(0, SELECTION_OFFSET, &[1]);
+        memory_manager.write(0, STATUS_OFFSET, &[1 << REMOVING_FLAG]);
+        assert!(!memory_manager.hotunplug_pending());
+        eject(&mut memory_manager, 1);
+
+        assert!(!memory_manager.hotplug_slots[1].active);
+        assert!(memory_manager.hotplug_slots[0].active);
+        assert_eq!(memory_manager.current_ram, BOOT_RAM + DIMM_SIZE);
+        assert!(memory_manager
+            .guest_memory
+            .memory()
+            .find_region(GuestAddress(base))
+            .is_none());
+        let regions = memory_manager.take_unplugged_regions();
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].start_addr(), GuestAddress(base));
+        assert_eq!(regions[0].len(), DIMM_SIZE);
+
+        // A second eject of the same slot is ignored
+        eject(&mut memory_manager, 1);
+        assert_eq!(memory_manager.current_ram, BOOT_RAM + DIMM_SIZE);
+        assert!(memory_manager.take_unplugged_regions().is_empty());
+
+        // The freed slot and address range get reused
+        assert_eq!(
+            memory_manager
+                .resize(BOOT_RAM + 2 * DIMM_SIZE)
+                .unwrap()
+                .unwrap()
+                .start_addr(),
+            GuestAddress(base)
+        );
+    }
+}
@@ -5,8 +5,13 @@
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use vm_migration::protocol::MemoryRangeTable;
 use vm_migration::{MigratableError, Snapshot};
 
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
@@ -17,6 +22,256 @@ use crate::vm_config::VmConfig;
 pub const SNAPSHOT_STATE_FILE: &str = "state.json";
 pub const SNAPSHOT_CONFIG_FILE: &str = "config.json";
 
+// The VMM thread is busy for the whole duration of a migration, so the
+// progress is kept here for the API threads to read it directly. Only one
+// migration can happen at a time, the VM it belongs to is recorded with it.
+static MIGRATION_PROGRESS: Lazy<Mutex<MigrationProgress>> = Lazy::new(Mutex::default);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MigrationRole {
+    Source,
+    Destination,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MigrationPhase {
+    /// No migration happened yet
+    #[default]
+    None,
+    /// Connection and configuration exchange
+    Setup,
+    /// First copy of the guest memory
+    Memory,
+    /// Iterative copy of the memory dirtied by the running guest
+    DirtyMemory,
+    /// VM paused, sending the last dirty pages and the device state
+    StopAndCopy,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl MigrationPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationPhase::None => "none",
+            MigrationPhase::Setup => "setup",
+            MigrationPhase::Memory => "memory",
+            MigrationPhase::DirtyMemory => "dirty-memory",
+            MigrationPhase::StopAndCopy => "stop-and-copy",
+            MigrationPhase::Completed => "completed",
+            MigrationPhase::Failed => "failed",
+            MigrationPhase::Cancelled => "cancelled",
+        }
+    }
+
+    fn in_progress(&self) -> bool {
+        matches!(
+            self,
+            MigrationPhase::Setup
+                | MigrationPhase::Memory
+                | MigrationPhase::DirtyMemory
+                | MigrationPhase::StopAndCopy
+        )
+    }
+}
+
+/// Progress of the ongoing migration, or outcome of the last one.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MigrationStatus {
+    pub role: Option<MigrationRole>,
+    pub phase: MigrationPhase,
+    /// Number of passes over the guest memory
+    pub iteration: u64,
+    pub transferred_bytes: u64,
+    /// Estimated amount of memory dirtied during the last pass
+    pub remaining_bytes: u64,
+    /// Bytes per second dirtied by the guest
+    pub dirty_rate: u64,
+    /// Bytes per second sent during the last pass
+    pub transfer_rate: u64,
+    pub expected_downtime_ms: u64,
+    pub elapsed_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct MigrationProgress {
+    vm_id: String,
+    status: MigrationStatus,
+    start: Option<Instant>,
+    last_pass: Option<Duration>,
+    cancel: bool,
+    // The state is being sent, the destination is about to take over.
+    committed: bool,
+}
+
+fn migration_event(role: MigrationRole, phase: MigrationPhase) {
+    let role = match role {
+        MigrationRole::Source => "source",
+        MigrationRole::Destination => "destination",
+    };
+    event!("vm", &format!("migration-{}", phase.as_str()), "role", role);
+}
+
+impl MigrationProgress {
+    fn cancel(&mut self) -> Result<(), MigratableError> {
+        if self.status.role != Some(MigrationRole::Source) || !self.status.phase.in_progress() {
+            return Err(MigratableError::MigrateSend(anyhow!(
+                "No outgoing migration in progress"
+            )));
+        }
+        if self.committed {
+            return Err(MigratableError::MigrateSend(anyhow!(
+                "The VM state has been sent, the migration can no longer be cancelled"
+            )));
+        }
+        self.cancel = true;
+        Ok(())
+    }
+}
+
+/// Progress of the ongoing or last migration of the VM `vm_id`, the other
+/// VMs reporting no migration.
+pub fn migration_status(vm_id: &str) -> MigrationStatus {
+    let progress = MIGRATION_PROGRESS.lock().unwrap();
+    if progress.vm_id != vm_id {
+        return MigrationStatus::default();
+    }
+    let mut status = progress.status.clone();
+    if let Some(start) = progress.start {
+        status.elapsed_ms = start.elapsed().as_millis() as u64;
+    }
+    status
+}
+
+/// Asks the outgoing migration of the VM `vm_id` to be abandoned. This is
+/// only noticed between two steps of the migration, the source VM being
+/// resumed afterwards. It is refused once the VM state has been sent.
+pub fn migration_cancel(vm_id: &str) -> Result<(), MigratableError> {
+    let mut progress = MIGRATION_PROGRESS.lock().unwrap();
+    if progress.vm_id != vm_id {
+        return Err(MigratableError::MigrateSend(anyhow!(
+            "No outgoing migration of VM {} in progress",
+            vm_id
+        )));
+    }
+    progress.cancel()
+}
+
+/// Same as `migration_cancel()`, whichever VM is being sent.
+pub(crate) fn migration_cancel_ongoing() -> Result<(), MigratableError> {
+    MIGRATION_PROGRESS.lock().unwrap().cancel()
+}
+
+pub(crate) fn migration_cancelled() -> bool {
+    MIGRATION_PROGRESS.lock().unwrap().cancel
+}
+
+/// Stops accepting cancellations before the VM state is sent, returning
+/// whether the migration was cancelled until then.
+pub(crate) fn migration_commit() -> bool {
+    let mut progress = MIGRATION_PROGRESS.lock().unwrap();
+    progress.committed = true;
+    progress.cancel
+}
+
+pub(crate) fn migration_start(vm_id: &str, role: MigrationRole) {
+    *MIGRATION_PROGRESS.lock().unwrap() = MigrationProgress {
+        vm_id: vm_id.to_owned(),
+        status: MigrationStatus {
+            role: Some(role),
+            phase: MigrationPhase::Setup,
+            ..Default::default()
+        },
+        start: Some(Instant::now()),
+        ..Default::default()
+    };
+    migration_event(role, MigrationPhase::Setup);
+}
+
+pub(crate) fn migration_set_phase(phase: MigrationPhase) {
+    let mut progress = MIGRATION_PROGRESS.lock().unwrap();
+    if progress.status.phase == phase {
+        return;
+    }
+    progress.status.phase = phase;
+    if let Some(role) = progress.status.role {
+        migration_event(role, phase);
+    }
+}
+
+pub(crate) fn memory_table_bytes(table: &MemoryRangeTable) -> u64 {
+    table.regions().iter().map(|r| r.length).sum()
+}
+
+/// Accounts for a pass over the guest memory, `bytes` having been copied in
+/// `duration`.
+pub(crate) fn migration_memory_pass(bytes: u64, duration: Duration) {
+    let mut progress = MIGRATION_PROGRESS.lock().unwrap();
+    let duration = duration.max(Duration::from_millis(1));
+    let status = &mut progress.status;
+    status.iteration += 1;
+    status.transferred_bytes += bytes;
+    status.transfer_rate = (bytes as f64 / duration.as_secs_f64()) as u64;
+
+    // What is sent by a pass is what got dirtied while the previous one was
+    // running, which in turn estimates what this pass left behind.
+    if let Some(last_pass) = progress.last_pass {
+        let status = &mut progress.status;
+        status.dirty_rate = (bytes as f64 / last_pass.as_secs_f64()) as u64;
+        status.remaining_bytes = (status.dirty_rate as f64 * duration.as_secs_f64()) as u64;
+        if let Some(downtime_ms) = (status.remaining_bytes * 1000).checked_div(status.transfer_rate)
+        {
+            status.expected_downtime_ms = downtime_ms;
+        }
+    }
+    progress.last_pass = Some(duration);
+}
+
+/// Accounts for memory received by the destination, the first pass being
+/// the full copy and the next ones the dirty pages.
+pub(crate) fn migration_memory_received(bytes: u64) {
+    let phase = {
+        let mut progress = MIGRATION_PROGRESS.lock().unwrap();
+        progress.status.iteration += 1;
+        progress.status.transferred_bytes += bytes;
+        if progress.status.iteration == 1 {
+            MigrationPhase::Memory
+        } else {
+            MigrationPhase::DirtyMemory
+        }
+    };
+    migration_set_phase(phase);
+}
+
+/// Marks the migration as abandoned, either through `vm.migration-cancel` or
+/// because the source gave up.
+pub(crate) fn migration_abandoned() {
+    MIGRATION_PROGRESS.lock().unwrap().cancel = true;
+}
+
+pub(crate) fn migration_finish(result: &Result<(), MigratableError>) {
+    let mut progress = MIGRATION_PROGRESS.lock().unwrap();
+    let phase = match result {
+        Ok(()) => MigrationPhase::Completed,
+        Err(_) if progress.cancel => MigrationPhase::Cancelled,
+        Err(e) => {
+            progress.status.error = Some(e.to_string());
+            MigrationPhase::Failed
+        }
+    };
+    if let Some(start) = progress.start.take() {
+        progress.status.elapsed_ms = start.elapsed().as_millis() as u64;
+    }
+    progress.status.phase = phase;
+    if let Some(role) = progress.status.role {
+        migration_event(role, phase);
+    }
+}
+
 pub fn url_to_path(url: &str) -> std::result::Result<PathBuf, MigratableError> {
     let path: PathBuf = url
         .strip_prefix("file://")
@@ -87,3 +342,35 @@ pub fn get_vm_snapshot(snapshot: &Snapshot) -> std::result::Result<VmSnapshot, M
         "Could not find VM config snapshot section"
     )))
 }
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    // The progress being shared, a single test goes through it.
+    #[test]
+    fn test_migration_progress() {
+        migration_start("vm1", MigrationRole::Source);
+        migration_set_phase(MigrationPhase::DirtyMemory);
+        assert_eq!(migration_status("vm1").phase, MigrationPhase::DirtyMemory);
+        assert_eq!(migration_status("default").phase, MigrationPhase::None);
+
+        // Only the migrating VM can be cancelled.
+        migration_cancel("default").unwrap_err();
+        assert!(!migration_cancelled());
+        migration_cancel("vm1").unwrap();
+        assert!(migration_commit());
+        migration_finish(&Err(MigratableError::MigrateSend(anyhow!("cancelled"))));
+        assert_eq!(migration_status("vm1").phase, MigrationPhase::Cancelled);
+
+        // Past the commit, the migration can't be cancelled and succeeds.
+        migration_start("vm1", MigrationRole::Source);
+        migration_set_phase(MigrationPhase::StopAndCopy);
+        assert!(!migration_commit());
+        migration_cancel("vm1").unwrap_err();
+        migration_finish(&Ok(()));
+        let status = migration_status("vm1");
+        assert_eq!(status.phase, MigrationPhase::Completed);
+        assert_eq!(status.error, None);
+    }
+}
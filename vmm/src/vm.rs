@@ -20,8 +20,7 @@ use std::num::Wrapping;
 use std::ops::Deref;
 use std::os::unix::net::UnixStream;
 use std::sync::{Arc, Mutex, RwLock};
-#[cfg(not(target_arch = "riscv64"))]
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{cmp, result, str, thread};
 
 use anyhow::anyhow;
@@ -55,26 +54,31 @@ use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracer::trace_scoped;
+use virtio_devices::RateLimiterConfig;
 use vm_device::Bus;
 #[cfg(feature = "tdx")]
-use vm_memory::{Address, ByteValued, GuestMemoryRegion, ReadVolatile};
+use vm_memory::{Address, ByteValued, ReadVolatile};
 use vm_memory::{
-    Bytes, GuestAddress, GuestAddressSpace, GuestMemory, GuestMemoryAtomic, WriteVolatile,
+    Bytes, GuestAddress, GuestAddressSpace, GuestMemory, GuestMemoryAtomic, GuestMemoryRegion,
+    WriteVolatile,
 };
-use vm_migration::protocol::{MemoryRangeTable, Request, Response};
+use vm_migration::protocol::{MemoryRange, MemoryRangeTable, Request, Response};
 use vm_migration::{
     snapshot_from_id, Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable,
 };
 use vmm_sys_util::eventfd::EventFd;
 use vmm_sys_util::sock_ctrl_msg::ScmSocket;
 
+use crate::api::VmInfoResponse;
 use crate::config::{add_to_config, ValidationError};
-use crate::console_devices::{ConsoleDeviceError, ConsoleInfo};
+use crate::console_devices::{
+    open_console_file, switch_console_file, ConsoleDeviceError, ConsoleInfo,
+};
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use crate::coredump::{
     CpuElf64Writable, DumpState, Elf64Writable, GuestDebuggable, GuestDebuggableError, NoteDescType,
 };
-use crate::device_manager::{DeviceManager, DeviceManagerError};
+use crate::device_manager::{DeviceCounters, DeviceManager, DeviceManagerError};
 use crate::device_tree::DeviceTree;
 #[cfg(feature = "guest_debug")]
 use crate::gdb::{Debuggable, DebuggableError, GdbRequestPayload, GdbResponsePayload};
@@ -160,6 +164,18 @@ pub enum Error {
     #[error("VM is already created")]
     VmAlreadyCreated,
 
+    #[error("No VM with id {0:?}")]
+    VmNotFound(String),
+
+    #[error("Invalid VM id {0:?}")]
+    InvalidVmId(String),
+
+    #[error("Cannot create EventFd: {0}")]
+    EventFdCreate(#[source] io::Error),
+
+    #[error("Cannot register the VM events: {0}")]
+    VmEventRegister(#[source] io::Error),
+
     #[error("VM is not running")]
     VmNotRunning,
 
@@ -327,6 +343,15 @@ pub enum Error {
 
     #[error("Error creating console devices")]
     CreateConsoleDevices(ConsoleDeviceError),
+
+    #[error("Invalid configuration update: {0}")]
+    InvalidUpdate(#[source] crate::config::Error),
+
+    #[error("Fields can't be updated while the VM is running: {}", .0.join(", "))]
+    UpdateRequiresReboot(Vec<String>),
+
+    #[error("Error switching the console output to another file: {0}")]
+    SwitchConsoleFile(#[source] ConsoleDeviceError),
 }
 pub type Result<T> = result::Result<T, Error>;
 
@@ -459,7 +484,7 @@ pub struct Vm {
     threads: Vec<thread::JoinHandle<()>>,
     device_manager: Arc<Mutex<DeviceManager>>,
     config: Arc<Mutex<VmConfig>>,
-    state: RwLock<VmState>,
+    state: Arc<RwLock<VmState>>,
     cpu_manager: Arc<Mutex<cpu::CpuManager>>,
     memory_manager: Arc<Mutex<MemoryManager>>,
     #[cfg_attr(any(not(feature = "kvm"), target_arch = "aarch64"), allow(dead_code))]
@@ -490,6 +515,7 @@ impl Vm {
         seccomp_action: &SeccompAction,
         hypervisor: Arc<dyn hypervisor::Hypervisor>,
         activate_evt: EventFd,
+        memory_unplug_evt: EventFd,
         #[cfg(not(target_arch = "riscv64"))] timestamp: Instant,
         console_info: Option<ConsoleInfo>,
         console_resize_pipe: Option<Arc<File>>,
@@ -498,6 +524,11 @@ impl Vm {
     ) -> Result<Self> {
         trace_scoped!("Vm::new_from_memory_manager");
 
+        memory_manager
+            .lock()
+            .unwrap()
+            .set_memory_unplug_evt(memory_unplug_evt);
+
         let boot_id_list = config
             .lock()
             .unwrap()
@@ -692,7 +723,7 @@ impl Vm {
             device_manager,
             config,
             threads: Vec::with_capacity(1),
-            state: RwLock::new(vm_state),
+            state: Arc::new(RwLock::new(vm_state)),
             cpu_manager,
             memory_manager,
             vm,
@@ -800,6 +831,7 @@ impl Vm {
         seccomp_action: &SeccompAction,
         hypervisor: Arc<dyn hypervisor::Hypervisor>,
         activate_evt: EventFd,
+        memory_unplug_evt: EventFd,
         console_info: Option<ConsoleInfo>,
         console_resize_pipe: Option<Arc<File>>,
         original_termios: Arc<Mutex<Option<termios>>>,
@@ -880,6 +912,7 @@ impl Vm {
             seccomp_action,
             hypervisor,
             activate_evt,
+            memory_unplug_evt,
             #[cfg(not(target_arch = "riscv64"))]
             timestamp,
             console_info,
@@ -1473,7 +1506,7 @@ impl Vm {
     }
 
     pub fn shutdown(&mut self) -> Result<()> {
-        let mut state = self.state.try_write().map_err(|_| Error::PoisonedState)?;
+        let mut state = self.state.write().map_err(|_| Error::PoisonedState)?;
         let new_state = VmState::Shutdown;
 
         state.valid_transition(new_state)?;
@@ -1500,6 +1533,30 @@ impl Vm {
         Ok(())
     }
 
+    /// Remove the DIMMs ejected by the guest from the devices, and release
+    /// their backing memory.
+    pub fn remove_unplugged_memory(&self) -> Result<()> {
+        let regions = self.memory_manager.lock().unwrap().take_unplugged_regions();
+        let mut result = Ok(());
+        for region in regions.iter() {
+            // The guest ejected the DIMM, so the VM config shrinks now even
+            // if a device fails to release the region.
+            let memory_config = &mut self.config.lock().unwrap().memory;
+            memory_config.size = memory_config.size.saturating_sub(region.len());
+
+            if let Err(e) = self.device_manager.lock().unwrap().remove_memory(region) {
+                error!(
+                    "Failed removing memory region {:#x}: {:?}",
+                    region.start_addr().0,
+                    e
+                );
+                result = Err(Error::DeviceManager(e));
+            }
+        }
+
+        result
+    }
+
     pub fn resize(
         &mut self,
         desired_vcpus: Option<u8>,
@@ -1541,24 +1598,31 @@ impl Vm {
                     .unwrap()
                     .update_memory(new_region)
                     .map_err(Error::DeviceManager)?;
+            }
 
-                match memory_config.hotplug_method {
-                    HotplugMethod::Acpi => {
-                        self.device_manager
-                            .lock()
-                            .unwrap()
-                            .notify_hotplug(AcpiNotificationFlags::MEMORY_DEVICES_CHANGED)
-                            .map_err(Error::DeviceManager)?;
-                    }
-                    HotplugMethod::VirtioMem => {}
-                }
+            // Let the guest know about DIMMs being inserted or waiting to
+            // be ejected.
+            if memory_config.hotplug_method == HotplugMethod::Acpi
+                && (new_region.is_some() || self.memory_manager.lock().unwrap().hotunplug_pending())
+            {
+                self.device_manager
+                    .lock()
+                    .unwrap()
+                    .notify_hotplug(AcpiNotificationFlags::MEMORY_DEVICES_CHANGED)
+                    .map_err(Error::DeviceManager)?;
             }
 
             // We update the VM config regardless of the actual guest resize
             // operation result (happened or not), so that if the VM reboots
-            // it will be running with the last configure memory size.
+            // it will be running with the last configure memory size. With
+            // ACPI, shrinking is only recorded once the guest ejects the
+            // DIMMs, see remove_unplugged_memory().
             match memory_config.hotplug_method {
-                HotplugMethod::Acpi => memory_config.size = desired_memory,
+                HotplugMethod::Acpi => {
+                    if desired_memory > memory_config.size {
+                        memory_config.size = desired_memory;
+                    }
+                }
                 HotplugMethod::VirtioMem => {
                     if desired_memory > memory_config.size {
                         memory_config.hotplugged_size = Some(desired_memory - memory_config.size);
@@ -1624,6 +1688,184 @@ impl Vm {
         Err(Error::ResizeZone)
     }
 
+    pub fn update_rate_limiter(
+        &mut self,
+        id: &str,
+        rate_limiter_config: RateLimiterConfig,
+    ) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .update_rate_limiter(id, rate_limiter_config)
+            .map_err(Error::DeviceManager)?;
+
+        event!("vm", "rate-limiter-updated", "id", id);
+
+        Ok(())
+    }
+
+    /// Apply the changes from `new_config` which can be made to the running
+    /// VM, see `VmConfig::apply_update()`. Fields which can't be updated live
+    /// are expected to be identical to the current configuration. Every
+    /// change is checked before any of them is applied, so that an invalid
+    /// update leaves the VM untouched.
+    pub fn update(
+        &mut self,
+        new_config: &VmConfig,
+        console_info: Option<&ConsoleInfo>,
+    ) -> Result<()> {
+        let old_config = self.config.lock().unwrap().clone();
+
+        let mut rate_limiters = Vec::new();
+        for group in new_config.rate_limit_groups.iter().flatten() {
+            if old_config.rate_limit_groups.iter().flatten().any(|old| {
+                old.id == group.id && old.rate_limiter_config != group.rate_limiter_config
+            }) {
+                rate_limiters.push((group.id.as_str(), group.rate_limiter_config));
+            }
+        }
+        for disk in new_config.disks.iter().flatten() {
+            if let (Some(id), Some(rate_limiter_config)) = (&disk.id, disk.rate_limiter_config) {
+                if old_config.disks.iter().flatten().any(|old| {
+                    old.id.as_ref() == Some(id)
+                        && old.rate_limiter_config != Some(rate_limiter_config)
+                }) {
+                    rate_limiters.push((id.as_str(), rate_limiter_config));
+                }
+            }
+        }
+        for net in new_config.net.iter().flatten() {
+            if let (Some(id), Some(rate_limiter_config)) = (&net.id, net.rate_limiter_config) {
+                if old_config.net.iter().flatten().any(|old| {
+                    old.id.as_ref() == Some(id)
+                        && old.rate_limiter_config != Some(rate_limiter_config)
+                }) {
+                    rate_limiters.push((id.as_str(), rate_limiter_config));
+                }
+            }
+        }
+        {
+            let device_manager = self.device_manager.lock().unwrap();
+            for (id, rate_limiter_config) in &rate_limiters {
+                device_manager
+                    .validate_rate_limiter_update(id, rate_limiter_config)
+                    .map_err(Error::DeviceManager)?;
+            }
+        }
+
+        let balloon_size = match (&old_config.balloon, &new_config.balloon) {
+            (Some(old), Some(new)) if old.size != new.size => Some(new.size),
+            _ => None,
+        };
+        if balloon_size.is_some() && self.device_manager.lock().unwrap().balloon().is_none() {
+            return Err(Error::DeviceManager(
+                DeviceManagerError::MissingVirtioBalloon,
+            ));
+        }
+
+        let console_files = [
+            (
+                console_info.map(|info| &info.serial_main_fd),
+                &old_config.serial.file,
+                &new_config.serial.file,
+            ),
+            (
+                console_info.map(|info| &info.console_main_fd),
+                &old_config.console.file,
+                &new_config.console.file,
+            ),
+            #[cfg(target_arch = "x86_64")]
+            (
+                console_info.map(|info| &info.debug_main_fd),
+                &old_config.debug_console.file,
+                &new_config.debug_console.file,
+            ),
+        ];
+        let mut console_switches = Vec::new();
+        for (output, old_file, new_file) in console_files {
+            if let Some(new_file) = new_file.as_ref().filter(|f| old_file.as_ref() != Some(*f)) {
+                let output =
+                    output.ok_or(Error::SwitchConsoleFile(ConsoleDeviceError::NotFileOutput))?;
+                console_switches.push((output, new_file));
+            }
+        }
+        // Opening the new files is the last step which can fail because of
+        // the update itself, it creates them, so it comes after every other
+        // check.
+        let console_switches = console_switches
+            .into_iter()
+            .map(|(output, path)| {
+                open_console_file(output, path)
+                    .map(|file| (output, file))
+                    .map_err(Error::SwitchConsoleFile)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for (id, rate_limiter_config) in rate_limiters {
+            self.update_rate_limiter(id, rate_limiter_config)?;
+        }
+
+        if let Some(balloon_size) = balloon_size {
+            self.resize(None, None, Some(balloon_size))?;
+        }
+
+        if old_config.cpus.affinity != new_config.cpus.affinity {
+            self.cpu_manager
+                .lock()
+                .unwrap()
+                .set_affinity(new_config.cpus.affinity.as_deref())
+                .map_err(Error::CpuManager)?;
+        }
+
+        for (output, file) in console_switches {
+            switch_console_file(output, &file).map_err(Error::SwitchConsoleFile)?;
+        }
+
+        // Rate limiters and the balloon size were stored along the way,
+        // store the remaining changes so that a snapshot or a reboot picks
+        // them up.
+        let mut config = self.config.lock().unwrap();
+        config.cpus.affinity.clone_from(&new_config.cpus.affinity);
+        config.serial.file.clone_from(&new_config.serial.file);
+        config.console.file.clone_from(&new_config.console.file);
+        #[cfg(target_arch = "x86_64")]
+        config
+            .debug_console
+            .file
+            .clone_from(&new_config.debug_console.file);
+        // The guest only reads the vsock CID and the MTU of its network
+        // devices when it boots.
+        if let (Some(vsock), Some(new_vsock)) = (&mut config.vsock, &new_config.vsock) {
+            vsock.cid = new_vsock.cid;
+        }
+        for net in config.net.iter_mut().flatten() {
+            if let Some(new_net) = new_config
+                .net
+                .iter()
+                .flatten()
+                .find(|new_net| new_net.id.is_some() && new_net.id == net.id)
+            {
+                net.mtu = new_net.mtu;
+            }
+        }
+
+        event!("vm", "updated");
+
+        Ok(())
+    }
+
+    pub fn resize_disk(&mut self, id: &str, desired_size: u64) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .resize_disk(id, desired_size)
+            .map_err(Error::DeviceManager)?;
+
+        event!("vm", "disk-resized", "id", id);
+
+        Ok(())
+    }
+
     pub fn add_device(&mut self, mut device_cfg: DeviceConfig) -> Result<PciDeviceInfo> {
         let pci_device_info = self
             .device_manager
@@ -1836,7 +2078,13 @@ impl Vm {
     }
 
     pub fn counters(&self) -> Result<HashMap<String, HashMap<&'static str, Wrapping<u64>>>> {
-        Ok(self.device_manager.lock().unwrap().counters())
+        let mut counters = self.device_manager.lock().unwrap().counters();
+        counters.extend(self.vcpu_stats()?);
+        Ok(counters)
+    }
+
+    pub fn vcpu_stats(&self) -> Result<HashMap<String, HashMap<&'static str, Wrapping<u64>>>> {
+        Ok(self.cpu_manager.lock().unwrap().counters())
     }
 
     #[cfg(feature = "tdx")]
@@ -2289,7 +2537,7 @@ impl Vm {
             .start_boot_vcpus(new_state == VmState::BreakPoint)
             .map_err(Error::CpuManager)?;
 
-        let mut state = self.state.try_write().map_err(|_| Error::PoisonedState)?;
+        let mut state = self.state.write().map_err(|_| Error::PoisonedState)?;
         *state = new_state;
         Ok(())
     }
@@ -2328,7 +2576,7 @@ impl Vm {
     /// Get the VM state. Returns an error if the state is poisoned.
     pub fn get_state(&self) -> Result<VmState> {
         self.state
-            .try_read()
+            .read()
             .map_err(|_| Error::PoisonedState)
             .map(|state| *state)
     }
@@ -2338,6 +2586,52 @@ impl Vm {
         self.device_manager.lock().unwrap().balloon_size()
     }
 
+    /// Gets the memory statistics reported by the guest through the balloon.
+    pub fn balloon_statistics(&self) -> Result<virtio_devices::BalloonStatistics> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .balloon_statistics()
+            .map_err(Error::DeviceManager)
+    }
+
+    /// Asks the guest, through the balloon, for the memory ranges it is not
+    /// using. The guest is allowed to use these pages again as soon as this
+    /// returns, so dirty pages must be logged before calling it.
+    pub fn free_page_hints(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<Vec<MemoryRange>, MigratableError> {
+        let waiter = self
+            .device_manager
+            .lock()
+            .unwrap()
+            .start_free_page_hinting()
+            .map_err(|e| {
+                MigratableError::MigrateSend(anyhow!("Error requesting free page hints: {:?}", e))
+            })?;
+        let Some(waiter) = waiter else {
+            return Ok(Vec::new());
+        };
+
+        // The device manager is not locked while waiting, the balloon
+        // notifies the waiter once the guest is done sending hints.
+        let free_page_hints = waiter.wait(timeout).unwrap_or_else(|| {
+            warn!("Timed out waiting for free page hints from the guest");
+            Vec::new()
+        });
+
+        self.device_manager
+            .lock()
+            .unwrap()
+            .stop_free_page_hinting()
+            .map_err(|e| {
+                MigratableError::MigrateSend(anyhow!("Error stopping free page hinting: {:?}", e))
+            })?;
+
+        Ok(free_page_hints)
+    }
+
     pub fn send_memory_fds(
         &mut self,
         socket: &mut UnixStream,
@@ -2565,12 +2859,86 @@ impl Vm {
     }
 }
 
+/// What the API threads can read about a VM while the VMM thread is busy
+/// running a job on behalf of the API.
+#[derive(Clone)]
+pub struct VmView {
+    config: Arc<Mutex<VmConfig>>,
+    running: Option<RunningVmView>,
+}
+
+#[derive(Clone)]
+struct RunningVmView {
+    state: Arc<RwLock<VmState>>,
+    // Copied when the view is created, as the jobs don't change the devices
+    // while they may keep the device manager and the device tree locked.
+    device_tree: Arc<DeviceTree>,
+    balloon: Option<Arc<Mutex<virtio_devices::Balloon>>>,
+    // Read without locking the device manager nor the CPU manager, which
+    // the jobs may keep locked.
+    device_counters: DeviceCounters,
+    vcpu_stats: Vec<(String, Arc<cpu::VcpuStats>)>,
+}
+
+impl VmView {
+    pub fn new(config: Arc<Mutex<VmConfig>>, vm: Option<&Vm>) -> Self {
+        VmView {
+            config,
+            running: vm.map(|vm| {
+                let device_manager = vm.device_manager.lock().unwrap();
+                RunningVmView {
+                    state: Arc::clone(&vm.state),
+                    device_tree: Arc::new(device_manager.device_tree().lock().unwrap().clone()),
+                    balloon: device_manager.balloon(),
+                    device_counters: device_manager.device_counters(),
+                    vcpu_stats: vm.cpu_manager.lock().unwrap().vcpu_stats(),
+                }
+            }),
+        }
+    }
+
+    pub fn info(&self) -> Result<VmInfoResponse> {
+        let config = self.config.lock().unwrap().clone();
+        let mut memory_actual_size = config.memory.total_size();
+
+        let (state, device_tree) = match &self.running {
+            Some(running) => {
+                let state = *running.state.read().map_err(|_| Error::PoisonedState)?;
+                if let Some(balloon) = &running.balloon {
+                    memory_actual_size -= balloon.lock().unwrap().get_actual();
+                }
+                (state, Some(DeviceTree::clone(&running.device_tree)))
+            }
+            None => (VmState::Created, None),
+        };
+
+        Ok(VmInfoResponse {
+            config: Box::new(config),
+            state,
+            memory_actual_size,
+            device_tree,
+        })
+    }
+
+    pub fn counters(&self) -> Result<HashMap<String, HashMap<&'static str, Wrapping<u64>>>> {
+        let running = self.running.as_ref().ok_or(Error::VmNotRunning)?;
+        let mut counters = running.device_counters.read();
+        counters.extend(
+            running
+                .vcpu_stats
+                .iter()
+                .map(|(id, stats)| (id.clone(), stats.counters())),
+        );
+        Ok(counters)
+    }
+}
+
 impl Pausable for Vm {
     fn pause(&mut self) -> std::result::Result<(), MigratableError> {
         event!("vm", "pausing");
         let mut state = self
             .state
-            .try_write()
+            .write()
             .map_err(|e| MigratableError::Pause(anyhow!("Could not get VM state: {}", e)))?;
         let new_state = VmState::Paused;
 
@@ -2612,7 +2980,7 @@ impl Pausable for Vm {
         let current_state = self.get_state().unwrap();
         let mut state = self
             .state
-            .try_write()
+            .write()
             .map_err(|e| MigratableError::Resume(anyhow!("Could not get VM state: {}", e)))?;
         let new_state = VmState::Running;
 
@@ -2841,7 +3209,7 @@ impl Debuggable for Vm {
 
         let mut state = self
             .state
-            .try_write()
+            .write()
             .map_err(|_| DebuggableError::PoisonedState)?;
         *state = VmState::BreakPoint;
         Ok(())
@@ -2969,6 +3337,80 @@ impl GuestDebuggable for Vm {
     }
 }
 
+#[cfg(test)]
+mod view_tests {
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::device_tree::DeviceNode;
+
+    fn vm_view(state: Arc<RwLock<VmState>>) -> VmView {
+        let config: VmConfig =
+            serde_json::from_str(r#"{"payload": {"kernel": "/path/to/kernel"}}"#).unwrap();
+        let mut device_tree = DeviceTree::new();
+        device_tree.insert(
+            "_disk0".to_string(),
+            DeviceNode::new("_disk0".to_string(), None),
+        );
+
+        VmView {
+            config: Arc::new(Mutex::new(config)),
+            running: Some(RunningVmView {
+                state,
+                device_tree: Arc::new(device_tree),
+                balloon: None,
+                device_counters: DeviceCounters::from_devices(vec![(
+                    "_disk0".to_string(),
+                    Arc::new(|| HashMap::from([("read_ops", Wrapping(3))])),
+                )]),
+                vcpu_stats: vec![("vcpu0".to_string(), Arc::default())],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_vm_view_info() {
+        let view = vm_view(Arc::new(RwLock::new(VmState::Running)));
+        let info = view.info().unwrap();
+        assert_eq!(info.state, VmState::Running);
+        assert_eq!(info.memory_actual_size, info.config.memory.total_size());
+        assert!(info.device_tree.unwrap().contains_key("_disk0"));
+        let counters = view.counters().unwrap();
+        assert_eq!(counters["_disk0"]["read_ops"], Wrapping(3));
+        assert_eq!(counters["vcpu0"]["exits"], Wrapping(0));
+
+        let view = VmView::new(view.config.clone(), None);
+        assert_eq!(view.info().unwrap().state, VmState::Created);
+        assert!(view.info().unwrap().device_tree.is_none());
+        assert!(matches!(view.counters(), Err(Error::VmNotRunning)));
+    }
+
+    #[test]
+    fn test_vm_view_info_during_transition() {
+        let state = Arc::new(RwLock::new(VmState::Running));
+        let view = vm_view(state.clone());
+        let barrier = Arc::new(Barrier::new(2));
+
+        // The VMM thread pausing the VM while a job is running.
+        let pausing = {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let mut state = state.write().unwrap();
+                barrier.wait();
+                thread::sleep(Duration::from_millis(100));
+                *state = VmState::Paused;
+            })
+        };
+
+        // vm.info waits for the transition instead of failing.
+        barrier.wait();
+        assert_eq!(view.info().unwrap().state, VmState::Paused);
+        pausing.join().unwrap();
+    }
+}
+
 #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
 #[cfg(test)]
 mod tests {
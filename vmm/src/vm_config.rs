@@ -2,13 +2,13 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 //
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::PathBuf;
 use std::{fs, result};
 
-use net_util::MacAddr;
+use net_util::{IpNetwork, MacAddr};
 use serde::{Deserialize, Serialize};
-use virtio_devices::RateLimiterConfig;
+use virtio_devices::{RateLimitShareConfig, RateLimiterConfig};
 
 use crate::landlock::LandlockError;
 use crate::Landlock;
@@ -102,6 +102,8 @@ pub struct PlatformConfig {
     #[serde(default = "default_platformconfig_iommu_address_width_bits")]
     pub iommu_address_width_bits: u8,
     #[serde(default)]
+    pub iommu_max_domains: Option<u32>,
+    #[serde(default)]
     pub serial_number: Option<String>,
     #[serde(default)]
     pub uuid: Option<String>,
@@ -231,6 +233,10 @@ pub struct RateLimiterGroupConfig {
     pub id: String,
     #[serde(default)]
     pub rate_limiter_config: RateLimiterConfig,
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub share: Option<RateLimitShareConfig>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -258,6 +264,8 @@ pub struct DiskConfig {
     #[serde(default)]
     pub rate_limit_group: Option<String>,
     #[serde(default)]
+    pub rate_limit_share: Option<RateLimitShareConfig>,
+    #[serde(default)]
     pub rate_limiter_config: Option<RateLimiterConfig>,
     #[serde(default)]
     pub id: Option<String>,
@@ -301,9 +309,12 @@ pub struct NetConfig {
     #[serde(default = "default_netconfig_tap")]
     pub tap: Option<String>,
     #[serde(default = "default_netconfig_ip")]
-    pub ip: Ipv4Addr,
-    #[serde(default = "default_netconfig_mask")]
-    pub mask: Ipv4Addr,
+    pub ip: IpAddr,
+    /// Defaults to a mask matching the family of `ip`
+    #[serde(default)]
+    pub mask: Option<IpAddr>,
+    #[serde(default)]
+    pub extra_ips: Option<Vec<IpNetwork>>,
     #[serde(default = "default_netconfig_mac")]
     pub mac: MacAddr,
     #[serde(default)]
@@ -332,6 +343,12 @@ pub struct NetConfig {
     #[serde(default)]
     pub rate_limiter_config: Option<RateLimiterConfig>,
     #[serde(default)]
+    pub rx_rate_limit_group: Option<String>,
+    #[serde(default)]
+    pub tx_rate_limit_group: Option<String>,
+    #[serde(default)]
+    pub rate_limit_share: Option<RateLimitShareConfig>,
+    #[serde(default)]
     pub pci_segment: u16,
     #[serde(default = "default_netconfig_true")]
     pub offload_tso: bool,
@@ -349,12 +366,15 @@ pub fn default_netconfig_tap() -> Option<String> {
     None
 }
 
-pub fn default_netconfig_ip() -> Ipv4Addr {
-    Ipv4Addr::new(192, 168, 249, 1)
+pub fn default_netconfig_ip() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(192, 168, 249, 1))
 }
 
-pub fn default_netconfig_mask() -> Ipv4Addr {
-    Ipv4Addr::new(255, 255, 255, 0)
+pub fn default_netconfig_mask(ip: &IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::new(255, 255, 255, 0)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::new(0xffff, 0xffff, 0xffff, 0xffff, 0, 0, 0, 0)),
+    }
 }
 
 pub fn default_netconfig_mac() -> MacAddr {
@@ -404,6 +424,8 @@ pub struct RngConfig {
     pub src: PathBuf,
     #[serde(default)]
     pub iommu: bool,
+    #[serde(default)]
+    pub rate_limiter_config: Option<RateLimiterConfig>,
 }
 
 pub const DEFAULT_RNG_SOURCE: &str = "/dev/urandom";
@@ -413,14 +435,24 @@ impl Default for RngConfig {
         RngConfig {
             src: PathBuf::from(DEFAULT_RNG_SOURCE),
             iommu: false,
+            rate_limiter_config: None,
         }
     }
 }
 
 impl ApplyLandlock for RngConfig {
     fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
-        // Rng Path only need read access
-        landlock.add_rule_with_access(self.src.to_path_buf(), "r")?;
+        // An entropy daemon socket must be writable to send requests,
+        // while a file only needs read access.
+        if let Some(socket) = self
+            .src
+            .to_str()
+            .and_then(|src| src.strip_prefix(virtio_devices::EGD_SOURCE_PREFIX))
+        {
+            landlock.add_rule_with_access(PathBuf::from(socket), "rw")?;
+        } else {
+            landlock.add_rule_with_access(self.src.to_path_buf(), "r")?;
+        }
         Ok(())
     }
 }
@@ -434,6 +466,13 @@ pub struct BalloonConfig {
     /// Option to enable free page reporting from the guest.
     #[serde(default)]
     pub free_page_reporting: bool,
+    /// Interval in seconds at which memory statistics are requested from
+    /// the guest. Statistics are disabled when 0.
+    #[serde(default)]
+    pub statistics_polling_interval: u64,
+    /// Option to let the guest hint about free pages during live migration.
+    #[serde(default)]
+    pub free_page_hinting: bool,
 }
 
 #[cfg(feature = "pvmemcontrol")]
@@ -449,6 +488,8 @@ pub struct FsConfig {
     #[serde(default = "default_fsconfig_queue_size")]
     pub queue_size: u16,
     #[serde(default)]
+    pub cache_size: Option<u64>,
+    #[serde(default)]
     pub id: Option<String>,
     #[serde(default)]
     pub pci_segment: u16,
@@ -469,6 +510,28 @@ impl ApplyLandlock for FsConfig {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ShareConfig {
+    pub path: PathBuf,
+    pub tag: String,
+    #[serde(default)]
+    pub readonly: bool,
+    #[serde(default)]
+    pub iommu: bool,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub pci_segment: u16,
+}
+
+impl ApplyLandlock for ShareConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        let access = if self.readonly { "r" } else { "rw" };
+        landlock.add_rule_with_access(self.path.to_path_buf(), access)?;
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct PmemConfig {
     pub file: PathBuf,
@@ -479,6 +542,8 @@ pub struct PmemConfig {
     #[serde(default)]
     pub discard_writes: bool,
     #[serde(default)]
+    pub shmem_region: bool,
+    #[serde(default)]
     pub id: Option<String>,
     #[serde(default)]
     pub pci_segment: u16,
@@ -486,7 +551,13 @@ pub struct PmemConfig {
 
 impl ApplyLandlock for PmemConfig {
     fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
-        let access = if self.discard_writes { "r" } else { "rw" };
+        // Creating a temporary file in a directory always requires write
+        // access, even if guest writes are discarded.
+        let access = if self.discard_writes && !self.file.is_dir() {
+            "r"
+        } else {
+            "rw"
+        };
         landlock.add_rule_with_access(self.file.to_path_buf(), access)?;
         Ok(())
     }
@@ -594,6 +665,8 @@ pub struct UserDeviceConfig {
     pub id: Option<String>,
     #[serde(default)]
     pub pci_segment: u16,
+    #[serde(default)]
+    pub migratable: bool,
 }
 
 impl ApplyLandlock for UserDeviceConfig {
@@ -634,6 +707,8 @@ pub struct VsockConfig {
     #[serde(default)]
     pub iommu: bool,
     #[serde(default)]
+    pub seqpacket: bool,
+    #[serde(default)]
     pub id: Option<String>,
     #[serde(default)]
     pub pci_segment: u16,
@@ -642,6 +717,12 @@ pub struct VsockConfig {
 impl ApplyLandlock for VsockConfig {
     fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
         landlock.add_rule_with_access(self.socket.to_path_buf(), "rw")?;
+        if self.seqpacket {
+            // Host-initiated seqpacket connections have their own listener
+            let mut seqpacket_socket = self.socket.clone().into_os_string();
+            seqpacket_socket.push("_seqpacket");
+            landlock.add_rule_with_access(seqpacket_socket.into(), "rw")?;
+        }
         Ok(())
     }
 }
@@ -780,6 +861,7 @@ pub struct VmConfig {
     pub rng: RngConfig,
     pub balloon: Option<BalloonConfig>,
     pub fs: Option<Vec<FsConfig>>,
+    pub shares: Option<Vec<ShareConfig>>,
     pub pmem: Option<Vec<PmemConfig>>,
     #[serde(default = "default_serial")]
     pub serial: ConsoleConfig,
@@ -846,6 +928,12 @@ impl VmConfig {
             }
         }
 
+        if let Some(share_configs) = &self.shares {
+            for share_config in share_configs.iter() {
+                share_config.apply_landlock(&mut landlock)?;
+            }
+        }
+
         if let Some(pmem_configs) = &self.pmem {
             for pmem_config in pmem_configs.iter() {
                 pmem_config.apply_landlock(&mut landlock)?;